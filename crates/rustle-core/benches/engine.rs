@@ -0,0 +1,207 @@
+use std::net::TcpListener;
+use std::time::Duration;
+use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+use actix_web::http::header::{ACCEPT_RANGES, CONTENT_RANGE, RANGE};
+use bytes::Bytes;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rustle_core::downloader::RustleDownloader;
+use rustle_core::io::{write_bytes_to_file_in_dir, FileWriter, StdFileWriter};
+
+/// Size of the synthetic file served to the engine.
+const FILE_SIZE: usize = 16 * 1024 * 1024;
+
+/// Numbers of parts the download is split into.
+const PARTS: [u8; 6] = [1, 2, 4, 8, 16, 32];
+
+/// Sizes of the body chunks the server writes, i.e. the buffers the engine receives.
+const CHUNK_SIZES: [usize; 4] = [4 * 1024, 16 * 1024, 64 * 1024, 256 * 1024];
+
+/// Minimum times between two progress updates of the engine.
+const PROGRESS_INTERVALS: [Duration; 3] = [Duration::ZERO, Duration::from_millis(10), Duration::from_millis(100)];
+
+/// Serves the synthetic file from memory, honoring `Range: bytes=<start>-<end>` requests.
+/// `?chunk=<size>` streams the body in chunks of that size instead of a single one.
+async fn synthetic_file(request: HttpRequest, content: web::Data<Bytes>) -> HttpResponse {
+    let total = content.len();
+    let range = request.headers().get(RANGE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("bytes="))
+        .and_then(|value| value.split_once('-'))
+        .and_then(|(start, end)| Some((start.parse::<usize>().ok()?, end.parse::<usize>().ok()?)));
+    let chunk_size = request.query_string().strip_prefix("chunk=").and_then(|size| size.parse::<usize>().ok());
+
+    let (mut response, body) = match range {
+        Some((start, end)) if start < total => {
+            let end = end.min(total - 1);
+            let mut response = HttpResponse::PartialContent();
+            response.insert_header((CONTENT_RANGE, format!("bytes {}-{}/{}", start, end, total)));
+            (response, content.slice(start..=end))
+        },
+        _ => (HttpResponse::Ok(), content.get_ref().clone()),
+    };
+    response.insert_header((ACCEPT_RANGES, "bytes"));
+
+    match chunk_size.filter(|size| *size > 0) {
+        Some(chunk_size) => {
+            let length = body.len() as u64;
+            let chunks = (0..body.len()).step_by(chunk_size)
+                .map(move |offset| Ok::<_, actix_web::Error>(body.slice(offset..(offset + chunk_size).min(body.len()))))
+                .collect::<Vec<_>>();
+            response.no_chunking(length).streaming(futures::stream::iter(chunks))
+        },
+        None => response.body(body),
+    }
+}
+
+/// Starts the synthetic server on a loopback port in a background thread and returns the file url.
+fn start_synthetic_server() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("Couldn't bind the synthetic server");
+    let port = listener.local_addr().unwrap().port();
+    let content = web::Data::new(Bytes::from((0..FILE_SIZE).map(|i| (i % 251) as u8).collect::<Vec<u8>>()));
+
+    std::thread::spawn(move || {
+        actix_web::rt::System::new().block_on(
+            HttpServer::new(move || App::new().app_data(content.clone()).route("/file.bin", web::route().to(synthetic_file)))
+                .listen(listener).unwrap()
+                .run()
+        )
+    });
+
+    format!("http://127.0.0.1:{}/file.bin", port)
+}
+
+/// Scenario represents how the synthetic file is downloaded.
+#[derive(Debug, Clone, Copy)]
+struct Scenario {
+    parts: Option<u8>,              // Number of parts, picked from the file size by the engine if `None`
+    progress_interval: Duration,    // Minimum time between two progress updates
+    poll_progress: bool,            // Whether a poller reads the progress as fast as possible
+}
+
+impl Scenario {
+    /// Returns a scenario splitting the download in `parts` without polling the progress.
+    fn parts(parts: u8) -> Scenario {
+        Scenario { parts: Some(parts), progress_interval: Duration::ZERO, poll_progress: false }
+    }
+}
+
+/// Downloads the synthetic file from `url` as described by `scenario`.
+async fn download(url: &str, out_dir: &str, scenario: Scenario) {
+    let mut engine = RustleDownloader::new(*PARTS.last().unwrap()).unwrap();
+    engine.set_url(url).await.unwrap();
+    engine.set_out_dir(out_dir).await.unwrap();
+    if let Some(parts) = scenario.parts {
+        engine.set_connections(parts).await.unwrap();
+    }
+    engine.set_progress_interval(scenario.progress_interval).await;
+    engine.init().await.unwrap();
+
+    let downloading = {
+        let engine = engine.clone();
+        tokio::spawn(async move { engine.download(false).await })
+    };
+    if scenario.poll_progress {
+        while !downloading.is_finished() {
+            criterion::black_box(engine.next_progress_vec().await);
+            tokio::task::yield_now().await;
+        }
+    }
+    assert_eq!(downloading.await.unwrap(), Ok(true));
+}
+
+fn bench_download(c: &mut Criterion) {
+    let url = start_synthetic_server();
+    let out_dir = std::env::temp_dir().join("rustle-bench");
+    let out_dir = out_dir.to_string_lossy();
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+
+    // Scheduler overhead and disk write of the whole download
+    let mut group = c.benchmark_group("download");
+    group.sample_size(10).throughput(Throughput::Bytes(FILE_SIZE as u64));
+    for parts in PARTS {
+        group.bench_with_input(BenchmarkId::from_parameter(parts), &parts, |b, &parts| {
+            b.to_async(&runtime).iter(|| download(&url, &out_dir, Scenario::parts(parts)))
+        });
+    }
+    group.finish();
+
+    // Same download while a poller keeps reading the progress, exposing lock contention
+    let mut group = c.benchmark_group("download_with_progress_polling");
+    group.sample_size(10).throughput(Throughput::Bytes(FILE_SIZE as u64));
+    for parts in PARTS {
+        let scenario = Scenario { poll_progress: true, ..Scenario::parts(parts) };
+        group.bench_with_input(BenchmarkId::from_parameter(parts), &scenario, |b, &scenario| {
+            b.to_async(&runtime).iter(|| download(&url, &out_dir, scenario))
+        });
+    }
+    group.finish();
+
+    // Number of parts picked from the file size, compared to the fixed counts of `download`
+    let mut group = c.benchmark_group("segmentation");
+    group.sample_size(10).throughput(Throughput::Bytes(FILE_SIZE as u64));
+    let scenario = Scenario { parts: None, ..Scenario::parts(1) };
+    group.bench_with_input(BenchmarkId::from_parameter("adaptive"), &scenario, |b, &scenario| {
+        b.to_async(&runtime).iter(|| download(&url, &out_dir, scenario))
+    });
+    group.finish();
+
+    // Cost of handling the received bytes in small or large chunks
+    let mut group = c.benchmark_group("chunk_size");
+    group.sample_size(10).throughput(Throughput::Bytes(FILE_SIZE as u64));
+    for chunk_size in CHUNK_SIZES {
+        let url = format!("{}?chunk={}", url, chunk_size);
+        group.bench_with_input(BenchmarkId::from_parameter(chunk_size), &chunk_size, |b, _| {
+            b.to_async(&runtime).iter(|| download(&url, &out_dir, Scenario::parts(8)))
+        });
+    }
+    group.finish();
+
+    // Overhead of the progress updates while the progress is polled
+    let mut group = c.benchmark_group("progress_interval");
+    group.sample_size(10).throughput(Throughput::Bytes(FILE_SIZE as u64));
+    for progress_interval in PROGRESS_INTERVALS {
+        let scenario = Scenario { progress_interval, poll_progress: true, ..Scenario::parts(8) };
+        group.bench_with_input(BenchmarkId::from_parameter(format!("{:?}", progress_interval)), &scenario, |b, &scenario| {
+            b.to_async(&runtime).iter(|| download(&url, &out_dir, scenario))
+        });
+    }
+    group.finish();
+}
+
+/// Writes the content through `writer`, as the engine does once a download is complete.
+async fn write_file(writer: &dyn FileWriter, content: &Bytes, out_dir: &std::path::Path) {
+    writer.write_file(content.clone(), "write.bin", out_dir).await.unwrap()
+}
+
+fn bench_write(c: &mut Criterion) {
+    let out_dir = std::env::temp_dir().join("rustle-bench");
+    let content = Bytes::from(vec![0u8; FILE_SIZE]);
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+
+    let mut group = c.benchmark_group("write");
+    group.sample_size(10).throughput(Throughput::Bytes(FILE_SIZE as u64));
+    group.bench_function("write_bytes_to_file_in_dir", |b| {
+        b.iter(|| write_bytes_to_file_in_dir(&content, "write.bin", &out_dir).unwrap())
+    });
+    let std_file_writer = StdFileWriter::default();
+    group.bench_function("std_file_writer", |b| {
+        b.to_async(&runtime).iter(|| write_file(&std_file_writer, &content, &out_dir))
+    });
+    // Small writes, to tune `write_buffer_kib` for the disk
+    let small_writes_writer = StdFileWriter { write_size: 64 * 1024 };
+    group.bench_function("std_file_writer_64k_writes", |b| {
+        b.to_async(&runtime).iter(|| write_file(&small_writes_writer, &content, &out_dir))
+    });
+    // Compared to `std_file_writer` when run with `--features io-uring`
+    #[cfg(all(target_os = "linux", feature = "io-uring"))]
+    {
+        let uring_file_writer = rustle_core::io::UringFileWriter::default();
+        group.bench_function("uring_file_writer", |b| {
+            b.to_async(&runtime).iter(|| write_file(&uring_file_writer, &content, &out_dir))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_download, bench_write);
+criterion_main!(benches);
@@ -0,0 +1,166 @@
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::Serialize;
+
+/// AuditRecord represents a single event of the chunk-level audit trail.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum AuditRecord {
+    /// A range request was sent for a part.
+    RangeRequest { part: usize, url: String, range: String },
+    /// The response headers of a part's request were received.
+    Response { part: usize, status: u16, content_range: Option<String> },
+    /// A chunk of the body was received, `offset` is its position in the file.
+    Chunk { part: usize, offset: u64, length: usize, sha256: String },
+    /// A part finished streaming, `sha256` covers all of its bytes.
+    PartDone { part: usize, bytes: u64, sha256: String },
+}
+
+/// A record along with the time it was logged.
+#[derive(Serialize)]
+struct AuditEntry<'a> {
+    timestamp_ms: u128,             // Milliseconds since the unix epoch
+    #[serde(flatten)]
+    record: &'a AuditRecord,
+}
+
+/// AuditLog appends audit records to a file, one JSON object per line, so that a
+/// delivered file can later be verified against exactly what the server sent.
+#[derive(Debug)]
+pub struct AuditLog {
+    writer: Mutex<BufWriter<File>>,     // Buffered writer of the log file
+}
+
+impl AuditLog {
+    /// Creates (or truncates) the audit log file at `path`, creating its directory if needed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error message if the directory or the file couldn't be created.
+    pub fn create(path: &Path) -> Result<AuditLog, String> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| format!("Couldn't create the audit log directory, error : {}", e))?;
+        }
+        let file = File::create(path).map_err(|e| format!("Couldn't create the audit log {}, error : {}", path.display(), e))?;
+        Ok(AuditLog { writer: Mutex::new(BufWriter::new(file)) })
+    }
+
+    /// Appends a record to the log. Part completions are flushed to disk right away.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error message if the record couldn't be written.
+    pub fn record(&self, record: AuditRecord) -> Result<(), String> {
+        let timestamp_ms = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis()).unwrap_or(0);
+        let line = serde_json::to_string(&AuditEntry { timestamp_ms, record: &record })
+            .map_err(|e| format!("Couldn't serialize the audit record, error : {}", e))?;
+
+        let mut writer = self.writer.lock().unwrap();
+        writeln!(writer, "{}", line).map_err(|e| format!("Couldn't write to the audit log, error : {}", e))?;
+        if let AuditRecord::PartDone { .. } = record {
+            writer.flush().map_err(|e| format!("Couldn't flush the audit log, error : {}", e))?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the hex encoded SHA-256 digest of `bytes`.
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    use sha2::{Digest, Sha256};
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+/// Returns the hex encoded SHA-256 digest of the file at `path`, read in chunks.
+pub fn sha256_file(path: &Path) -> Result<String, String> {
+    use sha2::{Digest, Sha256};
+    let mut file = File::open(path).map_err(|e| format!("Couldn't open {}, error : {}", path.display(), e))?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher).map_err(|e| format!("Couldn't read {}, error : {}", path.display(), e))?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Returns whether `value` is a hex encoded SHA-256 digest, i.e. 64 hexadecimal digits.
+pub fn is_sha256_hex(value: &str) -> bool {
+    value.len() == 64 && value.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("rustle-test-audit-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn read_records(path: &Path) -> Vec<serde_json::Value> {
+        fs::read_to_string(path).unwrap().lines().map(|line| serde_json::from_str(line).unwrap()).collect()
+    }
+
+    #[test]
+    fn records_are_written_one_per_line() {
+        let dir = test_dir("records");
+        let path = dir.join("logs").join("audit.jsonl");
+        let log = AuditLog::create(&path).unwrap();
+        log.record(AuditRecord::RangeRequest { part: 0, url: String::from("https://example.com/file.iso"), range: String::from("bytes=0-3") }).unwrap();
+        log.record(AuditRecord::Response { part: 0, status: 206, content_range: Some(String::from("bytes 0-3/4")) }).unwrap();
+        log.record(AuditRecord::Chunk { part: 0, offset: 0, length: 4, sha256: sha256_hex(b"data") }).unwrap();
+        // Part completions are flushed, the log doesn't need to be dropped
+        log.record(AuditRecord::PartDone { part: 0, bytes: 4, sha256: sha256_hex(b"data") }).unwrap();
+
+        let records = read_records(&path);
+        let events: Vec<&str> = records.iter().map(|record| record["event"].as_str().unwrap()).collect();
+        assert_eq!(events, vec!["range_request", "response", "chunk", "part_done"]);
+        assert!(records.iter().all(|record| record["timestamp_ms"].as_u64().is_some_and(|ms| ms > 0)));
+        assert_eq!(records[0]["range"], "bytes=0-3");
+        assert_eq!(records[1]["status"], 206);
+        assert_eq!(records[1]["content_range"], "bytes 0-3/4");
+        assert_eq!(records[2]["offset"], 0);
+        assert_eq!(records[3]["bytes"], 4);
+        assert_eq!(records[3]["sha256"], "3a6eb0790f39ac87c94f3856b2dd2c5d110e6811602261a9a923d3bb23adc8b7");
+
+        // Creating the log again truncates it
+        drop(log);
+        let log = AuditLog::create(&path).unwrap();
+        log.record(AuditRecord::Response { part: 1, status: 200, content_range: None }).unwrap();
+        drop(log);
+        let records = read_records(&path);
+        assert_eq!(records.len(), 1);
+        assert!(records[0]["content_range"].is_null());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn unwritable_paths_are_reported() {
+        let dir = test_dir("unwritable");
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("file");
+        fs::write(&file, b"").unwrap();
+
+        let error = AuditLog::create(&file.join("audit.jsonl")).unwrap_err();
+        assert!(error.starts_with("Couldn't create the audit log directory"), "{}", error);
+        let error = AuditLog::create(&dir).unwrap_err();
+        assert!(error.starts_with(&format!("Couldn't create the audit log {}", dir.display())), "{}", error);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn sha256_digests_are_hex_encoded() {
+        let dir = test_dir("sha256");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("file");
+        fs::write(&path, b"data").unwrap();
+
+        assert_eq!(sha256_file(&path), Ok(sha256_hex(b"data")));
+        assert!(is_sha256_hex(&sha256_hex(b"data")));
+        assert!(is_sha256_hex(&"AB".repeat(32)));
+        assert!(!is_sha256_hex("abc"));
+        assert!(!is_sha256_hex(&"g".repeat(64)));
+        assert!(sha256_file(&dir.join("missing")).unwrap_err().starts_with("Couldn't open"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
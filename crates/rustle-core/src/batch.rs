@@ -0,0 +1,135 @@
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use super::downloader::{RustleDownloader, ValidUrl};
+
+/// BatchEntry represents a download listed in a batch file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatchEntry {
+    pub line: usize,                    // Line of the entry in the file, starting at 1
+    pub url: String,                    // URL of the file
+    pub file_name: Option<String>,      // Name of the downloaded file, detected from the response if missing
+    pub dir: Option<PathBuf>,           // Subdirectory of the output directory the file is written to, e.g. of a mirrored site
+    pub referer: Option<String>,        // Page the URL was found on, sent as the `Referer` of the requests
+}
+
+/// BatchError represents a line of a batch file that couldn't be parsed, the other lines are still imported.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatchError {
+    pub line: usize,        // Line of the error in the file, starting at 1
+    pub message: String,    // Description of the error
+}
+
+impl fmt::Display for BatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+/// BatchImport represents a list of downloads imported from a text file, one per line:
+///
+/// ```text
+/// # Comments and empty lines are ignored
+/// https://example.com/file.iso
+/// https://example.com/file.iso.sha256 checksum.txt
+/// ```
+///
+/// A URL can be followed by the name of the downloaded file, separated by whitespace.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BatchImport {
+    pub entries: Vec<BatchEntry>,   // Valid entries, in the order of the file
+    pub errors: Vec<BatchError>,    // Lines that couldn't be parsed
+}
+
+impl BatchImport {
+    /// Loads a batch file, see `BatchImport`.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the text file.
+    ///
+    /// Returns an error if the file couldn't be read, invalid lines are reported in `errors`.
+    pub fn load(path: &Path) -> Result<BatchImport, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("Couldn't read the list {}, error : {}", path.display(), e))?;
+        Ok(BatchImport::parse(&text))
+    }
+
+    /// Parses the content of a batch file, see `BatchImport`.
+    pub fn parse(text: &str) -> BatchImport {
+        let mut batch = BatchImport::default();
+        for (i, line) in text.lines().enumerate() {
+            let line_number = i + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (url, file_name) = match line.split_once(char::is_whitespace) {
+                Some((url, file_name)) => (url, Some(file_name.trim())),
+                None => (line, None),
+            };
+            match BatchImport::parse_entry(url, file_name) {
+                Ok(file_name) => batch.entries.push(BatchEntry { file_name, ..BatchEntry::new(line_number, url) }),
+                Err(message) => batch.errors.push(BatchError { line: line_number, message }),
+            }
+        }
+        batch
+    }
+
+    /// Validates the URL and file name of a line, returning the file name.
+    fn parse_entry(url: &str, file_name: Option<&str>) -> Result<Option<String>, String> {
+        ValidUrl::new(url).map_err(|e| format!("invalid URL `{}`, error : {}", url, e))?;
+        match file_name {
+            Some(name) if name == "." || name == ".." || name.contains(['/', '\\']) => {
+                Err(format!("invalid file name `{}`, it must not be a path", name))
+            },
+            file_name => Ok(file_name.map(String::from)),
+        }
+    }
+}
+
+impl BatchEntry {
+    /// Creates the entry of line `line` downloading the file at `url`.
+    pub fn new(line: usize, url: &str) -> BatchEntry {
+        BatchEntry { line, url: url.to_string(), file_name: None, dir: None, referer: None }
+    }
+
+    /// Configures the engine to download the entry and initializes it.
+    ///
+    /// # Arguments
+    ///
+    /// * `engine` - The engine to configure, its output directory must already be set.
+    ///
+    /// Returns an error if the initialization fails.
+    pub async fn apply(&self, engine: &mut RustleDownloader) -> Result<(), String> {
+        if let Some(dir) = self.dir.as_ref() {
+            let out_dir = engine.get_out_dir().await.ok_or("The output directory isn't set")?.join(dir);
+            engine.set_out_dir(&out_dir.to_string_lossy()).await?;
+        }
+        if let Some(file_name) = self.file_name.as_ref() {
+            engine.set_file_name(file_name).await;
+        }
+        if let Some(referer) = self.referer.as_deref() {
+            engine.set_referer(Some(referer)).await?;
+        }
+        engine.set_url(&self.url).await?;
+        engine.init().await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_reports_invalid_lines() {
+        let batch = BatchImport::parse("# Releases\nhttps://example.com/a.iso\n\n  https://example.com/b.iso\tb copy.iso  \nexample.com/c.iso\nhttps://example.com/d.iso ../d.iso\n");
+        assert_eq!(batch.entries, vec![
+            BatchEntry::new(2, "https://example.com/a.iso"),
+            BatchEntry { file_name: Some(String::from("b copy.iso")), ..BatchEntry::new(4, "https://example.com/b.iso") },
+        ]);
+        assert_eq!(batch.errors.iter().map(|error| error.line).collect::<Vec<_>>(), vec![5, 6]);
+        assert!(batch.errors[1].to_string().starts_with("line 6: invalid file name"));
+    }
+}
@@ -0,0 +1,554 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use std::str::FromStr;
+use bytes::Bytes;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::Method;
+use super::audit::is_sha256_hex;
+use super::client::{AddressFamily, HttpVersion, TlsOptions};
+use super::connection_manager::ConnectionManager;
+use super::credentials::CredentialStore;
+use super::dns::DnsOptions;
+use super::downloader::{RetryPolicy, RustleDownloader, ValidUrl, DEFAULT_STALE_AFTER, DEFAULT_STALL_TIMEOUT};
+use super::encoding::ContentEncodingMode;
+use super::io::{BufferingStrategy, FilePermissions, FileWriter};
+use super::protocol::ProtocolRegistry;
+use super::refresh::UrlRefresher;
+use super::transport::Transport;
+
+/// Default maximum number of parallel connections of a built download.
+pub const DEFAULT_MAX_CONNECTIONS: u8 = 4;
+
+/// RustleDownloaderBuilder configures a download and builds an initialized `RustleDownloader`,
+/// created with `RustleDownloader::builder()`.
+///
+/// ```no_run
+/// # async fn example() -> Result<(), String> {
+/// use rustle_core::RustleDownloader;
+///
+/// let engine = RustleDownloader::builder()
+///     .url("https://example.com/file.iso")
+///     .out_dir("./downloads")
+///     .connections(4)
+///     .header("Authorization", "Bearer token")
+///     .build()
+///     .await?;
+/// engine.download(false).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct RustleDownloaderBuilder {
+    url: Option<String>,                                // URL of the file
+    out_dir: Option<PathBuf>,                           // Output directory of the file
+    max_connections: u8,                                // Maximum number of parallel connections
+    connections: Option<u8>,                            // Explicit number of connections, skips the adaptive selection
+    headers: HeaderMap,                                 // Extra request headers
+    raw_headers: Vec<(String, String)>,                 // Extra request headers added by name, validated by `build()`
+    method: Option<String>,                             // Method of the requests, validated by `build()`
+    body: Option<Bytes>,                                // Body of the requests
+    content_encoding: Option<ContentEncodingMode>,      // Whether compressed responses are decoded
+    file_name: Option<String>,                          // Name of the file, instead of the detected one
+    sha256: Option<String>,                             // Expected SHA-256 of the file
+    tls: Option<TlsOptions>,                            // TLS options
+    address_family: Option<AddressFamily>,              // Preferred IP address family
+    dns: Option<DnsOptions>,                            // Servers the hostnames are resolved with
+    proxy: Option<String>,                              // Proxy the requests go through
+    env_proxy: bool,                                    // Whether the proxies of the environment are used when no proxy is set
+    user_agent: Option<String>,                         // `User-Agent` header of the requests
+    referer: Option<String>,                            // `Referer` header of the requests
+    http_version: Option<HttpVersion>,                  // HTTP versions the requests may use
+    speed_limit: Option<u64>,                           // Maximum speed in bytes per second
+    credential_store: Option<Arc<CredentialStore>>,     // Store the credentials of the host and the proxy are looked up in
+    url_refresher: Option<Arc<dyn UrlRefresher>>,       // Supplies a new URL when the URL expires
+    connection_manager: Option<Arc<ConnectionManager>>, // Connection manager shared with other downloads
+    retry_policy: Option<RetryPolicy>,                  // How the download is retried
+    progress_interval: Option<Duration>,                // Minimum time between two progress updates
+    auto_connections: bool,                             // Whether the number of connections is tuned from the throughput
+    stale_after: Option<Duration>,                      // Time paused after which the file is checked again before resuming
+    stall_timeout: Option<Duration>,                    // Time without any chunk after which a part requests its range again
+    segment_hashing: bool,                              // Whether the segments are hashed to repair corrupt ones
+    buffering: BufferingStrategy,                       // Where the parts keep their bytes until the file is complete
+    staging_dir: Option<PathBuf>,                       // Directory the file is written to before it's moved to `out_dir`
+    file_permissions: FilePermissions,                  // Mode of the completed file on Unix
+    provenance: bool,                                   // Whether the URL is recorded in the metadata of the completed file
+    har_log: Option<PathBuf>,                           // HAR file the requests are recorded in, for debugging
+    type_check: bool,                                   // Whether a `Content-Type` not matching the extension is confirmed first
+    protocols: Option<Arc<ProtocolRegistry>>,           // Supported protocols, `http` and `https` if not set
+    transport: Option<Arc<dyn Transport>>,              // Transport replacing the client of the protocol
+    file_writer: Option<Arc<dyn FileWriter>>,           // Writer of the file, `StdFileWriter` if not set
+}
+
+impl Default for RustleDownloaderBuilder {
+    fn default() -> Self {
+        RustleDownloaderBuilder {
+            url: None,
+            out_dir: None,
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+            connections: None,
+            headers: HeaderMap::new(),
+            raw_headers: Vec::new(),
+            method: None,
+            body: None,
+            content_encoding: None,
+            file_name: None,
+            sha256: None,
+            tls: None,
+            address_family: None,
+            dns: None,
+            proxy: None,
+            env_proxy: true,
+            user_agent: None,
+            referer: None,
+            http_version: None,
+            speed_limit: None,
+            credential_store: None,
+            url_refresher: None,
+            connection_manager: None,
+            retry_policy: None,
+            progress_interval: None,
+            auto_connections: false,
+            stale_after: Some(DEFAULT_STALE_AFTER),
+            stall_timeout: Some(DEFAULT_STALL_TIMEOUT),
+            segment_hashing: false,
+            buffering: BufferingStrategy::Memory,
+            staging_dir: None,
+            file_permissions: FilePermissions::default(),
+            provenance: false,
+            har_log: None,
+            type_check: true,
+            protocols: None,
+            transport: None,
+            file_writer: None,
+        }
+    }
+}
+
+impl RustleDownloaderBuilder {
+    /// Sets the URL of the file, required.
+    pub fn url(mut self, url: &str) -> Self {
+        self.url = Some(url.to_string());
+        self
+    }
+
+    /// Sets the output directory of the file, required.
+    pub fn out_dir(mut self, out_dir: impl Into<PathBuf>) -> Self {
+        self.out_dir = Some(out_dir.into());
+        self
+    }
+
+    /// Sets the maximum number of parallel connections, the number used is picked from the
+    /// content length up to it. Defaults to `DEFAULT_MAX_CONNECTIONS`.
+    pub fn max_connections(mut self, max_connections: u8) -> Self {
+        self.max_connections = max_connections;
+        self
+    }
+
+    /// Sets an explicit number of parallel connections, instead of picking it from the content length.
+    pub fn connections(mut self, connections: u8) -> Self {
+        self.connections = Some(connections);
+        self
+    }
+
+    /// Sets the extra headers sent with every request, replacing the previously set ones.
+    pub fn headers(mut self, headers: HeaderMap) -> Self {
+        self.headers = headers;
+        self.raw_headers.clear();
+        self
+    }
+
+    /// Adds an extra header sent with every request, its name and value are validated by `build()`.
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.raw_headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Sets the method of the download requests, e.g. `POST`, it's validated by `build()`.
+    /// Other methods than `GET` download the file over a single connection, see `RustleDownloader::set_request`.
+    pub fn method(mut self, method: &str) -> Self {
+        self.method = Some(method.to_string());
+        self
+    }
+
+    /// Sets the body sent with the download requests, e.g. the JSON expected by an export API.
+    pub fn body(mut self, body: impl Into<Bytes>) -> Self {
+        self.body = Some(body.into());
+        self
+    }
+
+    /// Sets whether a compressed response of a download over a single connection is decoded or stored as sent.
+    pub fn content_encoding(mut self, content_encoding: ContentEncodingMode) -> Self {
+        self.content_encoding = Some(content_encoding);
+        self
+    }
+
+    /// Sets the name of the downloaded file, instead of the one detected from the response.
+    pub fn file_name(mut self, file_name: &str) -> Self {
+        self.file_name = Some(file_name.to_string());
+        self
+    }
+
+    /// Sets the SHA-256 the downloaded file must match, as 64 hexadecimal characters.
+    pub fn expected_sha256(mut self, sha256: &str) -> Self {
+        self.sha256 = Some(sha256.to_string());
+        self
+    }
+
+    /// Sets the TLS options, e.g. extra root certificates or a client certificate.
+    pub fn tls_options(mut self, tls: TlsOptions) -> Self {
+        self.tls = Some(tls);
+        self
+    }
+
+    /// Sets the preferred IP address family.
+    pub fn address_family(mut self, address_family: AddressFamily) -> Self {
+        self.address_family = Some(address_family);
+        self
+    }
+
+    /// Sets the DNS servers or the DNS-over-HTTPS endpoint the hostnames are resolved with.
+    pub fn dns(mut self, dns: DnsOptions) -> Self {
+        self.dns = Some(dns);
+        self
+    }
+
+    /// Sets the proxy the requests go through, e.g. `http://proxy:3128` or `socks5://proxy:1080`.
+    pub fn proxy(mut self, proxy: &str) -> Self {
+        self.proxy = Some(proxy.to_string());
+        self
+    }
+
+    /// Uses the proxies of the `HTTP_PROXY`, `HTTPS_PROXY`, `ALL_PROXY` and `NO_PROXY` environment
+    /// variables when no proxy is set. Enabled by default.
+    pub fn env_proxy(mut self, env_proxy: bool) -> Self {
+        self.env_proxy = env_proxy;
+        self
+    }
+
+    /// Sets the `User-Agent` header of the requests.
+    pub fn user_agent(mut self, user_agent: &str) -> Self {
+        self.user_agent = Some(user_agent.to_string());
+        self
+    }
+
+    /// Sets the `Referer` header of the requests, e.g. the page the file was found on.
+    pub fn referer(mut self, referer: &str) -> Self {
+        self.referer = Some(referer.to_string());
+        self
+    }
+
+    /// Sets the HTTP versions the requests may use, HTTP/2 is negotiated with the server by default.
+    pub fn http_version(mut self, http_version: HttpVersion) -> Self {
+        self.http_version = Some(http_version);
+        self
+    }
+
+    /// Limits the speed of the download, in bytes per second.
+    pub fn speed_limit(mut self, bytes_per_second: u64) -> Self {
+        self.speed_limit = Some(bytes_per_second);
+        self
+    }
+
+    /// Sets the store the credentials of the host and the proxy are looked up in, e.g. the OS credential store.
+    pub fn credential_store(mut self, credential_store: Arc<CredentialStore>) -> Self {
+        self.credential_store = Some(credential_store);
+        self
+    }
+
+    /// Sets the refresher supplying a new URL when the URL expires during the download, e.g. a signed CDN URL.
+    pub fn url_refresher(mut self, url_refresher: Arc<dyn UrlRefresher>) -> Self {
+        self.url_refresher = Some(url_refresher);
+        self
+    }
+
+    /// Sets the connection manager shared with other downloads.
+    pub fn connection_manager(mut self, connection_manager: Arc<ConnectionManager>) -> Self {
+        self.connection_manager = Some(connection_manager);
+        self
+    }
+
+    /// Sets how the download is retried when it fails.
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = Some(retry_policy);
+        self
+    }
+
+    /// Sets the minimum time between two progress updates.
+    pub fn progress_interval(mut self, interval: Duration) -> Self {
+        self.progress_interval = Some(interval);
+        self
+    }
+
+    /// Tunes the number of connections from the measured throughput, starting with a few of them.
+    pub fn auto_connections(mut self, auto_connections: bool) -> Self {
+        self.auto_connections = auto_connections;
+        self
+    }
+
+    /// Sets the time the download stays paused before the file is checked on the server again when
+    /// it's resumed, `None` to never check it again. One hour by default.
+    pub fn stale_after(mut self, stale_after: Option<Duration>) -> Self {
+        self.stale_after = stale_after;
+        self
+    }
+
+    /// Sets the time a part waits for the next chunk before the rest of its range is requested
+    /// again, `None` to wait forever. 30 seconds by default.
+    pub fn stall_timeout(mut self, stall_timeout: Option<Duration>) -> Self {
+        self.stall_timeout = stall_timeout;
+        self
+    }
+
+    /// Hashes the segments as they stream, so only the corrupt ones are downloaded again.
+    pub fn segment_hashing(mut self, segment_hashing: bool) -> Self {
+        self.segment_hashing = segment_hashing;
+        self
+    }
+
+    /// Sets where the parts keep their bytes until the file is complete, in memory by default.
+    pub fn buffering(mut self, buffering: BufferingStrategy) -> Self {
+        self.buffering = buffering;
+        self
+    }
+
+    /// Sets the directory the file is written to before it's moved to the output directory.
+    pub fn staging_dir(mut self, staging_dir: impl Into<PathBuf>) -> Self {
+        self.staging_dir = Some(staging_dir.into());
+        self
+    }
+
+    /// Sets the mode of the completed file on Unix.
+    pub fn file_permissions(mut self, file_permissions: FilePermissions) -> Self {
+        self.file_permissions = file_permissions;
+        self
+    }
+
+    /// Records the URL in the metadata of the completed file, e.g. its `user.xdg.origin.url` extended attribute.
+    pub fn provenance(mut self, provenance: bool) -> Self {
+        self.provenance = provenance;
+        self
+    }
+
+    /// Records every request of the download and writes them to a HAR file after each run, for debugging.
+    pub fn har_log(mut self, path: impl Into<PathBuf>) -> Self {
+        self.har_log = Some(path.into());
+        self
+    }
+
+    /// Checks the `Content-Type` of the file against its extension, the download waits for
+    /// `confirm_type()` on a mismatch. Enabled by default.
+    pub fn type_check(mut self, type_check: bool) -> Self {
+        self.type_check = type_check;
+        self
+    }
+
+    /// Sets the supported protocols, the client is created by the protocol of the URL's scheme.
+    pub fn protocols(mut self, protocols: Arc<ProtocolRegistry>) -> Self {
+        self.protocols = Some(protocols);
+        self
+    }
+
+    /// Sets the transport sending the requests, instead of the client of the URL's protocol.
+    pub fn transport(mut self, transport: Arc<dyn Transport>) -> Self {
+        self.transport = Some(transport);
+        self
+    }
+
+    /// Sets the writer of the downloaded file, e.g. a `UringFileWriter`.
+    pub fn file_writer(mut self, file_writer: Arc<dyn FileWriter>) -> Self {
+        self.file_writer = Some(file_writer);
+        self
+    }
+
+    /// Validates the configuration, then creates the download and initializes it with `init()`.
+    ///
+    /// Returns an error if an option is invalid, before any request is sent, or if the
+    /// initialization fails.
+    pub async fn build(self) -> Result<RustleDownloader, String> {
+        let url = self.url.ok_or("No URL was supplied")?;
+        let valid_url = ValidUrl::new(&url).map_err(|e| format!("Invalid URL {}, error : {}", url, e))?;
+        if self.transport.is_none() {
+            self.protocols.clone().unwrap_or_default().protocol(valid_url.as_url())?;
+        }
+        let out_dir = self.out_dir.filter(|out_dir| !out_dir.as_os_str().is_empty()).ok_or("No output directory was supplied")?;
+        if self.max_connections == 0 || self.connections == Some(0) {
+            return Err(String::from("Number of connections must be greater than zero"));
+        }
+        if let Some(sha256) = self.sha256.as_ref().filter(|sha256| !is_sha256_hex(sha256)) {
+            return Err(format!("`{}` isn't a valid SHA-256 checksum", sha256));
+        }
+        let mut headers = self.headers;
+        for (name, value) in self.raw_headers {
+            let header_name = HeaderName::from_bytes(name.as_bytes()).map_err(|e| format!("Invalid header name `{}`, error : {}", name, e))?;
+            let header_value = HeaderValue::from_str(&value).map_err(|e| format!("Invalid value of header `{}`, error : {}", name, e))?;
+            headers.append(header_name, header_value);
+        }
+
+        let mut engine = RustleDownloader::new(self.max_connections)?;
+        engine.set_url(&url).await?;
+        engine.set_out_dir(&out_dir.to_string_lossy()).await?;
+        if let Some(connections) = self.connections {
+            engine.set_connections(connections).await?;
+        }
+        engine.set_headers(headers).await;
+        if self.method.is_some() || self.body.is_some() {
+            let method = match self.method.as_deref() {
+                Some(method) => Method::from_str(&method.to_ascii_uppercase()).map_err(|e| format!("Invalid method `{}`, error : {}", method, e))?,
+                None => Method::GET,
+            };
+            engine.set_request(method, self.body).await;
+        }
+        if let Some(content_encoding) = self.content_encoding {
+            engine.set_content_encoding(content_encoding).await;
+        }
+        if let Some(file_name) = self.file_name.as_ref() {
+            engine.set_file_name(file_name).await;
+        }
+        if let Some(sha256) = self.sha256.as_ref() {
+            engine.set_expected_sha256(sha256).await?;
+        }
+        if let Some(tls) = self.tls {
+            engine.set_tls_options(tls).await;
+        }
+        if let Some(address_family) = self.address_family {
+            engine.set_address_family(address_family).await;
+        }
+        if let Some(dns) = self.dns {
+            engine.set_dns(dns).await;
+        }
+        engine.set_proxy(self.proxy).await;
+        engine.set_env_proxy(self.env_proxy).await;
+        engine.set_user_agent(self.user_agent).await;
+        engine.set_referer(self.referer.as_deref()).await?;
+        if let Some(http_version) = self.http_version {
+            engine.set_http_version(http_version).await;
+        }
+        engine.set_speed_limit(self.speed_limit).await?;
+        engine.set_credential_store(self.credential_store).await;
+        engine.set_url_refresher(self.url_refresher).await;
+        if let Some(connection_manager) = self.connection_manager {
+            engine.set_connection_manager(connection_manager).await;
+        }
+        if let Some(retry_policy) = self.retry_policy {
+            engine.set_retry_policy(retry_policy).await;
+        }
+        if let Some(interval) = self.progress_interval {
+            engine.set_progress_interval(interval).await;
+        }
+        engine.set_auto_connections(self.auto_connections).await;
+        engine.set_stale_after(self.stale_after).await;
+        engine.set_stall_timeout(self.stall_timeout).await;
+        engine.set_segment_hashing(self.segment_hashing).await;
+        engine.set_buffering(self.buffering).await?;
+        engine.set_staging_dir(self.staging_dir).await;
+        engine.set_file_permissions(self.file_permissions).await;
+        engine.set_provenance(self.provenance).await;
+        engine.set_type_check(self.type_check).await;
+        if let Some(path) = self.har_log {
+            engine.set_har_log(Some(path)).await;
+        }
+        if let Some(protocols) = self.protocols {
+            engine.set_protocols(protocols).await;
+        }
+        if let Some(transport) = self.transport {
+            engine.set_transport(transport).await;
+        }
+        if let Some(file_writer) = self.file_writer {
+            engine.set_file_writer(file_writer).await;
+        }
+
+        engine.init().await?;
+        Ok(engine)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::downloader::tests::MockTransport;
+
+    /// A builder downloading the file of `transport` into a test directory named after `name`.
+    fn mock_builder(transport: &Arc<MockTransport>, name: &str) -> (RustleDownloaderBuilder, PathBuf) {
+        let out_dir = std::env::temp_dir().join(format!("rustle-test-builder-{}-{}", name, std::process::id()));
+        let builder = RustleDownloader::builder()
+            .url("http://mock.test/file.bin")
+            .out_dir(&out_dir)
+            .transport(transport.clone());
+        (builder, out_dir)
+    }
+
+    #[tokio::test]
+    async fn defaults_reach_the_download() {
+        let transport = Arc::new(MockTransport::new(1024));
+        let (builder, out_dir) = mock_builder(&transport, "defaults");
+        let engine = builder.build().await.unwrap();
+
+        assert_eq!(engine.get_url().await.as_deref(), Some("http://mock.test/file.bin"));
+        assert_eq!(engine.get_out_dir().await, Some(out_dir.clone()));
+        assert_eq!(engine.get_file_path().await, Some(out_dir.join("file.bin")));
+        assert_eq!(engine.get_connections().await, None);
+        assert_eq!(engine.get_method().await, Method::GET);
+        assert_eq!(engine.get_referer().await, None);
+        assert_eq!(engine.get_speed_limit().await, None);
+        assert_eq!(engine.get_expected_sha256().await, None);
+        assert_eq!(engine.get_buffering().await, BufferingStrategy::Memory);
+    }
+
+    #[tokio::test]
+    async fn setters_reach_the_download() {
+        let transport = Arc::new(MockTransport::new(1024));
+        let (builder, out_dir) = mock_builder(&transport, "setters");
+        let sha256 = "AB".repeat(32);
+        let engine = builder
+            .connections(2)
+            .method("post")
+            .body("payload")
+            .file_name("renamed.bin")
+            .expected_sha256(&sha256)
+            .referer("https://example.com/")
+            .speed_limit(1024 * 1024)
+            .buffering(BufferingStrategy::PartFiles)
+            .build()
+            .await
+            .unwrap();
+
+        assert_eq!(engine.get_connections().await, Some(2));
+        assert_eq!(engine.get_method().await, Method::POST);
+        assert_eq!(engine.get_file_path().await, Some(out_dir.join("renamed.bin")));
+        assert_eq!(engine.get_expected_sha256().await, Some(sha256.to_ascii_lowercase()));
+        assert_eq!(engine.get_referer().await.as_deref(), Some("https://example.com/"));
+        assert_eq!(engine.get_speed_limit().await, Some(1024 * 1024));
+        assert_eq!(engine.get_buffering().await, BufferingStrategy::PartFiles);
+    }
+
+    #[tokio::test]
+    async fn missing_url_or_out_dir_is_an_error() {
+        let transport = Arc::new(MockTransport::new(1024));
+        let (_, out_dir) = mock_builder(&transport, "missing");
+
+        let no_url = RustleDownloader::builder().out_dir(&out_dir).transport(transport.clone()).build().await;
+        assert_eq!(no_url.unwrap_err(), "No URL was supplied");
+        let no_out_dir = RustleDownloader::builder().url("http://mock.test/file.bin").transport(transport.clone()).build().await;
+        assert_eq!(no_out_dir.unwrap_err(), "No output directory was supplied");
+        let empty_out_dir = RustleDownloader::builder().url("http://mock.test/file.bin").out_dir("").transport(transport).build().await;
+        assert_eq!(empty_out_dir.unwrap_err(), "No output directory was supplied");
+    }
+
+    #[tokio::test]
+    async fn invalid_options_are_rejected() {
+        let transport = Arc::new(MockTransport::new(1024));
+        let (builder, _) = mock_builder(&transport, "invalid");
+
+        let error = builder.clone().url("not a url").build().await.unwrap_err();
+        assert!(error.starts_with("Invalid URL not a url"), "{}", error);
+        let error = builder.clone().connections(0).build().await.unwrap_err();
+        assert_eq!(error, "Number of connections must be greater than zero");
+        let error = builder.clone().max_connections(0).build().await.unwrap_err();
+        assert_eq!(error, "Number of connections must be greater than zero");
+        let error = builder.clone().expected_sha256("abc").build().await.unwrap_err();
+        assert_eq!(error, "`abc` isn't a valid SHA-256 checksum");
+        let error = builder.header("bad header", "value").build().await.unwrap_err();
+        assert!(error.starts_with("Invalid header name `bad header`"), "{}", error);
+    }
+}
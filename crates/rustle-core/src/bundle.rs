@@ -0,0 +1,470 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, REFERER};
+use toml_edit::{value, Array, ArrayOfTables, DocumentMut, Item, Table, TableLike};
+use super::audit::is_sha256_hex;
+use super::downloader::{RustleDownloader, ValidUrl};
+use super::resume::{ResumePart, ResumeToken};
+
+/// Extension of bundle files, e.g. `ubuntu.iso.rustle`.
+pub const BUNDLE_EXTENSION: &str = "rustle";
+
+/// Version of the bundle format of bundles without settings nor session, readable by older versions.
+const BUNDLE_FORMAT: i64 = 1;
+
+/// Version of the bundle format adding the `settings` and `session` tables.
+const SESSION_BUNDLE_FORMAT: i64 = 2;
+
+/// DownloadBundle represents a `.rustle` file: a self-contained description of a download
+/// that can be shared and opened elsewhere to queue the same download, verified by its checksum.
+///
+/// It's a small TOML file:
+///
+/// ```toml
+/// format = 1
+/// urls = ["https://example.com/file.iso", "https://mirror.example.com/file.iso"]
+/// file_name = "file.iso"
+/// sha256 = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08"
+///
+/// [headers]
+/// Cookie = ""
+/// ```
+///
+/// Headers with an empty value are placeholders, e.g. for a session cookie that shouldn't be
+/// shared, they have to be filled in by the receiver before the bundle can be opened.
+///
+/// A session bundle (format 2), written by `from_download()`, also holds the settings of the
+/// download and its state with the bytes received so far, so opening it continues the download
+/// where it was, e.g. to hand it off from the terminal interface to the GUI:
+///
+/// ```toml
+/// format = 2
+/// urls = ["https://example.com/file.iso"]
+/// file_name = "file.iso"
+///
+/// [headers]
+/// Referer = "https://example.com/downloads"
+///
+/// [settings]
+/// connections = 4             # optional, picked from the size of the file if missing
+/// speed_limit = 1048576       # optional, in bytes per second
+///
+/// [session]
+/// out_dir = "/home/me/Downloads"
+/// content_length = 4194304    # optional
+/// content_type = "application/x-iso9660-image"  # optional
+/// accepts_ranges = true
+/// etag = "\"5e8f-1a2b\""      # optional, like last_modified, checked before continuing
+/// method = "POST"             # optional, with the base64 `body` sent with it
+///
+/// [[session.parts]]
+/// start = 0                   # first and last byte of the range
+/// end = 2097151
+/// done = true
+/// data = "<base64>"           # bytes received so far, from `start`
+/// digests = ["<sha256>"]      # optional, SHA-256 of each segment of `data`
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DownloadBundle {
+    pub urls: Vec<String>,                  // URL of the file, followed by mirrors tried in order
+    pub file_name: Option<String>,          // Name of the downloaded file, detected from the response if missing
+    pub sha256: Option<String>,             // Expected SHA-256 of the file
+    pub headers: BTreeMap<String, String>,  // Extra request headers, an empty value is a placeholder
+    pub connections: Option<u8>,            // Number of connections of the download, picked from the size of the file if missing
+    pub speed_limit: Option<u64>,           // Speed limit of the download in bytes per second
+    pub session: Option<ResumeToken>,       // State of an unfinished download, continued from the first URL instead of starting over
+}
+
+impl DownloadBundle {
+    /// Creates a bundle downloading the file at `url`.
+    pub fn new(url: &str) -> DownloadBundle {
+        DownloadBundle { urls: vec![url.to_string()], ..Default::default() }
+    }
+
+    /// Creates a session bundle continuing a download, with its settings and the bytes received
+    /// so far. The download must be paused or stopped, see `RustleDownloader::resume_token()`.
+    pub async fn from_download(engine: &RustleDownloader) -> Result<DownloadBundle, String> {
+        let session = engine.resume_token().await?;
+        // Written with the headers, like the one of a shared bundle, and read back into the session
+        let referer = session.referer.clone();
+        Ok(DownloadBundle {
+            urls: vec![session.url.clone()],
+            file_name: session.file_name.clone(),
+            sha256: session.expected_sha256.clone(),
+            headers: referer.map(|referer| (REFERER.to_string(), referer)).into_iter().collect(),
+            connections: engine.get_connections().await,
+            speed_limit: engine.get_speed_limit().await,
+            session: Some(session),
+        })
+    }
+
+    /// Returns the path the session bundle of a download is saved to, next to its file,
+    /// e.g. `file.iso.rustle`.
+    pub fn path_for(file_path: &Path) -> PathBuf {
+        let mut path = file_path.as_os_str().to_owned();
+        path.push(format!(".{}", BUNDLE_EXTENSION));
+        PathBuf::from(path)
+    }
+
+    /// Loads a bundle from a `.rustle` file.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the bundle file.
+    ///
+    /// Returns an error if the file couldn't be read or isn't a valid bundle.
+    pub fn load(path: &Path) -> Result<DownloadBundle, String> {
+        let source = fs::read_to_string(path).map_err(|e| format!("Couldn't read the bundle {}, error : {}", path.display(), e))?;
+        DownloadBundle::from_toml(&source).map_err(|e| format!("Invalid bundle {}, {}", path.display(), e))
+    }
+
+    /// Saves the bundle to a `.rustle` file, overwriting it if it exists.
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        fs::write(path, self.to_toml()).map_err(|e| format!("Couldn't write the bundle {}, error : {}", path.display(), e))
+    }
+
+    /// Parses a bundle from the content of a `.rustle` file, see `DownloadBundle`.
+    /// Unknown keys are ignored.
+    pub fn from_toml(source: &str) -> Result<DownloadBundle, String> {
+        let document = source.parse::<DocumentMut>().map_err(|e| format!("error : {}", e))?;
+
+        match document.get("format").and_then(Item::as_integer) {
+            Some(BUNDLE_FORMAT | SESSION_BUNDLE_FORMAT) => {},
+            Some(format) => return Err(format!("unsupported format version {}", format)),
+            None => return Err(String::from("missing `format` version")),
+        }
+
+        let urls = document.get("urls")
+            .and_then(Item::as_array)
+            .ok_or("`urls` must be an array of strings")?
+            .iter()
+            .map(|url| url.as_str().map(String::from).ok_or("`urls` must be an array of strings"))
+            .collect::<Result<Vec<String>, _>>()?;
+        if urls.is_empty() {
+            return Err(String::from("`urls` must contain at least one URL"));
+        }
+        for url in &urls {
+            ValidUrl::new(url).map_err(|e| format!("invalid URL `{}`, error : {}", url, e))?;
+        }
+
+        let string = |key: &str| -> Result<Option<String>, String> {
+            match document.get(key) {
+                None => Ok(None),
+                Some(item) => item.as_str().map(|s| Some(s.to_string())).ok_or(format!("`{}` must be a string", key)),
+            }
+        };
+        let file_name = string("file_name")?;
+        let sha256 = string("sha256")?;
+        if let Some(sha256) = sha256.as_ref().filter(|sha256| !is_sha256_hex(sha256)) {
+            return Err(format!("`{}` isn't a valid SHA-256 checksum", sha256));
+        }
+
+        let mut headers = BTreeMap::new();
+        if let Some(item) = document.get("headers") {
+            let table = item.as_table_like().ok_or("`headers` must be a table")?;
+            for (name, item) in table.iter() {
+                let header_value = item.as_str().ok_or(format!("header `{}` must be a string", name))?;
+                HeaderName::from_bytes(name.as_bytes()).map_err(|_| format!("invalid header name `{}`", name))?;
+                HeaderValue::from_str(header_value).map_err(|_| format!("invalid value for header `{}`", name))?;
+                headers.insert(name.to_string(), header_value.to_string());
+            }
+        }
+
+        let mut connections = None;
+        let mut speed_limit = None;
+        if let Some(item) = document.get("settings") {
+            let table = item.as_table_like().ok_or("`settings` must be a table")?;
+            connections = optional_integer(table, "connections")?
+                .map(|connections| u8::try_from(connections).ok().filter(|connections| *connections > 0).ok_or("`connections` must be between 1 and 255"))
+                .transpose()?;
+            speed_limit = optional_integer(table, "speed_limit")?
+                .map(|speed_limit| u64::try_from(speed_limit).ok().filter(|speed_limit| *speed_limit > 0).ok_or("`speed_limit` must be greater than zero"))
+                .transpose()?;
+        }
+
+        let session = match document.get("session") {
+            None => None,
+            Some(item) => {
+                let table = item.as_table_like().ok_or("`session` must be a table")?;
+                let referer = headers.iter().find(|(name, referer)| name.eq_ignore_ascii_case(REFERER.as_str()) && !referer.is_empty()).map(|(_, referer)| referer.clone());
+                let session = parse_session(table, &urls[0], &file_name, &sha256, referer).map_err(|e| format!("invalid `session`, {}", e))?;
+                Some(session)
+            },
+        };
+
+        Ok(DownloadBundle { urls, file_name, sha256, headers, connections, speed_limit, session })
+    }
+
+    /// Returns the content of the `.rustle` file of the bundle.
+    pub fn to_toml(&self) -> String {
+        let mut document = DocumentMut::new();
+        let has_settings = self.connections.is_some() || self.speed_limit.is_some();
+        document["format"] = value(if has_settings || self.session.is_some() { SESSION_BUNDLE_FORMAT } else { BUNDLE_FORMAT });
+        document["urls"] = value(self.urls.iter().collect::<Array>());
+        if let Some(file_name) = self.file_name.as_ref() {
+            document["file_name"] = value(file_name);
+        }
+        if let Some(sha256) = self.sha256.as_ref() {
+            document["sha256"] = value(sha256);
+        }
+
+        // Always written, so the receiver knows where placeholders go
+        let mut headers = Table::new();
+        for (name, header_value) in &self.headers {
+            headers[name.as_str()] = value(header_value);
+        }
+        headers.decor_mut().set_prefix("\n# Extra request headers, fill in the empty values before opening the bundle\n");
+        document["headers"] = Item::Table(headers);
+
+        if has_settings {
+            let mut settings = Table::new();
+            if let Some(connections) = self.connections {
+                settings["connections"] = value(connections as i64);
+            }
+            if let Some(speed_limit) = self.speed_limit {
+                settings["speed_limit"] = value(speed_limit as i64);
+            }
+            document["settings"] = Item::Table(settings);
+        }
+        if let Some(session) = self.session.as_ref() {
+            let mut table = session_table(session);
+            table.decor_mut().set_prefix("\n# State of the download and the bytes received so far, it continues from them\n");
+            document["session"] = Item::Table(table);
+        }
+
+        format!("# Rustle download bundle, open it with Rustle to queue the download\n{}", document)
+    }
+
+    /// Returns the names of the headers that are still placeholders.
+    pub fn missing_headers(&self) -> Vec<&str> {
+        self.headers.iter()
+            .filter(|(_, header_value)| header_value.is_empty())
+            .map(|(name, _)| name.as_str())
+            .collect()
+    }
+
+    /// Configures the engine from the bundle (headers, file name, checksum and settings) and initializes it,
+    /// trying each URL in order until one of them succeeds. A session bundle continues the download
+    /// from its session instead, without a request.
+    ///
+    /// # Arguments
+    ///
+    /// * `engine` - The engine to configure, its output directory must already be set.
+    ///
+    /// Returns an error if a header is still a placeholder or none of the URLs could be initialized.
+    pub async fn apply(&self, engine: &mut RustleDownloader) -> Result<(), String> {
+        let missing = self.missing_headers();
+        if !missing.is_empty() {
+            return Err(format!("Fill in the {} header(s) in the bundle before opening it", missing.join(", ")));
+        }
+
+        let mut headers = HeaderMap::new();
+        for (name, header_value) in &self.headers {
+            let name = HeaderName::from_bytes(name.as_bytes()).map_err(|_| format!("Invalid header name `{}`", name))?;
+            let header_value = HeaderValue::from_str(header_value).map_err(|_| format!("Invalid value for header `{}`", name))?;
+            headers.append(name, header_value);
+        }
+        engine.set_headers(headers).await;
+
+        if let Some(file_name) = self.file_name.as_ref() {
+            engine.set_file_name(file_name).await;
+        }
+        if let Some(sha256) = self.sha256.as_ref() {
+            engine.set_expected_sha256(sha256).await?;
+        }
+        if let Some(connections) = self.connections {
+            engine.set_connections(connections).await?;
+        }
+        if self.speed_limit.is_some() {
+            engine.set_speed_limit(self.speed_limit).await?;
+        }
+        if let Some(session) = self.session.as_ref() {
+            engine.set_resume_token(session.clone()).await?;
+            return Ok(());
+        }
+
+        let mut errors = Vec::new();
+        for url in &self.urls {
+            let result = match engine.set_url(url).await {
+                Ok(_) => engine.init().await,
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(_) => return Ok(()),
+                Err(e) => errors.push(format!("{} : {}", url, e)),
+            }
+        }
+        Err(format!("Couldn't initialize the download from any URL of the bundle, {}", errors.join(" | ")))
+    }
+}
+
+/// Returns the integer of a table, if it's present.
+fn optional_integer(table: &dyn TableLike, key: &str) -> Result<Option<i64>, String> {
+    match table.get(key) {
+        None => Ok(None),
+        Some(item) => item.as_integer().map(Some).ok_or(format!("`{}` must be an integer", key)),
+    }
+}
+
+/// Returns the string of a table, if it's present.
+fn optional_string(table: &dyn TableLike, key: &str) -> Result<Option<String>, String> {
+    match table.get(key) {
+        None => Ok(None),
+        Some(item) => item.as_str().map(|s| Some(s.to_string())).ok_or(format!("`{}` must be a string", key)),
+    }
+}
+
+/// Returns the byte offset of a table.
+fn byte_offset(table: &dyn TableLike, key: &str) -> Result<u64, String> {
+    optional_integer(table, key)?
+        .and_then(|offset| u64::try_from(offset).ok())
+        .ok_or(format!("`{}` must be a byte offset", key))
+}
+
+/// Decodes base64 data of a table, empty if it's missing.
+fn base64_data(table: &dyn TableLike, key: &str) -> Result<Vec<u8>, String> {
+    optional_string(table, key)?
+        .map(|data| STANDARD.decode(data).map_err(|e| format!("`{}` isn't valid base64, error : {}", key, e)))
+        .transpose()
+        .map(Option::unwrap_or_default)
+}
+
+/// Parses the `session` table of a bundle into the resume token of its download, see `DownloadBundle`.
+fn parse_session(table: &dyn TableLike, url: &str, file_name: &Option<String>, sha256: &Option<String>, referer: Option<String>) -> Result<ResumeToken, String> {
+    let out_dir = optional_string(table, "out_dir")?.ok_or("missing `out_dir`")?;
+    let content_length = optional_integer(table, "content_length")?
+        .map(|length| u64::try_from(length).map_err(|_| String::from("`content_length` must be a byte length")))
+        .transpose()?;
+    let accepts_ranges = match table.get("accepts_ranges") {
+        None => false,
+        Some(item) => item.as_bool().ok_or("`accepts_ranges` must be a boolean")?,
+    };
+
+    let mut parts = Vec::new();
+    if let Some(item) = table.get("parts") {
+        let tables = item.as_array_of_tables().ok_or("`parts` must be an array of tables")?;
+        for part in tables.iter() {
+            let digests = match part.get("digests") {
+                None => Vec::new(),
+                Some(item) => item.as_array()
+                    .and_then(|digests| digests.iter().map(|digest| digest.as_str().map(String::from)).collect::<Option<Vec<_>>>())
+                    .ok_or("`digests` must be an array of strings")?,
+            };
+            parts.push(ResumePart {
+                start_byte: byte_offset(part, "start")?,
+                end_byte: byte_offset(part, "end")?,
+                done: part.get("done").and_then(Item::as_bool).unwrap_or(false),
+                data: base64_data(part, "data")?,
+                digests,
+            });
+        }
+    }
+
+    let token = ResumeToken {
+        url: url.to_string(),
+        out_dir: PathBuf::from(out_dir),
+        file_name: file_name.clone(),
+        expected_sha256: sha256.clone(),
+        content_length,
+        content_type: optional_string(table, "content_type")?,
+        accepts_ranges,
+        etag: optional_string(table, "etag")?,
+        last_modified: optional_string(table, "last_modified")?,
+        method: optional_string(table, "method")?,
+        body: base64_data(table, "body")?,
+        referer,
+        parts,
+    };
+    token.validate()?;
+    Ok(token)
+}
+
+/// Returns the `session` table of a bundle holding a resume token, see `DownloadBundle`.
+/// The URL, file name, checksum and referer of the token are the ones of the bundle.
+fn session_table(session: &ResumeToken) -> Table {
+    let mut table = Table::new();
+    table["out_dir"] = value(session.out_dir.to_string_lossy().as_ref());
+    if let Some(content_length) = session.content_length {
+        table["content_length"] = value(content_length as i64);
+    }
+    if let Some(content_type) = session.content_type.as_ref() {
+        table["content_type"] = value(content_type);
+    }
+    table["accepts_ranges"] = value(session.accepts_ranges);
+    if let Some(etag) = session.etag.as_ref() {
+        table["etag"] = value(etag);
+    }
+    if let Some(last_modified) = session.last_modified.as_ref() {
+        table["last_modified"] = value(last_modified);
+    }
+    if let Some(method) = session.method.as_ref() {
+        table["method"] = value(method);
+        if !session.body.is_empty() {
+            table["body"] = value(STANDARD.encode(&session.body));
+        }
+    }
+
+    let mut parts = ArrayOfTables::new();
+    for part in &session.parts {
+        let mut part_table = Table::new();
+        part_table["start"] = value(part.start_byte as i64);
+        part_table["end"] = value(part.end_byte as i64);
+        part_table["done"] = value(part.done);
+        part_table["data"] = value(STANDARD.encode(&part.data));
+        if !part.digests.is_empty() {
+            part_table["digests"] = value(part.digests.iter().collect::<Array>());
+        }
+        parts.push(part_table);
+    }
+    if !parts.is_empty() {
+        table["parts"] = Item::ArrayOfTables(parts);
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn session_bundle_continues_the_download() {
+        let token = ResumeToken {
+            url: String::from("https://example.com/file.bin"),
+            out_dir: "./".into(),
+            file_name: Some(String::from("file.bin")),
+            content_length: Some(8),
+            accepts_ranges: true,
+            etag: Some(String::from("\"abc\"")),
+            parts: vec![
+                ResumePart { start_byte: 0, end_byte: 3, done: true, data: b"abcd".to_vec(), digests: Vec::new() },
+                ResumePart { start_byte: 4, end_byte: 7, done: false, data: b"ef".to_vec(), digests: Vec::new() },
+            ],
+            referer: Some(String::from("https://example.com/downloads")),
+            ..Default::default()
+        };
+        let mut engine = RustleDownloader::from_resume_token(token.clone(), 2).unwrap();
+        engine.set_connections(3).await.unwrap();
+        engine.set_speed_limit(Some(1024)).await.unwrap();
+
+        let bundle = DownloadBundle::from_download(&engine).await.unwrap();
+        let toml = bundle.to_toml();
+        assert!(toml.contains("format = 2"));
+        let bundle = DownloadBundle::from_toml(&toml).unwrap();
+        assert_eq!((bundle.connections, bundle.speed_limit), (Some(3), Some(1024)));
+        assert_eq!(bundle.headers.get("referer").map(String::as_str), Some("https://example.com/downloads"));
+
+        // Opened with another engine, the download continues from the same bytes without a request
+        let mut opened = RustleDownloader::new(8).unwrap();
+        bundle.apply(&mut opened).await.unwrap();
+        assert_eq!(opened.get_connections().await, Some(3));
+        assert_eq!(opened.get_speed_limit().await, Some(1024));
+        assert_eq!(opened.resume_token().await.unwrap(), token);
+
+        // Bundles without settings nor session keep the first format
+        assert!(DownloadBundle::new("https://example.com/file.bin").to_toml().contains("format = 1"));
+        assert!(DownloadBundle::from_toml("format = 2\nurls = [\"https://example.com/file.bin\"]\n[session]\n").unwrap_err().contains("out_dir"));
+    }
+}
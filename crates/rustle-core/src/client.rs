@@ -0,0 +1,318 @@
+use std::fs;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use bytes::Bytes;
+use reqwest::{Certificate, Client, Identity, Method, NoProxy, Proxy};
+use reqwest::header::{HeaderMap, HeaderValue, REFERER};
+use super::dns::{DnsOptions, DnsResolver};
+use super::encoding::ContentEncodingMode;
+
+/// TlsOptions represents the TLS configuration of the HTTP client.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TlsOptions {
+    pub extra_root_certificates: Vec<PathBuf>,  // PEM (bundle) or DER files trusted on top of the system roots
+    pub accept_invalid_certs: bool,             // Accept invalid/self-signed certificates, only meant for internal servers
+    pub client_certificate: Option<PathBuf>,    // PEM certificate presented to mTLS-protected endpoints
+    pub client_key: Option<PathBuf>,            // PKCS#8 PEM private key of the client certificate
+}
+
+/// Represents the preferred IP address family used to connect to servers.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum AddressFamily {
+    /// Use the addresses in the order returned by the system resolver.
+    #[default]
+    Auto,
+    /// Try IPv4 addresses first, then IPv6 ones.
+    PreferIpv4,
+    /// Try IPv6 addresses first, then IPv4 ones.
+    PreferIpv6,
+    /// Only connect over IPv4.
+    Ipv4Only,
+}
+
+impl AddressFamily {
+    /// All the address family preferences, in the order they are presented to the user.
+    pub const ALL: [AddressFamily; 4] = [AddressFamily::Auto, AddressFamily::PreferIpv4, AddressFamily::PreferIpv6, AddressFamily::Ipv4Only];
+
+    /// Returns the name used for the preference in the settings file.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AddressFamily::Auto => "auto",
+            AddressFamily::PreferIpv4 => "prefer_ipv4",
+            AddressFamily::PreferIpv6 => "prefer_ipv6",
+            AddressFamily::Ipv4Only => "ipv4_only",
+        }
+    }
+}
+
+impl fmt::Display for AddressFamily {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for AddressFamily {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        AddressFamily::ALL.into_iter()
+            .find(|family| family.as_str() == s)
+            .ok_or_else(|| format!("Unknown address family `{}`", s))
+    }
+}
+
+/// Represents the HTTP versions the client may use.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum HttpVersion {
+    /// HTTP/2 if the server offers it during the TLS handshake, HTTP/1.1 otherwise.
+    #[default]
+    Auto,
+    /// Only HTTP/1.1, for servers with a buggy HTTP/2 implementation.
+    Http1,
+    /// Only HTTP/2, the parts of a download share a single multiplexed connection.
+    Http2,
+}
+
+impl HttpVersion {
+    /// All the HTTP version choices, in the order they are presented to the user.
+    pub const ALL: [HttpVersion; 3] = [HttpVersion::Auto, HttpVersion::Http1, HttpVersion::Http2];
+
+    /// Returns the name used for the choice in the settings file.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HttpVersion::Auto => "auto",
+            HttpVersion::Http1 => "http1",
+            HttpVersion::Http2 => "http2",
+        }
+    }
+}
+
+impl fmt::Display for HttpVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for HttpVersion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        HttpVersion::ALL.into_iter()
+            .find(|version| version.as_str() == s)
+            .ok_or_else(|| format!("Unknown HTTP version `{}`", s))
+    }
+}
+
+/// ClientOptions represents the configuration used to build the engine's HTTP client.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ClientOptions {
+    pub tls: TlsOptions,                    // TLS configuration
+    pub address_family: AddressFamily,      // Preferred IP address family
+    pub dns: DnsOptions,                    // Servers the hostnames are resolved with, the system resolver by default
+    pub headers: HeaderMap,                 // Extra headers sent with every request, e.g. cookies or authorization
+    pub proxy: Option<String>,              // URL of the proxy all requests go through, e.g. `http://proxy:3128` or `socks5://proxy:1080`
+    pub ignore_env_proxy: bool,             // Whether the proxies of the environment are ignored when `proxy` isn't set, see `EnvProxy`
+    pub user_agent: Option<String>,         // `User-Agent` header of the requests, reqwest's default if `None`
+    pub referer: Option<String>,            // `Referer` header of the requests, e.g. the page the file was found on, overriding the one of `headers`
+    pub http_version: HttpVersion,          // HTTP versions the client may use
+    pub method: Method,                     // Method of the download requests, `GET` by default
+    pub body: Option<Bytes>,                // Body sent with the download requests, e.g. the JSON of an export API
+    pub content_encoding: ContentEncodingMode,  // Whether compressed responses of single connection downloads are decoded
+}
+
+impl ClientOptions {
+    /// Returns the headers sent with every request: the extra headers, with the `Referer` if it's set.
+    pub fn request_headers(&self) -> HeaderMap {
+        let mut headers = self.headers.clone();
+        if let Some(referer) = self.referer.as_deref().and_then(|referer| HeaderValue::from_str(referer).ok()) {
+            headers.insert(REFERER, referer);
+        }
+        headers
+    }
+}
+
+/// EnvProxy represents the proxies configured with the `HTTP_PROXY`, `HTTPS_PROXY`, `ALL_PROXY`
+/// and `NO_PROXY` environment variables, like curl the lowercase names take precedence.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EnvProxy {
+    pub http: Option<String>,       // Proxy of the `http` URLs
+    pub https: Option<String>,      // Proxy of the `https` URLs
+    pub all: Option<String>,        // Proxy of the URLs the other ones don't cover
+    pub no_proxy: Option<String>,   // Hosts, domains and IP ranges connected to directly, separated by commas, e.g. `localhost,.corp.example,10.0.0.0/8`
+}
+
+impl EnvProxy {
+    /// Reads the proxies of the process' environment.
+    pub fn from_env() -> Self {
+        EnvProxy::from_vars(|name| std::env::var(name).ok())
+    }
+
+    /// Reads the proxies from the variables returned by `var`, empty ones are ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `var` - Returns the value of an environment variable, `None` if it isn't set.
+    pub fn from_vars(var: impl Fn(&str) -> Option<String>) -> Self {
+        let get = |name: &str| var(&name.to_ascii_lowercase()).or_else(|| var(name))
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty());
+        EnvProxy {
+            http: get("HTTP_PROXY"),
+            https: get("HTTPS_PROXY"),
+            all: get("ALL_PROXY"),
+            no_proxy: get("NO_PROXY"),
+        }
+    }
+
+    /// Returns the proxies of the client, each skipping the hosts of `no_proxy`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error message naming the variable if a proxy URL is invalid.
+    pub fn proxies(&self) -> Result<Vec<Proxy>, String> {
+        let no_proxy = self.no_proxy.as_deref().and_then(NoProxy::from_string);
+        let mut proxies = Vec::new();
+        for (name, url) in [("HTTP_PROXY", &self.http), ("HTTPS_PROXY", &self.https), ("ALL_PROXY", &self.all)] {
+            let Some(url) = url.as_deref() else { continue };
+            let proxy = match name {
+                "HTTP_PROXY" => Proxy::http(url),
+                "HTTPS_PROXY" => Proxy::https(url),
+                _ => Proxy::all(url),
+            };
+            let proxy = proxy.map_err(|e| format!("Invalid proxy {} in {}, error : {}", url, name, e))?;
+            proxies.push(proxy.no_proxy(no_proxy.clone()));
+        }
+        Ok(proxies)
+    }
+}
+
+/// Builds an HTTP client from the given options.
+///
+/// # Arguments
+///
+/// * `options` - The options to apply to the client.
+///
+/// # Returns
+///
+/// * `Result<Client, String>` - The configured client, or an error message if a certificate
+///   couldn't be loaded or the client couldn't be built.
+pub fn build_client(options: &ClientOptions) -> Result<Client, String> {
+    let mut builder = Client::builder();
+
+    // TLS
+    for path in &options.tls.extra_root_certificates {
+        for certificate in read_certificates(path)? {
+            builder = builder.add_root_certificate(certificate);
+        }
+    }
+
+    match (&options.tls.client_certificate, &options.tls.client_key) {
+        (Some(certificate_path), Some(key_path)) => {
+            let certificate = read_file(certificate_path)?;
+            let key = read_file(key_path)?;
+            let identity = Identity::from_pkcs8_pem(&certificate, &key)
+                .map_err(|e| format!("Couldn't load the client certificate {}, error : {}", certificate_path.display(), e))?;
+            builder = builder.identity(identity);
+        },
+        (None, None) => {},
+        _ => {
+            return Err(String::from("Both a client certificate and its private key are required for mTLS"));
+        },
+    }
+
+    builder = builder.danger_accept_invalid_certs(options.tls.accept_invalid_certs);
+
+    // Resolver and address family, the parts share the resolver and its cache along with the client
+    builder = builder.dns_resolver(Arc::new(DnsResolver::new(options.address_family, options.dns.clone())));
+    if options.address_family == AddressFamily::Ipv4Only {
+        // Also covers URLs with a literal IPv6 address, which skip the resolver
+        builder = builder.local_address(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+    }
+
+    // Proxy and user agent, the proxy of the options overrides the ones of the environment
+    if let Some(proxy) = options.proxy.as_ref() {
+        let proxy = Proxy::all(proxy).map_err(|e| format!("Invalid proxy {}, error : {}", proxy, e))?;
+        builder = builder.proxy(proxy);
+    } else {
+        // reqwest's own lookup of the environment is replaced, so it can be ignored
+        builder = builder.no_proxy();
+        if !options.ignore_env_proxy {
+            for proxy in EnvProxy::from_env().proxies()? {
+                builder = builder.proxy(proxy);
+            }
+        }
+    }
+    if let Some(user_agent) = options.user_agent.as_ref() {
+        builder = builder.user_agent(user_agent);
+    }
+
+    // HTTP version, HTTP/2 is negotiated with ALPN by default
+    builder = match options.http_version {
+        HttpVersion::Auto => builder,
+        HttpVersion::Http1 => builder.http1_only(),
+        HttpVersion::Http2 => builder.http2_prior_knowledge(),
+    };
+
+    // Extra headers and referer, a request setting the same header (e.g. `Range`) overrides them
+    let headers = options.request_headers();
+    if !headers.is_empty() {
+        builder = builder.default_headers(headers);
+    }
+
+    builder.build().map_err(|e| format!("Couldn't build the HTTP client, error : {}", e))
+}
+
+/// Reads the certificates of a PEM bundle, or a single DER certificate.
+fn read_certificates(path: &PathBuf) -> Result<Vec<Certificate>, String> {
+    let bytes = read_file(path)?;
+
+    Certificate::from_pem_bundle(&bytes)
+        .ok()
+        .filter(|certificates| !certificates.is_empty())
+        .or_else(|| Certificate::from_der(&bytes).ok().map(|certificate| vec![certificate]))
+        .ok_or_else(|| format!("{} isn't a valid PEM or DER certificate", path.display()))
+}
+
+/// Reads a whole file, with an error message naming the file.
+fn read_file(path: &PathBuf) -> Result<Vec<u8>, String> {
+    fs::read(path).map_err(|e| format!("Couldn't read {}, error : {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn proxies_are_read_from_the_environment() {
+        let vars = [("HTTP_PROXY", "http://upper:3128"), ("http_proxy", "http://lower:3128"), ("HTTPS_PROXY", " "),
+            ("ALL_PROXY", "socks5://127.0.0.1:1080"), ("NO_PROXY", "localhost,.corp.example")];
+        let env_proxy = EnvProxy::from_vars(|name| vars.iter().find(|(var, _)| *var == name).map(|(_, value)| value.to_string()));
+        assert_eq!(env_proxy, EnvProxy {
+            http: Some(String::from("http://lower:3128")),
+            https: None,
+            all: Some(String::from("socks5://127.0.0.1:1080")),
+            no_proxy: Some(String::from("localhost,.corp.example")),
+        });
+        assert_eq!(env_proxy.proxies().unwrap().len(), 2);
+        assert_eq!(EnvProxy::from_vars(|_| None).proxies().unwrap().len(), 0);
+
+        let invalid = EnvProxy { https: Some(String::from("proxy host:3128")), ..Default::default() };
+        assert!(invalid.proxies().unwrap_err().contains("HTTPS_PROXY"));
+    }
+
+    #[test]
+    fn referer_overrides_the_extra_headers() {
+        let mut options = ClientOptions::default();
+        options.headers.insert(REFERER, HeaderValue::from_static("https://example.com/"));
+        options.headers.insert("cookie", HeaderValue::from_static("session=1"));
+        assert_eq!(options.request_headers(), options.headers);
+
+        options.referer = Some(String::from("https://example.com/gallery/1"));
+        let headers = options.request_headers();
+        assert_eq!(headers.get(REFERER).unwrap(), "https://example.com/gallery/1");
+        assert_eq!(headers.len(), 2);
+    }
+}
@@ -0,0 +1,553 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::sync::{oneshot, watch, OwnedSemaphorePermit, Semaphore};
+use tokio::task;
+use url::Url;
+use super::downloader::InitStrategy;
+use super::memory::MemoryBudget;
+use super::rate_limit::RateLimiter;
+use super::usage::DataUsage;
+
+/// Number of consecutive connection failures, across all downloads, after which the network is considered down.
+pub const OFFLINE_FAILURE_THRESHOLD: usize = 3;
+
+/// Time between two connectivity probes while the network is down, also the timeout of a probe.
+pub const NETWORK_PROBE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// NetworkState represents whether the downloads can reach the network.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum NetworkState {
+    #[default]
+    Online,     // Connections succeed, or fail for reasons other than connectivity
+    Offline,    // Connections keep failing, the downloads wait for the network to come back
+}
+
+/// ConnectionManager keeps the per-host state shared by downloads: it limits the number of
+/// concurrent connections opened to the same host, shares them fairly between the downloads,
+/// and remembers how each host can be probed. The connections to all the hosts can be capped too,
+/// see `set_total_limit`.
+/// It also follows the connectivity of the downloads, after `OFFLINE_FAILURE_THRESHOLD` consecutive
+/// connection failures the network is considered down until a probe or a connection succeeds.
+/// A single instance is meant to be shared (through an `Arc`) by all active downloads.
+#[derive(Debug)]
+pub struct ConnectionManager {
+    per_host_limit: Mutex<usize>,                   // Maximum number of concurrent connections to a single host
+    hosts: Mutex<HashMap<String, Arc<HostSlots>>>,  // Connection slots of each host
+    init_strategies: Mutex<HashMap<String, InitStrategy>>,  // Init strategy that last succeeded for each host
+    tuned_connections: Mutex<HashMap<String, usize>>,   // Number of connections the tuning last settled on for each host
+    network: watch::Sender<NetworkState>,           // Current connectivity of the downloads
+    failures: Mutex<(usize, Option<(String, u16)>)>,    // Consecutive connection failures, and the address of the last one, probed while offline
+    speed_limit: Mutex<Option<Arc<RateLimiter>>>,   // Limits the total throughput of the downloads
+    memory_budget: Mutex<Option<Arc<MemoryBudget>>>,    // Bounds the bytes the downloads buffer in memory
+    total_limit: Mutex<Option<(usize, Arc<Semaphore>)>>,    // Maximum number of concurrent connections to all the hosts, and the semaphore enforcing it
+    data_usage: Mutex<Option<Arc<DataUsage>>>,      // Counts the bytes received by the downloads, and pauses them once its quota is reached
+}
+
+impl ConnectionManager {
+    /// Creates a new ConnectionManager.
+    ///
+    /// # Arguments
+    ///
+    /// * `per_host_limit` - The maximum number of concurrent connections to a single host.
+    ///
+    /// Returns an error if the limit is zero.
+    pub fn new(per_host_limit: usize) -> Result<ConnectionManager, String> {
+        if per_host_limit == 0 {
+            return Err(String::from("Per-host connection limit must be greater than zero"));
+        }
+        Ok(ConnectionManager { 
+            per_host_limit: Mutex::new(per_host_limit), 
+            hosts: Mutex::new(HashMap::new()), 
+            init_strategies: Mutex::new(HashMap::new()),
+            tuned_connections: Mutex::new(HashMap::new()),
+            network: watch::Sender::new(NetworkState::Online),
+            failures: Mutex::new((0, None)),
+            speed_limit: Mutex::new(None),
+            memory_budget: Mutex::new(None),
+            total_limit: Mutex::new(None),
+            data_usage: Mutex::new(None),
+        })
+    }
+
+    /// Returns the maximum number of concurrent connections to a single host.
+    pub fn per_host_limit(&self) -> usize {
+        *self.per_host_limit.lock().unwrap()
+    }
+
+    /// Changes the maximum number of concurrent connections to a single host, for the hosts
+    /// already connected to as well. A raised limit grants the new slots to the waiting requests
+    /// right away, with a lowered one the connections already open keep their slot until they're done.
+    ///
+    /// # Arguments
+    ///
+    /// * `per_host_limit` - The maximum number of concurrent connections to a single host.
+    ///
+    /// Returns an error if the limit is zero.
+    pub fn set_per_host_limit(&self, per_host_limit: usize) -> Result<(), String> {
+        if per_host_limit == 0 {
+            return Err(String::from("Per-host connection limit must be greater than zero"));
+        }
+        // Holding the lock while updating the hosts, so a host added meanwhile can't get the previous limit
+        let mut limit = self.per_host_limit.lock().unwrap();
+        *limit = per_host_limit;
+        let hosts = self.hosts.lock().unwrap();
+        for slots in hosts.values() {
+            slots.set_limit(per_host_limit);
+        }
+        Ok(())
+    }
+
+    /// Limits the total speed of the downloads sharing the manager, on top of their own limits.
+    /// The parts already running pick up the new limit with their next request.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes_per_second` - The maximum speed, `None` for no limit.
+    ///
+    /// Returns an error if the limit is zero.
+    pub fn set_speed_limit(&self, bytes_per_second: Option<u64>) -> Result<(), String> {
+        *self.speed_limit.lock().unwrap() = bytes_per_second.map(RateLimiter::new).transpose()?.map(Arc::new);
+        Ok(())
+    }
+
+    /// Returns the limiter of the total speed of the downloads, if any.
+    pub fn speed_limit(&self) -> Option<Arc<RateLimiter>> {
+        self.speed_limit.lock().unwrap().clone()
+    }
+
+    /// Bounds the total number of bytes the downloads sharing the manager buffer in memory.
+    /// Downloads of a known length reserve it at once, the others as they receive their chunks.
+    /// The downloads already holding memory keep it from the previous budget until they're done.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_bytes` - The maximum number of buffered bytes, `None` for no limit.
+    ///
+    /// Returns an error if the budget is zero.
+    pub fn set_memory_budget(&self, max_bytes: Option<u64>) -> Result<(), String> {
+        *self.memory_budget.lock().unwrap() = max_bytes.map(MemoryBudget::new).transpose()?.map(Arc::new);
+        Ok(())
+    }
+
+    /// Returns the budget of the memory buffering the downloads, if any.
+    pub fn memory_budget(&self) -> Option<Arc<MemoryBudget>> {
+        self.memory_budget.lock().unwrap().clone()
+    }
+
+    /// Counts the bytes received by the downloads sharing the manager in `data_usage`, the downloads
+    /// are paused while its quota is reached. The parts already running count their next chunks.
+    ///
+    /// # Arguments
+    ///
+    /// * `data_usage` - The usage counting the bytes, `None` to stop counting them.
+    pub fn set_data_usage(&self, data_usage: Option<Arc<DataUsage>>) {
+        *self.data_usage.lock().unwrap() = data_usage;
+    }
+
+    /// Returns the usage counting the bytes received by the downloads, if any.
+    pub fn data_usage(&self) -> Option<Arc<DataUsage>> {
+        self.data_usage.lock().unwrap().clone()
+    }
+
+    /// Limits the number of concurrent connections the downloads sharing the manager open to all
+    /// the hosts, on top of the per-host limit, e.g. so many downloads don't trip the connection
+    /// limits of a router. The connections already open keep their slot from the previous limit,
+    /// setting the same limit again keeps them counted.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_connections` - The maximum number of connections, `None` for no limit.
+    ///
+    /// Returns an error if the limit is zero.
+    pub fn set_total_limit(&self, max_connections: Option<usize>) -> Result<(), String> {
+        if max_connections == Some(0) {
+            return Err(String::from("Total connection limit must be greater than zero"));
+        }
+        let mut total_limit = self.total_limit.lock().unwrap();
+        if total_limit.as_ref().map(|(limit, _)| *limit) != max_connections {
+            *total_limit = max_connections.map(|limit| (limit, Arc::new(Semaphore::new(limit))));
+        }
+        Ok(())
+    }
+
+    /// Returns the maximum number of concurrent connections to all the hosts, if any.
+    pub fn total_limit(&self) -> Option<usize> {
+        self.total_limit.lock().unwrap().as_ref().map(|(limit, _)| *limit)
+    }
+
+    /// Returns the number of connections currently open to all the hosts, only counted with a total limit.
+    pub fn total_connections(&self) -> usize {
+        self.total_limit.lock().unwrap().as_ref().map(|(limit, semaphore)| limit - semaphore.available_permits()).unwrap_or(0)
+    }
+
+    /// Waits until a connection to `host` can be opened without exceeding the limit.
+    /// The connection slot is released once the returned permit is dropped.
+    ///
+    /// Slots are shared fairly between downloads: a freed slot goes to the waiting download holding
+    /// the fewest connections to the host, oldest request first, and the downloads holding more than
+    /// their share give slots away with `ConnectionPermit::yield_to_waiters`. With a total limit, the
+    /// permit then waits for one of the connections to all the hosts, in the order they were requested.
+    ///
+    /// # Arguments
+    ///
+    /// * `host` - The hostname the connection will be opened to.
+    /// * `download` - A key identifying the download opening the connection.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<ConnectionPermit, String>` - The permit to hold for the lifetime of the connection.
+    pub async fn acquire(&self, host: &str, download: usize) -> Result<ConnectionPermit, String> {
+        let slots = {
+            let per_host_limit = self.per_host_limit.lock().unwrap();
+            let mut hosts = self.hosts.lock().unwrap();
+            hosts.entry(host.to_lowercase())
+                .or_insert_with(|| Arc::new(HostSlots::new(*per_host_limit)))
+                .clone()
+        };
+
+        let receiver = {
+            let mut state = slots.state.lock().unwrap();
+            state.waiting.retain(|waiter| !waiter.sender.is_closed());
+            if state.open() < state.limit {
+                *state.active.entry(download).or_default() += 1;
+                None
+            } else {
+                let (sender, receiver) = oneshot::channel();
+                state.waiting.push(Waiter { download, sender });
+                Some(receiver)
+            }
+        };
+        let mut permit = match receiver {
+            Some(receiver) => receiver.await.map_err(|_| format!("Couldn't acquire a connection to {}", host))?,
+            None => ConnectionPermit { slots, download, released: false, total: None },
+        };
+
+        // The host slot is taken first, so no total slot is held while waiting for a host
+        let total = self.total_limit.lock().unwrap().as_ref().map(|(_, semaphore)| semaphore.clone());
+        if let Some(semaphore) = total {
+            permit.total = Some(semaphore.acquire_owned().await.map_err(|_| format!("Couldn't acquire a connection to {}", host))?);
+        }
+        Ok(permit)
+    }
+
+    /// Returns the number of connections currently open to `host`.
+    pub fn active_connections(&self, host: &str) -> usize {
+        let hosts = self.hosts.lock().unwrap();
+        hosts.get(&host.to_lowercase())
+            .map(|slots| slots.state.lock().unwrap().open())
+            .unwrap_or(0)
+    }
+
+    /// Returns the init strategy that last succeeded for `host`, if any.
+    pub fn init_strategy(&self, host: &str) -> Option<InitStrategy> {
+        self.init_strategies.lock().unwrap().get(&host.to_lowercase()).copied()
+    }
+
+    /// Remembers the init strategy that succeeded for `host`, so it's tried first next time.
+    pub fn remember_init_strategy(&self, host: &str, strategy: InitStrategy) {
+        self.init_strategies.lock().unwrap().insert(host.to_lowercase(), strategy);
+    }
+
+    /// Returns the number of connections the tuning of a download from `host` last settled on, if any.
+    pub fn tuned_connections(&self, host: &str) -> Option<usize> {
+        self.tuned_connections.lock().unwrap().get(&host.to_lowercase()).copied()
+    }
+
+    /// Remembers the number of connections the tuning settled on for `host`, the next downloads from it start with them.
+    pub fn remember_tuned_connections(&self, host: &str, connections: usize) {
+        self.tuned_connections.lock().unwrap().insert(host.to_lowercase(), connections);
+    }
+
+    /// Returns the current connectivity of the downloads.
+    pub fn network_state(&self) -> NetworkState {
+        *self.network.borrow()
+    }
+
+    /// Returns a receiver notified when the connectivity of the downloads changes.
+    pub fn subscribe_network(&self) -> watch::Receiver<NetworkState> {
+        self.network.subscribe()
+    }
+
+    /// Records a successful connection, the network is back if it was considered down.
+    pub fn record_success(&self) {
+        self.failures.lock().unwrap().0 = 0;
+        self.network.send_if_modified(|state| std::mem::replace(state, NetworkState::Online) != NetworkState::Online);
+    }
+
+    /// Records a connection that failed for lack of connectivity (refused, timed out, dropped, ..).
+    /// Once `OFFLINE_FAILURE_THRESHOLD` failures follow each other the network is considered down,
+    /// and the address of `url` is probed every `NETWORK_PROBE_INTERVAL` until it can be reached.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The URL the connection was opened for.
+    pub fn record_failure(self: &Arc<ConnectionManager>, url: &Url) {
+        let address = {
+            let mut failures = self.failures.lock().unwrap();
+            failures.0 += 1;
+            if let (Some(host), Some(port)) = (url.host_str(), url.port_or_known_default()) {
+                failures.1 = Some((host.to_string(), port));
+            }
+            if failures.0 < OFFLINE_FAILURE_THRESHOLD {
+                return;
+            }
+            failures.1.clone()
+        };
+
+        let went_offline = self.network.send_if_modified(|state| std::mem::replace(state, NetworkState::Offline) != NetworkState::Offline);
+        if let (true, Some(address)) = (went_offline, address) {
+            let manager = self.clone();
+            task::spawn(async move { manager.probe_network(address).await });
+        }
+    }
+
+    /// Tries to connect to `address` until it succeeds or a download reaches the network first.
+    async fn probe_network(&self, address: (String, u16)) {
+        while self.network_state() == NetworkState::Offline {
+            tokio::time::sleep(NETWORK_PROBE_INTERVAL).await;
+            let probe = tokio::time::timeout(NETWORK_PROBE_INTERVAL, TcpStream::connect((address.0.as_str(), address.1))).await;
+            if let Ok(Ok(_)) = probe {
+                self.record_success();
+            }
+        }
+    }
+}
+
+/// HostSlots guards the connections to a single host, granting them fairly between downloads.
+#[derive(Debug)]
+struct HostSlots {
+    state: Mutex<HostSlotsState>,   // Connections held and requested by the downloads
+}
+
+#[derive(Debug, Default)]
+struct HostSlotsState {
+    limit: usize,                   // Maximum number of concurrent connections to the host
+    active: HashMap<usize, usize>,  // Connections held by each download
+    waiting: Vec<Waiter>,           // Requests waiting for a connection, oldest first
+}
+
+/// A request waiting for a connection slot.
+#[derive(Debug)]
+struct Waiter {
+    download: usize,                            // Key of the download the request is from
+    sender: oneshot::Sender<ConnectionPermit>,  // Receives the permit once a slot is granted
+}
+
+impl HostSlots {
+    fn new(limit: usize) -> HostSlots {
+        HostSlots { state: Mutex::new(HostSlotsState { limit, ..HostSlotsState::default() }) }
+    }
+
+    /// Changes the maximum number of connections to the host and grants the free slots to the waiting requests.
+    fn set_limit(self: &Arc<HostSlots>, limit: usize) {
+        let undelivered = {
+            let mut state = self.state.lock().unwrap();
+            state.limit = limit;
+            self.grant(&mut state)
+        };
+        drop(undelivered);
+    }
+
+    /// Frees the slot of `download` and grants the free slots to the waiting requests.
+    fn release(self: &Arc<HostSlots>, download: usize) {
+        // Permits that couldn't be delivered are dropped once the lock is released, freeing their slot again
+        let undelivered = {
+            let mut state = self.state.lock().unwrap();
+            if let Some(held) = state.active.get_mut(&download) {
+                *held -= 1;
+                if *held == 0 {
+                    state.active.remove(&download);
+                }
+            }
+            self.grant(&mut state)
+        };
+        drop(undelivered);
+    }
+
+    /// Grants the free slots to the waiting requests, returning the permits that couldn't be delivered.
+    /// They must be dropped once the lock of `state` is released.
+    fn grant(self: &Arc<HostSlots>, state: &mut HostSlotsState) -> Vec<ConnectionPermit> {
+        let mut undelivered = Vec::new();
+        while state.open() < state.limit {
+            let Some(index) = state.next_waiter() else { break };
+            let waiter = state.waiting.remove(index);
+            *state.active.entry(waiter.download).or_default() += 1;
+            let permit = ConnectionPermit { slots: self.clone(), download: waiter.download, released: false, total: None };
+            if let Err(permit) = waiter.sender.send(permit) {
+                undelivered.push(permit);
+            }
+        }
+        undelivered
+    }
+}
+
+impl HostSlotsState {
+    /// Returns the number of open connections.
+    fn open(&self) -> usize {
+        self.active.values().sum()
+    }
+
+    /// Returns the number of connections held by `download`.
+    fn held(&self, download: usize) -> usize {
+        self.active.get(&download).copied().unwrap_or(0)
+    }
+
+    /// Returns the index of the waiting request of the download holding the fewest connections, the oldest one on ties.
+    fn next_waiter(&mut self) -> Option<usize> {
+        self.waiting.retain(|waiter| !waiter.sender.is_closed());
+        self.waiting.iter().enumerate()
+            .min_by_key(|(index, waiter)| (self.held(waiter.download), *index))
+            .map(|(index, _)| index)
+    }
+
+    /// Returns the number of connections each download gets when the slots are shared equally
+    /// between the downloads holding or waiting for one.
+    fn fair_share(&self, limit: usize) -> usize {
+        let mut downloads: Vec<usize> = self.active.keys().chain(self.waiting.iter().map(|waiter| &waiter.download)).copied().collect();
+        downloads.sort_unstable();
+        downloads.dedup();
+        limit.div_ceil(downloads.len().max(1))
+    }
+}
+
+/// ConnectionPermit is a connection slot to a host, released once the permit is dropped.
+#[derive(Debug)]
+pub struct ConnectionPermit {
+    slots: Arc<HostSlots>,  // Slots of the host the connection is open to
+    download: usize,        // Key of the download holding the slot
+    released: bool,         // Whether the slot was already given to another download
+    total: Option<OwnedSemaphorePermit>,    // Slot of the total connection limit, if one is set
+}
+
+impl ConnectionPermit {
+    /// Gives the slot to a waiting request of another download when the download holding it has
+    /// more than its fair share of the host's connections and the other one less. The connection
+    /// must then be closed, and a new slot acquired to continue.
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - Whether the slot was given away.
+    pub fn yield_to_waiters(&mut self) -> bool {
+        if self.released {
+            return true;
+        }
+        let unfair = {
+            let mut state = self.slots.state.lock().unwrap();
+            state.waiting.retain(|waiter| !waiter.sender.is_closed());
+            let share = state.fair_share(state.limit);
+            state.held(self.download) > share
+                && state.waiting.iter().any(|waiter| waiter.download != self.download && state.held(waiter.download) < share)
+        };
+        if unfair {
+            self.released = true;
+            self.total = None;
+            self.slots.release(self.download);
+        }
+        unfair
+    }
+}
+
+impl Drop for ConnectionPermit {
+    fn drop(&mut self) {
+        if !self.released {
+            self.released = true;
+            self.slots.release(self.download);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn connections_are_shared_fairly() {
+        let manager = ConnectionManager::new(4).unwrap();
+        let mut first = Vec::new();
+        for _ in 0..4 {
+            first.push(manager.acquire("example.com", 1).await.unwrap());
+        }
+        assert!(!first[0].yield_to_waiters());
+
+        // The second download waits, then gets the slots the first one gives away until both hold their share
+        let second = tokio::join!(
+            manager.acquire("EXAMPLE.com", 2),
+            manager.acquire("example.com", 2),
+            async {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                first.iter_mut().map(ConnectionPermit::yield_to_waiters).filter(|yielded| *yielded).count()
+            },
+        );
+        assert_eq!(second.2, 2);
+        assert_eq!(manager.active_connections("example.com"), 4);
+
+        // A freed slot goes to the download holding the fewest connections, even if it asked last
+        let mut more_first = Box::pin(manager.acquire("example.com", 1));
+        let mut third = Box::pin(manager.acquire("example.com", 3));
+        assert!(tokio::time::timeout(Duration::from_millis(10), &mut more_first).await.is_err());
+        assert!(tokio::time::timeout(Duration::from_millis(10), &mut third).await.is_err());
+        first.pop();
+        let third = third.await.unwrap();
+        assert!(tokio::time::timeout(Duration::from_millis(10), &mut more_first).await.is_err());
+        drop(second);
+        let more_first = more_first.await.unwrap();
+        assert_eq!((third.download, more_first.download), (3, 1));
+        assert_eq!(manager.active_connections("example.com"), 3);
+    }
+
+    #[tokio::test]
+    async fn connections_are_capped_across_hosts() {
+        let manager = ConnectionManager::new(4).unwrap();
+        assert!(manager.set_total_limit(Some(0)).is_err());
+        manager.set_total_limit(Some(3)).unwrap();
+        let mut permits = Vec::new();
+        for host in ["a.example.com", "a.example.com", "b.example.com"] {
+            permits.push(manager.acquire(host, 1).await.unwrap());
+        }
+        assert_eq!(manager.total_connections(), 3);
+
+        // The host has free slots but the total limit is reached, the request waits for a connection to another host
+        let mut waiting = Box::pin(manager.acquire("c.example.com", 2));
+        assert!(tokio::time::timeout(Duration::from_millis(10), &mut waiting).await.is_err());
+        assert_eq!(manager.active_connections("c.example.com"), 1);
+        permits.remove(0);
+        let permit = waiting.await.unwrap();
+        assert_eq!(manager.total_connections(), 3);
+
+        // Dropping a waiting request frees its host slot
+        let abandoned = tokio::time::timeout(Duration::from_millis(10), manager.acquire("b.example.com", 3)).await;
+        assert!(abandoned.is_err());
+        assert_eq!(manager.active_connections("b.example.com"), 1);
+        drop((permit, permits));
+        assert_eq!(manager.total_connections(), 0);
+
+        manager.set_total_limit(None).unwrap();
+        assert_eq!(manager.total_limit(), None);
+    }
+
+    #[tokio::test]
+    async fn per_host_limit_changes_in_place() {
+        let manager = ConnectionManager::new(1).unwrap();
+        assert!(manager.set_per_host_limit(0).is_err());
+        let first = manager.acquire("example.com", 1).await.unwrap();
+
+        // Raising the limit grants the new slot to the waiting request
+        let mut waiting = Box::pin(manager.acquire("example.com", 2));
+        assert!(tokio::time::timeout(Duration::from_millis(10), &mut waiting).await.is_err());
+        manager.set_per_host_limit(2).unwrap();
+        let second = waiting.await.unwrap();
+        assert_eq!(manager.active_connections("example.com"), 2);
+
+        // Lowering it keeps the open connections, a new one waits until they're below the limit
+        manager.set_per_host_limit(1).unwrap();
+        assert_eq!(manager.per_host_limit(), 1);
+        let mut waiting = Box::pin(manager.acquire("example.com", 3));
+        drop(first);
+        assert!(tokio::time::timeout(Duration::from_millis(10), &mut waiting).await.is_err());
+        drop(second);
+        assert_eq!(waiting.await.unwrap().download, 3);
+    }
+}
@@ -0,0 +1,319 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::PathBuf;
+use std::time::Duration;
+use reqwest::header::CONTENT_TYPE;
+use url::Url;
+use super::batch::BatchEntry;
+use super::io::sanitize_file_name;
+use super::links::{extract_links, fetch_page, is_html, LinkFilter, LinkKind, PageLink};
+use super::transport::Transport;
+
+/// Product token matched against the `User-agent` lines of robots.txt files.
+pub const ROBOTS_AGENT: &str = "rustle";
+
+/// Timeout of the requests checking whether a link is a page, and fetching robots.txt files.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Maximum size of a robots.txt file, the rest is ignored.
+const MAX_ROBOTS_SIZE: usize = 512 * 1024;
+
+/// File name of the pages of directory URLs, e.g. `https://example.com/docs/`.
+const INDEX_FILE_NAME: &str = "index.html";
+
+/// CrawlOptions represents the limits of a recursive download, see `crawl()`.
+#[derive(Debug, Clone)]
+pub struct CrawlOptions {
+    pub max_depth: usize,       // Levels of pages followed from the start page, 0 only downloads its files
+    pub same_host: bool,        // Only follows and downloads the links of the start page's host
+    pub no_parent: bool,        // Only follows and downloads the links of the start page's host under its directory
+    pub respect_robots: bool,   // Skips the URLs disallowed for `ROBOTS_AGENT` by the robots.txt of their host
+    pub save_pages: bool,       // Also downloads the crawled pages matching the filter
+    pub filter: LinkFilter,     // Files to download, pages are followed whatever the filter
+    pub max_pages: usize,       // Maximum number of pages crawled
+}
+
+impl Default for CrawlOptions {
+    fn default() -> Self {
+        CrawlOptions {
+            max_depth: 1,
+            same_host: true,
+            no_parent: true,
+            respect_robots: true,
+            save_pages: false,
+            filter: LinkFilter::default(),
+            max_pages: 1000,
+        }
+    }
+}
+
+/// CrawlResult represents the files found by a recursive download.
+#[derive(Debug, Clone, Default)]
+pub struct CrawlResult {
+    pub files: Vec<BatchEntry>,     // Files to download, in the directory structure of their URL under `<host>/`
+    pub pages: usize,               // Number of pages crawled
+    pub disallowed: usize,          // Number of URLs skipped because of robots.txt
+    pub errors: Vec<String>,        // Links that couldn't be crawled, the crawl goes on without them
+}
+
+/// Crawls a site like `wget --recursive`, following the links of its pages up to `max_depth` and
+/// listing the files to download, e.g. to mirror a directory listing or a small site. The files
+/// are queued like any batch, see `BatchEntry::apply`.
+///
+/// Links are checked with a HEAD request to tell pages from files, except images, videos and the
+/// links found at the last level, which are files unless their path ends with a `/`.
+///
+/// # Arguments
+///
+/// * `transport` - The transport sending the requests, e.g. the one of the start page's protocol.
+/// * `start` - The URL of the start page.
+/// * `options` - The limits of the crawl.
+///
+/// Returns an error if the start page isn't an HTML page or is disallowed by robots.txt.
+pub async fn crawl(transport: &dyn Transport, start: &Url, options: &CrawlOptions) -> Result<CrawlResult, String> {
+    let mut crawler = Crawler { transport, options, root: start.clone(), robots: HashMap::new(), result: CrawlResult::default() };
+    if !crawler.allowed(start).await {
+        return Err(format!("{} is disallowed by robots.txt", start));
+    }
+    let (page_url, html) = fetch_page(transport, start).await?;
+    crawler.root = page_url.clone();
+
+    let mut seen = HashSet::from([page_url.to_string()]);
+    let mut pages = VecDeque::from([(page_url, extract_links(&html, &crawler.root), 0)]);
+    while let Some((page_url, links, depth)) = pages.pop_front() {
+        crawler.result.pages += 1;
+        if options.save_pages {
+            crawler.add_file(&PageLink { url: page_url.to_string(), kind: LinkKind::Link }, None);
+        }
+
+        for link in links {
+            let Ok(url) = Url::parse(&link.url) else { continue };
+            if !crawler.in_scope(&url) || !seen.insert(url.to_string()) {
+                continue;
+            }
+            if !crawler.allowed(&url).await {
+                crawler.result.disallowed += 1;
+                continue;
+            }
+
+            let can_follow = link.kind == LinkKind::Link && depth < options.max_depth
+                && crawler.result.pages + pages.len() < options.max_pages;
+            if !can_follow {
+                if link.kind != LinkKind::Link || !url.path().ends_with('/') {
+                    crawler.add_file(&link, Some(&page_url));
+                }
+                continue;
+            }
+
+            match crawler.is_page(&url).await {
+                Ok(true) => match fetch_page(transport, &url).await {
+                    // Redirects can leave the crawled part of the site
+                    Ok((page_url, _)) if page_url != url && (!crawler.in_scope(&page_url) || !seen.insert(page_url.to_string())) => {},
+                    Ok((page_url, html)) => {
+                        let links = extract_links(&html, &page_url);
+                        pages.push_back((page_url, links, depth + 1));
+                    },
+                    Err(e) => crawler.result.errors.push(e),
+                },
+                Ok(false) => crawler.add_file(&link, Some(&page_url)),
+                Err(e) => crawler.result.errors.push(e),
+            }
+        }
+    }
+    Ok(crawler.result)
+}
+
+/// Represents the state of a crawl.
+struct Crawler<'a> {
+    transport: &'a dyn Transport,
+    options: &'a CrawlOptions,
+    root: Url,                                      // URL of the start page, after redirects
+    robots: HashMap<String, RobotsRules>,           // Rules of the robots.txt files, keyed by origin
+    result: CrawlResult,
+}
+
+impl Crawler<'_> {
+    /// Returns whether `url` is in the crawled part of the site.
+    fn in_scope(&self, url: &Url) -> bool {
+        let same_origin = url.host_str() == self.root.host_str() && url.port_or_known_default() == self.root.port_or_known_default();
+        let root_dir = &self.root.path()[..self.root.path().rfind('/').map_or(0, |end| end + 1)];
+
+        matches!(url.scheme(), "http" | "https")
+            && (same_origin || !self.options.same_host)
+            && (!same_origin || !self.options.no_parent || url.path().starts_with(root_dir))
+    }
+
+    /// Returns whether `url` may be crawled according to the robots.txt of its host.
+    async fn allowed(&mut self, url: &Url) -> bool {
+        if !self.options.respect_robots {
+            return true;
+        }
+        let origin = url.origin().ascii_serialization();
+        if !self.robots.contains_key(&origin) {
+            let rules = self.fetch_robots(url).await;
+            self.robots.insert(origin.clone(), rules);
+        }
+        let path = &url[url::Position::BeforePath..url::Position::AfterQuery];
+        self.robots[&origin].allows(path)
+    }
+
+    /// Fetches the robots.txt of `url`'s host. A missing or unreachable file allows everything.
+    async fn fetch_robots(&self, url: &Url) -> RobotsRules {
+        let Ok(robots_url) = url.join("/robots.txt") else { return RobotsRules::default() };
+        let Ok(mut response) = self.transport.get_range(&robots_url, None, None, Some(REQUEST_TIMEOUT)).await else {
+            return RobotsRules::default();
+        };
+        if !response.status.is_success() {
+            return RobotsRules::default();
+        }
+
+        let mut text = Vec::new();
+        while let Ok(Some(chunk)) = response.chunk().await {
+            text.extend_from_slice(&chunk);
+            if text.len() >= MAX_ROBOTS_SIZE {
+                break;
+            }
+        }
+        RobotsRules::parse(&String::from_utf8_lossy(&text), ROBOTS_AGENT)
+    }
+
+    /// Returns whether `url` is an HTML page, with a HEAD request.
+    async fn is_page(&self, url: &Url) -> Result<bool, String> {
+        let response = self.transport.head(url, Some(REQUEST_TIMEOUT)).await
+            .map_err(|e| format!("Couldn't check {}, error : {}", url, e))?;
+        if !response.status.is_success() {
+            return Err(format!("Couldn't check {}, status : {}", url, response.status));
+        }
+        Ok(response.headers.get(CONTENT_TYPE).and_then(|value| value.to_str().ok()).is_some_and(is_html))
+    }
+
+    /// Adds `link` to the files to download if it matches the filter, in the directory structure of its URL.
+    /// The page it was found on is sent as the `Referer` of its requests.
+    fn add_file(&mut self, link: &PageLink, page_url: Option<&Url>) {
+        let Ok(url) = Url::parse(&link.url) else { return };
+        if !self.options.filter.matches(link) {
+            return;
+        }
+
+        let mut dir = PathBuf::from(sanitize_file_name(&match url.port() {
+            Some(port) => format!("{}_{}", url.host_str().unwrap_or_default(), port),
+            None => url.host_str().unwrap_or_default().to_string(),
+        }));
+        let mut segments: Vec<&str> = url.path_segments().map(|segments| segments.collect()).unwrap_or_default();
+        let file_name = match segments.pop() {
+            Some(name) if !name.is_empty() => name,
+            _ => INDEX_FILE_NAME,
+        };
+        for segment in segments.into_iter().filter(|segment| !segment.is_empty() && *segment != "." && *segment != "..") {
+            dir.push(sanitize_file_name(segment));
+        }
+
+        let line = self.result.files.len() + 1;
+        self.result.files.push(BatchEntry {
+            file_name: Some(sanitize_file_name(file_name)),
+            dir: Some(dir),
+            referer: page_url.map(Url::to_string),
+            ..BatchEntry::new(line, url.as_str())
+        });
+    }
+}
+
+/// RobotsRules represents the rules of a robots.txt file applying to an agent.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RobotsRules {
+    rules: Vec<(bool, String)>,     // Whether each path pattern is allowed, `*` matches anything and `$` ends it
+}
+
+impl RobotsRules {
+    /// Parses a robots.txt file, keeping the rules of the groups naming `agent`, or of the `*` group
+    /// if none does.
+    pub fn parse(text: &str, agent: &str) -> RobotsRules {
+        let agent = agent.to_ascii_lowercase();
+        let (mut agent_rules, mut any_rules) = (Vec::new(), Vec::new());
+        let mut group_agents: Vec<String> = Vec::new();
+        let mut in_rules = false;
+
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            let Some((key, value)) = line.split_once(':') else { continue };
+            let (key, value) = (key.trim().to_ascii_lowercase(), value.trim());
+            match key.as_str() {
+                "user-agent" => {
+                    // Consecutive user agents share the rules following them
+                    if in_rules {
+                        group_agents.clear();
+                        in_rules = false;
+                    }
+                    group_agents.push(value.to_ascii_lowercase());
+                },
+                "allow" | "disallow" => {
+                    in_rules = true;
+                    if value.is_empty() {
+                        continue;
+                    }
+                    let rule = (key == "allow", value.to_string());
+                    if group_agents.iter().any(|name| !name.is_empty() && name != "*" && agent.contains(name.as_str())) {
+                        agent_rules.push(rule.clone());
+                    }
+                    if group_agents.iter().any(|name| name == "*") {
+                        any_rules.push(rule);
+                    }
+                },
+                _ => {},
+            }
+        }
+        RobotsRules { rules: if agent_rules.is_empty() { any_rules } else { agent_rules } }
+    }
+
+    /// Returns whether `path` (with its query) may be crawled: the longest matching rule applies,
+    /// `Allow` wins a tie.
+    pub fn allows(&self, path: &str) -> bool {
+        self.rules.iter()
+            .filter(|(_, pattern)| robots_pattern_matches(pattern, path))
+            .max_by_key(|(allow, pattern)| (pattern.len(), *allow))
+            .is_none_or(|(allow, _)| *allow)
+    }
+}
+
+/// Returns whether a robots.txt path pattern matches `path`.
+fn robots_pattern_matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(pattern) => (pattern, true),
+        None => (pattern, false),
+    };
+    let mut parts = pattern.split('*');
+    let Some(rest) = path.strip_prefix(parts.next().unwrap_or_default()) else { return false };
+
+    // Each part after a `*` matches at its first occurrence, the last one at the end if anchored
+    let parts: Vec<&str> = parts.collect();
+    let mut rest = rest;
+    for (i, part) in parts.iter().enumerate() {
+        if anchored && i == parts.len() - 1 {
+            return rest.ends_with(part);
+        }
+        match rest.find(part) {
+            Some(start) => rest = &rest[start + part.len()..],
+            None => return false,
+        }
+    }
+    !anchored || rest.is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn robots_rules_apply_to_the_agent() {
+        let robots = "User-agent: *\nDisallow: /\n\nUser-agent: Googlebot\nUser-agent: rustle\nDisallow: /private/ # comment\nAllow: /private/public\nDisallow: /*.cgi$\n";
+        let rules = RobotsRules::parse(robots, ROBOTS_AGENT);
+        assert!(rules.allows("/files/a.iso"));
+        assert!(!rules.allows("/private/a.iso"));
+        assert!(rules.allows("/private/public/a.iso"));
+        assert!(!rules.allows("/bin/run.cgi"));
+        assert!(rules.allows("/bin/run.cgi?x=1"));
+
+        let rules = RobotsRules::parse(robots, "otherbot");
+        assert!(!rules.allows("/files/a.iso"));
+        assert!(RobotsRules::parse("User-agent: *\nDisallow:\n", ROBOTS_AGENT).allows("/"));
+    }
+}
@@ -0,0 +1,133 @@
+use std::fmt;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use keyring::Entry;
+use reqwest::header::HeaderValue;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// Service name the credentials are stored under in the OS credential store.
+pub const KEYRING_SERVICE: &str = "rustle";
+
+/// Credentials represents a username and a password stored for a host, e.g. the basic auth of a
+/// server, the login of a proxy or the access key and secret of an object storage.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Credentials {
+    pub username: String,   // User name, or access key
+    pub password: String,   // Password, or secret key
+}
+
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Credentials").field("username", &self.username).field("password", &"***").finish()
+    }
+}
+
+impl Credentials {
+    /// Returns the value of the `Authorization` header authenticating with these credentials.
+    pub fn basic_auth(&self) -> Result<HeaderValue, String> {
+        let token = STANDARD.encode(format!("{}:{}", self.username, self.password));
+        let mut value = HeaderValue::from_str(&format!("Basic {}", token))
+            .map_err(|e| format!("Invalid credentials, error : {}", e))?;
+        value.set_sensitive(true);
+        Ok(value)
+    }
+}
+
+/// Returns the key the credentials of a URL are stored under: its lowercase host, followed by
+/// the port if it's not the default one of the scheme.
+pub fn credential_key(url: &Url) -> Option<String> {
+    let host = url.host_str()?.to_ascii_lowercase();
+    Some(match url.port() {
+        Some(port) => format!("{}:{}", host, port),
+        None => host,
+    })
+}
+
+/// CredentialStore keeps the credentials of each host in the OS credential store (Keychain,
+/// Windows Credential Manager, Secret Service), so they never live in plaintext in the settings.
+/// The store is only reached from blocking tasks, some platforms block on their own runtime.
+#[derive(Debug, Clone)]
+pub struct CredentialStore {
+    service: String,    // Service name the entries are stored under
+}
+
+impl Default for CredentialStore {
+    fn default() -> Self {
+        CredentialStore { service: KEYRING_SERVICE.to_string() }
+    }
+}
+
+impl CredentialStore {
+    /// Creates a store keeping its entries under another service name than `KEYRING_SERVICE`.
+    pub fn new(service: &str) -> CredentialStore {
+        CredentialStore { service: service.to_string() }
+    }
+
+    /// Returns the credentials stored for a host, `None` if there aren't any.
+    ///
+    /// # Arguments
+    ///
+    /// * `host` - The key of the host, see `credential_key()`.
+    ///
+    /// Returns an error if the credential store can't be reached or the entry is invalid.
+    pub async fn get(&self, host: &str) -> Result<Option<Credentials>, String> {
+        let entry = self.entry(host)?;
+        let secret = match blocking(move || entry.get_password()).await? {
+            Ok(secret) => secret,
+            Err(keyring::Error::NoEntry) => return Ok(None),
+            Err(e) => return Err(store_error(host, e)),
+        };
+        serde_json::from_str(&secret).map(Some).map_err(|e| format!("Invalid credentials stored for {}, error : {}", host, e))
+    }
+
+    /// Stores the credentials of a host, replacing the previous ones.
+    pub async fn set(&self, host: &str, credentials: &Credentials) -> Result<(), String> {
+        let entry = self.entry(host)?;
+        let secret = serde_json::to_string(credentials).map_err(|e| format!("Couldn't serialize the credentials, error : {}", e))?;
+        blocking(move || entry.set_password(&secret)).await?.map_err(|e| store_error(host, e))
+    }
+
+    /// Deletes the credentials of a host.
+    ///
+    /// Returns whether there were credentials stored for the host.
+    pub async fn delete(&self, host: &str) -> Result<bool, String> {
+        let entry = self.entry(host)?;
+        match blocking(move || entry.delete_credential()).await? {
+            Ok(()) => Ok(true),
+            Err(keyring::Error::NoEntry) => Ok(false),
+            Err(e) => Err(store_error(host, e)),
+        }
+    }
+
+    /// Returns the entry of a host in the credential store.
+    fn entry(&self, host: &str) -> Result<Entry, String> {
+        Entry::new(&self.service, &host.to_ascii_lowercase()).map_err(|e| store_error(host, e))
+    }
+}
+
+/// Runs a call to the credential store on a blocking task.
+async fn blocking<T: Send + 'static>(call: impl FnOnce() -> T + Send + 'static) -> Result<T, String> {
+    tokio::task::spawn_blocking(call).await.map_err(|e| format!("Couldn't reach the credential store, error : {}", e))
+}
+
+/// Formats an error of the credential store.
+fn store_error(host: &str, e: keyring::Error) -> String {
+    format!("Credential store error for {}, error : {}", host, e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_and_authorization_header() {
+        let url = Url::parse("https://User@Files.Example.com:8443/a.iso").unwrap();
+        assert_eq!(credential_key(&url).as_deref(), Some("files.example.com:8443"));
+        assert_eq!(credential_key(&Url::parse("https://example.com:443/").unwrap()).as_deref(), Some("example.com"));
+
+        let credentials = Credentials { username: String::from("Aladdin"), password: String::from("open sesame") };
+        assert_eq!(credentials.basic_auth().unwrap(), "Basic QWxhZGRpbjpvcGVuIHNlc2FtZQ==");
+        assert!(!format!("{:?}", credentials).contains("sesame"));
+    }
+}
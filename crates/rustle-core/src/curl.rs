@@ -0,0 +1,359 @@
+use std::iter::Peekable;
+use std::path::Path;
+use std::str::{Chars, FromStr};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use bytes::Bytes;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::Method;
+use super::downloader::RustleDownloader;
+
+/// Headers of a curl command that aren't sent by the engine: it sets the range itself, and doesn't
+/// decode compressed responses (browsers add `Accept-Encoding` to their "Copy as cURL" commands).
+const IGNORED_HEADERS: [&str; 6] = ["accept-encoding", "range", "if-range", "content-length", "host", "connection"];
+
+/// Options of curl without a value that don't change the download, e.g. `--compressed`.
+const IGNORED_FLAGS: [&str; 17] = [
+    "compressed", "location", "location-trusted", "silent", "show-error", "verbose", "include", "globoff",
+    "http1.1", "http2", "http2-prior-knowledge", "fail", "fail-with-body", "no-progress-meter",
+    "remote-name", "remote-header-name", "path-as-is",
+];
+
+/// Options of curl with a value that don't change the download, e.g. `--max-time 10`.
+const IGNORED_OPTIONS: [&str; 7] = ["connect-timeout", "max-time", "retry", "retry-delay", "max-redirs", "write-out", "limit-rate"];
+
+/// CurlCommand represents a download described by a curl command, e.g. copied from the browser's
+/// developer tools with "Copy as cURL" to capture an authenticated download.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CurlCommand {
+    pub url: String,                        // URL of the file
+    pub method: String,                     // Method of the request, `GET` unless set or implied by a body
+    pub headers: Vec<(String, String)>,     // Request headers in order, including the cookies, user agent, .. options
+    pub body: Option<String>,               // Body of the request, from the `--data` options
+    pub file_name: Option<String>,          // Name of the `--output` file
+    pub insecure: bool,                     // Whether invalid certificates are accepted, `--insecure`
+}
+
+impl CurlCommand {
+    /// Returns whether `text` looks like a curl command rather than a URL.
+    pub fn is_curl(text: &str) -> bool {
+        let text = text.trim_start();
+        text.strip_prefix("curl").is_some_and(|rest| rest.starts_with(char::is_whitespace))
+    }
+
+    /// Parses a curl command, in the syntax of a POSIX shell (quotes, `$'..'` strings and line continuations).
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - The command, starting with `curl`.
+    ///
+    /// Returns an error if it isn't a curl command, uses an unsupported option or doesn't have exactly one URL.
+    pub fn parse(command: &str) -> Result<CurlCommand, String> {
+        let mut args = split_arguments(command)?.into_iter();
+        if args.next().as_deref() != Some("curl") {
+            return Err(String::from("Not a curl command"));
+        }
+
+        let mut curl = CurlCommand::default();
+        let mut urls = Vec::new();
+        let mut method = None;
+        let mut cookies = Vec::new();
+        let mut data = Vec::new();
+
+        while let Some(arg) = args.next() {
+            let options = if let Some(long) = arg.strip_prefix("--") {
+                vec![(long.to_string(), None)]
+            } else if let Some(short) = arg.strip_prefix('-').filter(|short| !short.is_empty()) {
+                split_short_options(short).ok_or_else(|| format!("Unsupported curl option `{}`", arg))?
+            } else {
+                urls.push(arg);
+                continue;
+            };
+
+            for (name, value) in options {
+                let name = name.as_str();
+                if IGNORED_FLAGS.contains(&name) {
+                    continue;
+                }
+                if name == "insecure" {
+                    curl.insecure = true;
+                    continue;
+                }
+                if name == "head" {
+                    method = Some(String::from("HEAD"));
+                    continue;
+                }
+                let value = value.or_else(|| args.next()).ok_or_else(|| format!("The curl option `{}` is missing its value", arg))?;
+
+                match name {
+                    "url" => urls.push(value),
+                    "header" => {
+                        let (header, header_value) = value.split_once(':').ok_or_else(|| format!("Invalid curl header `{}`", value))?;
+                        let (header, header_value) = (header.trim(), header_value.trim());
+                        // `Name:` removes a header curl would send, there's nothing to remove here
+                        if !header_value.is_empty() && !IGNORED_HEADERS.contains(&header.to_ascii_lowercase().as_str()) {
+                            curl.headers.push((header.to_string(), header_value.to_string()));
+                        }
+                    },
+                    "cookie" => {
+                        if !value.contains('=') {
+                            return Err(format!("Cookie files aren't supported, `{}`", value));
+                        }
+                        cookies.push(value);
+                    },
+                    "user-agent" => curl.headers.push((String::from("User-Agent"), value)),
+                    "referer" => curl.headers.push((String::from("Referer"), value)),
+                    "user" => curl.headers.push((String::from("Authorization"), format!("Basic {}", STANDARD.encode(value)))),
+                    "request" => method = Some(value.to_ascii_uppercase()),
+                    "data" | "data-raw" | "data-binary" | "data-ascii" | "data-urlencode" => {
+                        if name != "data-raw" && value.starts_with('@') {
+                            return Err(format!("Reading the request body from a file isn't supported, `{}`", value));
+                        }
+                        data.push(value);
+                    },
+                    "output" => curl.file_name = Path::new(&value).file_name().map(|name| name.to_string_lossy().into_owned()),
+                    name if IGNORED_OPTIONS.contains(&name) => {},
+                    _ => return Err(format!("Unsupported curl option `{}`", arg)),
+                }
+            }
+        }
+
+        curl.url = match urls.len() {
+            0 => return Err(String::from("The curl command doesn't have a URL")),
+            1 => urls.pop().unwrap(),
+            _ => return Err(String::from("The curl command has several URLs, add them one by one")),
+        };
+        if !cookies.is_empty() {
+            curl.headers.push((String::from("Cookie"), cookies.join("; ")));
+        }
+        if !data.is_empty() {
+            curl.body = Some(data.join("&"));
+        }
+        curl.method = method.unwrap_or_else(|| String::from(if curl.body.is_some() { "POST" } else { "GET" }));
+        Ok(curl)
+    }
+
+    /// Returns the headers as a `HeaderMap`.
+    ///
+    /// Returns an error if a header name or value is invalid.
+    pub fn header_map(&self) -> Result<HeaderMap, String> {
+        let mut headers = HeaderMap::new();
+        for (name, header_value) in &self.headers {
+            let header_name = HeaderName::from_bytes(name.as_bytes()).map_err(|_| format!("Invalid header name `{}`", name))?;
+            let header_value = HeaderValue::from_str(header_value).map_err(|_| format!("Invalid value for header `{}`", name))?;
+            headers.append(header_name, header_value);
+        }
+        Ok(headers)
+    }
+
+    /// Configures the engine from the command (URL, method, body, headers and file name) and initializes it.
+    /// `insecure` isn't applied, it's up to the caller to merge it into the TLS options of the engine.
+    ///
+    /// # Arguments
+    ///
+    /// * `engine` - The engine to configure, its output directory must already be set.
+    ///
+    /// Returns an error if the command sends a HEAD request, or the initialization fails.
+    pub async fn apply(&self, engine: &mut RustleDownloader) -> Result<(), String> {
+        if self.method == "HEAD" {
+            return Err(String::from("The curl command sends a HEAD request, there's nothing to download"));
+        }
+        let method = Method::from_str(&self.method).map_err(|e| format!("Invalid method `{}`, error : {}", self.method, e))?;
+        if method != Method::GET || self.body.is_some() {
+            engine.set_request(method, self.body.clone().map(Bytes::from)).await;
+        }
+
+        engine.set_headers(self.header_map()?).await;
+        if let Some(file_name) = self.file_name.as_ref() {
+            engine.set_file_name(file_name).await;
+        }
+        engine.set_url(&self.url).await?;
+        engine.init().await?;
+        Ok(())
+    }
+}
+
+/// Splits a cluster of short options into their long names, e.g. `-sSL`. The first option taking
+/// a value ends the cluster, the rest of it is the value if any, e.g. `-XPOST`.
+///
+/// Returns `None` if an option isn't supported.
+fn split_short_options(cluster: &str) -> Option<Vec<(String, Option<String>)>> {
+    let mut options = Vec::new();
+    for (i, flag) in cluster.char_indices() {
+        let name = short_name(flag)?;
+        if IGNORED_FLAGS.contains(&name) || name == "insecure" || name == "head" {
+            options.push((name.to_string(), None));
+        } else {
+            let rest = &cluster[i + flag.len_utf8()..];
+            options.push((name.to_string(), Some(rest.to_string()).filter(|rest| !rest.is_empty())));
+            break;
+        }
+    }
+    Some(options)
+}
+
+/// Returns the long name of a short curl option.
+fn short_name(flag: char) -> Option<&'static str> {
+    Some(match flag {
+        'H' => "header",
+        'b' => "cookie",
+        'A' => "user-agent",
+        'e' => "referer",
+        'u' => "user",
+        'X' => "request",
+        'd' => "data",
+        'o' => "output",
+        'I' => "head",
+        'k' => "insecure",
+        'L' => "location",
+        's' => "silent",
+        'S' => "show-error",
+        'v' => "verbose",
+        'i' => "include",
+        'g' => "globoff",
+        'f' => "fail",
+        'O' => "remote-name",
+        'J' => "remote-header-name",
+        'm' => "max-time",
+        'w' => "write-out",
+        _ => return None,
+    })
+}
+
+/// Splits a command into its arguments like a POSIX shell, without any expansion.
+/// A backslash followed by whitespace is a line continuation, even when the line break was lost
+/// while pasting the command in a single-line input.
+fn split_arguments(command: &str) -> Result<Vec<String>, String> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut in_argument = false;
+    let mut chars = command.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                in_argument = true;
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => current.push(c),
+                        None => return Err(String::from("Unterminated single quote in the curl command")),
+                    }
+                }
+            },
+            '"' => {
+                in_argument = true;
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\' | '$' | '`')) => current.push(c),
+                            Some('\n') => {},
+                            Some(c) => {
+                                current.push('\\');
+                                current.push(c);
+                            },
+                            None => return Err(String::from("Unterminated double quote in the curl command")),
+                        },
+                        Some(c) => current.push(c),
+                        None => return Err(String::from("Unterminated double quote in the curl command")),
+                    }
+                }
+            },
+            // ANSI-C quoting, used by browsers for values with special characters
+            '$' if chars.peek() == Some(&'\'') => {
+                chars.next();
+                in_argument = true;
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some('\\') => current.push(unescape_ansi_c(&mut chars)?),
+                        Some(c) => current.push(c),
+                        None => return Err(String::from("Unterminated $'..' string in the curl command")),
+                    }
+                }
+            },
+            '\\' => match chars.next() {
+                Some(c) if c.is_whitespace() => {},
+                Some(c) => {
+                    in_argument = true;
+                    current.push(c);
+                },
+                None => {},
+            },
+            c if c.is_whitespace() => {
+                if in_argument {
+                    args.push(std::mem::take(&mut current));
+                    in_argument = false;
+                }
+            },
+            c => {
+                in_argument = true;
+                current.push(c);
+            },
+        }
+    }
+    if in_argument {
+        args.push(current);
+    }
+    Ok(args)
+}
+
+/// Reads the escape sequence following a backslash in a `$'..'` string.
+fn unescape_ansi_c(chars: &mut Peekable<Chars>) -> Result<char, String> {
+    match chars.next() {
+        Some('n') => Ok('\n'),
+        Some('r') => Ok('\r'),
+        Some('t') => Ok('\t'),
+        Some('x') => unescape_hex(chars, 2),
+        Some('u') => unescape_hex(chars, 4),
+        Some('U') => unescape_hex(chars, 8),
+        Some(c) => Ok(c),
+        None => Err(String::from("Unterminated $'..' string in the curl command")),
+    }
+}
+
+/// Reads the character of a hexadecimal escape sequence of up to `len` digits.
+fn unescape_hex(chars: &mut Peekable<Chars>, len: usize) -> Result<char, String> {
+    let mut code = String::new();
+    while code.len() < len && chars.peek().is_some_and(char::is_ascii_hexdigit) {
+        code.push(chars.next().unwrap());
+    }
+    u32::from_str_radix(&code, 16).ok().and_then(char::from_u32)
+        .ok_or_else(|| format!("Invalid escape sequence `{}` in the curl command", code))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_browser_command() {
+        let command = r#"curl 'https://example.com/files/report.pdf?id=7' \
+  -H 'accept: application/pdf' \
+  -H 'accept-encoding: gzip, deflate, br' \
+  -b 'session=abc; theme=dark' \
+  -H $'user-agent: Mozilla/5.0 \'test\'' \
+  --compressed -sSLo out/report.pdf -k"#;
+        let curl = CurlCommand::parse(command).unwrap();
+        assert_eq!(curl.url, "https://example.com/files/report.pdf?id=7");
+        assert_eq!(curl.method, "GET");
+        assert_eq!(curl.headers, vec![
+            (String::from("accept"), String::from("application/pdf")),
+            (String::from("user-agent"), String::from("Mozilla/5.0 'test'")),
+            (String::from("Cookie"), String::from("session=abc; theme=dark")),
+        ]);
+        assert_eq!(curl.file_name.as_deref(), Some("report.pdf"));
+        assert!(curl.insecure);
+    }
+
+    #[test]
+    fn parse_rejects_invalid_commands() {
+        assert!(CurlCommand::parse("wget https://example.com").is_err());
+        assert!(CurlCommand::parse("curl --proxy http://proxy https://example.com").is_err());
+        assert!(CurlCommand::parse("curl 'https://example.com").is_err());
+        assert!(CurlCommand::parse("curl -H").is_err());
+        assert_eq!(CurlCommand::parse("curl -XPOST -d a=1 https://example.com").unwrap().body.as_deref(), Some("a=1"));
+        assert_eq!(CurlCommand::parse("curl --data-raw '{}' https://example.com").unwrap().method, "POST");
+    }
+}
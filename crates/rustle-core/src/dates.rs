@@ -0,0 +1,63 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Seconds in a day, the dates are in UTC.
+pub const DAY_SECS: u64 = 24 * 3600;
+
+/// Returns the days between the Unix epoch and the day of `time`, in UTC.
+/// Times before the epoch are counted as the epoch.
+pub fn day_of(time: SystemTime) -> i64 {
+    (time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() / DAY_SECS) as i64
+}
+
+/// Returns the days between the Unix epoch and a civil date, negative before the epoch.
+/// See http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+///
+/// # Arguments
+///
+/// * `year` - The year.
+/// * `month` - The month, from 1 to 12.
+/// * `day` - The day of the month, from 1 to 31, it isn't checked against the length of the month.
+pub fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let (month, day) = (i64::from(month), i64::from(day));
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Returns the year, month and day of the month of a day count since the Unix epoch.
+/// See http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+pub fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    (year_of_era + era * 400 + i64::from(month <= 2), month as u32, day as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn civil_dates_round_trip() {
+        for (date, days) in [((1970, 1, 1), 0), ((2024, 2, 29), 19_782), ((2024, 3, 1), 19_783), ((1969, 12, 31), -1), ((2000, 1, 1), 10_957), ((1600, 3, 1), -135_080)] {
+            assert_eq!(days_from_civil(date.0, date.1, date.2), days);
+            assert_eq!(civil_from_days(days), date);
+        }
+        // Every day of a few centuries, leap years of all kinds included
+        for days in -200_000..200_000 {
+            let (year, month, day) = civil_from_days(days);
+            assert_eq!(days_from_civil(year, month, day), days);
+        }
+        assert_eq!(day_of(UNIX_EPOCH + Duration::from_secs(19_782 * DAY_SECS + DAY_SECS - 1)), 19_782);
+        assert_eq!(day_of(UNIX_EPOCH - Duration::from_secs(1)), 0);
+    }
+}
@@ -0,0 +1,454 @@
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use hyper::client::connect::dns::Name;
+use reqwest::dns::{Addrs, Resolve, Resolving};
+use reqwest::header::{ACCEPT, CONTENT_TYPE};
+use reqwest::Client;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpStream, UdpSocket};
+use url::Url;
+use super::client::AddressFamily;
+
+/// Port of the DNS servers given without one.
+pub const DNS_PORT: u16 = 53;
+
+/// Time a DNS server or a DNS-over-HTTPS endpoint has to answer a query.
+pub const DNS_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Time the addresses returned by the system resolver are cached, it doesn't tell their TTL.
+pub const SYSTEM_DNS_TTL: Duration = Duration::from_secs(60);
+
+/// Longest time addresses are cached, even if their records live longer.
+pub const MAX_DNS_TTL: Duration = Duration::from_secs(300);
+
+/// Number of hosts the cache holds before the expired ones are dropped.
+pub const DNS_CACHE_LEN: usize = 256;
+
+/// Media type of the DNS messages sent over HTTPS, see RFC 8484.
+const DNS_MESSAGE: &str = "application/dns-message";
+
+/// Record type of the IPv4 addresses.
+const TYPE_A: u16 = 1;
+/// Record type of the IPv6 addresses.
+const TYPE_AAAA: u16 = 28;
+
+/// DnsOptions represents the servers the hostnames are resolved with, the system resolver if none is set.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DnsOptions {
+    pub servers: Vec<SocketAddr>,   // DNS servers queried over UDP in order, falling back to TCP for truncated answers
+    pub over_https: Option<Url>,    // DNS-over-HTTPS endpoint, e.g. `https://cloudflare-dns.com/dns-query`, used instead of `servers`
+}
+
+impl DnsOptions {
+    /// Parses the address of a DNS server, e.g. `1.1.1.1`, `9.9.9.9:5353` or `[2606:4700::1111]:53`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error message if the server isn't an IP address, with an optional port.
+    pub fn parse_server(server: &str) -> Result<SocketAddr, String> {
+        let server = server.trim();
+        server.parse::<SocketAddr>()
+            .or_else(|_| server.parse::<IpAddr>().map(|ip| SocketAddr::new(ip, DNS_PORT)))
+            .map_err(|_| format!("Invalid DNS server `{}`, expected an IP address with an optional port", server))
+    }
+
+    /// Parses a DNS-over-HTTPS endpoint, which has to be an `https` URL.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error message if the endpoint isn't an `https` URL.
+    pub fn parse_over_https(endpoint: &str) -> Result<Url, String> {
+        Url::parse(endpoint.trim()).ok()
+            .filter(|url| url.scheme() == "https")
+            .ok_or_else(|| format!("Invalid DNS-over-HTTPS endpoint `{}`, expected an https URL", endpoint))
+    }
+}
+
+/// Addresses of a host, until they expire.
+#[derive(Debug)]
+struct CacheEntry {
+    addrs: Vec<IpAddr>,     // Resolved addresses
+    expires: Instant,       // When the host is resolved again
+}
+
+/// DnsCache keeps the addresses of the resolved hosts for the TTL of their records. A host
+/// resolved by several requests at once, e.g. the parts of a download, is resolved once and
+/// the others wait for its addresses. Failed lookups aren't cached.
+#[derive(Debug, Default)]
+pub struct DnsCache {
+    entries: Mutex<HashMap<String, Arc<tokio::sync::Mutex<Option<CacheEntry>>>>>,  // Addresses of each host, locked while it's resolved
+}
+
+impl DnsCache {
+    /// Returns the cached addresses of `host`, or resolves them with `resolve` and caches them.
+    ///
+    /// # Arguments
+    ///
+    /// * `host` - The hostname.
+    /// * `resolve` - Resolves the host, returning its addresses and how long they're valid.
+    pub async fn get_or_resolve<F, Fut>(&self, host: &str, resolve: F) -> Result<Vec<IpAddr>, String>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<(Vec<IpAddr>, Duration), String>>,
+    {
+        let slot = {
+            let mut entries = self.entries.lock().unwrap();
+            let host = host.to_ascii_lowercase();
+            if entries.len() >= DNS_CACHE_LEN && !entries.contains_key(&host) {
+                // Drops the expired hosts nobody is resolving
+                let now = Instant::now();
+                entries.retain(|_, slot| slot.try_lock().map_or(true, |entry| entry.as_ref().is_some_and(|entry| entry.expires > now)));
+            }
+            entries.entry(host).or_default().clone()
+        };
+
+        let mut entry = slot.lock().await;
+        if let Some(entry) = entry.as_ref().filter(|entry| entry.expires > Instant::now()) {
+            return Ok(entry.addrs.clone());
+        }
+        let (addrs, ttl) = resolve().await?;
+        *entry = Some(CacheEntry { addrs: addrs.clone(), expires: Instant::now() + ttl.min(MAX_DNS_TTL) });
+        Ok(addrs)
+    }
+}
+
+/// DnsResolver resolves hostnames with the system resolver, custom DNS servers or a
+/// DNS-over-HTTPS endpoint, then filters and orders the addresses according to the
+/// preferred address family. The addresses are cached, so the parts of a download share
+/// a single resolution of their host.
+///
+/// The connector tries the addresses in order (falling back to the other family after a
+/// short delay), so putting the preferred family first is enough to avoid broken IPv6 routes.
+#[derive(Debug, Clone)]
+pub struct DnsResolver {
+    family: AddressFamily,      // Preferred address family
+    options: DnsOptions,        // Servers the hostnames are resolved with
+    cache: Arc<DnsCache>,       // Addresses of the resolved hosts
+    https_client: Option<Client>,   // Client of the DNS-over-HTTPS endpoint, its host is resolved by the system resolver
+}
+
+impl DnsResolver {
+    /// Creates a new DnsResolver with the given address family preference and servers.
+    pub fn new(family: AddressFamily, options: DnsOptions) -> Self {
+        let https_client = options.over_https.as_ref().map(|_| Client::new());
+        DnsResolver { family, options, cache: Arc::default(), https_client }
+    }
+
+    /// Resolves a host without the cache, returning its addresses and how long they're valid.
+    async fn lookup(&self, host: &str) -> Result<(Vec<IpAddr>, Duration), String> {
+        if self.options.over_https.is_none() && self.options.servers.is_empty() {
+            let addrs = tokio::net::lookup_host((host, 0)).await
+                .map_err(|e| format!("Couldn't resolve {}, error : {}", host, e))?;
+            return Ok((addrs.map(|addr| addr.ip()).collect(), SYSTEM_DNS_TTL));
+        }
+
+        let ipv6 = async {
+            match self.family {
+                AddressFamily::Ipv4Only => Ok(DnsAnswer::default()),
+                _ => self.query(host, TYPE_AAAA).await,
+            }
+        };
+        let (ipv4, ipv6) = tokio::join!(self.query(host, TYPE_A), ipv6);
+        let answers: Vec<DnsAnswer> = match (ipv4, ipv6) {
+            (Err(e), Err(_)) => return Err(e),
+            (ipv4, ipv6) => ipv4.into_iter().chain(ipv6).filter(|answer| !answer.addrs.is_empty()).collect(),
+        };
+        match answers.iter().map(|answer| answer.ttl).min() {
+            Some(ttl) => Ok((answers.into_iter().flat_map(|answer| answer.addrs).collect(), ttl)),
+            None => Err(format!("No address found for {}", host)),
+        }
+    }
+
+    /// Queries the records of `record_type` of a host, over HTTPS or from the first server answering.
+    async fn query(&self, host: &str, record_type: u16) -> Result<DnsAnswer, String> {
+        if let (Some(endpoint), Some(client)) = (self.options.over_https.as_ref(), self.https_client.as_ref()) {
+            // The ID is 0 over HTTPS so the answers can be cached by HTTP caches
+            let query = encode_query(0, host, record_type)?;
+            let response = client.post(endpoint.clone())
+                .header(CONTENT_TYPE, DNS_MESSAGE)
+                .header(ACCEPT, DNS_MESSAGE)
+                .timeout(DNS_TIMEOUT)
+                .body(query)
+                .send().await
+                .and_then(|response| response.error_for_status())
+                .map_err(|e| format!("DNS-over-HTTPS query to {} failed, error : {}", endpoint, e))?;
+            let message = response.bytes().await.map_err(|e| format!("DNS-over-HTTPS query to {} failed, error : {}", endpoint, e))?;
+            return decode_response(0, &message);
+        }
+
+        let mut last_error = String::from("No DNS server");
+        for server in self.options.servers.iter() {
+            match tokio::time::timeout(DNS_TIMEOUT, query_server(*server, host, record_type)).await {
+                Ok(Ok(answer)) => return Ok(answer),
+                Ok(Err(e)) => last_error = e,
+                Err(_) => last_error = format!("DNS server {} didn't answer", server),
+            }
+        }
+        Err(last_error)
+    }
+}
+
+impl Resolve for DnsResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let resolver = self.clone();
+        Box::pin(async move {
+            let host = name.as_str();
+            let addrs = resolver.cache.get_or_resolve(host, || resolver.lookup(host)).await?;
+            let addrs = sort_by_family(addrs.into_iter().map(|ip| SocketAddr::new(ip, 0)).collect(), resolver.family);
+            if addrs.is_empty() {
+                return Err(format!("No address of the allowed family found for {}", host).into());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Queries a DNS server over UDP, and over TCP if the answer doesn't fit in a datagram.
+async fn query_server(server: SocketAddr, host: &str, record_type: u16) -> Result<DnsAnswer, String> {
+    let id = RandomState::new().build_hasher().finish() as u16;
+    let query = encode_query(id, host, record_type)?;
+    let error = |e: std::io::Error| format!("DNS query to {} failed, error : {}", server, e);
+
+    let local: SocketAddr = match server {
+        SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+    };
+    let socket = UdpSocket::bind(local).await.map_err(error)?;
+    socket.connect(server).await.map_err(error)?;
+    socket.send(&query).await.map_err(error)?;
+    let mut message = vec![0; 512];
+    let answer = loop {
+        let len = socket.recv(&mut message).await.map_err(error)?;
+        // Datagrams of other queries are ignored
+        if message[..len.min(2)] == id.to_be_bytes() {
+            break decode_response(id, &message[..len])?;
+        }
+    };
+    if !answer.truncated {
+        return Ok(answer);
+    }
+
+    let mut stream = TcpStream::connect(server).await.map_err(error)?;
+    let mut framed = (query.len() as u16).to_be_bytes().to_vec();
+    framed.extend_from_slice(&query);
+    stream.write_all(&framed).await.map_err(error)?;
+    let len = stream.read_u16().await.map_err(error)?;
+    let mut message = vec![0; len as usize];
+    stream.read_exact(&mut message).await.map_err(error)?;
+    decode_response(id, &message)
+}
+
+/// Addresses found in the answer of a DNS server.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct DnsAnswer {
+    pub addrs: Vec<IpAddr>,     // Addresses of the A and AAAA records
+    pub ttl: Duration,          // Shortest TTL of the records
+    pub truncated: bool,        // Whether the answer was truncated to fit in a UDP datagram
+}
+
+/// Encodes the DNS query of the records of `record_type` of a host, with recursion desired.
+///
+/// # Errors
+///
+/// Returns an error message if the host isn't a valid domain name.
+pub fn encode_query(id: u16, host: &str, record_type: u16) -> Result<Vec<u8>, String> {
+    let invalid = || format!("Invalid hostname `{}`", host);
+    let mut message = Vec::with_capacity(18 + host.len());
+    message.extend_from_slice(&id.to_be_bytes());
+    // Recursion desired, one question
+    message.extend_from_slice(&[0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+    for label in host.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(invalid());
+        }
+        message.push(label.len() as u8);
+        message.extend_from_slice(label.as_bytes());
+    }
+    message.push(0);
+    if message.len() - 12 > 255 {
+        return Err(invalid());
+    }
+    message.extend_from_slice(&record_type.to_be_bytes());
+    // Class IN
+    message.extend_from_slice(&[0x00, 0x01]);
+    Ok(message)
+}
+
+/// Decodes the answer of a DNS server to the query `id`, keeping its A and AAAA records.
+///
+/// # Errors
+///
+/// Returns an error message if the answer is malformed, answers another query, or the server
+/// reported an error (e.g. the host doesn't exist).
+pub fn decode_response(id: u16, message: &[u8]) -> Result<DnsAnswer, String> {
+    let malformed = || String::from("Malformed DNS answer");
+    let read_u16 = |pos: usize| message.get(pos..pos + 2).map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]])).ok_or_else(malformed);
+
+    if read_u16(0)? != id || message[2] & 0x80 == 0 {
+        return Err(String::from("The DNS answer doesn't match the query"));
+    }
+    match message[3] & 0x0f {
+        0 => {},
+        3 => return Err(String::from("No such host")),
+        rcode => return Err(format!("The DNS server failed with code {}", rcode)),
+    }
+    let truncated = message[2] & 0x02 != 0;
+    let questions = read_u16(4)?;
+    let records = read_u16(6)?;
+
+    let mut pos = 12;
+    for _ in 0..questions {
+        pos = skip_name(message, pos)? + 4;
+    }
+    let mut answer = DnsAnswer { truncated, ..Default::default() };
+    let mut ttl = None;
+    for _ in 0..records {
+        pos = skip_name(message, pos)?;
+        let record_type = read_u16(pos)?;
+        let record_ttl = ((read_u16(pos + 4)? as u32) << 16) | read_u16(pos + 6)? as u32;
+        let len = read_u16(pos + 8)? as usize;
+        let data = message.get(pos + 10..pos + 10 + len).ok_or_else(malformed)?;
+        pos += 10 + len;
+
+        // CNAME records are followed by the addresses of their target
+        let ip = match (record_type, data.len()) {
+            (TYPE_A, 4) => IpAddr::from(<[u8; 4]>::try_from(data).unwrap()),
+            (TYPE_AAAA, 16) => IpAddr::from(<[u8; 16]>::try_from(data).unwrap()),
+            _ => continue,
+        };
+        answer.addrs.push(ip);
+        ttl = Some(ttl.unwrap_or(u32::MAX).min(record_ttl));
+    }
+    answer.ttl = Duration::from_secs(ttl.unwrap_or(0) as u64);
+    Ok(answer)
+}
+
+/// Returns the position following the name starting at `pos`, which may end with a compression pointer.
+fn skip_name(message: &[u8], mut pos: usize) -> Result<usize, String> {
+    loop {
+        match message.get(pos) {
+            Some(0) => return Ok(pos + 1),
+            Some(len) if len & 0xc0 == 0xc0 => return Ok(pos + 2),
+            Some(len) => pos += 1 + *len as usize,
+            None => return Err(String::from("Malformed DNS answer")),
+        }
+    }
+}
+
+/// Filters and orders addresses according to the address family preference.
+///
+/// # Arguments
+///
+/// * `addrs` - The resolved addresses.
+/// * `family` - The address family preference.
+///
+/// # Returns
+///
+/// * `Vec<SocketAddr>` - The addresses to try, in order.
+pub fn sort_by_family(mut addrs: Vec<SocketAddr>, family: AddressFamily) -> Vec<SocketAddr> {
+    match family {
+        AddressFamily::Auto => {},
+        AddressFamily::PreferIpv4 => addrs.sort_by_key(|addr| !addr.is_ipv4()),
+        AddressFamily::PreferIpv6 => addrs.sort_by_key(|addr| !addr.is_ipv6()),
+        AddressFamily::Ipv4Only => addrs.retain(|addr| addr.is_ipv4()),
+    }
+    addrs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Returns the answer of a server to `query`, with a CNAME followed by an A and an AAAA record.
+    fn answer_to(query: &[u8]) -> Vec<u8> {
+        let mut message = query.to_vec();
+        message[2] |= 0x80;
+        message[7] = 3;
+        // CNAME pointing to the question's name
+        message.extend_from_slice(&[0xc0, 0x0c, 0x00, 0x05, 0x00, 0x01, 0x00, 0x00, 0x0e, 0x10, 0x00, 0x02, 0xc0, 0x0c]);
+        message.extend_from_slice(&[0xc0, 0x0c, 0x00, 0x01, 0x00, 0x01, 0x00, 0x00, 0x00, 0x78, 0x00, 0x04, 93, 184, 216, 34]);
+        message.extend_from_slice(&[0xc0, 0x0c, 0x00, 0x1c, 0x00, 0x01, 0x00, 0x00, 0x00, 0x3c, 0x00, 0x10]);
+        message.extend_from_slice(&"2606:2800:220:1::248".parse::<Ipv6Addr>().unwrap().octets());
+        message
+    }
+
+    #[test]
+    fn queries_and_answers_are_encoded() {
+        let query = encode_query(0x1234, "example.com.", TYPE_A).unwrap();
+        assert_eq!(&query[..12], &[0x12, 0x34, 0x01, 0x00, 0x00, 0x01, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(&query[12..], b"\x07example\x03com\x00\x00\x01\x00\x01");
+        assert!(encode_query(1, "a..b", TYPE_A).is_err());
+        assert!(encode_query(1, &"a".repeat(64), TYPE_A).is_err());
+
+        let answer = decode_response(0x1234, &answer_to(&query)).unwrap();
+        assert_eq!(answer.addrs, vec![IpAddr::from([93, 184, 216, 34]), "2606:2800:220:1::248".parse::<IpAddr>().unwrap()]);
+        assert_eq!(answer.ttl, Duration::from_secs(60));
+        assert!(!answer.truncated);
+
+        assert!(decode_response(0x4321, &answer_to(&query)).is_err());
+        assert!(decode_response(0x1234, &answer_to(&query)[..40]).is_err());
+        let mut not_found = query.clone();
+        not_found[2] |= 0x80;
+        not_found[3] |= 0x03;
+        assert_eq!(decode_response(0x1234, &not_found), Err(String::from("No such host")));
+
+        assert_eq!(DnsOptions::parse_server("1.1.1.1"), Ok(SocketAddr::from(([1, 1, 1, 1], 53))));
+        assert_eq!(DnsOptions::parse_server("[::1]:5353"), Ok(SocketAddr::from((Ipv6Addr::LOCALHOST, 5353))));
+        assert!(DnsOptions::parse_server("dns.example").is_err());
+        assert!(DnsOptions::parse_over_https("http://dns.example/dns-query").is_err());
+    }
+
+    #[tokio::test]
+    async fn concurrent_lookups_share_a_resolution() {
+        let cache = Arc::new(DnsCache::default());
+        let resolutions = Arc::new(AtomicUsize::new(0));
+        let lookups = (0..4).map(|_| {
+            let (cache, resolutions) = (cache.clone(), resolutions.clone());
+            tokio::spawn(async move {
+                cache.get_or_resolve("Example.com", || async {
+                    resolutions.fetch_add(1, Ordering::Relaxed);
+                    tokio::time::sleep(Duration::from_millis(20)).await;
+                    Ok((vec![IpAddr::from([127, 0, 0, 1])], Duration::from_secs(60)))
+                }).await
+            })
+        }).collect::<Vec<_>>();
+        for lookup in lookups {
+            assert_eq!(lookup.await.unwrap(), Ok(vec![IpAddr::from([127, 0, 0, 1])]));
+        }
+        assert_eq!(resolutions.load(Ordering::Relaxed), 1);
+
+        // Expired and failed lookups are resolved again
+        let expired = cache.get_or_resolve("expired.test", || async { Ok((vec![IpAddr::from([127, 0, 0, 2])], Duration::ZERO)) }).await;
+        assert_eq!(expired, Ok(vec![IpAddr::from([127, 0, 0, 2])]));
+        let failed = cache.get_or_resolve("expired.test", || async { Err(String::from("No such host")) }).await;
+        assert_eq!(failed, Err(String::from("No such host")));
+    }
+
+    #[tokio::test]
+    async fn hosts_are_resolved_by_the_configured_server() {
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let options = DnsOptions { servers: vec![server.local_addr().unwrap()], over_https: None };
+        tokio::spawn(async move {
+            let mut query = vec![0; 512];
+            loop {
+                let (len, client) = server.recv_from(&mut query).await.unwrap();
+                server.send_to(&answer_to(&query[..len]), client).await.unwrap();
+            }
+        });
+
+        let resolver = DnsResolver::new(AddressFamily::Ipv4Only, options);
+        let (addrs, ttl) = resolver.lookup("example.com").await.unwrap();
+        // The answer carries both records, the IPv6 address is filtered out
+        assert!(addrs.contains(&IpAddr::from([93, 184, 216, 34])));
+        assert_eq!(ttl, Duration::from_secs(60));
+        let addrs: Vec<SocketAddr> = resolver.resolve(Name::from_str("example.com").unwrap()).await.unwrap().collect();
+        assert_eq!(addrs, vec![SocketAddr::from(([93, 184, 216, 34], 0))]);
+    }
+}
@@ -0,0 +1,205 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+use super::audit::is_sha256_hex;
+use super::downloader::{DownloadStatus, RustleDownloader, ValidUrl};
+use super::queue_manager::{Priority, QueueManager, QueueState};
+
+/// Default name of an exported download list.
+pub const DOWNLOAD_LIST_FILE_NAME: &str = "rustle-downloads.json";
+
+/// Version of the format written by `export_json`.
+const DOWNLOAD_LIST_FORMAT: u32 = 1;
+
+/// DownloadList represents the downloads of a queue exported as JSON, to move the queue to
+/// another machine or back it up:
+///
+/// ```json
+/// {
+///   "format": 1,
+///   "downloads": [
+///     {
+///       "url": "https://example.com/file.iso",
+///       "file_name": "file.iso",
+///       "out_dir": "/home/user/Downloads",
+///       "priority": "high",
+///       "status": "paused",
+///       "queued": false,
+///       "downloaded_bytes": 1048576,
+///       "content_length": 4194304
+///     }
+///   ]
+/// }
+/// ```
+///
+/// The progress is informative, the received bytes aren't part of the list so imported
+/// downloads start over.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DownloadList {
+    pub format: u32,                        // Version of the format
+    pub downloads: Vec<DownloadListEntry>,  // Downloads, in the order they were added
+}
+
+/// DownloadListEntry represents a download of an exported list.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DownloadListEntry {
+    pub url: String,                        // URL of the file
+    pub file_name: Option<String>,          // Name of the downloaded file
+    pub out_dir: Option<PathBuf>,           // Output directory, the one of the importing application if missing
+    pub connections: Option<u8>,            // Number of connections set by the user, picked from the file size if missing
+    pub expected_sha256: Option<String>,    // Expected SHA-256 of the file
+    pub priority: Priority,                 // Priority in the queue
+    pub status: DownloadStatus,             // Status of the download when it was exported
+    pub queued: bool,                       // Whether the download was started and waiting for, or holding, a slot of the queue
+    pub start_at: Option<u64>,              // Start time of a scheduled download, in seconds since the Unix epoch
+    pub downloaded_bytes: u64,              // Bytes received when it was exported
+    pub content_length: Option<u64>,        // Size of the file in bytes, if known
+}
+
+impl DownloadList {
+    /// Takes the downloads of a queue, see `export_json`.
+    pub async fn from_queue(queue: &QueueManager) -> DownloadList {
+        let mut downloads = Vec::new();
+        for id in queue.ids() {
+            let Some(engine) = queue.engine(id) else { continue };
+            let Some(url) = engine.get_url().await else { continue };
+            let state = queue.state(id).unwrap_or_default();
+            let start_at = match state {
+                QueueState::Scheduled => queue.start_time(id)
+                    .and_then(|start_at| start_at.duration_since(UNIX_EPOCH).ok())
+                    .map(|start_at| start_at.as_secs()),
+                _ => None,
+            };
+            downloads.push(DownloadListEntry {
+                url,
+                file_name: engine.get_file_path().await
+                    .and_then(|path| path.file_name().map(|name| name.to_string_lossy().into_owned())),
+                out_dir: engine.get_out_dir().await,
+                connections: engine.get_connections().await,
+                expected_sha256: engine.get_expected_sha256().await,
+                priority: queue.priority(id).unwrap_or_default(),
+                status: engine.get_status().await,
+                queued: matches!(state, QueueState::Queued | QueueState::Active | QueueState::DelayedRetry),
+                start_at,
+                downloaded_bytes: engine.get_progress_vec().await.iter().map(|part| part.downloaded_bytes as u64).sum(),
+                content_length: engine.get_file_info().await.and_then(|info| info.content_length),
+            });
+        }
+        DownloadList { format: DOWNLOAD_LIST_FORMAT, downloads }
+    }
+
+    /// Loads a list exported to a file, see `import_json`.
+    pub fn load(path: &Path) -> Result<DownloadList, String> {
+        let json = fs::read_to_string(path).map_err(|e| format!("Couldn't read the download list {}, error : {}", path.display(), e))?;
+        import_json(&json).map_err(|e| format!("Invalid download list {}, {}", path.display(), e))
+    }
+
+    /// Saves the list to a file, overwriting it if it exists.
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(self).map_err(|e| format!("Couldn't serialize the download list, error : {}", e))?;
+        fs::write(path, json).map_err(|e| format!("Couldn't write the download list {}, error : {}", path.display(), e))
+    }
+
+    /// Returns the downloads that aren't finished, the ones worth importing.
+    pub fn unfinished(&self) -> impl Iterator<Item = &DownloadListEntry> {
+        self.downloads.iter().filter(|entry| entry.status != DownloadStatus::Done)
+    }
+}
+
+impl DownloadListEntry {
+    /// Returns the start time of a scheduled download.
+    pub fn start_time(&self) -> Option<SystemTime> {
+        self.start_at.map(|start_at| UNIX_EPOCH + Duration::from_secs(start_at))
+    }
+
+    /// Returns whether the download should be started once imported, i.e. it was queued or
+    /// running when it was exported.
+    pub fn should_start(&self) -> bool {
+        self.queued || matches!(self.status, DownloadStatus::Downloading | DownloadStatus::Retrying)
+    }
+
+    /// Configures the engine to download the entry and initializes it.
+    ///
+    /// # Arguments
+    ///
+    /// * `engine` - The engine to configure, created with the settings of the importing application.
+    ///
+    /// Returns an error if the initialization fails.
+    pub async fn apply(&self, engine: &mut RustleDownloader) -> Result<(), String> {
+        if let Some(out_dir) = self.out_dir.as_ref() {
+            engine.set_out_dir(&out_dir.to_string_lossy()).await?;
+        }
+        if let Some(file_name) = self.file_name.as_ref() {
+            engine.set_file_name(file_name).await;
+        }
+        if let Some(connections) = self.connections {
+            engine.set_connections(connections).await?;
+        }
+        if let Some(sha256) = self.expected_sha256.as_ref() {
+            engine.set_expected_sha256(sha256).await?;
+        }
+        engine.set_url(&self.url).await?;
+        engine.init().await?;
+        Ok(())
+    }
+}
+
+/// Exports the downloads of a queue as JSON, see `DownloadList`.
+pub async fn export_json(queue: &QueueManager) -> Result<String, String> {
+    serde_json::to_string_pretty(&DownloadList::from_queue(queue).await)
+        .map_err(|e| format!("Couldn't serialize the download list, error : {}", e))
+}
+
+/// Parses a download list exported with `export_json`.
+///
+/// Returns an error if the JSON is invalid, its format isn't supported or a download has an
+/// invalid URL, file name or checksum.
+pub fn import_json(json: &str) -> Result<DownloadList, String> {
+    let list: DownloadList = serde_json::from_str(json).map_err(|e| format!("error : {}", e))?;
+    if list.format != DOWNLOAD_LIST_FORMAT {
+        return Err(format!("unsupported format version {}", list.format));
+    }
+
+    for (i, entry) in list.downloads.iter().enumerate() {
+        ValidUrl::new(&entry.url).map_err(|e| format!("download {}, invalid URL `{}`, error : {}", i + 1, entry.url, e))?;
+        if let Some(name) = entry.file_name.as_ref().filter(|name| *name == "." || *name == ".." || name.contains(['/', '\\'])) {
+            return Err(format!("download {}, invalid file name `{}`, it must not be a path", i + 1, name));
+        }
+        if let Some(sha256) = entry.expected_sha256.as_ref().filter(|sha256| !is_sha256_hex(sha256)) {
+            return Err(format!("download {}, `{}` isn't a valid SHA-256 checksum", i + 1, sha256));
+        }
+    }
+    Ok(list)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn export_then_import_keeps_the_downloads() {
+        let queue = QueueManager::new(1).unwrap();
+        let mut engine = RustleDownloader::new(4).unwrap();
+        engine.set_url("https://example.com/file.iso").await.unwrap();
+        engine.set_out_dir("./").await.unwrap();
+        engine.set_file_name("file.iso").await;
+        engine.set_connections(2).await.unwrap();
+        let id = queue.add(engine);
+        queue.set_priority(id, Priority::High).unwrap();
+
+        let list = import_json(&export_json(&queue).await.unwrap()).unwrap();
+        assert_eq!(list.downloads, vec![DownloadListEntry {
+            url: String::from("https://example.com/file.iso"),
+            file_name: Some(String::from("file.iso")),
+            out_dir: Some(PathBuf::from("./")),
+            connections: Some(2),
+            priority: Priority::High,
+            ..Default::default()
+        }]);
+
+        assert!(import_json(r#"{"format": 2, "downloads": []}"#).unwrap_err().contains("unsupported format"));
+        assert!(import_json(r#"{"format": 1, "downloads": [{"url": "https://example.com/a", "file_name": "../a"}]}"#).is_err());
+    }
+}
@@ -0,0 +1,4440 @@
+use futures::stream::{FuturesUnordered, StreamExt};
+use futures::FutureExt;
+use bytes::{Bytes, BytesMut};
+#[cfg(feature = "cli")]
+use indicatif::{ProgressBar, ProgressStyle};
+use tokio::task::JoinHandle;
+use tokio::task;
+use reqwest::{header::{HeaderMap, AUTHORIZATION, CONTENT_DISPOSITION, CONTENT_ENCODING, ACCEPT_RANGES, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, ETAG, LAST_MODIFIED, REFERER}, Method, StatusCode, Version};
+use serde::{Deserialize, Serialize};
+use url::Url;
+use std::{str::FromStr, time::Duration};
+use std::path::PathBuf;
+use tokio::sync::Mutex;
+use std::sync::Arc;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use super::io::{move_file, sanitize_file_name, BufferingStrategy, FilePermissions, FileWriter, MoveProgress, PartBuffers, StdFileWriter, DEFAULT_FILE_NAME};
+use super::connection_manager::{ConnectionManager, ConnectionPermit, NetworkState};
+use super::client::{AddressFamily, ClientOptions, HttpVersion, TlsOptions};
+use super::dns::DnsOptions;
+use super::audit::{is_sha256_hex, sha256_hex, AuditLog, AuditRecord};
+use super::resume::{check_resume, validate_referer, ResumeMismatch, ResumePart, ResumeToken};
+use super::protocol::ProtocolRegistry;
+use super::transport::{ByteRange, Transport, TransportError, TransportResponse};
+use super::error::ErrorKind;
+use super::builder::RustleDownloaderBuilder;
+use super::rate_limit::RateLimiter;
+use super::credentials::{credential_key, CredentialStore};
+use super::refresh::UrlRefresher;
+use super::encoding::{decode_content, is_compressed, ContentEncodingMode};
+use super::memory::{MemoryBudget, MemoryReservation};
+use super::timeline::{Timeline, TimelineEntry, TimelineEvent};
+use super::segments::{first_corrupt_segment, hash_segments, StreamHasher, SEGMENT_SIZE};
+use super::provenance::write_provenance;
+use super::har::{HarEntry, HarLog, HarTransport};
+use super::mime::{type_mismatch, with_type_extension, TypeMismatch};
+use super::verify::{verify_against, VerifyOptions, VerifyReport};
+use super::usage::DataUsage;
+use super::tuning::{ConnectionTuner, AUTO_START_CONNECTIONS};
+use std::path::Path;
+use tokio::sync::{mpsc, watch};
+use std::time::{Instant, SystemTime};
+
+/// Minimum number of bytes a single connection should be responsible for,
+/// files smaller than this are downloaded over a single connection.
+const MIN_PART_SIZE: u64 = 1024 * 1024;
+
+/// Default minimum time between two progress updates.
+pub const DEFAULT_PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Time constant of the smoothed speed, older throughput weighs `1/e` less every `SPEED_SMOOTHING_US`.
+const SPEED_SMOOTHING_US: f64 = 2_000_000.0;
+
+/// Time between two samples of the speed history.
+pub const SPEED_SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Number of samples kept in the speed history, i.e. the last two minutes.
+pub const SPEED_HISTORY_LEN: usize = 120;
+
+/// Error of the parts whose range request was answered with the whole file, the download is
+/// then restarted over a single connection.
+const RANGES_IGNORED: &str = "The server ignored the range request";
+
+/// Error of the parts that gave their connection to another download holding fewer connections
+/// to the host, the part continues from its received bytes once it gets a connection again.
+const CONNECTION_YIELDED: &str = "The connection was given to another download";
+
+/// Default time a part waits for the next chunk before its connection is considered dead and its
+/// range is requested again, see `RustleDownloader::set_stall_timeout`.
+pub const DEFAULT_STALL_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Number of times in a row a stalled part requests its range again before it fails, the count
+/// starts over once the part receives bytes.
+const MAX_STALL_RESTARTS: u32 = 3;
+
+/// Error of the parts that didn't receive any chunk for the stall timeout, the part continues
+/// from its received bytes with a new request.
+const PART_STALLED: &str = "The part stalled";
+
+/// Default time a download stays paused before the file is checked on the server again when it's
+/// resumed, see `RustleDownloader::set_stale_after`.
+pub const DEFAULT_STALE_AFTER: Duration = Duration::from_secs(3600);
+
+/// Error of an attempt resumed after a long pause, the next attempt checks the file on the server
+/// before its ranges are requested again.
+const STALE_PARTS: &str = "The download was paused for long, the file is checked again";
+
+/// Time between two throughput measurements of a download tuning its number of connections,
+/// the first one after each change is skipped while the new connections ramp up.
+const TUNE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Number of bytes a part buffers in memory before writing them to disk, with another
+/// `BufferingStrategy` than `Memory`.
+const BUFFER_FLUSH_SIZE: usize = 256 * 1024;
+
+/// Time between two checks of a reached data quota, it's available again once its period is over.
+const QUOTA_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Number of times a range request is sent again with a refreshed URL before the part fails.
+const MAX_URL_REFRESHES: u32 = 2;
+
+/// Minimum size of the ranges the remaining bytes of the last running part are split into,
+/// so a slow tail isn't split into requests shorter than the time it takes to send them.
+pub const MIN_TAIL_SPLIT: u64 = 1024 * 1024;
+
+/// Number of received chunks queued between the network side of a part and its sink, the
+/// response isn't read while the queue is full so a slow sink slows the socket down instead of
+/// buffering the chunks.
+pub const PIPELINE_DEPTH: usize = 8;
+
+/// Maximum number of bytes a part hashes after a chunk, while it catches up with the bytes it
+/// received before the ones of the previous parts were hashed, so its sink isn't held for long.
+const MAX_HASH_STEP: usize = 4 * 1024 * 1024;
+
+// Result of a part task: the part's bytes received so far, the digests of their segments, and whether it's complete or the error that stopped it
+type PartResult = (u64, BytesMut, Vec<String>, Result<bool, String>);
+
+/// Represents the level of support for partial requests.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SupportPartialRequest {
+    /// Indicates that partial requests are supported.
+    Yes,
+    /// Indicates that partial requests are not supported.
+    No,
+    /// Indicates an unknown level of support for partial requests.
+    #[default]
+    Unknown
+}
+
+/// Represents the request used to probe the download's headers during `init()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InitStrategy {
+    /// A HEAD request.
+    Head,
+    /// A GET request for the first byte only.
+    RangedGet,
+    /// A plain GET request whose body is discarded.
+    Get,
+}
+
+impl InitStrategy {
+    /// All the strategies, in the order they are tried by default.
+    pub const ALL: [InitStrategy; 3] = [InitStrategy::Head, InitStrategy::RangedGet, InitStrategy::Get];
+}
+
+/// ContentRange represents a parsed `Content-Range: bytes <start>-<end>/<total>` header value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContentRange {
+    pub start: u64,             // First byte of the range
+    pub end: u64,               // Last byte of the range (inclusive)
+    pub total: Option<u64>,     // Total length of the resource, `None` if the server sent `*`
+}
+
+impl FromStr for ContentRange {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid Content-Range header : {}", value);
+
+        let range = value.trim().strip_prefix("bytes ").ok_or_else(invalid)?;
+        let (span, total) = range.split_once('/').ok_or_else(invalid)?;
+        let (start, end) = span.split_once('-').ok_or_else(invalid)?;
+
+        let start: u64 = start.trim().parse().map_err(|_| invalid())?;
+        let end: u64 = end.trim().parse().map_err(|_| invalid())?;
+        let total = match total.trim() {
+            "*" => None,
+            total => Some(total.parse::<u64>().map_err(|_| invalid())?),
+        };
+
+        if end < start || total.is_some_and(|total| end >= total) {
+            return Err(invalid());
+        }
+
+        Ok(ContentRange { start, end, total })
+    }
+}
+
+/// A wrapper struct for a valid URL.
+/// It provides a convenient way to create and access a URL.
+#[derive(Debug, Clone)]
+pub struct ValidUrl(Url);
+
+impl ValidUrl {
+    /// Creates a new ValidUrl instance from a string representation of a URL.
+    /// Returns Ok with the ValidUrl if the URL is valid, or a ParseError if it's not.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - A string slice representing a URL.
+    pub fn new(url: &str) -> Result<Self, url::ParseError> {
+        let parsed_url = Url::from_str(url)?;
+        Ok(ValidUrl(parsed_url))
+    }
+
+    /// Returns the string representation of the URL.
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+
+    /// Returns the parsed URL.
+    pub fn as_url(&self) -> &Url {
+        &self.0
+    }
+
+    /// Returns the host of the URL, if present.
+    pub fn host(&self) -> Option<&str> {
+        self.0.host_str()
+    }
+}
+
+
+/// ResponseHeaderInfo represents the header information received in response to a request.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ResponseHeaderInfo {
+    pub support_partial: SupportPartialRequest,   // Indicates whether partial downloading is supported
+    pub content_length: Option<u64>,              // Length of the content in bytes
+    pub content_type: Option<String>,             // MIME type of the content
+    pub file_name: Option<String>,                // Name of the file
+    pub etag: Option<String>,                     // Entity tag of the file, if sent
+    pub last_modified: Option<String>,            // Last modification date of the file, if sent
+    pub content_encoding: Option<String>,         // `Content-Encoding` of a compressed response, its length is the compressed one
+}
+
+impl ResponseHeaderInfo {
+    /// Returns the validator sent in `If-Range`, so range requests fail instead of mixing two
+    /// versions of the file if it changes on the server. Weak entity tags can't be used there.
+    fn if_range(&self) -> Option<&str> {
+        self.etag.as_deref().filter(|etag| !etag.starts_with("W/")).or(self.last_modified.as_deref())
+    }
+}
+
+/// PartDownloadInfo represents information about a downloaded part of a file.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct PartDownloadInfo {
+    pub downloaded_bytes: usize,  // Number of bytes downloaded for this part
+    pub download_speed: f64,      // Download speed in bytes per second for this part, averaged over the current request
+    pub smoothed_speed: f64,      // Exponentially smoothed download speed in bytes per second, follows the live throughput
+    pub network_wait: Duration,   // Time the sink of the part waited for chunks from the network
+    pub backpressure: Duration,   // Time the network side of the part waited for the sink, with `PIPELINE_DEPTH` chunks queued
+}
+
+/// DownloadSpeed represents the aggregate download speed of all the parts.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct DownloadSpeed {
+    pub average: f64,     // Sum of the parts' speeds averaged over their current requests, in bytes per second
+    pub smoothed: f64,    // Sum of the parts' exponentially smoothed speeds, in bytes per second
+}
+
+/// DownloadSnapshot represents the state and progress of a download at one point in time, as
+/// returned by `snapshot()`. It serializes as is, e.g. to JSON for a daemon API or a log line.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DownloadSnapshot {
+    pub url: Option<String>,                  // URL of the download, if it was set
+    pub file_path: Option<PathBuf>,           // Path the file is written to, once the download is initialized
+    pub status: DownloadStatus,               // Current status of the download
+    pub file_info: Option<ResponseHeaderInfo>,  // File information from the response headers, once the download is initialized
+    pub parts: Vec<PartDownloadInfo>,         // Progress of each part
+    pub downloaded_bytes: u64,                // Bytes received by all the parts
+    pub speed: DownloadSpeed,                 // Aggregate download speed of all the parts
+    pub eta: Option<Duration>,                // Estimated remaining time, while the download is running
+    pub error_kind: Option<ErrorKind>,        // Kind of the error the last attempt failed with, if it did
+}
+
+/// RetryPolicy represents how a failed download is retried: the whole download is attempted
+/// again, continuing from the bytes received so far, after an exponentially growing delay.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub max_retries: u32,           // Number of retries after the first attempt, `0` disables them
+    pub initial_delay: Duration,    // Delay before the first retry, doubled after each retry
+    pub max_delay: Duration,        // Upper bound of the delay
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 0,
+            initial_delay: Duration::from_secs(2),
+            max_delay: Duration::from_secs(60),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Returns the delay before the given retry, starting at `1`.
+    pub fn delay(&self, retry: u32) -> Duration {
+        let factor = 2u32.saturating_pow(retry.saturating_sub(1));
+        self.initial_delay.saturating_mul(factor).min(self.max_delay)
+    }
+}
+
+/// RetryInfo represents a retry waiting to be attempted, while the download is `Retrying`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryInfo {
+    pub retry: u32,             // Number of the upcoming retry, starting at `1`
+    pub max_retries: u32,       // Number of retries allowed by the policy
+    pub next_attempt: Instant,  // When the retry starts
+    pub error: String,          // Error of the failed attempt
+    pub kind: ErrorKind,        // Kind of the error of the failed attempt
+}
+
+/// RustleDownloaderInner represents the internal state of the RustleDownloader.
+#[derive(Debug, Default)]
+struct RustleDownloaderInner {
+    pub url: Option<ValidUrl>,                    // URL for downloading
+    pub out_dir: Option<PathBuf>,                 // Output directory for downloaded files
+    pub max_parallel_connections: u8,             // Number of parallel connections allowed for partial downloading
+    pub connections_override: Option<u8>,         // Number of connections explicitly set by the user, skips the adaptive selection
+    pub get_headers_info: Option<ResponseHeaderInfo>,  // Header information received in response to a request
+    pub file_name: Option<String>,                // File name set by the user, overrides the detected one
+    pub expected_sha256: Option<String>,          // Lowercase hex SHA-256 the downloaded file must match
+    pub retry_policy: RetryPolicy,                // How failed downloads are retried
+    pub retry_info: Option<RetryInfo>,            // Retry waiting to be attempted, while `Retrying`
+    error_kind: Option<ErrorKind>,                // Kind of the first error of the last attempt, `None` if it didn't fail
+    pub progress_bar: Option<ProgressBar>,        // Progress bar for tracking download progress
+    progress: Arc<[PartCounters]>,                // Progress of each part, updated by the part tasks without taking this lock
+    progress_interval: Duration,                  // Minimum time between two progress updates (progress bar and snapshots)
+    last_progress: Option<Instant>,               // When the last progress snapshot was handed out
+    speed_history: VecDeque<f64>,                 // Aggregate throughput of the last `SPEED_HISTORY_LEN` sample intervals, oldest first
+    speed_sampler: Option<JoinHandle<()>>,        // Task appending to `speed_history` while the download runs
+    auto_connections: bool,                       // Whether the number of connections is tuned from the measured throughput
+    connection_limit: watch::Sender<usize>,       // Maximum number of parts downloading at once, lowered by the tuning
+    connection_tuner: Option<JoinHandle<()>>,     // Task tuning `connection_limit` while the download runs
+    pub download_status: watch::Sender<DownloadStatus>,  // Current download status, the part tasks stop when it leaves `Downloading`
+    pub connection_manager: Option<Arc<ConnectionManager>>,  // Shared manager limiting the connections per host
+    pub client_options: ClientOptions,            // Options used to build the client
+    pub client: Option<Arc<dyn Transport>>,       // Client of the URL's protocol shared by all requests, built lazily from `client_options`
+    pub protocols: Arc<ProtocolRegistry>,         // Protocols the client is picked from, by the scheme of the URL
+    pub transport: Option<Arc<dyn Transport>>,    // Transport replacing the client of the protocol, e.g. a mock in tests
+    pub file_writer: Option<Arc<dyn FileWriter>>, // Writes the file once it's complete, `StdFileWriter` if not set
+    pub audit_log: Option<Arc<AuditLog>>,         // Optional chunk-level audit trail of the download
+    har_log: Option<(Arc<HarLog>, Option<PathBuf>)>,  // Requests recorded for debugging, and the HAR file written after each run
+    speed_limit: Option<Arc<RateLimiter>>,        // Limits the throughput of the parts, shared by all of them
+    credential_store: Option<Arc<CredentialStore>>,   // Store the credentials of the host and the proxy are looked up in
+    credentials_loaded: bool,                     // Whether the stored credentials were added to `client_options`
+    url_refresher: Option<Arc<dyn UrlRefresher>>, // Supplies a new URL when the range requests are rejected as expired
+    url_refresh: Arc<Mutex<()>>,                  // Held while the URL is refreshed, so the parts ask for a single new URL
+    negotiated_version: Option<Version>,          // HTTP version of the last response
+    probe_response: Option<TransportResponse>,    // Response of the request probing a download with another method than `GET`, its body is the file
+    parts: Vec<PartState>,                        // Byte range and received bytes of each part
+    stream_hash: Arc<std::sync::Mutex<StreamHasher>>, // SHA-256 of the parts' bytes hashed as they stream, replaced when received bytes are discarded
+    sha256: Option<String>,                       // SHA-256 of the completed file
+    memory: Arc<Mutex<Option<MemoryReservation>>>,    // Memory of the connection manager's budget held by the parts' bytes
+    discard_partial: bool,                        // Whether the bytes received so far are dropped when the download is cancelled
+    parts_in_flight: bool,                        // Whether the part tasks hold the parts' buffers
+    paused_offline: bool,                         // Whether the download was paused because the network is down
+    paused_quota: bool,                           // Whether the download was paused because the data quota is reached
+    started_at: Option<SystemTime>,               // When `download()` was last called
+    check_kept_parts: bool,                       // Whether the kept parts are checked against the file on the server before the next attempt
+    stale_after: Option<Duration>,                // Time paused after which the kept parts are checked again before they're resumed
+    stall_timeout: Option<Duration>,              // Time without any chunk after which a part requests its range again
+    resume_mismatch: Option<ResumeMismatch>,      // Why the kept parts were discarded when `download()` was last called
+    segment_hashing: bool,                        // Whether the parts' segments are hashed as they stream, to repair corrupt ones
+    buffering: BufferingStrategy,                 // Where the parts keep their bytes until the file is complete
+    staging_dir: Option<PathBuf>,                 // Directory the file is written to before it's moved to `out_dir`
+    move_progress: Option<Arc<MoveProgress>>,     // Progress of the file moved from the staging directory, while it's moved
+    file_permissions: FilePermissions,            // Mode of the completed file on Unix
+    provenance: bool,                             // Whether the URL of the completed file is recorded in its metadata
+    type_check: bool,                             // Whether the download waits for a confirmation when its `Content-Type` doesn't match the extension
+    type_confirmed: bool,                         // Whether the user confirmed the type of the file, see `confirm_type()`
+}
+
+impl RustleDownloaderInner {
+    /// Returns whether the parts request the whole file without a range, starting over when
+    /// they're resumed: requests with another method than GET, and single part downloads from
+    /// servers that don't accept ranges or don't send the length of the file.
+    fn whole_file(&self) -> bool {
+        self.client_options.method != Method::GET
+            || (self.parts.len() <= 1 && self.get_headers_info.as_ref()
+                .is_none_or(|info| info.support_partial != SupportPartialRequest::Yes || info.content_length.is_none()))
+    }
+
+    /// Returns the buffers on disk the parts write their bytes to, `None` if they keep them in memory:
+    /// with the `Memory` strategy, and when the whole file is requested.
+    fn part_buffers(&self) -> Option<PartBuffers> {
+        if self.whole_file() {
+            return None;
+        }
+        let file_name = self.file_name.as_ref().or(self.get_headers_info.as_ref()?.file_name.as_ref())?;
+        let dir = self.staging_dir.as_ref().or(self.out_dir.as_ref())?;
+        PartBuffers::new(self.buffering, dir.join(file_name))
+    }
+
+    /// Removes the buffers on disk of the parts, before they're replaced.
+    fn remove_part_buffers(&self) {
+        if let Some(buffers) = self.part_buffers() {
+            buffers.remove(self.parts.iter().filter(|state| state.flushed > 0).map(|state| state.start_byte));
+        }
+    }
+
+    /// Drops the parts and the bytes they received, giving their memory back to the budget.
+    fn drop_parts(&mut self) {
+        self.remove_part_buffers();
+        self.parts.clear();
+        self.stream_hash = Arc::default();
+        self.progress = Arc::new([]);
+        self.memory = Arc::default();
+    }
+
+    /// Sets the download up from a validated resume token, see `RustleDownloader::from_resume_token()`.
+    ///
+    /// Returns the number of corrupt segments of the kept bytes, downloaded again.
+    fn restore(&mut self, token: ResumeToken) -> Result<usize, String> {
+        self.url = Some(ValidUrl::new(&token.url).map_err(|e| e.to_string())?);
+        self.out_dir = Some(token.out_dir);
+        self.file_name = token.file_name.clone();
+        self.expected_sha256 = token.expected_sha256.map(|sha256| sha256.to_ascii_lowercase());
+        if let Some(method) = token.method.as_deref() {
+            self.client_options.method = Method::from_str(method).map_err(|e| e.to_string())?;
+            self.client_options.body = Some(Bytes::from(token.body)).filter(|body| !body.is_empty());
+        }
+        self.client_options.referer = token.referer;
+        self.get_headers_info = Some(ResponseHeaderInfo {
+            support_partial: if token.accepts_ranges { SupportPartialRequest::Yes } else { SupportPartialRequest::No },
+            content_length: token.content_length,
+            content_type: token.content_type,
+            file_name: token.file_name,
+            etag: token.etag,
+            last_modified: token.last_modified,
+            content_encoding: None,
+        });
+
+        if token.parts.is_empty() {
+            return Ok(0);
+        }
+        self.parts = token.parts.into_iter().map(|part| PartState {
+            start_byte: part.start_byte,
+            end_byte: part.end_byte,
+            flushed: 0,
+            buffer: BytesMut::from(&part.data[..]),
+            digests: part.digests,
+            done: part.done,
+        }).collect();
+        self.reset_progress();
+
+        // The kept bytes may have been corrupted while stored, only the corrupt segments are downloaded again
+        self.segment_hashing = self.parts.iter().any(|state| !state.digests.is_empty());
+        Ok(self.repair_segments())
+    }
+
+    /// Checks the received segments of the parts against their digests, each corrupt segment becomes
+    /// a part of its own, downloaded again, and the bytes around it are kept.
+    ///
+    /// Returns the number of corrupt segments.
+    fn repair_segments(&mut self) -> usize {
+        let mut corrupt = 0;
+        let mut parts = Vec::with_capacity(self.parts.len());
+        for mut state in self.parts.drain(..) {
+            loop {
+                let Some(segment) = first_corrupt_segment(&state.buffer, &state.digests) else {
+                    parts.push(state);
+                    break;
+                };
+                let offset = segment * SEGMENT_SIZE;
+                // Some received bytes are missing, the part continues from the last segment it still has
+                if offset >= state.buffer.len() {
+                    state.digests.truncate(state.buffer.len() / SEGMENT_SIZE);
+                    state.done = false;
+                    parts.push(state);
+                    break;
+                }
+                corrupt += 1;
+
+                if segment > 0 {
+                    parts.push(PartState {
+                        start_byte: state.start_byte,
+                        end_byte: state.start_byte + offset as u64 - 1,
+                        flushed: 0,
+                        buffer: state.buffer.split_to(offset),
+                        digests: state.digests.drain(..segment).collect(),
+                        done: true,
+                    });
+                }
+                let segment_end = state.end_byte.min(state.start_byte + (offset + SEGMENT_SIZE) as u64 - 1);
+                parts.push(PartState { start_byte: state.start_byte + offset as u64, end_byte: segment_end, flushed: 0, buffer: BytesMut::new(), digests: Vec::new(), done: false });
+                if segment_end == state.end_byte {
+                    break;
+                }
+
+                // The bytes after the corrupt segment are checked the same way
+                state.start_byte = segment_end + 1;
+                state.buffer = state.buffer.split_off(SEGMENT_SIZE.min(state.buffer.len()));
+                state.digests.remove(0);
+            }
+        }
+        self.parts = parts;
+
+        // The repaired bytes may have been hashed already
+        if corrupt > 0 {
+            self.reset_progress();
+            self.stream_hash = Arc::default();
+        }
+        corrupt
+    }
+
+    /// Splits the bytes a part didn't receive yet into at most `connections` ranges of at least
+    /// `MIN_TAIL_SPLIT` bytes, the part keeps the first one and the others become new parts.
+    ///
+    /// Returns the number of ranges, `1` if the part wasn't split.
+    fn split_part(&mut self, part: usize, connections: u64) -> usize {
+        let state = &mut self.parts[part];
+        let received = state.start_byte + state.received();
+        let remaining = (state.end_byte + 1).saturating_sub(received);
+        let ranges = plan_ranges(remaining, connections.min(remaining / MIN_TAIL_SPLIT));
+        if ranges.len() < 2 {
+            return 1;
+        }
+
+        state.end_byte = received + ranges[0].end;
+        // The parts are written in order, the new ones follow the split part
+        let new_parts: Vec<PartState> = ranges[1..].iter().map(|range| PartState {
+            start_byte: received + range.start,
+            end_byte: received + range.end,
+            flushed: 0,
+            buffer: BytesMut::new(),
+            digests: Vec::new(),
+            done: false,
+        }).collect();
+        self.parts.splice(part + 1..part + 1, new_parts);
+        self.reset_progress();
+        ranges.len()
+    }
+
+    /// Creates the progress counters of the parts, starting from the bytes they already received.
+    fn reset_progress(&mut self) {
+        self.progress = PartCounters::new_parts(self.parts.len());
+        for (counters, state) in self.progress.iter().zip(self.parts.iter()) {
+            counters.downloaded_bytes.store(state.received(), Ordering::Relaxed);
+        }
+    }
+
+    /// Returns the transport sending the requests, the one set by the user or the client of the URL's
+    /// protocol, built from `client_options` on first use.
+    fn transport(&mut self) -> Result<Arc<dyn Transport>, String> {
+        let transport = match self.transport.as_ref() {
+            Some(transport) => transport.clone(),
+            None => {
+                if self.client.is_none() {
+                    let url = self.url.as_ref().ok_or("No valid url was supplied")?;
+                    self.client = Some(self.protocols.protocol(&url.0)?.transport(&self.client_options)?);
+                }
+                self.client.clone().unwrap()
+            },
+        };
+        Ok(match self.har_log.as_ref() {
+            Some((har_log, _)) => Arc::new(HarTransport::new(transport, har_log.clone(), &self.client_options)),
+            None => transport,
+        })
+    }
+}
+
+/// PartState represents the byte range assigned to a part and the bytes received for it so far.
+#[derive(Debug, Default)]
+struct PartState {
+    start_byte: u64,        // First byte of the range
+    end_byte: u64,          // Last byte of the range (inclusive)
+    flushed: u64,           // Bytes received so far and written to the part's buffer on disk, see `BufferingStrategy`
+    buffer: BytesMut,       // Bytes received so far after the flushed ones, a resumed request starts at `start_byte + received()`
+    digests: Vec<String>,   // SHA-256 of the received segments of the buffer, when the segments are hashed
+    done: bool,             // Whether the part is finished
+}
+
+impl PartState {
+    /// Returns the number of bytes received for the part, on disk and in memory.
+    fn received(&self) -> u64 {
+        self.flushed + self.buffer.len() as u64
+    }
+}
+
+/// PartCounters represents the progress of a part, stored in atomics so the part tasks
+/// can update it on every chunk without locking the downloader's state.
+#[derive(Debug)]
+struct PartCounters {
+    epoch: Instant,                 // Reference point of the timestamps below, shared by all parts
+    downloaded_bytes: AtomicU64,    // Number of bytes downloaded for this part
+    request_bytes: AtomicU64,       // Number of bytes received by the current request
+    request_start_us: AtomicU64,    // Microseconds from `epoch` to the start of the current request
+    last_tick_us: AtomicU64,        // Microseconds from `epoch` to the last received chunk
+    smoothed_speed: AtomicU64,      // Exponentially smoothed speed as of the last chunk, stored as `f64` bits
+    active: AtomicBool,             // Whether a request of this part is currently running
+    split: AtomicBool,              // Whether the part stops so its remaining bytes are split with the idle connections
+    network_wait_us: AtomicU64,     // Microseconds the sink waited for chunks from the network
+    backpressure_us: AtomicU64,     // Microseconds the network side waited for the sink
+}
+
+impl PartCounters {
+    /// Creates the counters of `num_parts` parts sharing the same epoch.
+    fn new_parts(num_parts: usize) -> Arc<[PartCounters]> {
+        let epoch = Instant::now();
+        (0..num_parts).map(|_| PartCounters {
+            epoch,
+            downloaded_bytes: AtomicU64::new(0),
+            request_bytes: AtomicU64::new(0),
+            request_start_us: AtomicU64::new(0),
+            last_tick_us: AtomicU64::new(0),
+            smoothed_speed: AtomicU64::new(0),
+            active: AtomicBool::new(false),
+            split: AtomicBool::new(false),
+            network_wait_us: AtomicU64::new(0),
+            backpressure_us: AtomicU64::new(0),
+        }).collect()
+    }
+
+    /// Microseconds elapsed since `epoch`.
+    fn now_us(&self) -> u64 {
+        self.epoch.elapsed().as_micros() as u64
+    }
+
+    /// Marks the start of a new request for this part, its speed is measured from here.
+    fn start_request(&self) {
+        let now = self.now_us();
+        self.request_bytes.store(0, Ordering::Relaxed);
+        self.request_start_us.store(now, Ordering::Relaxed);
+        self.last_tick_us.store(now, Ordering::Relaxed);
+        self.smoothed_speed.store(0.0f64.to_bits(), Ordering::Relaxed);
+        self.active.store(true, Ordering::Relaxed);
+    }
+
+    /// Marks the end of the current request, a part without a running request has no speed.
+    fn finish_request(&self) {
+        self.active.store(false, Ordering::Relaxed);
+    }
+
+    /// Adds a received chunk of `len` bytes.
+    /// Only the part's own task calls it, so the smoothed speed is updated without a compare-and-swap.
+    fn add_chunk(&self, len: u64) {
+        let now = self.now_us();
+        let elapsed_us = now.saturating_sub(self.last_tick_us.load(Ordering::Relaxed)).max(1) as f64;
+
+        // The rate since the previous chunk, weighted by how long it lasted
+        let rate = len as f64 * 1_000_000.0 / elapsed_us;
+        let weight = 1.0 - (-elapsed_us / SPEED_SMOOTHING_US).exp();
+        let smoothed = f64::from_bits(self.smoothed_speed.load(Ordering::Relaxed));
+        self.smoothed_speed.store((smoothed + weight * (rate - smoothed)).to_bits(), Ordering::Relaxed);
+
+        self.downloaded_bytes.fetch_add(len, Ordering::Relaxed);
+        self.request_bytes.fetch_add(len, Ordering::Relaxed);
+        self.last_tick_us.store(now, Ordering::Relaxed);
+    }
+
+    /// Adds the time the sink waited for a chunk from the network.
+    fn add_network_wait(&self, wait: Duration) {
+        self.network_wait_us.fetch_add(wait.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// Adds the time the network side waited for the sink to take a chunk.
+    fn add_backpressure(&self, wait: Duration) {
+        self.backpressure_us.fetch_add(wait.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// Download speed of the current request in bytes per second.
+    fn speed(&self) -> f64 {
+        if !self.active.load(Ordering::Relaxed) {
+            return 0.0;
+        }
+        let elapsed_us = self.last_tick_us.load(Ordering::Relaxed).saturating_sub(self.request_start_us.load(Ordering::Relaxed));
+        if elapsed_us == 0 {
+            return 0.0;
+        }
+        self.request_bytes.load(Ordering::Relaxed) as f64 * 1_000_000.0 / elapsed_us as f64
+    }
+
+    /// Exponentially smoothed download speed in bytes per second.
+    /// It keeps decaying while no chunk is received, so a stalled part drops towards zero.
+    fn smoothed_speed(&self) -> f64 {
+        if !self.active.load(Ordering::Relaxed) {
+            return 0.0;
+        }
+        let last_tick_us = self.last_tick_us.load(Ordering::Relaxed);
+        let idle_us = self.now_us().saturating_sub(last_tick_us) as f64;
+        let measured_us = last_tick_us.saturating_sub(self.request_start_us.load(Ordering::Relaxed)) as f64;
+
+        // The average starts from zero, scale it up by the weight the measured period has so far
+        let warmup = 1.0 - (-measured_us / SPEED_SMOOTHING_US).exp();
+        if warmup <= 0.0 {
+            return 0.0;
+        }
+        f64::from_bits(self.smoothed_speed.load(Ordering::Relaxed)) / warmup * (-idle_us / SPEED_SMOOTHING_US).exp()
+    }
+
+    /// Snapshot of the counters as a `PartDownloadInfo`.
+    fn info(&self) -> PartDownloadInfo {
+        PartDownloadInfo { 
+            downloaded_bytes: self.downloaded_bytes.load(Ordering::Relaxed) as usize, 
+            download_speed: self.speed(),
+            smoothed_speed: self.smoothed_speed(),
+            network_wait: Duration::from_micros(self.network_wait_us.load(Ordering::Relaxed)),
+            backpressure: Duration::from_micros(self.backpressure_us.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+/// DownloadStatus represents the status of a download.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DownloadStatus {
+    #[default]
+    Idle,       // Download is idle
+    Downloading,    // Download is in progress
+    Paused,     // Download is paused
+    Cancelled,  // Download was cancelled by the user
+    Retrying,   // Download failed and waits to be attempted again
+    Done,       // Download is completed
+    Error,      // Download encountered an error
+    Warning,    // Download waits for the user to confirm a file type that doesn't match its extension, see `confirm_type()`
+}
+
+/// RustleDownloader represents a downloader tool for downloading files.
+#[derive(Debug, Clone, Default)]
+pub struct RustleDownloader {
+    inner: Arc<Mutex<RustleDownloaderInner>>,
+    timeline: Arc<Timeline>,    // Events of the download, readable without the lock
+}
+
+
+impl RustleDownloader {
+    /// Initializes the RustleDownloader by performing an initial GET request.
+    /// The response headers should provide information about the support for 
+    /// partial requests and the download file information.
+    ///
+    /// # Returns
+    /// Returns a `Result` indicating whether the initialization was successful (`Ok(true)`)
+    /// or an error message (`Err(String)`).
+    pub async fn init(self: &mut RustleDownloader) -> Result<bool, String> {
+        /*
+            Probe the download with an initial request
+
+            -> response headers should give a hint about the support of 
+            partial requests and the information of the download file.
+
+            -> if a strategy fails (HEAD not allowed, timeout, ..) the next
+            one is tried, the one that worked is remembered for the host.
+
+            -> if the server doesn't say whether it accepts ranges, the
+            first byte is requested to find out.
+        */
+
+        self.load_credentials().await;
+        let (url, connection_manager, transport, method, mut status) = {
+            let mut inner = self.inner.lock().await;
+
+            assert!(inner.url.is_some(), "No valid url was supplied");
+            assert!(inner.out_dir.is_some(), "No valid out_dir was supplied");
+
+            inner.error_kind = None;
+            (inner.url.clone().unwrap(), inner.connection_manager.clone(), inner.transport()?,
+             inner.client_options.method.clone(), inner.download_status.subscribe())
+        };
+
+        // The lock isn't held while waiting for a connection or probing, the download can be paused,
+        // cancelled or polled meanwhile, a cancel stops the wait for a connection
+        let cancelled = async {
+            while status.changed().await.is_ok() {
+                if *status.borrow() == DownloadStatus::Cancelled {
+                    return;
+                }
+            }
+            std::future::pending().await
+        };
+        let _permit = tokio::select! {
+            permit = acquire_connection(connection_manager.as_ref(), &url, self.connection_key()) => permit?,
+            _ = cancelled => return Err(String::from("The download was cancelled")),
+        };
+
+        let strategies = init_strategies(connection_manager.as_ref(), &url);
+
+        // The request itself is the probe of other methods, its body is kept for the download
+        if method != Method::GET {
+            let response = match transport.get_range(&url.0, None, None, None).await {
+                Ok(response) => response,
+                Err(e) => {
+                    self.inner.lock().await.error_kind = Some(e.kind.into());
+                    return Err(format!("Couldn't probe the download, {}", e));
+                },
+            };
+            if !response.status.is_success() {
+                self.inner.lock().await.error_kind = Some(ErrorKind::HttpStatus(response.status.as_u16()));
+                return Err(format!("Couldn't probe the download, got status code : {}", response.status.as_str()));
+            }
+            let mut get_info = parse_response_headers(response.status, &response.url, &response.headers)?;
+            get_info.support_partial = SupportPartialRequest::No;
+            let mut inner = self.inner.lock().await;
+            inner.negotiated_version = Some(response.version);
+            inner.get_headers_info = Some(get_info);
+            inner.probe_response = Some(response);
+            return Ok(true);
+        }
+
+        let mut errors = Vec::new();
+        let mut error_kind = None;
+
+        for strategy in strategies {
+            let response = match probe(transport.as_ref(), &url, strategy).await {
+                Ok(response) => response,
+                Err((kind, e)) => {
+                    // The first failure tells the most, e.g. a host that isn't found fails all the strategies
+                    error_kind.get_or_insert(kind);
+                    errors.push(format!("{:?} : {}", strategy, e));
+                    continue;
+                },
+            };
+
+            let mut get_info = parse_response_headers(response.status, &response.url, &response.headers)?;
+            let version = response.version;
+
+            // Many servers accept ranges without sending `Accept-Ranges`, a range request tells
+            if get_info.support_partial == SupportPartialRequest::Unknown {
+                let range_response = match strategy {
+                    InitStrategy::RangedGet => Some(response),
+                    _ => {
+                        drop(response);
+                        probe(transport.as_ref(), &url, InitStrategy::RangedGet).await.ok()
+                    },
+                };
+                let total = range_response.as_ref().and_then(first_byte_total);
+                if let Some(total) = total.filter(|total| get_info.content_length.is_none_or(|length| length == *total)) {
+                    get_info.support_partial = SupportPartialRequest::Yes;
+                    get_info.content_length.get_or_insert(total);
+                }
+            }
+            {
+                let mut inner = self.inner.lock().await;
+                inner.negotiated_version = Some(version);
+                inner.get_headers_info = Some(get_info);
+                inner.error_kind = None;
+            }
+
+            if let (Some(manager), Some(host)) = (connection_manager.as_ref(), url.host()) {
+                manager.remember_init_strategy(host, strategy);
+            }
+
+            return Ok(true);
+        }
+
+        self.inner.lock().await.error_kind = error_kind;
+        Err(format!("Couldn't probe the download, {}", errors.join(" | ")))
+    }
+
+    /// Pauses the RustleDownloader, changing the download status to `Paused`.
+    /// In-flight range requests are aborted and the client is dropped so no connection is
+    /// held while paused, the bytes received by each part are kept to resume from.
+    pub async fn pause(self: &RustleDownloader) {
+        let mut inner = self.inner.lock().await;
+        if inner.download_status.send_replace(DownloadStatus::Paused) != DownloadStatus::Paused {
+            self.timeline.record(TimelineEvent::Paused { offline: false });
+        }
+        // Dropping the client closes its idle pooled connections too
+        inner.client = None;
+        inner.probe_response = None;
+    }
+
+    /// Resumes the RustleDownloader, changing the download status to `Downloading`.
+    /// The unfinished parts are requested again, starting from the bytes they already received.
+    /// A download waiting to be retried is retried right away.
+    pub async fn resume(self: &RustleDownloader) {
+        let inner = self.inner.lock().await;
+        if matches!(inner.download_status.send_replace(DownloadStatus::Downloading), DownloadStatus::Paused | DownloadStatus::Retrying) {
+            self.timeline.record(TimelineEvent::Resumed);
+        }
+    }
+
+    /// Cancels the RustleDownloader, changing the download status to `Cancelled`.
+    /// In-flight range requests are aborted and the running `download()` returns `Ok(false)`.
+    /// If `keep_partial` is `true` the bytes received so far are kept and a later `download()`
+    /// continues from them, otherwise they are discarded and the download starts over.
+    pub async fn cancel(self: &RustleDownloader, keep_partial: bool) {
+        let mut inner = self.inner.lock().await;
+        let previous_status = inner.download_status.send_replace(DownloadStatus::Cancelled);
+        self.timeline.record(TimelineEvent::Cancelled { keep_partial });
+        inner.client = None;
+        inner.probe_response = None;
+        inner.discard_partial = !keep_partial;
+
+        // A running download drops the parts itself once its tasks have stopped
+        let running = matches!(previous_status, DownloadStatus::Downloading | DownloadStatus::Paused | DownloadStatus::Retrying);
+        if !keep_partial && !running {
+            inner.drop_parts();
+        }
+    }
+
+    /// Retrieves the current download status of the RustleDownloader.
+    pub async fn get_status(self: &RustleDownloader) -> DownloadStatus {
+        *self.inner.lock().await.download_status.borrow()
+    }
+
+    /// Retrieves the host of the URL without waiting for the engine's lock, meant for idle engines
+    /// (e.g. when handing them to the queue manager). Returns `None` if there's no URL or the engine is busy.
+    pub fn try_get_host(self: &RustleDownloader) -> Option<String> {
+        let inner = self.inner.try_lock().ok()?;
+        inner.url.as_ref().and_then(|url| url.host()).map(|host| host.to_lowercase())
+    }
+
+    /// Retrieves the events of the download so far, oldest first (at most `TIMELINE_LEN`).
+    /// It doesn't wait for the engine's lock, the timeline can be read at any time.
+    pub fn get_timeline(self: &RustleDownloader) -> Vec<TimelineEntry> {
+        self.timeline.entries()
+    }
+
+    /// Records an event happening to the download outside of the engine, e.g. `TimelineEvent::Queued`
+    /// by the queue manager.
+    pub fn record_event(self: &RustleDownloader, event: TimelineEvent) {
+        self.timeline.record(event);
+    }
+
+    /// Retrieves why the bytes kept by the download were discarded when `download()` was last called,
+    /// if the file changed on the server since they were received. The download then started over.
+    pub async fn get_resume_mismatch(self: &RustleDownloader) -> Option<ResumeMismatch> {
+        self.inner.lock().await.resume_mismatch.clone()
+    }
+
+    /// Retrieves the mismatch between the `Content-Type` of the file and its extension, e.g. an `.iso`
+    /// served as `text/html`. Returns `None` once the type is confirmed or if the type isn't checked.
+    pub async fn get_type_mismatch(self: &RustleDownloader) -> Option<TypeMismatch> {
+        let inner = self.inner.lock().await;
+        if !inner.type_check || inner.type_confirmed {
+            return None;
+        }
+        let headers_info = inner.get_headers_info.as_ref()?;
+        let file_name = inner.file_name.as_ref().or(headers_info.file_name.as_ref())?;
+        type_mismatch(file_name, headers_info.content_type.as_deref())
+    }
+
+    /// Confirms the type of a file that doesn't match its extension, the next call to `download()`
+    /// transfers it. A `Warning` download becomes `Idle`.
+    pub async fn confirm_type(self: &RustleDownloader) {
+        let mut inner = self.inner.lock().await;
+        inner.type_confirmed = true;
+        inner.download_status.send_if_modified(|status| {
+            let warning = *status == DownloadStatus::Warning;
+            if warning {
+                *status = DownloadStatus::Idle;
+            }
+            warning
+        });
+    }
+
+    /// Retrieves the number of bytes moved from the staging directory to the output directory and the
+    /// size of the file, while the complete file is moved, see `set_staging_dir()`.
+    pub async fn get_move_progress(self: &RustleDownloader) -> Option<(u64, u64)> {
+        self.inner.lock().await.move_progress.as_ref()
+            .map(|progress| (progress.moved.load(Ordering::Relaxed), progress.total.load(Ordering::Relaxed)))
+    }
+
+    /// Retrieves the kind of the error the last attempt of the download failed with, e.g. to show
+    /// "Host not found" rather than the error message. `None` if it didn't fail.
+    pub async fn get_error_kind(self: &RustleDownloader) -> Option<ErrorKind> {
+        self.inner.lock().await.error_kind
+    }
+
+    /// Retrieves the retry waiting to be attempted, if the download is `Retrying`.
+    pub async fn get_retry_info(self: &RustleDownloader) -> Option<RetryInfo> {
+        self.inner.lock().await.retry_info.clone()
+    }
+
+    /// Takes a resume token of the download, holding everything needed to continue it later with
+    /// `from_resume_token()`, including the bytes received so far.
+    ///
+    /// Returns an error if the engine isn't initialized or the download is running, the bytes
+    /// held by the running requests are only kept once it's paused, cancelled with `keep_partial`, or failed.
+    pub async fn resume_token(self: &RustleDownloader) -> Result<ResumeToken, String> {
+        let inner = self.inner.lock().await;
+        if *inner.download_status.borrow() == DownloadStatus::Downloading {
+            return Err(String::from("Pause the download before taking its resume token"));
+        }
+        let (Some(url), Some(out_dir), Some(headers_info)) = (inner.url.as_ref(), inner.out_dir.as_ref(), inner.get_headers_info.as_ref()) else {
+            return Err(String::from("The download isn't initialized"));
+        };
+
+        // Paused parts may still be stopping, their bytes would be missing from the token
+        if inner.parts_in_flight {
+            return Err(String::from("The download is still stopping, try again"));
+        }
+        let whole_file = inner.whole_file();
+
+        // The bytes the parts wrote to disk are read back, the token holds all of them
+        let buffers = inner.part_buffers();
+        let data = |state: &PartState| -> Result<Vec<u8>, String> {
+            let mut data = match buffers.as_ref().filter(|_| state.flushed > 0) {
+                Some(buffers) => buffers.read(state.start_byte, state.flushed)
+                    .map_err(|e| format!("Couldn't read the bytes of the part at {}, error : {}", state.start_byte, e))?,
+                None => Vec::new(),
+            };
+            data.extend_from_slice(&state.buffer);
+            Ok(data)
+        };
+
+        Ok(ResumeToken {
+            url: url.as_str().to_string(),
+            out_dir: out_dir.clone(),
+            file_name: inner.file_name.clone().or_else(|| headers_info.file_name.clone()),
+            expected_sha256: inner.expected_sha256.clone(),
+            content_length: headers_info.content_length,
+            content_type: headers_info.content_type.clone(),
+            accepts_ranges: headers_info.support_partial == SupportPartialRequest::Yes,
+            etag: headers_info.etag.clone(),
+            last_modified: headers_info.last_modified.clone(),
+            method: Some(inner.client_options.method.to_string()).filter(|method| method != "GET"),
+            body: inner.client_options.body.as_ref().map(|body| body.to_vec()).unwrap_or_default(),
+            referer: inner.client_options.referer.clone(),
+            // Requests with another method than GET start over, their bytes aren't kept
+            parts: inner.parts.iter().map(|state| Ok(ResumePart {
+                start_byte: state.start_byte,
+                end_byte: state.end_byte,
+                done: state.done && !whole_file,
+                data: if whole_file { Vec::new() } else { data(state)? },
+                digests: if whole_file { Vec::new() } else { state.digests.clone() },
+            })).collect::<Result<_, String>>()?,
+        })
+    }
+
+    /// Retrieves the file information obtained from the response headers.
+    /// Returns `Some(ResponseHeaderInfo)` if the information is available, otherwise `None`.
+    pub async fn get_file_info(self: &RustleDownloader) -> Option<ResponseHeaderInfo>{
+        self.inner.lock().await.get_headers_info.clone()
+    }
+
+    /// Retrieves the path the file is written to, i.e. the output directory joined with the
+    /// file name set by the user or detected by `init()`. Returns `None` before initialization.
+    pub async fn get_file_path(self: &RustleDownloader) -> Option<PathBuf> {
+        let inner = self.inner.lock().await;
+        let file_name = inner.file_name.clone()
+            .or_else(|| inner.get_headers_info.as_ref().and_then(|info| info.file_name.clone()))?;
+        inner.out_dir.as_ref().map(|out_dir| out_dir.join(file_name))
+    }
+
+    /// Retrieves the directory the file is written to, if it was set.
+    pub async fn get_out_dir(self: &RustleDownloader) -> Option<PathBuf> {
+        self.inner.lock().await.out_dir.clone()
+    }
+
+    /// Retrieves the URL of the download, if it was set.
+    pub async fn get_url(self: &RustleDownloader) -> Option<String> {
+        self.inner.lock().await.url.as_ref().map(|url| url.as_str().to_string())
+    }
+
+    /// Retrieves the number of connections set with `set_connections()`, `None` if it's picked
+    /// from the size of the file.
+    pub async fn get_connections(self: &RustleDownloader) -> Option<u8> {
+        self.inner.lock().await.connections_override
+    }
+
+    /// Retrieves when the download was last started with `download()`.
+    pub async fn get_started_at(self: &RustleDownloader) -> Option<SystemTime> {
+        self.inner.lock().await.started_at
+    }
+
+    /// Retrieves the SHA-256 the downloaded file is verified against, lowercase hex.
+    pub async fn get_expected_sha256(self: &RustleDownloader) -> Option<String> {
+        self.inner.lock().await.expected_sha256.clone()
+    }
+
+    /// Retrieves the SHA-256 of the completed file, lowercase hex. It's computed while the parts
+    /// stream, so the file doesn't need to be read again. Returns `None` before the download is
+    /// complete, and for compressed responses decoded without an expected SHA-256.
+    pub async fn get_sha256(self: &RustleDownloader) -> Option<String> {
+        self.inner.lock().await.sha256.clone()
+    }
+
+    /// Retrieves a vector of `PartDownloadInfo` representing the progress of each download part.
+    /// This vector contains information such as the start and end range of each part and the number
+    /// of bytes downloaded for each part.
+    pub async fn get_progress_vec(self: &RustleDownloader) -> Vec<PartDownloadInfo> {
+        let progress = self.inner.lock().await.progress.clone();
+        progress.iter().map(PartCounters::info).collect()
+    }
+
+    /// Retrieves the aggregate download speed of all the parts, both averaged and smoothed.
+    pub async fn get_download_speed(self: &RustleDownloader) -> DownloadSpeed {
+        let progress = self.inner.lock().await.progress.clone();
+        // Folded from `0.0` as an empty float sum is `-0.0`
+        DownloadSpeed {
+            average: progress.iter().map(PartCounters::speed).fold(0.0, |total, speed| total + speed),
+            smoothed: progress.iter().map(PartCounters::smoothed_speed).fold(0.0, |total, speed| total + speed),
+        }
+    }
+
+    /// Retrieves the aggregate throughput of the download over the last `SPEED_HISTORY_LEN`
+    /// sample intervals, in bytes per second and oldest first. Paused intervals are sampled as `0.0`.
+    pub async fn get_speed_history(self: &RustleDownloader) -> Vec<f64> {
+        self.inner.lock().await.speed_history.iter().copied().collect()
+    }
+
+    /// Estimates the remaining download time from the remaining bytes and the smoothed speed.
+    /// Returns `None` if the download isn't running, its length is unknown, or no speed
+    /// was measured yet.
+    pub async fn get_eta(self: &RustleDownloader) -> Option<Duration> {
+        let (progress, content_length) = {
+            let inner = self.inner.lock().await;
+            if *inner.download_status.borrow() != DownloadStatus::Downloading {
+                return None;
+            }
+            (inner.progress.clone(), inner.get_headers_info.as_ref()?.content_length?)
+        };
+        estimate_eta(content_length, &progress)
+    }
+
+    /// Takes a snapshot of the download, gathering its status and progress in a single
+    /// serializable value, see `DownloadSnapshot`.
+    pub async fn snapshot(self: &RustleDownloader) -> DownloadSnapshot {
+        let parts = self.get_progress_vec().await;
+        DownloadSnapshot {
+            url: self.get_url().await,
+            file_path: self.get_file_path().await,
+            status: self.get_status().await,
+            file_info: self.get_file_info().await,
+            downloaded_bytes: parts.iter().map(|part| part.downloaded_bytes as u64).sum(),
+            parts,
+            speed: self.get_download_speed().await,
+            eta: self.get_eta().await,
+            error_kind: self.get_error_kind().await,
+        }
+    }
+
+    /// Waits for the next progress tick and returns the progress of each download part.
+    /// Snapshots are handed out at most once per progress interval, however fast the
+    /// chunks are received, so pollers don't need to throttle themselves.
+    pub async fn next_progress_vec(self: &RustleDownloader) -> Vec<PartDownloadInfo> {
+        let next_tick = {
+            let inner = self.inner.lock().await;
+            inner.last_progress.map(|last| last + inner.progress_interval)
+        };
+        if let Some(next_tick) = next_tick {
+            tokio::time::sleep_until(next_tick.into()).await;
+        }
+
+        let progress = {
+            let mut inner = self.inner.lock().await;
+            inner.last_progress = Some(Instant::now());
+            inner.progress.clone()
+        };
+        progress.iter().map(PartCounters::info).collect()
+    }
+
+
+    /* Setters */
+    /// Sets the URL for the RustleDownloader.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - A string slice containing the URL to be set.
+    ///
+    /// Returns an error if the provided URL is invalid.
+    pub async fn set_url(self: &mut RustleDownloader, url: &str) -> Result<&RustleDownloader, String> {
+        let url = ValidUrl::new(url).map_err(|e| e.to_string())?;
+        let mut inner = self.inner.lock().await;
+        inner.url = Some(url);
+        inner.credentials_loaded = false;
+        drop(inner);
+        Ok(self)
+    }
+
+    /// Sets the output directory for the RustleDownloader.
+    ///
+    /// # Arguments
+    ///
+    /// * `out_dir` - A string slice containing the path of the output directory.
+    ///
+    /// Returns an error if the provided directory path is invalid.
+    pub async fn set_out_dir(self: &mut RustleDownloader, out_dir: &str) -> Result<&RustleDownloader, String> {
+        let out_dir = PathBuf::from_str(out_dir).map_err(|e| e.to_string())?;
+        self.inner.lock().await.out_dir = Some(out_dir);
+        Ok(self)
+    }
+
+    /// Sets an explicit number of connections for the RustleDownloader, overriding
+    /// the number that would otherwise be picked from the content length.
+    ///
+    /// # Arguments
+    ///
+    /// * `connections` - The number of parallel connections to use for downloading.
+    ///
+    /// Returns an error if the number of connections is zero.
+    pub async fn set_connections(self: &mut RustleDownloader, connections: u8) -> Result<&RustleDownloader, String> {
+        if connections == 0 {
+            return Err(String::from("Number of connections must be greater than zero"));
+        }
+        self.inner.lock().await.connections_override = Some(connections);
+        Ok(self)
+    }
+
+    /// Sets the connection manager shared between downloads, limiting the number of
+    /// concurrent connections opened to the same host.
+    ///
+    /// # Arguments
+    ///
+    /// * `connection_manager` - The shared `ConnectionManager`.
+    pub async fn set_connection_manager(self: &mut RustleDownloader, connection_manager: Arc<ConnectionManager>) -> &RustleDownloader {
+        self.inner.lock().await.connection_manager = Some(connection_manager);
+        self
+    }
+
+    /// Sets the TLS options of the RustleDownloader, e.g. extra root certificates,
+    /// accepting self-signed certificates or a client certificate for mTLS.
+    ///
+    /// # Arguments
+    ///
+    /// * `tls` - The TLS options to use for all requests of this download.
+    pub async fn set_tls_options(self: &mut RustleDownloader, tls: TlsOptions) -> &RustleDownloader {
+        let mut inner = self.inner.lock().await;
+        inner.client_options.tls = tls;
+        inner.client = None;
+        drop(inner);
+        self
+    }
+
+    /// Sets the preferred IP address family of the RustleDownloader, e.g. to avoid
+    /// hosts with a broken IPv6 setup.
+    ///
+    /// # Arguments
+    ///
+    /// * `address_family` - The address family preference.
+    pub async fn set_address_family(self: &mut RustleDownloader, address_family: AddressFamily) -> &RustleDownloader {
+        let mut inner = self.inner.lock().await;
+        inner.client_options.address_family = address_family;
+        inner.client = None;
+        drop(inner);
+        self
+    }
+
+    /// Sets the servers the hostnames of this download are resolved with, e.g. a DNS-over-HTTPS
+    /// endpoint on networks tampering with DNS. The parts share the resolved addresses.
+    ///
+    /// # Arguments
+    ///
+    /// * `dns` - The DNS servers, the system resolver if none is set.
+    pub async fn set_dns(self: &mut RustleDownloader, dns: DnsOptions) -> &RustleDownloader {
+        let mut inner = self.inner.lock().await;
+        inner.client_options.dns = dns;
+        inner.client = None;
+        drop(inner);
+        self
+    }
+
+    /// Sets extra headers sent with every request of this download, e.g. a cookie or
+    /// an authorization token required by the server.
+    ///
+    /// # Arguments
+    ///
+    /// * `headers` - The headers to send, replacing the previously set ones.
+    pub async fn set_headers(self: &mut RustleDownloader, headers: HeaderMap) -> &RustleDownloader {
+        let mut inner = self.inner.lock().await;
+        inner.client_options.headers = headers;
+        inner.client = None;
+        inner.credentials_loaded = false;
+        drop(inner);
+        self
+    }
+
+    /// Sets the proxy all the requests of this download go through.
+    ///
+    /// # Arguments
+    ///
+    /// * `proxy` - The URL of the proxy, e.g. `http://proxy:3128` or `socks5://proxy:1080`, `None` to use the
+    ///   proxies of the environment, see `set_env_proxy()`.
+    pub async fn set_proxy(self: &mut RustleDownloader, proxy: Option<String>) -> &RustleDownloader {
+        let mut inner = self.inner.lock().await;
+        inner.client_options.proxy = proxy;
+        inner.client = None;
+        inner.credentials_loaded = false;
+        drop(inner);
+        self
+    }
+
+    /// Sets whether the requests go through the proxies of the `HTTP_PROXY`, `HTTPS_PROXY` and
+    /// `ALL_PROXY` environment variables, skipping the hosts of `NO_PROXY`, when no proxy is set.
+    ///
+    /// # Arguments
+    ///
+    /// * `env_proxy` - Whether the proxies of the environment are used, `true` by default.
+    pub async fn set_env_proxy(self: &mut RustleDownloader, env_proxy: bool) -> &RustleDownloader {
+        let mut inner = self.inner.lock().await;
+        inner.client_options.ignore_env_proxy = !env_proxy;
+        inner.client = None;
+        drop(inner);
+        self
+    }
+
+    /// Sets the `User-Agent` header of the requests of this download, e.g. for servers
+    /// rejecting unknown clients.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_agent` - The user agent, `None` for the default one.
+    pub async fn set_user_agent(self: &mut RustleDownloader, user_agent: Option<String>) -> &RustleDownloader {
+        let mut inner = self.inner.lock().await;
+        inner.client_options.user_agent = user_agent;
+        inner.client = None;
+        drop(inner);
+        self
+    }
+
+    /// Sets the `Referer` header of the requests of this download, e.g. the page the file was
+    /// found on, for hosts refusing the requests that don't come from their pages. It overrides
+    /// the `Referer` of `set_headers()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `referer` - The URL of the referring page, `None` to send no referer.
+    ///
+    /// Returns an error if the referer isn't a valid URL.
+    pub async fn set_referer(self: &mut RustleDownloader, referer: Option<&str>) -> Result<&RustleDownloader, String> {
+        if let Some(referer) = referer {
+            validate_referer(referer)?;
+        }
+        let mut inner = self.inner.lock().await;
+        inner.client_options.referer = referer.map(String::from);
+        inner.client = None;
+        drop(inner);
+        Ok(self)
+    }
+
+    /// Returns the `Referer` header of the requests of this download, if it's set.
+    pub async fn get_referer(self: &RustleDownloader) -> Option<String> {
+        self.inner.lock().await.client_options.referer.clone()
+    }
+
+    /// Limits the speed of this download, the parts share the limit. Downloads sharing a
+    /// connection manager are also limited by its speed limit.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes_per_second` - The maximum speed, `None` for no limit.
+    ///
+    /// Returns an error if the limit is zero.
+    pub async fn set_speed_limit(self: &mut RustleDownloader, bytes_per_second: Option<u64>) -> Result<&RustleDownloader, String> {
+        let speed_limit = bytes_per_second.map(RateLimiter::new).transpose()?.map(Arc::new);
+        self.inner.lock().await.speed_limit = speed_limit;
+        Ok(self)
+    }
+
+    /// Returns the speed limit of this download in bytes per second, if any.
+    pub async fn get_speed_limit(self: &RustleDownloader) -> Option<u64> {
+        self.inner.lock().await.speed_limit.as_ref().map(|limit| limit.bytes_per_second())
+    }
+
+    /// Sets the HTTP versions the requests may use, e.g. HTTP/1.1 only for servers with a buggy
+    /// HTTP/2 implementation, or HTTP/2 only so the parts share a single connection.
+    ///
+    /// # Arguments
+    ///
+    /// * `http_version` - The HTTP versions allowed.
+    pub async fn set_http_version(self: &mut RustleDownloader, http_version: HttpVersion) -> &RustleDownloader {
+        let mut inner = self.inner.lock().await;
+        inner.client_options.http_version = http_version;
+        inner.client = None;
+        drop(inner);
+        self
+    }
+
+    /// Returns the HTTP version negotiated with the server, as of the last response.
+    pub async fn get_http_version(self: &RustleDownloader) -> Option<Version> {
+        self.inner.lock().await.negotiated_version
+    }
+
+    /// Sets the store the credentials of the download are looked up in, by `init()` and `download()`.
+    /// The credentials of the URL's host are sent as basic auth, unless the URL or the headers
+    /// already carry some, and the ones of the proxy's host log into the proxy.
+    ///
+    /// # Arguments
+    ///
+    /// * `credential_store` - The store, e.g. the OS credential store, `None` to not look up credentials.
+    pub async fn set_credential_store(self: &mut RustleDownloader, credential_store: Option<Arc<CredentialStore>>) -> &RustleDownloader {
+        let mut inner = self.inner.lock().await;
+        inner.credential_store = credential_store;
+        inner.credentials_loaded = false;
+        drop(inner);
+        self
+    }
+
+    /// Adds the stored credentials of the URL's host and the proxy to the client options, once.
+    /// A store that can't be reached (e.g. no Secret Service running) is treated as an empty one,
+    /// the server then rejects the requests needing credentials.
+    async fn load_credentials(self: &RustleDownloader) {
+        let (store, url, proxy) = {
+            let inner = self.inner.lock().await;
+            match (inner.credential_store.clone(), inner.url.clone()) {
+                (Some(store), Some(url)) if !inner.credentials_loaded => (store, url, inner.client_options.proxy.clone()),
+                _ => return,
+            }
+        };
+
+        let credentials = match credential_key(&url.0) {
+            Some(key) if url.0.username().is_empty() => store.get(&key).await.ok().flatten(),
+            _ => None,
+        };
+        let proxy = proxy.and_then(|proxy| Url::parse(&proxy).ok()).filter(|proxy| proxy.username().is_empty());
+        let proxy_credentials = match proxy.as_ref().and_then(credential_key) {
+            Some(key) => store.get(&key).await.ok().flatten(),
+            None => None,
+        };
+
+        let mut inner = self.inner.lock().await;
+        inner.credentials_loaded = true;
+        if let Some(authorization) = credentials.and_then(|credentials| credentials.basic_auth().ok()) {
+            if !inner.client_options.headers.contains_key(AUTHORIZATION) {
+                inner.client_options.headers.insert(AUTHORIZATION, authorization);
+                inner.client = None;
+            }
+        }
+        if let (Some(mut proxy), Some(credentials)) = (proxy, proxy_credentials) {
+            if proxy.set_username(&credentials.username).is_ok() && proxy.set_password(Some(&credentials.password)).is_ok() {
+                inner.client_options.proxy = Some(proxy.to_string());
+                inner.client = None;
+            }
+        }
+    }
+
+    /// Sets the method and the body of the download requests, e.g. `POST` with the JSON body an
+    /// export API or a report generator expects. Such requests can't ask for ranges, the file is
+    /// downloaded over a single connection, and a paused download sends the request again from
+    /// the start. `init()` reads the headers of the response and the download continues with its body,
+    /// so the request is only sent once. A transport set with `set_transport` is given them by its
+    /// creator, e.g. with `HttpTransport::with_request`.
+    ///
+    /// # Arguments
+    ///
+    /// * `method` - The method, `GET` for a regular download.
+    /// * `body` - The body of the requests, if any.
+    pub async fn set_request(self: &mut RustleDownloader, method: Method, body: Option<Bytes>) -> &RustleDownloader {
+        let mut inner = self.inner.lock().await;
+        inner.client_options.method = method;
+        inner.client_options.body = body;
+        inner.client = None;
+        drop(inner);
+        self
+    }
+
+    /// Sets whether a compressed response (`Content-Encoding: gzip`, ..) of a download over a single
+    /// connection is decoded before the file is written, or stored as sent. Range requests always
+    /// ask for the uncompressed file.
+    ///
+    /// # Arguments
+    ///
+    /// * `content_encoding` - What is stored.
+    pub async fn set_content_encoding(self: &mut RustleDownloader, content_encoding: ContentEncodingMode) -> &RustleDownloader {
+        let mut inner = self.inner.lock().await;
+        inner.client_options.content_encoding = content_encoding;
+        inner.client = None;
+        drop(inner);
+        self
+    }
+
+    /// Returns the method of the download requests.
+    pub async fn get_method(self: &RustleDownloader) -> Method {
+        self.inner.lock().await.client_options.method.clone()
+    }
+
+    /// Sets the refresher supplying a new URL when a range request is answered with `403 Forbidden`
+    /// or `410 Gone`, e.g. because a signed URL expired. The parts then continue from the bytes
+    /// they already received with the new URL, instead of failing.
+    ///
+    /// # Arguments
+    ///
+    /// * `url_refresher` - The refresher, `None` to fail the parts on expired URLs.
+    pub async fn set_url_refresher(self: &mut RustleDownloader, url_refresher: Option<Arc<dyn UrlRefresher>>) -> &RustleDownloader {
+        self.inner.lock().await.url_refresher = url_refresher;
+        self
+    }
+
+    /// Replaces the expired URL of the download with the one supplied by the refresher, once for
+    /// all the parts rejected with the same URL.
+    ///
+    /// # Arguments
+    ///
+    /// * `expired` - The URL the range request was rejected with.
+    ///
+    /// Returns the new URL, or an error if there's no refresher or it failed.
+    async fn refresh_url(self: &RustleDownloader, expired: &ValidUrl) -> Result<ValidUrl, String> {
+        let (url_refresher, url_refresh) = {
+            let inner = self.inner.lock().await;
+            (inner.url_refresher.clone().ok_or("The URL expired and no refresher was set")?, inner.url_refresh.clone())
+        };
+        let _refreshing = url_refresh.lock().await;
+
+        // Another part may have refreshed the URL while this one was waiting
+        if let Some(url) = self.inner.lock().await.url.clone().filter(|url| url.as_str() != expired.as_str()) {
+            return Ok(url);
+        }
+
+        let url = url_refresher.refresh_url(&expired.0).await
+            .map_err(|e| format!("Couldn't refresh the expired URL, error : {}", e))?;
+        let url = ValidUrl::new(&url).map_err(|e| format!("The refreshed URL {} is invalid, error : {}", url, e))?;
+        let mut inner = self.inner.lock().await;
+        if url.0.scheme() != expired.0.scheme() {
+            inner.client = None;
+        }
+        inner.url = Some(url.clone());
+        self.timeline.record(TimelineEvent::UrlRefreshed);
+        Ok(url)
+    }
+
+    /// Sets the name of the downloaded file, instead of the one detected by `init()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `file_name` - The file name, it's sanitized like detected names.
+    pub async fn set_file_name(self: &mut RustleDownloader, file_name: &str) -> &RustleDownloader {
+        self.inner.lock().await.file_name = Some(sanitize_file_name(file_name));
+        self
+    }
+
+    /// Sets the SHA-256 the downloaded file must match, the download fails instead of
+    /// writing the file if it doesn't.
+    ///
+    /// # Arguments
+    ///
+    /// * `sha256` - The expected checksum, as 64 hexadecimal digits.
+    ///
+    /// Returns an error if the checksum isn't a valid SHA-256.
+    pub async fn set_expected_sha256(self: &mut RustleDownloader, sha256: &str) -> Result<&RustleDownloader, String> {
+        if !is_sha256_hex(sha256) {
+            return Err(format!("`{}` isn't a valid SHA-256 checksum", sha256));
+        }
+        self.inner.lock().await.expected_sha256 = Some(sha256.to_ascii_lowercase());
+        Ok(self)
+    }
+
+    /// Sets the transport sending the requests of this download, instead of the client of the
+    /// URL's protocol built from the TLS, address family and header options. Meant for tests
+    /// serving the file from memory, new protocols are added with `set_protocols()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `transport` - The transport to use for all requests of this download.
+    pub async fn set_transport(self: &mut RustleDownloader, transport: Arc<dyn Transport>) -> &RustleDownloader {
+        self.inner.lock().await.transport = Some(transport);
+        self
+    }
+
+    /// Sets the writer of the downloaded file, `StdFileWriter` by default.
+    ///
+    /// # Arguments
+    ///
+    /// * `file_writer` - The writer, e.g. a `UringFileWriter` for very fast links.
+    pub async fn set_file_writer(self: &mut RustleDownloader, file_writer: Arc<dyn FileWriter>) -> &RustleDownloader {
+        self.inner.lock().await.file_writer = Some(file_writer);
+        self
+    }
+
+    /// Sets the protocols of the RustleDownloader, the client sending the requests is created by the
+    /// protocol of the URL's scheme. Only `http` and `https` are supported by default.
+    ///
+    /// # Arguments
+    ///
+    /// * `protocols` - The registry of the supported protocols.
+    pub async fn set_protocols(self: &mut RustleDownloader, protocols: Arc<ProtocolRegistry>) -> &RustleDownloader {
+        let mut inner = self.inner.lock().await;
+        inner.protocols = protocols;
+        inner.client = None;
+        drop(inner);
+        self
+    }
+
+    /// Sets how failed downloads are retried, they aren't retried by default.
+    ///
+    /// # Arguments
+    ///
+    /// * `retry_policy` - The number of retries and the delays between them.
+    pub async fn set_retry_policy(self: &mut RustleDownloader, retry_policy: RetryPolicy) -> &RustleDownloader {
+        self.inner.lock().await.retry_policy = retry_policy;
+        self
+    }
+
+    /// Sets whether the number of connections is tuned from the measured throughput: the download
+    /// starts with `AUTO_START_CONNECTIONS` connections, or the number last settled on for its host,
+    /// and adds one at a time as long as the throughput improves, up to the maximum. It's disabled
+    /// by default, and ignored when the number of connections is set with `set_connections()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `auto_connections` - Whether the number of connections is tuned.
+    pub async fn set_auto_connections(self: &mut RustleDownloader, auto_connections: bool) -> &RustleDownloader {
+        self.inner.lock().await.auto_connections = auto_connections;
+        self
+    }
+
+    /// Sets the time a download stays paused before the file is checked on the server again when
+    /// it's resumed, like `init()` does, instead of requesting its ranges right away: an expired
+    /// link is refreshed (see `set_url_refresher()`) and a changed file is downloaded again.
+    ///
+    /// # Arguments
+    ///
+    /// * `stale_after` - The time, `DEFAULT_STALE_AFTER` by default, `None` to never check it again.
+    pub async fn set_stale_after(self: &mut RustleDownloader, stale_after: Option<Duration>) -> &RustleDownloader {
+        self.inner.lock().await.stale_after = stale_after;
+        self
+    }
+
+    /// Sets the time a part waits for the next chunk before its connection is considered dead (e.g.
+    /// a server that hangs, or a connection dropped without being closed): the request is aborted
+    /// and the rest of its range requested again, see `TimelineEvent::PartStalled`.
+    ///
+    /// # Arguments
+    ///
+    /// * `stall_timeout` - The time, `DEFAULT_STALL_TIMEOUT` by default, `None` to wait forever.
+    pub async fn set_stall_timeout(self: &mut RustleDownloader, stall_timeout: Option<Duration>) -> &RustleDownloader {
+        self.inner.lock().await.stall_timeout = stall_timeout;
+        self
+    }
+
+    /// Sets the minimum time between two progress updates, both for the progress bar
+    /// and for the snapshots returned by `next_progress_vec()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `interval` - The progress tick interval, `DEFAULT_PROGRESS_INTERVAL` if not set.
+    pub async fn set_progress_interval(self: &mut RustleDownloader, interval: Duration) -> &RustleDownloader {
+        self.inner.lock().await.progress_interval = interval;
+        self
+    }
+
+    /// Sets the staging directory the file is written to once it's complete, e.g. on a fast disk, before
+    /// it's moved to the output directory. Moves across file systems copy the file, see `get_move_progress()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `staging_dir` - The staging directory, the file is written to the output directory directly if `None`.
+    pub async fn set_staging_dir(self: &mut RustleDownloader, staging_dir: Option<PathBuf>) -> &RustleDownloader {
+        self.inner.lock().await.staging_dir = staging_dir.filter(|staging_dir| !staging_dir.as_os_str().is_empty());
+        self
+    }
+
+    /// Sets the mode of the file once it's complete, on Unix. The executable bits can be added to
+    /// scripts and executables depending on their `Content-Type`, see `FilePermissions`.
+    ///
+    /// # Arguments
+    ///
+    /// * `file_permissions` - The permissions of the file, the default keeps the mode given by the umask.
+    pub async fn set_file_permissions(self: &mut RustleDownloader, file_permissions: FilePermissions) -> &RustleDownloader {
+        self.inner.lock().await.file_permissions = file_permissions;
+        self
+    }
+
+    /// Records the URL of the file in its metadata once it's complete, like the browsers do: the
+    /// `Zone.Identifier` stream on Windows and the `user.xdg.origin.url` extended attribute on
+    /// Linux and macOS, see `provenance::write_provenance`.
+    ///
+    /// # Arguments
+    ///
+    /// * `provenance` - Whether the URL is recorded, `false` by default.
+    pub async fn set_provenance(self: &mut RustleDownloader, provenance: bool) -> &RustleDownloader {
+        self.inner.lock().await.provenance = provenance;
+        self
+    }
+
+    /// Checks the `Content-Type` of the file against its extension before it's downloaded. On a
+    /// mismatch, e.g. an `.iso` served as `text/html`, `download()` stops with the `Warning` status
+    /// until the type is confirmed with `confirm_type()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `type_check` - Whether the type is checked, `true` by default.
+    pub async fn set_type_check(self: &mut RustleDownloader, type_check: bool) -> &RustleDownloader {
+        self.inner.lock().await.type_check = type_check;
+        self
+    }
+
+    /// Enables the hashing of the parts' segments as they stream. The digests are stored in the
+    /// resume token, the segments that don't match them when the download is resumed or completed
+    /// are downloaded again instead of the whole file.
+    ///
+    /// # Arguments
+    ///
+    /// * `segment_hashing` - Whether the segments are hashed, disabled by default.
+    pub async fn set_segment_hashing(self: &mut RustleDownloader, segment_hashing: bool) -> &RustleDownloader {
+        self.inner.lock().await.segment_hashing = segment_hashing;
+        self
+    }
+
+    /// Sets where the parts keep the bytes they receive until the file is complete: in memory by
+    /// default, or on disk next to the file (in the staging directory if set) for files larger than
+    /// the memory. The parts on disk write their bytes every `BUFFER_FLUSH_SIZE` bytes, their
+    /// segments aren't hashed (see `set_segment_hashing()`), they don't reserve memory from the
+    /// budget of the connection manager, and the file is moved in place instead of being written
+    /// by the file writer.
+    ///
+    /// # Arguments
+    ///
+    /// * `buffering` - The buffering strategy.
+    ///
+    /// Returns an error if some bytes of the download are already on disk.
+    pub async fn set_buffering(self: &mut RustleDownloader, buffering: BufferingStrategy) -> Result<&RustleDownloader, String> {
+        let mut inner = self.inner.lock().await;
+        if buffering != inner.buffering && inner.parts.iter().any(|state| state.flushed > 0) {
+            return Err(String::from("The buffering strategy can't change once the download wrote some bytes to disk"));
+        }
+        inner.buffering = buffering;
+        drop(inner);
+        Ok(self)
+    }
+
+    /// Returns where the parts keep the bytes they receive, see `set_buffering()`.
+    pub async fn get_buffering(self: &RustleDownloader) -> BufferingStrategy {
+        self.inner.lock().await.buffering
+    }
+
+    /// Enables the chunk-level audit trail: every range request, response status, and
+    /// the size and SHA-256 of every received chunk are appended to a JSON lines file.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the audit log file, it is truncated if it exists.
+    ///
+    /// Returns an error if the audit log file couldn't be created.
+    pub async fn set_audit_log(self: &mut RustleDownloader, path: &Path) -> Result<&RustleDownloader, String> {
+        let audit_log = AuditLog::create(path)?;
+        self.inner.lock().await.audit_log = Some(Arc::new(audit_log));
+        Ok(self)
+    }
+
+    /// Enables the debug mode recording every request of the download along with its response
+    /// headers, status and timings, e.g. to find out why a host breaks multi-part downloads.
+    /// Enable it before `init()` to also record the request probing the file, calling it again
+    /// keeps the recorded requests and only changes the path.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The HAR file the requests are written to after each call to `download()`, if any,
+    ///   see `export_har()`.
+    pub async fn set_har_log(self: &mut RustleDownloader, path: Option<PathBuf>) -> &RustleDownloader {
+        let mut inner = self.inner.lock().await;
+        match inner.har_log.as_mut() {
+            Some((_, har_path)) => *har_path = path,
+            None => inner.har_log = Some((Arc::default(), path)),
+        }
+        drop(inner);
+        self
+    }
+
+    /// Retrieves the requests recorded since `set_har_log()` was called, in the order they were answered.
+    pub async fn get_har_entries(self: &RustleDownloader) -> Vec<HarEntry> {
+        self.inner.lock().await.har_log.as_ref().map(|(har_log, _)| har_log.entries()).unwrap_or_default()
+    }
+
+    /// Writes the requests recorded so far to a HAR file, which browsers and HAR viewers open.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error message if the requests aren't recorded or the file couldn't be written.
+    pub async fn export_har(self: &RustleDownloader, path: &Path) -> Result<(), String> {
+        let har_log = self.inner.lock().await.har_log.as_ref().map(|(har_log, _)| har_log.clone());
+        har_log.ok_or("The requests aren't recorded, see `set_har_log()`")?.write(path)
+    }
+
+    /// Checks a local copy of the file against the server without downloading it, e.g. a file
+    /// downloaded earlier: its size, its version and its SHA-256 if one is known, see `VerifyReport`.
+    /// The download must be initialized, the probe of `init()` gives the remote size and validators.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error message if the download isn't initialized or the local file couldn't be read.
+    pub async fn verify_file(self: &RustleDownloader, path: &Path, options: &VerifyOptions) -> Result<VerifyReport, String> {
+        let (url, info, transport) = {
+            let mut inner = self.inner.lock().await;
+            let info = inner.get_headers_info.clone().ok_or("The download isn't initialized, see `init()`")?;
+            (inner.url.clone().unwrap(), info, inner.transport()?)
+        };
+        verify_against(path, url.as_url(), &info, transport.as_ref(), options).await
+    }
+
+    /// Creates a new instance of RustleDownloader.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_parallel_connections` - The maximum number of parallel connections for downloading.
+    ///
+    /// Returns an error if the maximum number of parallel connections is zero.
+    pub fn new (max_parallel_connections : u8) -> Result<RustleDownloader, String>{
+        Ok(
+            RustleDownloader 
+                { 
+                    inner: Arc::new(Mutex::new(RustleDownloaderInner 
+                        {
+                         url : None,
+                         out_dir : None,
+                         max_parallel_connections,
+                         connections_override: None,
+                         get_headers_info: None, 
+                         file_name: None,
+                         expected_sha256: None,
+                         retry_policy: RetryPolicy::default(),
+                         retry_info: None,
+                         progress_bar: None,
+                         progress: Arc::new([]),
+                         progress_interval: DEFAULT_PROGRESS_INTERVAL,
+                         last_progress: None,
+                         speed_history: VecDeque::with_capacity(SPEED_HISTORY_LEN),
+                         speed_sampler: None,
+                         auto_connections: false,
+                         connection_limit: watch::Sender::new(usize::MAX),
+                         connection_tuner: None,
+                         download_status: watch::Sender::new(DownloadStatus::Idle),
+                         connection_manager: None,
+                         client_options: ClientOptions::default(),
+                         client: None,
+                         protocols: Arc::new(ProtocolRegistry::default()),
+                         transport: None,
+                         file_writer: None,
+                         audit_log: None,
+                         har_log: None,
+                         speed_limit: None,
+                         credential_store: None,
+                         credentials_loaded: false,
+                         url_refresher: None,
+                         url_refresh: Arc::new(Mutex::new(())),
+                         negotiated_version: None,
+                         probe_response: None,
+                         parts: Vec::new(),
+                         stream_hash: Arc::default(),
+                         sha256: None,
+                         memory: Arc::default(),
+                         discard_partial: false,
+                         parts_in_flight: false,
+                         paused_offline: false,
+                         paused_quota: false,
+                         started_at: None,
+                         check_kept_parts: false,
+                         stale_after: Some(DEFAULT_STALE_AFTER),
+                         stall_timeout: Some(DEFAULT_STALL_TIMEOUT),
+                         resume_mismatch: None,
+                         error_kind: None,
+                         segment_hashing: false,
+                         buffering: BufferingStrategy::Memory,
+                         staging_dir: None,
+                         move_progress: None,
+                         file_permissions: FilePermissions::default(),
+                         provenance: false,
+                         type_check: true,
+                         type_confirmed: false,
+                        })),
+                    timeline: Arc::default(),
+                })
+    }
+
+    /// Returns a builder configuring a download and building it initialized, as an alternative
+    /// to `new()` followed by the setters and `init()`.
+    pub fn builder() -> RustleDownloaderBuilder {
+        RustleDownloaderBuilder::default()
+    }
+
+    /// Creates a RustleDownloader continuing the download described by a resume token, see `resume_token()`.
+    /// The engine is initialized from the token, `init()` isn't needed, and `download()` continues
+    /// from the bytes it holds. Options that aren't part of the token (connection manager, TLS, ..)
+    /// are set as usual.
+    ///
+    /// # Arguments
+    ///
+    /// * `token` - The resume token of the download.
+    /// * `max_parallel_connections` - The maximum number of parallel connections, used if the download starts over.
+    ///
+    /// Returns an error if the token is invalid.
+    pub fn from_resume_token(token: ResumeToken, max_parallel_connections: u8) -> Result<RustleDownloader, String> {
+        token.validate()?;
+
+        let engine = RustleDownloader::new(max_parallel_connections)?;
+        let corrupt = engine.inner.try_lock().expect("the engine was just created").restore(token)?;
+        if corrupt > 0 {
+            engine.timeline.record(TimelineEvent::SegmentsRepaired { segments: corrupt });
+        }
+        Ok(engine)
+    }
+
+    /// Continues the download described by a resume token with this engine, like `from_resume_token()`
+    /// while keeping the options already set (connection manager, TLS, ..). The parts the engine
+    /// holds are replaced by the ones of the token.
+    ///
+    /// # Arguments
+    ///
+    /// * `token` - The resume token of the download.
+    ///
+    /// Returns an error if the token is invalid, or if the download is running.
+    pub async fn set_resume_token(self: &mut RustleDownloader, token: ResumeToken) -> Result<&RustleDownloader, String> {
+        token.validate()?;
+
+        let corrupt = {
+            let mut inner = self.inner.lock().await;
+            if matches!(*inner.download_status.borrow(), DownloadStatus::Downloading | DownloadStatus::Paused | DownloadStatus::Retrying) {
+                return Err(String::from("Cancel the download before continuing another one"));
+            }
+            inner.drop_parts();
+            inner.restore(token)?
+        };
+        if corrupt > 0 {
+            self.timeline.record(TimelineEvent::SegmentsRepaired { segments: corrupt });
+        }
+        Ok(self)
+    }
+
+    /// Downloads a file asynchronously from a given URL using multiple parallel connections.
+    /// If `with_progress_bar` is `true`, a progress bar will be displayed during the download process.
+    ///
+    /// # Arguments
+    ///
+    /// * `self` - The RustleDownloader object reference.
+    /// * `with_progress_bar` - A boolean value indicating whether to display a progress bar.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<bool, String>` - A Result indicating whether the download was successful (`false` if it was cancelled)
+    ///   or an error occurred.
+    ///
+    /// A failed download is retried according to the retry policy, the status is `Retrying` while it
+    /// waits for the next attempt. `resume()` retries right away and `cancel()` stops waiting.
+    ///
+    /// With a connection manager, the download is paused while the network is down and resumed
+    /// once it's back, see `is_paused_offline()`. Attempts failing meanwhile don't count as retries.
+    /// It's also paused while the quota of its data usage is reached, see `is_paused_quota()`.
+    ///
+    /// A file whose `Content-Type` doesn't match its extension isn't downloaded, the status is
+    /// `Warning` and `Ok(false)` is returned until the type is confirmed, see `get_type_mismatch()`.
+    pub async fn download(self: &RustleDownloader, with_progress_bar: bool) -> Result<bool, String> {
+        if let Some(mismatch) = self.get_type_mismatch().await {
+            self.inner.lock().await.download_status.send_replace(DownloadStatus::Warning);
+            self.timeline.record(TimelineEvent::TypeMismatch { mismatch });
+            return Ok(false);
+        }
+        let connection_manager = {
+            let mut inner = self.inner.lock().await;
+            inner.started_at = Some(SystemTime::now());
+            inner.check_kept_parts = true;
+            inner.resume_mismatch = None;
+            inner.connection_manager.clone()
+        };
+        self.timeline.record(TimelineEvent::Started);
+        let data_usage = connection_manager.as_ref().and_then(|manager| manager.data_usage());
+        let quota_follower = data_usage.clone().map(|data_usage| {
+            let self_cloned = self.clone();
+            task::spawn(async move { self_cloned.follow_quota(data_usage).await })
+        });
+        let network_follower = connection_manager.map(|manager| {
+            let self_cloned = self.clone();
+            task::spawn(async move { self_cloned.follow_network(manager).await })
+        });
+
+        let result = self.download_with_retries(with_progress_bar).await;
+        if let Err(error) = result.as_ref() {
+            let kind = self.failed_error_kind().await;
+            self.timeline.record(TimelineEvent::Failed { error: error.clone(), kind });
+        }
+
+        for follower in network_follower.into_iter().chain(quota_follower) {
+            follower.abort();
+        }
+        let har_log = {
+            let mut inner = self.inner.lock().await;
+            inner.paused_offline = false;
+            inner.paused_quota = false;
+            inner.har_log.clone()
+        };
+        // The usage is saved periodically while downloading, the last bytes once the run ends
+        if let Some(data_usage) = data_usage {
+            let _ = data_usage.save();
+        }
+        // The debug dump doesn't change the outcome of the download
+        if let Some((har_log, Some(path))) = har_log {
+            let _ = har_log.write(&path);
+        }
+        result
+    }
+
+    /// Returns whether the download is paused because the network is down, it's resumed once it's back.
+    pub async fn is_paused_offline(self: &RustleDownloader) -> bool {
+        self.inner.lock().await.paused_offline
+    }
+
+    /// Returns whether the download is paused because the data quota is reached, it's resumed once
+    /// the quota is available again, see `DataUsage`.
+    pub async fn is_paused_quota(self: &RustleDownloader) -> bool {
+        self.inner.lock().await.paused_quota
+    }
+
+    /// Pauses the running download while the quota of `data_usage` is reached, and resumes it once
+    /// the period is over or the quota is raised, unless it was paused or resumed by the user meanwhile.
+    async fn follow_quota(self: &RustleDownloader, data_usage: Arc<DataUsage>) {
+        let mut quota_reached = data_usage.subscribe_quota();
+        let mut status = self.inner.lock().await.download_status.subscribe();
+        let running = |status: &DownloadStatus| matches!(status, DownloadStatus::Downloading | DownloadStatus::Retrying);
+        loop {
+            if quota_reached.wait_for(|reached| *reached).await.is_err() {
+                return;
+            }
+            // A download starting, or resumed by the user, while the quota is reached is paused as well
+            if status.wait_for(running).await.is_err() {
+                return;
+            }
+            {
+                let mut inner = self.inner.lock().await;
+                if !data_usage.is_quota_reached() || !running(&inner.download_status.borrow()) {
+                    continue;
+                }
+                inner.download_status.send_replace(DownloadStatus::Paused);
+                inner.client = None;
+                inner.paused_quota = true;
+                self.timeline.record(TimelineEvent::QuotaReached);
+            }
+
+            // The period of the quota ends without any byte being counted, so it's checked periodically
+            while data_usage.check_quota() && *status.borrow() == DownloadStatus::Paused {
+                tokio::select! {
+                    _ = tokio::time::sleep(QUOTA_CHECK_INTERVAL) => {},
+                    changed = quota_reached.changed() => if changed.is_err() { return },
+                    changed = status.changed() => if changed.is_err() { return },
+                }
+            }
+            {
+                let mut inner = self.inner.lock().await;
+                if std::mem::take(&mut inner.paused_quota) && *inner.download_status.borrow() == DownloadStatus::Paused {
+                    inner.download_status.send_replace(DownloadStatus::Downloading);
+                    self.timeline.record(TimelineEvent::Resumed);
+                }
+            }
+
+            // A download resumed by the user while the quota is reached continues until the next period
+            if quota_reached.wait_for(|reached| !*reached).await.is_err() {
+                return;
+            }
+        }
+    }
+
+    /// Pauses the running download when the network goes down and resumes it when it's back,
+    /// unless it was paused or resumed by the user meanwhile.
+    async fn follow_network(self: &RustleDownloader, connection_manager: Arc<ConnectionManager>) {
+        let mut network = connection_manager.subscribe_network();
+        loop {
+            if network.wait_for(|state| *state == NetworkState::Offline).await.is_err() {
+                return;
+            }
+            self.pause_offline().await;
+
+            if network.wait_for(|state| *state == NetworkState::Online).await.is_err() {
+                return;
+            }
+            let mut inner = self.inner.lock().await;
+            if std::mem::take(&mut inner.paused_offline) && *inner.download_status.borrow() == DownloadStatus::Paused {
+                inner.download_status.send_replace(DownloadStatus::Downloading);
+                self.timeline.record(TimelineEvent::Resumed);
+            }
+        }
+    }
+
+    /// Pauses the download until the network is back, if it's running or failed.
+    async fn pause_offline(self: &RustleDownloader) {
+        let mut inner = self.inner.lock().await;
+        if matches!(*inner.download_status.borrow(), DownloadStatus::Downloading | DownloadStatus::Retrying | DownloadStatus::Error) {
+            inner.download_status.send_replace(DownloadStatus::Paused);
+            inner.client = None;
+            inner.paused_offline = true;
+            self.timeline.record(TimelineEvent::Paused { offline: true });
+        }
+    }
+
+    /// Runs the attempts of `download()` according to the retry policy.
+    async fn download_with_retries(self: &RustleDownloader, with_progress_bar: bool) -> Result<bool, String> {
+        let (retry_policy, connection_manager) = {
+            let inner = self.inner.lock().await;
+            (inner.retry_policy, inner.connection_manager.clone())
+        };
+        let mut retry = 0;
+
+        loop {
+            let error = match self.download_attempt(with_progress_bar).await {
+                Err(e) if e == STALE_PARTS => continue,
+                // Failing because the network is down isn't a retry, wait for it to come back
+                Err(_) if connection_manager.as_ref().is_some_and(|manager| manager.network_state() == NetworkState::Offline) => {
+                    self.pause_offline().await;
+                    let mut status = self.inner.lock().await.download_status.subscribe();
+                    let _ = status.wait_for(|status| *status != DownloadStatus::Paused).await;
+                    if *status.borrow() == DownloadStatus::Cancelled {
+                        return self.finish_cancelled().await;
+                    }
+                    continue;
+                },
+                Err(e) if retry < retry_policy.max_retries => e,
+                result => return result,
+            };
+            // Failures happening again whatever the attempt, e.g. a missing file, aren't retried
+            let kind = self.failed_error_kind().await;
+            if !kind.is_retryable() {
+                return Err(error);
+            }
+            retry += 1;
+
+            let delay = retry_policy.delay(retry);
+            let mut status = {
+                let mut inner = self.inner.lock().await;
+                self.timeline.record(TimelineEvent::Retrying { retry, max_retries: retry_policy.max_retries, delay, error: error.clone(), kind });
+                inner.retry_info = Some(RetryInfo { retry, max_retries: retry_policy.max_retries, next_attempt: Instant::now() + delay, error, kind });
+                inner.download_status.send_replace(DownloadStatus::Retrying);
+                inner.download_status.subscribe()
+            };
+
+            // Wait for the delay, or until the status changes, `pause()` then waits for `resume()`
+            let waiting_since = Instant::now();
+            tokio::select! {
+                _ = tokio::time::sleep(delay) => {},
+                _ = status.wait_for(|status| *status != DownloadStatus::Retrying) => {},
+            }
+            let _ = status.wait_for(|status| *status != DownloadStatus::Paused).await;
+
+            if self.is_stale(waiting_since).await {
+                self.inner.lock().await.check_kept_parts = true;
+            }
+            self.inner.lock().await.retry_info = None;
+            if *status.borrow() == DownloadStatus::Cancelled {
+                return self.finish_cancelled().await;
+            }
+        }
+    }
+
+    /// Records the kind of an error of the current attempt, unless an earlier error was recorded:
+    /// the first failure is the cause, the parts failing after it are cancelled.
+    async fn record_error_kind(self: &RustleDownloader, kind: ErrorKind) {
+        self.inner.lock().await.error_kind.get_or_insert(kind);
+    }
+
+    /// Returns the kind of the error the last attempt failed with, `Other` if none was recorded,
+    /// e.g. when the file couldn't be written.
+    async fn failed_error_kind(self: &RustleDownloader) -> ErrorKind {
+        *self.inner.lock().await.error_kind.get_or_insert(ErrorKind::Other)
+    }
+
+    /// Returns whether a download waiting since `since` waited long enough for its kept parts to be
+    /// checked on the server again, see `set_stale_after()`.
+    async fn is_stale(self: &RustleDownloader, since: Instant) -> bool {
+        self.inner.lock().await.stale_after.is_some_and(|stale_after| since.elapsed() >= stale_after)
+    }
+
+    /// Ends a download cancelled between two attempts, dropping its parts unless they're kept.
+    async fn finish_cancelled(self: &RustleDownloader) -> Result<bool, String> {
+        let mut inner = self.inner.lock().await;
+        if inner.discard_partial {
+            inner.drop_parts();
+        }
+        Ok(false)
+    }
+
+    /// Checks that the file on the server is still the one the kept parts were received from, once
+    /// per `download()`, before they're continued. If its length, `ETag` or `Last-Modified` date
+    /// changed, the parts are discarded and the download starts over, see `get_resume_mismatch()`.
+    async fn check_kept_parts(self: &RustleDownloader) -> Result<(), String> {
+        let (url, transport, known, connection_manager) = {
+            let mut inner = self.inner.lock().await;
+            let received = inner.parts.iter().any(|state| state.done || state.received() > 0);
+            // Parts requesting the whole file start over anyway
+            if !inner.check_kept_parts || !received || inner.whole_file() {
+                inner.check_kept_parts = false;
+                return Ok(());
+            }
+            let Some(known) = inner.get_headers_info.clone() else { return Ok(()) };
+            (inner.url.clone().unwrap(), inner.transport()?, known, inner.connection_manager.clone())
+        };
+
+        // An expired link is refreshed first, like the range requests do
+        let (mut url, mut transport, mut refreshes) = (url, transport, 0);
+        let current = loop {
+            let _permit = acquire_connection(connection_manager.as_ref(), &url, self.connection_key()).await?;
+            let mut errors = Vec::new();
+            let mut expired = None;
+            let mut current = None;
+            for strategy in init_strategies(connection_manager.as_ref(), &url) {
+                match probe_request(transport.as_ref(), &url, strategy).await {
+                    Ok(response) if response.status.is_success() => {
+                        current = Some(parse_response_headers(response.status, &response.url, &response.headers)?);
+                        break;
+                    },
+                    Ok(response) => {
+                        if matches!(response.status, StatusCode::FORBIDDEN | StatusCode::GONE) {
+                            expired = Some(response.status);
+                        }
+                        self.record_error_kind(ErrorKind::HttpStatus(response.status.as_u16())).await;
+                        errors.push(format!("{:?} : got status code : {}", strategy, response.status.as_str()));
+                    },
+                    Err(e) => {
+                        self.record_error_kind(e.kind.into()).await;
+                        errors.push(format!("{:?} : {}", strategy, e));
+                    },
+                }
+            }
+            match (current, expired) {
+                (Some(current), _) => {
+                    self.inner.lock().await.error_kind = None;
+                    break current;
+                },
+                (None, Some(_)) if refreshes < MAX_URL_REFRESHES && self.inner.lock().await.url_refresher.is_some() => {
+                    refreshes += 1;
+                    url = self.refresh_url(&url).await?;
+                    transport = self.inner.lock().await.transport()?;
+                },
+                (None, Some(status)) => return Err(format!("The link expired, got status code : {}", status.as_str())),
+                (None, None) => return Err(format!("Couldn't check the file on the server before resuming, {}", errors.join(" | "))),
+            }
+        };
+
+        let mut inner = self.inner.lock().await;
+        inner.check_kept_parts = false;
+        if let Err(mismatch) = check_resume(&known, &current) {
+            self.timeline.record(TimelineEvent::Restarted { reason: mismatch.to_string() });
+            inner.drop_parts();
+            if let Some(headers_info) = inner.get_headers_info.as_mut() {
+                headers_info.content_length = current.content_length;
+                headers_info.etag = current.etag;
+                headers_info.last_modified = current.last_modified;
+            }
+            inner.resume_mismatch = Some(mismatch);
+        }
+        Ok(())
+    }
+
+    /// Runs a single attempt of `download()`, continuing from the parts kept by previous attempts.
+    async fn download_attempt(self: &RustleDownloader, with_progress_bar: bool) -> Result<bool, String> {
+        {
+            let mut inner = self.inner.lock().await;
+
+            assert!(inner.url.is_some(), "No valid url was supplied");
+            assert!(inner.out_dir.is_some(), "No valid out_dir was supplied");
+            inner.error_kind = None;
+        }
+        self.load_credentials().await;
+        self.check_kept_parts().await?;
+
+        // Get required variables from inner
+        let get_headers_info = {
+            let inner = self.inner.lock().await;
+            inner.get_headers_info.clone()
+        };
+        let (max_parallel_connections, connections_override) = {
+            let inner = self.inner.lock().await;
+            (inner.max_parallel_connections, inner.connections_override)
+        };
+
+        match get_headers_info.as_ref() {
+            Some(headers_info) => {
+                
+                let content_length = headers_info.content_length.unwrap_or(0);
+
+                // Use the user's explicit count if present, otherwise pick it from the content length
+                let mut num_parts = match connections_override {
+                    Some(connections) => connections as u64,
+                    None => adaptive_num_parts(content_length, max_parallel_connections),
+                };
+
+                // if partial downloads isn't allowed OR content-length isn't defined
+                if headers_info.support_partial != SupportPartialRequest::Yes || headers_info.content_length.is_none() {
+                    num_parts = 1;
+                }
+
+                // Init the progress bar
+                if with_progress_bar {
+                    self.inner.lock().await.progress_bar = new_progress_bar(content_length);
+                }
+
+                // Plan the byte range of each part, unless the parts kept by a cancelled download are continued
+                {
+                    let mut inner = self.inner.lock().await;
+                    if inner.parts.is_empty() {
+                        inner.stream_hash = Arc::default();
+                        inner.sha256 = None;
+                        inner.parts = plan_ranges(content_length, num_parts).into_iter()
+                            .map(|range| PartState { start_byte: range.start, end_byte: range.end, flushed: 0, buffer: BytesMut::new(), digests: Vec::new(), done: false })
+                            .collect();
+                        inner.progress = PartCounters::new_parts(inner.parts.len());
+                        inner.speed_history.clear();
+                    }
+
+                    // Update downloading status
+                    inner.download_status.send_replace(DownloadStatus::Downloading);
+                    inner.discard_partial = false;
+                }
+
+                let mut status = self.inner.lock().await.download_status.subscribe();
+
+                // Sample the throughput every `SPEED_SAMPLE_INTERVAL` until the download ends
+                {
+                    let self_cloned = self.clone();
+                    let sampler = task::spawn(async move { self_cloned.sample_speed_history().await });
+                    if let Some(previous) = self.inner.lock().await.speed_sampler.replace(sampler) {
+                        previous.abort();
+                    }
+                }
+
+                // Start with a few connections and add more while the throughput improves, unless the number is fixed
+                let mut connection_limit = {
+                    let mut inner = self.inner.lock().await;
+                    let tuner = (inner.auto_connections && connections_override.is_none() && num_parts > 1 && !inner.whole_file()).then(|| {
+                        let host = inner.url.as_ref().and_then(|url| url.host());
+                        let start = inner.connection_manager.as_ref().zip(host)
+                            .and_then(|(manager, host)| manager.tuned_connections(host))
+                            .unwrap_or(AUTO_START_CONNECTIONS);
+                        ConnectionTuner::new(start, num_parts as usize)
+                    });
+                    inner.connection_limit.send_replace(tuner.as_ref().map_or(usize::MAX, |tuner| tuner.limit()));
+                    let self_cloned = self.clone();
+                    let previous = match tuner {
+                        Some(tuner) => inner.connection_tuner.replace(task::spawn(async move { self_cloned.tune_connections(tuner).await })),
+                        None => inner.connection_tuner.take(),
+                    };
+                    if let Some(previous) = previous {
+                        previous.abort();
+                    }
+                    inner.connection_limit.subscribe()
+                };
+
+                // Download the unfinished parts until all of them are done.
+                // Parts stop early when the download is paused, their ranges are
+                // reissued from the bytes received so far once it's resumed.
+                // Cancelling stops them the same way and ends the download.
+                let mut whole_file;
+                loop {
+                    let (progress, bounds, mut pending) = {
+                        let mut inner = self.inner.lock().await;
+                        if *inner.download_status.borrow() == DownloadStatus::Cancelled {
+                            if inner.discard_partial {
+                                inner.drop_parts();
+                            }
+                            return Ok(false);
+                        }
+
+                        whole_file = inner.whole_file();
+                        let pending: VecDeque<usize> = inner.parts.iter().enumerate().filter(|(_, state)| !state.done).map(|(part, _)| part).collect();
+                        inner.parts_in_flight = true;
+                        (inner.progress.clone(), inner.parts.iter().map(|state| (state.start_byte, state.end_byte)).collect::<Vec<_>>(), pending)
+                    };
+
+                    let mut running: Vec<usize> = Vec::with_capacity(pending.len());
+                    let mut tasks = FuturesUnordered::new();
+                    let mut download_results = Vec::with_capacity(pending.len());
+                    loop {
+                        // Start the pending parts the connection limit allows, the others wait for a running part to end
+                        let max_running = (*connection_limit.borrow_and_update()).max(1);
+                        while running.len() < max_running && *status.borrow() == DownloadStatus::Downloading {
+                            let Some(part) = pending.pop_front() else { break };
+                            let task = self.spawn_part(part).await;
+                            running.push(part);
+                            tasks.push(async move { (part, task.await) });
+                        }
+
+                        // Once the other parts are done, the last running one stops and its remaining bytes are split with the idle connections
+                        if let [tail] = running[..] {
+                            let received = bounds[tail].0 + progress[tail].downloaded_bytes.load(Ordering::Relaxed);
+                            let all_done = pending.is_empty() && download_results.iter().all(|(_, result)| matches!(result, Ok((_, _, _, Ok(true)))));
+                            if !whole_file && num_parts > 1 && all_done && bounds[tail].1 + 1 >= received + 2 * MIN_TAIL_SPLIT {
+                                progress[tail].split.store(true, Ordering::Relaxed);
+                            }
+                        }
+                        tokio::select! {
+                            next = tasks.next() => {
+                                let Some((part, result)) = next else { break };
+                                running.retain(|running| *running != part);
+                                download_results.push((part, result));
+                            },
+                            Ok(()) = connection_limit.changed() => {},
+                        }
+                    }
+
+                    {
+                        let mut inner = self.inner.lock().await;
+                        inner.parts_in_flight = false;
+                        let mut errors = Vec::new();
+                        let mut split = None;
+                        for (part, result) in download_results {
+                            inner.progress[part].finish_request();
+                            if inner.progress[part].split.swap(false, Ordering::Relaxed) {
+                                split = Some(part);
+                            }
+                            let flushed = inner.parts[part].flushed;
+                            let (flushed, buffer, digests, result) = result.unwrap_or_else(|e| (flushed, BytesMut::new(), Vec::new(), Err(format!("The part task failed, error : {}", e))));
+                            inner.parts[part].flushed = flushed;
+                            inner.parts[part].buffer = buffer;
+                            // The counters follow the kept bytes, in case a failed task lost some
+                            let received = inner.parts[part].received();
+                            inner.progress[part].downloaded_bytes.store(received, Ordering::Relaxed);
+                            inner.parts[part].digests = digests;
+                            match result {
+                                Ok(done) => inner.parts[part].done = done,
+                                Err(e) => errors.push(format!("part {} : {}", part, e)),
+                            }
+                        }
+
+                        // The server sent the whole file instead of the ranges, it's downloaded again over a single connection
+                        let ranges_ignored = inner.get_headers_info.as_ref().is_some_and(|info| info.support_partial == SupportPartialRequest::No);
+                        if !whole_file && ranges_ignored {
+                            self.timeline.record(TimelineEvent::RangesIgnored);
+                            inner.remove_part_buffers();
+                            let end_byte = inner.parts.iter().map(|state| state.end_byte).max().unwrap_or(0);
+                            inner.parts = vec![PartState { start_byte: 0, end_byte, flushed: 0, buffer: BytesMut::new(), digests: Vec::new(), done: false }];
+                            inner.progress = PartCounters::new_parts(1);
+                            inner.stream_hash = Arc::default();
+                            errors.clear();
+                        }
+
+                        // The parts keep their bytes, a later `download()` continues from them
+                        if !errors.is_empty() && *inner.download_status.borrow() != DownloadStatus::Cancelled {
+                            inner.download_status.send_replace(DownloadStatus::Error);
+                            return Err(format!("Couldn't download the file, {}", errors.join(" | ")));
+                        }
+
+                        // The stopped part continues its range along with the new parts, from the next iteration
+                        if let Some(part) = split.filter(|part| !ranges_ignored && !inner.parts[*part].done) {
+                            let connections = (*connection_limit.borrow()).min(num_parts as usize) as u64;
+                            let ranges = inner.split_part(part, connections);
+                            if ranges > 1 {
+                                self.timeline.record(TimelineEvent::TailSplit { ranges });
+                            }
+                        }
+
+                        if inner.parts.iter().all(|state| state.done) {
+                            // The segments corrupted since they were received are downloaded again before the file is written
+                            let corrupt = if whole_file { 0 } else { inner.repair_segments() };
+                            if corrupt == 0 {
+                                break;
+                            }
+                            self.timeline.record(TimelineEvent::SegmentsRepaired { segments: corrupt });
+                        }
+                    }
+
+                    // Wait for `resume()` or `cancel()` without holding any connection
+                    let paused_at = Instant::now();
+                    let _ = status.wait_for(|status| *status != DownloadStatus::Paused).await;
+
+                    // The link may have expired or the file changed during a long pause, the next attempt checks it first
+                    if self.is_stale(paused_at).await && *status.borrow() == DownloadStatus::Downloading {
+                        self.timeline.record(TimelineEvent::Revalidating);
+                        self.inner.lock().await.check_kept_parts = true;
+                        return Err(String::from(STALE_PARTS));
+                    }
+                }
+
+                // Only the bytes the parts received past the hashed offset are left to hash
+                let (full_content, assembled, sha256) = {
+                    let mut inner = self.inner.lock().await;
+                    match inner.part_buffers() {
+                        // The parts on disk are put together next to the file, the parts are kept until it's done
+                        Some(buffers) => {
+                            let mut hasher = inner.stream_hash.lock().unwrap().clone();
+                            let parts: Vec<(u64, u64, Bytes)> = inner.parts.iter().map(|state| (state.start_byte, state.flushed, Bytes::copy_from_slice(&state.buffer))).collect();
+                            let len = inner.parts.iter().map(|state| state.received()).sum::<u64>();
+                            drop(inner);
+                            let assembled = task::spawn_blocking(move || {
+                                let from = hasher.offset();
+                                buffers.assemble(&parts, from, |offset, bytes| { hasher.update(offset, bytes, usize::MAX); }).map(|path| (path, hasher))
+                            }).await;
+                            let mut inner = self.inner.lock().await;
+                            match assembled.map_err(|e| e.to_string()).and_then(|assembled| assembled.map_err(|e| e.to_string())) {
+                                Ok((path, hasher)) => {
+                                    inner.parts.clear();
+                                    inner.stream_hash = Arc::default();
+                                    (Bytes::new(), Some((path, len)), (hasher.offset() == len).then(|| hasher.finalize()))
+                                },
+                                Err(e) => {
+                                    inner.download_status.send_replace(DownloadStatus::Error);
+                                    return Err(format!("Couldn't put the file together from its parts, error : {}", e));
+                                },
+                            }
+                        },
+                        None => {
+                            let mut hasher = std::mem::take(&mut *inner.stream_hash.lock().unwrap());
+                            let mut full_content = BytesMut::new();
+                            for state in inner.parts.drain(..) {
+                                hasher.update(state.start_byte, &state.buffer, usize::MAX);
+                                full_content.extend_from_slice(&state.buffer);
+                            }
+                            let sha256 = (hasher.offset() == full_content.len() as u64).then(|| hasher.finalize());
+                            (full_content.freeze(), None, sha256)
+                        },
+                    }
+                };
+                // Given back to the budget once the file is written
+                let _memory = std::mem::take(&mut self.inner.lock().await.memory);
+
+                let inner = self.inner.lock().await;
+                let file_name = inner.file_name.as_ref().or(headers_info.file_name.as_ref()).unwrap();
+
+                // Compressed responses are decoded, unless they're stored raw
+                let encoding = inner.get_headers_info.as_ref()
+                    .and_then(|headers_info| headers_info.content_encoding.clone())
+                    .filter(|_| inner.client_options.content_encoding == ContentEncodingMode::Decode && assembled.is_none());
+                let (full_content, sha256) = match encoding {
+                    Some(encoding) => match decode_content(&encoding, full_content) {
+                        Ok(decoded) => (decoded, None),
+                        Err(e) => {
+                            inner.download_status.send_replace(DownloadStatus::Error);
+                            return Err(e);
+                        },
+                    },
+                    None => (full_content, sha256),
+                };
+
+                // Verify the content before writing it, so a corrupted download never lands in the output directory
+                let sha256 = match (sha256, inner.expected_sha256.is_some()) {
+                    (None, true) => Some(sha256_hex(&full_content)),
+                    (sha256, _) => sha256,
+                };
+                if let (Some(expected), Some(actual)) = (inner.expected_sha256.as_ref(), sha256.as_ref()) {
+                    if actual != expected {
+                        if let Some((path, _)) = assembled.as_ref() {
+                            let _ = std::fs::remove_file(path);
+                        }
+                        inner.download_status.send_replace(DownloadStatus::Error);
+                        return Err(format!("Checksum mismatch, expected SHA-256 {} but got {}", expected, actual));
+                    }
+                }
+
+                // The file is written to the staging directory first, under a name of its own, then moved to the output directory
+                let out_path = inner.out_dir.as_ref().unwrap().join(file_name);
+                let staged_path = inner.staging_dir.as_ref().map(|staging_dir| staging_dir.join(format!("{}.{:x}.staged", file_name, self.connection_key())));
+                let (write_name, write_dir) = match staged_path.as_ref() {
+                    Some(staged_path) => (staged_path.file_name().unwrap().to_string_lossy().into_owned(), inner.staging_dir.clone().unwrap()),
+                    None => (file_name.clone(), inner.out_dir.clone().unwrap()),
+                };
+
+                // The lock isn't held while writing, the progress stays readable. The file put
+                // together from the parts on disk is next to it already, it's renamed
+                let (bytes, write) = match assembled {
+                    Some((path, len)) => (len, tokio::fs::rename(path, write_dir.join(&write_name)).boxed()),
+                    None => {
+                        let bytes = full_content.len() as u64;
+                        match inner.file_writer.as_ref() {
+                            Some(file_writer) => (bytes, file_writer.write_file(full_content, &write_name, &write_dir)),
+                            None => (bytes, StdFileWriter::default().write_file(full_content, &write_name, &write_dir)),
+                        }
+                    },
+                };
+                drop(inner);
+                if let Err(e) = write.await {
+                    self.inner.lock().await.download_status.send_replace(DownloadStatus::Error);
+                    return Err(e.to_string());
+                }
+
+                if let Some(staged_path) = staged_path {
+                    let progress = Arc::new(MoveProgress::default());
+                    self.inner.lock().await.move_progress = Some(progress.clone());
+                    let moved_path = out_path.clone();
+                    let moved = task::spawn_blocking(move || move_file(&staged_path, &moved_path, &progress)).await;
+                    let mut inner = self.inner.lock().await;
+                    inner.move_progress = None;
+                    if let Err(e) = moved.map_err(|e| e.to_string()).and_then(|moved| moved.map_err(|e| e.to_string())) {
+                        inner.download_status.send_replace(DownloadStatus::Error);
+                        return Err(format!("Couldn't move the file from the staging directory, error : {}", e));
+                    }
+                }
+
+                // The mode is set once the file is in the output directory, a copy across file systems doesn't keep it
+                {
+                    let inner = self.inner.lock().await;
+                    let content_type = inner.get_headers_info.as_ref().and_then(|headers_info| headers_info.content_type.clone());
+                    if let Err(e) = inner.file_permissions.apply(&out_path, content_type.as_deref()) {
+                        inner.download_status.send_replace(DownloadStatus::Error);
+                        return Err(format!("Couldn't set the mode of the file, error : {}", e));
+                    }
+
+                    // The file is complete either way, file systems without extended attributes or streams (e.g. FAT) don't record it
+                    if let Some(url) = inner.url.as_ref().filter(|_| inner.provenance) {
+                        let referrer = inner.client_options.headers.get(REFERER).and_then(|v| v.to_str().ok()).and_then(|v| Url::parse(v).ok());
+                        let _ = write_provenance(&out_path, &url.0, referrer.as_ref());
+                    }
+                }
+
+                // Finish and clear progress_bar if present
+                if let Some(progress_bar) = self.inner.lock().await.progress_bar.as_ref() {
+                    progress_bar.finish_and_clear();
+                }
+
+                {
+                    let mut inner = self.inner.lock().await;
+                    inner.sha256 = sha256;
+                    inner.download_status.send_replace(DownloadStatus::Done);
+                }
+                self.timeline.record(TimelineEvent::Completed { bytes });
+             
+                Ok(true)
+
+            },
+            None => {
+                self.inner.lock().await.download_status.send_replace(DownloadStatus::Error);
+                Err(String::from("Couldn't download the file, header info is missing"))
+            },
+        }
+
+    }
+
+    /// Spawns the task downloading the bytes `part` didn't receive yet, the task holds the part's
+    /// buffer until it ends.
+    async fn spawn_part(self: &RustleDownloader, part: usize) -> JoinHandle<PartResult> {
+        let mut inner = self.inner.lock().await;
+        let state = &mut inner.parts[part];
+        let buffer = std::mem::take(&mut state.buffer);
+        let digests = std::mem::take(&mut state.digests);
+        let (start_byte, end_byte, flushed) = (state.start_byte, state.end_byte, state.flushed);
+
+        let self_cloned = self.clone();
+        task::spawn(async move {
+            self_cloned.download_part_from_url(start_byte, end_byte, part, flushed, buffer, digests).await
+        })
+    }
+
+    /// Appends the throughput of every `SPEED_SAMPLE_INTERVAL` to the speed history,
+    /// until the download is neither downloading nor paused.
+    async fn sample_speed_history(self: &RustleDownloader) {
+        let downloaded = |progress: &[PartCounters]| -> u64 {
+            progress.iter().map(|counters| counters.downloaded_bytes.load(Ordering::Relaxed)).sum()
+        };
+
+        let mut interval = tokio::time::interval(SPEED_SAMPLE_INTERVAL);
+        interval.tick().await;
+        let mut last_downloaded = downloaded(&self.inner.lock().await.progress);
+
+        loop {
+            interval.tick().await;
+
+            let mut inner = self.inner.lock().await;
+            if !matches!(*inner.download_status.borrow(), DownloadStatus::Downloading | DownloadStatus::Paused) {
+                break;
+            }
+
+            let now_downloaded = downloaded(&inner.progress);
+            let sample = now_downloaded.saturating_sub(last_downloaded) as f64 / SPEED_SAMPLE_INTERVAL.as_secs_f64();
+            last_downloaded = now_downloaded;
+
+            if inner.speed_history.len() == SPEED_HISTORY_LEN {
+                inner.speed_history.pop_front();
+            }
+            inner.speed_history.push_back(sample);
+        }
+    }
+
+    /// Measures the throughput with the current number of connections every `TUNE_INTERVAL` and
+    /// updates `connection_limit` from it, until the tuner settles or the download is neither
+    /// downloading nor paused. The settled number is remembered for the host by the connection manager.
+    async fn tune_connections(self: &RustleDownloader, mut tuner: ConnectionTuner) {
+        let downloaded = |progress: &[PartCounters]| -> u64 {
+            progress.iter().map(|counters| counters.downloaded_bytes.load(Ordering::Relaxed)).sum()
+        };
+
+        let mut interval = tokio::time::interval(TUNE_INTERVAL);
+        interval.tick().await;
+        // `None` while the connections ramp up, after a change or a pause
+        let mut last_downloaded = None;
+
+        loop {
+            interval.tick().await;
+
+            let inner = self.inner.lock().await;
+            let status = *inner.download_status.borrow();
+            match status {
+                DownloadStatus::Downloading => {},
+                DownloadStatus::Paused => {
+                    last_downloaded = None;
+                    continue;
+                },
+                _ => break,
+            }
+
+            let now_downloaded = downloaded(&inner.progress);
+            let Some(previous) = last_downloaded.replace(now_downloaded) else { continue };
+            let throughput = now_downloaded.saturating_sub(previous) as f64 / TUNE_INTERVAL.as_secs_f64();
+            let limit = tuner.observe(throughput);
+            if limit != *inner.connection_limit.borrow() {
+                inner.connection_limit.send_replace(limit);
+                last_downloaded = None;
+            }
+
+            if tuner.is_settled() {
+                self.timeline.record(TimelineEvent::ConnectionsTuned { connections: limit });
+                if let Some((manager, host)) = inner.connection_manager.as_ref().zip(inner.url.as_ref().and_then(|url| url.host())) {
+                    manager.remember_tuned_connections(host, limit);
+                }
+                break;
+            }
+        }
+    }
+
+    /// Returns the key identifying the download to the connection manager, shared by its clones.
+    fn connection_key(&self) -> usize {
+        Arc::as_ptr(&self.inner) as usize
+    }
+
+    /// Downloads a specific part of a file from a given URL asynchronously.
+    /// It requests the part's range from the bytes it already received up to `end_byte`.
+    /// The `part_num` parameter is used for tracking progress and updating the progress bar.
+    /// If the download is paused or cancelled, the request is aborted and the bytes received so far are returned.
+    ///
+    /// # Arguments
+    ///
+    /// * `self` - The RustleDownloader object reference.
+    /// * `part_start` - The first byte of the part's range.
+    /// * `end_byte` - The ending byte index for the download range.
+    /// * `part_num` - The index of the part being downloaded.
+    /// * `flushed` - The number of bytes already received for this part and written to its buffer on disk.
+    /// * `buffer` - The bytes received for this part after the flushed ones, the new bytes are appended to it.
+    /// * `digests` - The digests of the received segments, the new segments' digests are appended to it.
+    ///
+    /// # Returns
+    ///
+    /// * `(u64, BytesMut, Vec<String>, Result<bool, String>)` - The number of the part's bytes on disk, the ones
+    ///   in memory and their digests, kept even if it failed, and whether the part is complete (`false` if it
+    ///   was paused or cancelled), or an error message.
+    async fn download_part_from_url(self: &RustleDownloader, part_start: u64, end_byte: u64, part_num: usize, mut flushed: u64, mut buffer: BytesMut, mut digests: Vec<String>) -> PartResult {
+        let buffers = self.inner.lock().await.part_buffers();
+        let mut stalls = 0;
+        let mut result = loop {
+            let received = flushed + buffer.len() as u64;
+            match self.stream_part(part_start, end_byte, part_num, &mut flushed, &mut buffer, &mut digests).await {
+                Err(e) if e == CONNECTION_YIELDED => continue,
+                Err(e) if e == PART_STALLED => {
+                    stalls = if flushed + buffer.len() as u64 > received { 1 } else { stalls + 1 };
+                    self.timeline.record(TimelineEvent::PartStalled { part: part_num });
+                    if stalls > MAX_STALL_RESTARTS {
+                        self.record_error_kind(ErrorKind::Timeout).await;
+                        break Err(format!("The part at {} stalled {} times in a row", part_start, stalls));
+                    }
+                },
+                result => break result,
+            }
+        };
+        // The part doesn't hold any bytes in memory once it stops
+        if let Some(buffers) = buffers.as_ref() {
+            if let Err(e) = flush_part(buffers, part_start, &mut flushed, &mut buffer, &mut digests).await {
+                result = result.and(Err(e));
+            }
+        }
+        (flushed, buffer, digests, result)
+    }
+
+    /// Streams the range of a part into `buffer`, writing it to the part's buffer on disk as it grows
+    /// with another `BufferingStrategy` than `Memory`, see `download_part_from_url`.
+    async fn stream_part(self: &RustleDownloader, part_start: u64, end_byte: u64, part_num: usize, flushed: &mut u64, buffer: &mut BytesMut, digests: &mut Vec<String>) -> Result<bool, String> {
+        let (transport, url, connection_manager, audit_log, mut status, progress, progress_bar, progress_interval, if_range, speed_limit, method, whole_file, segment_hashing, stall_timeout) = {
+            let mut inner = self.inner.lock().await;
+            let if_range = inner.get_headers_info.as_ref().and_then(ResponseHeaderInfo::if_range).map(String::from);
+            (inner.transport()?, inner.url.clone(), inner.connection_manager.clone(), inner.audit_log.clone(), 
+             inner.download_status.subscribe(), inner.progress.clone(), inner.progress_bar.clone(), inner.progress_interval, if_range,
+             inner.speed_limit.clone(), inner.client_options.method.clone(), inner.whole_file(), inner.segment_hashing, inner.stall_timeout)
+        };
+        let buffers = self.inner.lock().await.part_buffers();
+        let buffers = buffers.as_ref();
+        // The whole file is requested again from the start, its segments aren't kept, nor the ones written to disk
+        let segment_hashing = segment_hashing && !whole_file && buffers.is_none();
+        let global_speed_limit = connection_manager.as_ref().and_then(|manager| manager.speed_limit());
+        let memory_budget = connection_manager.as_ref().and_then(|manager| manager.memory_budget());
+        let data_usage = connection_manager.as_ref().and_then(|manager| manager.data_usage());
+        let (memory, mut stream_hash) = {
+            let inner = self.inner.lock().await;
+            (inner.memory.clone(), inner.stream_hash.clone())
+        };
+        // The range continues from the bytes the part received
+        let start_byte = part_start + *flushed + buffer.len() as u64;
+
+        // Held until the part is done or stopped, so the per-host limit is respected for the whole stream
+        let mut permit = acquire_connection(connection_manager.as_ref(), url.as_ref().unwrap(), self.connection_key()).await?;
+
+        // The download may have been paused or cancelled while waiting for a connection
+        if *status.borrow() != DownloadStatus::Downloading {
+            return Ok(false);
+        }
+        
+        // Parts requesting the whole file start over
+        let range = (!whole_file).then_some(ByteRange { start: start_byte, end: end_byte });
+        if whole_file && !buffer.is_empty() {
+            buffer.clear();
+            digests.clear();
+            progress[part_num].downloaded_bytes.store(0, Ordering::Relaxed);
+            stream_hash = Arc::default();
+            self.inner.lock().await.stream_hash = stream_hash.clone();
+        }
+
+        let mut url = url.unwrap();
+        let mut transport = transport;
+        let mut refreshes = 0;
+        let mut response = loop {
+            if let Some(audit_log) = audit_log.as_ref() {
+                let range = range.map(|range| range.to_string()).unwrap_or_else(|| format!("{} without range", method));
+                audit_log.record(AuditRecord::RangeRequest { part: part_num, url: url.as_str().to_string(), range })?;
+            }
+
+            // The response of the request probing the download is continued, so it's only sent once
+            let probe_response = match whole_file {
+                true => self.inner.lock().await.probe_response.take(),
+                false => None,
+            };
+            let response = match probe_response {
+                Some(response) => response,
+                // Connectivity failures are reported to the connection manager, which pauses the downloads when the network is down
+                None => match transport.get_range(&url.0, range, if_range.as_deref().filter(|_| !whole_file), None).await {
+                    Ok(response) => response,
+                    Err(e) => {
+                        if e.is_connectivity() {
+                            if let Some(manager) = connection_manager.as_ref() { manager.record_failure(&url.0) }
+                        }
+                        self.record_error_kind(e.kind.into()).await;
+                        return Err(format!("An error occured while sending the download request, error : {}", e));
+                    },
+                },
+            };
+            if let Some(manager) = connection_manager.as_ref() {
+                manager.record_success();
+            }
+
+            if let Some(audit_log) = audit_log.as_ref() {
+                let content_range = response.headers.get(CONTENT_RANGE).and_then(|v| v.to_str().ok()).map(String::from);
+                audit_log.record(AuditRecord::Response { part: part_num, status: response.status.as_u16(), content_range })?;
+            }
+
+            // An expired URL (e.g. a signed one) is replaced and the same range requested again
+            let expired = matches!(response.status, StatusCode::FORBIDDEN | StatusCode::GONE);
+            if !expired || refreshes == MAX_URL_REFRESHES || self.inner.lock().await.url_refresher.is_none() {
+                break response;
+            }
+            refreshes += 1;
+            url = self.refresh_url(&url).await?;
+            transport = self.inner.lock().await.transport()?;
+        };
+        let record_failure = || if let Some(manager) = connection_manager.as_ref() { manager.record_failure(&url.0) };
+        self.inner.lock().await.negotiated_version = Some(response.version);
+
+        if whole_file && !response.status.is_success() {
+            let status = response.status;
+            self.record_error_kind(ErrorKind::HttpStatus(status.as_u16())).await;
+            return Err(format!("The {} request failed, got status code : {} | content of response {}", method, status.as_str(), response.text().await));
+        }
+
+        // The length of a compressed response is the one of the received bytes, the file is decoded once complete
+        if whole_file {
+            let encoding = response.headers.get(CONTENT_ENCODING).and_then(|v| v.to_str().ok()).filter(|encoding| is_compressed(encoding)).map(String::from);
+            let length = response.headers.get(CONTENT_LENGTH).and_then(|v| v.to_str().ok()).and_then(|v| v.parse().ok());
+            if let Some(headers_info) = self.inner.lock().await.get_headers_info.as_mut() {
+                if encoding.is_some() || headers_info.content_encoding.is_some() {
+                    headers_info.content_length = length;
+                }
+                headers_info.content_encoding = encoding;
+            }
+        }
+
+        // The whole file is sent instead of the range when the validator doesn't match anymore,
+        // or when the server ignores ranges: the download then continues over a single connection
+        if response.status == StatusCode::OK && !whole_file {
+            let mut inner = self.inner.lock().await;
+            let headers_info = inner.get_headers_info.as_mut().ok_or("The download isn't initialized")?;
+            let changed = [(ETAG, &headers_info.etag), (LAST_MODIFIED, &headers_info.last_modified)].into_iter().any(|(name, known)| {
+                match (response.headers.get(name).and_then(|v| v.to_str().ok()), known) {
+                    (Some(value), Some(known)) => value != known,
+                    _ => false,
+                }
+            });
+            // The next attempt checks the file again and starts over
+            if changed {
+                inner.check_kept_parts = true;
+                return Err(String::from("The file changed on the server since the download started"));
+            }
+            headers_info.support_partial = SupportPartialRequest::No;
+            return Err(String::from(RANGES_IGNORED));
+        }
+        if response.status != StatusCode::PARTIAL_CONTENT && !whole_file {
+            let status = response.status;
+            self.record_error_kind(ErrorKind::HttpStatus(status.as_u16())).await;
+            return Err(format!("Didn't recieve partial content, got status code : {} | content of response {}", status.as_str(), response.text().await));
+        }
+
+        // A part is only kept if the server sent exactly the requested bytes, another range would corrupt the file
+        let expected_bytes = match range {
+            Some(range) => {
+                let content_length = self.inner.lock().await.get_headers_info.as_ref().and_then(|headers_info| headers_info.content_length);
+                Some(check_content_range(&response, range, content_length)?)
+            },
+            None => None,
+        };
+
+        // The speed is measured over the current request
+        progress[part_num].start_request();
+        let counters = &progress[part_num];
+        let mut received_bytes: u64 = 0;
+
+        // The network side reads the response into a bounded channel and the sink stores the chunks:
+        // the sink waiting for memory or for the speed limits stops the reads once `PIPELINE_DEPTH` chunks are queued
+        let (sender, receiver) = mpsc::channel::<Bytes>(PIPELINE_DEPTH);
+        let mut network_status = status.clone();
+        let network = async move {
+            loop {
+                // Returning drops the response, aborting the request and releasing the connection
+                let chunk = tokio::select! {
+                    chunk = response.chunk() => chunk,
+                    _ = network_status.wait_for(|status| *status != DownloadStatus::Downloading) => return Ok(false),
+                    // The sink stopped the part
+                    _ = sender.closed() => return Ok(false),
+                    // Only the wait for the network counts, not the one for the sink
+                    _ = stall_timer(stall_timeout) => return Err(String::from(PART_STALLED)),
+                };
+                let chunk = match chunk {
+                    Ok(Some(chunk)) => chunk,
+                    Ok(None) => return Ok(true),
+                    Err(e) => {
+                        record_failure();
+                        self.record_error_kind(e.kind.into()).await;
+                        return Err(format!("The connection was lost while downloading, error : {}", e));
+                    },
+                };
+
+                let waiting = Instant::now();
+                if sender.send(chunk).await.is_err() {
+                    return Ok(false);
+                }
+                counters.add_backpressure(waiting.elapsed());
+            }
+        };
+
+        let sink = async {
+            let mut receiver = receiver;
+            let mut last_tick = Instant::now();
+            loop {
+                let waiting = Instant::now();
+                let Some(chunk) = receiver.recv().await else { break };
+                counters.add_network_wait(waiting.elapsed());
+
+                // Wait for the buffered bytes to fit in the memory budget, the parts writing them to disk hold few of them
+                if let Some(budget) = memory_budget.as_ref().filter(|_| buffers.is_none()) {
+                    let buffered = progress.iter().map(|counters| counters.downloaded_bytes.load(Ordering::Relaxed)).sum::<u64>() + chunk.len() as u64;
+                    let content_length = self.inner.lock().await.get_headers_info.as_ref().and_then(|headers_info| headers_info.content_length);
+                    tokio::select! {
+                        result = reserve_memory(&memory, budget, buffered.max(content_length.unwrap_or(0))) => result?,
+                        _ = status.wait_for(|status| *status != DownloadStatus::Downloading) => return Ok(false),
+                    }
+                }
+                if expected_bytes.is_some_and(|expected| received_bytes + chunk.len() as u64 > expected) {
+                    return Err(String::from("The server sent more bytes than the requested range"));
+                }
+
+                if let Some(audit_log) = audit_log.as_ref() {
+                    audit_log.record(AuditRecord::Chunk { 
+                        part: part_num, 
+                        offset: start_byte + received_bytes, 
+                        length: chunk.len(), 
+                        sha256: sha256_hex(&chunk) 
+                    })?;
+                }
+
+                buffer.extend_from_slice(&chunk);
+                received_bytes += chunk.len() as u64;
+                if segment_hashing {
+                    hash_segments(buffer, digests, false);
+                }
+                // A part hashing its bytes doesn't wait for another one, the bytes are hashed later on
+                if let Ok(mut hasher) = stream_hash.try_lock() {
+                    hasher.update(part_start + *flushed, buffer, MAX_HASH_STEP);
+                }
+                if let Some(buffers) = buffers.filter(|_| buffer.len() >= BUFFER_FLUSH_SIZE) {
+                    flush_part(buffers, part_start, flushed, buffer, digests).await?;
+                }
+
+                // Add the number of downloaded chunks to track progress
+                counters.add_chunk(chunk.len() as u64);
+                if let Some(data_usage) = data_usage.as_ref() {
+                    data_usage.add(chunk.len() as u64);
+                }
+
+                // Wait for the chunk to fit in the speed limits, the next chunk is taken as much later
+                for limit in speed_limit.iter().chain(global_speed_limit.iter()) {
+                    limit.acquire(chunk.len() as u64).await;
+                }
+
+                // Update progress bar if present, at most once per progress tick
+                if let Some(progress_bar) = progress_bar.as_ref().filter(|_| last_tick.elapsed() >= progress_interval) {
+                    last_tick = Instant::now();
+                    let downloading_speed : f64 = progress.iter().map(PartCounters::smoothed_speed).sum();
+                    progress_bar.set_position(progress.iter().map(|counters| counters.downloaded_bytes.load(Ordering::Relaxed)).sum());
+                    progress_bar.set_message(&format!(
+                        "{:.2} MB/s",
+                        downloading_speed / 1_000_000.0
+                    ));
+                }
+
+                // The last running part stops, its remaining bytes are split with the idle connections
+                if counters.split.load(Ordering::Relaxed) {
+                    return Ok(false);
+                }
+
+                // A download holding more than its share of the host's connections lets the others catch up,
+                // the whole file can't be requested again from an offset so those parts keep their connection
+                if !whole_file && permit.as_mut().is_some_and(ConnectionPermit::yield_to_waiters) {
+                    return Err(String::from(CONNECTION_YIELDED));
+                }
+            }
+            Ok(true)
+        };
+
+        // The sink stopping first stops the network side, so its result comes first. Otherwise the
+        // sink stores the queued chunks before the error of the network side is returned
+        let (network, sink) = tokio::join!(network, sink);
+        if !sink? || !network? {
+            return Ok(false);
+        }
+
+        // The part is requested again from the bytes it received
+        if let Some(expected) = expected_bytes.filter(|expected| received_bytes < *expected) {
+            return Err(format!("The response ended after {} of the {} bytes of the range", received_bytes, expected));
+        }
+        if segment_hashing {
+            hash_segments(buffer, digests, true);
+        }
+
+        if let Some(audit_log) = audit_log.as_ref() {
+            // The bytes on disk are read back, the audit log covers the whole part
+            let mut bytes = match buffers.filter(|_| *flushed > 0) {
+                Some(buffers) => buffers.read(part_start, *flushed).map_err(|e| format!("Couldn't read the bytes of the part back, error : {}", e))?,
+                None => Vec::new(),
+            };
+            bytes.extend_from_slice(buffer);
+            audit_log.record(AuditRecord::PartDone { 
+                part: part_num, 
+                bytes: bytes.len() as u64, 
+                sha256: sha256_hex(&bytes) 
+            })?;
+        }
+
+        Ok(true)
+    }
+}
+
+/// Writes the bytes a part holds in memory to its buffer on disk, after the `flushed` ones.
+/// The digests of the buffer's segments are dropped along with it, see `set_buffering()`.
+async fn flush_part(buffers: &PartBuffers, part_start: u64, flushed: &mut u64, buffer: &mut BytesMut, digests: &mut Vec<String>) -> Result<(), String> {
+    if buffer.is_empty() {
+        return Ok(());
+    }
+    let (part_buffers, offset, bytes) = (buffers.clone(), *flushed, Bytes::copy_from_slice(buffer));
+    task::spawn_blocking(move || part_buffers.write(part_start, offset, &bytes)).await
+        .map_err(|e| e.to_string())
+        .and_then(|written| written.map_err(|e| e.to_string()))
+        .map_err(|e| format!("Couldn't write the received bytes to {}, error : {}", buffers.path(part_start).display(), e))?;
+    *flushed += buffer.len() as u64;
+    buffer.clear();
+    digests.clear();
+    Ok(())
+}
+
+/// Extracts the file name from a `Content-Disposition` header value,
+/// e.g. `report.pdf` from `attachment; filename="report.pdf"`.
+///
+/// # Arguments
+///
+/// * `value` - The value of the header.
+///
+/// # Returns
+///
+/// * `Option<&str>` - The file name as sent by the server (not sanitized), or `None` if there's no `filename` parameter.
+pub fn content_disposition_file_name(value: &str) -> Option<&str> {
+    value
+        .split(';')
+        .find_map(|part| part.trim().strip_prefix("filename="))
+        .map(|filename| filename.trim_matches('"').trim_matches('\''))
+}
+
+/// Extracts the information of the file from the headers of a response. It only depends on its
+/// arguments, so malformed values sent by servers can be tested and fuzzed on their own.
+///
+/// # Arguments
+///
+/// * `status` - The status of the response, the length of a `206 Partial Content` is taken from its `Content-Range`.
+/// * `url` - The URL of the response, after redirects, the file is named from its path without `Content-Disposition`.
+/// * `headers` - The headers of the response.
+///
+/// # Returns
+///
+/// * A `Result` containing the extracted `ResponseHeaderInfo` or an error message
+pub fn parse_response_headers(status: StatusCode, url: &Url, headers: &HeaderMap) -> Result<ResponseHeaderInfo, String> {
+    let mut res_headers_info= ResponseHeaderInfo::default();
+
+    // Content-Length 
+    if let Some(cl_value) = headers.get(CONTENT_LENGTH) {
+        let cl_string = cl_value.to_str().map_err(|e| format!("An error occurred while parsing the content-length: {}", e))?;
+        let content_bytes = cl_string.parse().map_err(|e| format!("Content-Length isn't a valid number, error : {}", e))?;
+        res_headers_info.content_length = Some(content_bytes);
+    }
+
+    // Content-Range, the Content-Length of a partial response is the length of the range only
+    if status == StatusCode::PARTIAL_CONTENT {
+        if let Some(cr_value) = headers.get(CONTENT_RANGE) {
+            let cr_string = cr_value.to_str().map_err(|e| format!("An error occurred while parsing the content-range: {}", e))?;
+            res_headers_info.content_length = cr_string.parse::<ContentRange>()?.total;
+        }
+    }
+
+    // Accept-Ranges
+    if let Some(ar_value) = headers.get(ACCEPT_RANGES) {
+        let ar_string = ar_value.to_str().map_err(|e| format!("An error occurred while parsing the header value: {}", e))?;
+        if ar_string.contains("bytes") {
+            res_headers_info.support_partial = SupportPartialRequest::Yes;
+        } else {
+            res_headers_info.support_partial = SupportPartialRequest::No;
+        }
+    }
+
+    // Content-Type
+    if let Some (ct_value) = headers.get(CONTENT_TYPE){
+        let content_type = ct_value
+        .to_str()
+        .map_err(|err| format!("Cannot convert content-disposition header value to string, err: {}", err))?;
+        res_headers_info.content_type = Some(content_type.to_string());
+    }
+
+    // Content-Encoding, the length of a compressed response isn't the one of the file and can't be split into ranges
+    res_headers_info.content_encoding = headers.get(CONTENT_ENCODING)
+        .and_then(|v| v.to_str().ok())
+        .filter(|encoding| is_compressed(encoding))
+        .map(String::from);
+    if res_headers_info.content_encoding.is_some() {
+        res_headers_info.support_partial = SupportPartialRequest::No;
+    }
+
+    // Validators, used to detect a file changing between two range requests
+    res_headers_info.etag = headers.get(ETAG).and_then(|v| v.to_str().ok()).map(String::from);
+    res_headers_info.last_modified = headers.get(LAST_MODIFIED).and_then(|v| v.to_str().ok()).map(String::from);
+
+    // Content-Disposition
+    // 1. Using the content-disposition field
+    if let Some (cd_value) = headers.get(CONTENT_DISPOSITION){
+        let filename = cd_value
+            .to_str()
+            .map_err(|err| format!("Cannot convert content-disposition header value to string, err: {}", err))?;
+
+        let filename = content_disposition_file_name(filename)
+            .ok_or("Filename not found in content-disposition header.")?;
+
+        res_headers_info.file_name = Some(sanitize_file_name(filename));
+    }
+    // 2. Using the file path itself 
+    else if let Some(filename) = url.path_segments().and_then(|mut segments| segments.next_back()) {
+        res_headers_info.file_name = Some(sanitize_file_name(filename));
+    }
+    else {
+        // Default name in case the name cannot be detected
+        res_headers_info.file_name = Some(String::from(DEFAULT_FILE_NAME));
+    }
+
+    // A name without an extension, e.g. from `/download?id=42`, gets the one of the Content-Type
+    res_headers_info.file_name = res_headers_info.file_name.map(|file_name| with_type_extension(&file_name, res_headers_info.content_type.as_deref()));
+
+    Ok(res_headers_info)
+}
+
+/// Creates the terminal progress bar of a download.
+///
+/// # Arguments
+///
+/// * `content_length` - The length of the content in bytes.
+#[cfg(feature = "cli")]
+fn new_progress_bar(content_length: u64) -> Option<ProgressBar> {
+    let pb = ProgressBar::new(content_length);
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} | {msg} ({eta})")
+            .progress_chars("#>-"),
+    );
+    Some(pb)
+}
+
+/// Without the `cli` feature there is no terminal progress bar, `download(true)` behaves like `download(false)`.
+#[cfg(not(feature = "cli"))]
+fn new_progress_bar(_content_length: u64) -> Option<ProgressBar> {
+    None
+}
+
+/// Stands in for `indicatif::ProgressBar` without the `cli` feature, it can't be constructed.
+#[cfg(not(feature = "cli"))]
+#[derive(Debug, Clone)]
+enum ProgressBar {}
+
+#[cfg(not(feature = "cli"))]
+impl ProgressBar {
+    fn set_position(&self, _position: u64) {
+        match *self {}
+    }
+
+    fn set_message(&self, _message: &str) {
+        match *self {}
+    }
+
+    fn finish_and_clear(&self) {
+        match *self {}
+    }
+}
+
+/// Picks the number of parts to split a download into based on its content length.
+/// Small files use a single connection, larger ones use one connection per
+/// `MIN_PART_SIZE` bytes, capped at `max_parallel_connections`.
+///
+/// # Arguments
+///
+/// * `content_length` - The length of the content in bytes.
+/// * `max_parallel_connections` - The maximum number of parallel connections allowed.
+///
+/// # Returns
+///
+/// * `u64` - The number of parts to download in parallel (at least 1).
+pub fn adaptive_num_parts(content_length: u64, max_parallel_connections: u8) -> u64 {
+    (content_length / MIN_PART_SIZE).clamp(1, max_parallel_connections.max(1) as u64)
+}
+
+/// Splits a file into the byte ranges of its parts: at most `num_parts` consecutive ranges
+/// covering every byte once, the first ones being a byte longer when the length doesn't divide
+/// evenly. A file shorter than `num_parts` bytes gets a part per byte.
+///
+/// # Arguments
+///
+/// * `content_length` - The length of the content in bytes, `0` if it's unknown.
+/// * `num_parts` - The number of parts to split it into.
+///
+/// # Returns
+///
+/// * `Vec<ByteRange>` - The inclusive ranges of the parts, a single `0-0` range if the length is `0`
+///   (the whole file is then requested without a range).
+pub fn plan_ranges(content_length: u64, num_parts: u64) -> Vec<ByteRange> {
+    let num_parts = num_parts.clamp(1, content_length.max(1));
+    let (size, longer) = (content_length / num_parts, content_length % num_parts);
+
+    let mut start = 0;
+    (0..num_parts).map(|part| {
+        let len = (size + (part < longer) as u64).max(1);
+        let range = ByteRange { start, end: start + len - 1 };
+        start += len;
+        range
+    }).collect()
+}
+
+/// Estimates the time needed to download the rest of the content at the current smoothed speed.
+///
+/// # Arguments
+///
+/// * `content_length` - The length of the content in bytes.
+/// * `progress` - The progress counters of the parts.
+///
+/// # Returns
+///
+/// * `Option<Duration>` - The remaining time, or `None` if nothing is being received.
+fn estimate_eta(content_length: u64, progress: &[PartCounters]) -> Option<Duration> {
+    let downloaded: u64 = progress.iter().map(|counters| counters.downloaded_bytes.load(Ordering::Relaxed)).sum();
+    let speed: f64 = progress.iter().map(PartCounters::smoothed_speed).sum();
+    if speed <= 0.0 {
+        return None;
+    }
+    Some(Duration::from_secs_f64(content_length.saturating_sub(downloaded) as f64 / speed))
+}
+
+/// Sends the probe request of an init strategy.
+///
+/// # Arguments
+///
+/// * `transport` - The transport to send the request with.
+/// * `url` - The URL of the download.
+/// * `strategy` - The strategy deciding which request to send.
+///
+/// # Returns
+///
+/// * `Result<TransportResponse, (ErrorKind, String)>` - The response if it was successful, or the kind of the error and its message.
+async fn probe(transport: &dyn Transport, url: &ValidUrl, strategy: InitStrategy) -> Result<TransportResponse, (ErrorKind, String)> {
+    let response = probe_request(transport, url, strategy).await.map_err(|e| (ErrorKind::from(e.kind), e.to_string()))?;
+
+    if !response.status.is_success() {
+        return Err((ErrorKind::HttpStatus(response.status.as_u16()), format!("got status code : {}", response.status.as_str())));
+    }
+
+    Ok(response)
+}
+
+/// Waits for the stall timeout of a part, forever if it's `None`.
+async fn stall_timer(stall_timeout: Option<Duration>) {
+    match stall_timeout {
+        Some(stall_timeout) => tokio::time::sleep(stall_timeout).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Sends the request of an init strategy, returning its response whatever its status.
+async fn probe_request(transport: &dyn Transport, url: &ValidUrl, strategy: InitStrategy) -> Result<TransportResponse, TransportError> {
+    let timeout = Some(Duration::from_secs(3));
+    let request = match strategy {
+        InitStrategy::Head => transport.head(&url.0, timeout),
+        InitStrategy::RangedGet => transport.get_range(&url.0, Some(ByteRange { start: 0, end: 0 }), None, timeout),
+        // The body isn't read, it's discarded once the response is dropped
+        InitStrategy::Get => transport.get_range(&url.0, None, None, timeout),
+    };
+    request.await
+}
+
+/// Returns the init strategies in the order they're tried, starting with the one known to work
+/// for the host of `url`, if any.
+fn init_strategies(connection_manager: Option<&Arc<ConnectionManager>>, url: &ValidUrl) -> Vec<InitStrategy> {
+    let mut strategies = InitStrategy::ALL.to_vec();
+    if let Some(known) = connection_manager.zip(url.host()).and_then(|(manager, host)| manager.init_strategy(host)) {
+        strategies.retain(|strategy| *strategy != known);
+        strategies.insert(0, known);
+    }
+    strategies
+}
+
+/// Returns the total length of the file if `response` is the `206 Partial Content` answer to a
+/// request for the first byte, i.e. the server accepts ranges.
+fn first_byte_total(response: &TransportResponse) -> Option<u64> {
+    if response.status != StatusCode::PARTIAL_CONTENT {
+        return None;
+    }
+    let content_range = response.headers.get(CONTENT_RANGE)?.to_str().ok()?.parse::<ContentRange>().ok()?;
+    match content_range {
+        ContentRange { start: 0, end: 0, total: Some(total) } => Some(total),
+        _ => None,
+    }
+}
+
+/// Checks the `Content-Range` of a `206 Partial Content` answer against the requested range.
+/// The server may only shorten the range at the end of the file.
+///
+/// # Arguments
+///
+/// * `response` - The response to the range request.
+/// * `requested` - The requested range.
+/// * `content_length` - The known length of the file, if any.
+///
+/// Returns the number of bytes the response body must contain, or an error if the server sent
+/// another range than the requested one.
+fn check_content_range(response: &TransportResponse, requested: ByteRange, content_length: Option<u64>) -> Result<u64, String> {
+    let value = response.headers.get(CONTENT_RANGE)
+        .ok_or_else(|| format!("The server sent no Content-Range for the range {}", requested))?
+        .to_str().map_err(|_| String::from("The server sent an invalid Content-Range"))?;
+    let content_range = value.parse::<ContentRange>()?;
+
+    let total = content_range.total.or(content_length);
+    let expected_end = total.map_or(requested.end, |total| requested.end.min(total.saturating_sub(1)));
+    let total_matches = content_range.total.is_none_or(|total| content_length.is_none_or(|length| length == total));
+    if content_range.start != requested.start || content_range.end != expected_end || !total_matches {
+        return Err(format!("The server sent the range {} instead of {}", value, requested));
+    }
+    Ok(content_range.end - content_range.start + 1)
+}
+
+/// Grows the memory a download holds from `budget` to `bytes`. The whole file is reserved with
+/// its first chunk when its length is known, so downloads waiting for memory don't hold any.
+///
+/// # Arguments
+///
+/// * `memory` - The reservation of the download, replaced if it's from another budget.
+/// * `budget` - The budget of the connection manager.
+/// * `bytes` - The number of bytes the download buffers.
+async fn reserve_memory(memory: &Mutex<Option<MemoryReservation>>, budget: &Arc<MemoryBudget>, bytes: u64) -> Result<(), String> {
+    let mut memory = memory.lock().await;
+    let reservation = match memory.as_mut() {
+        Some(reservation) if reservation.is_from(budget) => reservation,
+        _ => memory.insert(budget.reservation()),
+    };
+    reservation.grow_to(bytes).await
+}
+
+/// Waits for a connection slot to the host of `url` when a connection manager is set.
+///
+/// # Arguments
+///
+/// * `connection_manager` - The optional shared `ConnectionManager`.
+/// * `url` - The URL the connection will be opened to.
+/// * `download` - The key of the download opening the connection, see `RustleDownloader::connection_key`.
+///
+/// # Returns
+///
+/// * `Result<Option<ConnectionPermit>, String>` - The permit to hold while the connection is open,
+///   or `None` if no connection manager is set.
+async fn acquire_connection(connection_manager: Option<&Arc<ConnectionManager>>, url: &ValidUrl, download: usize) -> Result<Option<ConnectionPermit>, String> {
+    match (connection_manager, url.host()) {
+        (Some(manager), Some(host)) => Ok(Some(manager.acquire(host, download).await?)),
+        _ => Ok(None),
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+    use bytes::Bytes;
+    use futures::future::BoxFuture;
+    use futures::stream::{self, StreamExt};
+    use reqwest::header::{HeaderName, HeaderValue};
+    use super::super::client::ClientOptions;
+    use super::super::protocol::Protocol;
+    use super::super::transport::{TransportError, TransportErrorKind};
+    use super::super::verify::VerifyIssue;
+    use super::super::usage::{DataQuota, QuotaPeriod};
+
+    /// Header parsing cases, the format is described at the top of the file.
+    const HEADER_CASES: &str = include_str!("../tests/fixtures/header_cases.txt");
+
+    /// A response from the fixtures file and the header info expected from it.
+    struct HeaderCase {
+        name: String,
+        url: String,
+        status: u16,
+        headers: Vec<(String, String)>,
+        expected: Result<ResponseHeaderInfo, String>,
+    }
+
+    fn parse_header_cases(source: &str) -> Vec<HeaderCase> {
+        let mut cases = Vec::new();
+
+        for block in source.split("\n## ").skip(1) {
+            let mut lines = block.lines();
+            let name = lines.next().unwrap().trim().to_string();
+            let mut lines = lines.map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#'));
+
+            let url = lines.next().unwrap_or_else(|| panic!("{}: missing url", name)).to_string();
+            let status = lines.next().and_then(|status| status.parse().ok()).unwrap_or_else(|| panic!("{}: missing status", name));
+
+            let headers = lines.by_ref()
+                .take_while(|line| *line != "--")
+                .map(|line| {
+                    let (key, value) = line.split_once(':').unwrap_or_else(|| panic!("{}: invalid header {}", name, line));
+                    (key.trim().to_string(), value.trim().to_string())
+                })
+                .collect();
+
+            let mut info = ResponseHeaderInfo::default();
+            let mut error = None;
+            for line in lines {
+                let (key, value) = line.split_once(':').unwrap_or_else(|| panic!("{}: invalid expectation {}", name, line));
+                let value = value.trim();
+                match key.trim() {
+                    "file_name" => info.file_name = Some(value.to_string()),
+                    "content_length" => info.content_length = Some(value.parse().unwrap()),
+                    "content_type" => info.content_type = Some(value.to_string()),
+                    "etag" => info.etag = Some(value.to_string()),
+                    "last_modified" => info.last_modified = Some(value.to_string()),
+                    "content_encoding" => info.content_encoding = Some(value.to_string()),
+                    "support_partial" => info.support_partial = match value {
+                        "Yes" => SupportPartialRequest::Yes,
+                        "No" => SupportPartialRequest::No,
+                        _ => SupportPartialRequest::Unknown,
+                    },
+                    "error" => error = Some(value.to_string()),
+                    key => panic!("{}: unknown expectation {}", name, key),
+                }
+            }
+
+            cases.push(HeaderCase { name, url, status, headers, expected: error.map_or(Ok(info), Err) });
+        }
+
+        cases
+    }
+
+    #[test]
+    fn parse_response_headers_matches_fixtures() {
+        let cases = parse_header_cases(HEADER_CASES);
+        assert!(!cases.is_empty());
+
+        let mut failures = Vec::new();
+        for case in cases {
+            let mut headers = HeaderMap::new();
+            for (key, value) in &case.headers {
+                headers.append(HeaderName::from_bytes(key.as_bytes()).unwrap(), HeaderValue::from_str(value).unwrap());
+            }
+            let status = StatusCode::from_u16(case.status).unwrap();
+            let result = parse_response_headers(status, &Url::parse(&case.url).unwrap(), &headers);
+            let matches = match (&case.expected, &result) {
+                (Ok(expected), Ok(info)) => expected == info,
+                (Err(expected), Err(e)) => e.contains(expected.as_str()),
+                _ => false,
+            };
+            if !matches {
+                failures.push(format!("{}: expected {:?}, got {:?}", case.name, case.expected, result));
+            }
+        }
+
+        assert!(failures.is_empty(), "{}", failures.join("\n"));
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn planned_ranges_cover_each_byte_once(content_length in 1u64..=1 << 40, num_parts in 0u64..=64) {
+            let ranges = plan_ranges(content_length, num_parts);
+            proptest::prop_assert_eq!(ranges.len() as u64, num_parts.clamp(1, content_length));
+            proptest::prop_assert_eq!(ranges[0].start, 0);
+            proptest::prop_assert_eq!(ranges.last().unwrap().end, content_length - 1);
+            for pair in ranges.windows(2) {
+                proptest::prop_assert_eq!(pair[1].start, pair[0].end + 1);
+            }
+            // The parts are as even as possible
+            let lens: Vec<u64> = ranges.iter().map(|range| range.end - range.start + 1).collect();
+            proptest::prop_assert!(lens.iter().max().unwrap() - lens.iter().min().unwrap() <= 1);
+        }
+
+        #[test]
+        fn small_files_get_a_part_per_byte(content_length in 1u64..=64, num_parts in 1u64..=64) {
+            let ranges = plan_ranges(content_length, num_parts);
+            proptest::prop_assert!(ranges.iter().all(|range| range.start <= range.end && range.end < content_length));
+            proptest::prop_assert_eq!(ranges.iter().map(|range| range.end - range.start + 1).sum::<u64>(), content_length);
+        }
+    }
+
+    #[test]
+    fn unknown_length_is_a_single_range() {
+        assert_eq!(plan_ranges(0, 8), vec![ByteRange { start: 0, end: 0 }]);
+    }
+
+    #[test]
+    fn parts_follow_the_content_length() {
+        // Below a part's worth of bytes, and unknown lengths, use a single connection
+        assert_eq!(adaptive_num_parts(0, 8), 1);
+        assert_eq!(adaptive_num_parts(MIN_PART_SIZE - 1, 8), 1);
+        assert_eq!(adaptive_num_parts(3 * MIN_PART_SIZE + 1, 8), 3);
+        // Clamped to the maximum, a maximum of 0 still downloads over one connection
+        assert_eq!(adaptive_num_parts(100 * MIN_PART_SIZE, 8), 8);
+        assert_eq!(adaptive_num_parts(100 * MIN_PART_SIZE, 0), 1);
+        assert_eq!(adaptive_num_parts(u64::MAX, u8::MAX), u8::MAX as u64);
+    }
+
+    /// MockTransport serves a file from memory, honoring ranges like an HTTP server.
+    /// The queue manager tests drive their downloads with it too.
+    #[derive(Debug, Default)]
+    pub(crate) struct MockTransport {
+        pub(crate) data: Bytes,                             // Content of the file
+        chunk_size: usize,                                  // Size of the body chunks
+        pub(crate) chunk_delay: Duration,                   // Delay before each body chunk
+        pub(crate) failures: AtomicUsize,                   // Number of range requests failing with a connection error before the next ones succeed
+        ranges: std::sync::Mutex<Vec<ByteRange>>,           // Ranges requested by the parts
+        pub(crate) expired: std::sync::Mutex<Option<String>>,   // URL whose range requests are answered with `403 Forbidden`
+        expire_head: bool,                                  // Whether the HEAD requests of the expired URL are answered with `403 Forbidden` too
+        ignore_ranges: bool,                                // Whether the whole file is sent with `200 OK` instead of the ranges
+        hide_accept_ranges: bool,                           // Whether `Accept-Ranges` is left out of the responses
+        shifted_ranges: AtomicUsize,                        // Number of range requests answered with the range starting a byte later
+        stalls: AtomicUsize,                                // Number of range requests whose body hangs without sending anything
+        etag: std::sync::Mutex<Option<String>>,             // `ETag` sent with the responses
+        slow_part: Option<(u64, Duration)>,                 // Start of the ranges sent slower, and the delay before each of their chunks
+        content_type: Option<&'static str>,                 // `Content-Type` sent with the responses
+        in_flight: Arc<AtomicUsize>,                        // Number of range requests whose body is being sent
+        concurrency: std::sync::Mutex<Vec<usize>>,          // Number of range requests in flight when each one started, itself included
+    }
+
+    /// Counts a range request as in flight until its body is dropped.
+    struct InFlight(Arc<AtomicUsize>);
+
+    impl Drop for InFlight {
+        fn drop(&mut self) {
+            self.0.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+
+    impl MockTransport {
+        pub(crate) fn new(len: usize) -> Self {
+            MockTransport {
+                data: (0..len).map(|i| (i % 251) as u8).collect(),
+                chunk_size: 16 * 1024,
+                ..Default::default()
+            }
+        }
+
+        fn headers(&self) -> HeaderMap {
+            let mut headers = HeaderMap::new();
+            headers.insert(CONTENT_LENGTH, HeaderValue::from(self.data.len()));
+            if !self.hide_accept_ranges {
+                headers.insert(ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+            }
+            if let Some(etag) = self.etag.lock().unwrap().as_deref() {
+                headers.insert(ETAG, HeaderValue::from_str(etag).unwrap());
+            }
+            if let Some(content_type) = self.content_type {
+                headers.insert(CONTENT_TYPE, HeaderValue::from_static(content_type));
+            }
+            headers
+        }
+    }
+
+    impl Transport for MockTransport {
+        fn head(&self, url: &Url, _timeout: Option<Duration>) -> BoxFuture<'static, Result<TransportResponse, TransportError>> {
+            let expired = self.expire_head && self.expired.lock().unwrap().as_deref() == Some(url.as_str());
+            let status = if expired { StatusCode::FORBIDDEN } else { StatusCode::OK };
+            let response = TransportResponse::new(status, url.clone(), self.headers(), stream::empty());
+            Box::pin(async move { Ok(response) })
+        }
+
+        fn get_range(&self, url: &Url, range: Option<ByteRange>, _if_range: Option<&str>, _timeout: Option<Duration>) -> BoxFuture<'static, Result<TransportResponse, TransportError>> {
+            if self.failures.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |failures| failures.checked_sub(1)).is_ok() {
+                return Box::pin(async { Err(TransportError::new(TransportErrorKind::ConnectionRefused, "connection refused")) });
+            }
+
+            if self.expired.lock().unwrap().as_deref() == Some(url.as_str()) {
+                let response = TransportResponse::new(StatusCode::FORBIDDEN, url.clone(), HeaderMap::new(), stream::empty());
+                return Box::pin(async move { Ok(response) });
+            }
+
+            let range = range.filter(|_| !self.ignore_ranges);
+            let status = if range.is_some() { StatusCode::PARTIAL_CONTENT } else { StatusCode::OK };
+            let range = range.unwrap_or(ByteRange { start: 0, end: u64::MAX });
+            self.ranges.lock().unwrap().push(range);
+            let shifted = status == StatusCode::PARTIAL_CONTENT
+                && self.shifted_ranges.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |shifted| shifted.checked_sub(1)).is_ok();
+            let range = ByteRange { start: range.start + shifted as u64, ..range };
+            // Like HTTP servers, the end of the range is clamped to the end of the file
+            let end = range.end.min(self.data.len() as u64 - 1);
+            let mut headers = self.headers();
+            if status == StatusCode::PARTIAL_CONTENT {
+                headers.insert(CONTENT_LENGTH, HeaderValue::from(end + 1 - range.start));
+                headers.insert(CONTENT_RANGE, HeaderValue::from_str(&format!("bytes {}-{}/{}", range.start, end, self.data.len())).unwrap());
+            }
+
+            let body = self.data.slice(range.start as usize..=end as usize);
+            let chunks: Vec<_> = (0..body.len()).step_by(self.chunk_size).map(|i| body.slice(i..(i + self.chunk_size).min(body.len()))).collect();
+            let delay = match self.slow_part {
+                Some((start, delay)) if start == range.start => delay,
+                _ => self.chunk_delay,
+            };
+            let stalled = self.stalls.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |stalls| stalls.checked_sub(1)).is_ok();
+            let chunks = if stalled { Vec::new() } else { chunks };
+            let in_flight = self.in_flight.fetch_add(1, Ordering::Relaxed) + 1;
+            self.concurrency.lock().unwrap().push(in_flight);
+            let guard = InFlight(self.in_flight.clone());
+            let body = stream::iter(chunks).then(move |chunk| {
+                let _ = &guard;
+                async move {
+                    tokio::time::sleep(delay).await;
+                    Ok(chunk)
+                }
+            });
+            let body = match stalled {
+                true => body.chain(stream::pending()).boxed(),
+                false => body.boxed(),
+            };
+            let response = TransportResponse::new(status, url.clone(), headers, body);
+            Box::pin(async move { Ok(response) })
+        }
+    }
+
+    /// Creates an initialized download of `transport`'s file into a new temporary directory.
+    pub(crate) async fn mock_download(transport: &Arc<MockTransport>, connections: u8, name: &str) -> (RustleDownloader, PathBuf) {
+        let out_dir = std::env::temp_dir().join(format!("rustle-test-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&out_dir);
+        let engine = RustleDownloader::builder()
+            .url("http://mock.test/file.bin")
+            .out_dir(&out_dir)
+            .connections(connections)
+            .retry_policy(RetryPolicy { max_retries: 2, initial_delay: Duration::from_millis(10), max_delay: Duration::from_millis(10) })
+            .transport(transport.clone())
+            .build()
+            .await
+            .unwrap();
+        (engine, out_dir)
+    }
+
+    #[tokio::test]
+    async fn mock_download_requests_each_byte_once() {
+        let transport = Arc::new(MockTransport::new(4 * 1024 * 1024));
+        let (engine, out_dir) = mock_download(&transport, 4, "ranges").await;
+
+        assert_eq!(engine.download(false).await, Ok(true));
+        assert_eq!(std::fs::read(out_dir.join("file.bin")).unwrap(), transport.data);
+
+        let mut ranges = transport.ranges.lock().unwrap().clone();
+        ranges.sort_by_key(|range| range.start);
+        assert_eq!(ranges.len(), 4);
+        assert_eq!(ranges[0].start, 0);
+        for pair in ranges.windows(2) {
+            assert_eq!(pair[1].start, pair[0].end + 1);
+        }
+        std::fs::remove_dir_all(out_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn mock_download_restarts_stalled_parts() {
+        let transport = Arc::new(MockTransport::new(1024 * 1024));
+        transport.stalls.store(2, Ordering::Relaxed);
+        let (mut engine, out_dir) = mock_download(&transport, 1, "stall").await;
+        engine.set_stall_timeout(Some(Duration::from_millis(50))).await;
+
+        assert_eq!(engine.download(false).await, Ok(true));
+        assert_eq!(std::fs::read(out_dir.join("file.bin")).unwrap(), transport.data);
+        assert_eq!(transport.ranges.lock().unwrap().len(), 3);
+        assert_eq!(engine.get_timeline().iter().filter(|entry| matches!(entry.event, TimelineEvent::PartStalled { part: 0 })).count(), 2);
+        std::fs::remove_dir_all(&out_dir).unwrap();
+
+        // A part stalling without receiving anything fails once it stalled too many times in a row
+        let transport = Arc::new(MockTransport::new(1024 * 1024));
+        transport.stalls.store(usize::MAX, Ordering::Relaxed);
+        let (mut engine, out_dir) = mock_download(&transport, 1, "stall-fail").await;
+        engine.set_stall_timeout(Some(Duration::from_millis(20))).await;
+        engine.set_retry_policy(RetryPolicy { max_retries: 0, ..Default::default() }).await;
+
+        assert!(engine.download(false).await.unwrap_err().contains("stalled 4 times"));
+        assert_eq!(engine.get_error_kind().await, Some(ErrorKind::Timeout));
+        assert_eq!(transport.ranges.lock().unwrap().len(), 4);
+        assert!(!out_dir.join("file.bin").exists());
+    }
+
+    #[tokio::test]
+    async fn mock_download_snapshot_is_serializable() {
+        let transport = Arc::new(MockTransport::new(1024 * 1024));
+        let (engine, out_dir) = mock_download(&transport, 2, "snapshot").await;
+        assert_eq!(engine.download(false).await, Ok(true));
+
+        let snapshot = serde_json::to_value(engine.snapshot().await).unwrap();
+        assert_eq!(snapshot["url"], "http://mock.test/file.bin");
+        assert_eq!(snapshot["status"], "done");
+        assert_eq!(snapshot["file_info"]["support_partial"], "yes");
+        assert_eq!(snapshot["downloaded_bytes"], 1024 * 1024);
+        assert_eq!(snapshot["parts"].as_array().unwrap().len(), 2);
+        assert!(snapshot["eta"].is_null());
+
+        let snapshot: DownloadSnapshot = serde_json::from_value(snapshot).unwrap();
+        assert_eq!(snapshot.file_path, Some(out_dir.join("file.bin")));
+        std::fs::remove_dir_all(out_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn init_waiting_for_a_connection_can_be_cancelled() {
+        let transport = Arc::new(MockTransport::new(64 * 1024));
+        let (mut engine, out_dir) = mock_download(&transport, 1, "init-cancel").await;
+        let manager = Arc::new(ConnectionManager::new(1).unwrap());
+        engine.set_connection_manager(manager.clone()).await;
+        let held = manager.acquire("mock.test", usize::MAX).await.unwrap();
+
+        // The host is saturated, init waits for a connection without holding the engine's lock
+        let mut initializing = engine.clone();
+        let init = tokio::spawn(async move { initializing.init().await });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!init.is_finished());
+        let status = tokio::time::timeout(Duration::from_millis(100), engine.get_status()).await;
+        assert_eq!(status, Ok(DownloadStatus::Idle));
+
+        tokio::time::timeout(Duration::from_millis(100), engine.cancel(false)).await.unwrap();
+        let init = tokio::time::timeout(Duration::from_millis(100), init).await.unwrap().unwrap();
+        assert!(init.unwrap_err().contains("cancelled"));
+        assert_eq!(manager.active_connections("mock.test"), 1);
+        drop(held);
+        let _ = std::fs::remove_dir_all(out_dir);
+    }
+
+    #[tokio::test]
+    async fn mock_download_is_retried() {
+        let transport = Arc::new(MockTransport::new(1024 * 1024));
+        transport.failures.store(2, Ordering::Relaxed);
+        let (engine, out_dir) = mock_download(&transport, 1, "retry").await;
+
+        assert_eq!(engine.download(false).await, Ok(true));
+        assert_eq!(std::fs::read(out_dir.join("file.bin")).unwrap(), transport.data);
+        std::fs::remove_dir_all(out_dir).unwrap();
+
+        // One more failure than the policy allows
+        let transport = Arc::new(MockTransport::new(1024 * 1024));
+        transport.failures.store(3, Ordering::Relaxed);
+        let (engine, out_dir) = mock_download(&transport, 1, "give-up").await;
+
+        assert!(engine.download(false).await.unwrap_err().contains("connection refused"));
+        assert_eq!(engine.get_status().await, DownloadStatus::Error);
+        let events = engine.get_timeline();
+        assert_eq!(events.iter().filter(|entry| matches!(entry.event, TimelineEvent::Retrying { .. })).count(), 2);
+        assert!(matches!(&events.last().unwrap().event, TimelineEvent::Failed { error, kind: ErrorKind::ConnectionRefused } if error.contains("connection refused")));
+        assert_eq!(engine.get_error_kind().await, Some(ErrorKind::ConnectionRefused));
+        assert!(!out_dir.join("file.bin").exists());
+    }
+
+    #[tokio::test]
+    async fn mock_downloads_share_the_memory_budget() {
+        let manager = Arc::new(ConnectionManager::new(8).unwrap());
+        manager.set_memory_budget(Some(6 * 1024 * 1024)).unwrap();
+        let budget = manager.memory_budget().unwrap();
+        let mut transport = MockTransport::new(4 * 1024 * 1024);
+        transport.chunk_delay = Duration::from_millis(1);
+        let transport = Arc::new(transport);
+
+        // The second download waits for the first one to give its memory back
+        let (mut first, first_dir) = mock_download(&transport, 4, "memory-first").await;
+        let (mut second, second_dir) = mock_download(&transport, 4, "memory-second").await;
+        first.set_connection_manager(manager.clone()).await;
+        second.set_connection_manager(manager.clone()).await;
+        let (first_result, second_result) = tokio::join!(first.download(false), second.download(false));
+        assert_eq!((first_result, second_result), (Ok(true), Ok(true)));
+        assert_eq!(std::fs::read(second_dir.join("file.bin")).unwrap(), transport.data);
+        assert_eq!(budget.available_bytes(), budget.max_bytes());
+        std::fs::remove_dir_all(first_dir).unwrap();
+        std::fs::remove_dir_all(second_dir).unwrap();
+
+        // A file larger than the budget can't be buffered
+        manager.set_memory_budget(Some(1024 * 1024)).unwrap();
+        let (mut engine, out_dir) = mock_download(&transport, 4, "memory-exceeded").await;
+        engine.set_connection_manager(manager).await;
+        engine.set_retry_policy(RetryPolicy { max_retries: 0, ..Default::default() }).await;
+        assert!(engine.download(false).await.unwrap_err().contains("memory budget"));
+        let _ = std::fs::remove_dir_all(out_dir);
+    }
+
+    #[tokio::test]
+    async fn mock_download_pauses_once_the_quota_is_reached() {
+        let manager = Arc::new(ConnectionManager::new(8).unwrap());
+        let data_usage = Arc::new(DataUsage::in_memory());
+        data_usage.set_quota(Some(DataQuota { bytes: 1024 * 1024, period: QuotaPeriod::Day }));
+        manager.set_data_usage(Some(data_usage.clone()));
+        let mut transport = MockTransport::new(4 * 1024 * 1024);
+        transport.chunk_delay = Duration::from_millis(1);
+        let transport = Arc::new(transport);
+        let (mut engine, out_dir) = mock_download(&transport, 2, "quota").await;
+        engine.set_connection_manager(manager.clone()).await;
+
+        let running = {
+            let engine = engine.clone();
+            task::spawn(async move { engine.download(false).await })
+        };
+        while !engine.is_paused_quota().await {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        assert_eq!(engine.get_status().await, DownloadStatus::Paused);
+        assert!(engine.get_timeline().iter().any(|entry| entry.event == TimelineEvent::QuotaReached));
+        let counted = data_usage.today();
+        assert!((1024 * 1024..2 * 1024 * 1024).contains(&counted), "{}", counted);
+
+        // Raising the quota resumes the download
+        data_usage.set_quota(Some(DataQuota { bytes: 8 * 1024 * 1024, period: QuotaPeriod::Day }));
+        assert_eq!(running.await.unwrap(), Ok(true));
+        assert_eq!(std::fs::read(out_dir.join("file.bin")).unwrap(), transport.data);
+        assert_eq!(data_usage.today(), transport.data.len() as u64);
+        std::fs::remove_dir_all(out_dir).unwrap();
+    }
+
+    /// Starts downloading `transport`'s file, pauses the download once it received bytes and waits
+    /// for longer than its stale time, `change` then changes the file on the server before it's resumed.
+    async fn pause_until_stale(engine: &RustleDownloader, change: impl FnOnce()) -> JoinHandle<Result<bool, String>> {
+        let running = {
+            let engine = engine.clone();
+            task::spawn(async move { engine.download(false).await })
+        };
+        while engine.get_progress_vec().await.iter().all(|part| part.downloaded_bytes == 0) {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        engine.pause().await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        change();
+        engine.resume().await;
+        running
+    }
+
+    #[tokio::test]
+    async fn mock_download_checks_the_file_again_after_a_long_pause() {
+        let mut transport = MockTransport::new(4 * 1024 * 1024);
+        transport.chunk_delay = Duration::from_millis(1);
+        transport.expire_head = true;
+        *transport.etag.lock().unwrap() = Some(String::from("\"v1\""));
+        let transport = Arc::new(transport);
+
+        // The file changed during the pause, it's downloaded again instead of continuing the ranges
+        let (mut engine, out_dir) = mock_download(&transport, 2, "stale-changed").await;
+        engine.set_stale_after(Some(Duration::from_millis(50))).await;
+        let running = pause_until_stale(&engine, || *transport.etag.lock().unwrap() = Some(String::from("\"v2\""))).await;
+        assert_eq!(running.await.unwrap(), Ok(true));
+        assert_eq!(std::fs::read(out_dir.join("file.bin")).unwrap(), transport.data);
+        assert_eq!(engine.get_resume_mismatch().await, Some(ResumeMismatch::Etag { before: String::from("\"v1\""), now: String::from("\"v2\"") }));
+        let timeline = engine.get_timeline();
+        assert!(timeline.iter().any(|entry| entry.event == TimelineEvent::Revalidating));
+        assert!(!timeline.iter().any(|entry| matches!(entry.event, TimelineEvent::Retrying { .. })));
+        std::fs::remove_dir_all(&out_dir).unwrap();
+
+        // The link expired during the pause, it's refreshed before the ranges are requested
+        let (mut engine, out_dir) = mock_download(&transport, 2, "stale-expired").await;
+        engine.set_stale_after(Some(Duration::from_millis(50))).await;
+        engine.set_url_refresher(Some(Arc::new(crate::refresh::RefreshFn(|expired: Url| async move {
+            Ok(format!("{}?signature=2", expired))
+        })))).await;
+        let running = pause_until_stale(&engine, || *transport.expired.lock().unwrap() = Some(String::from("http://mock.test/file.bin"))).await;
+        assert_eq!(running.await.unwrap(), Ok(true));
+        assert_eq!(std::fs::read(out_dir.join("file.bin")).unwrap(), transport.data);
+        assert_eq!(engine.get_url().await.as_deref(), Some("http://mock.test/file.bin?signature=2"));
+        assert_eq!(engine.get_resume_mismatch().await, None);
+        std::fs::remove_dir_all(&out_dir).unwrap();
+
+        // Without a refresher the download fails on the expired link
+        *transport.expired.lock().unwrap() = None;
+        let (mut engine, out_dir) = mock_download(&transport, 2, "stale-failed").await;
+        engine.set_stale_after(Some(Duration::from_millis(50))).await;
+        engine.set_retry_policy(RetryPolicy { max_retries: 0, ..Default::default() }).await;
+        let running = pause_until_stale(&engine, || *transport.expired.lock().unwrap() = Some(String::from("http://mock.test/file.bin"))).await;
+        assert!(running.await.unwrap().unwrap_err().contains("The link expired"));
+        let _ = std::fs::remove_dir_all(&out_dir);
+    }
+
+    #[tokio::test]
+    async fn mock_downloads_share_the_connections() {
+        let manager = Arc::new(ConnectionManager::new(2).unwrap());
+        let mut large = MockTransport::new(8 * 1024 * 1024);
+        large.chunk_delay = Duration::from_millis(1);
+        let large = Arc::new(large);
+        let mut small = MockTransport::new(512 * 1024);
+        small.chunk_delay = Duration::from_millis(1);
+        let small = Arc::new(small);
+
+        // The first download holds both connections, it gives one to the second download
+        let (mut first, first_dir) = mock_download(&large, 4, "fair-first").await;
+        let (mut second, second_dir) = mock_download(&small, 2, "fair-second").await;
+        first.set_connection_manager(manager.clone()).await;
+        second.set_connection_manager(manager.clone()).await;
+        large.ranges.lock().unwrap().clear();
+        let running = {
+            let first = first.clone();
+            task::spawn(async move { first.download(false).await })
+        };
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(second.download(false).await, Ok(true));
+        assert_eq!(first.get_status().await, DownloadStatus::Downloading);
+
+        // The part that gave its connection away continued from its received bytes
+        assert_eq!(running.await.unwrap(), Ok(true));
+        assert_eq!(std::fs::read(first_dir.join("file.bin")).unwrap(), large.data);
+        assert_eq!(std::fs::read(second_dir.join("file.bin")).unwrap(), small.data);
+        assert!(large.ranges.lock().unwrap().len() > 4);
+        assert_eq!(manager.active_connections("mock.test"), 0);
+        std::fs::remove_dir_all(first_dir).unwrap();
+        std::fs::remove_dir_all(second_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn mock_download_resumes_after_pause() {
+        let mut transport = MockTransport::new(1024 * 1024);
+        transport.chunk_delay = Duration::from_millis(5);
+        let transport = Arc::new(transport);
+        let (engine, out_dir) = mock_download(&transport, 1, "pause").await;
+
+        let running = {
+            let engine = engine.clone();
+            task::spawn(async move { engine.download(false).await })
+        };
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        engine.pause().await;
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(engine.get_status().await, DownloadStatus::Paused);
+
+        let received: u64 = engine.get_progress_vec().await.iter().map(|part| part.downloaded_bytes as u64).sum();
+        assert!(received > 0 && received < transport.data.len() as u64);
+        engine.resume().await;
+
+        assert_eq!(running.await.unwrap(), Ok(true));
+        assert_eq!(std::fs::read(out_dir.join("file.bin")).unwrap(), transport.data);
+
+        // The resumed request starts after the bytes received before the pause
+        let ranges = transport.ranges.lock().unwrap().clone();
+        assert_eq!(ranges, vec![ByteRange { start: 0, end: 1024 * 1024 - 1 }, ByteRange { start: received, end: 1024 * 1024 - 1 }]);
+        let events: Vec<TimelineEvent> = engine.get_timeline().into_iter().map(|entry| entry.event).collect();
+        assert_eq!(events, vec![
+            TimelineEvent::Started,
+            TimelineEvent::Paused { offline: false },
+            TimelineEvent::Resumed,
+            TimelineEvent::Completed { bytes: 1024 * 1024 },
+        ]);
+        std::fs::remove_dir_all(out_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn mock_download_buffers_the_parts_on_disk() {
+        for buffering in [BufferingStrategy::PartFiles, BufferingStrategy::Direct] {
+            let mut transport = MockTransport::new(4 * 1024 * 1024 + 7);
+            transport.chunk_delay = Duration::from_millis(1);
+            let transport = Arc::new(transport);
+            let (mut engine, out_dir) = mock_download(&transport, 4, &format!("buffering-{}", buffering)).await;
+            engine.set_buffering(buffering).await.unwrap();
+            let buffers = |out_dir: &Path| std::fs::read_dir(out_dir).map_or(0, |entries| entries.count());
+
+            let running = {
+                let engine = engine.clone();
+                task::spawn(async move { engine.download(false).await })
+            };
+            while engine.get_progress_vec().await.iter().map(|part| part.downloaded_bytes).sum::<usize>() < 2 * 1024 * 1024 {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+            engine.pause().await;
+
+            // The bytes the parts wrote to disk are read back into the token
+            let token = loop {
+                match engine.resume_token().await {
+                    Ok(token) => break token,
+                    Err(_) => tokio::time::sleep(Duration::from_millis(5)).await,
+                }
+            };
+            assert!(buffers(&out_dir) > 0);
+            assert!(engine.inner.lock().await.parts.iter().all(|state| state.buffer.is_empty()));
+            for part in token.parts.iter() {
+                let start = part.start_byte as usize;
+                assert_eq!(part.data, transport.data[start..start + part.data.len()]);
+            }
+            assert_eq!(engine.set_buffering(BufferingStrategy::Memory).await.err(), Some(String::from("The buffering strategy can't change once the download wrote some bytes to disk")));
+
+            // The file is put together from the buffers, which are removed
+            engine.resume().await;
+            assert_eq!(running.await.unwrap(), Ok(true));
+            assert_eq!(std::fs::read(out_dir.join("file.bin")).unwrap(), transport.data);
+            assert_eq!(engine.get_sha256().await, Some(sha256_hex(&transport.data)));
+            assert_eq!(buffers(&out_dir), 1);
+            std::fs::remove_dir_all(&out_dir).unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn ranges_are_probed_without_accept_ranges() {
+        let mut transport = MockTransport::new(4 * 1024 * 1024);
+        transport.hide_accept_ranges = true;
+        let transport = Arc::new(transport);
+        let (engine, out_dir) = mock_download(&transport, 4, "range-probe").await;
+
+        let info = engine.get_file_info().await.unwrap();
+        assert_eq!((info.support_partial, info.content_length), (SupportPartialRequest::Yes, Some(4 * 1024 * 1024)));
+        assert_eq!(transport.ranges.lock().unwrap().as_slice(), [ByteRange { start: 0, end: 0 }]);
+
+        assert_eq!(engine.download(false).await, Ok(true));
+        assert_eq!(std::fs::read(out_dir.join("file.bin")).unwrap(), transport.data);
+        assert_eq!(transport.ranges.lock().unwrap().len(), 5);
+        std::fs::remove_dir_all(out_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn mock_download_checks_the_kept_parts() {
+        let transport = Arc::new(MockTransport::new(1024 * 1024));
+        *transport.etag.lock().unwrap() = Some(String::from("\"v1\""));
+        let out_dir = std::env::temp_dir().join(format!("rustle-test-kept-parts-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&out_dir);
+        let half = 512 * 1024;
+        let token = |data: &[u8]| ResumeToken {
+            url: String::from("http://mock.test/file.bin"),
+            out_dir: out_dir.clone(),
+            file_name: Some(String::from("file.bin")),
+            content_length: Some(1024 * 1024),
+            accepts_ranges: true,
+            etag: Some(String::from("\"v1\"")),
+            parts: vec![
+                ResumePart { start_byte: 0, end_byte: half - 1, done: false, data: data[..1000].to_vec(), digests: Vec::new() },
+                ResumePart { start_byte: half, end_byte: 2 * half - 1, done: false, data: data[half as usize..half as usize + 1000].to_vec(), digests: Vec::new() },
+            ],
+            ..Default::default()
+        };
+
+        // The file didn't change, the parts continue from their bytes
+        let mut engine = RustleDownloader::from_resume_token(token(&transport.data), 2).unwrap();
+        engine.set_transport(transport.clone()).await;
+        assert_eq!(engine.download(false).await, Ok(true));
+        assert_eq!(std::fs::read(out_dir.join("file.bin")).unwrap(), transport.data);
+        assert_eq!(engine.get_resume_mismatch().await, None);
+        let mut starts: Vec<u64> = transport.ranges.lock().unwrap().iter().map(|range| range.start).collect();
+        starts.sort_unstable();
+        assert_eq!(starts, vec![1000, half + 1000]);
+        std::fs::remove_dir_all(&out_dir).unwrap();
+
+        // The file changed, the kept bytes are discarded and it's downloaded again
+        transport.ranges.lock().unwrap().clear();
+        *transport.etag.lock().unwrap() = Some(String::from("\"v2\""));
+        let mut engine = RustleDownloader::from_resume_token(token(&[0; 1024 * 1024]), 2).unwrap();
+        engine.set_transport(transport.clone()).await;
+        assert_eq!(engine.download(false).await, Ok(true));
+        assert_eq!(std::fs::read(out_dir.join("file.bin")).unwrap(), transport.data);
+        assert_eq!(engine.get_resume_mismatch().await, Some(ResumeMismatch::Etag { before: String::from("\"v1\""), now: String::from("\"v2\"") }));
+        assert!(engine.get_timeline().iter().any(|entry| matches!(entry.event, TimelineEvent::Restarted { .. })));
+        assert!(transport.ranges.lock().unwrap().iter().any(|range| range.start == 0));
+        std::fs::remove_dir_all(&out_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn mock_download_repairs_corrupt_segments() {
+        let mut transport = MockTransport::new(4 * SEGMENT_SIZE);
+        transport.chunk_delay = Duration::from_millis(1);
+        let transport = Arc::new(transport);
+        let (mut engine, out_dir) = mock_download(&transport, 1, "segments").await;
+        engine.set_segment_hashing(true).await;
+
+        let running = {
+            let engine = engine.clone();
+            task::spawn(async move { engine.download(false).await })
+        };
+        while engine.get_progress_vec().await.iter().map(|part| part.downloaded_bytes).sum::<usize>() < 2 * SEGMENT_SIZE {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        engine.cancel(true).await;
+        assert_eq!(running.await.unwrap(), Ok(false));
+
+        // The digests of the received segments are kept in the token
+        let mut token = engine.resume_token().await.unwrap();
+        let received = token.parts[0].data.len();
+        assert_eq!(token.parts[0].digests.len(), received / SEGMENT_SIZE);
+        assert_eq!(token.parts[0].digests[1], sha256_hex(&transport.data[SEGMENT_SIZE..2 * SEGMENT_SIZE]));
+
+        // Only the corrupt segment is downloaded again, along with the bytes that weren't received
+        token.parts[0].data[SEGMENT_SIZE + 10] ^= 0xff;
+        transport.ranges.lock().unwrap().clear();
+        let mut engine = RustleDownloader::from_resume_token(token, 1).unwrap();
+        engine.set_transport(transport.clone()).await;
+        assert_eq!(engine.download(false).await, Ok(true));
+        assert_eq!(std::fs::read(out_dir.join("file.bin")).unwrap(), transport.data);
+
+        let mut ranges = transport.ranges.lock().unwrap().clone();
+        ranges.sort_by_key(|range| range.start);
+        assert_eq!(ranges, vec![
+            ByteRange { start: SEGMENT_SIZE as u64, end: 2 * SEGMENT_SIZE as u64 - 1 },
+            ByteRange { start: received as u64, end: 4 * SEGMENT_SIZE as u64 - 1 },
+        ]);
+        assert!(engine.get_timeline().iter().any(|entry| entry.event == TimelineEvent::SegmentsRepaired { segments: 1 }));
+        assert_eq!(engine.get_sha256().await, Some(sha256_hex(&transport.data)));
+        std::fs::remove_dir_all(&out_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn mock_download_hashes_the_parts_as_they_stream() {
+        let mut transport = MockTransport::new(4 * 1024 * 1024);
+        transport.chunk_delay = Duration::from_millis(1);
+        let transport = Arc::new(transport);
+        let (mut engine, out_dir) = mock_download(&transport, 4, "stream-hash").await;
+        engine.set_expected_sha256(&sha256_hex(&transport.data)).await.unwrap();
+
+        let running = {
+            let engine = engine.clone();
+            task::spawn(async move { engine.download(false).await })
+        };
+        while engine.get_progress_vec().await.iter().map(|part| part.downloaded_bytes).sum::<usize>() < 3 * 1024 * 1024 {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        // The first part is hashed while the others stream
+        let hashed = engine.inner.lock().await.stream_hash.lock().unwrap().offset();
+        assert!(hashed >= 512 * 1024, "{}", hashed);
+        assert_eq!(running.await.unwrap(), Ok(true));
+        assert_eq!(engine.get_sha256().await, Some(sha256_hex(&transport.data)));
+
+        // The file isn't written when the digest doesn't match
+        std::fs::remove_dir_all(&out_dir).unwrap();
+        let (mut engine, out_dir) = mock_download(&transport, 4, "stream-hash-mismatch").await;
+        engine.set_expected_sha256(&"0".repeat(64)).await.unwrap();
+        assert!(engine.download(false).await.unwrap_err().starts_with("Checksum mismatch"));
+        assert!(!out_dir.join("file.bin").exists());
+        assert_eq!(engine.get_sha256().await, None);
+        let _ = std::fs::remove_dir_all(out_dir);
+    }
+
+    #[tokio::test]
+    async fn mock_download_slow_sink_applies_backpressure() {
+        let transport = Arc::new(MockTransport::new(1024 * 1024));
+        let (mut engine, out_dir) = mock_download(&transport, 1, "backpressure").await;
+        engine.set_speed_limit(Some(2 * 1024 * 1024)).await.unwrap();
+
+        assert_eq!(engine.download(false).await, Ok(true));
+        assert_eq!(std::fs::read(out_dir.join("file.bin")).unwrap(), transport.data);
+
+        // The mock sends the chunks right away, so the network side waited for the limited sink
+        let progress = engine.get_progress_vec().await;
+        assert_eq!(progress.len(), 1);
+        assert!(progress[0].backpressure > Duration::from_millis(50), "{:?}", progress[0]);
+        assert!(progress[0].backpressure > progress[0].network_wait, "{:?}", progress[0]);
+        let _ = std::fs::remove_dir_all(out_dir);
+    }
+
+    #[tokio::test]
+    async fn mock_download_splits_the_slow_tail() {
+        let mut transport = MockTransport::new(16 * 1024 * 1024);
+        transport.slow_part = Some((12 * 1024 * 1024, Duration::from_millis(50)));
+        let transport = Arc::new(transport);
+        let (engine, out_dir) = mock_download(&transport, 4, "tail").await;
+
+        // The slow part stops once the others are done, its remaining bytes are split into 3 ranges
+        assert_eq!(engine.download(false).await, Ok(true));
+        assert_eq!(std::fs::read(out_dir.join("file.bin")).unwrap(), transport.data);
+
+        let mut ranges = transport.ranges.lock().unwrap().clone();
+        ranges.sort_by_key(|range| range.start);
+        assert_eq!(ranges.len(), 7);
+        assert_eq!(ranges[3], ByteRange { start: 12 * 1024 * 1024, end: 16 * 1024 * 1024 - 1 });
+        assert!(ranges[4..].windows(2).all(|pair| pair[0].end + 1 == pair[1].start));
+        assert_eq!(ranges[6].end, 16 * 1024 * 1024 - 1);
+        assert!(engine.get_timeline().iter().any(|entry| entry.event == TimelineEvent::TailSplit { ranges: 3 }));
+        assert_eq!(engine.get_sha256().await, Some(sha256_hex(&transport.data)));
+        std::fs::remove_dir_all(&out_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn mock_download_tunes_the_number_of_connections() {
+        // Each connection is throttled, so every connection added improves the throughput
+        let mut transport = MockTransport::new(16 * 1024 * 1024);
+        transport.chunk_delay = Duration::from_millis(10);
+        let transport = Arc::new(transport);
+        let out_dir = std::env::temp_dir().join(format!("rustle-test-tuning-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&out_dir);
+        let engine = RustleDownloader::builder()
+            .url("http://mock.test/file.bin")
+            .out_dir(&out_dir)
+            .max_connections(8)
+            .auto_connections(true)
+            .transport(transport.clone())
+            .build()
+            .await
+            .unwrap();
+
+        assert_eq!(engine.download(false).await, Ok(true));
+        assert_eq!(std::fs::read(out_dir.join("file.bin")).unwrap(), transport.data);
+
+        // The download starts with 2 of its 8 parts, the next ones wait for the running parts to end,
+        // and more run at once after the tuning measured the throughput
+        let concurrency = transport.concurrency.lock().unwrap().clone();
+        assert_eq!(concurrency.len(), 8);
+        assert!(concurrency[..4].iter().all(|in_flight| *in_flight <= 2));
+        assert!(concurrency.iter().any(|in_flight| *in_flight > 2));
+        assert_eq!(engine.get_sha256().await, Some(sha256_hex(&transport.data)));
+        std::fs::remove_dir_all(&out_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn mock_download_moves_the_file_from_the_staging_dir() {
+        let transport = Arc::new(MockTransport::new(1024 * 1024));
+        let (mut engine, out_dir) = mock_download(&transport, 2, "staging").await;
+        let staging_dir = out_dir.with_extension("staging");
+        engine.set_staging_dir(Some(staging_dir.clone())).await;
+        engine.set_file_permissions(FilePermissions { mode: Some(0o600), executable_types: true }).await;
+
+        assert_eq!(engine.download(false).await, Ok(true));
+        assert_eq!(std::fs::read(out_dir.join("file.bin")).unwrap(), transport.data);
+        // The mode is set on the moved file
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(std::fs::metadata(out_dir.join("file.bin")).unwrap().permissions().mode() & 0o7777, 0o600);
+        }
+        assert_eq!(std::fs::read_dir(&staging_dir).unwrap().count(), 0);
+        assert_eq!(engine.get_move_progress().await, None);
+        std::fs::remove_dir_all(&out_dir).unwrap();
+        std::fs::remove_dir_all(&staging_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn mock_download_waits_for_a_mismatching_type_to_be_confirmed() {
+        let mut transport = MockTransport::new(1024 * 1024);
+        transport.content_type = Some("text/html; charset=utf-8");
+        let transport = Arc::new(transport);
+        let (mut engine, out_dir) = mock_download(&transport, 2, "type-mismatch").await;
+        engine.set_file_name("ubuntu.iso").await;
+        transport.ranges.lock().unwrap().clear();
+
+        // Nothing is transferred until the type is confirmed
+        assert_eq!(engine.download(false).await, Ok(false));
+        assert_eq!(engine.get_status().await, DownloadStatus::Warning);
+        assert_eq!(engine.get_type_mismatch().await.unwrap().extension, "iso");
+        assert!(matches!(engine.get_timeline().last().unwrap().event, TimelineEvent::TypeMismatch { .. }));
+        assert!(transport.ranges.lock().unwrap().is_empty());
+
+        engine.confirm_type().await;
+        assert_eq!(engine.get_status().await, DownloadStatus::Idle);
+        assert_eq!(engine.get_type_mismatch().await, None);
+        assert_eq!(engine.download(false).await, Ok(true));
+        assert_eq!(std::fs::read(out_dir.join("ubuntu.iso")).unwrap(), transport.data);
+        std::fs::remove_dir_all(out_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn mock_download_records_the_requests_in_a_har_file() {
+        let transport = Arc::new(MockTransport::new(1024 * 1024));
+        let (mut engine, out_dir) = mock_download(&transport, 2, "har").await;
+        let har_path = out_dir.join("file.bin.har");
+        engine.set_har_log(Some(har_path.clone())).await;
+
+        assert_eq!(engine.download(false).await, Ok(true));
+        let mut entries = engine.get_har_entries().await;
+        entries.sort_by_key(|entry| entry.request_headers.iter().find(|(name, _)| name == "range").map(|(_, range)| range.clone()));
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].request_headers, vec![(String::from("range"), String::from("bytes=0-524287"))]);
+        assert!(entries.iter().all(|entry| entry.method == "GET" && entry.status == Some(206) && entry.receive.is_some()));
+        assert_eq!(entries.iter().map(|entry| entry.body_size).sum::<u64>(), transport.data.len() as u64);
+
+        let har: serde_json::Value = serde_json::from_slice(&std::fs::read(&har_path).unwrap()).unwrap();
+        assert_eq!(har["log"]["entries"].as_array().unwrap().len(), 2);
+        assert_eq!(har["log"]["entries"][0]["request"]["url"], "http://mock.test/file.bin");
+        std::fs::remove_dir_all(out_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn mock_download_verifies_a_local_copy() {
+        let transport = Arc::new(MockTransport::new(1024 * 1024));
+        *transport.etag.lock().unwrap() = Some(String::from("\"v2\""));
+        let (engine, out_dir) = mock_download(&transport, 2, "verify").await;
+        assert_eq!(engine.download(false).await, Ok(true));
+        let path = out_dir.join("file.bin");
+        std::fs::write(super::super::verify::sidecar_path(&path), format!("{}  file.bin\n", sha256_hex(&transport.data))).unwrap();
+
+        let report = engine.verify_file(&path, &VerifyOptions { etag: Some(String::from("\"v2\"")), ..Default::default() }).await.unwrap();
+        assert!(report.is_ok() && report.is_hash_verified(), "{:?}", report);
+        assert_eq!((report.local_size, report.remote_size), (Some(1024 * 1024), Some(1024 * 1024)));
+
+        let report = engine.verify_file(&path, &VerifyOptions { etag: Some(String::from("\"v1\"")), ..Default::default() }).await.unwrap();
+        assert_eq!(report.issues, vec![VerifyIssue::EtagChanged { downloaded: String::from("\"v1\""), remote: String::from("\"v2\"") }]);
+        assert!(report.is_complete() && report.sha256.is_none());
+
+        let mut data = transport.data.to_vec();
+        data[1000] ^= 1;
+        std::fs::write(&path, &data).unwrap();
+        let report = engine.verify_file(&path, &VerifyOptions::default()).await.unwrap();
+        assert!(matches!(report.issues.as_slice(), [VerifyIssue::HashMismatch { .. }]), "{:?}", report);
+
+        std::fs::write(&path, &data[..1000]).unwrap();
+        let report = engine.verify_file(&path, &VerifyOptions::default()).await.unwrap();
+        assert_eq!(report.issues, vec![VerifyIssue::Incomplete { local_size: 1000, remote_size: 1024 * 1024 }]);
+        assert!(!report.is_complete());
+
+        let report = engine.verify_file(&out_dir.join("missing.bin"), &VerifyOptions::default()).await.unwrap();
+        assert_eq!(report.issues, vec![VerifyIssue::Missing]);
+        std::fs::remove_dir_all(out_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn referer_is_kept_in_the_resume_token() {
+        let mut engine = RustleDownloader::new(2).unwrap();
+        assert!(engine.set_referer(Some("/gallery")).await.is_err());
+        engine.set_referer(Some("https://example.com/gallery")).await.unwrap();
+
+        let token = ResumeToken {
+            url: String::from("https://example.com/file.bin"),
+            out_dir: "./".into(),
+            referer: engine.get_referer().await,
+            ..Default::default()
+        };
+        let restored = RustleDownloader::from_resume_token(token.clone(), 2).unwrap();
+        assert_eq!(restored.get_referer().await.as_deref(), Some("https://example.com/gallery"));
+        assert!(RustleDownloader::from_resume_token(ResumeToken { referer: Some(String::from("gallery")), ..token }, 2).is_err());
+    }
+
+    #[tokio::test]
+    async fn mock_download_restarts_when_ranges_are_ignored() {
+        let mut transport = MockTransport::new(4 * 1024 * 1024);
+        transport.ignore_ranges = true;
+        let transport = Arc::new(transport);
+        let (engine, out_dir) = mock_download(&transport, 4, "ignored-ranges").await;
+        transport.ranges.lock().unwrap().clear();
+
+        // The 4 parts get the whole file, it's downloaded again by a single one
+        assert_eq!(engine.download(false).await, Ok(true));
+        assert_eq!(std::fs::read(out_dir.join("file.bin")).unwrap(), transport.data);
+        assert_eq!(transport.ranges.lock().unwrap().len(), 5);
+        assert_eq!(engine.get_file_info().await.unwrap().support_partial, SupportPartialRequest::No);
+        std::fs::remove_dir_all(out_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn mock_download_retries_wrong_ranges() {
+        let transport = Arc::new(MockTransport::new(4 * 1024 * 1024));
+        transport.shifted_ranges.store(2, Ordering::Relaxed);
+        let (engine, out_dir) = mock_download(&transport, 4, "wrong-ranges").await;
+
+        // The 2 parts answered with the wrong range are requested again
+        assert_eq!(engine.download(false).await, Ok(true));
+        assert_eq!(std::fs::read(out_dir.join("file.bin")).unwrap(), transport.data);
+        assert_eq!(transport.ranges.lock().unwrap().len(), 6);
+        std::fs::remove_dir_all(&out_dir).unwrap();
+
+        let (mut engine, out_dir) = mock_download(&transport, 1, "wrong-range").await;
+        engine.set_retry_policy(RetryPolicy { max_retries: 0, ..Default::default() }).await;
+        transport.shifted_ranges.store(1, Ordering::Relaxed);
+        assert!(engine.download(false).await.unwrap_err().contains("instead of bytes=0-"));
+        assert!(!out_dir.join("file.bin").exists());
+        let _ = std::fs::remove_dir_all(out_dir);
+    }
+
+    #[tokio::test]
+    async fn mock_download_refreshes_expired_url() {
+        let transport = Arc::new(MockTransport::new(4 * 1024 * 1024));
+        *transport.expired.lock().unwrap() = Some(String::from("http://mock.test/file.bin"));
+        let (mut engine, out_dir) = mock_download(&transport, 4, "refresh").await;
+
+        // All the parts are rejected, a single new URL is asked for
+        let refreshes = Arc::new(AtomicUsize::new(0));
+        let counter = refreshes.clone();
+        engine.set_url_refresher(Some(Arc::new(crate::refresh::RefreshFn(move |expired: Url| {
+            counter.fetch_add(1, Ordering::Relaxed);
+            async move { Ok(format!("{}?signature=2", expired)) }
+        })))).await;
+
+        assert_eq!(engine.download(false).await, Ok(true));
+        assert_eq!(std::fs::read(out_dir.join("file.bin")).unwrap(), transport.data);
+        assert_eq!(refreshes.load(Ordering::Relaxed), 1);
+        assert_eq!(engine.get_url().await.as_deref(), Some("http://mock.test/file.bin?signature=2"));
+        assert_eq!(transport.ranges.lock().unwrap().len(), 4);
+        std::fs::remove_dir_all(&out_dir).unwrap();
+
+        // Without a refresher the parts fail on the expired URL, retrying doesn't help
+        let (engine, out_dir) = mock_download(&transport, 1, "expired").await;
+        assert!(engine.download(false).await.unwrap_err().contains("403"));
+        assert_eq!(engine.get_error_kind().await, Some(ErrorKind::HttpStatus(403)));
+        assert!(!engine.get_timeline().iter().any(|entry| matches!(entry.event, TimelineEvent::Retrying { .. })));
+        assert!(!out_dir.join("file.bin").exists());
+    }
+
+    #[tokio::test]
+    async fn mock_download_with_post_sends_a_single_request() {
+        let transport = Arc::new(MockTransport::new(2 * 1024 * 1024));
+        let out_dir = std::env::temp_dir().join(format!("rustle-test-post-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&out_dir);
+        let engine = RustleDownloader::builder()
+            .url("http://mock.test/export")
+            .out_dir(&out_dir)
+            .connections(4)
+            .method("post")
+            .body(r#"{"format":"csv"}"#)
+            .transport(transport.clone())
+            .build()
+            .await
+            .unwrap();
+        assert_eq!(engine.get_method().await, Method::POST);
+
+        // The probe's response is the download, over a single connection without a range
+        assert_eq!(engine.download(false).await, Ok(true));
+        assert_eq!(std::fs::read(out_dir.join("export")).unwrap(), transport.data);
+        assert_eq!(transport.ranges.lock().unwrap().len(), 1);
+        assert_eq!(engine.get_progress_vec().await.len(), 1);
+
+        let token = engine.resume_token().await.unwrap();
+        assert_eq!((token.method.as_deref(), token.body.as_slice()), (Some("POST"), &br#"{"format":"csv"}"#[..]));
+        let restored = RustleDownloader::from_resume_token(token, 4).unwrap();
+        assert_eq!(restored.get_method().await, Method::POST);
+        std::fs::remove_dir_all(out_dir).unwrap();
+    }
+
+    /// MockProtocol downloads the URLs of its scheme with a `MockTransport`.
+    #[derive(Debug)]
+    struct MockProtocol(Arc<MockTransport>);
+
+    impl Protocol for MockProtocol {
+        fn transport(&self, _options: &ClientOptions) -> Result<Arc<dyn Transport>, String> {
+            Ok(self.0.clone())
+        }
+    }
+
+    #[tokio::test]
+    async fn registered_protocol_downloads_its_scheme() {
+        let transport = Arc::new(MockTransport::new(1024 * 1024));
+        let out_dir = std::env::temp_dir().join(format!("rustle-test-protocol-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&out_dir);
+
+        let unsupported = RustleDownloader::builder().url("mock://server/file.bin").out_dir(&out_dir).build().await;
+        assert_eq!(unsupported.unwrap_err(), "Unsupported protocol `mock`, supported ones are http, https");
+
+        let mut protocols = ProtocolRegistry::default();
+        protocols.register("MOCK", Arc::new(MockProtocol(transport.clone())));
+        assert_eq!(protocols.schemes(), vec!["http", "https", "mock"]);
+        let engine = RustleDownloader::builder()
+            .url("mock://server/file.bin")
+            .out_dir(&out_dir)
+            .protocols(Arc::new(protocols))
+            .build()
+            .await
+            .unwrap();
+
+        assert_eq!(engine.download(false).await, Ok(true));
+        assert_eq!(std::fs::read(out_dir.join("file.bin")).unwrap(), transport.data);
+        std::fs::remove_dir_all(out_dir).unwrap();
+    }
+}
@@ -0,0 +1,171 @@
+use std::fmt;
+use std::fs;
+use std::io::ErrorKind as IoErrorKind;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use super::queue_manager::QueueManager;
+
+/// File name of the drain request, in the data directory of the application.
+pub const DRAIN_FILE_NAME: &str = "drain";
+
+/// Interval at which the running application checks the drain request.
+pub const DRAIN_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// DrainState represents the progress of a drain request. It's written to the drain file, so the
+/// tooling requesting the drain can wait for the running application to finish its downloads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DrainState {
+    Requested,  // The drain was requested, the application hasn't picked it up yet
+    Draining,   // The application drains its queue, downloads are still running
+    Drained,    // No download is running anymore, or the application exited
+}
+
+impl DrainState {
+    /// Returns the name of the state, as written to the drain file.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DrainState::Requested => "requested",
+            DrainState::Draining => "draining",
+            DrainState::Drained => "drained",
+        }
+    }
+}
+
+impl fmt::Display for DrainState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for DrainState {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [DrainState::Requested, DrainState::Draining, DrainState::Drained].into_iter()
+            .find(|state| state.as_str() == s)
+            .ok_or_else(|| format!("Unknown drain state `{}`", s))
+    }
+}
+
+/// Reads the state of the drain request at `path`.
+///
+/// Returns `None` if no drain is requested, or an error if the file couldn't be read.
+pub fn read_drain_state(path: &Path) -> Result<Option<DrainState>, String> {
+    match fs::read_to_string(path) {
+        Ok(content) => content.trim().parse().map(Some),
+        Err(e) if e.kind() == IoErrorKind::NotFound => Ok(None),
+        Err(e) => Err(format!("Couldn't read the drain request {}, error : {}", path.display(), e)),
+    }
+}
+
+/// Writes the state of the drain request at `path`, creating its directory if needed. The file is
+/// replaced at once, so the other process never reads it half written.
+pub fn write_drain_state(path: &Path, state: DrainState) -> Result<(), String> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir).map_err(|e| format!("Couldn't create {}, error : {}", dir.display(), e))?;
+    }
+    let part_path = path.with_extension("part");
+    fs::write(&part_path, state.as_str()).map_err(|e| format!("Couldn't write the drain request {}, error : {}", part_path.display(), e))?;
+    fs::rename(&part_path, path).map_err(|e| format!("Couldn't write the drain request {}, error : {}", path.display(), e))
+}
+
+/// Removes the drain request at `path`, if any, so the queue of the running application starts
+/// downloads again.
+pub fn clear_drain(path: &Path) -> Result<(), String> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != IoErrorKind::NotFound => Err(format!("Couldn't remove the drain request {}, error : {}", path.display(), e)),
+        _ => Ok(()),
+    }
+}
+
+/// Applies the drain request at `path` to `queue`, called every `DRAIN_POLL_INTERVAL` by the
+/// running application: the queue drains while the request exists, and its progress is written
+/// back. Once the request is removed, the queue starts downloads again.
+///
+/// # Returns
+///
+/// * `Result<bool, String>` - Whether the queue is draining, or an error if the request couldn't
+///   be read or updated.
+pub async fn sync_drain(path: &Path, queue: &Arc<QueueManager>) -> Result<bool, String> {
+    let Some(state) = read_drain_state(path)? else {
+        if queue.is_draining() {
+            queue.resume_queue();
+        }
+        return Ok(false);
+    };
+
+    queue.drain();
+    let progress = if queue.is_drained().await { DrainState::Drained } else { DrainState::Draining };
+    if state != progress {
+        write_drain_state(path, progress)?;
+    }
+    Ok(true)
+}
+
+/// Marks the drain request at `path`, if any, as drained when the application exits, its
+/// downloads are stopped and saved by then.
+pub fn release_drain(path: &Path) -> Result<(), String> {
+    match read_drain_state(path)? {
+        Some(_) => write_drain_state(path, DrainState::Drained),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::downloader::tests::{mock_download, MockTransport};
+    use super::super::queue_manager::QueueState;
+
+    #[tokio::test]
+    async fn drain_request_is_applied_to_the_queue() {
+        let dir = std::env::temp_dir().join(format!("rustle-test-drain-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join(DRAIN_FILE_NAME);
+        let manager = Arc::new(QueueManager::new(1).unwrap());
+        let mut transport = MockTransport::new(256 * 1024);
+        transport.chunk_delay = Duration::from_millis(10);
+        let (engine, out_dir) = mock_download(&Arc::new(transport), 1, "drain-request").await;
+        let id = manager.add(engine);
+
+        assert_eq!(sync_drain(&path, &manager).await, Ok(false));
+        assert_eq!(read_drain_state(&path), Ok(None));
+
+        write_drain_state(&path, DrainState::Requested).unwrap();
+        manager.start(id).unwrap();
+        assert_eq!(sync_drain(&path, &manager).await, Ok(true));
+        assert!(manager.is_draining());
+        assert_eq!(read_drain_state(&path), Ok(Some(DrainState::Draining)));
+
+        assert_eq!(manager.wait(id).await, Ok(true));
+        assert_eq!(sync_drain(&path, &manager).await, Ok(true));
+        assert_eq!(read_drain_state(&path), Ok(Some(DrainState::Drained)));
+
+        clear_drain(&path).unwrap();
+        assert_eq!(clear_drain(&path), Ok(()));
+        assert_eq!(sync_drain(&path, &manager).await, Ok(false));
+        assert!(!manager.is_draining());
+        assert_eq!(manager.state(id), Some(QueueState::Finished));
+        fs::remove_dir_all(dir).unwrap();
+        fs::remove_dir_all(out_dir).unwrap();
+    }
+
+    #[test]
+    fn exiting_releases_the_drain_request() {
+        let dir = std::env::temp_dir().join(format!("rustle-test-drain-release-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join(DRAIN_FILE_NAME);
+
+        release_drain(&path).unwrap();
+        assert!(!path.exists());
+        write_drain_state(&path, DrainState::Draining).unwrap();
+        release_drain(&path).unwrap();
+        assert_eq!(read_drain_state(&path), Ok(Some(DrainState::Drained)));
+
+        fs::write(&path, "stopped").unwrap();
+        assert_eq!(read_drain_state(&path), Err(String::from("Unknown drain state `stopped`")));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,102 @@
+use std::fmt;
+use std::io::Read;
+use std::str::FromStr;
+use bytes::Bytes;
+use flate2::read::{GzDecoder, ZlibDecoder};
+
+/// Value of `Accept-Encoding` sent with requests for the whole file when compressed responses are decoded.
+pub const ACCEPTED_ENCODINGS: &str = "gzip, deflate, br";
+
+/// Represents what is stored when a server compresses a response with `Content-Encoding`.
+/// Range requests always ask for the uncompressed file (`Accept-Encoding: identity`), the ranges
+/// wouldn't line up otherwise, so this only applies to downloads over a single connection.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ContentEncodingMode {
+    /// Compressed responses are accepted and decoded before the file is written.
+    #[default]
+    Decode,
+    /// The response is stored as sent, e.g. a `.tar.gz` that a server wrongly marks as `gzip` encoded.
+    Raw,
+}
+
+impl ContentEncodingMode {
+    /// All the modes, in the order they are presented to the user.
+    pub const ALL: [ContentEncodingMode; 2] = [ContentEncodingMode::Decode, ContentEncodingMode::Raw];
+
+    /// Returns the name used for the mode in the settings file.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ContentEncodingMode::Decode => "decode",
+            ContentEncodingMode::Raw => "raw",
+        }
+    }
+}
+
+impl fmt::Display for ContentEncodingMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ContentEncodingMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ContentEncodingMode::ALL.into_iter()
+            .find(|mode| mode.as_str() == s)
+            .ok_or_else(|| format!("Unknown content encoding mode `{}`", s))
+    }
+}
+
+/// Returns whether a `Content-Encoding` value means the body is compressed, `identity` isn't.
+pub fn is_compressed(encoding: &str) -> bool {
+    !encoding.split(',').all(|coding| matches!(coding.trim().to_ascii_lowercase().as_str(), "" | "identity"))
+}
+
+/// Decodes a body compressed with the codings of a `Content-Encoding` value, applied in order.
+///
+/// # Arguments
+///
+/// * `encoding` - The value of the header, e.g. `gzip` or `deflate, br`.
+/// * `data` - The body as received.
+///
+/// Returns an error if a coding isn't supported or the body isn't valid.
+pub fn decode_content(encoding: &str, data: Bytes) -> Result<Bytes, String> {
+    let mut data = data;
+    // The codings are listed in the order they were applied, so they're undone from the last one
+    for coding in encoding.split(',').rev().map(|coding| coding.trim().to_ascii_lowercase()) {
+        let mut decoded = Vec::new();
+        let result = match coding.as_str() {
+            "" | "identity" => continue,
+            "gzip" | "x-gzip" => GzDecoder::new(&data[..]).read_to_end(&mut decoded),
+            "deflate" => ZlibDecoder::new(&data[..]).read_to_end(&mut decoded),
+            "br" => brotli_decompressor::Decompressor::new(&data[..], 64 * 1024).read_to_end(&mut decoded),
+            coding => return Err(format!("Unsupported content encoding `{}`, store the response raw instead", coding)),
+        };
+        result.map_err(|e| format!("Couldn't decode the {} response, error : {}", coding, e))?;
+        data = Bytes::from(decoded);
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    #[test]
+    fn decodes_compressed_bodies() {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"hello hello hello").unwrap();
+        let gzip = Bytes::from(encoder.finish().unwrap());
+
+        assert_eq!(decode_content("gzip", gzip.clone()).unwrap(), &b"hello hello hello"[..]);
+        assert_eq!(decode_content("identity", gzip.clone()).unwrap(), gzip);
+        assert!(decode_content("zstd", gzip.clone()).is_err());
+        assert!(decode_content("br", gzip).is_err());
+        assert!(is_compressed("GZIP") && !is_compressed("identity"));
+        assert_eq!("raw".parse::<ContentEncodingMode>(), Ok(ContentEncodingMode::Raw));
+    }
+}
@@ -0,0 +1,86 @@
+use std::fmt;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use super::transport::TransportErrorKind;
+
+/// ErrorKind represents what made a download fail, along with its error message: user interfaces
+/// show it in a few words ("Host not found"), and the retries skip the failures that retrying
+/// doesn't fix, see `is_retryable()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    /// The host name couldn't be resolved.
+    Dns,
+    /// The server refused the connection.
+    ConnectionRefused,
+    /// The TLS handshake failed, e.g. the certificate of the server isn't trusted.
+    Tls,
+    /// The connection couldn't be opened, or was lost while downloading.
+    Connection,
+    /// The server didn't answer in time.
+    Timeout,
+    /// The server answered with an unexpected HTTP status, e.g. `404`.
+    HttpStatus(u16),
+    /// Any other failure, e.g. an invalid response or a file that couldn't be written.
+    Other,
+}
+
+impl ErrorKind {
+    /// Returns whether another attempt may succeed. Client errors (`4xx`) other than timeouts and
+    /// rate limits, and TLS failures, happen again until the URL or the settings are changed.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            ErrorKind::Tls => false,
+            ErrorKind::HttpStatus(status) => !(400..500).contains(status) || matches!(status, 408 | 425 | 429),
+            _ => true,
+        }
+    }
+}
+
+impl From<TransportErrorKind> for ErrorKind {
+    fn from(kind: TransportErrorKind) -> Self {
+        match kind {
+            TransportErrorKind::Dns => ErrorKind::Dns,
+            TransportErrorKind::ConnectionRefused => ErrorKind::ConnectionRefused,
+            TransportErrorKind::Tls => ErrorKind::Tls,
+            TransportErrorKind::Connect => ErrorKind::Connection,
+            TransportErrorKind::Timeout => ErrorKind::Timeout,
+            TransportErrorKind::Other => ErrorKind::Other,
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ErrorKind::Dns => f.write_str("Host not found"),
+            ErrorKind::ConnectionRefused => f.write_str("Connection refused"),
+            ErrorKind::Tls => f.write_str("TLS error"),
+            ErrorKind::Connection => f.write_str("Connection failed"),
+            ErrorKind::Timeout => f.write_str("Timed out"),
+            ErrorKind::HttpStatus(status) => match StatusCode::from_u16(*status).ok().and_then(|status| status.canonical_reason()) {
+                Some(reason) => write!(f, "HTTP {} {}", status, reason),
+                None => write!(f, "HTTP {}", status),
+            },
+            ErrorKind::Other => f.write_str("Error"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kinds_are_described_and_retried() {
+        assert_eq!(ErrorKind::from(TransportErrorKind::Dns).to_string(), "Host not found");
+        assert_eq!(ErrorKind::HttpStatus(404).to_string(), "HTTP 404 Not Found");
+        assert_eq!(ErrorKind::HttpStatus(599).to_string(), "HTTP 599");
+
+        assert!(ErrorKind::Dns.is_retryable());
+        assert!(ErrorKind::HttpStatus(503).is_retryable());
+        assert!(ErrorKind::HttpStatus(429).is_retryable());
+        assert!(!ErrorKind::HttpStatus(404).is_retryable());
+        assert!(!ErrorKind::Tls.is_retryable());
+    }
+}
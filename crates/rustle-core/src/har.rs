@@ -0,0 +1,286 @@
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use futures::future::BoxFuture;
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, COOKIE, IF_RANGE, PROXY_AUTHORIZATION, RANGE, SET_COOKIE, USER_AGENT};
+use reqwest::{Method, Version};
+use serde_json::{json, Value};
+use url::Url;
+use super::dates::{civil_from_days, day_of, DAY_SECS};
+use super::client::ClientOptions;
+use super::provenance::public_url;
+use super::transport::{ByteRange, Transport, TransportError, TransportResponse};
+
+/// Maximum number of requests recorded per download, the later ones aren't recorded.
+pub const MAX_HAR_ENTRIES: usize = 10_000;
+
+/// Value replacing the credentials and cookies in the recorded headers.
+const REDACTED: &str = "[redacted]";
+
+/// HarEntry represents a request of a download and its response, as recorded by `HarTransport`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HarEntry {
+    pub started: SystemTime,                        // When the request was sent
+    pub method: String,                             // Method of the request
+    pub url: String,                                // URL of the request, without its credentials
+    pub request_headers: Vec<(String, String)>,     // Headers set by rustle, the client adds its own like `Host` or `Accept`
+    pub status: Option<u16>,                        // Status of the response, `None` if the request failed
+    pub http_version: Option<Version>,              // HTTP version of the response
+    pub response_url: Option<String>,               // URL of the response, after redirects
+    pub response_headers: Vec<(String, String)>,    // Headers of the response
+    pub error: Option<String>,                      // Why the request or the body failed
+    pub wait: Duration,                             // Time until the response headers were received
+    pub receive: Option<Duration>,                  // Time the body was read for, until it ended or was dropped
+    pub body_size: u64,                             // Number of body bytes read
+}
+
+impl HarEntry {
+    /// Returns the entry in the HAR 1.2 format.
+    fn to_json(&self) -> Value {
+        let headers = |headers: &[(String, String)]| -> Vec<Value> {
+            headers.iter().map(|(name, value)| json!({ "name": name, "value": value })).collect()
+        };
+        let query: Vec<Value> = Url::parse(&self.url).map(|url| {
+            url.query_pairs().map(|(name, value)| json!({ "name": name, "value": value })).collect()
+        }).unwrap_or_default();
+        let http_version = self.http_version.map(|version| format!("{:?}", version)).unwrap_or_default();
+        let millis = |duration: Duration| duration.as_secs_f64() * 1000.0;
+        let receive = self.receive.map_or(0.0, millis);
+        // A redirect is reported by the response's URL, the client follows it within the request
+        let redirect_url = self.response_url.as_ref().filter(|url| **url != self.url).cloned().unwrap_or_default();
+        let content_type = self.response_headers.iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case("content-type"))
+            .map(|(_, value)| value.clone())
+            .unwrap_or_default();
+
+        let mut entry = json!({
+            "startedDateTime": format_timestamp(self.started),
+            "time": millis(self.wait) + receive,
+            "request": {
+                "method": self.method,
+                "url": self.url,
+                "httpVersion": http_version,
+                "cookies": [],
+                "headers": headers(&self.request_headers),
+                "queryString": query,
+                "headersSize": -1,
+                "bodySize": -1,
+            },
+            "response": {
+                "status": self.status.unwrap_or(0),
+                "statusText": self.status.and_then(|status| reqwest::StatusCode::from_u16(status).ok()?.canonical_reason()).unwrap_or_default(),
+                "httpVersion": http_version,
+                "cookies": [],
+                "headers": headers(&self.response_headers),
+                "content": { "size": self.body_size, "mimeType": content_type },
+                "redirectURL": redirect_url,
+                "headersSize": -1,
+                "bodySize": self.body_size,
+            },
+            "cache": {},
+            "timings": { "send": 0, "wait": millis(self.wait), "receive": receive },
+        });
+        if let Some(error) = self.error.as_ref() {
+            entry["comment"] = Value::from(error.as_str());
+        }
+        entry
+    }
+}
+
+/// HarLog records the requests of a download, so the exchanges with a host breaking multi-part
+/// downloads can be inspected, e.g. in the network panel of a browser.
+#[derive(Debug, Default)]
+pub struct HarLog {
+    entries: Mutex<Vec<HarEntry>>,  // Recorded requests, in the order they were answered, at most `MAX_HAR_ENTRIES`
+}
+
+impl HarLog {
+    /// Records an entry, returning its index, or `None` if the log is full.
+    fn push(&self, entry: HarEntry) -> Option<usize> {
+        let mut entries = self.entries.lock().unwrap();
+        (entries.len() < MAX_HAR_ENTRIES).then(|| {
+            entries.push(entry);
+            entries.len() - 1
+        })
+    }
+
+    /// Returns the recorded requests, in the order they were answered.
+    pub fn entries(&self) -> Vec<HarEntry> {
+        self.entries.lock().unwrap().clone()
+    }
+
+    /// Returns the recorded requests as a HAR 1.2 document.
+    pub fn to_json(&self) -> Value {
+        let entries: Vec<Value> = self.entries.lock().unwrap().iter().map(HarEntry::to_json).collect();
+        json!({
+            "log": {
+                "version": "1.2",
+                "creator": { "name": "rustle", "version": env!("CARGO_PKG_VERSION") },
+                "pages": [],
+                "entries": entries,
+            }
+        })
+    }
+
+    /// Writes the recorded requests to a HAR file, replacing it if it exists.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error message if the file couldn't be written.
+    pub fn write(&self, path: &Path) -> Result<(), String> {
+        let har = serde_json::to_string_pretty(&self.to_json()).map_err(|e| format!("Couldn't serialize the HAR log, error : {}", e))?;
+        fs::write(path, har).map_err(|e| format!("Couldn't write the HAR log {}, error : {}", path.display(), e))
+    }
+}
+
+/// HarTransport records the requests sent by another transport in a `HarLog`, along with their
+/// response headers and timings. Credentials and cookies are redacted.
+#[derive(Debug)]
+pub struct HarTransport {
+    transport: Arc<dyn Transport>,  // Transport sending the requests
+    log: Arc<HarLog>,               // Log the requests are recorded in
+    method: Method,                 // Method of the GET requests, see `ClientOptions::method`
+    headers: HeaderMap,             // Headers sent with every request
+}
+
+impl HarTransport {
+    /// Creates a transport recording the requests of `transport` in `log`.
+    ///
+    /// # Arguments
+    ///
+    /// * `transport` - The transport sending the requests.
+    /// * `log` - The log the requests are recorded in.
+    /// * `options` - The options `transport` was built with, for the method and headers of the requests.
+    pub fn new(transport: Arc<dyn Transport>, log: Arc<HarLog>, options: &ClientOptions) -> Self {
+        let mut headers = options.request_headers();
+        if let Some(user_agent) = options.user_agent.as_deref().and_then(|user_agent| HeaderValue::from_str(user_agent).ok()) {
+            headers.insert(USER_AGENT, user_agent);
+        }
+        HarTransport { transport, log, method: options.method.clone(), headers }
+    }
+
+    /// Records the response of `request` once its headers are received, and its body once it ends.
+    fn record(&self, method: &Method, url: &Url, headers: HeaderMap, request: BoxFuture<'static, Result<TransportResponse, TransportError>>) -> BoxFuture<'static, Result<TransportResponse, TransportError>> {
+        let log = self.log.clone();
+        let (method, url, request_headers) = (method.to_string(), public_url(url), header_pairs(&headers));
+
+        Box::pin(async move {
+            let started = SystemTime::now();
+            let start = Instant::now();
+            let result = request.await;
+            let mut entry = HarEntry {
+                started,
+                method,
+                url,
+                request_headers,
+                status: None,
+                http_version: None,
+                response_url: None,
+                response_headers: Vec::new(),
+                error: None,
+                wait: start.elapsed(),
+                receive: None,
+                body_size: 0,
+            };
+            match result {
+                Ok(response) => {
+                    entry.status = Some(response.status.as_u16());
+                    entry.http_version = Some(response.version);
+                    entry.response_url = Some(public_url(&response.url));
+                    entry.response_headers = header_pairs(&response.headers);
+                    let Some(index) = log.push(entry) else { return Ok(response) };
+                    Ok(response.on_body_end(move |body_size, error| {
+                        let mut entries = log.entries.lock().unwrap();
+                        let entry = &mut entries[index];
+                        entry.receive = Some(start.elapsed().saturating_sub(entry.wait));
+                        entry.body_size = body_size;
+                        entry.error = error;
+                    }))
+                },
+                Err(e) => {
+                    entry.error = Some(e.message.clone());
+                    log.push(entry);
+                    Err(e)
+                },
+            }
+        })
+    }
+}
+
+impl Transport for HarTransport {
+    fn head(&self, url: &Url, timeout: Option<Duration>) -> BoxFuture<'static, Result<TransportResponse, TransportError>> {
+        self.record(&Method::HEAD, url, self.headers.clone(), self.transport.head(url, timeout))
+    }
+
+    fn get_range(&self, url: &Url, range: Option<ByteRange>, if_range: Option<&str>, timeout: Option<Duration>) -> BoxFuture<'static, Result<TransportResponse, TransportError>> {
+        let mut headers = self.headers.clone();
+        if let Some(range) = range.and_then(|range| HeaderValue::from_str(&range.to_string()).ok()) {
+            headers.insert(RANGE, range);
+        }
+        if let Some(if_range) = if_range.and_then(|if_range| HeaderValue::from_str(if_range).ok()) {
+            headers.insert(IF_RANGE, if_range);
+        }
+        self.record(&self.method, url, headers, self.transport.get_range(url, range, if_range, timeout))
+    }
+}
+
+/// Returns the headers as name and value pairs, redacting the credentials and cookies.
+fn header_pairs(headers: &HeaderMap) -> Vec<(String, String)> {
+    headers.iter().map(|(name, value)| {
+        let value = match *name {
+            AUTHORIZATION | PROXY_AUTHORIZATION | COOKIE | SET_COOKIE => String::from(REDACTED),
+            _ => String::from_utf8_lossy(value.as_bytes()).into_owned(),
+        };
+        (name.to_string(), value)
+    }).collect()
+}
+
+/// Formats a time as an ISO 8601 timestamp in UTC with milliseconds, e.g. `2024-05-01T12:30:00.250Z`.
+fn format_timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs() % DAY_SECS;
+    let (year, month, day) = civil_from_days(day_of(time));
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z", year, month, day, secs / 3600, secs / 60 % 60, secs % 60, since_epoch.subsec_millis())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_are_exported_as_har() {
+        let entry = HarEntry {
+            started: UNIX_EPOCH + Duration::from_millis(1_714_566_600_250),
+            method: String::from("GET"),
+            url: String::from("https://example.com/file.iso?token=abc"),
+            request_headers: header_pairs(&HeaderMap::from_iter([
+                (RANGE, HeaderValue::from_static("bytes=0-99")),
+                (AUTHORIZATION, HeaderValue::from_static("Bearer secret")),
+            ])),
+            status: Some(206),
+            http_version: Some(Version::HTTP_2),
+            response_url: Some(String::from("https://cdn.example.com/file.iso?token=abc")),
+            response_headers: vec![(String::from("content-type"), String::from("application/x-iso9660-image"))],
+            error: Some(String::from("connection reset")),
+            wait: Duration::from_millis(40),
+            receive: Some(Duration::from_millis(60)),
+            body_size: 100,
+        };
+        assert_eq!(entry.request_headers[1], (String::from("authorization"), String::from(REDACTED)));
+
+        let log = HarLog::default();
+        log.push(entry);
+        let har = log.to_json();
+        let entry = &har["log"]["entries"][0];
+        assert_eq!(har["log"]["version"], "1.2");
+        assert_eq!(entry["startedDateTime"], "2024-05-01T12:30:00.250Z");
+        assert_eq!(entry["time"], 100.0);
+        assert_eq!(entry["request"]["queryString"][0], json!({ "name": "token", "value": "abc" }));
+        assert_eq!(entry["response"]["statusText"], "Partial Content");
+        assert_eq!(entry["response"]["httpVersion"], "HTTP/2.0");
+        assert_eq!(entry["response"]["content"]["mimeType"], "application/x-iso9660-image");
+        assert_eq!(entry["response"]["redirectURL"], "https://cdn.example.com/file.iso?token=abc");
+        assert_eq!(entry["comment"], "connection reset");
+    }
+}
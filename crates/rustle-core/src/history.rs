@@ -0,0 +1,339 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use rusqlite::{params, params_from_iter, Connection, Row};
+use super::audit::sha256_file;
+use super::downloader::RustleDownloader;
+
+/// File name of the history database, in the data directory of the application.
+pub const HISTORY_FILE_NAME: &str = "history.sqlite3";
+
+// Version of the schema, stored in `PRAGMA user_version`
+const SCHEMA_VERSION: i64 = 1;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS downloads (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        url TEXT NOT NULL,
+        file_name TEXT,
+        path TEXT,
+        size INTEGER,
+        started_at INTEGER NOT NULL,
+        finished_at INTEGER NOT NULL,
+        average_speed REAL,
+        sha256 TEXT,
+        status TEXT NOT NULL,
+        error TEXT
+    );
+    CREATE INDEX IF NOT EXISTS downloads_finished_at ON downloads (finished_at);
+";
+
+/// HistoryStatus represents how a download of the history ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HistoryStatus {
+    Done,       // The file was downloaded
+    Failed,     // The download failed after its retries
+}
+
+impl HistoryStatus {
+    /// Returns the name of the status, as stored in the database.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HistoryStatus::Done => "done",
+            HistoryStatus::Failed => "failed",
+        }
+    }
+}
+
+/// HistoryRecord represents a finished or failed download of the history.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryRecord {
+    pub id: Option<i64>,                // Identifier in the database, `None` until it's recorded
+    pub url: String,                    // URL of the file
+    pub file_name: Option<String>,      // Name of the downloaded file
+    pub path: Option<PathBuf>,          // Path the file was written to
+    pub size: Option<u64>,              // Size of the file in bytes, if known
+    pub started_at: SystemTime,         // When the download was started
+    pub finished_at: SystemTime,        // When the download finished or failed
+    pub average_speed: Option<f64>,     // Average speed in bytes per second, of a finished download
+    pub sha256: Option<String>,         // SHA-256 of the downloaded file, lowercase hex
+    pub status: HistoryStatus,          // How the download ended
+    pub error: Option<String>,          // Error of a failed download
+}
+
+/// HistoryQuery represents the filters of `DownloadHistory::query`, the records are returned
+/// newest first.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HistoryQuery {
+    pub search: Option<String>,         // Text the URL or the file name contains, case insensitive
+    pub status: Option<HistoryStatus>,  // Only the downloads that ended this way
+    pub since: Option<SystemTime>,      // Only the downloads finished after this time
+    pub limit: Option<usize>,           // Maximum number of records
+}
+
+/// HistoryRetention represents how long the history keeps its records, older ones are deleted
+/// by `DownloadHistory::prune`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HistoryRetention {
+    pub max_age: Option<Duration>,      // Records finished longer ago are deleted
+    pub max_entries: Option<usize>,     // Only the newest records are kept
+}
+
+/// DownloadHistory represents a persistent log of the finished and failed downloads, stored in
+/// an SQLite database. Cancelled downloads aren't recorded.
+#[derive(Debug)]
+pub struct DownloadHistory {
+    connection: Mutex<Connection>,
+}
+
+impl HistoryRecord {
+    /// Creates the record of a download from its engine and the result of `RustleDownloader::download`,
+    /// hashing the downloaded file.
+    ///
+    /// Returns `None` if the download was cancelled.
+    pub async fn from_engine(engine: &RustleDownloader, result: &Result<bool, String>) -> Option<HistoryRecord> {
+        let error = match result {
+            Ok(true) => None,
+            Ok(false) => return None,
+            Err(e) => Some(e.clone()),
+        };
+        let finished_at = SystemTime::now();
+        let started_at = engine.get_started_at().await.unwrap_or(finished_at);
+        let path = engine.get_file_path().await;
+        let size = engine.get_file_info().await.and_then(|info| info.content_length);
+
+        // The engine hashes the file as it streams, otherwise hashing reads the whole file outside of the runtime
+        let sha256 = match (error.as_ref(), engine.get_sha256().await, path.clone()) {
+            (None, Some(sha256), _) => Some(sha256),
+            (None, None, Some(path)) => tokio::task::spawn_blocking(move || sha256_file(&path)).await.ok().and_then(Result::ok),
+            _ => None,
+        };
+        let average_speed = match (error.as_ref(), size, finished_at.duration_since(started_at)) {
+            (None, Some(size), Ok(duration)) if !duration.is_zero() => Some(size as f64 / duration.as_secs_f64()),
+            _ => None,
+        };
+
+        Some(HistoryRecord {
+            id: None,
+            url: engine.get_url().await.unwrap_or_default(),
+            file_name: path.as_ref().and_then(|path| path.file_name()).map(|name| name.to_string_lossy().into_owned()),
+            path,
+            size,
+            started_at,
+            finished_at,
+            average_speed,
+            sha256,
+            status: if error.is_some() { HistoryStatus::Failed } else { HistoryStatus::Done },
+            error,
+        })
+    }
+
+    /// Returns how long the download took, including the time it was paused.
+    pub fn duration(&self) -> Duration {
+        self.finished_at.duration_since(self.started_at).unwrap_or_default()
+    }
+
+    /// Reads a record from a row of the `downloads` table.
+    fn from_row(row: &Row) -> rusqlite::Result<HistoryRecord> {
+        let status: String = row.get("status")?;
+        Ok(HistoryRecord {
+            id: Some(row.get("id")?),
+            url: row.get("url")?,
+            file_name: row.get("file_name")?,
+            path: row.get::<_, Option<String>>("path")?.map(PathBuf::from),
+            size: row.get::<_, Option<i64>>("size")?.map(|size| size as u64),
+            started_at: from_millis(row.get("started_at")?),
+            finished_at: from_millis(row.get("finished_at")?),
+            average_speed: row.get("average_speed")?,
+            sha256: row.get("sha256")?,
+            status: if status == HistoryStatus::Done.as_str() { HistoryStatus::Done } else { HistoryStatus::Failed },
+            error: row.get("error")?,
+        })
+    }
+}
+
+impl DownloadHistory {
+    /// Opens the history stored at `path`, creating the database and its directory if needed.
+    ///
+    /// Returns an error if the database couldn't be opened or was created by a newer version.
+    pub fn open(path: &Path) -> Result<DownloadHistory, String> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir).map_err(|e| format!("Couldn't create {}, error : {}", dir.display(), e))?;
+        }
+        let connection = Connection::open(path).map_err(|e| format!("Couldn't open the history {}, error : {}", path.display(), e))?;
+        DownloadHistory::with_connection(connection)
+    }
+
+    /// Opens a history kept in memory, lost once dropped.
+    pub fn open_in_memory() -> Result<DownloadHistory, String> {
+        let connection = Connection::open_in_memory().map_err(|e| format!("Couldn't open the history, error : {}", e))?;
+        DownloadHistory::with_connection(connection)
+    }
+
+    /// Creates the schema of a new database, or checks the version of an existing one.
+    fn with_connection(connection: Connection) -> Result<DownloadHistory, String> {
+        let version: i64 = connection.query_row("PRAGMA user_version", [], |row| row.get(0)).map_err(history_error)?;
+        if version > SCHEMA_VERSION {
+            return Err(format!("The history was created by a newer version of rustle (schema {})", version));
+        }
+        connection.execute_batch(SCHEMA).map_err(history_error)?;
+        connection.pragma_update(None, "user_version", SCHEMA_VERSION).map_err(history_error)?;
+        Ok(DownloadHistory { connection: Mutex::new(connection) })
+    }
+
+    /// Records a finished or failed download and returns its identifier.
+    pub fn record(&self, record: &HistoryRecord) -> Result<i64, String> {
+        let connection = self.connection();
+        connection.execute(
+            "INSERT INTO downloads (url, file_name, path, size, started_at, finished_at, average_speed, sha256, status, error)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            params![
+                record.url,
+                record.file_name,
+                record.path.as_ref().map(|path| path.to_string_lossy().into_owned()),
+                record.size.map(|size| size as i64),
+                to_millis(record.started_at),
+                to_millis(record.finished_at),
+                record.average_speed,
+                record.sha256,
+                record.status.as_str(),
+                record.error,
+            ],
+        ).map_err(history_error)?;
+        Ok(connection.last_insert_rowid())
+    }
+
+    /// Records a download once `RustleDownloader::download` returned `result`, see `HistoryRecord::from_engine`.
+    /// Cancelled downloads aren't recorded.
+    pub async fn record_for(&self, engine: &RustleDownloader, result: &Result<bool, String>) -> Result<(), String> {
+        if let Some(record) = HistoryRecord::from_engine(engine, result).await {
+            self.record(&record)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the records matching `query`, newest first.
+    pub fn query(&self, query: &HistoryQuery) -> Result<Vec<HistoryRecord>, String> {
+        let mut conditions = Vec::new();
+        let mut values: Vec<rusqlite::types::Value> = Vec::new();
+        if let Some(search) = query.search.as_ref().filter(|search| !search.is_empty()) {
+            // `LIKE` is case insensitive for ASCII, the wildcards of the search are matched literally
+            let pattern = format!("%{}%", search.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_"));
+            conditions.push("(url LIKE ? ESCAPE '\\' OR file_name LIKE ? ESCAPE '\\')");
+            values.push(pattern.clone().into());
+            values.push(pattern.into());
+        }
+        if let Some(status) = query.status {
+            conditions.push("status = ?");
+            values.push(status.as_str().to_string().into());
+        }
+        if let Some(since) = query.since {
+            conditions.push("finished_at >= ?");
+            values.push(to_millis(since).into());
+        }
+
+        let mut sql = String::from("SELECT * FROM downloads");
+        if !conditions.is_empty() {
+            sql.push_str(" WHERE ");
+            sql.push_str(&conditions.join(" AND "));
+        }
+        sql.push_str(" ORDER BY finished_at DESC, id DESC");
+        if let Some(limit) = query.limit {
+            sql.push_str(&format!(" LIMIT {}", limit));
+        }
+
+        let connection = self.connection();
+        let mut statement = connection.prepare(&sql).map_err(history_error)?;
+        let records = statement.query_map(params_from_iter(values), HistoryRecord::from_row).map_err(history_error)?
+            .collect::<rusqlite::Result<Vec<HistoryRecord>>>()
+            .map_err(history_error)?;
+        Ok(records)
+    }
+
+    /// Deletes the records older than the retention allows, returning how many were deleted.
+    pub fn prune(&self, retention: &HistoryRetention) -> Result<usize, String> {
+        let connection = self.connection();
+        let mut deleted = 0;
+        if let Some(max_age) = retention.max_age {
+            let oldest = SystemTime::now().checked_sub(max_age).unwrap_or(UNIX_EPOCH);
+            deleted += connection.execute("DELETE FROM downloads WHERE finished_at < ?1", params![to_millis(oldest)])
+                .map_err(history_error)?;
+        }
+        if let Some(max_entries) = retention.max_entries {
+            deleted += connection.execute(
+                "DELETE FROM downloads WHERE id NOT IN (SELECT id FROM downloads ORDER BY finished_at DESC, id DESC LIMIT ?1)",
+                params![max_entries as i64],
+            ).map_err(history_error)?;
+        }
+        Ok(deleted)
+    }
+
+    /// Returns the connection to the database, locked.
+    pub(crate) fn connection(&self) -> MutexGuard<'_, Connection> {
+        self.connection.lock().unwrap()
+    }
+
+    /// Deletes all the records.
+    pub fn clear(&self) -> Result<(), String> {
+        self.connection().execute("DELETE FROM downloads", []).map_err(history_error)?;
+        Ok(())
+    }
+}
+
+/// Formats an error of the database.
+fn history_error(e: rusqlite::Error) -> String {
+    format!("History error : {}", e)
+}
+
+/// Returns the milliseconds since the Unix epoch of a time, as stored in the database.
+fn to_millis(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH).map(|duration| duration.as_millis() as i64).unwrap_or(0)
+}
+
+/// Returns the time of milliseconds since the Unix epoch.
+fn from_millis(millis: i64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_millis(millis.max(0) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(url: &str, finished_secs: u64, status: HistoryStatus) -> HistoryRecord {
+        HistoryRecord {
+            id: None,
+            url: url.to_string(),
+            file_name: url.rsplit('/').next().map(String::from),
+            path: None,
+            size: Some(1024),
+            started_at: UNIX_EPOCH + Duration::from_secs(finished_secs - 2),
+            finished_at: UNIX_EPOCH + Duration::from_secs(finished_secs),
+            average_speed: Some(512.0),
+            sha256: None,
+            status,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn query_and_prune_records() {
+        let history = DownloadHistory::open_in_memory().unwrap();
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        history.record(&record("https://example.com/old.iso", now - 10 * 24 * 3600, HistoryStatus::Done)).unwrap();
+        history.record(&record("https://example.com/a_b.zip", now - 60, HistoryStatus::Failed)).unwrap();
+        let id = history.record(&record("https://example.com/new.iso", now, HistoryStatus::Done)).unwrap();
+
+        let all = history.query(&HistoryQuery::default()).unwrap();
+        assert_eq!(all.iter().map(|record| record.file_name.as_deref().unwrap()).collect::<Vec<_>>(), vec!["new.iso", "a_b.zip", "old.iso"]);
+        assert_eq!(all[0], HistoryRecord { id: Some(id), ..record("https://example.com/new.iso", now, HistoryStatus::Done) });
+        assert_eq!(all[0].duration(), Duration::from_secs(2));
+
+        let isos = history.query(&HistoryQuery { search: Some(String::from(".ISO")), status: Some(HistoryStatus::Done), limit: Some(1), ..Default::default() }).unwrap();
+        assert_eq!(isos.len(), 1);
+        assert_eq!(history.query(&HistoryQuery { search: Some(String::from("a_")), ..Default::default() }).unwrap().len(), 1);
+
+        assert_eq!(history.prune(&HistoryRetention { max_age: Some(Duration::from_secs(24 * 3600)), max_entries: Some(1) }).unwrap(), 2);
+        assert_eq!(history.query(&HistoryQuery::default()).unwrap().len(), 1);
+    }
+}
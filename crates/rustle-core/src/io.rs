@@ -0,0 +1,677 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use bytes::Bytes;
+use futures::future::BoxFuture;
+
+/// File name used when no usable name can be detected for a download.
+pub const DEFAULT_FILE_NAME: &str = "download_file";
+
+/// Default size of the writes of the file writers. The received chunks are coalesced in memory
+/// and the file is written in writes of this size, larger writes suit NVMe drives and smaller
+/// ones slow HDDs.
+pub const DEFAULT_WRITE_SIZE: usize = 1024 * 1024;
+
+/// Maximum length in bytes of a sanitized file name. File systems allow 255, the rest is left
+/// for the suffixes of the staged and part files.
+pub const MAX_FILE_NAME_LEN: usize = 200;
+
+/// Extension of the files holding the bytes of the parts until the file is complete, see `BufferingStrategy`.
+pub const PART_BUFFER_EXTENSION: &str = "rustle-buf";
+
+/// Length from which paths need the `\\?\` prefix on Windows, the `MAX_PATH` of its APIs.
+pub const WINDOWS_MAX_PATH: usize = 260;
+
+/// Content types of scripts and executables, whose files get the executable bits with
+/// `FilePermissions::executable_types`.
+const EXECUTABLE_TYPES: &[&str] = &[
+    "application/x-executable", "application/x-elf", "application/x-pie-executable", "application/x-mach-binary",
+    "application/x-appimage", "application/vnd.appimage", "application/x-iso9660-appimage",
+    "application/x-sh", "application/x-shellscript", "text/x-sh", "text/x-shellscript",
+    "text/x-python", "text/x-script.python", "application/x-perl", "text/x-perl",
+];
+
+/// Names of the devices of Windows, a file named like one, whatever its extension, opens the device.
+/// `COM0`-`COM9` and `LPT0`-`LPT9` are handled by `is_reserved_name`.
+const RESERVED_NAMES: &[&str] = &["CON", "PRN", "AUX", "NUL", "CONIN$", "CONOUT$"];
+
+/// Write bytes to a file in a specified directory.
+///
+/// # Arguments
+///
+/// * `bytes` - The bytes to be written to the file.
+/// * `file_name` - The name of the file to be created or overwritten.
+/// * `out_dir` - The directory in which the file will be created, if it doesn't exist.
+///
+/// # Errors
+///
+/// Returns an `io::Error` if there was any error creating the directory, creating the file,
+/// or writing the bytes to the file.
+pub fn write_bytes_to_file_in_dir(
+    bytes: &bytes::Bytes,
+    file_name: &str,
+    out_dir: &Path,
+) -> Result<(), io::Error> {
+    write_bytes_in_chunks(bytes, &out_dir.join(file_name), bytes.len())
+}
+
+/// Writes bytes to a file in writes of at most `write_size` bytes, creating its directory if it doesn't exist.
+///
+/// # Arguments
+///
+/// * `bytes` - The bytes to be written to the file.
+/// * `file_path` - The path of the file to be created or overwritten.
+/// * `write_size` - The maximum size of each write.
+///
+/// # Errors
+///
+/// Returns an `io::Error` if there was any error creating the directory, creating the file,
+/// or writing the bytes to the file.
+pub fn write_bytes_in_chunks(bytes: &[u8], file_path: &Path, write_size: usize) -> Result<(), io::Error> {
+    let file_path = long_path(file_path);
+    // Create the output directory if it doesn't exist
+    if let Some(dir) = file_path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let mut file = File::create(&file_path)?;
+    for chunk in bytes.chunks(write_size.max(1)) {
+        file.write_all(chunk)?;
+    }
+
+    Ok(())
+}
+
+/// FileWriter writes the files of the downloads once their content is complete and verified.
+/// `StdFileWriter` is used by default, `UringFileWriter` submits the writes through io_uring on
+/// Linux when the `io-uring` feature is enabled.
+pub trait FileWriter: fmt::Debug + Send + Sync {
+    /// Writes `bytes` to the file `file_name` of `out_dir`, creating the directory if it doesn't exist.
+    fn write_file(&self, bytes: Bytes, file_name: &str, out_dir: &Path) -> BoxFuture<'static, Result<(), io::Error>>;
+}
+
+/// StdFileWriter writes the files with `std::fs` on a blocking task, see `write_bytes_in_chunks`.
+#[derive(Debug, Clone, Copy)]
+pub struct StdFileWriter {
+    pub write_size: usize,  // Maximum size of each write, `DEFAULT_WRITE_SIZE` by default
+}
+
+impl Default for StdFileWriter {
+    fn default() -> Self {
+        StdFileWriter { write_size: DEFAULT_WRITE_SIZE }
+    }
+}
+
+impl FileWriter for StdFileWriter {
+    fn write_file(&self, bytes: Bytes, file_name: &str, out_dir: &Path) -> BoxFuture<'static, Result<(), io::Error>> {
+        let (file_path, write_size) = (out_dir.join(file_name), self.write_size);
+        Box::pin(async move {
+            tokio::task::spawn_blocking(move || write_bytes_in_chunks(&bytes, &file_path, write_size)).await
+                .map_err(io::Error::other)?
+        })
+    }
+}
+
+/// Number of writes `UringFileWriter` keeps in flight.
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+const URING_QUEUE_DEPTH: usize = 32;
+
+/// UringFileWriter writes the files through io_uring, `URING_QUEUE_DEPTH` writes of
+/// `write_size` bytes being in flight at once, so writing isn't the bottleneck of very fast
+/// links. io_uring needs its own runtime, each file is written from a thread running one.
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+#[derive(Debug, Clone, Copy)]
+pub struct UringFileWriter {
+    pub write_size: usize,  // Size of the submitted writes, `DEFAULT_WRITE_SIZE` by default
+}
+
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+impl Default for UringFileWriter {
+    fn default() -> Self {
+        UringFileWriter { write_size: DEFAULT_WRITE_SIZE }
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+impl FileWriter for UringFileWriter {
+    fn write_file(&self, bytes: Bytes, file_name: &str, out_dir: &Path) -> BoxFuture<'static, Result<(), io::Error>> {
+        let (file_path, out_dir, write_size) = (out_dir.join(file_name), out_dir.to_path_buf(), self.write_size.max(1));
+        Box::pin(async move {
+            let (sender, receiver) = tokio::sync::oneshot::channel();
+            std::thread::spawn(move || {
+                let _ = sender.send(tokio_uring::start(write_with_uring(bytes, file_path, out_dir, write_size)));
+            });
+            receiver.await.map_err(|_| io::Error::other("The io_uring writer stopped"))?
+        })
+    }
+}
+
+/// Writes `bytes` to `file_path` on the io_uring runtime of the current thread, see `UringFileWriter`.
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+async fn write_with_uring(bytes: Bytes, file_path: PathBuf, out_dir: PathBuf, write_size: usize) -> Result<(), io::Error> {
+    use futures::{StreamExt, TryStreamExt};
+
+    fs::create_dir_all(out_dir)?;
+    let file = tokio_uring::fs::File::create(&file_path).await?;
+    let written = futures::stream::iter((0..bytes.len()).step_by(write_size))
+        .map(|offset| {
+            let (file, chunk) = (&file, bytes.slice(offset..(offset + write_size).min(bytes.len())));
+            async move { file.write_all_at(chunk, offset as u64).await.0 }
+        })
+        .buffer_unordered(URING_QUEUE_DEPTH)
+        .try_collect::<()>()
+        .await;
+    let closed = file.close().await;
+    written.and(closed)
+}
+
+/// BufferingStrategy represents where the parts keep the bytes they receive until the file is complete.
+/// Downloads requesting the whole file, e.g. over a single connection from servers without ranges,
+/// are always buffered in memory, their compressed responses are decoded once complete.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum BufferingStrategy {
+    /// In memory, the file is written once complete. The fastest for small files, large ones are held whole.
+    #[default]
+    Memory,
+    /// In a temporary file per part next to the file, concatenated into the file once complete.
+    PartFiles,
+    /// At their offset in a temporary file next to the file, renamed once complete. It writes every
+    /// byte once, on file systems supporting sparse files.
+    Direct,
+}
+
+impl BufferingStrategy {
+    /// All the strategies, in the order they are presented to the user.
+    pub const ALL: [BufferingStrategy; 3] = [BufferingStrategy::Memory, BufferingStrategy::PartFiles, BufferingStrategy::Direct];
+
+    /// Returns the name used for the strategy in the settings file.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BufferingStrategy::Memory => "memory",
+            BufferingStrategy::PartFiles => "part_files",
+            BufferingStrategy::Direct => "direct",
+        }
+    }
+}
+
+impl fmt::Display for BufferingStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for BufferingStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        BufferingStrategy::ALL.into_iter()
+            .find(|strategy| strategy.as_str() == s)
+            .ok_or_else(|| format!("Unknown buffering strategy `{}`", s))
+    }
+}
+
+/// PartBuffers writes the bytes received by the parts of a download to disk, following a
+/// `BufferingStrategy` other than `Memory`, and puts the file together once they're complete.
+/// Parts are identified by their first byte, which doesn't change when a part is split.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PartBuffers {
+    strategy: BufferingStrategy,    // `PartFiles` or `Direct`
+    path: PathBuf,                  // Path of the file, the buffers are named after it
+}
+
+impl PartBuffers {
+    /// Creates the buffers of the file at `path`, `None` for the `Memory` strategy.
+    pub fn new(strategy: BufferingStrategy, path: PathBuf) -> Option<PartBuffers> {
+        (strategy != BufferingStrategy::Memory).then_some(PartBuffers { strategy, path })
+    }
+
+    /// Returns the path of the file holding the bytes of the part starting at `part_start`, the
+    /// file is put together there with the `Direct` strategy.
+    pub fn path(&self, part_start: u64) -> PathBuf {
+        let mut path = self.path.as_os_str().to_os_string();
+        match self.strategy {
+            BufferingStrategy::PartFiles => path.push(format!(".{}.{}", part_start, PART_BUFFER_EXTENSION)),
+            _ => path.push(format!(".{}", PART_BUFFER_EXTENSION)),
+        }
+        long_path(&PathBuf::from(path))
+    }
+
+    /// Returns the offset of the byte `offset` of the part starting at `part_start` in its buffer.
+    fn offset(&self, part_start: u64, offset: u64) -> u64 {
+        match self.strategy {
+            BufferingStrategy::PartFiles => offset,
+            _ => part_start + offset,
+        }
+    }
+
+    /// Writes `bytes` from the byte `offset` of the part starting at `part_start`, creating its
+    /// buffer and its directory if they don't exist.
+    pub fn write(&self, part_start: u64, offset: u64, bytes: &[u8]) -> Result<(), io::Error> {
+        let path = self.path(part_start);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut file = fs::OpenOptions::new().write(true).create(true).truncate(false).open(path)?;
+        file.seek(SeekFrom::Start(self.offset(part_start, offset)))?;
+        file.write_all(bytes)
+    }
+
+    /// Reads the first `len` bytes written for the part starting at `part_start`.
+    pub fn read(&self, part_start: u64, len: u64) -> Result<Vec<u8>, io::Error> {
+        let mut file = File::open(self.path(part_start))?;
+        file.seek(SeekFrom::Start(self.offset(part_start, 0)))?;
+        let mut bytes = Vec::with_capacity(len as usize);
+        file.take(len).read_to_end(&mut bytes)?;
+        if bytes.len() as u64 != len {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "The buffer of the part is shorter than the bytes written to it"));
+        }
+        Ok(bytes)
+    }
+
+    /// Removes the buffers of the parts starting at `part_starts`, the missing ones are ignored.
+    pub fn remove(&self, part_starts: impl IntoIterator<Item = u64>) {
+        let mut paths: Vec<PathBuf> = part_starts.into_iter().map(|part_start| self.path(part_start)).collect();
+        paths.dedup();
+        for path in paths {
+            let _ = fs::remove_file(path);
+        }
+    }
+
+    /// Puts the file together from the parts, each given by its first byte, the number of its bytes
+    /// written to its buffer and the bytes it still holds in memory, in file order. The bytes of
+    /// the file from `from` are passed in order to `on_bytes` along with their offset, e.g. to
+    /// hash the ones that weren't hashed while they streamed.
+    ///
+    /// Returns the path of the complete file, next to the file, that's then renamed to it.
+    pub fn assemble(&self, parts: &[(u64, u64, Bytes)], from: u64, mut on_bytes: impl FnMut(u64, &[u8])) -> Result<PathBuf, io::Error> {
+        let path = PartBuffers { strategy: BufferingStrategy::Direct, path: self.path.clone() }.path(0);
+        let mut chunk = vec![0; MOVE_CHUNK_SIZE];
+        let mut on_bytes = |offset: u64, bytes: &[u8]| {
+            let skipped = from.saturating_sub(offset).min(bytes.len() as u64) as usize;
+            if skipped < bytes.len() {
+                on_bytes(offset + skipped as u64, &bytes[skipped..]);
+            }
+        };
+        match self.strategy {
+            BufferingStrategy::PartFiles => {
+                let mut file = File::create(&path)?;
+                let mut offset = 0;
+                for (part_start, flushed, bytes) in parts {
+                    let mut buffer = File::open(self.path(*part_start))?.take(*flushed);
+                    loop {
+                        let read = buffer.read(&mut chunk)?;
+                        if read == 0 {
+                            break;
+                        }
+                        file.write_all(&chunk[..read])?;
+                        on_bytes(offset, &chunk[..read]);
+                        offset += read as u64;
+                    }
+                    file.write_all(bytes)?;
+                    on_bytes(offset, bytes);
+                    offset += bytes.len() as u64;
+                }
+                file.sync_all()?;
+                self.remove(parts.iter().map(|(part_start, _, _)| *part_start));
+            },
+            _ => {
+                for (part_start, flushed, bytes) in parts.iter().filter(|(_, _, bytes)| !bytes.is_empty()) {
+                    self.write(*part_start, *flushed, bytes)?;
+                }
+                let len = parts.last().map_or(0, |(part_start, flushed, bytes)| part_start + flushed + bytes.len() as u64);
+                let mut file = fs::OpenOptions::new().read(true).write(true).create(true).truncate(false).open(&path)?;
+                file.set_len(len)?;
+                let mut offset = file.seek(SeekFrom::Start(from.min(len)))?;
+                loop {
+                    let read = file.read(&mut chunk)?;
+                    if read == 0 {
+                        break;
+                    }
+                    on_bytes(offset, &chunk[..read]);
+                    offset += read as u64;
+                }
+                file.sync_all()?;
+            },
+        }
+        Ok(path)
+    }
+}
+
+/// Size of the chunks a file moved across file systems is copied by.
+const MOVE_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// MoveProgress represents the progress of a file moved by `move_file`, updated while it's copied.
+#[derive(Debug, Default)]
+pub struct MoveProgress {
+    pub moved: AtomicU64,   // Number of bytes moved so far
+    pub total: AtomicU64,   // Size of the moved file
+}
+
+/// Moves a file, e.g. from the staging directory to the output directory, creating the directory
+/// of `to` if it doesn't exist. The file is renamed when both paths are on the same file system,
+/// otherwise it's copied next to `to` under a temporary name, renamed once complete and removed
+/// from `from`, so `to` never holds a partial file.
+///
+/// # Arguments
+///
+/// * `from` - The path of the moved file.
+/// * `to` - The path it's moved to, replaced if it exists.
+/// * `progress` - Updated with the number of bytes moved so far.
+///
+/// # Errors
+///
+/// Returns an `io::Error` if the file couldn't be renamed nor copied.
+pub fn move_file(from: &Path, to: &Path, progress: &MoveProgress) -> Result<(), io::Error> {
+    let (from, to) = (&long_path(from), &long_path(to));
+    let total = fs::metadata(from)?.len();
+    progress.total.store(total, Ordering::Relaxed);
+    if let Some(dir) = to.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    match fs::rename(from, to) {
+        Ok(()) => {
+            progress.moved.store(total, Ordering::Relaxed);
+            Ok(())
+        },
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            copy_file(from, to, progress)?;
+            fs::remove_file(from)
+        },
+        Err(e) => Err(e),
+    }
+}
+
+/// Copies `from` to `to` through a temporary file next to `to`, see `move_file`.
+fn copy_file(from: &Path, to: &Path, progress: &MoveProgress) -> Result<(), io::Error> {
+    let mut temporary = to.as_os_str().to_os_string();
+    temporary.push(".moving");
+    let temporary = PathBuf::from(temporary);
+
+    let copy = || -> Result<(), io::Error> {
+        let mut source = File::open(from)?;
+        let mut target = File::create(&temporary)?;
+        let mut chunk = vec![0; MOVE_CHUNK_SIZE];
+        loop {
+            let read = source.read(&mut chunk)?;
+            if read == 0 {
+                break;
+            }
+            target.write_all(&chunk[..read])?;
+            progress.moved.fetch_add(read as u64, Ordering::Relaxed);
+        }
+        // The copy is on disk before the source is removed
+        target.sync_all()?;
+        fs::rename(&temporary, to)
+    };
+    copy().inspect_err(|_| { let _ = fs::remove_file(&temporary); })
+}
+
+/// FilePermissions sets the mode of the completed files on Unix, it's ignored on other platforms.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FilePermissions {
+    pub mode: Option<u32>,      // Mode of the files, e.g. `0o644`, the one given by the umask if `None`
+    pub executable_types: bool, // Add the executable bits to the files of scripts and executables, see `is_executable_type`
+}
+
+impl FilePermissions {
+    /// Returns the mode of a completed file, the executable bits being added where the file is
+    /// readable, e.g. `0o644` becomes `0o755`.
+    ///
+    /// # Arguments
+    ///
+    /// * `current` - The mode the file was created with.
+    /// * `content_type` - The `Content-Type` of the file, if sent.
+    pub fn mode_of(&self, current: u32, content_type: Option<&str>) -> u32 {
+        let mode = self.mode.unwrap_or(current) & 0o7777;
+        match self.executable_types && content_type.is_some_and(is_executable_type) {
+            true => mode | ((mode & 0o444) >> 2),
+            false => mode,
+        }
+    }
+
+    /// Sets the mode of a completed file, see `mode_of`. Does nothing on other platforms than Unix.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::Error` if the mode of the file couldn't be read or set.
+    pub fn apply(&self, path: &Path, content_type: Option<&str>) -> Result<(), io::Error> {
+        #[cfg(unix)]
+        if *self != FilePermissions::default() {
+            use std::os::unix::fs::PermissionsExt;
+            let mut permissions = fs::metadata(path)?.permissions();
+            let mode = self.mode_of(permissions.mode(), content_type);
+            if mode != permissions.mode() & 0o7777 {
+                permissions.set_mode(mode);
+                fs::set_permissions(path, permissions)?;
+            }
+        }
+        #[cfg(not(unix))]
+        let _ = (path, content_type);
+        Ok(())
+    }
+}
+
+/// Whether a `Content-Type` is the one of a script or an executable, e.g. `application/x-sh` or
+/// `application/vnd.appimage`. Its parameters and case are ignored.
+pub fn is_executable_type(content_type: &str) -> bool {
+    let mime = content_type.split(';').next().unwrap_or_default().trim();
+    EXECUTABLE_TYPES.iter().any(|executable| executable.eq_ignore_ascii_case(mime))
+}
+
+/// Returns the path to use with the file system APIs, i.e. on Windows the absolute path with the
+/// `\\?\` prefix when it's longer than `WINDOWS_MAX_PATH`, see `windows_long_path`. Other paths,
+/// and all of them on other platforms, are returned as is.
+///
+/// # Arguments
+///
+/// * `path` - The path of a file or a directory.
+pub fn long_path(path: &Path) -> PathBuf {
+    #[cfg(windows)]
+    if let Some(prefixed) = std::path::absolute(path).ok().as_deref().and_then(Path::to_str).and_then(windows_long_path) {
+        return PathBuf::from(prefixed);
+    }
+    path.to_path_buf()
+}
+
+/// Adds the `\\?\` prefix to an absolute Windows path longer than `WINDOWS_MAX_PATH`, so the
+/// Windows APIs accept it, e.g. `\\?\C:\downloads\...` or `\\?\UNC\server\share\...`.
+///
+/// # Arguments
+///
+/// * `path` - An absolute and normalized Windows path, the prefix disables the normalization.
+///
+/// # Returns
+///
+/// The prefixed path, or `None` if the path is short enough, relative or already prefixed.
+pub fn windows_long_path(path: &str) -> Option<String> {
+    if path.encode_utf16().count() < WINDOWS_MAX_PATH || path.starts_with(r"\\?\") || path.starts_with(r"\\.\") {
+        return None;
+    }
+    if let Some(unc) = path.strip_prefix(r"\\") {
+        return Some(format!(r"\\?\UNC\{}", unc));
+    }
+    let drive = path.as_bytes();
+    (drive.len() > 2 && drive[0].is_ascii_alphabetic() && drive[1] == b':' && drive[2] == b'\\').then(|| format!(r"\\?\{}", path))
+}
+
+/// Whether a file name opens a device on Windows, e.g. `CON` or `aux.txt`. The part of the name
+/// before its first dot is compared, ignoring the case and the trailing spaces.
+///
+/// # Arguments
+///
+/// * `file_name` - The name of a file, without its directory.
+pub fn is_reserved_name(file_name: &str) -> bool {
+    let stem = file_name.split('.').next().unwrap_or_default().trim_end().to_ascii_uppercase();
+    RESERVED_NAMES.contains(&stem.as_str()) || stem.strip_prefix("COM").or_else(|| stem.strip_prefix("LPT"))
+        .is_some_and(|number| matches!(number, "0" | "1" | "2" | "3" | "4" | "5" | "6" | "7" | "8" | "9" | "\u{b9}" | "\u{b2}" | "\u{b3}"))
+}
+
+/// Shortens a file name to `MAX_FILE_NAME_LEN` bytes, keeping its extension when it's short.
+fn truncate_file_name(file_name: &str) -> String {
+    if file_name.len() <= MAX_FILE_NAME_LEN {
+        return file_name.to_string();
+    }
+    let extension = file_name.rsplit_once('.').map(|(_, extension)| extension).filter(|extension| extension.len() <= 16).unwrap_or_default();
+    let mut stem_len = MAX_FILE_NAME_LEN - extension.len() - usize::from(!extension.is_empty());
+    while !file_name.is_char_boundary(stem_len) {
+        stem_len -= 1;
+    }
+    match extension {
+        "" => file_name[..stem_len].trim_end().to_string(),
+        extension => format!("{}.{}", file_name[..stem_len].trim_end(), extension),
+    }
+}
+
+/// Turns a file name received from a server into a safe name for a file in the output directory.
+///
+/// Only the last path component is kept, so names like `../../.bashrc` can't escape the
+/// output directory, control characters and characters reserved on Windows are replaced
+/// with `_`, and surrounding whitespace and trailing dots are removed. The names of the
+/// Windows devices get a `_` after their stem, e.g. `aux.txt` becomes `aux_.txt`, and
+/// names longer than `MAX_FILE_NAME_LEN` bytes are shortened.
+///
+/// # Arguments
+///
+/// * `file_name` - The file name to sanitize.
+///
+/// # Returns
+///
+/// The sanitized file name, or `DEFAULT_FILE_NAME` if nothing usable is left.
+pub fn sanitize_file_name(file_name: &str) -> String {
+    let last_component = file_name.rsplit(['/', '\\']).next().unwrap_or_default();
+
+    let sanitized: String = last_component
+        .chars()
+        .map(|c| if c.is_control() || matches!(c, '<' | '>' | ':' | '"' | '|' | '?' | '*') { '_' } else { c })
+        .collect();
+    let sanitized = sanitized.trim().trim_end_matches('.').trim_end();
+
+    if sanitized.is_empty() || sanitized == "." || sanitized == ".." {
+        return DEFAULT_FILE_NAME.to_string();
+    }
+    if is_reserved_name(sanitized) {
+        let (stem, extension) = sanitized.split_at(sanitized.find('.').unwrap_or(sanitized.len()));
+        return format!("{}_{}", stem.trim_end(), extension);
+    }
+    truncate_file_name(sanitized)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn file_writers_write_the_content() {
+        let out_dir = std::env::temp_dir().join(format!("rustle-test-writers-{}", std::process::id()));
+        let content = Bytes::from((0..3 * 1024 * 1024 + 17).map(|i| (i % 251) as u8).collect::<Vec<u8>>());
+
+        StdFileWriter::default().write_file(content.clone(), "std.bin", &out_dir).await.unwrap();
+        assert_eq!(fs::read(out_dir.join("std.bin")).unwrap(), content);
+        StdFileWriter { write_size: 4096 }.write_file(content.clone(), "small-writes.bin", &out_dir).await.unwrap();
+        assert_eq!(fs::read(out_dir.join("small-writes.bin")).unwrap(), content);
+        #[cfg(all(target_os = "linux", feature = "io-uring"))]
+        {
+            UringFileWriter::default().write_file(content.clone(), "uring.bin", &out_dir).await.unwrap();
+            assert_eq!(fs::read(out_dir.join("uring.bin")).unwrap(), content);
+            UringFileWriter { write_size: 4096 }.write_file(content.clone(), "uring-small-writes.bin", &out_dir).await.unwrap();
+            assert_eq!(fs::read(out_dir.join("uring-small-writes.bin")).unwrap(), content);
+        }
+        fs::remove_dir_all(out_dir).unwrap();
+    }
+
+    #[test]
+    fn part_buffers_put_the_file_together() {
+        let dir = std::env::temp_dir().join(format!("rustle-test-buffers-{}", std::process::id()));
+        let content: Vec<u8> = (0..100_000).map(|i| (i % 251) as u8).collect();
+        assert_eq!(PartBuffers::new(BufferingStrategy::Memory, dir.join("file.bin")), None);
+        assert_eq!("part_files".parse(), Ok(BufferingStrategy::PartFiles));
+
+        for strategy in [BufferingStrategy::PartFiles, BufferingStrategy::Direct] {
+            let buffers = PartBuffers::new(strategy, dir.join("file.bin")).unwrap();
+            // The second part is written out of order and keeps its last bytes in memory
+            buffers.write(60_000, 10_000, &content[70_000..90_000]).unwrap();
+            buffers.write(60_000, 0, &content[60_000..70_000]).unwrap();
+            buffers.write(0, 0, &content[..60_000]).unwrap();
+            assert_eq!(buffers.read(60_000, 30_000).unwrap(), content[60_000..90_000]);
+            assert!(buffers.read(60_000, 40_000).is_err());
+
+            let parts = [(0, 60_000, Bytes::new()), (60_000, 30_000, Bytes::copy_from_slice(&content[90_000..]))];
+            let mut hashed = Vec::new();
+            let path = buffers.assemble(&parts, 50_000, |offset, bytes| {
+                assert_eq!(offset, 50_000 + hashed.len() as u64);
+                hashed.extend_from_slice(bytes);
+            }).unwrap();
+            assert_eq!(fs::read(&path).unwrap(), content);
+            assert_eq!(hashed, content[50_000..]);
+            fs::remove_file(path).unwrap();
+            assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn files_are_moved_and_copied() {
+        let dir = std::env::temp_dir().join(format!("rustle-test-move-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let content: Vec<u8> = (0..2 * MOVE_CHUNK_SIZE + 5).map(|i| (i % 251) as u8).collect();
+        fs::write(dir.join("staged.bin"), &content).unwrap();
+
+        let progress = MoveProgress::default();
+        move_file(&dir.join("staged.bin"), &dir.join("out").join("file.bin"), &progress).unwrap();
+        assert!(!dir.join("staged.bin").exists());
+        assert_eq!(fs::read(dir.join("out").join("file.bin")).unwrap(), content);
+        assert_eq!(progress.moved.load(Ordering::Relaxed), content.len() as u64);
+
+        // Across file systems the file is copied chunk by chunk
+        let progress = MoveProgress::default();
+        copy_file(&dir.join("out").join("file.bin"), &dir.join("copy.bin"), &progress).unwrap();
+        assert_eq!(fs::read(dir.join("copy.bin")).unwrap(), content);
+        assert_eq!(progress.moved.load(Ordering::Relaxed), content.len() as u64);
+        assert!(!dir.join("copy.bin.moving").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn file_permissions_set_the_mode() {
+        let permissions = FilePermissions { mode: Some(0o640), executable_types: true };
+        assert_eq!(permissions.mode_of(0o600, Some("application/octet-stream")), 0o640);
+        assert_eq!(permissions.mode_of(0o600, Some("Application/X-Sh; charset=utf-8")), 0o750);
+        assert_eq!(FilePermissions { mode: None, executable_types: true }.mode_of(0o100644, Some("application/vnd.appimage")), 0o755);
+        assert_eq!(FilePermissions::default().mode_of(0o644, Some("application/x-sh")), 0o644);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let path = std::env::temp_dir().join(format!("rustle-test-mode-{}.sh", std::process::id()));
+            fs::write(&path, "#!/bin/sh\n").unwrap();
+            permissions.apply(&path, Some("text/x-shellscript")).unwrap();
+            assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o7777, 0o750);
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn windows_names_and_paths_are_handled() {
+        assert_eq!(sanitize_file_name("CON"), "CON_");
+        assert_eq!(sanitize_file_name("aux.txt"), "aux_.txt");
+        assert_eq!(sanitize_file_name("Lpt1 .tar.gz"), "Lpt1_.tar.gz");
+        assert_eq!(sanitize_file_name("com\u{b9}"), "com\u{b9}_");
+        assert_eq!(sanitize_file_name("console.log"), "console.log");
+        assert_eq!(sanitize_file_name("COM10"), "COM10");
+
+        let long_name = format!("{}.iso", "\u{e9}".repeat(150));
+        let sanitized = sanitize_file_name(&long_name);
+        assert!(sanitized.len() <= MAX_FILE_NAME_LEN && sanitized.ends_with("\u{e9}.iso"), "{}", sanitized);
+
+        let long_path = format!(r"C:\downloads\{}", "a".repeat(WINDOWS_MAX_PATH));
+        assert_eq!(windows_long_path(&long_path), Some(format!(r"\\?\{}", long_path)));
+        assert_eq!(windows_long_path(&format!(r"\\server\share\{}", "a".repeat(WINDOWS_MAX_PATH))), Some(format!(r"\\?\UNC\server\share\{}", "a".repeat(WINDOWS_MAX_PATH))));
+        assert_eq!(windows_long_path(&format!(r"\\?\{}", long_path)), None);
+        assert_eq!(windows_long_path(r"C:\downloads\file.iso"), None);
+        assert_eq!(windows_long_path(&format!(r"downloads\{}", "a".repeat(WINDOWS_MAX_PATH))), None);
+    }
+}
@@ -0,0 +1,336 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+use super::audit::sha256_hex;
+use super::downloader::{DownloadStatus, RustleDownloader};
+use super::io::{sanitize_file_name, DEFAULT_FILE_NAME};
+use super::queue_manager::{Priority, QueueManager, QueueState};
+use super::resume::ResumeToken;
+
+/// File name of the session journal, in the data directory of the application.
+pub const JOURNAL_FILE_NAME: &str = "session.json";
+
+/// Version of the format written by `SessionJournal::save`.
+const JOURNAL_FORMAT: u32 = 1;
+
+/// Extension of the part files holding the bytes received by the downloads of a journal.
+pub const PART_FILE_EXTENSION: &str = "rustle-part";
+
+/// Time given to the parts of a paused download to stop and hand back their bytes.
+const STOP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Interval between two attempts to take the resume token of a stopping download.
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// SessionJournal represents the downloads of a queue saved when the application exits, with
+/// the bytes received so far, so the next launch restores them where they were.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionJournal {
+    pub format: u32,                    // Version of the format
+    pub downloads: Vec<JournalEntry>,   // Unfinished downloads, in the order they were added
+}
+
+/// JournalEntry represents an unfinished download of the journal.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub token: ResumeToken,             // State of the download, with the bytes received so far
+    pub connections: Option<u8>,        // Number of connections set by the user
+    pub priority: Priority,             // Priority in the queue
+    pub status: DownloadStatus,         // Status of the download when the application exited
+    pub resume: bool,                   // Whether the download was running or queued, so it's started again
+    pub start_at: Option<u64>,          // Start time of a scheduled download, in seconds since the Unix epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub part_file: Option<PathBuf>,     // File holding the bytes received by the parts, left out of `token`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub part_lengths: Vec<u64>,         // Number of bytes of each part in `part_file`, in order
+}
+
+/// PartFileLocation represents where the bytes received by the downloads of a journal are written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PartFileLocation {
+    /// Next to the file of each download, e.g. `file.iso.rustle-part`.
+    NextToFile,
+    /// In a dedicated directory, e.g. the cache directory of the application.
+    Dir(PathBuf),
+}
+
+impl PartFileLocation {
+    /// Returns the path of the part file of a download.
+    pub fn part_file(&self, token: &ResumeToken) -> PathBuf {
+        let file_name = sanitize_file_name(token.file_name.as_deref().unwrap_or(DEFAULT_FILE_NAME));
+        match self {
+            PartFileLocation::NextToFile => token.out_dir.join(format!("{}.{}", file_name, PART_FILE_EXTENSION)),
+            // Downloads of files named alike don't share their part file
+            PartFileLocation::Dir(dir) => {
+                let key = sha256_hex(format!("{}\n{}", token.url, token.out_dir.display()).as_bytes());
+                dir.join(format!("{}.{}.{}", file_name, &key[..12], PART_FILE_EXTENSION))
+            },
+        }
+    }
+}
+
+impl SessionJournal {
+    /// Pauses the running downloads of a queue and takes their state, to be saved before the
+    /// application exits. Finished and cancelled downloads, and the ones that aren't initialized,
+    /// aren't part of the journal.
+    ///
+    /// # Arguments
+    ///
+    /// * `queue` - The queue manager owning the downloads.
+    ///
+    /// Returns the journal, and the errors of the downloads whose state couldn't be taken.
+    pub async fn shutdown(queue: &QueueManager) -> (SessionJournal, Vec<String>) {
+        let ids = queue.ids();
+        let mut running = Vec::new();
+        for id in ids.iter().copied() {
+            let Some(engine) = queue.engine(id) else { continue };
+            let status = engine.get_status().await;
+            if matches!(status, DownloadStatus::Downloading | DownloadStatus::Retrying) {
+                engine.pause().await;
+            }
+            running.push((id, engine, status));
+        }
+
+        let mut downloads = Vec::new();
+        let mut errors = Vec::new();
+        for (id, engine, status) in running {
+            if matches!(status, DownloadStatus::Done | DownloadStatus::Cancelled) || engine.get_file_info().await.is_none() {
+                continue;
+            }
+            let token = match stopped_resume_token(&engine).await {
+                Ok(token) => token,
+                Err(e) => {
+                    errors.push(format!("{} : {}", engine.get_url().await.unwrap_or_default(), e));
+                    continue;
+                },
+            };
+            let state = queue.state(id).unwrap_or_default();
+            downloads.push(JournalEntry {
+                token,
+                connections: engine.get_connections().await,
+                priority: queue.priority(id).unwrap_or_default(),
+                status,
+                resume: matches!(status, DownloadStatus::Downloading | DownloadStatus::Retrying)
+                    || matches!(state, QueueState::Queued | QueueState::DelayedRetry),
+                start_at: match state {
+                    QueueState::Scheduled => queue.start_time(id)
+                        .and_then(|start_at| start_at.duration_since(UNIX_EPOCH).ok())
+                        .map(|start_at| start_at.as_secs()),
+                    _ => None,
+                },
+                part_file: None,
+                part_lengths: Vec::new(),
+            });
+        }
+        (SessionJournal { format: JOURNAL_FORMAT, downloads }, errors)
+    }
+
+    /// Loads a journal saved with `save()`.
+    ///
+    /// Returns an error if the file couldn't be read or isn't a valid journal.
+    pub fn load(path: &Path) -> Result<SessionJournal, String> {
+        let json = fs::read_to_string(path).map_err(|e| format!("Couldn't read the session journal {}, error : {}", path.display(), e))?;
+        let journal: SessionJournal = serde_json::from_str(&json)
+            .map_err(|e| format!("Invalid session journal {}, error : {}", path.display(), e))?;
+        if journal.format != JOURNAL_FORMAT {
+            return Err(format!("Invalid session journal {}, unsupported format version {}", path.display(), journal.format));
+        }
+        Ok(journal)
+    }
+
+    /// Moves the bytes received by the downloads to part files, so the journal only holds their state.
+    /// The bytes of a download whose part file couldn't be written stay in the journal.
+    ///
+    /// # Arguments
+    ///
+    /// * `location` - Where the part files are written.
+    ///
+    /// Returns the errors of the part files that couldn't be written.
+    pub fn write_part_files(&mut self, location: &PartFileLocation) -> Vec<String> {
+        let mut errors = Vec::new();
+        for entry in self.downloads.iter_mut().filter(|entry| entry.token.downloaded_bytes() > 0) {
+            let path = location.part_file(&entry.token);
+            let data: Vec<u8> = entry.token.parts.iter().flat_map(|part| part.data.iter().copied()).collect();
+            let written = path.parent().map_or(Ok(()), fs::create_dir_all).and_then(|_| fs::write(&path, data));
+            if let Err(e) = written {
+                errors.push(format!("Couldn't write the part file {}, error : {}", path.display(), e));
+                continue;
+            }
+            entry.part_lengths = entry.token.parts.iter_mut().map(|part| std::mem::take(&mut part.data).len() as u64).collect();
+            entry.part_file = Some(path);
+        }
+        errors
+    }
+
+    /// Reads the bytes of the downloads back from their part files into the journal, then deletes
+    /// the part files. A download whose part file is missing or doesn't match the journal starts over.
+    ///
+    /// Returns the errors of the part files that couldn't be read.
+    pub fn read_part_files(&mut self) -> Vec<String> {
+        let mut errors = Vec::new();
+        for entry in self.downloads.iter_mut() {
+            let Some(path) = entry.part_file.take() else { continue };
+            let lengths = std::mem::take(&mut entry.part_lengths);
+            match fs::read(&path) {
+                Ok(data) if lengths.len() == entry.token.parts.len() && lengths.iter().sum::<u64>() == data.len() as u64 => {
+                    let mut offset = 0;
+                    for (part, length) in entry.token.parts.iter_mut().zip(lengths) {
+                        part.data = data[offset..offset + length as usize].to_vec();
+                        offset += length as usize;
+                    }
+                },
+                result => {
+                    let error = result.err().map(|e| e.to_string()).unwrap_or_else(|| String::from("its size doesn't match the journal"));
+                    errors.push(format!("Couldn't read the part file {}, the download starts over, error : {}", path.display(), error));
+                    for part in entry.token.parts.iter_mut() {
+                        part.data.clear();
+                        part.digests.clear();
+                        part.done = false;
+                    }
+                },
+            }
+            let _ = fs::remove_file(&path);
+        }
+        errors
+    }
+
+    /// Saves the journal, replacing the previous one only once it's fully written.
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir).map_err(|e| format!("Couldn't create {}, error : {}", dir.display(), e))?;
+        }
+        let json = serde_json::to_string(self).map_err(|e| format!("Couldn't serialize the session journal, error : {}", e))?;
+        let part_path = path.with_extension("json.part");
+        fs::write(&part_path, json).map_err(|e| format!("Couldn't write the session journal {}, error : {}", part_path.display(), e))?;
+        fs::rename(&part_path, path).map_err(|e| format!("Couldn't write the session journal {}, error : {}", path.display(), e))
+    }
+}
+
+impl JournalEntry {
+    /// Creates the engine continuing the download, the caller configures the rest from its settings.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_connections` - The maximum number of parallel connections of the engine.
+    pub async fn engine(&self, max_connections: u8) -> Result<RustleDownloader, String> {
+        let mut engine = RustleDownloader::from_resume_token(self.token.clone(), max_connections)?;
+        if let Some(connections) = self.connections {
+            engine.set_connections(connections).await?;
+        }
+        Ok(engine)
+    }
+
+    /// Returns the start time of a scheduled download.
+    pub fn start_time(&self) -> Option<SystemTime> {
+        self.start_at.map(|start_at| UNIX_EPOCH + Duration::from_secs(start_at))
+    }
+}
+
+/// Deletes the part files of a directory that aren't referenced by a journal, left by a session
+/// that didn't save its journal or whose journal was lost.
+///
+/// # Arguments
+///
+/// * `dir` - The directory holding the part files, it isn't searched recursively.
+/// * `journal` - The journal whose part files are kept, if any.
+///
+/// Returns the deleted part files.
+pub fn remove_orphan_part_files(dir: &Path, journal: Option<&SessionJournal>) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else { return Vec::new() };
+    let referenced: Vec<&Path> = journal.iter()
+        .flat_map(|journal| journal.downloads.iter())
+        .filter_map(|entry| entry.part_file.as_deref())
+        .collect();
+    entries.filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|extension| extension == PART_FILE_EXTENSION))
+        .filter(|path| !referenced.contains(&path.as_path()))
+        .filter(|path| fs::remove_file(path).is_ok())
+        .collect()
+}
+
+/// Waits until the process is asked to terminate, by Ctrl-C or by `SIGTERM` on Unix, so the
+/// applications save the journal before exiting.
+pub async fn termination_signal() {
+    let ctrl_c = async {
+        if tokio::signal::ctrl_c().await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        if let Ok(mut terminate) = signal(SignalKind::terminate()) {
+            tokio::select! {
+                _ = terminate.recv() => {},
+                _ = ctrl_c => {},
+            }
+            return;
+        }
+    }
+    ctrl_c.await
+}
+
+/// Takes the resume token of a paused download, waiting for its parts to stop.
+async fn stopped_resume_token(engine: &RustleDownloader) -> Result<ResumeToken, String> {
+    let deadline = Instant::now() + STOP_TIMEOUT;
+    loop {
+        match engine.resume_token().await {
+            Err(_) if Instant::now() < deadline => tokio::time::sleep(STOP_POLL_INTERVAL).await,
+            result => return result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resume::ResumePart;
+
+    #[tokio::test]
+    async fn journal_restores_the_received_bytes() {
+        let token = ResumeToken {
+            url: String::from("https://example.com/file.bin"),
+            out_dir: "./".into(),
+            file_name: Some(String::from("file.bin")),
+            content_length: Some(8),
+            accepts_ranges: true,
+            parts: vec![
+                ResumePart { start_byte: 0, end_byte: 3, done: true, data: b"abcd".to_vec(), digests: Vec::new() },
+                ResumePart { start_byte: 4, end_byte: 7, done: false, data: b"ef".to_vec(), digests: Vec::new() },
+            ],
+            ..Default::default()
+        };
+        let queue = QueueManager::new(1).unwrap();
+        let id = queue.add(RustleDownloader::from_resume_token(token.clone(), 2).unwrap());
+        queue.set_priority(id, Priority::Low).unwrap();
+
+        let (journal, errors) = SessionJournal::shutdown(&queue).await;
+        assert!(errors.is_empty());
+        let path = std::env::temp_dir().join(format!("rustle-journal-{}.json", std::process::id()));
+        journal.save(&path).unwrap();
+        let journal = SessionJournal::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(journal.downloads.len(), 1);
+        let entry = &journal.downloads[0];
+        assert_eq!((entry.priority, entry.resume, entry.token.downloaded_bytes()), (Priority::Low, false, 6));
+        let engine = entry.engine(2).await.unwrap();
+        assert_eq!(engine.resume_token().await.unwrap(), token);
+
+        // The bytes move to a part file and back, the orphans are deleted
+        let dir = std::env::temp_dir().join(format!("rustle-parts-{}", std::process::id()));
+        let mut journal = journal;
+        assert!(journal.write_part_files(&PartFileLocation::Dir(dir.clone())).is_empty());
+        let part_file = journal.downloads[0].part_file.clone().unwrap();
+        assert_eq!((fs::read(&part_file).unwrap(), journal.downloads[0].token.downloaded_bytes()), (b"abcdef".to_vec(), 0));
+        let orphan = dir.join(format!("old.{}", PART_FILE_EXTENSION));
+        fs::write(&orphan, b"old").unwrap();
+        assert_eq!(remove_orphan_part_files(&dir, Some(&journal)), vec![orphan]);
+
+        assert!(journal.read_part_files().is_empty());
+        assert_eq!(journal.downloads[0].token, token);
+        assert!(!part_file.exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
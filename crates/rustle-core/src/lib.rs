@@ -0,0 +1,96 @@
+//! Download engine of rustle, downloading files over multiple connections.
+//!
+//! The engine doesn't depend on any user interface, a download is configured with
+//! `RustleDownloader::builder()` and driven with `RustleDownloader::download`:
+//!
+//! ```no_run
+//! # async fn example() -> Result<(), String> {
+//! use rustle_core::RustleDownloader;
+//!
+//! let engine = RustleDownloader::builder()
+//!     .url("https://example.com/file.iso")
+//!     .out_dir("./downloads")
+//!     .build()
+//!     .await?;
+//! engine.download(false).await?;
+//! println!("{:?}", engine.get_status().await);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Several downloads are scheduled with a `QueueManager`, and share their network state
+//! through a `ConnectionManager`.
+
+pub mod io;
+pub mod downloader;
+pub mod connection_manager;
+pub mod client;
+pub mod dns;
+pub mod audit;
+pub mod queue_manager;
+pub mod drain;
+pub mod bundle;
+pub mod resume;
+pub mod builder;
+pub mod transport;
+pub mod protocol;
+pub mod curl;
+pub mod url_pattern;
+pub mod batch;
+pub mod links;
+pub mod crawl;
+pub mod zsync;
+pub mod download_list;
+pub mod history;
+pub mod stats;
+pub mod journal;
+pub mod rate_limit;
+pub mod credentials;
+pub mod refresh;
+pub mod encoding;
+pub mod memory;
+pub mod timeline;
+pub mod segments;
+pub mod provenance;
+pub mod mime;
+pub mod har;
+pub mod verify;
+pub mod usage;
+pub mod tuning;
+pub mod error;
+pub mod dates;
+
+pub use batch::{BatchEntry, BatchError, BatchImport};
+pub use builder::RustleDownloaderBuilder;
+pub use bundle::DownloadBundle;
+pub use client::{AddressFamily, EnvProxy, HttpVersion, TlsOptions};
+pub use credentials::{CredentialStore, Credentials};
+pub use curl::CurlCommand;
+pub use connection_manager::{ConnectionManager, ConnectionPermit, NetworkState};
+pub use encoding::ContentEncodingMode;
+pub use error::ErrorKind;
+pub use dns::{DnsCache, DnsOptions, DnsResolver};
+pub use drain::DrainState;
+pub use download_list::{export_json, import_json, DownloadList, DownloadListEntry};
+pub use downloader::{DownloadSnapshot, DownloadStatus, RetryInfo, RetryPolicy, RustleDownloader};
+pub use io::{move_file, BufferingStrategy, FilePermissions, FileWriter, MoveProgress, PartBuffers, StdFileWriter};
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+pub use io::UringFileWriter;
+pub use har::{HarEntry, HarLog, HarTransport};
+pub use history::{DownloadHistory, HistoryQuery, HistoryRecord, HistoryRetention, HistoryStatus};
+pub use journal::{JournalEntry, PartFileLocation, SessionJournal};
+pub use memory::{MemoryBudget, MemoryReservation};
+pub use mime::TypeMismatch;
+pub use rate_limit::RateLimiter;
+pub use refresh::{RefreshFn, UrlRefresher};
+pub use queue_manager::{DownloadId, Priority, QueueManager, QueueState};
+pub use resume::{ResumeMismatch, ResumePart, ResumeToken};
+pub use protocol::{Protocol, ProtocolRegistry};
+pub use transport::{HttpTransport, Transport};
+pub use stats::{HistoryStats, StatsPeriod};
+pub use timeline::{Timeline, TimelineEntry, TimelineEvent};
+pub use tuning::ConnectionTuner;
+pub use url_pattern::expand_url_pattern;
+pub use usage::{DataQuota, DataUsage, QuotaPeriod, UsageTotal};
+pub use verify::{verify_local_file, HashSource, VerifyIssue, VerifyOptions, VerifyReport};
+pub use zsync::{DeltaStats, ZsyncControl};
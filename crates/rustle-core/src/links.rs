@@ -0,0 +1,344 @@
+use std::collections::HashSet;
+use std::time::Duration;
+use regex::Regex;
+use reqwest::header::CONTENT_TYPE;
+use url::Url;
+use super::transport::Transport;
+
+/// Maximum size of a page read by `fetch_links`, so a large file isn't downloaded as a page.
+pub const MAX_PAGE_SIZE: usize = 16 * 1024 * 1024;
+
+/// Timeout of the request fetching a page.
+const PAGE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Schemes of links that can't be downloaded.
+const IGNORED_SCHEMES: [&str; 6] = ["javascript", "mailto", "tel", "data", "blob", "about"];
+
+/// Represents the element a link was found in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LinkKind {
+    /// A link, `<a href>`.
+    Link,
+    /// An image, `<img>` or the poster of a video.
+    Image,
+    /// A video, `<video>` or one of its `<source>`.
+    Video,
+    /// An audio file, `<audio>` or one of its `<source>`.
+    Audio,
+}
+
+impl LinkKind {
+    /// Returns the name of the kind, e.g. to label links in a list.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LinkKind::Link => "link",
+            LinkKind::Image => "image",
+            LinkKind::Video => "video",
+            LinkKind::Audio => "audio",
+        }
+    }
+}
+
+/// PageLink represents a URL found in a web page.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PageLink {
+    pub url: String,        // Absolute URL of the link, without its fragment
+    pub kind: LinkKind,     // Element the link was found in
+}
+
+impl PageLink {
+    /// Returns the lowercase extension of the file the link points to, if it has one.
+    pub fn extension(&self) -> Option<String> {
+        let url = Url::parse(&self.url).ok()?;
+        let name = url.path_segments()?.next_back()?;
+        let (stem, extension) = name.rsplit_once('.')?;
+        (!stem.is_empty() && !extension.is_empty()).then(|| extension.to_ascii_lowercase())
+    }
+}
+
+/// LinkFilter selects the links of a page by extension and regular expression.
+#[derive(Debug, Clone, Default)]
+pub struct LinkFilter {
+    pub extensions: Vec<String>,    // Lowercase extensions without the dot, any extension matches if empty
+    pub pattern: Option<Regex>,     // Regular expression the URL must match
+}
+
+impl LinkFilter {
+    /// Creates a filter from user input.
+    ///
+    /// # Arguments
+    ///
+    /// * `extensions` - The extensions separated by commas or spaces, e.g. `jpg, .png`, empty for any.
+    /// * `pattern` - The regular expression the URLs must match, empty for any.
+    ///
+    /// Returns an error if the regular expression is invalid.
+    pub fn new(extensions: &str, pattern: &str) -> Result<LinkFilter, String> {
+        let extensions = extensions.split([',', ' '])
+            .map(|extension| extension.trim().trim_start_matches('.').to_ascii_lowercase())
+            .filter(|extension| !extension.is_empty())
+            .collect();
+        let pattern = match pattern.trim() {
+            "" => None,
+            pattern => Some(Regex::new(pattern).map_err(|e| format!("Invalid regular expression, {}", e))?),
+        };
+        Ok(LinkFilter { extensions, pattern })
+    }
+
+    /// Returns whether `link` is selected by the filter.
+    pub fn matches(&self, link: &PageLink) -> bool {
+        let extension_matches = self.extensions.is_empty()
+            || link.extension().is_some_and(|extension| self.extensions.contains(&extension));
+        extension_matches && self.pattern.as_ref().is_none_or(|pattern| pattern.is_match(&link.url))
+    }
+}
+
+/// Fetches an HTML page and extracts its links, see `extract_links`.
+///
+/// # Arguments
+///
+/// * `transport` - The transport sending the request, e.g. the one of a download's protocol.
+/// * `url` - The URL of the page.
+///
+/// Returns an error if the request fails, or the response isn't an HTML page of at most `MAX_PAGE_SIZE` bytes.
+pub async fn fetch_links(transport: &dyn Transport, url: &Url) -> Result<Vec<PageLink>, String> {
+    let (page_url, html) = fetch_page(transport, url).await?;
+    Ok(extract_links(&html, &page_url))
+}
+
+/// Fetches an HTML page, returning its URL after redirects and its source, see `fetch_links`.
+pub async fn fetch_page(transport: &dyn Transport, url: &Url) -> Result<(Url, String), String> {
+    let mut response = transport.get_range(url, None, None, Some(PAGE_TIMEOUT)).await
+        .map_err(|e| format!("Couldn't fetch the page {}, error : {}", url, e))?;
+    if !response.status.is_success() {
+        return Err(format!("Couldn't fetch the page {}, status : {}", url, response.status));
+    }
+    let content_type = response.headers.get(CONTENT_TYPE).and_then(|value| value.to_str().ok()).unwrap_or_default();
+    if !content_type.is_empty() && !is_html(content_type) {
+        return Err(format!("{} isn't a web page but a `{}` file", url, content_type));
+    }
+
+    let mut page = Vec::new();
+    while let Some(chunk) = response.chunk().await.map_err(|e| format!("Couldn't read the page {}, error : {}", url, e))? {
+        if page.len() + chunk.len() > MAX_PAGE_SIZE {
+            return Err(format!("The page {} is larger than {} bytes", url, MAX_PAGE_SIZE));
+        }
+        page.extend_from_slice(&chunk);
+    }
+    Ok((response.url, String::from_utf8_lossy(&page).into_owned()))
+}
+
+/// Returns whether a `Content-Type` is the one of an HTML page.
+pub fn is_html(content_type: &str) -> bool {
+    let mime = content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+    mime == "text/html" || mime == "application/xhtml+xml"
+}
+
+/// Extracts the URLs of the links, images, videos and audio files of an HTML page, in order of
+/// appearance and without duplicates. Relative URLs are resolved against the page's `<base>` or URL,
+/// links to the page itself and to `javascript:`, `mailto:`, .. URLs are skipped.
+///
+/// # Arguments
+///
+/// * `html` - The source of the page.
+/// * `page_url` - The URL of the page, after redirects.
+pub fn extract_links(html: &str, page_url: &Url) -> Vec<PageLink> {
+    let mut base = page_url.clone();
+    let mut base_found = false;
+    let mut media = None;
+    let mut seen = HashSet::new();
+    let mut links = Vec::new();
+
+    let mut add = |value: &str, kind: LinkKind, base: &Url| {
+        let Ok(mut url) = base.join(value.trim()) else { return };
+        url.set_fragment(None);
+        let mut page = page_url.clone();
+        page.set_fragment(None);
+        if url != page && !IGNORED_SCHEMES.contains(&url.scheme()) && seen.insert(url.to_string()) {
+            links.push(PageLink { url: url.to_string(), kind });
+        }
+    };
+
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start + 1..];
+        if let Some(comment) = rest.strip_prefix("!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+
+        let name_end = rest.find(|c: char| !c.is_ascii_alphanumeric()).unwrap_or(rest.len());
+        // A `<` that doesn't start a tag is text, e.g. `1 < 2`
+        if name_end == 0 && !rest.starts_with(['/', '!', '?']) {
+            continue;
+        }
+        let name = rest[..name_end].to_ascii_lowercase();
+        let (attributes, after) = parse_attributes(&rest[name_end..]);
+        rest = after;
+
+        match name.as_str() {
+            // Their content isn't HTML, it could contain anything looking like a tag
+            "script" | "style" => {
+                let end_tag = format!("</{}", name);
+                rest = rest.to_ascii_lowercase().find(&end_tag).map_or("", |end| &rest[end..]);
+            },
+            "base" if !base_found => {
+                if let Some(url) = attribute(&attributes, "href").and_then(|href| page_url.join(href.trim()).ok()) {
+                    base = url;
+                    base_found = true;
+                }
+            },
+            "a" => attribute(&attributes, "href").into_iter().for_each(|href| add(href, LinkKind::Link, &base)),
+            "img" => {
+                attribute(&attributes, "src").into_iter().for_each(|src| add(src, LinkKind::Image, &base));
+                srcset_urls(attribute(&attributes, "srcset")).for_each(|src| add(src, LinkKind::Image, &base));
+            },
+            "video" | "audio" | "picture" => {
+                let kind = match name.as_str() {
+                    "video" => LinkKind::Video,
+                    "audio" => LinkKind::Audio,
+                    _ => LinkKind::Image,
+                };
+                media = Some(kind);
+                attribute(&attributes, "src").into_iter().for_each(|src| add(src, kind, &base));
+                attribute(&attributes, "poster").into_iter().for_each(|poster| add(poster, LinkKind::Image, &base));
+            },
+            "source" => {
+                let kind = media.unwrap_or(LinkKind::Video);
+                attribute(&attributes, "src").into_iter().for_each(|src| add(src, kind, &base));
+                srcset_urls(attribute(&attributes, "srcset")).for_each(|src| add(src, LinkKind::Image, &base));
+            },
+            _ => {},
+        }
+    }
+    links
+}
+
+/// Returns the value of an attribute, names are lowercase.
+fn attribute<'a>(attributes: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    attributes.iter().find(|(attribute, _)| attribute == name).map(|(_, value)| value.as_str())
+}
+
+/// Returns the URLs of a `srcset` attribute, e.g. `small.jpg 480w, large.jpg 1080w`.
+fn srcset_urls(srcset: Option<&str>) -> impl Iterator<Item = &str> {
+    srcset.unwrap_or_default().split(',').filter_map(|candidate| candidate.split_whitespace().next())
+}
+
+/// Parses the attributes of a tag up to its closing `>`, returning them with lowercase names and
+/// decoded values, and the text following the tag.
+fn parse_attributes(tag: &str) -> (Vec<(String, String)>, &str) {
+    let mut attributes = Vec::new();
+    let mut rest = tag;
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == '/');
+        if rest.is_empty() {
+            return (attributes, rest);
+        }
+        if let Some(after) = rest.strip_prefix('>') {
+            return (attributes, after);
+        }
+
+        let name_end = rest.find(|c: char| c.is_whitespace() || matches!(c, '=' | '>' | '/')).unwrap_or(rest.len()).max(1);
+        let name = rest[..name_end].to_ascii_lowercase();
+        rest = rest[name_end..].trim_start();
+
+        let value = match rest.strip_prefix('=') {
+            Some(after) => {
+                let after = after.trim_start();
+                let (value, after) = match after.chars().next() {
+                    Some(quote @ ('"' | '\'')) => {
+                        let end = after[1..].find(quote).map_or(after.len(), |end| end + 1);
+                        (&after[1..end], after.get(end + 1..).unwrap_or(""))
+                    },
+                    _ => {
+                        let end = after.find(|c: char| c.is_whitespace() || c == '>').unwrap_or(after.len());
+                        (&after[..end], &after[end..])
+                    },
+                };
+                rest = after;
+                decode_entities(value)
+            },
+            None => String::new(),
+        };
+        attributes.push((name, value));
+    }
+}
+
+/// Decodes the character references of an attribute value, e.g. `&amp;` in the query of a URL.
+fn decode_entities(value: &str) -> String {
+    let mut decoded = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let entity = rest[1..].find(';').map(|end| &rest[1..end + 1]).filter(|entity| entity.len() <= 8);
+        let character = entity.and_then(|entity| match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some('\u{a0}'),
+            _ => {
+                let code = match entity.strip_prefix("#x").or_else(|| entity.strip_prefix("#X")) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                    None => entity.strip_prefix('#').and_then(|decimal| decimal.parse().ok()),
+                };
+                code.and_then(char::from_u32)
+            },
+        });
+        match (entity, character) {
+            (Some(entity), Some(character)) => {
+                decoded.push(character);
+                rest = &rest[entity.len() + 2..];
+            },
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            },
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extract_links_resolves_and_deduplicates() {
+        let html = r#"<html><head><base href="https://cdn.example.com/files/"><script>var s = "<a href='/fake.zip'>";</script></head>
+            <body><!-- <a href="commented.zip"> -->
+            <a href="report.pdf?a=1&amp;b=2#page=3">Report</a> <A HREF=report.pdf?a=1&amp;b=2>Again</A>
+            <a href="mailto:me@example.com">Mail</a> 1 < 2
+            <img src="/img/cover.JPG" srcset="/img/cover-small.jpg 480w, /img/cover-large.jpg 1080w">
+            <video poster="poster.png"><source src="clip.webm" type="video/webm"></video>
+            <audio src="//media.example.com/song.mp3"></audio></body></html>"#;
+        let page = Url::parse("https://example.com/downloads/index.html").unwrap();
+        let links: Vec<(String, LinkKind)> = extract_links(html, &page).into_iter().map(|link| (link.url, link.kind)).collect();
+        assert_eq!(links, vec![
+            (String::from("https://cdn.example.com/files/report.pdf?a=1&b=2"), LinkKind::Link),
+            (String::from("https://cdn.example.com/img/cover.JPG"), LinkKind::Image),
+            (String::from("https://cdn.example.com/img/cover-small.jpg"), LinkKind::Image),
+            (String::from("https://cdn.example.com/img/cover-large.jpg"), LinkKind::Image),
+            (String::from("https://cdn.example.com/files/poster.png"), LinkKind::Image),
+            (String::from("https://cdn.example.com/files/clip.webm"), LinkKind::Video),
+            (String::from("https://media.example.com/song.mp3"), LinkKind::Audio),
+        ]);
+
+        let links = extract_links("<a href='#top'>Top</a><a href=?page=2>Next</a>", &page);
+        assert_eq!(links, vec![PageLink { url: String::from("https://example.com/downloads/index.html?page=2"), kind: LinkKind::Link }]);
+    }
+
+    #[test]
+    fn filter_by_extension_and_pattern() {
+        let link = |url: &str| PageLink { url: url.to_string(), kind: LinkKind::Link };
+        let filter = LinkFilter::new(".JPG, png", "cover").unwrap();
+        assert!(filter.matches(&link("https://example.com/cover.jpg")));
+        assert!(!filter.matches(&link("https://example.com/back.jpg")));
+        assert!(!filter.matches(&link("https://example.com/cover.gif")));
+        assert!(!filter.matches(&link("https://example.com/cover")));
+        assert!(LinkFilter::new("", "").unwrap().matches(&link("https://example.com/")));
+        assert!(LinkFilter::new("", "(").is_err());
+    }
+}
@@ -0,0 +1,118 @@
+use std::sync::Arc;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Granularity of the budget, reservations are rounded up to it.
+const UNIT: u64 = 1024;
+
+/// MemoryBudget bounds the bytes the downloads sharing it buffer in memory, each permit of its
+/// semaphore standing for a KiB. The parts of a download wait for the budget before buffering
+/// their chunks, so many parallel downloads can't exhaust the memory of a small machine.
+#[derive(Debug)]
+pub struct MemoryBudget {
+    units: u32,                 // Size of the budget, in `UNIT`s
+    semaphore: Arc<Semaphore>,  // Permits of the memory that isn't reserved
+}
+
+impl MemoryBudget {
+    /// Creates a new MemoryBudget.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_bytes` - The maximum number of bytes buffered at the same time.
+    ///
+    /// Returns an error if the budget is zero or greater than 4 TiB.
+    pub fn new(max_bytes: u64) -> Result<MemoryBudget, String> {
+        let units = u32::try_from(max_bytes.div_ceil(UNIT)).map_err(|_| String::from("Memory budget must be at most 4 TiB"))?;
+        if units == 0 {
+            return Err(String::from("Memory budget must be greater than zero"));
+        }
+        Ok(MemoryBudget { units, semaphore: Arc::new(Semaphore::new(units as usize)) })
+    }
+
+    /// Returns the maximum number of buffered bytes.
+    pub fn max_bytes(&self) -> u64 {
+        self.units as u64 * UNIT
+    }
+
+    /// Returns the number of bytes that can be buffered without waiting.
+    pub fn available_bytes(&self) -> u64 {
+        self.semaphore.available_permits() as u64 * UNIT
+    }
+
+    /// Returns an empty reservation of the budget, grown with `MemoryReservation::grow_to`.
+    pub fn reservation(self: &Arc<MemoryBudget>) -> MemoryReservation {
+        MemoryReservation { budget: self.clone(), bytes: 0, permit: None }
+    }
+}
+
+/// MemoryReservation holds part of a `MemoryBudget`, the memory is given back once it's dropped.
+#[derive(Debug)]
+pub struct MemoryReservation {
+    budget: Arc<MemoryBudget>,              // Budget the memory is reserved from
+    bytes: u64,                             // Number of reserved bytes
+    permit: Option<OwnedSemaphorePermit>,   // Permits of the reserved memory
+}
+
+impl MemoryReservation {
+    /// Returns the number of reserved bytes.
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    /// Returns whether the memory is reserved from `budget`.
+    pub fn is_from(&self, budget: &Arc<MemoryBudget>) -> bool {
+        Arc::ptr_eq(&self.budget, budget)
+    }
+
+    /// Grows the reservation to `bytes`, waiting for the other reservations to give back memory.
+    /// Nothing is reserved if it already holds as many bytes.
+    ///
+    /// Returns an error if `bytes` is greater than the whole budget, it could never be reserved.
+    pub async fn grow_to(&mut self, bytes: u64) -> Result<(), String> {
+        if bytes <= self.bytes {
+            return Ok(());
+        }
+        let units = bytes.div_ceil(UNIT);
+        if units > self.budget.units as u64 {
+            return Err(format!("The download needs {} bytes of memory, more than the memory budget of {} bytes", bytes, self.budget.max_bytes()));
+        }
+
+        let missing = (units - self.bytes.div_ceil(UNIT)) as u32;
+        if missing > 0 {
+            let permit = self.budget.semaphore.clone().acquire_many_owned(missing).await
+                .map_err(|_| String::from("The memory budget was closed"))?;
+            match self.permit.as_mut() {
+                Some(held) => held.merge(permit),
+                None => self.permit = Some(permit),
+            }
+        }
+        self.bytes = bytes;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn reservations_wait_for_the_budget() {
+        assert!(MemoryBudget::new(0).is_err());
+        let budget = Arc::new(MemoryBudget::new(10 * 1024).unwrap());
+
+        let mut first = budget.reservation();
+        first.grow_to(6 * 1024).await.unwrap();
+        first.grow_to(1).await.unwrap();
+        assert_eq!((first.bytes(), budget.available_bytes()), (6 * 1024, 4 * 1024));
+        assert!(first.grow_to(11 * 1024).await.is_err());
+
+        // The second reservation waits until the first one gives its memory back
+        let mut second = budget.reservation();
+        assert!(tokio::time::timeout(Duration::from_millis(50), second.grow_to(5 * 1024)).await.is_err());
+        drop(first);
+        second.grow_to(5 * 1024).await.unwrap();
+        assert_eq!(budget.available_bytes(), 5 * 1024);
+        assert!(second.is_from(&budget));
+    }
+}
@@ -0,0 +1,191 @@
+use std::fmt;
+
+/// Extensions of the common content types, the first one is given to the files of the type
+/// that don't have an extension. Generic types like `application/octet-stream` aren't listed.
+const MIME_EXTENSIONS: &[(&str, &[&str])] = &[
+    // Archives and packages
+    ("application/zip", &["zip"]),
+    ("application/x-zip-compressed", &["zip"]),
+    ("application/gzip", &["gz", "tgz"]),
+    ("application/x-gzip", &["gz", "tgz"]),
+    ("application/x-tar", &["tar"]),
+    ("application/x-xz", &["xz", "txz"]),
+    ("application/x-bzip2", &["bz2", "tbz2"]),
+    ("application/zstd", &["zst"]),
+    ("application/x-7z-compressed", &["7z"]),
+    ("application/vnd.rar", &["rar"]),
+    ("application/x-rar-compressed", &["rar"]),
+    ("application/x-iso9660-image", &["iso"]),
+    ("application/x-apple-diskimage", &["dmg"]),
+    ("application/vnd.debian.binary-package", &["deb"]),
+    ("application/x-rpm", &["rpm"]),
+    ("application/vnd.android.package-archive", &["apk"]),
+    ("application/java-archive", &["jar"]),
+    ("application/x-msdownload", &["exe", "dll", "msi"]),
+    ("application/vnd.microsoft.portable-executable", &["exe", "dll"]),
+    ("application/x-msi", &["msi"]),
+    ("application/vnd.appimage", &["AppImage", "appimage"]),
+    ("application/wasm", &["wasm"]),
+    // Documents
+    ("application/pdf", &["pdf"]),
+    ("application/epub+zip", &["epub"]),
+    ("application/json", &["json"]),
+    ("application/xml", &["xml"]),
+    ("application/msword", &["doc"]),
+    ("application/vnd.openxmlformats-officedocument.wordprocessingml.document", &["docx"]),
+    ("application/vnd.ms-excel", &["xls"]),
+    ("application/vnd.openxmlformats-officedocument.spreadsheetml.sheet", &["xlsx"]),
+    ("application/vnd.ms-powerpoint", &["ppt"]),
+    ("application/vnd.openxmlformats-officedocument.presentationml.presentation", &["pptx"]),
+    ("application/vnd.oasis.opendocument.text", &["odt"]),
+    ("application/vnd.oasis.opendocument.spreadsheet", &["ods"]),
+    ("text/plain", &["txt"]),
+    ("text/html", &["html", "htm"]),
+    ("text/css", &["css"]),
+    ("text/csv", &["csv"]),
+    ("text/xml", &["xml"]),
+    ("text/markdown", &["md"]),
+    ("text/javascript", &["js", "mjs"]),
+    ("application/javascript", &["js", "mjs"]),
+    // Images
+    ("image/png", &["png"]),
+    ("image/jpeg", &["jpg", "jpeg"]),
+    ("image/gif", &["gif"]),
+    ("image/webp", &["webp"]),
+    ("image/avif", &["avif"]),
+    ("image/svg+xml", &["svg"]),
+    ("image/bmp", &["bmp"]),
+    ("image/tiff", &["tif", "tiff"]),
+    ("image/x-icon", &["ico"]),
+    ("image/vnd.microsoft.icon", &["ico"]),
+    // Audio
+    ("audio/mpeg", &["mp3"]),
+    ("audio/mp4", &["m4a"]),
+    ("audio/aac", &["aac"]),
+    ("audio/ogg", &["ogg", "oga", "opus"]),
+    ("audio/opus", &["opus"]),
+    ("audio/flac", &["flac"]),
+    ("audio/x-flac", &["flac"]),
+    ("audio/wav", &["wav"]),
+    ("audio/x-wav", &["wav"]),
+    // Video
+    ("video/mp4", &["mp4", "m4v"]),
+    ("video/webm", &["webm"]),
+    ("video/x-matroska", &["mkv"]),
+    ("video/quicktime", &["mov"]),
+    ("video/x-msvideo", &["avi"]),
+    ("video/mpeg", &["mpeg", "mpg"]),
+    ("video/mp2t", &["ts"]),
+    ("video/ogg", &["ogv", "ogg"]),
+    // Fonts
+    ("font/woff", &["woff"]),
+    ("font/woff2", &["woff2"]),
+    ("font/ttf", &["ttf"]),
+    ("font/otf", &["otf"]),
+];
+
+/// TypeMismatch represents a file whose `Content-Type` doesn't match its extension, e.g. an `.iso`
+/// served as `text/html` is usually an error page of the server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeMismatch {
+    pub extension: String,      // Extension of the file name
+    pub content_type: String,   // MIME type sent by the server, without its parameters
+}
+
+impl fmt::Display for TypeMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.content_type.as_str() {
+            "text/html" => write!(f, "The .{} file is served as a web page, likely an error page of the server", self.extension),
+            content_type => write!(f, "The .{} file is served as {}", self.extension, content_type),
+        }
+    }
+}
+
+/// Returns the MIME type of a `Content-Type`, lowercase and without its parameters, e.g.
+/// `text/html` for `text/html; charset=utf-8`.
+pub fn mime_type(content_type: &str) -> String {
+    content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase()
+}
+
+/// Returns the extension given to the files of a `Content-Type` without one, e.g. `pdf` for
+/// `application/pdf`. Returns `None` for generic and unknown types.
+pub fn extension_for(content_type: &str) -> Option<&'static str> {
+    let mime = mime_type(content_type);
+    MIME_EXTENSIONS.iter().find(|(known, _)| *known == mime).map(|(_, extensions)| extensions[0])
+}
+
+/// Returns the extension of a file name, e.g. `gz` for `file.tar.gz`. Names starting with
+/// their only dot, like `.bashrc`, and names ending with a dot have none.
+pub fn file_extension(file_name: &str) -> Option<&str> {
+    file_name.rsplit_once('.').filter(|(stem, extension)| !stem.is_empty() && !extension.is_empty()).map(|(_, extension)| extension)
+}
+
+/// Checks that the `Content-Type` of a file is one of the types of its extension. Only known
+/// extensions and types are compared, generic types like `application/octet-stream` match any file.
+///
+/// # Arguments
+///
+/// * `file_name` - The name of the file.
+/// * `content_type` - The `Content-Type` of the file, if sent.
+///
+/// # Returns
+///
+/// The mismatch, or `None` if the type matches the extension or one of them isn't known.
+pub fn type_mismatch(file_name: &str, content_type: Option<&str>) -> Option<TypeMismatch> {
+    let extension = file_extension(file_name)?.to_ascii_lowercase();
+    let mime = mime_type(content_type?);
+    let extension_types: Vec<&str> = MIME_EXTENSIONS.iter()
+        .filter(|(_, extensions)| extensions.iter().any(|known| known.eq_ignore_ascii_case(&extension)))
+        .map(|(known, _)| *known)
+        .collect();
+    let type_known = MIME_EXTENSIONS.iter().any(|(known, _)| *known == mime);
+    (type_known && !extension_types.is_empty() && !extension_types.contains(&mime.as_str()))
+        .then_some(TypeMismatch { extension, content_type: mime })
+}
+
+/// Appends the extension of its `Content-Type` to a file name without one, e.g. `download`
+/// served as `application/pdf` becomes `download.pdf`, so the file opens with the right application.
+/// Names with a dot, including hidden files like `.bashrc`, are kept.
+///
+/// # Arguments
+///
+/// * `file_name` - The name of the file.
+/// * `content_type` - The `Content-Type` of the file, if sent.
+pub fn with_type_extension(file_name: &str, content_type: Option<&str>) -> String {
+    match content_type.and_then(extension_for).filter(|_| !file_name.contains('.')) {
+        Some(extension) => format!("{}.{}", file_name, extension),
+        None => file_name.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mismatching_types_are_detected() {
+        let mismatch = type_mismatch("ubuntu.ISO", Some("text/html; charset=utf-8")).unwrap();
+        assert_eq!(mismatch, TypeMismatch { extension: String::from("iso"), content_type: String::from("text/html") });
+        assert_eq!(mismatch.to_string(), "The .iso file is served as a web page, likely an error page of the server");
+        assert_eq!(type_mismatch("song.mp3", Some("video/mp4")).unwrap().to_string(), "The .mp3 file is served as video/mp4");
+
+        assert_eq!(type_mismatch("backup.tar.gz", Some("application/x-gzip")), None);
+        assert_eq!(type_mismatch("clip.ogg", Some("video/ogg")), None);
+        assert_eq!(type_mismatch("ubuntu.iso", Some("application/octet-stream")), None);
+        assert_eq!(type_mismatch("model.safetensors", Some("text/html")), None);
+        assert_eq!(type_mismatch("ubuntu.iso", None), None);
+    }
+
+    #[test]
+    fn extensions_are_derived_from_the_type() {
+        assert_eq!(with_type_extension("download", Some("application/pdf")), "download.pdf");
+        assert_eq!(with_type_extension("clip", Some("Video/MP4; codecs=\"avc1\"")), "clip.mp4");
+        assert_eq!(with_type_extension(".bashrc", Some("text/plain")), ".bashrc");
+        assert_eq!(with_type_extension("archive.tar.gz", Some("application/zip")), "archive.tar.gz");
+        assert_eq!(with_type_extension("blob", Some("application/octet-stream")), "blob");
+        assert_eq!(with_type_extension("blob", None), "blob");
+        assert_eq!(file_extension("archive.tar.gz"), Some("gz"));
+        assert_eq!(file_extension(".bashrc"), None);
+        assert!(MIME_EXTENSIONS.iter().all(|(mime, extensions)| *mime == mime_type(mime) && !extensions.is_empty()));
+    }
+}
@@ -0,0 +1,147 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+use url::Url;
+use super::client::{build_client, ClientOptions};
+use super::transport::{HttpTransport, Transport};
+
+/// Protocol creates the transport downloading the URLs of a scheme, registered in a `ProtocolRegistry`.
+/// New protocols (ftp, sftp, s3, ..) are added as plugins implementing it, without changing the engine.
+pub trait Protocol: fmt::Debug + Send + Sync {
+    /// Creates the transport of a download, shared by all its requests until it's paused or cancelled.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - The TLS, address family and extra header options of the download.
+    fn transport(&self, options: &ClientOptions) -> Result<Arc<dyn Transport>, String>;
+}
+
+/// HttpProtocol downloads `http` and `https` URLs with an `HttpTransport`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct HttpProtocol;
+
+impl Protocol for HttpProtocol {
+    fn transport(&self, options: &ClientOptions) -> Result<Arc<dyn Transport>, String> {
+        let transport = HttpTransport::new(build_client(options)?)
+            .with_request(options.method.clone(), options.body.clone())
+            .with_content_encoding(options.content_encoding);
+        Ok(Arc::new(transport))
+    }
+}
+
+/// ProtocolRegistry maps URL schemes to the protocols downloading them.
+/// The default registry supports `http` and `https`.
+#[derive(Debug, Clone)]
+pub struct ProtocolRegistry {
+    protocols: BTreeMap<String, Arc<dyn Protocol>>,     // Protocols, keyed by their lowercase scheme
+}
+
+impl Default for ProtocolRegistry {
+    fn default() -> Self {
+        let mut registry = ProtocolRegistry::empty();
+        registry.register("http", Arc::new(HttpProtocol));
+        registry.register("https", Arc::new(HttpProtocol));
+        registry
+    }
+}
+
+impl ProtocolRegistry {
+    /// Creates a registry without any protocol.
+    pub fn empty() -> Self {
+        ProtocolRegistry { protocols: BTreeMap::new() }
+    }
+
+    /// Registers the protocol downloading the URLs of `scheme`, replacing the previous one.
+    ///
+    /// Returns the replaced protocol, if any.
+    pub fn register(&mut self, scheme: &str, protocol: Arc<dyn Protocol>) -> Option<Arc<dyn Protocol>> {
+        self.protocols.insert(scheme.to_ascii_lowercase(), protocol)
+    }
+
+    /// Removes the protocol of `scheme`, returning it if it was registered.
+    pub fn unregister(&mut self, scheme: &str) -> Option<Arc<dyn Protocol>> {
+        self.protocols.remove(&scheme.to_ascii_lowercase())
+    }
+
+    /// Returns the protocol of `scheme`, if it's supported.
+    pub fn get(&self, scheme: &str) -> Option<&Arc<dyn Protocol>> {
+        self.protocols.get(&scheme.to_ascii_lowercase())
+    }
+
+    /// Returns whether the URLs of `scheme` can be downloaded.
+    pub fn supports(&self, scheme: &str) -> bool {
+        self.get(scheme).is_some()
+    }
+
+    /// Returns the supported schemes, in alphabetical order.
+    pub fn schemes(&self) -> Vec<&str> {
+        self.protocols.keys().map(String::as_str).collect()
+    }
+
+    /// Returns the protocol of `url`'s scheme, or an error naming the supported ones.
+    pub fn protocol(&self, url: &Url) -> Result<&Arc<dyn Protocol>, String> {
+        self.get(url.scheme())
+            .ok_or_else(|| format!("Unsupported protocol `{}`, supported ones are {}", url.scheme(), self.schemes().join(", ")))
+    }
+
+    /// Checks that `url` is a valid URL of a supported scheme, e.g. to validate user input.
+    pub fn check_url(&self, url: &str) -> Result<(), String> {
+        let parsed = Url::parse(url).map_err(|e| format!("Invalid URL {}, error : {}", url, e))?;
+        self.protocol(&parsed).map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A protocol whose transports fail with its name, to tell which one is registered.
+    #[derive(Debug)]
+    struct NamedProtocol(&'static str);
+
+    impl Protocol for NamedProtocol {
+        fn transport(&self, _options: &ClientOptions) -> Result<Arc<dyn Transport>, String> {
+            Err(String::from(self.0))
+        }
+    }
+
+    fn name_of(registry: &ProtocolRegistry, scheme: &str) -> Option<String> {
+        registry.get(scheme).map(|protocol| protocol.transport(&ClientOptions::default()).unwrap_err())
+    }
+
+    #[test]
+    fn registered_schemes_are_checked() {
+        let mut registry = ProtocolRegistry::default();
+        assert_eq!(registry.check_url("https://example.com/file.iso"), Ok(()));
+        assert_eq!(registry.check_url("HTTP://example.com/file.iso"), Ok(()));
+        assert_eq!(
+            registry.check_url("ftp://example.com/file.iso"),
+            Err(String::from("Unsupported protocol `ftp`, supported ones are http, https"))
+        );
+        assert!(registry.check_url("not a url").unwrap_err().starts_with("Invalid URL not a url"));
+
+        registry.register("FTP", Arc::new(NamedProtocol("ftp")));
+        assert!(registry.supports("ftp"));
+        assert_eq!(registry.check_url("ftp://example.com/file.iso"), Ok(()));
+        assert_eq!(registry.schemes(), vec!["ftp", "http", "https"]);
+
+        assert!(registry.unregister("https").is_some());
+        assert_eq!(
+            registry.check_url("https://example.com/file.iso"),
+            Err(String::from("Unsupported protocol `https`, supported ones are ftp, http"))
+        );
+        assert!(ProtocolRegistry::empty().check_url("http://example.com/").is_err());
+    }
+
+    #[test]
+    fn registering_a_scheme_again_overrides_it() {
+        let mut registry = ProtocolRegistry::empty();
+        assert!(registry.register("s3", Arc::new(NamedProtocol("first"))).is_none());
+        let replaced = registry.register("S3", Arc::new(NamedProtocol("second")));
+
+        assert_eq!(replaced.unwrap().transport(&ClientOptions::default()).unwrap_err(), "first");
+        assert_eq!(name_of(&registry, "s3").as_deref(), Some("second"));
+        assert_eq!(registry.schemes(), vec!["s3"]);
+        assert_eq!(name_of(&registry, "sftp"), None);
+    }
+}
@@ -0,0 +1,737 @@
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+use tokio::task;
+use super::downloader::{DownloadStatus, RustleDownloader};
+use super::error::ErrorKind;
+use super::timeline::TimelineEvent;
+
+/// Identifier of a download owned by the queue manager.
+pub type DownloadId = usize;
+
+// Result of a finished download, as returned by `RustleDownloader::download`
+type DownloadResult = Result<bool, String>;
+
+// Longest sleep of a scheduled download before the wall clock is checked again,
+// so clock changes and suspends delay the start by at most this long
+const SCHEDULE_RECHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Delays before the queue retries a failed download, the last one is used for all the following retries.
+pub const QUEUE_RETRY_DELAYS: [Duration; 3] = [
+    Duration::from_secs(5 * 60),
+    Duration::from_secs(15 * 60),
+    Duration::from_secs(60 * 60),
+];
+
+/// QueueState represents where a download is in the queue.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum QueueState {
+    #[default]
+    Idle,       // Added but not started
+    Scheduled,  // Waiting for its start time before being queued
+    Queued,     // Waiting for a free slot
+    Active,     // Holding a slot, downloading or paused
+    DelayedRetry,   // Failed, waiting to be queued again at its retry time
+    Finished,   // The download returned, it's done, cancelled or failed
+}
+
+/// Priority of a download, queued downloads with a higher priority start first.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+impl Priority {
+    /// All the priorities, in the order they are presented to the user.
+    pub const ALL: [Priority; 3] = [Priority::High, Priority::Normal, Priority::Low];
+
+    /// Returns the name of the priority.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Priority::High => "high",
+            Priority::Normal => "normal",
+            Priority::Low => "low",
+        }
+    }
+}
+
+impl fmt::Display for Priority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Priority {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Priority::ALL.into_iter()
+            .find(|priority| priority.as_str() == s)
+            .ok_or_else(|| format!("Unknown priority `{}`", s))
+    }
+}
+
+/// QueueEntry represents a download owned by the queue manager.
+#[derive(Debug)]
+struct QueueEntry {
+    engine: Arc<RustleDownloader>,      // Engine of the download
+    state: QueueState,                  // Current state in the queue
+    priority: Priority,                 // Priority used to pick the next queued download
+    start_at: Option<SystemTime>,       // Time a scheduled or delayed-retry download is queued at
+    retries: usize,                     // Number of times the queue retried the download since it was started
+    host: Option<String>,               // Lowercase host of the download's URL
+    host_paused: bool,                  // Whether the download was paused by `pause_host()`, to be resumed with its host
+    result: watch::Sender<Option<DownloadResult>>,  // Result of the last run, `None` until it finishes
+}
+
+/// QueueInner represents the mutable state of the queue manager.
+#[derive(Debug, Default)]
+struct QueueInner {
+    max_active: usize,                          // Maximum number of downloads running at once
+    max_retries: usize,                         // Number of times a failed download is queued again, `0` disables it
+    next_id: DownloadId,                        // Identifier given to the next added download
+    entries: HashMap<DownloadId, QueueEntry>,   // All the downloads owned by the manager
+    queue: VecDeque<DownloadId>,                // Started downloads waiting for a slot, by priority then in order
+    paused_hosts: BTreeSet<String>,             // Lowercase hosts whose downloads are paused and not started
+    draining: bool,                             // Whether the queued downloads are kept from starting, see `drain()`
+}
+
+impl QueueInner {
+    /// Inserts a download in the queue after all the ones with the same or a higher priority.
+    fn enqueue(&mut self, id: DownloadId, priority: Priority) {
+        let position = self.queue.iter()
+            .position(|queued| self.entries.get(queued).is_some_and(|entry| entry.priority < priority))
+            .unwrap_or(self.queue.len());
+        self.queue.insert(position, id);
+    }
+}
+
+/// QueueManager owns the downloads and limits how many of them run at the same time,
+/// started downloads wait in a queue and are started automatically as others finish.
+/// All the downloads from a host can be paused at once, e.g. when a mirror misbehaves, its
+/// queued downloads then wait without holding up the ones from other hosts.
+/// A single instance is meant to be shared (through an `Arc`) by the whole application.
+#[derive(Debug)]
+pub struct QueueManager {
+    inner: Mutex<QueueInner>,
+}
+
+impl QueueManager {
+    /// Creates a new QueueManager.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_active` - The maximum number of downloads running at the same time.
+    ///
+    /// Returns an error if the limit is zero.
+    pub fn new(max_active: usize) -> Result<QueueManager, String> {
+        if max_active == 0 {
+            return Err(String::from("Maximum number of active downloads must be greater than zero"));
+        }
+        Ok(QueueManager { inner: Mutex::new(QueueInner { max_active, ..Default::default() }) })
+    }
+
+    /// Returns the maximum number of downloads running at the same time.
+    pub fn max_active(&self) -> usize {
+        self.inner.lock().unwrap().max_active
+    }
+
+    /// Changes the maximum number of downloads running at the same time.
+    /// Raising it starts queued downloads right away, lowering it lets the running ones finish.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_active` - The new limit.
+    ///
+    /// Returns an error if the limit is zero.
+    pub fn set_max_active(self: &Arc<QueueManager>, max_active: usize) -> Result<(), String> {
+        if max_active == 0 {
+            return Err(String::from("Maximum number of active downloads must be greater than zero"));
+        }
+        self.inner.lock().unwrap().max_active = max_active;
+        self.schedule();
+        Ok(())
+    }
+
+    /// Returns the number of times a failed download is queued again.
+    pub fn max_retries(&self) -> usize {
+        self.inner.lock().unwrap().max_retries
+    }
+
+    /// Changes the number of times a failed download is queued again before its error is final,
+    /// the retries are delayed by `QUEUE_RETRY_DELAYS`. `0`, the default, disables them.
+    /// Downloads already waiting for a retry keep it.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_retries` - The new number of retries.
+    pub fn set_max_retries(&self, max_retries: usize) {
+        self.inner.lock().unwrap().max_retries = max_retries;
+    }
+
+    /// Takes ownership of an initialized download, it stays idle until `start()` is called.
+    ///
+    /// # Arguments
+    ///
+    /// * `engine` - The engine of the download.
+    ///
+    /// # Returns
+    ///
+    /// * `DownloadId` - The identifier of the download in the manager.
+    pub fn add(&self, engine: RustleDownloader) -> DownloadId {
+        let host = engine.try_get_host();
+        let mut inner = self.inner.lock().unwrap();
+        let id = inner.next_id;
+        inner.next_id += 1;
+        inner.entries.insert(id, QueueEntry {
+            engine: Arc::new(engine),
+            host,
+            host_paused: false,
+            state: QueueState::Idle,
+            priority: Priority::default(),
+            start_at: None,
+            retries: 0,
+            result: watch::Sender::new(None)
+        });
+        id
+    }
+
+    /// Removes a download from the manager, dropping it from the queue if it's waiting there.
+    /// A running download isn't stopped, cancel it through its engine first.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The identifier of the download.
+    pub fn remove(&self, id: DownloadId) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.entries.remove(&id);
+        inner.queue.retain(|queued| *queued != id);
+    }
+
+    /// Returns the identifiers of all the downloads, in the order they were added.
+    pub fn ids(&self) -> Vec<DownloadId> {
+        let mut ids: Vec<DownloadId> = self.inner.lock().unwrap().entries.keys().copied().collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    /// Returns the engine of a download.
+    pub fn engine(&self, id: DownloadId) -> Option<Arc<RustleDownloader>> {
+        self.inner.lock().unwrap().entries.get(&id).map(|entry| entry.engine.clone())
+    }
+
+    /// Returns the state of a download in the queue.
+    pub fn state(&self, id: DownloadId) -> Option<QueueState> {
+        self.inner.lock().unwrap().entries.get(&id).map(|entry| entry.state)
+    }
+
+    /// Returns the time a scheduled download, or one waiting for a retry, will be queued at.
+    pub fn start_time(&self, id: DownloadId) -> Option<SystemTime> {
+        self.inner.lock().unwrap().entries.get(&id).and_then(|entry| entry.start_at)
+    }
+
+    /// Returns the host of a download, lowercase.
+    pub fn host(&self, id: DownloadId) -> Option<String> {
+        self.inner.lock().unwrap().entries.get(&id).and_then(|entry| entry.host.clone())
+    }
+
+    /// Returns whether the downloads from `host` are paused.
+    pub fn is_host_paused(&self, host: &str) -> bool {
+        self.inner.lock().unwrap().paused_hosts.contains(&host.to_lowercase())
+    }
+
+    /// Returns the hosts whose downloads are paused, in alphabetical order.
+    pub fn paused_hosts(&self) -> Vec<String> {
+        self.inner.lock().unwrap().paused_hosts.iter().cloned().collect()
+    }
+
+    /// Pauses all the running downloads from `host`, and keeps its queued downloads from starting
+    /// until `resume_host()` is called, the queued downloads from other hosts start instead.
+    /// Like any paused download, the paused ones keep their slot.
+    ///
+    /// # Arguments
+    ///
+    /// * `host` - The host to pause, case insensitive.
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<DownloadId>` - The downloads that were paused.
+    pub async fn pause_host(&self, host: &str) -> Vec<DownloadId> {
+        let host = host.to_lowercase();
+        let active = {
+            let mut inner = self.inner.lock().unwrap();
+            inner.paused_hosts.insert(host.clone());
+            inner.entries.iter()
+                .filter(|(_, entry)| entry.state == QueueState::Active && entry.host.as_ref() == Some(&host))
+                .map(|(id, entry)| (*id, entry.engine.clone()))
+                .collect::<Vec<_>>()
+        };
+
+        let mut paused = Vec::new();
+        for (id, engine) in active {
+            if matches!(engine.get_status().await, DownloadStatus::Downloading | DownloadStatus::Retrying) {
+                engine.pause().await;
+                paused.push(id);
+            }
+        }
+
+        let mut inner = self.inner.lock().unwrap();
+        for id in paused.iter() {
+            if let Some(entry) = inner.entries.get_mut(id) {
+                entry.host_paused = true;
+            }
+        }
+        paused
+    }
+
+    /// Resumes the downloads paused by `pause_host()` and lets the queued downloads from `host` start again.
+    /// Downloads paused by the user meanwhile stay paused.
+    ///
+    /// # Arguments
+    ///
+    /// * `host` - The host to resume, case insensitive.
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<DownloadId>` - The downloads that were resumed.
+    pub async fn resume_host(self: &Arc<QueueManager>, host: &str) -> Vec<DownloadId> {
+        let host = host.to_lowercase();
+        let paused = {
+            let mut inner = self.inner.lock().unwrap();
+            inner.paused_hosts.remove(&host);
+            inner.entries.iter_mut()
+                .filter(|(_, entry)| entry.host_paused && entry.host.as_ref() == Some(&host))
+                .map(|(id, entry)| {
+                    entry.host_paused = false;
+                    (*id, entry.engine.clone())
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let mut resumed = Vec::new();
+        for (id, engine) in paused {
+            if engine.get_status().await == DownloadStatus::Paused {
+                engine.resume().await;
+                resumed.push(id);
+            }
+        }
+        self.schedule();
+        resumed
+    }
+
+    /// Returns the number of times the queue retried a download since it was started.
+    pub fn retries(&self, id: DownloadId) -> Option<usize> {
+        self.inner.lock().unwrap().entries.get(&id).map(|entry| entry.retries)
+    }
+
+    /// Returns the priority of a download.
+    pub fn priority(&self, id: DownloadId) -> Option<Priority> {
+        self.inner.lock().unwrap().entries.get(&id).map(|entry| entry.priority)
+    }
+
+    /// Changes the priority of a download, moving it in the queue if it's waiting there.
+    /// Downloads already holding a slot keep running.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The identifier of the download.
+    /// * `priority` - The new priority.
+    ///
+    /// Returns an error if the download doesn't exist.
+    pub fn set_priority(&self, id: DownloadId, priority: Priority) -> Result<(), String> {
+        let mut inner = self.inner.lock().unwrap();
+        let entry = inner.entries.get_mut(&id).ok_or(format!("No download with id {}", id))?;
+        if entry.priority == priority {
+            return Ok(());
+        }
+        entry.priority = priority;
+        if entry.state == QueueState::Queued {
+            inner.queue.retain(|queued| *queued != id);
+            inner.enqueue(id, priority);
+        }
+        Ok(())
+    }
+
+    /// Returns the position of a queued download, `0` being the next one to start.
+    pub fn queue_position(&self, id: DownloadId) -> Option<usize> {
+        self.inner.lock().unwrap().queue.iter().position(|queued| *queued == id)
+    }
+
+    /// Drains the queue: the running downloads finish, but no queued download is started until
+    /// `resume_queue()` is called, e.g. before the machine is rebooted for maintenance.
+    /// Downloads queued meanwhile, including the scheduled ones and the retries, wait in the queue.
+    pub fn drain(&self) {
+        self.inner.lock().unwrap().draining = true;
+    }
+
+    /// Stops draining the queue, the queued downloads start again as slots are free.
+    pub fn resume_queue(self: &Arc<QueueManager>) {
+        self.inner.lock().unwrap().draining = false;
+        self.schedule();
+    }
+
+    /// Returns whether the queue is draining, see `drain()`.
+    pub fn is_draining(&self) -> bool {
+        self.inner.lock().unwrap().draining
+    }
+
+    /// Returns whether the queue is drained: it's draining and none of its downloads is running.
+    /// Downloads paused or waiting for a confirmation keep their slot but don't hold the drain up.
+    pub async fn is_drained(&self) -> bool {
+        let active = {
+            let inner = self.inner.lock().unwrap();
+            if !inner.draining {
+                return false;
+            }
+            inner.entries.values()
+                .filter(|entry| entry.state == QueueState::Active)
+                .map(|entry| entry.engine.clone())
+                .collect::<Vec<_>>()
+        };
+
+        for engine in active {
+            if !matches!(engine.get_status().await, DownloadStatus::Paused | DownloadStatus::Warning) {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Returns the number of downloads currently holding a slot.
+    pub fn active_count(&self) -> usize {
+        self.inner.lock().unwrap().entries.values().filter(|entry| entry.state == QueueState::Active).count()
+    }
+
+    /// Queues a download, it starts as soon as a slot is free.
+    /// Starting a download that's already queued or active does nothing, one waiting for
+    /// a retry is retried right away.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The identifier of the download.
+    ///
+    /// Returns an error if the download doesn't exist.
+    pub fn start(self: &Arc<QueueManager>, id: DownloadId) -> Result<(), String> {
+        self.queue(id, false)
+    }
+
+    /// Queues a download for `start()`, or for a delayed retry which keeps the retry count.
+    fn queue(self: &Arc<QueueManager>, id: DownloadId, retry: bool) -> Result<(), String> {
+        {
+            let mut inner = self.inner.lock().unwrap();
+            let entry = inner.entries.get_mut(&id).ok_or(format!("No download with id {}", id))?;
+            if matches!(entry.state, QueueState::Queued | QueueState::Active) {
+                return Ok(());
+            }
+            // A retry, even a manual one, continues the same run, `wait()` still waits for its result
+            if entry.state != QueueState::DelayedRetry {
+                entry.result.send_replace(None);
+            }
+            if !retry && entry.state != QueueState::DelayedRetry {
+                entry.retries = 0;
+            }
+            entry.state = QueueState::Queued;
+            entry.start_at = None;
+            entry.engine.record_event(TimelineEvent::Queued);
+            let priority = entry.priority;
+            inner.enqueue(id, priority);
+        }
+        self.schedule();
+        Ok(())
+    }
+
+    /// Schedules a download to be queued at the given time, as if `start()` was called then.
+    /// A time in the past queues it right away. Scheduling it again replaces the previous time,
+    /// and calling `start()` before the time is reached queues it immediately.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The identifier of the download.
+    /// * `start_at` - The time to queue the download at.
+    ///
+    /// Returns an error if the download doesn't exist or is already queued or active.
+    pub fn start_at(self: &Arc<QueueManager>, id: DownloadId, start_at: SystemTime) -> Result<(), String> {
+        {
+            let mut inner = self.inner.lock().unwrap();
+            let entry = inner.entries.get_mut(&id).ok_or(format!("No download with id {}", id))?;
+            if matches!(entry.state, QueueState::Queued | QueueState::Active) {
+                return Err(format!("Download {} is already started", id));
+            }
+            entry.state = QueueState::Scheduled;
+            entry.start_at = Some(start_at);
+            entry.engine.record_event(TimelineEvent::Scheduled { at: start_at });
+            entry.retries = 0;
+            entry.result.send_replace(None);
+        }
+
+        self.queue_at(id, start_at, QueueState::Scheduled);
+        Ok(())
+    }
+
+    /// Queues a download once `start_at` is reached, if it's still in `state` waiting for that time.
+    fn queue_at(self: &Arc<QueueManager>, id: DownloadId, start_at: SystemTime, state: QueueState) {
+        let manager = self.clone();
+        task::spawn(async move {
+            while let Ok(remaining) = start_at.duration_since(SystemTime::now()) {
+                if remaining.is_zero() {
+                    break;
+                }
+                tokio::time::sleep(remaining.min(SCHEDULE_RECHECK_INTERVAL)).await;
+            }
+            // Skip if the download was removed, started manually or rescheduled meanwhile
+            let still_waiting = manager.inner.lock().unwrap().entries.get(&id)
+                .is_some_and(|entry| entry.state == state && entry.start_at == Some(start_at));
+            if still_waiting {
+                let _ = manager.queue(id, true);
+            }
+        });
+    }
+
+    /// Waits until a started or scheduled download finishes.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The identifier of the download.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<bool, String>` - The result of `RustleDownloader::download`, or an error if the
+    ///   download doesn't exist or was removed before finishing.
+    pub async fn wait(&self, id: DownloadId) -> Result<bool, String> {
+        let mut result = {
+            let inner = self.inner.lock().unwrap();
+            inner.entries.get(&id).ok_or(format!("No download with id {}", id))?.result.subscribe()
+        };
+
+        let result = result.wait_for(|result| result.is_some()).await
+            .map_err(|_| format!("Download {} was removed before finishing", id))?;
+        result.clone().unwrap()
+    }
+
+    /// Queues a download like `start()` and waits until it finishes.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The identifier of the download.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<bool, String>` - The result of `RustleDownloader::download`, or an error if the
+    ///   download doesn't exist or was removed before finishing.
+    pub async fn run(self: &Arc<QueueManager>, id: DownloadId) -> Result<bool, String> {
+        self.start(id)?;
+        self.wait(id).await
+    }
+
+    /// Starts queued downloads, highest priority first, while there are free slots.
+    /// The downloads from paused hosts are skipped, they keep their place in the queue.
+    /// Nothing is started while the queue is draining.
+    fn schedule(self: &Arc<QueueManager>) {
+        let mut inner = self.inner.lock().unwrap();
+        let mut active = inner.entries.values().filter(|entry| entry.state == QueueState::Active).count();
+
+        while active < inner.max_active && !inner.draining {
+            let next = inner.queue.iter().position(|queued| {
+                inner.entries.get(queued)
+                    .and_then(|entry| entry.host.as_ref())
+                    .is_none_or(|host| !inner.paused_hosts.contains(host))
+            });
+            let Some(id) = next.and_then(|position| inner.queue.remove(position)) else { break };
+            let Some(entry) = inner.entries.get_mut(&id) else { continue };
+            entry.state = QueueState::Active;
+            active += 1;
+
+            let engine = entry.engine.clone();
+            let manager = self.clone();
+            task::spawn(async move {
+                let result = engine.download(false).await;
+                let error_kind = engine.get_error_kind().await;
+                manager.finish(id, result, error_kind);
+            });
+        }
+    }
+
+    /// Records the result of a download and hands its slot to the next queued one.
+    /// A failed download is queued again later instead while it has retries left, unless
+    /// `error_kind` says another attempt would fail the same way.
+    fn finish(self: &Arc<QueueManager>, id: DownloadId, result: DownloadResult, error_kind: Option<ErrorKind>) {
+        let mut retry_at = None;
+        {
+            let mut inner = self.inner.lock().unwrap();
+            let max_retries = inner.max_retries;
+            if let Some(entry) = inner.entries.get_mut(&id) {
+                let retryable = error_kind.is_none_or(|kind| kind.is_retryable());
+                if result.is_err() && retryable && entry.retries < max_retries {
+                    let delay = QUEUE_RETRY_DELAYS[entry.retries.min(QUEUE_RETRY_DELAYS.len() - 1)];
+                    entry.retries += 1;
+                    entry.state = QueueState::DelayedRetry;
+                    entry.start_at = Some(SystemTime::now() + delay);
+                    retry_at = entry.start_at;
+                } else {
+                    entry.state = QueueState::Finished;
+                    entry.result.send_replace(Some(result));
+                }
+            }
+        }
+        if let Some(retry_at) = retry_at {
+            self.queue_at(id, retry_at, QueueState::DelayedRetry);
+        }
+        self.schedule();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use std::sync::atomic::Ordering;
+    use super::super::downloader::tests::{mock_download, MockTransport};
+
+    /// Creates a transport whose downloads take a moment, so they're still running while the queue is checked.
+    fn slow_transport() -> Arc<MockTransport> {
+        let mut transport = MockTransport::new(256 * 1024);
+        transport.chunk_delay = Duration::from_millis(10);
+        Arc::new(transport)
+    }
+
+    /// Adds `count` downloads of `transport`'s file to the manager.
+    async fn add_downloads(manager: &QueueManager, transport: &Arc<MockTransport>, count: usize, name: &str) -> (Vec<DownloadId>, Vec<PathBuf>) {
+        let mut ids = Vec::new();
+        let mut out_dirs = Vec::new();
+        for i in 0..count {
+            let (engine, out_dir) = mock_download(transport, 1, &format!("queue-{}-{}", name, i)).await;
+            ids.push(manager.add(engine));
+            out_dirs.push(out_dir);
+        }
+        (ids, out_dirs)
+    }
+
+    /// Waits until `id` leaves `state`.
+    async fn wait_state_change(manager: &QueueManager, id: DownloadId, state: QueueState) {
+        while manager.state(id) == Some(state) {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn max_active_caps_the_running_downloads() {
+        let manager = Arc::new(QueueManager::new(2).unwrap());
+        let transport = slow_transport();
+        let (ids, out_dirs) = add_downloads(&manager, &transport, 3, "cap").await;
+
+        for id in &ids {
+            manager.start(*id).unwrap();
+        }
+        assert_eq!(manager.active_count(), 2);
+        assert_eq!(manager.state(ids[2]), Some(QueueState::Queued));
+
+        for id in &ids {
+            assert_eq!(manager.wait(*id).await, Ok(true));
+            assert!(manager.active_count() <= 2);
+        }
+        assert!(ids.iter().all(|id| manager.state(*id) == Some(QueueState::Finished)));
+        for out_dir in out_dirs {
+            assert_eq!(std::fs::read(out_dir.join("file.bin")).unwrap(), transport.data);
+            std::fs::remove_dir_all(out_dir).unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn finished_download_hands_its_slot_to_the_highest_priority() {
+        let manager = Arc::new(QueueManager::new(1).unwrap());
+        let transport = slow_transport();
+        let (ids, out_dirs) = add_downloads(&manager, &transport, 3, "priority").await;
+        let (first, normal, high) = (ids[0], ids[1], ids[2]);
+
+        manager.set_priority(high, Priority::High).unwrap();
+        for id in [first, normal, high] {
+            manager.start(id).unwrap();
+        }
+        assert_eq!(manager.queue_position(high), Some(0));
+        assert_eq!(manager.queue_position(normal), Some(1));
+
+        // The download queued last starts next, the other one keeps waiting for a slot
+        assert_eq!(manager.wait(first).await, Ok(true));
+        wait_state_change(&manager, high, QueueState::Queued).await;
+        assert_eq!(manager.state(normal), Some(QueueState::Queued));
+
+        assert_eq!(manager.wait(normal).await, Ok(true));
+        assert_eq!(manager.state(high), Some(QueueState::Finished));
+        for out_dir in out_dirs {
+            std::fs::remove_dir_all(out_dir).unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn failed_download_is_retried_later() {
+        let manager = Arc::new(QueueManager::new(1).unwrap());
+        manager.set_max_retries(1);
+        let transport = Arc::new(MockTransport::new(64 * 1024));
+        transport.failures.store(usize::MAX, Ordering::Relaxed);
+        let (ids, out_dirs) = add_downloads(&manager, &transport, 1, "retry").await;
+
+        let started = SystemTime::now();
+        manager.start(ids[0]).unwrap();
+        wait_state_change(&manager, ids[0], QueueState::Active).await;
+
+        // The slot is free while the download waits for its retry, its result isn't known yet
+        assert_eq!(manager.state(ids[0]), Some(QueueState::DelayedRetry));
+        assert_eq!(manager.retries(ids[0]), Some(1));
+        assert_eq!(manager.active_count(), 0);
+        let start_time = manager.start_time(ids[0]).unwrap();
+        assert!(start_time >= started + QUEUE_RETRY_DELAYS[0]);
+        assert!(tokio::time::timeout(Duration::from_millis(10), manager.wait(ids[0])).await.is_err());
+        let _ = std::fs::remove_dir_all(&out_dirs[0]);
+    }
+
+    #[tokio::test]
+    async fn non_retryable_failure_finishes() {
+        let manager = Arc::new(QueueManager::new(1).unwrap());
+        manager.set_max_retries(1);
+        let transport = Arc::new(MockTransport::new(64 * 1024));
+        let (ids, out_dirs) = add_downloads(&manager, &transport, 1, "forbidden").await;
+        *transport.expired.lock().unwrap() = Some(String::from("http://mock.test/file.bin"));
+
+        // `403 Forbidden` is answered again whatever the attempt, the queue doesn't retry it
+        assert!(manager.run(ids[0]).await.is_err());
+        assert_eq!(manager.state(ids[0]), Some(QueueState::Finished));
+        assert_eq!(manager.retries(ids[0]), Some(0));
+        let _ = std::fs::remove_dir_all(&out_dirs[0]);
+    }
+
+    #[tokio::test]
+    async fn draining_queue_finishes_the_running_downloads_only() {
+        let manager = Arc::new(QueueManager::new(1).unwrap());
+        let transport = slow_transport();
+        let (ids, out_dirs) = add_downloads(&manager, &transport, 2, "drain").await;
+
+        manager.start(ids[0]).unwrap();
+        manager.start(ids[1]).unwrap();
+        manager.drain();
+        assert!(manager.is_draining());
+        assert!(!manager.is_drained().await);
+
+        // The running download finishes, the queued one doesn't take its slot
+        assert_eq!(manager.wait(ids[0]).await, Ok(true));
+        assert_eq!(manager.state(ids[1]), Some(QueueState::Queued));
+        assert_eq!(manager.active_count(), 0);
+        assert!(manager.is_drained().await);
+
+        manager.resume_queue();
+        assert!(!manager.is_draining());
+        assert!(!manager.is_drained().await);
+        assert_eq!(manager.state(ids[1]), Some(QueueState::Active));
+        assert_eq!(manager.wait(ids[1]).await, Ok(true));
+        for out_dir in out_dirs {
+            std::fs::remove_dir_all(out_dir).unwrap();
+        }
+    }
+}
@@ -0,0 +1,73 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Bytes received at full speed after the limiter was idle, so short pauses between chunks don't
+/// lower the average speed below the limit.
+const BURST: Duration = Duration::from_millis(250);
+
+/// RateLimiter caps the throughput of the parts sharing it, e.g. the parts of a download or all
+/// the downloads. The received bytes are spread over time: each chunk reserves the time it takes
+/// at the limit, and the part waits until its reservation is over.
+#[derive(Debug)]
+pub struct RateLimiter {
+    bytes_per_second: u64,          // Maximum throughput
+    next_free: Mutex<Instant>,      // End of the last reservation
+}
+
+impl RateLimiter {
+    /// Creates a new RateLimiter.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes_per_second` - The maximum throughput.
+    ///
+    /// Returns an error if the limit is zero.
+    pub fn new(bytes_per_second: u64) -> Result<RateLimiter, String> {
+        if bytes_per_second == 0 {
+            return Err(String::from("Speed limit must be greater than zero"));
+        }
+        let now = Instant::now();
+        Ok(RateLimiter { bytes_per_second, next_free: Mutex::new(now.checked_sub(BURST).unwrap_or(now)) })
+    }
+
+    /// Returns the maximum throughput, in bytes per second.
+    pub fn bytes_per_second(&self) -> u64 {
+        self.bytes_per_second
+    }
+
+    /// Reserves the time `bytes` take at the limit and returns how long to wait before receiving more.
+    fn reserve(&self, bytes: u64) -> Duration {
+        let now = Instant::now();
+        let mut next_free = self.next_free.lock().unwrap();
+        let start = (*next_free).max(now.checked_sub(BURST).unwrap_or(now));
+        *next_free = start + Duration::from_secs_f64(bytes as f64 / self.bytes_per_second as f64);
+        next_free.saturating_duration_since(now)
+    }
+
+    /// Waits until `bytes` received bytes fit in the limit.
+    pub async fn acquire(&self, bytes: u64) {
+        let wait = self.reserve(bytes);
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spreads_the_bytes_at_the_limit() {
+        let limiter = RateLimiter::new(1000).unwrap();
+        assert!(RateLimiter::new(0).is_err());
+
+        // The burst allowance is spent first, then every byte waits for its share of the second
+        let first = limiter.reserve(250);
+        let second = limiter.reserve(500);
+        let third = limiter.reserve(500);
+        assert!(first <= Duration::from_millis(10), "{:?}", first);
+        assert!(second > Duration::from_millis(400) && second <= Duration::from_millis(500), "{:?}", second);
+        assert!(third > Duration::from_millis(900) && third <= Duration::from_millis(1000), "{:?}", third);
+    }
+}
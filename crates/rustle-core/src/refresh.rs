@@ -0,0 +1,50 @@
+use std::fmt;
+use std::future::Future;
+use futures::future::BoxFuture;
+use url::Url;
+
+/// UrlRefresher supplies a new URL for a download whose URL expired, e.g. a signed CDN URL that
+/// is only valid for a few minutes. The engine asks for it when a range request is answered with
+/// `403 Forbidden` or `410 Gone`, then continues the parts from the bytes they already received.
+///
+/// The new URL must point to the same file, it's checked against the validator of the download
+/// like any other range request.
+pub trait UrlRefresher: fmt::Debug + Send + Sync {
+    /// Returns the URL replacing `expired`.
+    ///
+    /// Returns an error if no new URL could be obtained, the range request then fails as it would
+    /// without a refresher.
+    fn refresh_url(&self, expired: &Url) -> BoxFuture<'static, Result<String, String>>;
+}
+
+/// RefreshFn adapts a closure returning a future to a `UrlRefresher`, e.g.
+/// `RefreshFn(|expired| async move { sign(expired).await })`.
+pub struct RefreshFn<F>(pub F);
+
+impl<F> fmt::Debug for RefreshFn<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("RefreshFn").finish_non_exhaustive()
+    }
+}
+
+impl<F, Fut> UrlRefresher for RefreshFn<F>
+where
+    F: Fn(Url) -> Fut + Send + Sync,
+    Fut: Future<Output = Result<String, String>> + Send + 'static,
+{
+    fn refresh_url(&self, expired: &Url) -> BoxFuture<'static, Result<String, String>> {
+        Box::pin((self.0)(expired.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn closures_refresh_urls() {
+        let refresher = RefreshFn(|expired: Url| async move { Ok(format!("{}?signature=new", expired)) });
+        let expired = Url::parse("https://cdn.example.com/file.iso").unwrap();
+        assert_eq!(refresher.refresh_url(&expired).await.unwrap(), "https://cdn.example.com/file.iso?signature=new");
+    }
+}
@@ -0,0 +1,242 @@
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use reqwest::Method;
+use reqwest::header::HeaderValue;
+use serde::{Deserialize, Serialize};
+use super::audit::is_sha256_hex;
+use super::downloader::{ResponseHeaderInfo, ValidUrl};
+
+/// ResumeToken represents the state of an unfinished download, taken with `RustleDownloader::resume_token`
+/// and restored with `RustleDownloader::from_resume_token`.
+///
+/// It's serializable so applications embedding the engine can persist it in their own storage
+/// (a database, a JSON file, ..) and continue the download later, e.g. after a restart.
+/// The engine keeps the received bytes in memory, so they're part of the token.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResumeToken {
+    pub url: String,                        // URL of the file
+    pub out_dir: PathBuf,                   // Output directory of the file
+    pub file_name: Option<String>,          // Name of the downloaded file
+    pub expected_sha256: Option<String>,    // Expected SHA-256 of the file, if it's verified
+    pub content_length: Option<u64>,        // Length of the file in bytes, if known
+    pub content_type: Option<String>,       // MIME type of the file
+    pub accepts_ranges: bool,               // Whether the server accepts range requests
+    pub etag: Option<String>,               // `ETag` of the file when the download started
+    pub last_modified: Option<String>,      // `Last-Modified` date of the file when the download started
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub method: Option<String>,             // Method of the requests if it's not `GET`
+    #[serde(default, skip_serializing_if = "Vec::is_empty", with = "base64_data")]
+    pub body: Vec<u8>,                      // Body sent with the requests, serialized as base64
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub referer: Option<String>,            // `Referer` header of the requests, if it's set
+    pub parts: Vec<ResumePart>,             // Parts of the download, empty if nothing was received yet
+}
+
+/// ResumePart represents the byte range of a part and the bytes received for it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResumePart {
+    pub start_byte: u64,    // First byte of the range
+    pub end_byte: u64,      // Last byte of the range (inclusive)
+    pub done: bool,         // Whether the part is finished
+    #[serde(with = "base64_data")]
+    pub data: Vec<u8>,      // Bytes received so far, starting at `start_byte`, serialized as base64
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub digests: Vec<String>,   // SHA-256 of the segments of `data`, when they were hashed
+}
+
+/// Serializes the received bytes as a base64 string, much smaller than an array of numbers in text formats.
+mod base64_data {
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(data: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&STANDARD.encode(data))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        STANDARD.decode(encoded).map_err(serde::de::Error::custom)
+    }
+}
+
+impl ResumeToken {
+    /// Returns the number of bytes received so far.
+    pub fn downloaded_bytes(&self) -> u64 {
+        self.parts.iter().map(|part| part.data.len() as u64).sum()
+    }
+
+    /// Checks that the token describes a download the engine can continue.
+    pub fn validate(&self) -> Result<(), String> {
+        ValidUrl::new(&self.url).map_err(|e| format!("Invalid URL {}, error : {}", self.url, e))?;
+        if let Some(method) = self.method.as_ref() {
+            Method::from_str(method).map_err(|e| format!("Invalid method {}, error : {}", method, e))?;
+        }
+        if let Some(referer) = self.referer.as_ref() {
+            validate_referer(referer)?;
+        }
+
+        if let Some(sha256) = self.expected_sha256.as_ref() {
+            if !is_sha256_hex(sha256) {
+                return Err(format!("`{}` isn't a valid SHA-256 checksum", sha256));
+            }
+        }
+
+        for (i, part) in self.parts.iter().enumerate() {
+            if part.end_byte < part.start_byte || part.data.len() as u64 > part.end_byte - part.start_byte + 1 {
+                return Err(format!("Invalid part {}, {} bytes received for the range {}-{}", i, part.data.len(), part.start_byte, part.end_byte));
+            }
+            if let Some(digest) = part.digests.iter().find(|digest| !is_sha256_hex(digest)) {
+                return Err(format!("Invalid part {}, `{}` isn't a valid segment digest", i, digest));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Checks that a `Referer` is an absolute URL that can be sent in a header.
+pub(crate) fn validate_referer(referer: &str) -> Result<(), String> {
+    url::Url::parse(referer).map_err(|e| format!("Invalid referer {}, error : {}", referer, e))?;
+    HeaderValue::from_str(referer).map_err(|e| format!("Invalid referer {}, error : {}", referer, e))?;
+    Ok(())
+}
+
+/// ResumeMismatch represents why the bytes received by a download were discarded when it was
+/// resumed: the file on the server isn't the one they were received from anymore.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResumeMismatch {
+    ContentLength { before: u64, now: u64 },    // The length of the file changed
+    Etag { before: String, now: String },       // The `ETag` of the file changed
+    LastModified { before: String, now: String },   // The `Last-Modified` date of the file changed
+}
+
+impl fmt::Display for ResumeMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResumeMismatch::ContentLength { before, now } => write!(f, "The size of the file changed from {} to {} bytes", before, now),
+            ResumeMismatch::Etag { before, now } => write!(f, "The ETag of the file changed from {} to {}", before, now),
+            ResumeMismatch::LastModified { before, now } => write!(f, "The file was modified on the server, on {} instead of {}", now, before),
+        }
+    }
+}
+
+/// Checks that the file described by the headers of a new response is still the one the kept bytes
+/// were received from, by comparing the validators captured when the download was initialized.
+/// Validators missing from either side aren't compared.
+///
+/// # Arguments
+///
+/// * `known` - The information of the file when the download was initialized.
+/// * `current` - The information of the file now.
+///
+/// Returns the first validator that changed, the kept bytes can't be reused then.
+pub fn check_resume(known: &ResponseHeaderInfo, current: &ResponseHeaderInfo) -> Result<(), ResumeMismatch> {
+    if let (Some(before), Some(now)) = (known.content_length, current.content_length) {
+        if before != now {
+            return Err(ResumeMismatch::ContentLength { before, now });
+        }
+    }
+    if let (Some(before), Some(now)) = (known.etag.as_ref(), current.etag.as_ref()) {
+        if before != now {
+            return Err(ResumeMismatch::Etag { before: before.clone(), now: now.clone() });
+        }
+    }
+    if let (Some(before), Some(now)) = (known.last_modified.as_ref(), current.last_modified.as_ref()) {
+        if before != now {
+            return Err(ResumeMismatch::LastModified { before: before.clone(), now: now.clone() });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIGEST: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+    fn token() -> ResumeToken {
+        ResumeToken {
+            url: String::from("https://example.com/file.iso"),
+            out_dir: PathBuf::from("downloads"),
+            file_name: Some(String::from("file.iso")),
+            expected_sha256: Some(DIGEST.to_string()),
+            content_length: Some(8),
+            accepts_ranges: true,
+            etag: Some(String::from("\"v1\"")),
+            last_modified: Some(String::from("Wed, 01 May 2024 12:00:00 GMT")),
+            method: Some(String::from("POST")),
+            body: b"q=1".to_vec(),
+            referer: Some(String::from("https://example.com/")),
+            parts: vec![
+                ResumePart { start_byte: 0, end_byte: 3, done: true, data: vec![0, 1, 2, 3], digests: vec![DIGEST.to_string()] },
+                ResumePart { start_byte: 4, end_byte: 7, done: false, data: vec![4], digests: Vec::new() },
+            ],
+            ..ResumeToken::default()
+        }
+    }
+
+    fn headers(content_length: Option<u64>, etag: Option<&str>, last_modified: Option<&str>) -> ResponseHeaderInfo {
+        ResponseHeaderInfo {
+            content_length,
+            etag: etag.map(String::from),
+            last_modified: last_modified.map(String::from),
+            ..ResponseHeaderInfo::default()
+        }
+    }
+
+    #[test]
+    fn tokens_round_trip() {
+        let token = token();
+        assert_eq!(token.validate(), Ok(()));
+        assert_eq!(token.downloaded_bytes(), 5);
+
+        let json = serde_json::to_string(&token).unwrap();
+        assert!(json.contains("\"data\":\"AAECAw==\""));
+        assert_eq!(serde_json::from_str::<ResumeToken>(&json).unwrap(), token);
+
+        // The optional fields are left out of the tokens of plain downloads
+        let plain = ResumeToken { method: None, body: Vec::new(), referer: None, ..token };
+        let json = serde_json::to_string(&plain).unwrap();
+        assert!(!json.contains("method") && !json.contains("body") && !json.contains("referer"));
+        assert_eq!(serde_json::from_str::<ResumeToken>(&json).unwrap(), plain);
+    }
+
+    #[test]
+    fn changed_files_are_detected() {
+        let known = headers(Some(8), Some("\"v1\""), Some("Wed, 01 May 2024 12:00:00 GMT"));
+        assert_eq!(check_resume(&known, &known.clone()), Ok(()));
+
+        assert_eq!(check_resume(&known, &headers(Some(8), Some("\"v2\""), Some("Wed, 01 May 2024 12:00:00 GMT"))),
+            Err(ResumeMismatch::Etag { before: String::from("\"v1\""), now: String::from("\"v2\"") }));
+        let modified = check_resume(&known, &headers(Some(8), Some("\"v1\""), Some("Thu, 02 May 2024 12:00:00 GMT"))).unwrap_err();
+        assert_eq!(modified.to_string(), "The file was modified on the server, on Thu, 02 May 2024 12:00:00 GMT instead of Wed, 01 May 2024 12:00:00 GMT");
+        assert_eq!(check_resume(&known, &headers(Some(9), Some("\"v2\""), None)),
+            Err(ResumeMismatch::ContentLength { before: 8, now: 9 }));
+
+        // Validators missing from either side aren't compared
+        assert_eq!(check_resume(&known, &headers(None, None, None)), Ok(()));
+        assert_eq!(check_resume(&headers(Some(8), None, None), &headers(Some(8), Some("\"v2\""), Some("today"))), Ok(()));
+    }
+
+    #[test]
+    fn corrupt_tokens_are_rejected() {
+        let json = serde_json::to_string(&token()).unwrap();
+        assert!(serde_json::from_str::<ResumeToken>(&json.replace("AAECAw==", "not base64!")).is_err());
+        assert!(serde_json::from_str::<ResumeToken>(&json[..json.len() / 2]).is_err());
+
+        let invalid = |change: fn(&mut ResumeToken)| {
+            let mut token = token();
+            change(&mut token);
+            token.validate().unwrap_err()
+        };
+        assert!(invalid(|token| token.url = String::from("not a url")).contains("Invalid URL"));
+        assert!(invalid(|token| token.method = Some(String::from("GET FILE"))).contains("Invalid method"));
+        assert!(invalid(|token| token.referer = Some(String::from("gallery"))).contains("Invalid referer"));
+        assert!(invalid(|token| token.expected_sha256 = Some(String::from("abc"))).contains("valid SHA-256"));
+        assert!(invalid(|token| token.parts[1].data = vec![0; 5]).contains("Invalid part 1"));
+        assert!(invalid(|token| token.parts[0].end_byte = 0).contains("Invalid part 0"));
+        assert!(invalid(|token| token.parts[0].digests[0].push('0')).contains("segment digest"));
+    }
+}
@@ -0,0 +1,124 @@
+use sha2::{Digest, Sha256};
+use super::audit::sha256_hex;
+
+/// Size of the segments hashed while a part streams, the last segment of a part may be shorter.
+/// A corrupt segment is downloaded again on its own, so it's also the granularity of the repairs.
+pub const SEGMENT_SIZE: usize = 1024 * 1024;
+
+/// Appends the digests of the segments of `data` completed since the last call, `data` being the
+/// bytes of a part received so far. The segments are aligned on the start of the part.
+///
+/// # Arguments
+///
+/// * `data` - The bytes of the part received so far.
+/// * `digests` - The hex encoded SHA-256 of the part's complete segments, in order.
+/// * `complete` - Whether the part is complete, its last segment is hashed even if it's shorter.
+pub fn hash_segments(data: &[u8], digests: &mut Vec<String>, complete: bool) {
+    while (digests.len() + 1) * SEGMENT_SIZE <= data.len() {
+        let start = digests.len() * SEGMENT_SIZE;
+        digests.push(sha256_hex(&data[start..start + SEGMENT_SIZE]));
+    }
+    if complete && digests.len() * SEGMENT_SIZE < data.len() {
+        digests.push(sha256_hex(&data[digests.len() * SEGMENT_SIZE..]));
+    }
+}
+
+/// Returns the index of the first segment of `data` that doesn't match its digest, the segments
+/// without a digest aren't checked.
+///
+/// # Arguments
+///
+/// * `data` - The bytes of a part.
+/// * `digests` - The digests of its segments, see `hash_segments`.
+pub fn first_corrupt_segment(data: &[u8], digests: &[String]) -> Option<usize> {
+    digests.iter().enumerate().position(|(i, digest)| {
+        let start = i * SEGMENT_SIZE;
+        let end = (start + SEGMENT_SIZE).min(data.len());
+        start >= data.len() || sha256_hex(&data[start..end]) != *digest
+    })
+}
+
+/// StreamHasher computes the SHA-256 of a file while its parts stream, so it's known without
+/// reading the file again once it's complete. The bytes are hashed in file order from `offset()`:
+/// the part streaming there feeds its chunks as they arrive, and the next part's received bytes
+/// are caught up once it's reached. SHA-256 states can't be combined, the bytes the parts received
+/// past the offset are hashed when the file is complete.
+#[derive(Debug, Clone, Default)]
+pub struct StreamHasher {
+    hasher: Sha256,     // State of the digest of the bytes before `offset`
+    offset: u64,        // Number of bytes hashed from the start of the file
+}
+
+impl StreamHasher {
+    /// Hashes the bytes of `data` past the hashed offset, if it reaches it, `data` being the bytes
+    /// of the file from `start`.
+    ///
+    /// # Arguments
+    ///
+    /// * `start` - The offset of `data` in the file.
+    /// * `data` - The bytes received from `start`, e.g. the buffer of a part.
+    /// * `max_bytes` - The maximum number of bytes hashed by the call, the rest is hashed by the next ones.
+    ///
+    /// # Returns
+    ///
+    /// The number of bytes hashed.
+    pub fn update(&mut self, start: u64, data: &[u8], max_bytes: usize) -> usize {
+        let end = start + data.len() as u64;
+        if start > self.offset || end <= self.offset {
+            return 0;
+        }
+        let from = (self.offset - start) as usize;
+        let bytes = &data[from..data.len().min(from.saturating_add(max_bytes))];
+        self.hasher.update(bytes);
+        self.offset += bytes.len() as u64;
+        bytes.len()
+    }
+
+    /// Returns the number of bytes hashed from the start of the file.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Returns the hex encoded SHA-256 of the bytes hashed so far.
+    pub fn finalize(self) -> String {
+        format!("{:x}", self.hasher.finalize())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_the_corrupt_segments() {
+        let mut data = vec![7u8; 2 * SEGMENT_SIZE + 10];
+        let mut digests = Vec::new();
+        hash_segments(&data[..SEGMENT_SIZE + 5], &mut digests, false);
+        assert_eq!(digests.len(), 1);
+        hash_segments(&data, &mut digests, false);
+        assert_eq!(digests.len(), 2);
+        hash_segments(&data, &mut digests, true);
+        assert_eq!(digests.len(), 3);
+        assert_eq!(first_corrupt_segment(&data, &digests), None);
+
+        data[SEGMENT_SIZE + 3] = 0;
+        assert_eq!(first_corrupt_segment(&data, &digests), Some(1));
+        assert_eq!(first_corrupt_segment(&data[..SEGMENT_SIZE], &digests[..1]), None);
+        assert_eq!(first_corrupt_segment(&data[..SEGMENT_SIZE], &digests), Some(1));
+    }
+
+    #[test]
+    fn parts_are_hashed_in_file_order() {
+        let data: Vec<u8> = (0..1000u32).map(|i| (i % 251) as u8).collect();
+        let mut hasher = StreamHasher::default();
+
+        // The second part is ahead, its bytes wait for the first part to reach them
+        assert_eq!(hasher.update(600, &data[600..700], usize::MAX), 0);
+        assert_eq!(hasher.update(0, &data[..300], 100), 100);
+        assert_eq!(hasher.update(0, &data[..600], usize::MAX), 500);
+        assert_eq!(hasher.update(0, &data[..600], usize::MAX), 0);
+        assert_eq!(hasher.update(600, &data[600..], usize::MAX), 400);
+        assert_eq!(hasher.offset(), 1000);
+        assert_eq!(hasher.finalize(), sha256_hex(&data));
+    }
+}
@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use rusqlite::params;
+use url::Url;
+use super::history::DownloadHistory;
+
+// Milliseconds in a day, the history stores its times in milliseconds since the Unix epoch
+const DAY_MILLIS: i64 = 24 * 3600 * 1000;
+
+/// StatsPeriod represents the length of the periods the downloads are grouped by, in UTC.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StatsPeriod {
+    #[default]
+    Day,
+    Week,   // Weeks start on Monday
+}
+
+impl StatsPeriod {
+    /// Returns the length of the period in days, and the days between the Monday starting the
+    /// week of the Unix epoch (a Thursday) and the epoch.
+    fn days_and_offset(&self) -> (i64, i64) {
+        match self {
+            StatsPeriod::Day => (1, 0),
+            StatsPeriod::Week => (7, 3),
+        }
+    }
+}
+
+/// StatsSummary represents the totals of a group of downloads, only the finished ones count for
+/// the bytes and the speed.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct StatsSummary {
+    pub downloads: usize,               // Number of finished or failed downloads
+    pub failed: usize,                  // Number of failed downloads
+    pub bytes: u64,                     // Bytes of the finished downloads
+    pub average_speed: Option<f64>,     // Bytes per second over the time spent on the finished downloads of a known size
+}
+
+/// PeriodStats represents the downloads that ended during a day or a week.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PeriodStats {
+    pub start: SystemTime,          // Start of the period, midnight UTC
+    pub summary: StatsSummary,      // Totals of the period
+}
+
+/// HostStats represents the downloads from a host.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HostStats {
+    pub host: String,               // Lowercase host of the URLs
+    pub summary: StatsSummary,      // Totals of the host
+}
+
+/// HistoryStats represents the statistics of the history shown by the applications, see `DownloadHistory::stats`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HistoryStats {
+    pub summary: StatsSummary,      // Totals of all the downloads
+    pub periods: Vec<PeriodStats>,  // Totals per period with downloads, oldest first
+    pub hosts: Vec<HostStats>,      // Totals per host, most downloaded bytes first
+}
+
+impl StatsSummary {
+    /// Creates a summary from the aggregated columns of the history.
+    fn new(downloads: i64, failed: i64, bytes: i64, done_millis: i64) -> StatsSummary {
+        StatsSummary {
+            downloads: downloads as usize,
+            failed: failed as usize,
+            bytes: bytes.max(0) as u64,
+            average_speed: (done_millis > 0).then(|| bytes as f64 / (done_millis as f64 / 1000.0)),
+        }
+    }
+}
+
+// Aggregated columns of `StatsSummary::new`, the time only counts the finished downloads of a known size
+const SUMMARY_COLUMNS: &str = "COUNT(*),
+    COALESCE(SUM(status = 'failed'), 0),
+    COALESCE(SUM(CASE WHEN status = 'done' THEN size END), 0),
+    COALESCE(SUM(CASE WHEN status = 'done' AND size IS NOT NULL THEN MAX(finished_at - started_at, 0) END), 0)";
+
+impl DownloadHistory {
+    /// Returns the totals, per period and per host statistics of the downloads, see `HistoryStats`.
+    ///
+    /// # Arguments
+    ///
+    /// * `since` - Only the downloads finished after this time are counted, all of them if `None`.
+    /// * `period` - The length of the periods.
+    pub fn stats(&self, since: Option<SystemTime>, period: StatsPeriod) -> Result<HistoryStats, String> {
+        Ok(HistoryStats {
+            summary: self.summary(since)?,
+            periods: self.stats_by_period(since, period)?,
+            hosts: self.stats_by_host(since)?,
+        })
+    }
+
+    /// Returns the totals of the downloads finished after `since`, or all of them.
+    pub fn summary(&self, since: Option<SystemTime>) -> Result<StatsSummary, String> {
+        let sql = format!("SELECT {} FROM downloads WHERE finished_at >= ?1", SUMMARY_COLUMNS);
+        self.connection().query_row(&sql, params![since_millis(since)], |row| {
+            Ok(StatsSummary::new(row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+        }).map_err(stats_error)
+    }
+
+    /// Returns the totals of each day or week with downloads finished after `since`, oldest first.
+    pub fn stats_by_period(&self, since: Option<SystemTime>, period: StatsPeriod) -> Result<Vec<PeriodStats>, String> {
+        let (days, offset) = period.days_and_offset();
+        let sql = format!("SELECT (finished_at / {} + {}) / {} AS period, {} FROM downloads WHERE finished_at >= ?1 GROUP BY period ORDER BY period",
+                        DAY_MILLIS, offset, days, SUMMARY_COLUMNS);
+        let connection = self.connection();
+        let mut statement = connection.prepare(&sql).map_err(stats_error)?;
+        let periods = statement.query_map(params![since_millis(since)], |row| {
+            let start_day = row.get::<_, i64>(0)? * days - offset;
+            Ok(PeriodStats {
+                start: UNIX_EPOCH + Duration::from_millis((start_day * DAY_MILLIS).max(0) as u64),
+                summary: StatsSummary::new(row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?),
+            })
+        }).map_err(stats_error)?
+        .collect::<rusqlite::Result<Vec<PeriodStats>>>()
+        .map_err(stats_error)?;
+        Ok(periods)
+    }
+
+    /// Returns the totals of each host with downloads finished after `since`, most downloaded
+    /// bytes first. The hosts are parsed from the URLs, so the URLs are grouped in SQL first.
+    pub fn stats_by_host(&self, since: Option<SystemTime>) -> Result<Vec<HostStats>, String> {
+        let sql = format!("SELECT url, {} FROM downloads WHERE finished_at >= ?1 GROUP BY url", SUMMARY_COLUMNS);
+        let connection = self.connection();
+        let mut statement = connection.prepare(&sql).map_err(stats_error)?;
+        let mut totals: HashMap<String, (i64, i64, i64, i64)> = HashMap::new();
+        let mut rows = statement.query(params![since_millis(since)]).map_err(stats_error)?;
+        while let Some(row) = rows.next().map_err(stats_error)? {
+            let url: String = row.get(0).map_err(stats_error)?;
+            let host = Url::parse(&url).ok().and_then(|url| url.host_str().map(str::to_ascii_lowercase)).unwrap_or_default();
+            let total = totals.entry(host).or_default();
+            total.0 += row.get::<_, i64>(1).map_err(stats_error)?;
+            total.1 += row.get::<_, i64>(2).map_err(stats_error)?;
+            total.2 += row.get::<_, i64>(3).map_err(stats_error)?;
+            total.3 += row.get::<_, i64>(4).map_err(stats_error)?;
+        }
+
+        let mut hosts: Vec<HostStats> = totals.into_iter()
+            .map(|(host, (downloads, failed, bytes, millis))| HostStats { host, summary: StatsSummary::new(downloads, failed, bytes, millis) })
+            .collect();
+        hosts.sort_by(|a, b| b.summary.bytes.cmp(&a.summary.bytes).then_with(|| a.host.cmp(&b.host)));
+        Ok(hosts)
+    }
+}
+
+/// Returns the milliseconds since the Unix epoch of `since`, `0` to count all the downloads.
+fn since_millis(since: Option<SystemTime>) -> i64 {
+    since.and_then(|since| since.duration_since(UNIX_EPOCH).ok()).map(|since| since.as_millis() as i64).unwrap_or(0)
+}
+
+/// Formats an error of the database.
+fn stats_error(e: rusqlite::Error) -> String {
+    format!("History error : {}", e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::{HistoryRecord, HistoryStatus};
+
+    #[test]
+    fn aggregates_per_period_and_host() {
+        let history = DownloadHistory::open_in_memory().unwrap();
+        // Monday 2024-01-01 and the following Wednesday and Monday, at noon UTC
+        for (url, day, size, status) in [
+            ("https://a.example.com/1.iso", 19723, 4000, HistoryStatus::Done),
+            ("https://A.example.com/2.iso", 19725, 2000, HistoryStatus::Done),
+            ("https://b.example.com/3.iso", 19725, 500, HistoryStatus::Failed),
+            ("https://b.example.com/4.iso", 19730, 1000, HistoryStatus::Done),
+        ] {
+            let finished_at = UNIX_EPOCH + Duration::from_secs(day * 24 * 3600 + 12 * 3600);
+            history.record(&HistoryRecord {
+                id: None,
+                url: url.to_string(),
+                file_name: None,
+                path: None,
+                size: Some(size),
+                started_at: finished_at - Duration::from_secs(2),
+                finished_at,
+                average_speed: None,
+                sha256: None,
+                status,
+                error: None,
+            }).unwrap();
+        }
+
+        let stats = history.stats(None, StatsPeriod::Week).unwrap();
+        assert_eq!(stats.summary, StatsSummary { downloads: 4, failed: 1, bytes: 7000, average_speed: Some(7000.0 / 6.0) });
+        assert_eq!(stats.periods.iter().map(|period| (period.start, period.summary.bytes)).collect::<Vec<_>>(), vec![
+            (UNIX_EPOCH + Duration::from_secs(19723 * 24 * 3600), 6000),
+            (UNIX_EPOCH + Duration::from_secs(19730 * 24 * 3600), 1000),
+        ]);
+        assert_eq!(stats.hosts.iter().map(|host| (host.host.as_str(), host.summary.downloads, host.summary.bytes)).collect::<Vec<_>>(),
+            vec![("a.example.com", 2, 6000), ("b.example.com", 2, 1000)]);
+
+        let since = UNIX_EPOCH + Duration::from_secs(19725 * 24 * 3600);
+        assert_eq!(history.stats_by_period(Some(since), StatsPeriod::Day).unwrap().len(), 2);
+    }
+}
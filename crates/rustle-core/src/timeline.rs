@@ -0,0 +1,133 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+use super::mime::TypeMismatch;
+use super::error::ErrorKind;
+
+/// Maximum number of events kept per download, the oldest ones are dropped first.
+pub const TIMELINE_LEN: usize = 200;
+
+/// TimelineEvent represents a significant event in the life of a download.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TimelineEvent {
+    /// The download was scheduled to be queued at a later time.
+    Scheduled { at: SystemTime },
+    /// The download waits in the queue for a free slot.
+    Queued,
+    /// The `Content-Type` of the file doesn't match its extension, the download waits for the user to confirm it.
+    TypeMismatch { mismatch: TypeMismatch },
+    /// A run of the download started, the parts continue from the bytes they already received.
+    Started,
+    /// The file changed on the server since the kept bytes were received, they were discarded.
+    Restarted { reason: String },
+    /// The server ignored the range requests, the file is downloaded again over a single connection.
+    RangesIgnored,
+    /// Segments of the received bytes didn't match their digests, they're downloaded again.
+    SegmentsRepaired { segments: usize },
+    /// The remaining bytes of the last running part were split into `ranges` ranges, downloaded in parallel.
+    TailSplit { ranges: usize },
+    /// The connection tuning found the number of connections giving the best throughput.
+    ConnectionsTuned { connections: usize },
+    /// A part didn't receive any chunk for the stall timeout, the rest of its range is requested again.
+    PartStalled { part: usize },
+    /// The URL expired and was replaced by a refreshed one.
+    UrlRefreshed,
+    /// An attempt failed, the download is attempted again after `delay`.
+    Retrying { retry: u32, max_retries: u32, delay: Duration, error: String, kind: ErrorKind },
+    /// The download was paused, by the user or because the network went down.
+    Paused { offline: bool },
+    /// The download was paused because the data quota of the downloads is reached.
+    QuotaReached,
+    /// The download was resumed.
+    Resumed,
+    /// The download was resumed after a long pause, the file is checked on the server before its ranges are requested again.
+    Revalidating,
+    /// The download was cancelled, keeping the received bytes or not.
+    Cancelled { keep_partial: bool },
+    /// The download failed and won't be attempted again by the engine.
+    Failed { error: String, kind: ErrorKind },
+    /// The file was written, `bytes` is its size.
+    Completed { bytes: u64 },
+}
+
+impl fmt::Display for TimelineEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TimelineEvent::Scheduled { .. } => f.write_str("Scheduled"),
+            TimelineEvent::Queued => f.write_str("Queued"),
+            TimelineEvent::TypeMismatch { mismatch } => write!(f, "{}, waiting for confirmation", mismatch),
+            TimelineEvent::Started => f.write_str("Started"),
+            TimelineEvent::Restarted { reason } => write!(f, "Started over, {}", reason),
+            TimelineEvent::RangesIgnored => f.write_str("The server ignored the ranges, downloading over a single connection"),
+            TimelineEvent::SegmentsRepaired { segments } => write!(f, "{} corrupt segments, downloading them again", segments),
+            TimelineEvent::TailSplit { ranges } => write!(f, "Split the last part into {} ranges", ranges),
+            TimelineEvent::ConnectionsTuned { connections } => write!(f, "Settled on {} connections", connections),
+            TimelineEvent::PartStalled { part } => write!(f, "Part {} stalled, requesting its range again", part + 1),
+            TimelineEvent::UrlRefreshed => f.write_str("The URL expired, refreshed it"),
+            TimelineEvent::Retrying { retry, max_retries, delay, error, kind } => {
+                write!(f, "Retry {}/{} in {}s, {}: {}", retry, max_retries, delay.as_secs(), kind, error)
+            },
+            TimelineEvent::Paused { offline: true } => f.write_str("Paused, waiting for the network"),
+            TimelineEvent::Paused { offline: false } => f.write_str("Paused"),
+            TimelineEvent::QuotaReached => f.write_str("Paused, the data quota is reached"),
+            TimelineEvent::Resumed => f.write_str("Resumed"),
+            TimelineEvent::Revalidating => f.write_str("Resumed after a long pause, checking the file on the server"),
+            TimelineEvent::Cancelled { keep_partial: true } => f.write_str("Cancelled, keeping the received data"),
+            TimelineEvent::Cancelled { keep_partial: false } => f.write_str("Cancelled"),
+            TimelineEvent::Failed { error, kind } => write!(f, "Failed, {}: {}", kind, error),
+            TimelineEvent::Completed { bytes } => write!(f, "Completed, {} bytes written", bytes),
+        }
+    }
+}
+
+/// An event along with the time it happened.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimelineEntry {
+    pub time: SystemTime,       // When the event happened
+    pub event: TimelineEvent,   // What happened
+}
+
+/// Timeline records the events of a download, shared by its engine and the queue manager owning it.
+/// It isn't behind the engine's lock, so it can be read while the download runs.
+#[derive(Debug, Default)]
+pub struct Timeline {
+    entries: Mutex<VecDeque<TimelineEntry>>,    // Events, oldest first, at most `TIMELINE_LEN`
+}
+
+impl Timeline {
+    /// Records an event happening now, dropping the oldest one if the timeline is full.
+    pub fn record(&self, event: TimelineEvent) {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() == TIMELINE_LEN {
+            entries.pop_front();
+        }
+        entries.push_back(TimelineEntry { time: SystemTime::now(), event });
+    }
+
+    /// Returns the recorded events, oldest first.
+    pub fn entries(&self) -> Vec<TimelineEntry> {
+        self.entries.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_latest_events() {
+        let timeline = Timeline::default();
+        timeline.record(TimelineEvent::Queued);
+        for _ in 0..TIMELINE_LEN {
+            timeline.record(TimelineEvent::Started);
+        }
+        timeline.record(TimelineEvent::Completed { bytes: 10 });
+
+        let entries = timeline.entries();
+        assert_eq!(entries.len(), TIMELINE_LEN);
+        assert_eq!(entries[0].event, TimelineEvent::Started);
+        assert_eq!(entries.last().unwrap().event.to_string(), "Completed, 10 bytes written");
+        assert!(entries.windows(2).all(|pair| pair[0].time <= pair[1].time));
+    }
+}
@@ -0,0 +1,286 @@
+use std::error::Error as StdError;
+use std::fmt;
+use std::time::Duration;
+use bytes::Bytes;
+use futures::future::BoxFuture;
+use futures::stream::{self, BoxStream, Stream, StreamExt};
+use reqwest::header::{HeaderMap, HeaderValue, ACCEPT_ENCODING, IF_RANGE, RANGE};
+use reqwest::{Client, Method, RequestBuilder, StatusCode, Version};
+use url::Url;
+use super::encoding::{ContentEncodingMode, ACCEPTED_ENCODINGS};
+
+/// Transport sends the requests of the engine. `HttpTransport` is used unless another one is set
+/// with `RustleDownloader::set_transport`, e.g. a mock serving a file from memory in tests.
+///
+/// The returned futures own everything they need, and dropping a response (or its body) must
+/// abort the request: it's how pausing and cancelling release the connections.
+pub trait Transport: fmt::Debug + Send + Sync {
+    /// Sends a HEAD request to `url`.
+    fn head(&self, url: &Url, timeout: Option<Duration>) -> BoxFuture<'static, Result<TransportResponse, TransportError>>;
+
+    /// Sends a GET request to `url` for `range`, or for the whole file if it's `None`.
+    /// `if_range` is sent as `If-Range`, the server then answers with the whole file if it changed.
+    fn get_range(&self, url: &Url, range: Option<ByteRange>, if_range: Option<&str>, timeout: Option<Duration>) -> BoxFuture<'static, Result<TransportResponse, TransportError>>;
+}
+
+/// ByteRange represents the inclusive range of bytes requested with a `Range` header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRange {
+    pub start: u64,     // First byte of the range
+    pub end: u64,       // Last byte of the range (inclusive)
+}
+
+impl fmt::Display for ByteRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "bytes={}-{}", self.start, self.end)
+    }
+}
+
+/// TransportResponse represents the status and headers of a response, and its body read chunk by chunk.
+pub struct TransportResponse {
+    pub status: StatusCode,     // Status code of the response
+    pub url: Url,               // URL of the response, after redirects
+    pub headers: HeaderMap,     // Headers of the response
+    pub version: Version,       // HTTP version of the response, negotiated with the server
+    body: BoxStream<'static, Result<Bytes, TransportError>>,
+}
+
+impl fmt::Debug for TransportResponse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TransportResponse")
+            .field("status", &self.status)
+            .field("url", &self.url)
+            .field("headers", &self.headers)
+            .field("version", &self.version)
+            .finish_non_exhaustive()
+    }
+}
+
+impl TransportResponse {
+    /// Creates a response from its status, URL, headers and a stream of body chunks, received over HTTP/1.1.
+    pub fn new(status: StatusCode, url: Url, headers: HeaderMap, body: impl Stream<Item = Result<Bytes, TransportError>> + Send + 'static) -> Self {
+        TransportResponse { status, url, headers, version: Version::HTTP_11, body: body.boxed() }
+    }
+
+    /// Sets the HTTP version the response was received over.
+    pub fn with_version(mut self, version: Version) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// Calls `callback` with the number of body bytes read and the error ending the body, if any,
+    /// once the response is dropped, e.g. to record how long the body was read for.
+    pub fn on_body_end(mut self, callback: impl FnOnce(u64, Option<String>) + Send + 'static) -> Self {
+        let mut end = BodyEnd { bytes: 0, error: None, callback: Some(callback) };
+        let body = std::mem::replace(&mut self.body, stream::empty().boxed());
+        self.body = body.map(move |chunk| {
+            end.read(&chunk);
+            chunk
+        }).boxed();
+        self
+    }
+
+    /// Returns the next chunk of the body, or `None` once it's complete.
+    pub async fn chunk(&mut self) -> Result<Option<Bytes>, TransportError> {
+        self.body.next().await.transpose()
+    }
+
+    /// Reads the rest of the body as text, replacing invalid UTF-8. It stops at the first error,
+    /// it's only meant for error messages.
+    pub async fn text(mut self) -> String {
+        let mut body = Vec::new();
+        while let Ok(Some(chunk)) = self.chunk().await {
+            body.extend_from_slice(&chunk);
+        }
+        String::from_utf8_lossy(&body).into_owned()
+    }
+}
+
+/// Calls its callback with the bytes read from a body and the error ending it when it's dropped.
+struct BodyEnd<F: FnOnce(u64, Option<String>)> {
+    bytes: u64,             // Number of bytes read
+    error: Option<String>,  // Error ending the body
+    callback: Option<F>,    // Called once, on drop
+}
+
+impl<F: FnOnce(u64, Option<String>)> BodyEnd<F> {
+    /// Counts a chunk read from the body, or keeps the error ending it.
+    fn read(&mut self, chunk: &Result<Bytes, TransportError>) {
+        match chunk {
+            Ok(chunk) => self.bytes += chunk.len() as u64,
+            Err(e) => self.error = Some(e.message.clone()),
+        }
+    }
+}
+
+impl<F: FnOnce(u64, Option<String>)> Drop for BodyEnd<F> {
+    fn drop(&mut self) {
+        if let Some(callback) = self.callback.take() {
+            callback(self.bytes, self.error.take());
+        }
+    }
+}
+
+/// Represents the kind of a transport error, connectivity errors are reported to the connection manager.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransportErrorKind {
+    /// The host name couldn't be resolved.
+    Dns,
+    /// The server refused the connection, nothing listens on its port.
+    ConnectionRefused,
+    /// The TLS handshake failed, e.g. the certificate of the server isn't trusted.
+    Tls,
+    /// The connection to the server couldn't be opened, or was lost.
+    Connect,
+    /// The request timed out.
+    Timeout,
+    /// Any other error, e.g. an invalid response.
+    Other,
+}
+
+/// TransportError represents a request that failed without a response, or a body that couldn't be read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransportError {
+    pub kind: TransportErrorKind,   // Kind of the error
+    pub message: String,            // Description of the error
+}
+
+impl TransportError {
+    /// Creates an error of the given kind.
+    pub fn new(kind: TransportErrorKind, message: impl Into<String>) -> Self {
+        TransportError { kind, message: message.into() }
+    }
+
+    /// Returns whether the error means the server couldn't be reached, e.g. the network is down.
+    /// A failed TLS handshake reached the server, it doesn't count.
+    pub fn is_connectivity(&self) -> bool {
+        matches!(self.kind, TransportErrorKind::Dns | TransportErrorKind::ConnectionRefused | TransportErrorKind::Connect | TransportErrorKind::Timeout)
+    }
+}
+
+impl fmt::Display for TransportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl From<reqwest::Error> for TransportError {
+    fn from(e: reqwest::Error) -> Self {
+        let kind = match error_kind(&e) {
+            Some(kind) => kind,
+            None if e.is_timeout() => TransportErrorKind::Timeout,
+            None if e.is_connect() => TransportErrorKind::Connect,
+            None => TransportErrorKind::Other,
+        };
+        // The causes tell what failed, e.g. `dns error: failed to lookup address information`
+        let mut message = e.to_string();
+        let mut source = e.source();
+        while let Some(cause) = source {
+            message = format!("{}: {}", message, cause);
+            source = cause.source();
+        }
+        TransportError::new(kind, message)
+    }
+}
+
+/// Returns the kind of the failure a `reqwest` error comes from, if it's a DNS, refused connection,
+/// TLS or timeout one, from the chain of its causes. hyper and the TLS backends don't expose typed
+/// errors for all of them, the messages of the causes are matched as well.
+fn error_kind(e: &reqwest::Error) -> Option<TransportErrorKind> {
+    let mut source: Option<&(dyn StdError + 'static)> = Some(e);
+    while let Some(cause) = source {
+        if let Some(io_error) = cause.downcast_ref::<std::io::Error>() {
+            match io_error.kind() {
+                std::io::ErrorKind::ConnectionRefused => return Some(TransportErrorKind::ConnectionRefused),
+                std::io::ErrorKind::TimedOut => return Some(TransportErrorKind::Timeout),
+                _ => {},
+            }
+        }
+        let message = cause.to_string().to_lowercase();
+        if message.starts_with("dns error") || message.contains("failed to lookup address") || message.contains("no address found") {
+            return Some(TransportErrorKind::Dns);
+        }
+        if message.contains("certificate") || message.contains("tls handshake") || message.contains("ssl") {
+            return Some(TransportErrorKind::Tls);
+        }
+        source = cause.source();
+    }
+    None
+}
+
+/// HttpTransport sends the requests over HTTP(S) with a `reqwest` client, see `client::build_client`.
+#[derive(Debug, Clone)]
+pub struct HttpTransport {
+    client: Client,
+    method: Method,         // Method of the GET requests, e.g. `POST` for an export API
+    body: Option<Bytes>,    // Body of the GET requests
+    content_encoding: ContentEncodingMode,  // Whether compressed responses are accepted for the whole file
+}
+
+impl HttpTransport {
+    /// Creates a transport sending the requests with `client`.
+    pub fn new(client: Client) -> Self {
+        HttpTransport { client, method: Method::GET, body: None, content_encoding: ContentEncodingMode::default() }
+    }
+
+    /// Sets whether compressed responses are accepted for the whole file, with `Content-Encoding`.
+    /// HEAD and range requests always ask for the uncompressed file, so the lengths and ranges
+    /// are the ones of the file.
+    pub fn with_content_encoding(mut self, content_encoding: ContentEncodingMode) -> Self {
+        self.content_encoding = content_encoding;
+        self
+    }
+
+    /// Sends the requests of `get_range` with another method than `GET` and a body, HEAD
+    /// requests are unchanged.
+    pub fn with_request(mut self, method: Method, body: Option<Bytes>) -> Self {
+        self.method = method;
+        self.body = body;
+        self
+    }
+
+    fn send(request: RequestBuilder, timeout: Option<Duration>) -> BoxFuture<'static, Result<TransportResponse, TransportError>> {
+        let request = match timeout {
+            Some(timeout) => request.timeout(timeout),
+            None => request,
+        };
+
+        Box::pin(async move {
+            let response = request.send().await?;
+            let (status, url, headers, version) = (response.status(), response.url().clone(), response.headers().clone(), response.version());
+            // Dropping the stream drops the response, aborting the request
+            let body = stream::unfold(Some(response), |response| async move {
+                let mut response = response?;
+                match response.chunk().await {
+                    Ok(Some(chunk)) => Some((Ok(chunk), Some(response))),
+                    Ok(None) => None,
+                    Err(e) => Some((Err(TransportError::from(e)), None)),
+                }
+            });
+            Ok(TransportResponse::new(status, url, headers, body).with_version(version))
+        })
+    }
+}
+
+impl Transport for HttpTransport {
+    fn head(&self, url: &Url, timeout: Option<Duration>) -> BoxFuture<'static, Result<TransportResponse, TransportError>> {
+        let request = self.client.head(url.as_str()).header(ACCEPT_ENCODING, HeaderValue::from_static("identity"));
+        HttpTransport::send(request, timeout)
+    }
+
+    fn get_range(&self, url: &Url, range: Option<ByteRange>, if_range: Option<&str>, timeout: Option<Duration>) -> BoxFuture<'static, Result<TransportResponse, TransportError>> {
+        let mut request = self.client.request(self.method.clone(), url.as_str());
+        if let Some(body) = self.body.clone() {
+            request = request.body(body);
+        }
+        match range {
+            Some(range) => request = request.header(RANGE, range.to_string()).header(ACCEPT_ENCODING, HeaderValue::from_static("identity")),
+            None if self.content_encoding == ContentEncodingMode::Decode => request = request.header(ACCEPT_ENCODING, HeaderValue::from_static(ACCEPTED_ENCODINGS)),
+            None => {},
+        }
+        if let Some(if_range) = if_range {
+            request = request.header(IF_RANGE, if_range);
+        }
+        HttpTransport::send(request, timeout)
+    }
+}
@@ -0,0 +1,88 @@
+/// Number of connections a download tuning its connections starts with, unless a count was
+/// already tuned for its host.
+pub const AUTO_START_CONNECTIONS: usize = 2;
+
+/// Minimum relative gain of the throughput for a connection added by the tuning to be kept.
+pub const MIN_CONNECTION_GAIN: f64 = 0.1;
+
+/// ConnectionTuner finds the number of connections giving a download the best throughput from its
+/// server, by hill climbing: starting from a few connections, one more is added as long as the
+/// throughput measured with it improves by `MIN_CONNECTION_GAIN`. The last one is removed once it
+/// doesn't, and the tuner is settled. Servers throttling each connection gain from more of them,
+/// while the bandwidth of saturated links is split between more connections for nothing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConnectionTuner {
+    max: usize,                         // Maximum number of connections of the download
+    limit: usize,                       // Number of connections the download currently uses
+    previous: Option<(usize, f64)>,     // Number of connections before the last one was added, and their throughput
+    settled: bool,                      // Whether the best number of connections was found
+}
+
+impl ConnectionTuner {
+    /// Creates a tuner starting from `start` connections, up to `max` connections.
+    pub fn new(start: usize, max: usize) -> Self {
+        let max = max.max(1);
+        ConnectionTuner { max, limit: start.clamp(1, max), previous: None, settled: false }
+    }
+
+    /// Returns the number of connections the download should use.
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Returns whether the best number of connections was found, the throughput isn't observed anymore.
+    pub fn is_settled(&self) -> bool {
+        self.settled
+    }
+
+    /// Records the throughput measured with the current number of connections, in bytes per second.
+    ///
+    /// Returns the number of connections the download should use next.
+    pub fn observe(&mut self, throughput: f64) -> usize {
+        if self.settled {
+            return self.limit;
+        }
+        match self.previous {
+            // The last connection didn't improve the throughput enough, it's removed
+            Some((previous_limit, previous_throughput)) if throughput < previous_throughput * (1.0 + MIN_CONNECTION_GAIN) => {
+                self.limit = previous_limit;
+                self.settled = true;
+            },
+            _ if self.limit >= self.max => self.settled = true,
+            _ => {
+                self.previous = Some((self.limit, throughput));
+                self.limit += 1;
+            },
+        }
+        self.limit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn connections_are_added_while_the_throughput_improves() {
+        // Each connection is throttled to 1 MB/s, the link saturates at 3.2 MB/s
+        let throughput = |connections: usize| (connections as f64).min(3.2) * 1_000_000.0;
+        let mut tuner = ConnectionTuner::new(AUTO_START_CONNECTIONS, 8);
+        while !tuner.is_settled() {
+            tuner.observe(throughput(tuner.limit()));
+        }
+        assert_eq!(tuner.limit(), 3);
+        assert_eq!(tuner.observe(0.0), 3);
+
+        // A server without throttling gains nothing from the second connection
+        let mut tuner = ConnectionTuner::new(1, 8);
+        assert_eq!(tuner.observe(5_000_000.0), 2);
+        assert_eq!(tuner.observe(5_200_000.0), 1);
+        assert!(tuner.is_settled());
+
+        // The tuning stops at the maximum
+        let mut tuner = ConnectionTuner::new(4, 2);
+        assert_eq!(tuner.limit(), 2);
+        assert_eq!(tuner.observe(1_000_000.0), 2);
+        assert!(tuner.is_settled());
+    }
+}
@@ -0,0 +1,176 @@
+/// Maximum number of URLs a pattern can expand to, so a typo doesn't queue millions of downloads.
+pub const MAX_PATTERN_URLS: usize = 10_000;
+
+/// Represents a part of a URL pattern.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    /// Text copied as is.
+    Literal(String),
+    /// Values of a `[start-end]` range or a `{a,b,c}` set, one of them is used in each URL.
+    Values(Vec<String>),
+}
+
+/// Returns whether `text` is a URL pattern expanding to several URLs, see `expand_url_pattern`.
+pub fn is_url_pattern(text: &str) -> bool {
+    parse_pattern(text).map_or(true, |segments| segments.iter().any(|segment| matches!(segment, Segment::Values(_))))
+}
+
+/// Expands a URL pattern into the URLs it describes, e.g. `https://host/file_[001-120].jpg` or
+/// `https://host/{intro,main,outro}.mp4`.
+///
+/// * `[start-end]` is a range of numbers, zero-padded to the width of `start` if it starts with a
+///   zero, or of letters (`[a-z]`). A step is added after a colon, e.g. `[0-100:10]`.
+/// * `{a,b,c}` is a set of values, which can be empty (`file{,.sig}`).
+/// * A backslash escapes the next bracket or brace. A bracket that isn't a range is kept as is,
+///   e.g. the IPv6 address of `http://[::1]:8080/file`.
+///
+/// The URLs are listed with the first pattern varying the slowest, a text without any pattern
+/// expands to itself.
+///
+/// # Arguments
+///
+/// * `pattern` - The URL pattern to expand.
+///
+/// Returns an error if a pattern is invalid or it expands to more than `MAX_PATTERN_URLS` URLs.
+pub fn expand_url_pattern(pattern: &str) -> Result<Vec<String>, String> {
+    let segments = parse_pattern(pattern)?;
+    let count = segments.iter()
+        .try_fold(1usize, |count, segment| match segment {
+            Segment::Literal(_) => Some(count),
+            Segment::Values(values) => count.checked_mul(values.len()),
+        })
+        .filter(|count| *count <= MAX_PATTERN_URLS)
+        .ok_or_else(|| format!("The pattern expands to more than {} URLs", MAX_PATTERN_URLS))?;
+
+    let mut urls = vec![String::new()];
+    for segment in &segments {
+        urls = match segment {
+            Segment::Literal(text) => urls.into_iter().map(|url| url + text).collect(),
+            Segment::Values(values) => urls.iter()
+                .flat_map(|url| values.iter().map(move |value| format!("{}{}", url, value)))
+                .collect(),
+        };
+    }
+    debug_assert_eq!(urls.len(), count);
+    Ok(urls)
+}
+
+/// Splits a URL pattern into its literal text and its ranges and sets.
+fn parse_pattern(pattern: &str) -> Result<Vec<Segment>, String> {
+    let mut segments = Vec::new();
+    let mut literal = String::new();
+    let mut rest = pattern;
+
+    while let Some(c) = rest.chars().next() {
+        match c {
+            '\\' if rest[1..].starts_with(['[', ']', '{', '}']) => {
+                literal.push_str(&rest[1..2]);
+                rest = &rest[2..];
+                continue;
+            },
+            '{' => {
+                let end = rest.find('}').ok_or_else(|| format!("Unclosed `{{` in the pattern `{}`", pattern))?;
+                let set = &rest[1..end];
+                if set.contains(['{', '[']) {
+                    return Err(format!("Nested patterns aren't supported, `{}`", pattern));
+                }
+                segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                segments.push(Segment::Values(set.split(',').map(String::from).collect()));
+                rest = &rest[end + 1..];
+                continue;
+            },
+            '[' => {
+                if let Some(end) = rest.find(']') {
+                    if let Some(values) = parse_range(&rest[1..end])? {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                        segments.push(Segment::Values(values));
+                        rest = &rest[end + 1..];
+                        continue;
+                    }
+                }
+            },
+            _ => {},
+        }
+        literal.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    segments.push(Segment::Literal(literal));
+    segments.retain(|segment| segment != &Segment::Literal(String::new()));
+    Ok(segments)
+}
+
+/// Parses the inside of a `[start-end:step]` range into its values.
+///
+/// Returns `None` if it isn't a range, or an error if it's an invalid or too long one.
+fn parse_range(range: &str) -> Result<Option<Vec<String>>, String> {
+    let (bounds, step) = match range.split_once(':') {
+        Some((bounds, step)) if !step.is_empty() && step.bytes().all(|b| b.is_ascii_digit()) => (bounds, Some(step)),
+        Some(_) => return Ok(None),
+        None => (range, None),
+    };
+    let Some((start, end)) = bounds.split_once('-') else { return Ok(None) };
+    let step = match step {
+        Some(step) => step.parse::<usize>().ok().filter(|step| *step > 0)
+            .ok_or_else(|| format!("Invalid step in the range `[{}]`", range))?,
+        None => 1,
+    };
+
+    let is_number = |text: &str| !text.is_empty() && text.bytes().all(|b| b.is_ascii_digit());
+    let is_letter = |text: &str| text.len() == 1 && text.bytes().all(|b| b.is_ascii_alphabetic());
+
+    let values: Vec<String> = if is_number(start) && is_number(end) {
+        let (first, last) = match (start.parse::<u64>(), end.parse::<u64>()) {
+            (Ok(first), Ok(last)) if first <= last => (first, last),
+            _ => return Err(format!("Invalid range `[{}]`, the start must be lower than the end", range)),
+        };
+        if (last - first) / step as u64 >= MAX_PATTERN_URLS as u64 {
+            return Err(format!("The range `[{}]` expands to more than {} URLs", range, MAX_PATTERN_URLS));
+        }
+        let width = if start.len() > 1 && start.starts_with('0') { start.len() } else { 0 };
+        (first..=last).step_by(step).map(|value| format!("{:0width$}", value, width = width)).collect()
+    } else if is_letter(start) && is_letter(end) {
+        let (first, last) = (start.as_bytes()[0], end.as_bytes()[0]);
+        if first > last || first.is_ascii_lowercase() != last.is_ascii_lowercase() {
+            return Err(format!("Invalid range `[{}]`, the letters must be in order and of the same case", range));
+        }
+        (first..=last).step_by(step).map(|value| char::from(value).to_string()).collect()
+    } else {
+        return Ok(None);
+    };
+    Ok(Some(values))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expand_ranges_and_sets() {
+        let urls = expand_url_pattern("https://host/{a,b}/file_[08-10].jpg").unwrap();
+        assert_eq!(urls, vec![
+            "https://host/a/file_08.jpg", "https://host/a/file_09.jpg", "https://host/a/file_10.jpg",
+            "https://host/b/file_08.jpg", "https://host/b/file_09.jpg", "https://host/b/file_10.jpg",
+        ]);
+        assert_eq!(expand_url_pattern("https://host/[x-z]{,.sig}").unwrap(),
+            vec!["https://host/x", "https://host/x.sig", "https://host/y", "https://host/y.sig", "https://host/z", "https://host/z.sig"]);
+        assert_eq!(expand_url_pattern("https://host/[0-100:50]").unwrap(), vec!["https://host/0", "https://host/50", "https://host/100"]);
+    }
+
+    #[test]
+    fn keep_literal_brackets() {
+        assert!(!is_url_pattern("http://[::1]:8080/file"));
+        assert!(!is_url_pattern(r"https://host/\[1-2\]"));
+        assert_eq!(expand_url_pattern(r"https://host/\[1-2\]").unwrap(), vec!["https://host/[1-2]"]);
+        assert!(is_url_pattern("https://host/{a"));
+    }
+
+    #[test]
+    fn reject_invalid_patterns() {
+        assert!(expand_url_pattern("https://host/[9-1]").is_err());
+        assert!(expand_url_pattern("https://host/[a-Z]").is_err());
+        assert!(expand_url_pattern("https://host/[1-5:0]").is_err());
+        assert!(expand_url_pattern("https://host/{a,{b}}").is_err());
+        assert!(expand_url_pattern("https://host/[0-99999]").is_err());
+        assert!(expand_url_pattern("https://host/[0-999]/[0-99]").is_err());
+    }
+}
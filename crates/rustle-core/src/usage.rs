@@ -0,0 +1,280 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+use super::dates::{civil_from_days, day_of, days_from_civil, DAY_SECS};
+
+/// File name of the data usage, in the data directory of the application.
+pub const USAGE_FILE_NAME: &str = "usage.json";
+
+/// Number of days the usage is kept for, i.e. the last 13 months.
+pub const USAGE_RETENTION_DAYS: i64 = 400;
+
+// Minimum time between two writes of the usage file while the downloads receive data
+const SAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// QuotaPeriod represents the period a data quota applies to, in UTC.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum QuotaPeriod {
+    Day,
+    #[default]
+    Month,  // Calendar month, e.g. the plans of metered connections
+}
+
+/// DataQuota represents the number of bytes the downloads may receive per period, they're
+/// paused once it's reached until the next period or until the quota is raised.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DataQuota {
+    pub bytes: u64,             // Bytes allowed per period
+    pub period: QuotaPeriod,    // Period the bytes are counted over
+}
+
+/// UsageTotal represents the bytes received during a day or a month.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UsageTotal {
+    pub start: SystemTime,  // Start of the period, midnight UTC
+    pub bytes: u64,         // Bytes received by the downloads
+}
+
+/// Content of the usage file, the bytes of each day keyed by its `YYYY-MM-DD` date.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct UsageFile {
+    days: BTreeMap<String, u64>,
+}
+
+#[derive(Debug, Default)]
+struct UsageState {
+    days: BTreeMap<i64, u64>,       // Bytes received each day, keyed by the days since the Unix epoch
+    last_saved: Option<Instant>,    // When the file was last written
+    dirty: bool,                    // Whether bytes were counted since the file was last written
+}
+
+/// DataUsage counts the bytes received by the downloads per day, persisted in a JSON file, and
+/// enforces an optional data quota, e.g. on metered or satellite connections. It's shared by the
+/// downloads through their `ConnectionManager`, see `ConnectionManager::set_data_usage`: the
+/// downloads are paused while the quota is reached.
+#[derive(Debug)]
+pub struct DataUsage {
+    path: Option<PathBuf>,                  // File the usage is saved to, `None` for an in-memory usage
+    state: Mutex<UsageState>,               // Bytes received per day
+    quota: Mutex<Option<DataQuota>>,        // Quota of the downloads, if any
+    quota_reached: watch::Sender<bool>,     // Whether the quota of the current period is reached
+}
+
+impl DataUsage {
+    /// Opens the usage saved at `path`, creating its directory. A missing file starts from no usage,
+    /// the days older than `USAGE_RETENTION_DAYS` are dropped.
+    ///
+    /// Returns an error if the file couldn't be read or isn't a usage file.
+    pub fn open(path: &Path) -> Result<DataUsage, String> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir).map_err(|e| format!("Couldn't create {}, error : {}", dir.display(), e))?;
+        }
+        let file: UsageFile = match fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content).map_err(|e| format!("Couldn't parse {}, error : {}", path.display(), e))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => UsageFile::default(),
+            Err(e) => return Err(format!("Couldn't read {}, error : {}", path.display(), e)),
+        };
+        let oldest = today() - USAGE_RETENTION_DAYS;
+        let days = file.days.iter()
+            .filter_map(|(date, bytes)| parse_date(date).map(|day| (day, *bytes)))
+            .filter(|(day, _)| *day > oldest)
+            .collect();
+        Ok(DataUsage { path: Some(path.to_path_buf()), ..DataUsage::with_days(days) })
+    }
+
+    /// Creates a usage that isn't saved, e.g. for tests or applications keeping their own records.
+    pub fn in_memory() -> DataUsage {
+        DataUsage::with_days(BTreeMap::new())
+    }
+
+    fn with_days(days: BTreeMap<i64, u64>) -> DataUsage {
+        DataUsage {
+            path: None,
+            state: Mutex::new(UsageState { days, ..UsageState::default() }),
+            quota: Mutex::new(None),
+            quota_reached: watch::Sender::new(false),
+        }
+    }
+
+    /// Counts bytes received by a download today. The file is written at most every `SAVE_INTERVAL`,
+    /// `save()` writes the rest.
+    pub fn add(&self, bytes: u64) {
+        let save = {
+            let mut state = self.state.lock().unwrap();
+            *state.days.entry(today()).or_default() += bytes;
+            state.dirty = true;
+            state.last_saved.is_none_or(|last_saved| last_saved.elapsed() >= SAVE_INTERVAL)
+        };
+        self.check_quota();
+        // The bytes are counted either way, the file is written again at the next interval
+        if save {
+            let _ = self.save();
+        }
+    }
+
+    /// Writes the usage to its file if bytes were counted since it was last written.
+    ///
+    /// Returns an error message if the file couldn't be written.
+    pub fn save(&self) -> Result<(), String> {
+        let Some(path) = self.path.as_ref() else { return Ok(()) };
+        let content = {
+            let mut state = self.state.lock().unwrap();
+            state.last_saved = Some(Instant::now());
+            if !std::mem::take(&mut state.dirty) {
+                return Ok(());
+            }
+            let oldest = today() - USAGE_RETENTION_DAYS;
+            state.days.retain(|day, _| *day > oldest);
+            let file = UsageFile { days: state.days.iter().map(|(day, bytes)| (format_date(*day), *bytes)).collect() };
+            serde_json::to_string_pretty(&file).map_err(|e| format!("Couldn't serialize the data usage, error : {}", e))?
+        };
+        // Written under another name first, so a crash doesn't leave a truncated file
+        let temp_path = path.with_extension("json.tmp");
+        fs::write(&temp_path, content).map_err(|e| format!("Couldn't write {}, error : {}", temp_path.display(), e))?;
+        fs::rename(&temp_path, path).map_err(|e| format!("Couldn't write {}, error : {}", path.display(), e))
+    }
+
+    /// Returns the bytes received today, in UTC.
+    pub fn today(&self) -> u64 {
+        self.state.lock().unwrap().days.get(&today()).copied().unwrap_or(0)
+    }
+
+    /// Returns the bytes received during the current day or month, in UTC.
+    pub fn current(&self, period: QuotaPeriod) -> u64 {
+        let today = today();
+        let start = match period {
+            QuotaPeriod::Day => today,
+            QuotaPeriod::Month => month_start(today),
+        };
+        self.state.lock().unwrap().days.range(start..=today).map(|(_, bytes)| bytes).sum()
+    }
+
+    /// Returns the bytes received each day or month with usage since `since`, or all of them, oldest first.
+    pub fn totals(&self, since: Option<SystemTime>, period: QuotaPeriod) -> Vec<UsageTotal> {
+        let since = since.map(day_of).unwrap_or(i64::MIN);
+        let mut totals: BTreeMap<i64, u64> = BTreeMap::new();
+        for (day, bytes) in self.state.lock().unwrap().days.range(since..) {
+            let start = match period {
+                QuotaPeriod::Day => *day,
+                QuotaPeriod::Month => month_start(*day),
+            };
+            *totals.entry(start).or_default() += bytes;
+        }
+        totals.into_iter()
+            .map(|(day, bytes)| UsageTotal { start: UNIX_EPOCH + Duration::from_secs(day.max(0) as u64 * DAY_SECS), bytes })
+            .collect()
+    }
+
+    /// Sets the quota of the downloads, `None` for no quota. The downloads paused by the previous
+    /// quota are resumed if the new one isn't reached.
+    pub fn set_quota(&self, quota: Option<DataQuota>) {
+        *self.quota.lock().unwrap() = quota;
+        self.check_quota();
+    }
+
+    /// Returns the quota of the downloads, if any.
+    pub fn quota(&self) -> Option<DataQuota> {
+        *self.quota.lock().unwrap()
+    }
+
+    /// Returns the bytes left in the quota of the current period, `None` without a quota.
+    pub fn remaining(&self) -> Option<u64> {
+        self.quota().map(|quota| quota.bytes.saturating_sub(self.current(quota.period)))
+    }
+
+    /// Checks the quota against the usage of the current period, which starts over at midnight
+    /// UTC or on the first day of the month, and notifies the subscribers if it changed.
+    ///
+    /// Returns whether the quota is reached.
+    pub fn check_quota(&self) -> bool {
+        let reached = self.remaining() == Some(0);
+        self.quota_reached.send_if_modified(|state| std::mem::replace(state, reached) != reached);
+        reached
+    }
+
+    /// Returns whether the quota of the current period is reached, as of the last check.
+    pub fn is_quota_reached(&self) -> bool {
+        *self.quota_reached.borrow()
+    }
+
+    /// Returns a receiver notified when the quota is reached or available again.
+    pub fn subscribe_quota(&self) -> watch::Receiver<bool> {
+        self.quota_reached.subscribe()
+    }
+}
+
+/// Returns the days between the Unix epoch and today, in UTC.
+fn today() -> i64 {
+    day_of(SystemTime::now())
+}
+
+/// Returns the first day of the month of `day`.
+fn month_start(day: i64) -> i64 {
+    let (_, _, day_of_month) = civil_from_days(day);
+    day - (day_of_month as i64 - 1)
+}
+
+/// Formats a day as `YYYY-MM-DD`.
+fn format_date(day: i64) -> String {
+    let (year, month, day) = civil_from_days(day);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Parses a `YYYY-MM-DD` date into the days since the Unix epoch.
+fn parse_date(date: &str) -> Option<i64> {
+    let mut fields = date.splitn(3, '-').map(str::parse::<i64>);
+    let (year, month, day) = (fields.next()?.ok()?, fields.next()?.ok()?, fields.next()?.ok()?);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    Some(days_from_civil(year, month as u32, day as u32))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dates_round_trip() {
+        for (date, day) in [("1970-01-01", 0), ("2024-02-29", 19_782), ("2024-03-01", 19_783), ("1969-12-31", -1)] {
+            assert_eq!(parse_date(date), Some(day));
+            assert_eq!(format_date(day), date);
+        }
+        assert_eq!(month_start(19_782), 19_754);
+        assert_eq!(parse_date("2024-13-01"), None);
+        assert_eq!(parse_date("yesterday"), None);
+    }
+
+    #[test]
+    fn usage_is_persisted_and_the_quota_enforced() {
+        let path = std::env::temp_dir().join(format!("rustle-test-usage-{}", std::process::id())).join(USAGE_FILE_NAME);
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+        let usage = DataUsage::open(&path).unwrap();
+        let mut quota_reached = usage.subscribe_quota();
+        usage.set_quota(Some(DataQuota { bytes: 1000, period: QuotaPeriod::Month }));
+
+        usage.add(600);
+        assert!(!usage.is_quota_reached());
+        assert_eq!(usage.remaining(), Some(400));
+        usage.add(400);
+        assert!(usage.is_quota_reached());
+        assert!(quota_reached.has_changed().unwrap());
+        assert!(*quota_reached.borrow_and_update());
+
+        // Raising the quota makes it available again
+        usage.set_quota(Some(DataQuota { bytes: 2000, period: QuotaPeriod::Day }));
+        assert!(!*quota_reached.borrow_and_update());
+        usage.save().unwrap();
+
+        let reopened = DataUsage::open(&path).unwrap();
+        assert_eq!((reopened.today(), reopened.current(QuotaPeriod::Month)), (1000, 1000));
+        let totals = reopened.totals(None, QuotaPeriod::Day);
+        assert_eq!(totals, vec![UsageTotal { start: UNIX_EPOCH + Duration::from_secs(today() as u64 * DAY_SECS), bytes: 1000 }]);
+        assert_eq!(reopened.quota(), None);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
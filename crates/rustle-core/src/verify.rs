@@ -0,0 +1,316 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use url::Url;
+use super::audit::{is_sha256_hex, sha256_file};
+use super::dates::{days_from_civil, DAY_SECS};
+use super::downloader::{ResponseHeaderInfo, RustleDownloader};
+use super::transport::Transport;
+
+/// Extension of the checksum files published next to the files, in the format of `sha256sum`.
+pub const SIDECAR_EXTENSION: &str = "sha256";
+
+// Timeout of the request of a remote checksum file
+const SIDECAR_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Largest remote checksum file read, they list a digest per file
+const MAX_SIDECAR_LEN: usize = 64 * 1024;
+
+/// VerifyOptions represents what a local file is checked against besides the size of the remote one.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VerifyOptions {
+    pub etag: Option<String>,       // `ETag` of the version that was downloaded, the file is outdated if the server sends another one
+    pub sha256: Option<String>,     // Known SHA-256 of the file, e.g. from the history, preferred over the checksum files
+    pub remote_sidecar: bool,       // Whether `<url>.sha256` is fetched when there's no local `<path>.sha256`
+}
+
+/// HashSource represents where the SHA-256 a file is checked against comes from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HashSource {
+    Given,                  // Set in `VerifyOptions::sha256`
+    LocalSidecar(PathBuf),  // Read from the checksum file next to the local file
+    RemoteSidecar(Url),     // Fetched from the checksum file next to the remote file
+}
+
+/// VerifyIssue represents a reason the local file isn't a complete and current copy of the remote one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VerifyIssue {
+    Missing,                                            // The local file doesn't exist
+    Incomplete { local_size: u64, remote_size: u64 },   // The local file is shorter, e.g. an interrupted download
+    Oversized { local_size: u64, remote_size: u64 },    // The local file is longer, it's another file or the remote one shrank
+    EtagChanged { downloaded: String, remote: String }, // The server sends another `ETag` than the downloaded version's
+    ModifiedSince { last_modified: String },            // The server's `Last-Modified` is newer than the local file
+    HashMismatch { expected: String, actual: String },  // The SHA-256 of the local file isn't the expected one
+}
+
+impl fmt::Display for VerifyIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerifyIssue::Missing => write!(f, "The file doesn't exist"),
+            VerifyIssue::Incomplete { local_size, remote_size } => write!(f, "The file is incomplete, {} of {} bytes", local_size, remote_size),
+            VerifyIssue::Oversized { local_size, remote_size } => write!(f, "The file has {} bytes, the remote one {}", local_size, remote_size),
+            VerifyIssue::EtagChanged { downloaded, remote } => write!(f, "The remote file changed, its ETag is {} instead of {}", remote, downloaded),
+            VerifyIssue::ModifiedSince { last_modified } => write!(f, "The remote file was modified since, on {}", last_modified),
+            VerifyIssue::HashMismatch { expected, actual } => write!(f, "The SHA-256 of the file is {} instead of {}", actual, expected),
+        }
+    }
+}
+
+/// VerifyReport represents the result of checking a local file against its remote source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifyReport {
+    pub path: PathBuf,                          // Path of the local file
+    pub url: Url,                               // URL of the remote file
+    pub local_size: Option<u64>,                // Size of the local file, `None` if it doesn't exist
+    pub remote_size: Option<u64>,               // Size of the remote file, if the server sent it
+    pub etag: Option<String>,                   // `ETag` sent by the server
+    pub last_modified: Option<String>,          // `Last-Modified` sent by the server
+    pub expected_sha256: Option<(String, HashSource)>,  // SHA-256 the file is checked against, and where it comes from
+    pub sha256: Option<String>,                 // SHA-256 of the local file, only computed when one is expected and the size matches
+    pub issues: Vec<VerifyIssue>,               // Reasons the file isn't a complete and current copy, empty if it is
+}
+
+impl VerifyReport {
+    /// Returns whether the local file has all the bytes of the remote one, i.e. it exists and
+    /// isn't shorter. A size the server didn't send counts as complete.
+    pub fn is_complete(&self) -> bool {
+        !self.issues.iter().any(|issue| matches!(issue, VerifyIssue::Missing | VerifyIssue::Incomplete { .. }))
+    }
+
+    /// Returns whether the local file is a complete and current copy of the remote one, i.e. no check failed.
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    /// Returns whether the file was checked against a SHA-256, otherwise only its size and date are known to match.
+    pub fn is_hash_verified(&self) -> bool {
+        self.sha256.is_some() && self.expected_sha256.as_ref().is_some_and(|(expected, _)| self.sha256.as_deref() == Some(expected))
+    }
+}
+
+/// Checks a local file against its remote source, without downloading it: its size against
+/// the `Content-Length`, its version against the `ETag` or `Last-Modified`, and its SHA-256
+/// against the one of `options` or of a checksum file (`<path>.sha256`, then `<url>.sha256`).
+///
+/// The request is configured with the default settings, use `RustleDownloader::verify_file`
+/// on an initialized download to send it with its own.
+///
+/// # Arguments
+///
+/// * `path` - The path of the local file.
+/// * `url` - The URL of the remote file.
+/// * `options` - What the file is checked against besides its size.
+///
+/// # Returns
+///
+/// The report of the checks, or an error if the remote file couldn't be probed.
+pub async fn verify_local_file(path: &Path, url: &str, options: &VerifyOptions) -> Result<VerifyReport, String> {
+    let out_dir = path.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let engine = RustleDownloader::builder().url(url).out_dir(out_dir).build().await?;
+    engine.verify_file(path, options).await
+}
+
+/// Checks a local file against the probed information of its remote source, see `verify_local_file`.
+/// `transport` fetches the remote checksum file if it's enabled.
+pub async fn verify_against(path: &Path, url: &Url, info: &ResponseHeaderInfo, transport: &dyn Transport, options: &VerifyOptions) -> Result<VerifyReport, String> {
+    let mut report = VerifyReport {
+        path: path.to_path_buf(),
+        url: url.clone(),
+        local_size: None,
+        // The length of a compressed response isn't the size of the file
+        remote_size: info.content_length.filter(|_| info.content_encoding.is_none()),
+        etag: info.etag.clone(),
+        last_modified: info.last_modified.clone(),
+        expected_sha256: None,
+        sha256: None,
+        issues: Vec::new(),
+    };
+
+    let metadata = match tokio::fs::metadata(path).await {
+        Ok(metadata) if metadata.is_file() => metadata,
+        Ok(_) => return Err(format!("{} isn't a file", path.display())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            report.issues.push(VerifyIssue::Missing);
+            return Ok(report);
+        },
+        Err(e) => return Err(format!("Couldn't read {}, error : {}", path.display(), e)),
+    };
+    let local_size = metadata.len();
+    report.local_size = Some(local_size);
+
+    match report.remote_size {
+        Some(remote_size) if local_size < remote_size => report.issues.push(VerifyIssue::Incomplete { local_size, remote_size }),
+        Some(remote_size) if local_size > remote_size => report.issues.push(VerifyIssue::Oversized { local_size, remote_size }),
+        _ => {},
+    }
+
+    // The entity tag tells whether it's the downloaded version, the dates only whether it's older
+    match (options.etag.as_deref(), info.etag.as_deref()) {
+        (Some(downloaded), Some(remote)) => {
+            if downloaded != remote {
+                report.issues.push(VerifyIssue::EtagChanged { downloaded: downloaded.to_string(), remote: remote.to_string() });
+            }
+        },
+        _ => {
+            let remote_modified = info.last_modified.as_deref().and_then(parse_http_date);
+            if let (Some(remote_modified), Ok(local_modified)) = (remote_modified, metadata.modified()) {
+                if remote_modified > local_modified {
+                    report.issues.push(VerifyIssue::ModifiedSince { last_modified: info.last_modified.clone().unwrap() });
+                }
+            }
+        },
+    }
+
+    report.expected_sha256 = expected_sha256(path, url, transport, options).await;
+    if let Some((expected, _)) = report.expected_sha256.as_ref().filter(|_| report.issues.is_empty()) {
+        let hashed_path = path.to_path_buf();
+        let actual = tokio::task::spawn_blocking(move || sha256_file(&hashed_path)).await
+            .map_err(|e| format!("Couldn't hash {}, error : {}", path.display(), e))??;
+        if actual != *expected {
+            report.issues.push(VerifyIssue::HashMismatch { expected: expected.clone(), actual: actual.clone() });
+        }
+        report.sha256 = Some(actual);
+    }
+
+    Ok(report)
+}
+
+/// Returns the SHA-256 a file is checked against, from `options`, the local checksum file or the
+/// remote one in that order. A checksum file that can't be read or doesn't list the file is skipped.
+async fn expected_sha256(path: &Path, url: &Url, transport: &dyn Transport, options: &VerifyOptions) -> Option<(String, HashSource)> {
+    if let Some(sha256) = options.sha256.as_deref().filter(|sha256| is_sha256_hex(sha256)) {
+        return Some((sha256.to_ascii_lowercase(), HashSource::Given));
+    }
+
+    let file_name = path.file_name()?.to_string_lossy().into_owned();
+    let local_sidecar = sidecar_path(path);
+    if let Ok(content) = tokio::fs::read_to_string(&local_sidecar).await {
+        if let Some(sha256) = parse_sidecar(&content, &file_name) {
+            return Some((sha256, HashSource::LocalSidecar(local_sidecar)));
+        }
+    }
+
+    if !options.remote_sidecar {
+        return None;
+    }
+    let remote_sidecar = sidecar_url(url)?;
+    let mut response = transport.get_range(&remote_sidecar, None, None, Some(SIDECAR_TIMEOUT)).await.ok()?;
+    if !response.status.is_success() {
+        return None;
+    }
+    let mut content = Vec::new();
+    while let Some(chunk) = response.chunk().await.ok()? {
+        content.extend_from_slice(&chunk);
+        if content.len() > MAX_SIDECAR_LEN {
+            return None;
+        }
+    }
+    let remote_name = url.path_segments().and_then(|mut segments| segments.next_back()).unwrap_or_default();
+    let content = String::from_utf8_lossy(&content);
+    parse_sidecar(&content, remote_name).or_else(|| parse_sidecar(&content, &file_name))
+        .map(|sha256| (sha256, HashSource::RemoteSidecar(remote_sidecar)))
+}
+
+/// Returns the path of the checksum file of a local file, e.g. `ubuntu.iso.sha256` for `ubuntu.iso`.
+pub fn sidecar_path(path: &Path) -> PathBuf {
+    let mut sidecar = path.as_os_str().to_owned();
+    sidecar.push(".");
+    sidecar.push(SIDECAR_EXTENSION);
+    PathBuf::from(sidecar)
+}
+
+/// Returns the URL of the checksum file of a remote file, e.g. `https://example.com/ubuntu.iso.sha256`,
+/// keeping the query. Returns `None` for URLs without a file name.
+pub fn sidecar_url(url: &Url) -> Option<Url> {
+    let file_name = url.path_segments()?.next_back().filter(|name| !name.is_empty())?;
+    let mut sidecar = url.clone();
+    sidecar.path_segments_mut().ok()?.pop().push(&format!("{}.{}", file_name, SIDECAR_EXTENSION));
+    sidecar.set_fragment(None);
+    Some(sidecar)
+}
+
+/// Returns the SHA-256 of `file_name` listed in a checksum file, lowercase hex. Both the `sha256sum`
+/// format (`<digest>  <name>`, `*` before binary names) and the BSD one (`SHA256 (<name>) = <digest>`)
+/// are read, a file with a single bare digest applies to any name.
+pub fn parse_sidecar(content: &str, file_name: &str) -> Option<String> {
+    let mut digests = Vec::new();
+    for line in content.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+        let (digest, name) = match line.strip_prefix("SHA256 (") {
+            Some(rest) => match rest.rsplit_once(") = ") {
+                Some((name, digest)) => (digest.trim(), Some(name)),
+                None => continue,
+            },
+            None => match line.split_once(char::is_whitespace) {
+                Some((digest, name)) => (digest, Some(name.trim_start().trim_start_matches('*'))),
+                None => (line, None),
+            },
+        };
+        if !is_sha256_hex(digest) {
+            continue;
+        }
+        // Names may be listed with their directory, e.g. `./ubuntu.iso`
+        match name.map(|name| name.rsplit(['/', '\\']).next().unwrap_or(name)) {
+            Some(name) if name == file_name => return Some(digest.to_ascii_lowercase()),
+            _ => digests.push((digest, name)),
+        }
+    }
+    match digests.as_slice() {
+        [(digest, None)] => Some(digest.to_ascii_lowercase()),
+        _ => None,
+    }
+}
+
+/// Parses an HTTP date in the format of `Last-Modified`, e.g. `Sun, 06 Nov 1994 08:49:37 GMT`.
+/// The obsolete formats aren't read.
+pub fn parse_http_date(value: &str) -> Option<SystemTime> {
+    const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+    let (_, date) = value.trim().split_once(", ")?;
+    let fields: Vec<&str> = date.split(' ').collect();
+    let [day, month, year, time, "GMT"] = fields.as_slice() else {
+        return None;
+    };
+    let day: u32 = day.parse().ok()?;
+    let month = MONTHS.iter().position(|name| name == month)? as u32 + 1;
+    let year: i64 = year.parse().ok()?;
+    let time: Vec<u64> = time.split(':').map(str::parse).collect::<Result<_, _>>().ok()?;
+    let [hours, minutes, seconds] = time.as_slice() else {
+        return None;
+    };
+    if !(1..=31).contains(&day) || *hours > 23 || *minutes > 59 || *seconds > 60 {
+        return None;
+    }
+    let days = u64::try_from(days_from_civil(year, month, day)).ok()?;
+    Some(UNIX_EPOCH + Duration::from_secs(days * DAY_SECS + hours * 3600 + minutes * 60 + seconds))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIGEST: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+    #[test]
+    fn checksum_files_are_parsed() {
+        let sha256sum = format!("{}  other.iso\n{} *ubuntu.iso\n", "0".repeat(64), DIGEST.to_uppercase());
+        assert_eq!(parse_sidecar(&sha256sum, "ubuntu.iso").as_deref(), Some(DIGEST));
+        assert_eq!(parse_sidecar(&sha256sum, "missing.iso"), None);
+        assert_eq!(parse_sidecar(&format!("SHA256 (./dist/ubuntu.iso) = {}", DIGEST), "ubuntu.iso").as_deref(), Some(DIGEST));
+        assert_eq!(parse_sidecar(&format!("# checksum\n{}\n", DIGEST), "any.bin").as_deref(), Some(DIGEST));
+        assert_eq!(parse_sidecar("not a digest  ubuntu.iso", "ubuntu.iso"), None);
+
+        assert_eq!(sidecar_path(Path::new("downloads/ubuntu.iso")), Path::new("downloads/ubuntu.iso.sha256"));
+        let url = Url::parse("https://example.com/releases/ubuntu.iso?mirror=1#top").unwrap();
+        assert_eq!(sidecar_url(&url).unwrap().as_str(), "https://example.com/releases/ubuntu.iso.sha256?mirror=1");
+        assert_eq!(sidecar_url(&Url::parse("https://example.com/").unwrap()), None);
+    }
+
+    #[test]
+    fn http_dates_are_parsed() {
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"), Some(UNIX_EPOCH + Duration::from_secs(784_111_777)));
+        assert_eq!(parse_http_date("Wed, 01 May 2024 12:30:00 GMT"), Some(UNIX_EPOCH + Duration::from_secs(1_714_566_600)));
+        assert_eq!(parse_http_date("Thu, 29 Feb 2024 00:00:00 GMT"), Some(UNIX_EPOCH + Duration::from_secs(1_709_164_800)));
+        assert_eq!(parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"), None);
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:37 CET"), None);
+        assert_eq!(parse_http_date("Sun, 06 Foo 1994 08:49:37 GMT"), None);
+    }
+}
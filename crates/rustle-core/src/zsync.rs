@@ -0,0 +1,461 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use sha1::{Digest, Sha1};
+use url::Url;
+use super::io::sanitize_file_name;
+use super::transport::{ByteRange, Transport};
+
+/// Extension of zsync control files, e.g. `ubuntu.iso.zsync`.
+pub const ZSYNC_EXTENSION: &str = "zsync";
+
+/// Maximum size of a control file, it has a few bytes per block of the file.
+const MAX_CONTROL_SIZE: usize = 64 * 1024 * 1024;
+
+/// Timeout of the request fetching a control file.
+const CONTROL_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Size of the chunks of the older file read at once while looking for blocks.
+const SCAN_CHUNK_SIZE: usize = 16 * 1024 * 1024;
+
+/// Extension of the file written while it's updated, it replaces the target once verified.
+const PART_EXTENSION: &str = "zsync-part";
+
+/// ZsyncControl represents a `.zsync` control file published next to a file: the rolling and
+/// MD4 checksums of each of its blocks, so a client with an older version of the file can reuse
+/// the blocks it already has and only download the other ones with range requests.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ZsyncControl {
+    pub file_name: String,          // Name of the file
+    pub length: u64,                // Size of the file in bytes
+    pub block_size: usize,          // Size of the blocks, the last one is padded with zeros
+    pub seq_matches: usize,         // Number of consecutive blocks that must match, 1 or 2
+    pub rsum_bytes: usize,          // Bytes of the rolling checksum stored per block
+    pub checksum_bytes: usize,      // Bytes of the MD4 checksum stored per block
+    pub url: String,                // URL of the file, relative to the control file
+    pub sha1: Option<String>,       // SHA-1 of the whole file, lowercase hex
+    blocks: Vec<BlockChecksums>,    // Checksums of each block
+}
+
+/// Represents the checksums of a block, truncated like in the control file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct BlockChecksums {
+    rsum: u32,          // Last `rsum_bytes` of the rolling checksum
+    checksum: Vec<u8>,  // First `checksum_bytes` of the MD4
+}
+
+/// DeltaStats represents the outcome of a delta download.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeltaStats {
+    pub path: PathBuf,      // Path of the updated file
+    pub length: u64,        // Size of the file in bytes
+    pub reused: u64,        // Bytes copied from the older file
+    pub downloaded: u64,    // Bytes downloaded
+}
+
+impl ZsyncControl {
+    /// Parses a control file, as written by `zsyncmake`.
+    ///
+    /// Returns an error if it's invalid, or only lists a compressed version of the file (`Z-URL`).
+    pub fn parse(data: &[u8]) -> Result<ZsyncControl, String> {
+        let header_end = data.windows(2).position(|window| window == b"\n\n").ok_or("missing the end of the header")?;
+        let header = std::str::from_utf8(&data[..header_end]).map_err(|_| "the header isn't valid UTF-8")?;
+
+        let mut values: HashMap<String, String> = HashMap::new();
+        for line in header.lines() {
+            let Some((key, value)) = line.split_once(':') else { return Err(format!("invalid header line `{}`", line)) };
+            // Only the first URL is used, the other ones are mirrors
+            values.entry(key.trim().to_ascii_lowercase()).or_insert_with(|| value.trim().to_string());
+        }
+        let value = |key: &str| values.get(key).ok_or(format!("missing `{}`", key));
+        let number = |key: &str| -> Result<u64, String> { value(key)?.parse().map_err(|_| format!("invalid `{}`", key)) };
+
+        let url = match values.get("url") {
+            Some(url) => url.clone(),
+            None if values.contains_key("z-url") => return Err(String::from("compressed files (`Z-URL`) aren't supported")),
+            None => return Err(String::from("missing `url`")),
+        };
+        let length = number("length")?;
+        let block_size = number("blocksize")? as usize;
+        if block_size == 0 || !block_size.is_power_of_two() {
+            return Err(String::from("the block size must be a power of two"));
+        }
+        let hash_lengths: Vec<usize> = value("hash-lengths")?.split(',').map(|length| length.trim().parse()).collect::<Result<_, _>>()
+            .map_err(|_| "invalid `hash-lengths`")?;
+        let [seq_matches, rsum_bytes, checksum_bytes] = hash_lengths[..] else { return Err(String::from("invalid `hash-lengths`")) };
+        if !(1..=2).contains(&seq_matches) || !(1..=4).contains(&rsum_bytes) || !(3..=16).contains(&checksum_bytes) {
+            return Err(String::from("invalid `hash-lengths`"));
+        }
+        let sha1 = values.get("sha-1").map(|sha1| sha1.to_ascii_lowercase());
+
+        let block_count = length.div_ceil(block_size as u64) as usize;
+        let block_len = rsum_bytes + checksum_bytes;
+        let sums = &data[header_end + 2..];
+        if sums.len() < block_count * block_len {
+            return Err(format!("expected the checksums of {} blocks", block_count));
+        }
+        let blocks = sums.chunks_exact(block_len).take(block_count)
+            .map(|sums| BlockChecksums {
+                rsum: sums[..rsum_bytes].iter().fold(0, |rsum, byte| (rsum << 8) | *byte as u32),
+                checksum: sums[rsum_bytes..].to_vec(),
+            })
+            .collect();
+
+        let file_name = values.get("filename").cloned().unwrap_or_else(|| url.rsplit('/').next().unwrap_or_default().to_string());
+        Ok(ZsyncControl { file_name, length, block_size, seq_matches, rsum_bytes, checksum_bytes, url, sha1, blocks })
+    }
+
+    /// Returns the number of blocks of the file.
+    pub fn block_count(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// Returns the range of bytes of a block in the file.
+    fn block_range(&self, block: usize) -> (u64, u64) {
+        let start = (block * self.block_size) as u64;
+        (start, (start + self.block_size as u64).min(self.length))
+    }
+
+    /// Returns the rolling checksum of a window, truncated like the ones of the control file.
+    fn truncated_rsum(&self, (a, b): (u16, u16)) -> u32 {
+        let rsum = ((a as u32) << 16) | b as u32;
+        match self.rsum_bytes {
+            4 => rsum,
+            bytes => rsum & ((1 << (8 * bytes)) - 1),
+        }
+    }
+
+    /// Returns whether `data` (a full block, zero-padded) has the checksums of `block`.
+    fn block_matches(&self, block: usize, data: &[u8]) -> bool {
+        let sums = &self.blocks[block];
+        self.truncated_rsum(rsum(data)) == sums.rsum && md4(data)[..self.checksum_bytes] == sums.checksum[..]
+    }
+
+    /// Copies the blocks of the file found in an older version into `out`, looking for them at
+    /// every offset with the rolling checksum like rsync.
+    ///
+    /// # Arguments
+    ///
+    /// * `seed` - The older version of the file.
+    /// * `out` - The file being assembled, of the size of the new file.
+    ///
+    /// Returns whether each block was found, or an error if a file couldn't be read or written.
+    pub fn copy_matching_blocks(&self, seed: &mut impl Read, out: &mut (impl Write + Seek)) -> std::io::Result<Vec<bool>> {
+        let mut known = vec![false; self.block_count()];
+        if known.is_empty() {
+            return Ok(known);
+        }
+
+        let mut blocks_by_rsum: HashMap<u32, Vec<usize>> = HashMap::new();
+        for (block, sums) in self.blocks.iter().enumerate() {
+            blocks_by_rsum.entry(sums.rsum).or_default().push(block);
+        }
+
+        let block_size = self.block_size;
+        // Enough data to check consecutive blocks, and the byte rolled in
+        let window = block_size * self.seq_matches + 1;
+        let mut buffer = Vec::new();
+        let mut position = 0;
+        let mut data_end = None;
+        let mut rolling = None;
+        let mut remaining = known.len();
+
+        while remaining > 0 {
+            if buffer.len() - position < window && data_end.is_none() {
+                buffer.drain(..position);
+                position = 0;
+                let read = seed.by_ref().take(SCAN_CHUNK_SIZE as u64).read_to_end(&mut buffer)?;
+                if read == 0 {
+                    // The last block of the file is padded with zeros
+                    data_end = Some(buffer.len());
+                    buffer.resize(buffer.len() + window, 0);
+                }
+                continue;
+            }
+            if data_end.is_some_and(|data_end| position >= data_end) || buffer.len() - position < window {
+                break;
+            }
+
+            let (a, b) = *rolling.get_or_insert_with(|| rsum(&buffer[position..position + block_size]));
+            let found = blocks_by_rsum.get(&self.truncated_rsum((a, b))).and_then(|blocks| {
+                blocks.iter().copied().find(|block| {
+                    !known[*block] && self.block_matches(*block, &buffer[position..position + block_size])
+                        && (self.seq_matches == 1 || *block + 1 == known.len()
+                            || self.block_matches(*block + 1, &buffer[position + block_size..position + 2 * block_size]))
+                })
+            });
+
+            match found {
+                Some(first) => {
+                    // The following blocks were checked too when consecutive matches are required
+                    for block in first..(first + self.seq_matches).min(known.len()) {
+                        let (start, end) = self.block_range(block);
+                        let offset = position + (block - first) * block_size;
+                        out.seek(SeekFrom::Start(start))?;
+                        out.write_all(&buffer[offset..offset + (end - start) as usize])?;
+                        if !known[block] {
+                            known[block] = true;
+                            remaining -= 1;
+                        }
+                    }
+                    position += block_size;
+                    rolling = None;
+                },
+                None => {
+                    let (old, new) = (buffer[position] as u16, buffer[position + block_size] as u16);
+                    let a = a.wrapping_sub(old).wrapping_add(new);
+                    let b = b.wrapping_sub(old.wrapping_mul(block_size as u16)).wrapping_add(a);
+                    rolling = Some((a, b));
+                    position += 1;
+                },
+            }
+        }
+        Ok(known)
+    }
+}
+
+/// Fetches and parses a control file.
+///
+/// # Arguments
+///
+/// * `transport` - The transport sending the request.
+/// * `url` - The URL of the `.zsync` file.
+pub async fn fetch_control(transport: &dyn Transport, url: &Url) -> Result<ZsyncControl, String> {
+    let mut response = transport.get_range(url, None, None, Some(CONTROL_TIMEOUT)).await
+        .map_err(|e| format!("Couldn't fetch the control file {}, error : {}", url, e))?;
+    if !response.status.is_success() {
+        return Err(format!("Couldn't fetch the control file {}, status : {}", url, response.status));
+    }
+    let mut data = Vec::new();
+    while let Some(chunk) = response.chunk().await.map_err(|e| format!("Couldn't read the control file {}, error : {}", url, e))? {
+        if data.len() + chunk.len() > MAX_CONTROL_SIZE {
+            return Err(format!("The control file {} is larger than {} bytes", url, MAX_CONTROL_SIZE));
+        }
+        data.extend_from_slice(&chunk);
+    }
+    ZsyncControl::parse(&data).map_err(|e| format!("Invalid control file {}, {}", url, e))
+}
+
+/// Downloads the new version of a file described by a zsync control file, reusing the blocks of
+/// an older version and downloading the other ones with range requests. The file is written next
+/// to the target and replaces it once its SHA-1 is verified, the older version can be the target.
+///
+/// # Arguments
+///
+/// * `transport` - The transport sending the requests.
+/// * `control_url` - The URL of the `.zsync` file.
+/// * `seed` - The older version of the file, the whole file is downloaded without one.
+/// * `out_dir` - The directory the file is written to, under the name of the control file.
+///
+/// Returns an error if a request fails, the server doesn't support range requests or the file
+/// doesn't match its checksum.
+pub async fn delta_download(transport: &dyn Transport, control_url: &Url, seed: Option<&Path>, out_dir: &Path) -> Result<DeltaStats, String> {
+    let control = fetch_control(transport, control_url).await?;
+    let url = control_url.join(&control.url).map_err(|e| format!("Invalid URL {} in the control file, error : {}", control.url, e))?;
+    let path = out_dir.join(sanitize_file_name(&control.file_name));
+    let part_path = path.with_file_name(format!("{}.{}", sanitize_file_name(&control.file_name), PART_EXTENSION));
+
+    fs::create_dir_all(out_dir).map_err(|e| format!("Couldn't create {}, error : {}", out_dir.display(), e))?;
+    let result = assemble(transport, &control, &url, seed, &part_path).await;
+    let (reused, downloaded) = match result {
+        Ok(stats) => stats,
+        Err(e) => {
+            let _ = fs::remove_file(&part_path);
+            return Err(e);
+        },
+    };
+    fs::rename(&part_path, &path).map_err(|e| format!("Couldn't move the file to {}, error : {}", path.display(), e))?;
+
+    Ok(DeltaStats { path, length: control.length, reused, downloaded })
+}
+
+/// Writes the new version of the file to `part_path` and verifies it, returning the bytes reused and downloaded.
+async fn assemble(transport: &dyn Transport, control: &ZsyncControl, url: &Url, seed: Option<&Path>, part_path: &Path) -> Result<(u64, u64), String> {
+    let write_error = |e: std::io::Error| format!("Couldn't write {}, error : {}", part_path.display(), e);
+    let mut out = File::create(part_path).map_err(write_error)?;
+    out.set_len(control.length).map_err(write_error)?;
+
+    // Looking for the blocks reads the whole older file, it's done outside of the runtime
+    let known = match seed {
+        Some(seed) => {
+            let (control, seed, mut out) = (control.clone(), seed.to_path_buf(), out.try_clone().map_err(write_error)?);
+            tokio::task::spawn_blocking(move || {
+                let mut seed = File::open(&seed).map_err(|e| format!("Couldn't open {}, error : {}", seed.display(), e))?;
+                control.copy_matching_blocks(&mut seed, &mut out).map_err(|e| format!("Couldn't reuse the older file, error : {}", e))
+            }).await.map_err(|e| e.to_string())??
+        },
+        None => vec![false; control.block_count()],
+    };
+
+    // Consecutive missing blocks are fetched with a single request
+    let mut reused = 0;
+    let mut ranges: Vec<(u64, u64)> = Vec::new();
+    for (block, known) in known.iter().enumerate() {
+        let (start, end) = control.block_range(block);
+        match (known, ranges.last_mut()) {
+            (true, _) => reused += end - start,
+            (false, Some(range)) if range.1 == start => range.1 = end,
+            (false, _) => ranges.push((start, end)),
+        }
+    }
+
+    let mut downloaded = 0;
+    for (start, end) in ranges {
+        let mut response = transport.get_range(url, Some(ByteRange { start, end: end - 1 }), None, None).await
+            .map_err(|e| format!("Couldn't download {}, error : {}", url, e))?;
+        let whole_file = start == 0 && end == control.length && response.status.is_success();
+        if response.status.as_u16() != 206 && !whole_file {
+            return Err(format!("Couldn't download the changed blocks of {}, the server doesn't support range requests (status : {})", url, response.status));
+        }
+
+        out.seek(SeekFrom::Start(start)).map_err(write_error)?;
+        let mut offset = start;
+        while let Some(chunk) = response.chunk().await.map_err(|e| format!("Couldn't download {}, error : {}", url, e))? {
+            let len = (chunk.len() as u64).min(end - offset) as usize;
+            out.write_all(&chunk[..len]).map_err(write_error)?;
+            offset += len as u64;
+        }
+        if offset != end {
+            return Err(format!("The download of {} stopped at byte {} instead of {}", url, offset, end));
+        }
+        downloaded += end - start;
+    }
+    out.sync_all().map_err(write_error)?;
+    drop(out);
+
+    if let Some(expected) = control.sha1.as_ref() {
+        let sha1 = sha1_file(part_path)?;
+        if &sha1 != expected {
+            return Err(format!("The updated file doesn't match its SHA-1, expected {} got {}", expected, sha1));
+        }
+    }
+    Ok((reused, downloaded))
+}
+
+/// Returns the lowercase hex SHA-1 of a file.
+fn sha1_file(path: &Path) -> Result<String, String> {
+    let mut file = File::open(path).map_err(|e| format!("Couldn't open {}, error : {}", path.display(), e))?;
+    let mut hasher = Sha1::new();
+    let mut buffer = vec![0; 1024 * 1024];
+    loop {
+        let read = file.read(&mut buffer).map_err(|e| format!("Couldn't read {}, error : {}", path.display(), e))?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+/// Returns the rolling checksum of a block, the `a` and `b` sums of zsync.
+fn rsum(data: &[u8]) -> (u16, u16) {
+    data.iter().fold((0u16, 0u16), |(a, b), byte| {
+        let a = a.wrapping_add(*byte as u16);
+        (a, b.wrapping_add(a))
+    })
+}
+
+/// Returns the MD4 digest of `data` (RFC 1320), used by zsync for the block checksums.
+fn md4(data: &[u8]) -> [u8; 16] {
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64).wrapping_mul(8)).to_le_bytes());
+
+    let f = |x: u32, y: u32, z: u32| (x & y) | (!x & z);
+    let g = |x: u32, y: u32, z: u32| (x & y) | (x & z) | (y & z);
+    let h = |x: u32, y: u32, z: u32| x ^ y ^ z;
+
+    let mut state: [u32; 4] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476];
+    for chunk in message.chunks_exact(64) {
+        let x: Vec<u32> = chunk.chunks_exact(4).map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]])).collect();
+        let [mut a, mut b, mut c, mut d] = state;
+
+        for i in [0, 4, 8, 12] {
+            a = a.wrapping_add(f(b, c, d)).wrapping_add(x[i]).rotate_left(3);
+            d = d.wrapping_add(f(a, b, c)).wrapping_add(x[i + 1]).rotate_left(7);
+            c = c.wrapping_add(f(d, a, b)).wrapping_add(x[i + 2]).rotate_left(11);
+            b = b.wrapping_add(f(c, d, a)).wrapping_add(x[i + 3]).rotate_left(19);
+        }
+        for i in 0..4 {
+            a = a.wrapping_add(g(b, c, d)).wrapping_add(x[i]).wrapping_add(0x5a827999).rotate_left(3);
+            d = d.wrapping_add(g(a, b, c)).wrapping_add(x[i + 4]).wrapping_add(0x5a827999).rotate_left(5);
+            c = c.wrapping_add(g(d, a, b)).wrapping_add(x[i + 8]).wrapping_add(0x5a827999).rotate_left(9);
+            b = b.wrapping_add(g(c, d, a)).wrapping_add(x[i + 12]).wrapping_add(0x5a827999).rotate_left(13);
+        }
+        for i in [0, 2, 1, 3] {
+            a = a.wrapping_add(h(b, c, d)).wrapping_add(x[i]).wrapping_add(0x6ed9eba1).rotate_left(3);
+            d = d.wrapping_add(h(a, b, c)).wrapping_add(x[i + 8]).wrapping_add(0x6ed9eba1).rotate_left(9);
+            c = c.wrapping_add(h(d, a, b)).wrapping_add(x[i + 4]).wrapping_add(0x6ed9eba1).rotate_left(11);
+            b = b.wrapping_add(h(c, d, a)).wrapping_add(x[i + 12]).wrapping_add(0x6ed9eba1).rotate_left(15);
+        }
+
+        for (word, value) in state.iter_mut().zip([a, b, c, d]) {
+            *word = word.wrapping_add(value);
+        }
+    }
+
+    let mut digest = [0; 16];
+    for (bytes, word) in digest.chunks_exact_mut(4).zip(state) {
+        bytes.copy_from_slice(&word.to_le_bytes());
+    }
+    digest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// Returns the control file of `data`, like `zsyncmake`.
+    fn make_control(data: &[u8], block_size: usize, seq_matches: usize) -> Vec<u8> {
+        let mut control = format!("zsync: 0.6.2\nFilename: new.bin\nBlocksize: {}\nLength: {}\nHash-Lengths: {},3,5\nURL: new.bin\n\n",
+            block_size, data.len(), seq_matches).into_bytes();
+        for block in data.chunks(block_size) {
+            let mut block = block.to_vec();
+            block.resize(block_size, 0);
+            let (a, b) = rsum(&block);
+            control.extend_from_slice(&[a as u8, (b >> 8) as u8, b as u8]);
+            control.extend_from_slice(&md4(&block)[..5]);
+        }
+        control
+    }
+
+    #[test]
+    fn md4_test_vectors() {
+        let hex = |data: &[u8]| md4(data).iter().map(|byte| format!("{:02x}", byte)).collect::<String>();
+        assert_eq!(hex(b""), "31d6cfe0d16ae931b73c59d7e0c089c0");
+        assert_eq!(hex(b"abc"), "a448017aaf21d8525fc10ae87aa6729d");
+        assert_eq!(hex(b"12345678901234567890123456789012345678901234567890123456789012345678901234567890"), "e33b4ddc9c38f2199c3e7b164fcc0536");
+    }
+
+    #[test]
+    fn copies_shifted_blocks_of_the_older_file() {
+        let new: Vec<u8> = (0..1000u32).map(|i| (i.wrapping_mul(2654435761) >> 24) as u8).collect();
+        // Inserted bytes shift the following blocks, and the middle changed
+        let mut old = b"prefix".to_vec();
+        old.extend_from_slice(&new[..400]);
+        old.extend_from_slice(&[0xff; 64]);
+        old.extend_from_slice(&new[464..]);
+
+        for seq_matches in [1, 2] {
+            let control = ZsyncControl::parse(&make_control(&new, 32, seq_matches)).unwrap();
+            assert_eq!((control.block_count(), control.length, control.file_name.as_str()), (32, 1000, "new.bin"));
+
+            let mut out = Cursor::new(vec![0; new.len()]);
+            let known = control.copy_matching_blocks(&mut Cursor::new(&old), &mut out).unwrap();
+            for (block, known) in known.iter().enumerate() {
+                let (start, end) = control.block_range(block);
+                let changed = start < 464 && end > 400;
+                assert_eq!(*known, !changed, "block {} with {} sequential matches", block, seq_matches);
+                if *known {
+                    assert_eq!(out.get_ref()[start as usize..end as usize], new[start as usize..end as usize]);
+                }
+            }
+        }
+    }
+}
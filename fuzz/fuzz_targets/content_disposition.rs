@@ -0,0 +1,12 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rustle_core::downloader::content_disposition_file_name;
+use rustle_core::io::sanitize_file_name;
+
+// Any Content-Disposition value must parse without panicking, down to the file name written to disk
+fuzz_target!(|value: &str| {
+    if let Some(file_name) = content_disposition_file_name(value) {
+        let _ = sanitize_file_name(file_name);
+    }
+});
@@ -0,0 +1,14 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rustle_core::downloader::ContentRange;
+
+// A parsed Content-Range must describe a valid range of the resource
+fuzz_target!(|value: &str| {
+    if let Ok(range) = value.parse::<ContentRange>() {
+        assert!(range.start <= range.end);
+        if let Some(total) = range.total {
+            assert!(range.end < total);
+        }
+    }
+});
@@ -0,0 +1,12 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rustle_core::io::sanitize_file_name;
+
+// A sanitized file name must stay inside the output directory
+fuzz_target!(|file_name: &str| {
+    let sanitized = sanitize_file_name(file_name);
+    assert!(!sanitized.is_empty());
+    assert!(!sanitized.contains(['/', '\\']));
+    assert!(sanitized != "." && sanitized != "..");
+});
@@ -0,0 +1,41 @@
+#![no_main]
+
+use http::{HeaderMap, HeaderName, HeaderValue, StatusCode};
+use libfuzzer_sys::{arbitrary, fuzz_target};
+use rustle_core::downloader::{parse_response_headers, SupportPartialRequest};
+use url::Url;
+
+/// Headers read by the parser, the fuzzed headers are picked among them.
+const HEADER_NAMES: [&str; 8] = [
+    "content-length", "content-range", "accept-ranges", "content-type",
+    "content-encoding", "etag", "last-modified", "content-disposition",
+];
+
+/// Response represents an arbitrary response sent by a server.
+#[derive(Debug, arbitrary::Arbitrary)]
+struct Response<'a> {
+    status: u16,                    // Status code, only valid codes are parsed
+    path: &'a str,                  // Path of the URL the file may be named from
+    headers: Vec<(u8, &'a [u8])>,   // Index in `HEADER_NAMES` and raw value of each header
+}
+
+// Any response must parse without panicking, into a safe file name
+fuzz_target!(|response: Response| {
+    let Ok(status) = StatusCode::from_u16(response.status) else { return };
+    let Ok(url) = Url::parse("https://example.com/").and_then(|base| base.join(response.path)) else { return };
+    let mut headers = HeaderMap::new();
+    for (name, value) in response.headers {
+        let name = HeaderName::from_static(HEADER_NAMES[name as usize % HEADER_NAMES.len()]);
+        if let Ok(value) = HeaderValue::from_bytes(value) {
+            headers.append(name, value);
+        }
+    }
+
+    if let Ok(info) = parse_response_headers(status, &url, &headers) {
+        let file_name = info.file_name.expect("A file name is always detected");
+        assert!(!file_name.is_empty() && !file_name.contains(['/', '\\']) && file_name != "..");
+        if info.content_encoding.is_some() {
+            assert_eq!(info.support_partial, SupportPartialRequest::No);
+        }
+    }
+});
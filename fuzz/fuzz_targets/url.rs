@@ -0,0 +1,13 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rustle_core::downloader::ValidUrl;
+
+// A parsed URL must serialize to a string that parses back to the same URL
+fuzz_target!(|url: &str| {
+    if let Ok(valid_url) = ValidUrl::new(url) {
+        let _ = valid_url.host();
+        let reparsed = ValidUrl::new(valid_url.as_str()).expect("a serialized url should parse again");
+        assert_eq!(reparsed.as_str(), valid_url.as_str());
+    }
+});
@@ -0,0 +1,3 @@
+pub mod schema;
+pub mod settings;
+pub mod profiles;
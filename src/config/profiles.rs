@@ -0,0 +1,231 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use toml_edit::{DocumentMut, ImDocument, Item, Table, Value};
+use super::schema::{validate, ConfigIssue, SchemaEntry, SettingKind};
+use super::settings::{Settings, SETTINGS_SCHEMA};
+
+/// Name of the profile made of the top-level entries of the settings file.
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// Profiles represents the settings file: the default settings plus named profiles
+/// (declared as `[profiles.<name>]` tables) overriding some of them.
+#[derive(Debug, Clone, Default)]
+pub struct Profiles {
+    default: Settings,                      // Settings from the top-level entries
+    named: BTreeMap<String, Settings>,      // Settings of each named profile, inheriting from the default ones
+    active: Option<String>,                 // Name of the selected profile, `None` for the default one
+}
+
+impl Profiles {
+    /// Loads the profiles from a TOML file.
+    /// A missing file yields the default settings, invalid entries are reported and
+    /// replaced by their defaults instead of aborting the whole load.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the settings file.
+    ///
+    /// # Returns
+    ///
+    /// Returns a tuple containing:
+    /// * The loaded `Profiles`, with the default profile selected.
+    /// * A vector of the issues found in the file.
+    pub fn load(path: &Path) -> (Profiles, Vec<ConfigIssue>) {
+        match fs::read_to_string(path) {
+            Ok(source) => Profiles::from_toml(&source),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (Profiles::default(), Vec::new()),
+            Err(e) => {
+                eprintln!("Couldn't read the settings file {}, err : {}", path.display(), e);
+                (Profiles::default(), Vec::new())
+            },
+        }
+    }
+
+    /// Parses the profiles from the content of a TOML file, see `Profiles::load`.
+    pub fn from_toml(source: &str) -> (Profiles, Vec<ConfigIssue>) {
+        let schema: Vec<SchemaEntry> = SETTINGS_SCHEMA.iter()
+            .copied()
+            .chain([SchemaEntry { key: "profiles.*", kind: SettingKind::Table(SETTINGS_SCHEMA) }])
+            .collect();
+        let (values, issues) = validate(source, &schema);
+
+        let mut default = Settings::default();
+        default.apply(&values, "");
+
+        // Profiles are listed from the document itself, so that empty ones are kept too
+        let names: Vec<String> = ImDocument::parse(source).ok()
+            .and_then(|document| {
+                document.get("profiles")
+                    .and_then(|profiles| profiles.as_table_like())
+                    .map(|profiles| profiles.iter()
+                        .filter(|(_, item)| item.is_table_like())
+                        .map(|(name, _)| name.to_string())
+                        .collect())
+            })
+            .unwrap_or_default();
+
+        let named = names.into_iter()
+            .filter(|name| name != DEFAULT_PROFILE)
+            .map(|name| {
+                let mut settings = default.clone();
+                settings.apply(&values, &format!("profiles.{}.", name));
+                (name, settings)
+            })
+            .collect();
+
+        (Profiles { default, named, active: None }, issues)
+    }
+
+    /// Returns the names of all the profiles, starting with `DEFAULT_PROFILE`.
+    pub fn names(&self) -> Vec<String> {
+        std::iter::once(DEFAULT_PROFILE.to_string())
+            .chain(self.named.keys().cloned())
+            .collect()
+    }
+
+    /// Returns the name of the selected profile.
+    pub fn active_name(&self) -> &str {
+        self.active.as_deref().unwrap_or(DEFAULT_PROFILE)
+    }
+
+    /// Returns the settings of the selected profile.
+    pub fn active(&self) -> &Settings {
+        self.active.as_ref()
+            .and_then(|name| self.named.get(name))
+            .unwrap_or(&self.default)
+    }
+
+    /// Selects the profile whose settings are returned by `Profiles::active`.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the profile, `DEFAULT_PROFILE` selects the top-level settings.
+    ///
+    /// Returns an error if no profile with that name exists.
+    pub fn select(&mut self, name: &str) -> Result<&Settings, String> {
+        if name == DEFAULT_PROFILE {
+            self.active = None;
+        } else if self.named.contains_key(name) {
+            self.active = Some(name.to_string());
+        } else {
+            return Err(format!("Unknown profile `{}`, available profiles : {}", name, self.names().join(", ")));
+        }
+        Ok(self.active())
+    }
+
+    /// Changes a setting of the selected profile in the settings file, keeping the rest of the
+    /// file (comments, formatting) as it is, then reloads the profiles from it.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the settings file, created if it doesn't exist.
+    /// * `key` - The dotted key of the setting, e.g. `theme` or `history.enabled`.
+    /// * `value` - The new value, validated against the schema.
+    ///
+    /// Returns an error if the file couldn't be read or written, or if the value is invalid.
+    pub fn set(&mut self, path: &Path, key: &str, value: Value) -> Result<(), String> {
+        let profile = self.active.clone();
+        self.write(path, profile.as_deref(), key, value)
+    }
+
+    /// Changes a top-level setting in the settings file, shared by all the profiles, e.g. the window
+    /// geometry. Like `Profiles::set`, the rest of the file is kept as it is and the profiles are reloaded.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the settings file, created if it doesn't exist.
+    /// * `key` - The dotted key of the setting, e.g. `window.width`.
+    /// * `value` - The new value, validated against the schema.
+    ///
+    /// Returns an error if the file couldn't be read or written, or if the value is invalid.
+    pub fn set_default(&mut self, path: &Path, key: &str, value: Value) -> Result<(), String> {
+        self.write(path, None, key, value)
+    }
+
+    /// Writes a setting in the table of `profile`, or at the top level for `None`, then reloads the profiles.
+    fn write(&mut self, path: &Path, profile: Option<&str>, key: &str, value: Value) -> Result<(), String> {
+        let source = match fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(format!("Couldn't read the settings file {}, err : {}", path.display(), e)),
+        };
+        let mut document: DocumentMut = source.parse()
+            .map_err(|e| format!("Couldn't update the settings file {}, invalid TOML : {}", path.display(), e))?;
+
+        // The key is written in the table of the profile, as a dotted key if it's nested
+        let profile_segments: Vec<&str> = match profile {
+            Some(name) => vec!["profiles", name],
+            None => Vec::new(),
+        };
+        let path_segments: Vec<&str> = profile_segments.iter().copied().chain(key.split('.')).collect();
+        let full_key = path_segments.join(".");
+        let (name, tables) = path_segments.split_last().ok_or("Empty setting key")?;
+        let mut table = document.as_table_mut();
+        for (i, segment) in tables.iter().enumerate() {
+            table = table.entry(segment)
+                .or_insert_with(|| {
+                    let mut table = Table::new();
+                    table.set_implicit(true);
+                    table.set_dotted(i >= profile_segments.len());
+                    Item::Table(table)
+                })
+                .as_table_mut()
+                .ok_or_else(|| format!("Couldn't update `{}` in the settings file, `{}` isn't a table", full_key, segment))?;
+        }
+        table.insert(name, Item::Value(value));
+
+        let source = document.to_string();
+        let (profiles, issues) = Profiles::from_toml(&source);
+        if let Some(issue) = issues.iter().find(|issue| issue.key == full_key) {
+            return Err(issue.to_string());
+        }
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir).map_err(|e| format!("Couldn't create {}, err : {}", dir.display(), e))?;
+        }
+        fs::write(path, source).map_err(|e| format!("Couldn't write the settings file {}, err : {}", path.display(), e))?;
+
+        self.default = profiles.default;
+        self.named = profiles.named;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::schema::ConfigIssueKind;
+    use super::super::settings::ThemeChoice;
+
+    #[test]
+    fn invalid_values_fall_back_to_their_default() {
+        let (profiles, issues) = Profiles::from_toml("max_connections = 99\nper_host_connections = 2\nspeed = 1\n");
+
+        assert_eq!(profiles.active().max_connections, Settings::default().max_connections);
+        assert_eq!(profiles.active().per_host_connections, 2);
+        assert_eq!(issues.iter().map(|issue| (issue.line, issue.key.as_str())).collect::<Vec<_>>(), vec![(1, "max_connections"), (3, "speed")]);
+        assert_eq!(issues[1].kind, ConfigIssueKind::UnknownKey);
+    }
+
+    #[test]
+    fn profiles_only_override_the_keys_they_set() {
+        let source = "max_connections = 8\ntheme = \"dark\"\n\n[profiles.slow]\nmax_connections = 2\nper_host_connections = 0\n\n[profiles.empty]\n";
+        let (mut profiles, issues) = Profiles::from_toml(source);
+
+        assert_eq!(profiles.names(), vec![DEFAULT_PROFILE, "empty", "slow"]);
+        assert_eq!(issues.iter().map(|issue| issue.key.as_str()).collect::<Vec<_>>(), vec!["profiles.slow.per_host_connections"]);
+
+        // The profile inherits the top-level settings, and the defaults of its invalid entries
+        let slow = profiles.select("slow").unwrap();
+        assert_eq!(slow.max_connections, 2);
+        assert_eq!(slow.theme, ThemeChoice::Dark);
+        assert_eq!(slow.per_host_connections, Settings::default().per_host_connections);
+        assert_eq!(profiles.active_name(), "slow");
+
+        assert_eq!(profiles.select("empty").unwrap().max_connections, 8);
+        assert_eq!(profiles.select(DEFAULT_PROFILE).unwrap().max_connections, 8);
+        assert!(profiles.select("fast").is_err());
+        assert_eq!(profiles.active_name(), DEFAULT_PROFILE);
+    }
+}
@@ -0,0 +1,323 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Range;
+use toml_edit::{ImDocument, Item, TableLike, Value};
+
+/// Represents the type (and allowed range) of a setting in the configuration file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SettingKind {
+    /// A table whose entries are validated against a nested schema.
+    Table(&'static [SchemaEntry]),
+    /// An integer in the inclusive range `min..=max`.
+    Integer { min: i64, max: i64 },
+    /// A float in the inclusive range `min..=max`.
+    Float { min: f64, max: f64 },
+    /// Any string.
+    String,
+    /// An array of strings.
+    StringList,
+    /// One of the listed strings.
+    Choice(&'static [&'static str]),
+    /// A boolean flag.
+    Boolean,
+}
+
+impl SettingKind {
+    /// Returns the TOML name of the expected type, used in error messages.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            SettingKind::Table(_) => "table",
+            SettingKind::Integer { .. } => "integer",
+            SettingKind::Float { .. } => "float",
+            SettingKind::String => "string",
+            SettingKind::StringList => "array of strings",
+            SettingKind::Choice(_) => "string",
+            SettingKind::Boolean => "boolean",
+        }
+    }
+}
+
+/// SchemaEntry describes a single known key of the configuration file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SchemaEntry {
+    pub key: &'static str,      // Full (dotted) path of the key, e.g. `max_connections`, `*` matches any segment
+    pub kind: SettingKind,      // Expected type of the value
+}
+
+/// ConfigIssueKind represents the reason a configuration entry was rejected.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigIssueKind {
+    /// The file isn't valid TOML.
+    Syntax(String),
+    /// The key isn't part of the schema.
+    UnknownKey,
+    /// The value has a different type than the schema expects.
+    TypeMismatch { expected: &'static str, found: &'static str },
+    /// The value is outside the allowed range.
+    OutOfRange { min: String, max: String },
+    /// The value isn't one of the allowed choices.
+    InvalidChoice { allowed: &'static [&'static str] },
+}
+
+/// ConfigIssue represents a problem found while validating the configuration file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigIssue {
+    pub line: usize,            // Line number (1-based) where the issue was found
+    pub key: String,            // Dotted path of the offending key, empty for syntax errors
+    pub kind: ConfigIssueKind,  // What is wrong with the entry
+}
+
+impl fmt::Display for ConfigIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            ConfigIssueKind::Syntax(message) => {
+                write!(f, "line {}: invalid TOML, {}", self.line, message.trim().replace('\n', " "))
+            },
+            ConfigIssueKind::UnknownKey => {
+                write!(f, "line {}: unknown key `{}`", self.line, self.key)
+            },
+            ConfigIssueKind::TypeMismatch { expected, found } => {
+                write!(f, "line {}: `{}` should be of type {}, found {}", self.line, self.key, expected, found)
+            },
+            ConfigIssueKind::OutOfRange { min, max } => {
+                write!(f, "line {}: `{}` should be between {} and {}", self.line, self.key, min, max)
+            },
+            ConfigIssueKind::InvalidChoice { allowed } => {
+                write!(f, "line {}: `{}` should be one of {}", self.line, self.key, allowed.join(", "))
+            },
+        }
+    }
+}
+
+/// Validates a TOML document against a schema.
+///
+/// Every entry that matches the schema is returned, everything else is reported as an issue
+/// so the caller can keep running on the defaults of the rejected keys.
+///
+/// # Arguments
+///
+/// * `source` - The raw content of the configuration file.
+/// * `schema` - The list of known keys and their expected types.
+///
+/// # Returns
+///
+/// Returns a tuple containing:
+/// * A map from the dotted key path to its (valid) value.
+/// * A vector of the issues found, sorted by line number.
+pub fn validate(source: &str, schema: &[SchemaEntry]) -> (HashMap<String, Value>, Vec<ConfigIssue>) {
+    let mut values = HashMap::new();
+    let mut issues = Vec::new();
+
+    match ImDocument::parse(source) {
+        Ok(document) => {
+            validate_table(source, document.as_table(), "", "", schema, &mut values, &mut issues);
+        },
+        Err(e) => {
+            issues.push(ConfigIssue {
+                line: e.span().map(|span| line_of(source, span.start)).unwrap_or(1),
+                key: String::new(),
+                kind: ConfigIssueKind::Syntax(e.message().to_string()),
+            });
+        },
+    }
+
+    issues.sort_by_key(|issue| issue.line);
+    (values, issues)
+}
+
+/// Recursively validates the entries of a table.
+///
+/// `path_prefix` is the full path of the table in the document, used for reporting, while
+/// `schema_prefix` is its path relative to `schema`, used for matching the keys.
+fn validate_table(
+    source: &str,
+    table: &dyn TableLike,
+    path_prefix: &str,
+    schema_prefix: &str,
+    schema: &[SchemaEntry],
+    values: &mut HashMap<String, Value>,
+    issues: &mut Vec<ConfigIssue>,
+) {
+    for (key, item) in table.iter() {
+        let path = join_key(path_prefix, key);
+        let schema_path = join_key(schema_prefix, key);
+        let span = table.get_key_value(key).and_then(|(k, _)| k.span()).or_else(|| item.span());
+        let line = span.map(|span: Range<usize>| line_of(source, span.start)).unwrap_or(1);
+        let entry = schema.iter().find(|entry| key_matches(entry.key, &schema_path));
+
+        // Tables with their own schema, e.g. a named profile
+        if let Some(SchemaEntry { kind: SettingKind::Table(nested_schema), .. }) = entry {
+            match item.as_table_like() {
+                Some(sub_table) => validate_table(source, sub_table, &path, "", nested_schema, values, issues),
+                None => issues.push(ConfigIssue {
+                    line,
+                    key: path,
+                    kind: ConfigIssueKind::TypeMismatch { expected: "table", found: item.type_name() },
+                }),
+            }
+            continue;
+        }
+
+        // Other tables are only valid if the schema knows keys nested under them
+        if let Some(sub_table) = item.as_table_like() {
+            if schema.iter().any(|entry| is_nested_under(entry.key, &schema_path)) {
+                validate_table(source, sub_table, &path, &schema_path, schema, values, issues);
+            } else {
+                issues.push(ConfigIssue { line, key: path, kind: ConfigIssueKind::UnknownKey });
+            }
+            continue;
+        }
+
+        let Some(entry) = entry else {
+            issues.push(ConfigIssue { line, key: path, kind: ConfigIssueKind::UnknownKey });
+            continue;
+        };
+
+        match check_value(item, &entry.kind) {
+            Ok(value) => {
+                values.insert(path, value.clone());
+            },
+            Err(kind) => {
+                issues.push(ConfigIssue { line, key: path, kind });
+            },
+        }
+    }
+}
+
+/// Checks that an item has the type and range described by `kind`.
+fn check_value<'a>(item: &'a Item, kind: &SettingKind) -> Result<&'a Value, ConfigIssueKind> {
+    let mismatch = || ConfigIssueKind::TypeMismatch { expected: kind.type_name(), found: item.type_name() };
+    let value = item.as_value().ok_or_else(mismatch)?;
+
+    match kind {
+        SettingKind::Integer { min, max } => {
+            let number = value.as_integer().ok_or_else(mismatch)?;
+            if number < *min || number > *max {
+                return Err(ConfigIssueKind::OutOfRange { min: min.to_string(), max: max.to_string() });
+            }
+        },
+        SettingKind::Float { min, max } => {
+            // Integers are accepted where floats are expected
+            let number = value.as_float().or(value.as_integer().map(|n| n as f64)).ok_or_else(mismatch)?;
+            if number < *min || number > *max {
+                return Err(ConfigIssueKind::OutOfRange { min: min.to_string(), max: max.to_string() });
+            }
+        },
+        SettingKind::String => {
+            value.as_str().ok_or_else(mismatch)?;
+        },
+        SettingKind::StringList => {
+            let array = value.as_array().ok_or_else(mismatch)?;
+            if !array.iter().all(|element| element.is_str()) {
+                return Err(mismatch());
+            }
+        },
+        SettingKind::Boolean => {
+            value.as_bool().ok_or_else(mismatch)?;
+        },
+        SettingKind::Choice(allowed) => {
+            let choice = value.as_str().ok_or_else(mismatch)?;
+            if !allowed.contains(&choice) {
+                return Err(ConfigIssueKind::InvalidChoice { allowed });
+            }
+        },
+        SettingKind::Table(_) => {
+            return Err(mismatch());
+        },
+    }
+
+    Ok(value)
+}
+
+/// Joins a table path and a key into a dotted path.
+fn join_key(prefix: &str, key: &str) -> String {
+    if prefix.is_empty() { key.to_string() } else { format!("{}.{}", prefix, key) }
+}
+
+/// Checks whether a dotted `path` matches a schema key, where a `*` segment matches any segment.
+fn key_matches(pattern: &str, path: &str) -> bool {
+    let pattern_segments: Vec<&str> = pattern.split('.').collect();
+    let path_segments: Vec<&str> = path.split('.').collect();
+
+    pattern_segments.len() == path_segments.len()
+        && pattern_segments.iter().zip(&path_segments).all(|(p, s)| *p == "*" || p == s)
+}
+
+/// Checks whether a schema key describes an entry nested somewhere under the table at `path`.
+fn is_nested_under(pattern: &str, path: &str) -> bool {
+    let pattern_segments: Vec<&str> = pattern.split('.').collect();
+    let path_segments: Vec<&str> = path.split('.').collect();
+
+    pattern_segments.len() > path_segments.len()
+        && pattern_segments.iter().zip(&path_segments).all(|(p, s)| *p == "*" || p == s)
+}
+
+/// Converts a byte offset in `source` to a 1-based line number.
+fn line_of(source: &str, offset: usize) -> usize {
+    source[..offset.min(source.len())].matches('\n').count() + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROFILE_SCHEMA: &[SchemaEntry] = &[
+        SchemaEntry { key: "max_connections", kind: SettingKind::Integer { min: 1, max: 16 } },
+    ];
+
+    const SCHEMA: &[SchemaEntry] = &[
+        SchemaEntry { key: "max_connections", kind: SettingKind::Integer { min: 1, max: 16 } },
+        SchemaEntry { key: "theme", kind: SettingKind::Choice(&["light", "dark"]) },
+        SchemaEntry { key: "hooks.on_done", kind: SettingKind::String },
+        SchemaEntry { key: "profiles.*", kind: SettingKind::Table(PROFILE_SCHEMA) },
+    ];
+
+    #[test]
+    fn invalid_entries_are_reported_and_left_out() {
+        let source = "max_connections = 64\ntheme = \"blue\"\ncolour = \"red\"\n\n[hooks]\non_done = \"true\"\non_error = 1\n";
+        let (values, issues) = validate(source, SCHEMA);
+
+        // Only the valid entry is kept, the caller uses the defaults of the others
+        assert_eq!(values.len(), 1);
+        assert_eq!(values["hooks.on_done"].as_str(), Some("true"));
+        assert_eq!(issues, vec![
+            ConfigIssue { line: 1, key: String::from("max_connections"), kind: ConfigIssueKind::OutOfRange { min: String::from("1"), max: String::from("16") } },
+            ConfigIssue { line: 2, key: String::from("theme"), kind: ConfigIssueKind::InvalidChoice { allowed: &["light", "dark"] } },
+            ConfigIssue { line: 3, key: String::from("colour"), kind: ConfigIssueKind::UnknownKey },
+            ConfigIssue { line: 7, key: String::from("hooks.on_error"), kind: ConfigIssueKind::UnknownKey },
+        ]);
+        assert_eq!(issues[2].to_string(), "line 3: unknown key `colour`");
+    }
+
+    #[test]
+    fn wildcard_tables_use_their_own_schema() {
+        let source = "[profiles.slow]\nmax_connections = 2\ntheme = \"dark\"\n\n[profiles]\nfast = 4\n";
+        let (values, issues) = validate(source, SCHEMA);
+
+        assert_eq!(values["profiles.slow.max_connections"].as_integer(), Some(2));
+        assert_eq!(issues.iter().map(|issue| (issue.key.as_str(), &issue.kind)).collect::<Vec<_>>(), vec![
+            ("profiles.slow.theme", &ConfigIssueKind::UnknownKey),
+            ("profiles.fast", &ConfigIssueKind::TypeMismatch { expected: "table", found: "integer" }),
+        ]);
+    }
+
+    #[test]
+    fn wildcard_matches_a_single_segment() {
+        assert!(key_matches("profiles.*", "profiles.slow"));
+        assert!(key_matches("max_connections", "max_connections"));
+        assert!(!key_matches("profiles.*", "profiles"));
+        assert!(!key_matches("profiles.*", "profiles.slow.max_connections"));
+        assert!(!key_matches("hooks.on_done", "hooks.on_error"));
+        assert!(is_nested_under("profiles.*", "profiles"));
+        assert!(!is_nested_under("profiles.*", "profiles.slow"));
+    }
+
+    #[test]
+    fn syntax_errors_are_reported_with_their_line() {
+        let (values, issues) = validate("theme = \"dark\"\nmax_connections = \n", SCHEMA);
+        assert!(values.is_empty());
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].line, 2);
+        assert!(matches!(issues[0].kind, ConfigIssueKind::Syntax(_)));
+    }
+}
@@ -0,0 +1,604 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use toml_edit::Value;
+use rustle_core::client::{AddressFamily, ClientOptions, HttpVersion, TlsOptions};
+use rustle_core::dns::DnsOptions;
+use rustle_core::encoding::ContentEncodingMode;
+use rustle_core::connection_manager::ConnectionManager;
+use rustle_core::credentials::CredentialStore;
+use rustle_core::downloader::{RetryPolicy, RustleDownloader, DEFAULT_PROGRESS_INTERVAL, DEFAULT_STALE_AFTER, DEFAULT_STALL_TIMEOUT};
+use rustle_core::io::{BufferingStrategy, FilePermissions, FileWriter, StdFileWriter};
+use rustle_core::history::{DownloadHistory, HistoryRetention, HISTORY_FILE_NAME};
+use rustle_core::drain::DRAIN_FILE_NAME;
+use rustle_core::journal::{JournalEntry, PartFileLocation, JOURNAL_FILE_NAME};
+use rustle_core::usage::{DataQuota, DataUsage, QuotaPeriod, USAGE_FILE_NAME};
+use crate::hooks::Hooks;
+use super::schema::{SchemaEntry, SettingKind};
+
+/// Keys accepted in the settings file, along with their expected type.
+pub const SETTINGS_SCHEMA: &[SchemaEntry] = &[
+    SchemaEntry { key: "download_dir", kind: SettingKind::String },
+    SchemaEntry { key: "max_connections", kind: SettingKind::Integer { min: 1, max: 16 } },
+    SchemaEntry { key: "per_host_connections", kind: SettingKind::Integer { min: 1, max: 64 } },
+    SchemaEntry { key: "total_connections", kind: SettingKind::Integer { min: 0, max: 1024 } },
+    SchemaEntry { key: "auto_connections", kind: SettingKind::Boolean },
+    SchemaEntry { key: "max_active_downloads", kind: SettingKind::Integer { min: 1, max: 32 } },
+    SchemaEntry { key: "tls.extra_root_certificates", kind: SettingKind::StringList },
+    SchemaEntry { key: "tls.accept_invalid_certs", kind: SettingKind::Boolean },
+    SchemaEntry { key: "tls.client_certificate", kind: SettingKind::String },
+    SchemaEntry { key: "tls.client_key", kind: SettingKind::String },
+    SchemaEntry { key: "audit_logs", kind: SettingKind::Boolean },
+    SchemaEntry { key: "har_logs", kind: SettingKind::Boolean },
+    SchemaEntry { key: "dns.servers", kind: SettingKind::StringList },
+    SchemaEntry { key: "dns.over_https", kind: SettingKind::String },
+    SchemaEntry { key: "address_family", kind: SettingKind::Choice(&["auto", "prefer_ipv4", "prefer_ipv6", "ipv4_only"]) },
+    SchemaEntry { key: "progress_interval_ms", kind: SettingKind::Integer { min: 10, max: 10_000 } },
+    SchemaEntry { key: "stale_after_min", kind: SettingKind::Integer { min: 0, max: 10_080 } },
+    SchemaEntry { key: "stall_timeout_sec", kind: SettingKind::Integer { min: 0, max: 3_600 } },
+    SchemaEntry { key: "max_retries", kind: SettingKind::Integer { min: 0, max: 20 } },
+    SchemaEntry { key: "retry_delay_ms", kind: SettingKind::Integer { min: 100, max: 600_000 } },
+    SchemaEntry { key: "queue_retries", kind: SettingKind::Integer { min: 0, max: 10 } },
+    SchemaEntry { key: "hooks.on_done", kind: SettingKind::String },
+    SchemaEntry { key: "hooks.on_error", kind: SettingKind::String },
+    SchemaEntry { key: "hooks.refresh_url", kind: SettingKind::String },
+    SchemaEntry { key: "history.enabled", kind: SettingKind::Boolean },
+    SchemaEntry { key: "history.max_age_days", kind: SettingKind::Integer { min: 0, max: 36_500 } },
+    SchemaEntry { key: "history.max_entries", kind: SettingKind::Integer { min: 0, max: 1_000_000 } },
+    SchemaEntry { key: "proxy", kind: SettingKind::String },
+    SchemaEntry { key: "proxy_from_env", kind: SettingKind::Boolean },
+    SchemaEntry { key: "user_agent", kind: SettingKind::String },
+    SchemaEntry { key: "speed_limit_kib", kind: SettingKind::Integer { min: 0, max: 10_000_000 } },
+    SchemaEntry { key: "global_speed_limit_kib", kind: SettingKind::Integer { min: 0, max: 10_000_000 } },
+    SchemaEntry { key: "max_memory_mib", kind: SettingKind::Integer { min: 0, max: 4_194_304 } },
+    SchemaEntry { key: "data_quota_mib", kind: SettingKind::Integer { min: 0, max: 1_073_741_824 } },
+    SchemaEntry { key: "data_quota_period", kind: SettingKind::Choice(&["day", "month"]) },
+    SchemaEntry { key: "io_uring", kind: SettingKind::Boolean },
+    SchemaEntry { key: "write_buffer_kib", kind: SettingKind::Integer { min: 4, max: 65_536 } },
+    SchemaEntry { key: "theme", kind: SettingKind::Choice(&["system", "light", "dark"]) },
+    SchemaEntry { key: "notifications.on_done", kind: SettingKind::Boolean },
+    SchemaEntry { key: "notifications.on_error", kind: SettingKind::Boolean },
+    SchemaEntry { key: "window.width", kind: SettingKind::Integer { min: 200, max: 16_384 } },
+    SchemaEntry { key: "window.height", kind: SettingKind::Integer { min: 200, max: 16_384 } },
+    SchemaEntry { key: "window.x", kind: SettingKind::Integer { min: -65_536, max: 65_536 } },
+    SchemaEntry { key: "window.y", kind: SettingKind::Integer { min: -65_536, max: 65_536 } },
+    SchemaEntry { key: "keyring", kind: SettingKind::Boolean },
+    SchemaEntry { key: "content_encoding", kind: SettingKind::Choice(&["decode", "raw"]) },
+    SchemaEntry { key: "http_version", kind: SettingKind::Choice(&["auto", "http1", "http2"]) },
+    SchemaEntry { key: "segment_hashing", kind: SettingKind::Boolean },
+    SchemaEntry { key: "buffering", kind: SettingKind::Choice(&["memory", "part_files", "direct"]) },
+    SchemaEntry { key: "staging_dir", kind: SettingKind::String },
+    SchemaEntry { key: "part_files", kind: SettingKind::Choice(&["cache", "next_to_file"]) },
+    SchemaEntry { key: "file_mode", kind: SettingKind::Integer { min: 0, max: 0o7777 } },
+    SchemaEntry { key: "mark_executable", kind: SettingKind::Boolean },
+    SchemaEntry { key: "provenance", kind: SettingKind::Boolean },
+    SchemaEntry { key: "type_check", kind: SettingKind::Boolean },
+];
+
+/// Represents the color theme of the GUI.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ThemeChoice {
+    /// Follows the color scheme of the OS.
+    #[default]
+    System,
+    Light,
+    Dark,
+}
+
+impl ThemeChoice {
+    /// All the themes, in the order they are presented to the user.
+    pub const ALL: [ThemeChoice; 3] = [ThemeChoice::System, ThemeChoice::Light, ThemeChoice::Dark];
+
+    /// Returns the name used for the theme in the settings file.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ThemeChoice::System => "system",
+            ThemeChoice::Light => "light",
+            ThemeChoice::Dark => "dark",
+        }
+    }
+}
+
+impl fmt::Display for ThemeChoice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ThemeChoice {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ThemeChoice::ALL.into_iter()
+            .find(|theme| theme.as_str() == s)
+            .ok_or_else(|| format!("Unknown theme `{}`", s))
+    }
+}
+
+/// Represents the desktop notifications the GUI shows when the downloads end.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Notifications {
+    pub on_done: bool,              // Notify when a download finishes
+    pub on_error: bool,             // Notify when a download fails, after its retries
+}
+
+/// Represents the size and position of the GUI window, saved when it's closed and restored at launch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WindowGeometry {
+    pub width: u32,                 // Width of the window in logical pixels
+    pub height: u32,                // Height of the window in logical pixels
+    pub position: Option<(i32, i32)>,   // Position of the top-left corner in logical pixels, `None` lets the OS place the window
+}
+
+impl Default for WindowGeometry {
+    fn default() -> Self {
+        WindowGeometry { width: 600, height: 800, position: None }
+    }
+}
+
+impl WindowGeometry {
+    /// Returns the entries of the settings file storing the geometry, keyed by their dotted key.
+    pub fn entries(&self) -> Vec<(&'static str, Value)> {
+        let mut entries = vec![("window.width", Value::from(self.width as i64)), ("window.height", Value::from(self.height as i64))];
+        if let Some((x, y)) = self.position {
+            entries.push(("window.x", Value::from(x as i64)));
+            entries.push(("window.y", Value::from(y as i64)));
+        }
+        entries
+    }
+}
+
+/// Settings represents the application defaults loaded from the settings file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settings {
+    pub download_dir: PathBuf,      // Default output directory for new downloads
+    pub max_connections: u8,        // Default maximum number of parallel connections per download
+    pub per_host_connections: usize,    // Maximum number of concurrent connections to a single host across all downloads
+    pub total_connections: Option<usize>,   // Maximum number of concurrent connections of all the downloads, `total_connections = 0` for no limit
+    pub auto_connections: bool,     // Tune the number of connections of each download from its throughput, up to `max_connections`
+    pub max_active_downloads: usize,    // Maximum number of downloads running at the same time, the others wait in the queue
+    pub tls: TlsOptions,            // Default TLS options of new downloads
+    pub address_family: AddressFamily,  // Preferred IP address family
+    pub dns: DnsOptions,            // DNS servers, e.g. `dns.servers = ["1.1.1.1"]`, or DNS-over-HTTPS endpoint the hostnames are resolved with
+    pub audit_logs: bool,           // Write a chunk-level audit log (`<file name>.audit.jsonl`) next to each download
+    pub har_logs: bool,             // Record the requests of each download in a HAR file (`<file name>.har`) next to it, for debugging
+    pub progress_interval: Duration,    // Minimum time between two progress updates of a download
+    pub stale_after: Option<Duration>,  // Time a download stays paused before the file is checked on the server again when it's resumed, `stale_after_min = 0` to never check it
+    pub stall_timeout: Option<Duration>,    // Time a part waits for the next chunk before its range is requested again, `stall_timeout_sec = 0` to wait forever
+    pub retry_policy: RetryPolicy,  // How failed downloads are retried, the delay doubles after each retry
+    pub queue_retries: usize,       // Number of times a download failing all its retries is queued again later (5 min, 15 min, then every hour)
+    pub hooks: Hooks,               // Commands run after a download finishes or fails, or refreshing its expired URL
+    pub history: bool,              // Record the finished and failed downloads in `<data dir>/rustle/history.sqlite3`
+    pub history_retention: HistoryRetention,    // How long the history keeps its records, `0` keeps them forever
+    pub proxy: Option<String>,      // Proxy the requests go through, e.g. `http://proxy:3128` or `socks5://proxy:1080`
+    pub proxy_from_env: bool,       // Use `HTTP_PROXY`, `HTTPS_PROXY`, `ALL_PROXY` and `NO_PROXY` when `proxy` isn't set
+    pub user_agent: Option<String>, // `User-Agent` header of the requests
+    pub speed_limit: Option<u64>,   // Maximum speed of each download in bytes per second, `speed_limit_kib = 0` for no limit
+    pub global_speed_limit: Option<u64>,    // Maximum total speed of the downloads in bytes per second, `global_speed_limit_kib = 0` for no limit
+    pub max_memory: Option<u64>,    // Maximum number of bytes all the downloads buffer in memory, `max_memory_mib = 0` for no limit
+    pub data_quota: Option<u64>,    // Bytes the downloads may receive per quota period before they're paused, `data_quota_mib = 0` for no quota
+    pub data_quota_period: QuotaPeriod, // Period of the data quota, a day or a calendar month in UTC
+    pub io_uring: bool,             // Write the files through io_uring, only on Linux builds with the `io-uring` feature
+    pub write_size: usize,          // Size of the writes of the files in bytes, smaller for slow HDDs and larger for NVMe drives
+    pub theme: ThemeChoice,         // Color theme of the GUI, following the color scheme of the OS by default
+    pub notifications: Notifications,   // Desktop notifications of the GUI when the downloads finish or fail
+    pub window: WindowGeometry,     // Size and position of the GUI window when it was last closed
+    pub keyring: bool,              // Look up the credentials of the hosts and the proxy in the OS credential store
+    pub content_encoding: ContentEncodingMode,  // Whether compressed responses of single connection downloads are decoded or stored raw
+    pub http_version: HttpVersion,  // HTTP versions the requests may use, HTTP/2 is negotiated by default
+    pub segment_hashing: bool,      // Hash the downloaded segments, so only the corrupt ones are downloaded again when resuming
+    pub buffering: BufferingStrategy,   // Where the parts keep their bytes until the file is complete, in memory or on disk next to the file
+    pub staging_dir: Option<PathBuf>,   // Directory the files are written to before they're moved to their output directory
+    pub part_files: PartFileLocation,   // Where the bytes received by the unfinished downloads are saved on exit
+    pub file_permissions: FilePermissions,  // Mode of the completed files on Unix, e.g. `file_mode = 0o644`, and whether scripts and executables get the executable bits
+    pub provenance: bool,           // Record the URL of the completed files in their metadata, like the browsers do
+    pub type_check: bool,           // Ask before downloading a file whose type doesn't match its extension, e.g. an `.iso` served as a web page
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            download_dir: PathBuf::from("./"),
+            max_connections: 4,
+            per_host_connections: 8,
+            total_connections: Some(16),
+            auto_connections: true,
+            max_active_downloads: 3,
+            tls: TlsOptions::default(),
+            address_family: AddressFamily::Auto,
+            dns: DnsOptions::default(),
+            audit_logs: false,
+            har_logs: false,
+            progress_interval: DEFAULT_PROGRESS_INTERVAL,
+            stale_after: Some(DEFAULT_STALE_AFTER),
+            stall_timeout: Some(DEFAULT_STALL_TIMEOUT),
+            retry_policy: RetryPolicy { max_retries: 3, ..RetryPolicy::default() },
+            queue_retries: 3,
+            hooks: Hooks::default(),
+            history: true,
+            history_retention: HistoryRetention { max_age: Some(Duration::from_secs(365 * 24 * 3600)), max_entries: Some(10_000) },
+            proxy: None,
+            proxy_from_env: true,
+            user_agent: None,
+            speed_limit: None,
+            global_speed_limit: None,
+            max_memory: None,
+            data_quota: None,
+            data_quota_period: QuotaPeriod::Month,
+            io_uring: false,
+            write_size: rustle_core::io::DEFAULT_WRITE_SIZE,
+            theme: ThemeChoice::System,
+            notifications: Notifications::default(),
+            window: WindowGeometry::default(),
+            keyring: true,
+            content_encoding: ContentEncodingMode::Decode,
+            http_version: HttpVersion::Auto,
+            segment_hashing: false,
+            buffering: BufferingStrategy::Memory,
+            staging_dir: None,
+            part_files: Settings::part_files_dir().map(PartFileLocation::Dir).unwrap_or(PartFileLocation::NextToFile),
+            file_permissions: FilePermissions::default(),
+            provenance: false,
+            type_check: true,
+        }
+    }
+}
+
+impl Settings {
+    /// Returns the default location of the settings file, i.e. `<config dir>/rustle/config.toml`.
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("rustle").join("config.toml"))
+    }
+
+    /// Returns the default location of the download history, i.e. `<data dir>/rustle/history.sqlite3`.
+    pub fn history_path() -> Option<PathBuf> {
+        dirs::data_dir().map(|dir| dir.join("rustle").join(HISTORY_FILE_NAME))
+    }
+
+    /// Returns the default location of the session journal, i.e. `<data dir>/rustle/session.json`.
+    pub fn journal_path() -> Option<PathBuf> {
+        dirs::data_dir().map(|dir| dir.join("rustle").join(JOURNAL_FILE_NAME))
+    }
+
+    /// Returns the location of the drain request of the running application, i.e. `<data dir>/rustle/drain`.
+    pub fn drain_path() -> Option<PathBuf> {
+        dirs::data_dir().map(|dir| dir.join("rustle").join(DRAIN_FILE_NAME))
+    }
+
+    /// Returns the default location of the data usage, i.e. `<data dir>/rustle/usage.json`.
+    pub fn usage_path() -> Option<PathBuf> {
+        dirs::data_dir().map(|dir| dir.join("rustle").join(USAGE_FILE_NAME))
+    }
+
+    /// Returns the default directory of the part files, i.e. `<cache dir>/rustle/parts`, or
+    /// `%TEMP%\rustle\parts` on Windows.
+    pub fn part_files_dir() -> Option<PathBuf> {
+        if cfg!(windows) {
+            return Some(std::env::temp_dir().join("rustle").join("parts"));
+        }
+        dirs::cache_dir().map(|dir| dir.join("rustle").join("parts"))
+    }
+
+    /// Returns the directories searched for orphaned part files at startup: the part files directory,
+    /// or the download directory when the part files are written next to the files.
+    pub fn part_file_dirs(&self) -> Vec<PathBuf> {
+        match &self.part_files {
+            PartFileLocation::NextToFile => vec![self.download_dir.clone()],
+            PartFileLocation::Dir(dir) => vec![dir.clone()],
+        }
+    }
+
+    /// Opens the download history if `history` is set, deleting the records older than the retention.
+    ///
+    /// Returns `None` if the history is disabled, or an error if it couldn't be opened.
+    pub fn open_history(&self) -> Result<Option<DownloadHistory>, String> {
+        if !self.history {
+            return Ok(None);
+        }
+        let path = Settings::history_path().ok_or("Couldn't find the data directory of the history")?;
+        let history = DownloadHistory::open(&path)?;
+        history.prune(&self.history_retention)?;
+        Ok(Some(history))
+    }
+
+    /// Opens the data usage of the downloads, with the data quota of the settings.
+    ///
+    /// Returns an error if the usage couldn't be opened.
+    pub fn open_data_usage(&self) -> Result<DataUsage, String> {
+        let path = Settings::usage_path().ok_or("Couldn't find the data directory of the data usage")?;
+        let usage = DataUsage::open(&path)?;
+        usage.set_quota(self.data_quota());
+        Ok(usage)
+    }
+
+    /// Returns the data quota of the downloads, if any.
+    pub fn data_quota(&self) -> Option<DataQuota> {
+        self.data_quota.map(|bytes| DataQuota { bytes, period: self.data_quota_period })
+    }
+
+    /// Creates a download engine configured from the settings (directory, connections, TLS, ..),
+    /// the URL is left to the caller.
+    ///
+    /// # Arguments
+    ///
+    /// * `connection_manager` - The connection manager shared by all downloads.
+    pub async fn new_engine(&self, connection_manager: Arc<ConnectionManager>) -> Result<RustleDownloader, String> {
+        let mut engine = RustleDownloader::new(self.max_connections)?;
+        engine.set_out_dir(&self.download_dir.to_string_lossy()).await?;
+        self.configure_engine(&mut engine, connection_manager).await;
+        Ok(engine)
+    }
+
+    /// Creates the engine continuing a download of the session journal, configured from the
+    /// settings like `new_engine()` while the URL and the directory come from the journal.
+    ///
+    /// # Arguments
+    ///
+    /// * `entry` - The download of the journal.
+    /// * `connection_manager` - The connection manager shared by all downloads.
+    pub async fn restore_engine(&self, entry: &JournalEntry, connection_manager: Arc<ConnectionManager>) -> Result<RustleDownloader, String> {
+        let mut engine = entry.engine(self.max_connections).await?;
+        self.configure_engine(&mut engine, connection_manager).await;
+        Ok(engine)
+    }
+
+    /// Applies the settings shared by the new and restored engines.
+    async fn configure_engine(&self, engine: &mut RustleDownloader, connection_manager: Arc<ConnectionManager>) {
+        engine.set_connection_manager(connection_manager).await;
+        engine.set_tls_options(self.tls.clone()).await;
+        engine.set_address_family(self.address_family).await;
+        engine.set_dns(self.dns.clone()).await;
+        if self.har_logs {
+            // Recorded from the first request, the file is named once the file name is known
+            engine.set_har_log(None).await;
+        }
+        engine.set_content_encoding(self.content_encoding).await;
+        engine.set_http_version(self.http_version).await;
+        engine.set_proxy(self.proxy.clone()).await;
+        engine.set_env_proxy(self.proxy_from_env).await;
+        engine.set_user_agent(self.user_agent.clone()).await;
+        engine.set_speed_limit(self.speed_limit).await.expect("speed_limit is never zero");
+        engine.set_credential_store(self.keyring.then(|| Arc::new(CredentialStore::default()))).await;
+        engine.set_url_refresher(self.hooks.url_refresher()).await;
+        engine.set_progress_interval(self.progress_interval).await;
+        engine.set_auto_connections(self.auto_connections).await;
+        engine.set_stale_after(self.stale_after).await;
+        engine.set_stall_timeout(self.stall_timeout).await;
+        engine.set_retry_policy(self.retry_policy).await;
+        engine.set_segment_hashing(self.segment_hashing).await;
+        engine.set_buffering(self.buffering).await.expect("new and restored engines hold their bytes in memory");
+        engine.set_staging_dir(self.staging_dir.clone()).await;
+        engine.set_file_permissions(self.file_permissions).await;
+        engine.set_provenance(self.provenance).await;
+        engine.set_type_check(self.type_check).await;
+        let file_writer: Arc<dyn FileWriter> = Arc::new(StdFileWriter { write_size: self.write_size });
+        #[cfg(all(target_os = "linux", feature = "io-uring"))]
+        let file_writer: Arc<dyn FileWriter> = match self.io_uring {
+            true => Arc::new(rustle_core::UringFileWriter { write_size: self.write_size }),
+            false => file_writer,
+        };
+        engine.set_file_writer(file_writer).await;
+    }
+
+    /// Creates the connection manager shared by all downloads, limiting the connections per host
+    /// and in total, the total speed and the memory buffering the downloads.
+    pub fn new_connection_manager(&self) -> ConnectionManager {
+        let connection_manager = ConnectionManager::new(self.per_host_connections)
+            .expect("per_host_connections is validated to be non-zero");
+        connection_manager.set_total_limit(self.total_connections).expect("total_connections is never zero");
+        connection_manager.set_speed_limit(self.global_speed_limit).expect("global_speed_limit is never zero");
+        connection_manager.set_memory_budget(self.max_memory).expect("max_memory is never zero");
+        connection_manager
+    }
+
+    /// Returns the options of the clients fetching pages and control files outside of the
+    /// engines, e.g. to grab links.
+    pub fn client_options(&self) -> ClientOptions {
+        ClientOptions {
+            tls: self.tls.clone(),
+            address_family: self.address_family,
+            dns: self.dns.clone(),
+            content_encoding: self.content_encoding,
+            http_version: self.http_version,
+            proxy: self.proxy.clone(),
+            ignore_env_proxy: !self.proxy_from_env,
+            user_agent: self.user_agent.clone(),
+            ..Default::default()
+        }
+    }
+
+    /// Enables the audit log and the HAR log of an initialized engine if `audit_logs` and `har_logs`
+    /// are set, the logs are named after the file name.
+    pub async fn enable_debug_logs(&self, engine: &mut RustleDownloader) -> Result<(), String> {
+        let Some(file_name) = engine.get_file_path().await.and_then(|path| path.file_name().map(|name| name.to_string_lossy().into_owned())) else {
+            return Ok(());
+        };
+        if self.audit_logs {
+            engine.set_audit_log(&self.download_dir.join(format!("{}.audit.jsonl", file_name))).await?;
+        }
+        if self.har_logs {
+            engine.set_har_log(Some(self.download_dir.join(format!("{}.har", file_name)))).await;
+        }
+        Ok(())
+    }
+
+    /// Overrides the settings with the validated values found under `prefix`.
+    ///
+    /// # Arguments
+    ///
+    /// * `values` - The validated values, keyed by their dotted path.
+    /// * `prefix` - The path prefix of the keys to apply, e.g. `profiles.work.` or an empty string.
+    pub fn apply(&mut self, values: &HashMap<String, Value>, prefix: &str) {
+        let get = |key: &str| values.get(&format!("{}{}", prefix, key));
+
+        if let Some(download_dir) = get("download_dir").and_then(|v| v.as_str()) {
+            self.download_dir = PathBuf::from(download_dir);
+        }
+        if let Some(max_connections) = get("max_connections").and_then(|v| v.as_integer()) {
+            self.max_connections = max_connections as u8;
+        }
+        if let Some(per_host_connections) = get("per_host_connections").and_then(|v| v.as_integer()) {
+            self.per_host_connections = per_host_connections as usize;
+        }
+        if let Some(total_connections) = get("total_connections").and_then(|v| v.as_integer()) {
+            self.total_connections = Some(total_connections as usize).filter(|total_connections| *total_connections > 0);
+        }
+        if let Some(max_active_downloads) = get("max_active_downloads").and_then(|v| v.as_integer()) {
+            self.max_active_downloads = max_active_downloads as usize;
+        }
+        if let Some(certificates) = get("tls.extra_root_certificates").and_then(|v| v.as_array()) {
+            self.tls.extra_root_certificates = certificates.iter().filter_map(|v| v.as_str()).map(PathBuf::from).collect();
+        }
+        if let Some(accept_invalid_certs) = get("tls.accept_invalid_certs").and_then(|v| v.as_bool()) {
+            self.tls.accept_invalid_certs = accept_invalid_certs;
+        }
+        if let Some(client_certificate) = get("tls.client_certificate").and_then(|v| v.as_str()) {
+            self.tls.client_certificate = Some(PathBuf::from(client_certificate));
+        }
+        if let Some(client_key) = get("tls.client_key").and_then(|v| v.as_str()) {
+            self.tls.client_key = Some(PathBuf::from(client_key));
+        }
+        if let Some(auto_connections) = get("auto_connections").and_then(|v| v.as_bool()) {
+            self.auto_connections = auto_connections;
+        }
+        if let Some(audit_logs) = get("audit_logs").and_then(|v| v.as_bool()) {
+            self.audit_logs = audit_logs;
+        }
+        if let Some(har_logs) = get("har_logs").and_then(|v| v.as_bool()) {
+            self.har_logs = har_logs;
+        }
+        if let Some(servers) = get("dns.servers").and_then(|v| v.as_array()) {
+            self.dns.servers = servers.iter().filter_map(|v| v.as_str()).filter_map(|server| DnsOptions::parse_server(server).ok()).collect();
+        }
+        if let Some(over_https) = get("dns.over_https").and_then(|v| v.as_str()) {
+            self.dns.over_https = DnsOptions::parse_over_https(over_https).ok();
+        }
+        if let Some(address_family) = get("address_family").and_then(|v| v.as_str()).and_then(|v| v.parse().ok()) {
+            self.address_family = address_family;
+        }
+        if let Some(progress_interval_ms) = get("progress_interval_ms").and_then(|v| v.as_integer()) {
+            self.progress_interval = Duration::from_millis(progress_interval_ms as u64);
+        }
+        if let Some(stale_after_min) = get("stale_after_min").and_then(|v| v.as_integer()) {
+            self.stale_after = Some(Duration::from_secs(stale_after_min as u64 * 60)).filter(|stale_after| !stale_after.is_zero());
+        }
+        if let Some(stall_timeout_sec) = get("stall_timeout_sec").and_then(|v| v.as_integer()) {
+            self.stall_timeout = Some(Duration::from_secs(stall_timeout_sec as u64)).filter(|stall_timeout| !stall_timeout.is_zero());
+        }
+        if let Some(max_retries) = get("max_retries").and_then(|v| v.as_integer()) {
+            self.retry_policy.max_retries = max_retries as u32;
+        }
+        if let Some(retry_delay_ms) = get("retry_delay_ms").and_then(|v| v.as_integer()) {
+            self.retry_policy.initial_delay = Duration::from_millis(retry_delay_ms as u64);
+        }
+        if let Some(queue_retries) = get("queue_retries").and_then(|v| v.as_integer()) {
+            self.queue_retries = queue_retries as usize;
+        }
+        if let Some(on_done) = get("hooks.on_done").and_then(|v| v.as_str()) {
+            self.hooks.on_done = Some(on_done.to_string());
+        }
+        if let Some(on_error) = get("hooks.on_error").and_then(|v| v.as_str()) {
+            self.hooks.on_error = Some(on_error.to_string());
+        }
+        if let Some(refresh_url) = get("hooks.refresh_url").and_then(|v| v.as_str()) {
+            self.hooks.refresh_url = Some(refresh_url.to_string());
+        }
+        if let Some(history) = get("history.enabled").and_then(|v| v.as_bool()) {
+            self.history = history;
+        }
+        if let Some(max_age_days) = get("history.max_age_days").and_then(|v| v.as_integer()) {
+            self.history_retention.max_age = Some(Duration::from_secs(max_age_days as u64 * 24 * 3600)).filter(|max_age| !max_age.is_zero());
+        }
+        if let Some(max_entries) = get("history.max_entries").and_then(|v| v.as_integer()) {
+            self.history_retention.max_entries = Some(max_entries as usize).filter(|max_entries| *max_entries > 0);
+        }
+        if let Some(proxy) = get("proxy").and_then(|v| v.as_str()) {
+            self.proxy = Some(proxy.to_string()).filter(|proxy| !proxy.is_empty());
+        }
+        if let Some(proxy_from_env) = get("proxy_from_env").and_then(|v| v.as_bool()) {
+            self.proxy_from_env = proxy_from_env;
+        }
+        if let Some(user_agent) = get("user_agent").and_then(|v| v.as_str()) {
+            self.user_agent = Some(user_agent.to_string()).filter(|user_agent| !user_agent.is_empty());
+        }
+        if let Some(speed_limit_kib) = get("speed_limit_kib").and_then(|v| v.as_integer()) {
+            self.speed_limit = Some(speed_limit_kib as u64 * 1024).filter(|speed_limit| *speed_limit > 0);
+        }
+        if let Some(global_speed_limit_kib) = get("global_speed_limit_kib").and_then(|v| v.as_integer()) {
+            self.global_speed_limit = Some(global_speed_limit_kib as u64 * 1024).filter(|speed_limit| *speed_limit > 0);
+        }
+        if let Some(max_memory_mib) = get("max_memory_mib").and_then(|v| v.as_integer()) {
+            self.max_memory = Some(max_memory_mib as u64 * 1024 * 1024).filter(|max_memory| *max_memory > 0);
+        }
+        if let Some(data_quota_mib) = get("data_quota_mib").and_then(|v| v.as_integer()) {
+            self.data_quota = Some(data_quota_mib as u64 * 1024 * 1024).filter(|data_quota| *data_quota > 0);
+        }
+        if let Some(data_quota_period) = get("data_quota_period").and_then(|v| v.as_str()) {
+            self.data_quota_period = if data_quota_period == "day" { QuotaPeriod::Day } else { QuotaPeriod::Month };
+        }
+        if let Some(io_uring) = get("io_uring").and_then(|v| v.as_bool()) {
+            self.io_uring = io_uring;
+        }
+        if let Some(write_buffer_kib) = get("write_buffer_kib").and_then(|v| v.as_integer()) {
+            self.write_size = write_buffer_kib as usize * 1024;
+        }
+        if let Some(theme) = get("theme").and_then(|v| v.as_str()).and_then(|v| v.parse().ok()) {
+            self.theme = theme;
+        }
+        if let Some(on_done) = get("notifications.on_done").and_then(|v| v.as_bool()) {
+            self.notifications.on_done = on_done;
+        }
+        if let Some(on_error) = get("notifications.on_error").and_then(|v| v.as_bool()) {
+            self.notifications.on_error = on_error;
+        }
+        if let Some(width) = get("window.width").and_then(|v| v.as_integer()) {
+            self.window.width = width as u32;
+        }
+        if let Some(height) = get("window.height").and_then(|v| v.as_integer()) {
+            self.window.height = height as u32;
+        }
+        // The position is only restored when both of its coordinates are set
+        if let (Some(x), Some(y)) = (get("window.x").and_then(|v| v.as_integer()), get("window.y").and_then(|v| v.as_integer())) {
+            self.window.position = Some((x as i32, y as i32));
+        }
+        if let Some(keyring) = get("keyring").and_then(|v| v.as_bool()) {
+            self.keyring = keyring;
+        }
+        if let Some(http_version) = get("http_version").and_then(|v| v.as_str()).and_then(|v| v.parse().ok()) {
+            self.http_version = http_version;
+        }
+        if let Some(content_encoding) = get("content_encoding").and_then(|v| v.as_str()).and_then(|v| v.parse().ok()) {
+            self.content_encoding = content_encoding;
+        }
+        if let Some(segment_hashing) = get("segment_hashing").and_then(|v| v.as_bool()) {
+            self.segment_hashing = segment_hashing;
+        }
+        if let Some(buffering) = get("buffering").and_then(|v| v.as_str()).and_then(|v| v.parse().ok()) {
+            self.buffering = buffering;
+        }
+        if let Some(part_files) = get("part_files").and_then(|v| v.as_str()) {
+            self.part_files = match part_files {
+                "next_to_file" => PartFileLocation::NextToFile,
+                _ => Settings::part_files_dir().map(PartFileLocation::Dir).unwrap_or(PartFileLocation::NextToFile),
+            };
+        }
+        if let Some(file_mode) = get("file_mode").and_then(|v| v.as_integer()) {
+            self.file_permissions.mode = Some(file_mode as u32);
+        }
+        if let Some(mark_executable) = get("mark_executable").and_then(|v| v.as_bool()) {
+            self.file_permissions.executable_types = mark_executable;
+        }
+        if let Some(provenance) = get("provenance").and_then(|v| v.as_bool()) {
+            self.provenance = provenance;
+        }
+        if let Some(type_check) = get("type_check").and_then(|v| v.as_bool()) {
+            self.type_check = type_check;
+        }
+        if let Some(staging_dir) = get("staging_dir").and_then(|v| v.as_str()) {
+            self.staging_dir = Some(PathBuf::from(staging_dir)).filter(|staging_dir| !staging_dir.as_os_str().is_empty());
+        }
+    }
+}
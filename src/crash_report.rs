@@ -0,0 +1,134 @@
+use std::backtrace::Backtrace;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use url::Url;
+
+/// Page opening a new issue on the project's tracker.
+const NEW_ISSUE_URL: &str = "https://github.com/bishoyroufael/rustle/issues/new";
+
+/// Installs a panic hook writing a crash report to the crash folder, see `crash_dir()`.
+/// Nothing is sent anywhere, the user is shown a prefilled issue link to open if they want to report it.
+pub fn install() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        default_hook(info);
+
+        let panic = info.to_string();
+        let report = crash_report(&panic, &Backtrace::force_capture());
+        match write_report(&report) {
+            Ok(path) => {
+                eprintln!("Rustle crashed, a report was written to {}", path.display());
+                eprintln!("To report the crash, open the link below and attach the report:");
+                eprintln!("{}", issue_url(info.payload_as_str(), &panic, &path));
+            },
+            Err(e) => {
+                eprintln!("Rustle crashed and the crash report couldn't be written, error : {}", e);
+            },
+        }
+    }));
+}
+
+/// Returns the folder crash reports are written to, i.e. `<local data dir>/rustle/crashes`.
+pub fn crash_dir() -> Option<PathBuf> {
+    dirs::data_local_dir().map(|dir| dir.join("rustle").join("crashes"))
+}
+
+/// Builds the text of a crash report: version, platform, panic message and backtrace.
+///
+/// # Arguments
+///
+/// * `panic` - The panic, with its message and location.
+/// * `backtrace` - The backtrace of the panicking thread.
+fn crash_report(panic: &str, backtrace: &Backtrace) -> String {
+    format!(
+        "Rustle {}\nPlatform: {} {}\nThread: {}\nPanic: {}\n\nBacktrace:\n{}\n",
+        env!("CARGO_PKG_VERSION"),
+        std::env::consts::OS,
+        std::env::consts::ARCH,
+        std::thread::current().name().unwrap_or("<unnamed>"),
+        panic,
+        backtrace
+    )
+}
+
+/// Writes a crash report to a new file in the crash folder and returns its path.
+fn write_report(report: &str) -> Result<PathBuf, String> {
+    let dir = crash_dir().ok_or("Couldn't find the user data directory")?;
+    write_report_in(&dir, report)
+}
+
+/// Writes a crash report to a new file in `dir`, created if needed, and returns its path.
+fn write_report_in(dir: &Path, report: &str) -> Result<PathBuf, String> {
+    fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let path = dir.join(format!("crash-{}.txt", timestamp));
+    fs::write(&path, report).map_err(|e| e.to_string())?;
+    Ok(path)
+}
+
+/// Returns the link opening a new issue prefilled with the panic, the full report stays in the file to attach.
+///
+/// # Arguments
+///
+/// * `message` - The panic message, if it's a string.
+/// * `panic` - The panic, with its message and location.
+/// * `report_path` - The path of the crash report.
+fn issue_url(message: Option<&str>, panic: &str, report_path: &Path) -> Url {
+    let title = format!("Crash: {}", message.unwrap_or("panic"));
+    let body = format!(
+        "Rustle {} crashed on {} {}.\n\n```\n{}\n```\n\nThe full report is attached ({}).",
+        env!("CARGO_PKG_VERSION"),
+        std::env::consts::OS,
+        std::env::consts::ARCH,
+        panic,
+        report_path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default()
+    );
+    Url::parse_with_params(NEW_ISSUE_URL, [("title", title), ("body", body)]).expect("the issue url is valid")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PANIC: &str = "panicked at src/main.rs:10:5:\nindex out of bounds";
+
+    #[test]
+    fn report_lists_the_version_platform_and_panic() {
+        let report = crash_report(PANIC, &Backtrace::disabled());
+        let mut lines = report.lines();
+        assert_eq!(lines.next(), Some(format!("Rustle {}", env!("CARGO_PKG_VERSION")).as_str()));
+        assert_eq!(lines.next(), Some(format!("Platform: {} {}", std::env::consts::OS, std::env::consts::ARCH).as_str()));
+        assert!(lines.next().is_some_and(|line| line.starts_with("Thread: ")));
+        assert!(report.contains(&format!("Panic: {}\n\nBacktrace:\n", PANIC)));
+    }
+
+    #[test]
+    fn reports_are_written_to_new_files() {
+        let dir = std::env::temp_dir().join(format!("rustle-test-crashes-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        let path = write_report_in(&dir, "report").unwrap();
+        assert_eq!(path.parent(), Some(dir.as_path()));
+        let name = path.file_name().unwrap().to_str().unwrap();
+        assert!(name.strip_prefix("crash-").and_then(|name| name.strip_suffix(".txt")).is_some_and(|secs| secs.parse::<u64>().is_ok()), "{}", name);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "report");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn issue_url_is_prefilled_with_the_panic() {
+        let url = issue_url(Some("index out of bounds"), PANIC, Path::new("/crashes/crash-1700000000.txt"));
+        assert_eq!(url.as_str().split('?').next(), Some(NEW_ISSUE_URL));
+
+        let params: Vec<(String, String)> = url.query_pairs().into_owned().collect();
+        assert_eq!(params[0], (String::from("title"), String::from("Crash: index out of bounds")));
+        assert_eq!(params[1].0, "body");
+        assert!(params[1].1.contains(&format!("```\n{}\n```", PANIC)));
+        assert!(params[1].1.ends_with("The full report is attached (crash-1700000000.txt)."));
+
+        let url = issue_url(None, PANIC, Path::new("crash-1.txt"));
+        assert!(url.query_pairs().any(|(name, value)| name == "title" && value == "Crash: panic"));
+    }
+}
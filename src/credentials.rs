@@ -0,0 +1,76 @@
+use std::io::{self, BufRead, Write};
+use rustle_core::credentials::{credential_key, CredentialStore, Credentials};
+use url::Url;
+
+/// Usage of the `rustle credentials` command.
+const USAGE: &str = "Usage: rustle credentials <set|show|delete> <host or URL> [--user <name>]";
+
+/// Handles the `rustle credentials` command, managing the credentials of a host in the OS
+/// credential store. `set` reads the password from the standard input, so it's neither
+/// stored in the shell history nor in the settings file.
+///
+/// # Arguments
+///
+/// * `args` - The arguments following `credentials`.
+///
+/// Returns an error message if the arguments are invalid or the credential store couldn't be reached.
+pub fn run(args: &[String]) -> Result<(), String> {
+    let mut positional = Vec::new();
+    let mut user = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--user" => user = Some(args.next().ok_or(USAGE)?.clone()),
+            // The profile was already selected
+            "--profile" => { args.next(); },
+            arg if arg.starts_with("--profile=") => {},
+            arg if arg.starts_with('-') => return Err(String::from(USAGE)),
+            _ => positional.push(arg.as_str()),
+        }
+    }
+    let [action, host] = positional[..] else { return Err(String::from(USAGE)) };
+    let host = host_key(host);
+
+    let store = CredentialStore::default();
+    let runtime = tokio::runtime::Runtime::new().map_err(|e| format!("Couldn't start the runtime, error : {}", e))?;
+    runtime.block_on(async {
+        match action {
+            "set" => {
+                let username = user.ok_or("The user name is required, e.g. --user alice")?;
+                let password = read_password()?;
+                store.set(&host, &Credentials { username, password }).await?;
+                println!("Stored the credentials of {}", host);
+            },
+            "show" => match store.get(&host).await? {
+                Some(credentials) => println!("{} : {}", host, credentials.username),
+                None => println!("No credentials stored for {}", host),
+            },
+            "delete" => match store.delete(&host).await? {
+                true => println!("Deleted the credentials of {}", host),
+                false => println!("No credentials stored for {}", host),
+            },
+            _ => return Err(String::from(USAGE)),
+        }
+        Ok(())
+    })
+}
+
+/// Returns the key of a host given as is or as a URL, see `credential_key()`.
+fn host_key(host: &str) -> String {
+    Url::parse(host).ok()
+        .and_then(|url| credential_key(&url))
+        .unwrap_or_else(|| host.to_ascii_lowercase())
+}
+
+/// Reads the password from the first line of the standard input.
+fn read_password() -> Result<String, String> {
+    eprint!("Password: ");
+    io::stderr().flush().map_err(|e| e.to_string())?;
+    let mut password = String::new();
+    io::stdin().lock().read_line(&mut password).map_err(|e| format!("Couldn't read the password, error : {}", e))?;
+    let password = password.trim_end_matches(['\r', '\n']).to_string();
+    if password.is_empty() {
+        return Err(String::from("The password is empty"));
+    }
+    Ok(password)
+}
@@ -1,2 +0,0 @@
-pub mod io;
-pub mod downloader;
\ No newline at end of file
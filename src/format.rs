@@ -0,0 +1,182 @@
+use std::time::{Duration, SystemTime};
+use rustle_core::dates::{civil_from_days, day_of};
+use rustle_core::stats::{HistoryStats, StatsPeriod, StatsSummary};
+use rustle_core::timeline::TimelineEntry;
+use rustle_core::usage::{DataUsage, QuotaPeriod};
+
+/// Formats a file size in bytes into a human-readable string.
+///
+/// The function takes a file size in bytes as input and returns a formatted string
+/// representing the size in the most appropriate unit (B, KB, MB, GB, TB, or PB).
+///
+/// # Arguments
+///
+/// * `bytes` - The file size in bytes. Must be a non-negative integer.
+///
+/// # Returns
+///
+/// A formatted string representing the file size with the appropriate unit.
+pub fn format_file_size(bytes: u64) -> String {
+    if bytes < 1024 {
+        format!("{} B", bytes)
+    } else if bytes < 1024_u64.pow(2) {
+        format!("{:.2} KB", bytes as f64 / 1024.0)
+    } else if bytes < 1024_u64.pow(3) {
+        format!("{:.2} MB", bytes as f64 / 1024.0 / 1024.0)
+    } else if bytes < 1024_u64.pow(4) {
+        format!("{:.2} GB", bytes as f64 / 1024.0 / 1024.0 / 1024.0)
+    } else if bytes < 1024_u64.pow(5) {
+        format!("{:.2} TB", bytes as f64 / 1024.0 / 1024.0 / 1024.0 / 1024.0)
+    } else {
+        format!("{:.2} PB", bytes as f64 / 1024.0 / 1024.0 / 1024.0 / 1024.0 / 1024.0)
+    }
+}
+
+/// Formats a duration into a short human-readable string, e.g. `1h 02m 03s`, `02m 03s` or `3s`.
+///
+/// # Arguments
+///
+/// * `duration` - The duration to format, sub-second precision is dropped.
+///
+/// # Returns
+///
+/// A formatted string with the hours and minutes shown only when needed.
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (hours, minutes, seconds) = (secs / 3600, secs / 60 % 60, secs % 60);
+    if hours > 0 {
+        format!("{}h {:02}m {:02}s", hours, minutes, seconds)
+    } else if minutes > 0 {
+        format!("{:02}m {:02}s", minutes, seconds)
+    } else {
+        format!("{}s", seconds)
+    }
+}
+
+/// Formats the day of a time as `YYYY-MM-DD`, in UTC.
+///
+/// # Arguments
+///
+/// * `time` - The time to format, times before the Unix epoch are formatted as the epoch.
+pub fn format_date(time: SystemTime) -> String {
+    let (year, month, day) = civil_from_days(day_of(time));
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Formats the statistics of the download history as lines of text, shared by the `stats`
+/// command and the statistics of the GUI.
+///
+/// # Arguments
+///
+/// * `stats` - The statistics, see `DownloadHistory::stats`.
+/// * `period` - The length of the periods of `stats`.
+///
+/// # Returns
+///
+/// The summary, followed by the totals of each period and each host.
+pub fn format_stats(stats: &HistoryStats, period: StatsPeriod) -> Vec<String> {
+    let summary = |summary: &StatsSummary| {
+        let mut text = format!("{} downloads, {} failed, {}", summary.downloads, summary.failed, format_file_size(summary.bytes));
+        if let Some(speed) = summary.average_speed {
+            text.push_str(&format!(" at {}/s", format_file_size(speed as u64)));
+        }
+        text
+    };
+
+    let mut lines = vec![summary(&stats.summary)];
+    if !stats.periods.is_empty() {
+        lines.push(String::from(match period {
+            StatsPeriod::Day => "Per day",
+            StatsPeriod::Week => "Per week",
+        }));
+        lines.extend(stats.periods.iter().rev().map(|period| format!("  {}  {}", format_date(period.start), summary(&period.summary))));
+    }
+    if !stats.hosts.is_empty() {
+        lines.push(String::from("Per host"));
+        lines.extend(stats.hosts.iter().map(|host| format!("  {}  {}", host.host, summary(&host.summary))));
+    }
+    lines
+}
+
+/// Formats the data usage of the downloads as lines of text, shared by the `rustle stats` command
+/// and the statistics of the GUI.
+///
+/// # Arguments
+///
+/// * `usage` - The data usage, see `Settings::open_data_usage`.
+/// * `since` - The start of the months listed, `None` for all of them.
+///
+/// # Returns
+///
+/// The usage of today and this month, the data quota if any, followed by the totals of each month.
+pub fn format_usage(usage: &DataUsage, since: Option<SystemTime>) -> Vec<String> {
+    let mut lines = vec![format!("Data usage: {} today, {} this month",
+        format_file_size(usage.today()), format_file_size(usage.current(QuotaPeriod::Month)))];
+    if let Some(quota) = usage.quota() {
+        lines.push(format!("Quota: {} of {} {} used{}",
+            format_file_size(usage.current(quota.period)),
+            format_file_size(quota.bytes),
+            match quota.period {
+                QuotaPeriod::Day => "today",
+                QuotaPeriod::Month => "this month",
+            },
+            if usage.is_quota_reached() { ", the downloads are paused" } else { "" }));
+    }
+    let months = usage.totals(since, QuotaPeriod::Month);
+    if !months.is_empty() {
+        lines.push(String::from("Per month"));
+        lines.extend(months.iter().rev().map(|month| format!("  {}  {}", format_date(month.start), format_file_size(month.bytes))));
+    }
+    lines
+}
+
+/// Formats the timeline of a download as lines of text, shared by the terminal interface and the GUI.
+///
+/// # Arguments
+///
+/// * `entries` - The events of the download, see `RustleDownloader::get_timeline`.
+///
+/// # Returns
+///
+/// A line per event, prefixed by the time elapsed since the first one, e.g. `+02m 03s  Paused`.
+pub fn format_timeline(entries: &[TimelineEntry]) -> Vec<String> {
+    let Some(first) = entries.first() else { return Vec::new() };
+    entries.iter()
+        .map(|entry| format!("+{}  {}", format_duration(entry.time.duration_since(first.time).unwrap_or_default()), entry.event))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::UNIX_EPOCH;
+    use rustle_core::stats::PeriodStats;
+
+    #[test]
+    fn dates_are_formatted_in_utc() {
+        assert_eq!(format_date(UNIX_EPOCH), "1970-01-01");
+        assert_eq!(format_date(UNIX_EPOCH + Duration::from_secs(19_782 * 24 * 3600 + 24 * 3600 - 1)), "2024-02-29");
+        assert_eq!(format_date(UNIX_EPOCH + Duration::from_secs(19_783 * 24 * 3600)), "2024-03-01");
+        assert_eq!(format_date(UNIX_EPOCH - Duration::from_secs(1)), "1970-01-01");
+    }
+
+    #[test]
+    fn stats_list_the_periods_newest_first() {
+        let day = |day: u64| UNIX_EPOCH + Duration::from_secs(day * 24 * 3600);
+        let summary = StatsSummary { downloads: 1, failed: 0, bytes: 1024, average_speed: None };
+        let stats = HistoryStats {
+            summary: StatsSummary { downloads: 2, failed: 1, bytes: 2048, average_speed: Some(512.0) },
+            periods: vec![PeriodStats { start: day(19_723), summary }, PeriodStats { start: day(19_730), summary }],
+            hosts: Vec::new(),
+        };
+
+        assert_eq!(format_stats(&stats, StatsPeriod::Week), vec![
+            "2 downloads, 1 failed, 2.00 KB at 512 B/s",
+            "Per week",
+            "  2024-01-08  1 downloads, 0 failed, 1.00 KB",
+            "  2024-01-01  1 downloads, 0 failed, 1.00 KB",
+        ]);
+        assert_eq!(format_stats(&stats, StatsPeriod::Day)[1], "Per day");
+        assert_eq!(format_stats(&HistoryStats::default(), StatsPeriod::Day), vec!["0 downloads, 0 failed, 0 B"]);
+    }
+}
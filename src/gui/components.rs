@@ -6,22 +6,22 @@ use super::rustle_gui::Message;
 
 /// Returns a `Text` widget displaying a file download icon.
 pub fn file_download_icon() -> Text<'static> {
-    Text::new(Icon::FileEarmarkArrowDown.to_string()).font(ICON_FONT).into()
+    Text::new(Icon::FileEarmarkArrowDown.to_string()).font(ICON_FONT)
 }
 
 /// Returns a `Text` widget displaying a plus icon.
 pub fn plus_icon() -> Text<'static> {
-    Text::new(Icon::Plus.to_string()).font(ICON_FONT).into()
+    Text::new(Icon::Plus.to_string()).font(ICON_FONT)
 }
 
 /// Returns a `Text` widget displaying an info icon.
 pub fn info_icon() -> Text<'static> {
-    Text::new(Icon::Info.to_string()).font(ICON_FONT).into()
+    Text::new(Icon::Info.to_string()).font(ICON_FONT)
 }
 
 /// Returns a `Text` widget displaying a play icon.
 pub fn play_icon() -> Text<'static> {
-    Text::new(Icon::Play.to_string()).font(ICON_FONT).into()
+    Text::new(Icon::Play.to_string()).font(ICON_FONT)
 }
 
 /// Returns a `Text` widget displaying a pause icon.
@@ -34,6 +34,16 @@ pub fn cancel_icon() -> Text<'static> {
     Text::new(Icon::X.to_string()).font(ICON_FONT)
 }
 
+/// Returns a `Text` widget displaying a share icon.
+pub fn share_icon() -> Text<'static> {
+    Text::new(Icon::Share.to_string()).font(ICON_FONT)
+}
+
+/// Returns a `Text` widget displaying a gear icon.
+pub fn gear_icon() -> Text<'static> {
+    Text::new(Icon::Gear.to_string()).font(ICON_FONT)
+}
+
 /// Creates a `Badge` element with the specified text and style.
 ///
 /// # Arguments
@@ -59,7 +69,7 @@ pub fn badge(text: String, style: BadgeStyles) -> Element<'static, Message> {
 ///
 /// Returns a `ProgressBar` widget.
 pub fn progress_bar(value: f32, style: iced::theme::ProgressBar) -> iced::widget::ProgressBar<Renderer> {
-    ProgressBar::new(0.0..=100.0, value).style(style).into()
+    ProgressBar::new(0.0..=100.0, value).style(style)
 }
 
 /// Creates a `Button` widget with the provided text component and optional message callback.
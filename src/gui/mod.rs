@@ -1,4 +1,5 @@
 pub mod rustle_gui;
 pub mod utils;
 pub mod styles;
-pub mod components;
\ No newline at end of file
+pub mod components;
+pub mod notifications;
\ No newline at end of file
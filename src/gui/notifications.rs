@@ -0,0 +1,44 @@
+use notify_rust::Notification;
+use tokio::task;
+use rustle::config::settings::Notifications;
+use rustle::hooks::HookContext;
+
+/// Shows a desktop notification when a download finishes or fails, if enabled in the settings.
+///
+/// # Arguments
+///
+/// * `notifications` - The notifications enabled in the settings.
+/// * `context` - The download that ended, as passed to its hooks.
+///
+/// # Returns
+///
+/// Returns an error if the notification couldn't be shown, e.g. without a notification server.
+pub async fn notify_download_end(notifications : Notifications, context : HookContext) -> Result<(), String> {
+    let enabled = match context.error {
+        None => notifications.on_done,
+        Some(_) => notifications.on_error,
+    };
+    if !enabled {
+        return Ok(());
+    }
+    let name = context.path.as_ref()
+        .and_then(|path| path.file_name())
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or(context.url.clone());
+    let (summary, body) = match context.error.as_ref() {
+        None => (String::from("Download finished"), name),
+        Some(e) => (String::from("Download failed"), format!("{}, {}", name, e)),
+    };
+    // Showing a notification blocks until the notification server answers
+    task::spawn_blocking(move || {
+        Notification::new()
+            .appname("rustle")
+            .summary(&summary)
+            .body(&body)
+            .show()
+            .map(|_| ())
+            .map_err(|e| format!("Couldn't show the notification, err : {}", e))
+    })
+    .await
+    .map_err(|e| format!("Couldn't show the notification, err : {}", e))?
+}
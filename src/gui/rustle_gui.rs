@@ -1,16 +1,46 @@
 /*
     Imports
 */
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use crate::download_utils::downloader::{RustleDownloader, ResponseHeaderInfo, PartDownloadInfo, DownloadStatus};
-use iced::widget::{Text,Container, Column, Row, TextInput, Scrollable};
+use std::time::{Duration, Instant, SystemTime};
+use rustle_core::downloader::{RustleDownloader, ResponseHeaderInfo, PartDownloadInfo, DownloadStatus, RetryInfo};
+use rustle_core::connection_manager::ConnectionManager;
+use rustle_core::error::ErrorKind;
+use rustle_core::queue_manager::{QueueManager, QueueState, Priority};
+use rustle_core::drain::{clear_drain, release_drain, sync_drain, DRAIN_POLL_INTERVAL};
+use rustle_core::bundle::DownloadBundle;
+use rustle_core::audit::sha256_file;
+use rustle_core::protocol::ProtocolRegistry;
+use rustle_core::curl::CurlCommand;
+use rustle_core::url_pattern::{expand_url_pattern, is_url_pattern};
+use rustle_core::batch::{BatchEntry, BatchImport};
+use rustle_core::links::{fetch_links, LinkFilter, PageLink};
+use rustle_core::crawl::{crawl, CrawlOptions, CrawlResult};
+use rustle_core::zsync::{delta_download, DeltaStats};
+use rustle_core::history::DownloadHistory;
+use rustle_core::stats::StatsPeriod;
+use rustle_core::usage::DataUsage;
+use rustle_core::download_list::{DownloadList, DownloadListEntry, DOWNLOAD_LIST_FILE_NAME};
+use rustle_core::journal::{remove_orphan_part_files, termination_signal, JournalEntry, PartFileLocation, SessionJournal};
+use rustle_core::transport::Transport;
+use url::Url;
+use rustle::config::profiles::Profiles;
+use rustle::config::settings::{Notifications, Settings, ThemeChoice, WindowGeometry};
+use toml_edit::Value;
+use iced::widget::{Text,Container, Column, Row, TextInput, Scrollable, PickList, Checkbox, horizontal_space};
 use iced::{theme, 
+        clipboard,
+        subscription,
+        window,
         Alignment,
         Element,
         Application,
         Length, 
         Command, 
+        Event,
+        Subscription,
         Theme, 
         alignment::Horizontal
         };
@@ -18,9 +48,12 @@ use iced::{theme,
 use iced_aw::floating_element::Anchor;
 use iced_aw::{FloatingElement, Modal, Card, Spinner};
 use iced_aw::style::BadgeStyles;
-use super::utils::format_file_size;
+use rustle::format::{format_file_size, format_duration, format_stats, format_timeline, format_usage};
+use rustle::hooks::{HookContext, Hooks};
+use super::utils::{parse_start_time, parse_file_mode, parse_referer, parse_out_dir, check_writable, split_urls, join_pasted_lines, format_start_time, modal_download_url, is_list_path, is_zsync_url, is_download_list_path};
 use super::styles::*;
 use super::components::*;
+use super::notifications::notify_download_end;
 
 
 /*
@@ -36,10 +69,26 @@ struct DownloadRowInfo {
     file_size : Option<u64>,
     /// detected file type
     file_type : Option<String>,
+    /// HTTP version negotiated with the server, e.g. `HTTP/2.0`
+    http_version : Option<String>,
     /// vector storing the downloading progress
     download_progress : Vec<PartDownloadInfo>,
+    /// estimated remaining time of the download
+    eta : Option<Duration>,
     /// error message if present
     error : Option<String>,
+    /// upcoming retry while the download is retrying
+    retry : Option<RetryInfo>,
+    /// whether the download is paused until the network is back
+    paused_offline : bool,
+    /// whether the download is paused until the data quota is available again
+    paused_quota : bool,
+    /// why the downloaded data was discarded when the download was resumed, if the file changed on the server
+    restart_reason : Option<String>,
+    /// bytes moved from the staging directory and size of the file, while it's moved
+    moving : Option<(u64, u64)>,
+    /// why the type of the file doesn't match its extension, while the download waits for a confirmation
+    type_warning : Option<String>,
     /// engine for downloading the file
     engine : Arc<RustleDownloader>,
     /// downloading status
@@ -47,12 +96,21 @@ struct DownloadRowInfo {
 }
 
 impl DownloadRowInfo {
+    /// Returns the file type of the row, followed by the HTTP version once it's negotiated.
+    fn file_type_label(&self) -> String {
+        let file_type = self.file_type.clone().unwrap_or(String::from("Unknown"));
+        match self.http_version.as_ref() {
+            Some(version) => format!("{} · {}", file_type, version),
+            None => file_type,
+        }
+    }
+
     pub fn get_total_download_progress(self: &DownloadRowInfo) -> f32 {
         (self.download_progress.iter().map(|e| e.downloaded_bytes as f32).sum::<f32>()) 
                                 / (self.file_size.unwrap_or(1) as f32) * 100.0
     }
     pub fn get_download_speed_mbs(self: &DownloadRowInfo) -> f32 {
-        (self.download_progress.iter().map(|e| e.download_speed as f32).sum::<f32>()) / 1_000_000.0
+        (self.download_progress.iter().map(|e| e.smoothed_speed as f32).sum::<f32>()) / 1_000_000.0
     }
 }
 
@@ -69,15 +127,257 @@ pub struct RustleGUI {
     modal_url : String,
     /// modal url string field
     modal_is_loading : bool,
-    /// counter that acts as the key for the hashmap 
-    downloads_counter : usize
+    /// modal flag to accept invalid/self-signed certificates for the download
+    modal_accept_invalid_certs : bool,
+    /// modal start time field, empty to add the download without starting it
+    modal_start_at : String,
+    /// modal mode of the downloaded file on Unix, empty for the one of the settings
+    modal_file_mode : String,
+    /// referer of the download typed in the modal, sent as the `Referer` of its requests
+    modal_referer : String,
+    /// modal path of the older version of a file updated from a zsync control file
+    modal_seed_path : String,
+    /// modal output directory field, empty for the download directory of the settings
+    modal_out_dir : String,
+    /// error of the modal output directory, checked when it's typed since it creates a file
+    modal_out_dir_error : Option<String>,
+    /// modal number of connections, `None` for the one of the settings
+    modal_connections : Option<u8>,
+    /// pasted urls of the modal that couldn't be added, with their error
+    modal_failures : Vec<String>,
+    /// row waiting for the user to confirm cancelling it
+    cancel_confirm_row : Option<usize>,
+    /// settings profiles loaded at startup, the active one applies to new downloads
+    profiles : Profiles,
+    /// connection manager shared by all downloads
+    connection_manager : Arc<ConnectionManager>,
+    /// queue manager owning the downloads, the hashmap is keyed by its download ids
+    queue_manager : Arc<QueueManager>,
+    /// protocols the downloads are created with, the modal only accepts their URLs
+    protocols : Arc<ProtocolRegistry>,
+    /// links of the page grabbed from the modal, shown in the link selection modal
+    link_grabber : Option<LinkGrabber>,
+    /// history the finished and failed downloads are recorded in, if enabled
+    history : Option<Arc<DownloadHistory>>,
+    /// color scheme of the OS, the theme follows it when `ThemeChoice::System` is set
+    system_theme : ThemeChoice,
+    /// statistics of the history shown in the statistics modal, one line each
+    statistics : Option<Vec<String>>,
+    /// row whose timeline is shown in the timeline modal
+    timeline_row : Option<usize>,
+    /// fields of the settings modal, shown while it's set
+    settings_form : Option<SettingsForm>,
+    /// length of the periods of the statistics
+    statistics_period : StatsPeriod,
+    /// size and position of the window, saved to the settings file when it's closed
+    window_geometry : WindowGeometry,
+    /// whether the queue drains, requested with `rustle queue drain`
+    is_draining : bool,
+    /// flag set while the downloads are paused and saved before closing the window
+    is_shutting_down : bool
+}
+
+
+/*
+    Flags the GUI is started with
+*/
+#[derive(Debug, Default)]
+pub struct GuiFlags {
+    /// settings profiles loaded from the settings file
+    pub profiles : Profiles,
+    /// `.rustle` bundles opened from the command line, queued at startup
+    pub bundles : Vec<PathBuf>
 }
 
 
+// Days counted by the statistics of the download history
+const STATISTICS_DAYS: u64 = 30;
+
+// Pasted urls that couldn't be added listed in the modal, the others are counted
+const MODAL_FAILURES_SHOWN: usize = 5;
+
+// Numbers of connections a download can be added with, the range of `max_connections`
+const CONNECTION_CHOICES: [u8; 16] = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16];
+
 // Callback types
 type DownloadInitHeadType = Result<(Option<ResponseHeaderInfo>, RustleDownloader), String>;
-type UpdateDownloadType = (Vec<PartDownloadInfo>, DownloadStatus, usize, Arc<RustleDownloader>);
+type BundleInitType = Result<(Option<ResponseHeaderInfo>, RustleDownloader, Box<DownloadBundle>), String>;
+type ExportBundleType = Result<PathBuf, String>;
+type DeltaDownloadType = Result<DeltaStats, String>;
+type UpdateDownloadType = (Vec<PartDownloadInfo>, DownloadStatus, usize, Arc<RustleDownloader>, Option<Duration>, Option<RetryInfo>, bool, Option<String>, Option<String>, Option<(u64, u64)>, Option<String>, bool);
+type StartDownloadType = (usize, Result<bool, String>);
+type CancelDownloadType = (usize, bool);
+type HostPauseType = (bool, Vec<usize>);
+type BatchInitType = (BatchEntry, DownloadInitHeadType, Box<DownloadBatch>);
+type GrabLinksType = Result<Vec<PageLink>, String>;
+type MirrorSiteType = Result<CrawlResult, String>;
+type ListInitType = (DownloadListEntry, DownloadInitHeadType, Box<ListImport>);
+type StatisticsType = Result<Vec<String>, String>;
+type RestoredDownloadType = (JournalEntry, Result<(Option<ResponseHeaderInfo>, Vec<PartDownloadInfo>, RustleDownloader), String>);
+
+
+/*
+    Downloads of a URL pattern or an imported list, initialized one after the other
+*/
+#[derive(Debug, Clone)]
+pub struct DownloadBatch {
+    /// entries left to initialize
+    entries : VecDeque<BatchEntry>,
+    /// settings the downloads are created with
+    settings : Settings,
+    /// start time of the downloads, they're queued right away without one
+    start_at : Option<SystemTime>,
+    /// urls that are invalid or failed to initialize with their error, shown in the modal once the batch is done if set
+    failures : Option<Vec<(String, String)>>
+}
+
+/*
+    Downloads of an imported download list, initialized one after the other like a batch
+*/
+#[derive(Debug, Clone)]
+pub struct ListImport {
+    /// unfinished downloads left to initialize
+    entries : VecDeque<DownloadListEntry>,
+    /// settings the downloads are created with
+    settings : Settings
+}
+
+/*
+    Entries of the menu in the header
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MenuAction {
+    ExportList,
+    ImportList,
+    Statistics,
+    SwitchTheme
+}
+
+impl MenuAction {
+    const ALL: [MenuAction; 4] = [MenuAction::ExportList, MenuAction::ImportList, MenuAction::Statistics, MenuAction::SwitchTheme];
+}
+
+impl std::fmt::Display for MenuAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            MenuAction::ExportList => "Export download list",
+            MenuAction::ImportList => "Import download list",
+            MenuAction::Statistics => "Statistics",
+            MenuAction::SwitchTheme => "Switch theme",
+        })
+    }
+}
 
+/*
+    Links grabbed from a web page, the selected ones matching the filter are queued
+*/
+#[derive(Debug, Clone)]
+struct LinkGrabber {
+    /// links found in the page, supported by the protocols
+    links : Vec<PageLink>,
+    /// whether each link is selected
+    selected : Vec<bool>,
+    /// filter extensions field, e.g. `jpg, png`
+    extensions : String,
+    /// filter regular expression field
+    pattern : String,
+    /// whether the downloads accept invalid/self-signed certificates, like the page
+    accept_invalid_certs : bool,
+    /// output directory of the downloads typed in the modal, if any
+    out_dir : Option<PathBuf>,
+    /// number of connections of the downloads picked in the modal, if any
+    connections : Option<u8>,
+    /// url of the page, the start of a mirrored site
+    page_url : String,
+    /// mirror depth field, levels of pages followed from the page
+    depth : String,
+    /// mirror flag to only follow the links of the page's host
+    same_host : bool,
+    /// mirror flag to skip the urls disallowed by robots.txt
+    respect_robots : bool,
+    /// flag set while the site is crawled
+    is_crawling : bool
+}
+
+/*
+    Fields of the settings modal, the changed ones are saved to the active profile of the settings file
+*/
+#[derive(Debug, Clone)]
+struct SettingsForm {
+    /// default output directory field
+    download_dir : String,
+    /// default number of connections of a download field
+    max_connections : String,
+    /// speed limit of each download field, in KiB/s
+    speed_limit : String,
+    /// total speed limit of the downloads field, in KiB/s
+    global_speed_limit : String,
+    /// selected color theme
+    theme : ThemeChoice,
+    /// flag to notify when a download finishes
+    notify_on_done : bool,
+    /// flag to notify when a download fails
+    notify_on_error : bool
+}
+
+impl SettingsForm {
+    /// Creates the fields filled with the values of `settings`.
+    fn new(settings : &Settings) -> SettingsForm {
+        let kib = |limit : Option<u64>| (limit.unwrap_or(0) / 1024).to_string();
+        SettingsForm {
+            download_dir: settings.download_dir.display().to_string(),
+            max_connections: settings.max_connections.to_string(),
+            speed_limit: kib(settings.speed_limit),
+            global_speed_limit: kib(settings.global_speed_limit),
+            theme: settings.theme,
+            notify_on_done: settings.notifications.on_done,
+            notify_on_error: settings.notifications.on_error
+        }
+    }
+
+    /// Validates the fields and returns the settings changed from `settings`, keyed by their name in the settings file.
+    ///
+    /// Returns the error of the first invalid field.
+    fn changes(&self, settings : &Settings) -> Result<Vec<(&'static str, Value)>, String> {
+        let mut changes = Vec::new();
+
+        let download_dir = PathBuf::from(self.download_dir.trim());
+        if download_dir != settings.download_dir {
+            if !download_dir.is_dir() {
+                return Err(format!("The directory `{}` doesn't exist", download_dir.display()));
+            }
+            changes.push(("download_dir", Value::from(download_dir.to_string_lossy().into_owned())));
+        }
+
+        let max_connections = self.max_connections.trim().parse::<u8>().ok()
+            .filter(|connections| (1..=16).contains(connections))
+            .ok_or("The number of connections must be between 1 and 16")?;
+        if max_connections != settings.max_connections {
+            changes.push(("max_connections", Value::from(max_connections as i64)));
+        }
+
+        let limits = [("speed_limit_kib", &self.speed_limit, settings.speed_limit), ("global_speed_limit_kib", &self.global_speed_limit, settings.global_speed_limit)];
+        for (key, field, limit) in limits {
+            let kib = field.trim().parse::<u64>().ok()
+                .filter(|kib| *kib <= 10_000_000)
+                .ok_or("The speed limits must be a number of KiB/s, 0 for no limit")?;
+            if Some(kib * 1024).filter(|limit| *limit > 0) != limit {
+                changes.push((key, Value::from(kib as i64)));
+            }
+        }
+
+        if self.theme != settings.theme {
+            changes.push(("theme", Value::from(self.theme.as_str())));
+        }
+        if self.notify_on_done != settings.notifications.on_done {
+            changes.push(("notifications.on_done", Value::from(self.notify_on_done)));
+        }
+        if self.notify_on_error != settings.notifications.on_error {
+            changes.push(("notifications.on_error", Value::from(self.notify_on_error)));
+        }
+        Ok(changes)
+    }
+}
 
 /*
     GUI messages
@@ -90,19 +390,80 @@ pub enum Message {
     StartDownloadButtonPressed(usize),
     ResumeDownloadButtonPressed(usize),
     PauseDownloadButtonPressed(usize),
+    ConfirmTypeButtonPressed(usize),
     CancelDownloadButtonPressed(usize),
+    ExportBundleButtonPressed(usize),
+    HostPauseButtonPressed(usize),
+    CancelConfirmButtonPressed(bool),
+    CancelDismissButtonPressed,
     ModalTextInputOnInput(String),
+    ModalUrlPasted(String),
+    ModalClipboardRead((String, String, Option<String>)),
+    ModalAcceptInvalidCertsToggled(bool),
+    ModalStartAtOnInput(String),
+    ModalFileModeOnInput(String),
+    ModalRefererOnInput(String),
+    ModalSeedPathOnInput(String),
+    ModalOutDirOnInput(String),
+    ModalConnectionsSelected(u8),
+    MenuSelected(MenuAction),
+    StatisticsWeeklyToggled(bool),
+    StatisticsCloseButtonPressed,
+    TimelineButtonPressed(usize),
+    TimelineCloseButtonPressed,
+    SettingsButtonPressed,
+    SettingsDownloadDirOnInput(String),
+    SettingsMaxConnectionsOnInput(String),
+    SettingsSpeedLimitOnInput(String),
+    SettingsGlobalSpeedLimitOnInput(String),
+    SettingsThemeSelected(ThemeChoice),
+    SettingsNotifyOnDoneToggled(bool),
+    SettingsNotifyOnErrorToggled(bool),
+    SettingsSaveButtonPressed,
+    SettingsCancelButtonPressed,
+    ProfileSelected(String),
+    PrioritySelected((usize, Priority)),
+    GrabLinksButtonPressed,
+    LinkExtensionsOnInput(String),
+    LinkPatternOnInput(String),
+    LinkToggled((usize, bool)),
+    LinkSelectAllPressed,
+    LinkQueueButtonPressed,
+    LinkCancelButtonPressed,
+    LinkDepthOnInput(String),
+    LinkSameHostToggled(bool),
+    LinkRespectRobotsToggled(bool),
+    MirrorSiteButtonPressed,
+    SystemThemeChanged(ThemeChoice),
+    QueueDrainChanged(bool),
+    WindowResized((u32, u32)),
+    WindowMoved((i32, i32)),
+    ShutdownRequested,
 
     UpdateDownloadCallback(UpdateDownloadType),
     DownloadInitCallback(DownloadInitHeadType),
-    StartDownloadCallback(Result<bool, String>),
+    BatchInitCallback(BatchInitType),
+    GrabLinksCallback(GrabLinksType),
+    MirrorSiteCallback(MirrorSiteType),
+    BundleInitCallback(BundleInitType),
+    ExportBundleCallback(ExportBundleType),
+    DeltaDownloadCallback(DeltaDownloadType),
+    ExportListCallback(ExportBundleType),
+    StatisticsCallback(StatisticsType),
+    ListInitCallback(ListInitType),
+    StartDownloadCallback(StartDownloadType),
     PauseDownloadCallback(usize),
-    ResumeDownloadCallback(usize)
+    ResumeDownloadCallback(usize),
+    ConfirmTypeCallback(usize),
+    CancelDownloadCallback(CancelDownloadType),
+    HostPauseCallback(HostPauseType),
+    SessionRestoreCallback(Vec<RestoredDownloadType>),
+    ShutdownCallback
 }
 
 impl RustleGUI {
 
-    /// Updates the download progress and status for a specific row.
+    /// Waits for the engine's next progress tick, then reads the download progress and status for a specific row.
     ///
     /// # Arguments
     ///
@@ -116,28 +477,106 @@ impl RustleGUI {
     /// * The current download status.
     /// * The provided `row_id`.
     /// * A cloned `RustleDownloader` instance.
+    /// * The estimated remaining time.
+    /// * The upcoming retry if the download is retrying.
+    /// * Whether the download is paused until the network is back.
+    /// * The HTTP version negotiated with the server.
+    /// * Why the downloaded data was discarded when the download was resumed, if it was.
+    /// * The progress of the file moved from the staging directory, while it's moved.
+    /// * Why the type of the file doesn't match its extension, if it doesn't.
+    /// * Whether the download is paused until the data quota is available again.
     pub async fn update_download(engine : Arc<RustleDownloader>, row_id : usize) -> UpdateDownloadType {
 
         ( 
-        engine.get_progress_vec().await, 
+        engine.next_progress_vec().await, 
         engine.get_status().await, 
         row_id,
-        engine.clone()
+        engine.clone(),
+        engine.get_eta().await,
+        engine.get_retry_info().await,
+        engine.is_paused_offline().await,
+        engine.get_http_version().await.map(|version| format!("{:?}", version)),
+        engine.get_resume_mismatch().await.map(|mismatch| mismatch.to_string()),
+        engine.get_move_progress().await,
+        engine.get_type_mismatch().await.map(|mismatch| mismatch.to_string()),
+        engine.is_paused_quota().await
         )
     }
 
-    /// Starts the download using the provided `RustleDownloader` instance.
+    /// Returns whether a started download is waiting for its start time, a retry or in the queue, or was
+    /// handed a slot but its engine didn't start downloading yet.
     ///
     /// # Arguments
     ///
-    /// * `engine` - A shared Arc reference to the `RustleDownloader` instance.
+    /// * `row_id` - The identifier of the row.
+    /// * `download_status` - The last status read from the row's engine.
+    fn is_starting(&self, row_id : usize, download_status : DownloadStatus) -> bool {
+        match self.queue_manager.state(row_id) {
+            Some(QueueState::Scheduled | QueueState::Queued | QueueState::DelayedRetry) => true,
+            Some(QueueState::Active) => matches!(download_status, DownloadStatus::Idle | DownloadStatus::Cancelled | DownloadStatus::Error),
+            _ => false,
+        }
+    }
+
+    /// Queues the download in the queue manager and waits until it finishes, then runs its hook.
+    ///
+    /// # Arguments
+    ///
+    /// * `queue_manager` - A shared Arc reference to the `QueueManager` owning the download.
+    /// * `row_id` - The identifier of the row being downloaded, i.e. its id in the queue manager.
+    /// * `hooks` - The commands run after the download finishes or fails.
+    /// * `notifications` - The desktop notifications shown when the download finishes or fails.
+    /// * `history` - The history the finished or failed download is recorded in, if enabled.
     ///
     /// # Returns
     ///
-    /// Returns a `Result` indicating whether the download was successfully started (`Ok(true)`)
-    /// or an error message (`Err(String)`).
-    pub async fn start_download(engine : Arc<RustleDownloader>) -> Result<bool, String>{
-        engine.download(false).await
+    /// Returns a tuple containing:
+    /// * The provided `row_id`.
+    /// * A `Result` indicating whether the download was successful (`Ok(true)`)
+    ///   or an error message (`Err(String)`).
+    pub async fn start_download(queue_manager : Arc<QueueManager>, row_id : usize, hooks : Hooks, notifications : Notifications, history : Option<Arc<DownloadHistory>>) -> StartDownloadType {
+        if let Err(e) = queue_manager.start(row_id) {
+            return (row_id, Err(e));
+        }
+        RustleGUI::wait_download(queue_manager, row_id, hooks, notifications, history).await
+    }
+
+    /// Waits until a scheduled download finishes, then runs its hook, notifies it and records it in the history.
+    ///
+    /// # Arguments
+    ///
+    /// * `queue_manager` - A shared Arc reference to the `QueueManager` owning the download.
+    /// * `row_id` - The identifier of the row being downloaded, i.e. its id in the queue manager.
+    /// * `hooks` - The commands run after the download finishes or fails.
+    /// * `notifications` - The desktop notifications shown when the download finishes or fails.
+    /// * `history` - The history the finished or failed download is recorded in, if enabled.
+    ///
+    /// # Returns
+    ///
+    /// Returns the same tuple as `start_download`.
+    pub async fn wait_download(queue_manager : Arc<QueueManager>, row_id : usize, hooks : Hooks, notifications : Notifications, history : Option<Arc<DownloadHistory>>) -> StartDownloadType {
+        let mut result = queue_manager.wait(row_id).await;
+        if let Some(engine) = queue_manager.engine(row_id) {
+            if let Err(e) = hooks.run_for(&engine, &result).await {
+                println!("{}", e);
+            }
+            if let Some(history) = history {
+                if let Err(e) = history.record_for(&engine, &result).await {
+                    println!("{}", e);
+                }
+            }
+            // The row shows the kind of a classified failure, e.g. "Host not found", its message is printed
+            if let (Err(e), Some(kind)) = (result.as_ref(), engine.get_error_kind().await.filter(|kind| *kind != ErrorKind::Other)) {
+                println!("{}", e);
+                result = Err(kind.to_string());
+            }
+            if let Some(context) = HookContext::from_engine(&engine, &result).await {
+                if let Err(e) = notify_download_end(notifications, context).await {
+                    println!("{}", e);
+                }
+            }
+        }
+        (row_id, result)
     }
 
     /// Pauses the download using the provided `RustleDownloader` instance and returns the row ID.
@@ -170,37 +609,398 @@ impl RustleGUI {
         row_id
     }
 
-    /// Initializes a download using the provided URL and directory, returning initialization info.
+    /// Confirms the type of a file that doesn't match its extension and returns the row ID.
+    ///
+    /// # Arguments
+    ///
+    /// * `engine` - A shared Arc reference to the `RustleDownloader` instance.
+    /// * `row_id` - The identifier of the row to confirm.
+    ///
+    /// # Returns
+    ///
+    /// Returns the provided `row_id`.
+    pub async fn confirm_type(engine : Arc<RustleDownloader>, row_id : usize) -> usize {
+        engine.confirm_type().await;
+        row_id
+    }
+
+    /// Pauses all the downloads from a host, or resumes them if the host is paused.
+    ///
+    /// # Arguments
+    ///
+    /// * `queue_manager` - A shared Arc reference to the `QueueManager` owning the downloads.
+    /// * `host` - The host to pause or resume.
+    ///
+    /// # Returns
+    ///
+    /// Returns whether the host is now paused, and the rows that were paused or resumed.
+    pub async fn toggle_host_pause(queue_manager : Arc<QueueManager>, host : String) -> HostPauseType {
+        if queue_manager.is_host_paused(&host) {
+            (false, queue_manager.resume_host(&host).await)
+        } else {
+            (true, queue_manager.pause_host(&host).await)
+        }
+    }
+
+    /// Cancels the download using the provided `RustleDownloader` instance.
+    ///
+    /// # Arguments
+    ///
+    /// * `engine` - A shared Arc reference to the `RustleDownloader` instance.
+    /// * `row_id` - The identifier of the row to cancel.
+    /// * `keep_partial` - Whether the bytes downloaded so far are kept to continue from later.
+    ///
+    /// # Returns
+    ///
+    /// Returns a tuple containing the provided `row_id` and `keep_partial`.
+    pub async fn cancel_download(engine : Arc<RustleDownloader>, row_id : usize, keep_partial : bool) -> CancelDownloadType {
+        engine.cancel(keep_partial).await;
+        (row_id, keep_partial)
+    }
+
+    /// Initializes a download using the provided URL and settings, returning initialization info.
     ///
     /// # Arguments
     ///
     /// * `url` - The URL to download the file from.
-    /// * `dir` - The directory to save the downloaded file.
+    /// * `referer` - The `Referer` of the requests, if any.
+    /// * `settings` - The settings of the download (directory, connections, TLS, ..).
+    /// * `connection_manager` - The connection manager shared by all downloads.
     ///
     /// # Returns
     ///
     /// Returns a `Result` containing the initialization info as a tuple:
     /// * A `DownloadInitHeadType` containing file information.
     /// * A newly created `RustleDownloader` instance.
-    pub async fn init_download(url : String, dir: String) -> DownloadInitHeadType {
-        let download_engine = RustleDownloader::new(4);
-        match download_engine {
-            Ok(mut engine) => {
-                engine.set_url(&url).await?;
-                engine.set_out_dir(&dir).await?;
+    pub async fn init_download(url : String, referer : Option<String>, mut settings : Settings, connection_manager : Arc<ConnectionManager>, protocols : Arc<ProtocolRegistry>) -> DownloadInitHeadType {
+        // A pasted curl command brings its own headers and cookies, e.g. of an authenticated download
+        let curl = if CurlCommand::is_curl(&url) { Some(CurlCommand::parse(&url)?) } else { None };
+        if let Some(curl) = curl.as_ref() {
+            settings.tls.accept_invalid_certs |= curl.insecure;
+        }
 
+        let mut engine = settings.new_engine(connection_manager).await?;
+        engine.set_protocols(protocols).await;
+        engine.set_referer(referer.as_deref()).await?;
+        match curl {
+            Some(curl) => curl.apply(&mut engine).await?,
+            None => {
+                engine.set_url(&url).await?;
                 engine.init().await?;
+            },
+        }
+        settings.enable_debug_logs(&mut engine).await?;
+
+        Ok((engine.get_file_info().await, engine))
+    }
 
-                let h = engine.get_file_info().await;
+    /// Loads a `.rustle` bundle and initializes its download, returning initialization info.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the bundle file.
+    /// * `settings` - The settings of the download (directory, connections, TLS, ..).
+    /// * `connection_manager` - The connection manager shared by all downloads.
+    ///
+    /// # Returns
+    ///
+    /// Returns the same tuple as `init_download` followed by the loaded bundle.
+    pub async fn init_bundle(path : PathBuf, settings : Settings, connection_manager : Arc<ConnectionManager>, protocols : Arc<ProtocolRegistry>) -> BundleInitType {
+        let bundle = DownloadBundle::load(&path)?;
+        let mut engine = settings.new_engine(connection_manager).await?;
+        engine.set_protocols(protocols).await;
+        bundle.apply(&mut engine).await?;
+        settings.enable_debug_logs(&mut engine).await?;
 
-                Ok((h, engine))
+        Ok((engine.get_file_info().await, engine, Box::new(bundle)))
+    }
 
+    /// Exports a finished download as a `.rustle` bundle written next to the downloaded file,
+    /// with the checksum of the file so the receiver's copy is verified. An unfinished download
+    /// is exported as a session bundle holding the bytes received so far, continued where it was
+    /// once opened, e.g. in the terminal interface.
+    ///
+    /// # Arguments
+    ///
+    /// * `engine` - A shared Arc reference to the `RustleDownloader` instance.
+    /// * `url` - The URL the file was downloaded from.
+    ///
+    /// # Returns
+    ///
+    /// Returns the path of the written bundle, or an error message.
+    pub async fn export_bundle(engine : Arc<RustleDownloader>, url : String) -> ExportBundleType {
+        let file_path = engine.get_file_path().await.ok_or("The download isn't initialized")?;
+        if engine.get_status().await != DownloadStatus::Done {
+            let bundle_path = DownloadBundle::path_for(&file_path);
+            DownloadBundle::from_download(&engine).await?.save(&bundle_path)?;
+            return Ok(bundle_path);
+        }
+        // The engine hashed the file while it streamed, otherwise it's read again
+        let sha256 = match engine.get_sha256().await {
+            Some(sha256) => sha256,
+            None => {
+                let hashed_path = file_path.clone();
+                tokio::task::spawn_blocking(move || sha256_file(&hashed_path)).await
+                    .map_err(|e| format!("Couldn't hash the downloaded file, error : {}", e))??
             },
+        };
+
+        let bundle = DownloadBundle {
+            urls: vec![url],
+            file_name: file_path.file_name().map(|name| name.to_string_lossy().into_owned()),
+            sha256: Some(sha256),
+            // The referer isn't a secret, unlike the other headers, the bundle keeps it
+            headers: engine.get_referer().await.map(|referer| (String::from("Referer"), referer)).into_iter().collect(),
+            ..Default::default()
+        };
+        let bundle_path = DownloadBundle::path_for(&file_path);
+        bundle.save(&bundle_path)?;
+        Ok(bundle_path)
+    }
+
+    /// Adds a row for an initialized download, owned by the queue manager, and returns its id.
+    fn add_row(&mut self, url : String, headers : ResponseHeaderInfo, engine : RustleDownloader) -> usize {
+        let id = self.queue_manager.add(engine);
+        let engine = self.queue_manager.engine(id).expect("the download was just added");
+        self.downloads.insert(id,
+            DownloadRowInfo { 
+                file_url: Some(url), 
+                file_name: headers.file_name, 
+                file_size: Some(headers.content_length.unwrap_or(0)), 
+                file_type: headers.content_type, 
+                http_version: None,
+                download_progress: Vec::new(), 
+                eta: None,
+                error: None,
+                retry: None,
+                paused_offline: false,
+                paused_quota: false,
+                restart_reason: None,
+                moving: None,
+                type_warning: None,
+                engine,
+                download_status: DownloadStatus::Idle
+            }
+        );
+        id
+    }
+
+    /// Returns the palette of the theme of the active profile, following the OS if it's `ThemeChoice::System`.
+    fn palette(&self) -> &'static Palette {
+        Palette::of(self.profiles.active().theme, self.system_theme)
+    }
+
+    /// Saves the size and position of the window to the settings file if they changed, they're restored at the next launch.
+    fn save_window_geometry(&mut self) -> Result<(), String> {
+        // The profiles share the geometry, it's written at the top level of the file
+        let saved = self.profiles.active().window;
+        if saved == self.window_geometry {
+            return Ok(());
+        }
+        let path = Settings::default_path()
+            .ok_or_else(|| String::from("Couldn't find the config directory of the settings file"))?;
+        self.window_geometry.entries().into_iter()
+            .try_for_each(|(key, value)| self.profiles.set_default(&path, key, value))
+    }
+
+    /// Returns the commands queueing a download and updating its progress in the GUI.
+    fn start_row(&self, row_i : usize) -> Command<Message> {
+        let engine_arc = self.downloads[&row_i].engine.clone();
+        Command::batch(vec![
+            Command::perform(RustleGUI::start_download(self.queue_manager.clone(), row_i, self.profiles.active().hooks.clone(), self.profiles.active().notifications, self.history.clone())
+                                        , Message::StartDownloadCallback),
+            Command::perform(RustleGUI::update_download(engine_arc, row_i)
+                                        , Message::UpdateDownloadCallback)
+        ])
+    }
+
+    /// Returns the commands scheduling a download at `start_at` and updating its progress in the GUI.
+    fn schedule_row(&self, row_i : usize, start_at : SystemTime) -> Command<Message> {
+        match self.queue_manager.start_at(row_i, start_at) {
+            Ok(()) => Command::batch(vec![
+                Command::perform(RustleGUI::wait_download(self.queue_manager.clone(), row_i, self.profiles.active().hooks.clone(), self.profiles.active().notifications, self.history.clone())
+                                            , Message::StartDownloadCallback),
+                Command::perform(RustleGUI::update_download(self.downloads[&row_i].engine.clone(), row_i)
+                                            , Message::UpdateDownloadCallback)
+            ]),
             Err(e) => {
-                Err(e)
+                println!("{}", e);
+                Command::none()
             },
         }
+    }
+
+    /// Returns the command initializing the next entry of a batch, if any is left.
+    fn init_batch(&self, mut batch : Box<DownloadBatch>) -> Command<Message> {
+        let Some(entry) = batch.entries.pop_front() else { return Command::none() };
+        let connection_manager = self.connection_manager.clone();
+        let protocols = self.protocols.clone();
+        Command::perform(async move {
+            let result = RustleGUI::init_batch_entry(&entry, batch.settings.clone(), connection_manager, protocols).await;
+            (entry, result, batch)
+        }, Message::BatchInitCallback)
+    }
+
+    /// Returns the command initializing the next download of an imported list, if any is left.
+    fn init_list_import(&self, mut import : Box<ListImport>) -> Command<Message> {
+        let Some(entry) = import.entries.pop_front() else { return Command::none() };
+        let connection_manager = self.connection_manager.clone();
+        let protocols = self.protocols.clone();
+        Command::perform(async move {
+            let result = async {
+                let mut engine = import.settings.new_engine(connection_manager).await?;
+                engine.set_protocols(protocols).await;
+                entry.apply(&mut engine).await?;
+                import.settings.enable_debug_logs(&mut engine).await?;
+                Ok((engine.get_file_info().await, engine))
+            }.await;
+            (entry, result, import)
+        }, Message::ListInitCallback)
+    }
 
+    /// Exports the downloads of the queue to a JSON file, see `DownloadList`.
+    ///
+    /// # Arguments
+    ///
+    /// * `queue_manager` - The queue manager owning the downloads.
+    /// * `path` - The path of the written file.
+    ///
+    /// # Returns
+    ///
+    /// Returns the path of the written file, or an error message.
+    pub async fn export_download_list(queue_manager : Arc<QueueManager>, path : PathBuf) -> ExportBundleType {
+        DownloadList::from_queue(&queue_manager).await.save(&path)?;
+        Ok(path)
+    }
+
+    /// Reads the statistics of the download history of the last `STATISTICS_DAYS` days, formatted as lines.
+    ///
+    /// # Arguments
+    ///
+    /// * `history` - The download history, `None` if it's disabled.
+    /// * `data_usage` - The data usage of the downloads, followed by its lines if any.
+    /// * `period` - The length of the periods the downloads are grouped by.
+    pub async fn load_statistics(history : Option<Arc<DownloadHistory>>, data_usage : Option<Arc<DataUsage>>, period : StatsPeriod) -> StatisticsType {
+        let since = SystemTime::now() - Duration::from_secs(STATISTICS_DAYS * 24 * 3600);
+        let mut lines = match history {
+            Some(history) => format_stats(&history.stats(Some(since), period)?, period),
+            None if data_usage.is_some() => vec![String::from("The download history is disabled in the settings")],
+            None => return Err(String::from("The download history is disabled in the settings")),
+        };
+        if let Some(data_usage) = data_usage {
+            lines.extend(format_usage(&data_usage, Some(since)));
+        }
+        Ok(lines)
+    }
+
+    /// Pauses the downloads and saves them to the session journal, so they're restored at the next launch.
+    ///
+    /// # Arguments
+    ///
+    /// * `queue_manager` - The queue manager owning the downloads.
+    /// * `part_files` - Where the bytes received by the downloads are written.
+    pub async fn save_session(queue_manager : Arc<QueueManager>, part_files : PartFileLocation) {
+        let (mut journal, errors) = SessionJournal::shutdown(&queue_manager).await;
+        for e in errors {
+            println!("Couldn't save the download {}", e);
+        }
+        let Some(path) = Settings::journal_path() else { return };
+        if journal.downloads.is_empty() {
+            return;
+        }
+        for e in journal.write_part_files(&part_files) {
+            println!("{}", e);
+        }
+        if let Err(e) = journal.save(&path) {
+            println!("{}", e);
+        }
+    }
+
+    /// Creates the engines continuing the downloads of the session journal, in the order of the journal.
+    ///
+    /// # Arguments
+    ///
+    /// * `journal` - The journal saved when the application last exited.
+    /// * `settings` - The settings the engines are configured with (connections, TLS, ..).
+    /// * `connection_manager` - The connection manager shared by all downloads.
+    /// * `protocols` - The protocols downloading the files.
+    pub async fn restore_session(mut journal : SessionJournal, settings : Settings, connection_manager : Arc<ConnectionManager>, protocols : Arc<ProtocolRegistry>) -> Vec<RestoredDownloadType> {
+        for e in journal.read_part_files() {
+            println!("{}", e);
+        }
+        let mut restored = Vec::new();
+        for entry in journal.downloads {
+            let result = async {
+                let mut engine = settings.restore_engine(&entry, connection_manager.clone()).await?;
+                engine.set_protocols(protocols.clone()).await;
+                settings.enable_debug_logs(&mut engine).await?;
+                Ok((engine.get_file_info().await, engine.get_progress_vec().await, engine))
+            }.await;
+            restored.push((entry, result));
+        }
+        restored
+    }
+
+    /// Fetches a web page and returns its links that can be downloaded.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The URL of the page.
+    /// * `settings` - The settings the page is fetched with (TLS, address family).
+    /// * `protocols` - The protocols fetching the page and downloading the links.
+    pub async fn grab_links(url : String, settings : Settings, protocols : Arc<ProtocolRegistry>) -> GrabLinksType {
+        let (url, transport) = RustleGUI::page_transport(&url, &settings, &protocols)?;
+        let links = fetch_links(transport.as_ref(), &url).await?;
+
+        Ok(links.into_iter().filter(|link| protocols.check_url(&link.url).is_ok()).collect())
+    }
+
+    /// Crawls a site from a web page and returns the files to download, see `crawl()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The URL of the start page.
+    /// * `settings` - The settings the pages are fetched with (TLS, address family).
+    /// * `protocols` - The protocols fetching the pages and downloading the files.
+    /// * `options` - The limits of the crawl.
+    pub async fn mirror_site(url : String, settings : Settings, protocols : Arc<ProtocolRegistry>, options : CrawlOptions) -> MirrorSiteType {
+        let (url, transport) = RustleGUI::page_transport(&url, &settings, &protocols)?;
+        let mut result = crawl(transport.as_ref(), &url, &options).await?;
+        result.files.retain(|entry| protocols.check_url(&entry.url).is_ok());
+
+        Ok(result)
+    }
+
+    /// Updates an older version of a file from a zsync control file, only downloading the blocks
+    /// that changed, see `delta_download()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The URL of the `.zsync` control file.
+    /// * `seed_path` - The path of the older version, empty to download the whole file.
+    /// * `settings` - The settings the file is downloaded with (TLS, address family, output directory).
+    /// * `protocols` - The protocols downloading the file.
+    pub async fn delta_download(url : String, seed_path : String, settings : Settings, protocols : Arc<ProtocolRegistry>) -> DeltaDownloadType {
+        let (url, transport) = RustleGUI::page_transport(&url, &settings, &protocols)?;
+        let seed_path = Some(PathBuf::from(seed_path.trim())).filter(|path| !path.as_os_str().is_empty());
+        delta_download(transport.as_ref(), &url, seed_path.as_deref(), &settings.download_dir).await
+    }
+
+    /// Parses the URL of a web page and creates the transport fetching it.
+    fn page_transport(url : &str, settings : &Settings, protocols : &ProtocolRegistry) -> Result<(Url, Arc<dyn Transport>), String> {
+        let url = Url::parse(url.trim()).map_err(|e| format!("Invalid URL {}, error : {}", url, e))?;
+        let transport = protocols.protocol(&url)?.transport(&settings.client_options())?;
+        Ok((url, transport))
+    }
+
+    /// Initializes the download of a batch entry, returning the same tuple as `init_download`.
+    pub async fn init_batch_entry(entry : &BatchEntry, settings : Settings, connection_manager : Arc<ConnectionManager>, protocols : Arc<ProtocolRegistry>) -> DownloadInitHeadType {
+        let mut engine = settings.new_engine(connection_manager).await?;
+        engine.set_protocols(protocols).await;
+        entry.apply(&mut engine).await?;
+        settings.enable_debug_logs(&mut engine).await?;
+
+        Ok((engine.get_file_info().await, engine))
     }
 }
 
@@ -210,29 +1010,92 @@ impl Application for RustleGUI {
     type Message = Message;
     type Theme = Theme;
     type Executor = iced::executor::Default;
-    type Flags = ();
+    type Flags = GuiFlags;
 
     /// Creates a new `RustleGUI` instance along with an initial `Command`.
     ///
     /// # Arguments
     ///
-    /// * `_flags` - A placeholder argument that is not used in this method.
+    /// * `flags` - The settings profiles and the bundles opened at startup.
     ///
     /// # Returns
     ///
     /// Returns a tuple containing:
     /// * A newly constructed `RustleGUI` instance.
-    /// * An initial `Command` representing no action.
-    fn new(_flags: ()) -> (RustleGUI, Command<Message>) {
+    /// * An initial `Command` initializing the opened bundles.
+    fn new(flags: GuiFlags) -> (RustleGUI, Command<Message>) {
+        let GuiFlags { profiles, bundles } = flags;
+        let connection_manager = Arc::new(profiles.active().new_connection_manager());
+        connection_manager.set_data_usage(profiles.active().open_data_usage().map_err(|e| println!("{}", e)).ok().map(Arc::new));
+        let protocols = Arc::new(ProtocolRegistry::default());
+        let mut commands : Vec<Command<Message>> = bundles.into_iter()
+            .map(|path| Command::perform(RustleGUI::init_bundle(path, profiles.active().clone(), connection_manager.clone(), protocols.clone())
+                                                    , Message::BundleInitCallback))
+            .collect();
+        let queue_manager = QueueManager::new(profiles.active().max_active_downloads)
+                                                    .expect("max_active_downloads is validated to be non-zero");
+        queue_manager.set_max_retries(profiles.active().queue_retries);
+        let history = profiles.active().open_history().unwrap_or_else(|e| {
+            println!("{}", e);
+            None
+        }).map(Arc::new);
+
+        // A drain request left from before the application started, e.g. before a reboot, is removed
+        if let Some(Err(e)) = Settings::drain_path().map(|path| clear_drain(&path)) {
+            println!("{}", e);
+        }
+
+        // The downloads saved when the application last exited are restored once, the journal is deleted
+        let journal = Settings::journal_path().filter(|path| path.exists()).and_then(|path| {
+            let journal = SessionJournal::load(&path).map_err(|e| println!("{}", e)).ok();
+            if let Err(e) = std::fs::remove_file(&path) {
+                println!("Couldn't delete the session journal {}, error : {}", path.display(), e);
+            }
+            journal
+        });
+        // The part files of the previous sessions that aren't restored are deleted
+        for dir in profiles.active().part_file_dirs() {
+            remove_orphan_part_files(&dir, journal.as_ref());
+        }
+        if let Some(journal) = journal {
+            commands.push(Command::perform(
+                RustleGUI::restore_session(journal, profiles.active().clone(), connection_manager.clone(), protocols.clone()),
+                Message::SessionRestoreCallback));
+        }
+        commands.push(Command::perform(system_theme(), Message::SystemThemeChanged));
+        let window_geometry = profiles.active().window;
         (
             Self { 
                 downloads: HashMap::new(),
                 show_modal: false,
                 modal_url : String::from(""),
                 modal_is_loading: false,
-                downloads_counter: 0
+                modal_accept_invalid_certs: false,
+                modal_start_at: String::new(),
+                modal_file_mode: String::new(),
+                modal_referer: String::new(),
+                modal_seed_path: String::new(),
+                modal_out_dir: String::new(),
+                modal_out_dir_error: None,
+                modal_connections: None,
+                modal_failures: Vec::new(),
+                cancel_confirm_row: None,
+                profiles,
+                connection_manager,
+                queue_manager: Arc::new(queue_manager),
+                protocols,
+                link_grabber: None,
+                history,
+                system_theme: ThemeChoice::Light,
+                statistics: None,
+                timeline_row: None,
+                settings_form: None,
+                statistics_period: StatsPeriod::Day,
+                window_geometry,
+                is_draining: false,
+                is_shutting_down: false
             },
-            Command::none()
+            Command::batch(commands)
         )
     }
 
@@ -260,65 +1123,669 @@ impl Application for RustleGUI {
                 self.show_modal = true;
                 Command::none()
             },
-            Message::ModalCancelButtonPressed => {
-                self.show_modal = false;
+            Message::ModalCancelButtonPressed => {
+                self.show_modal = false;
+                self.modal_failures.clear();
+                Command::none()
+            },
+            Message::ModalSubmitButtonPressed => {
+                self.modal_failures.clear();
+                // Resolve the start time now so a time of day refers to when it was submitted
+                match parse_start_time(&self.modal_start_at) {
+                    Ok(start_at) => self.modal_start_at = start_at.map(format_start_time).unwrap_or_default(),
+                    Err(e) => {
+                        println!("{}", e);
+                        return Command::none();
+                    },
+                }
+                let mut settings = self.profiles.active().clone();
+                settings.tls.accept_invalid_certs |= self.modal_accept_invalid_certs;
+                match parse_file_mode(&self.modal_file_mode) {
+                    Ok(mode) => settings.file_permissions.mode = mode.or(settings.file_permissions.mode),
+                    Err(e) => {
+                        println!("{}", e);
+                        return Command::none();
+                    },
+                }
+                let referer = match parse_referer(&self.modal_referer) {
+                    Ok(referer) => referer,
+                    Err(e) => {
+                        println!("{}", e);
+                        return Command::none();
+                    },
+                };
+                // A picked number of connections is opened as is, instead of being tuned up to it
+                if let Some(connections) = self.modal_connections {
+                    settings.max_connections = connections;
+                    settings.auto_connections = false;
+                }
+                // The directory is checked again, it may have been removed since it was typed, and probed for writing once
+                match parse_out_dir(&self.modal_out_dir).and_then(|out_dir| out_dir.map(|dir| check_writable(&dir).map(|_| dir)).transpose()) {
+                    Ok(out_dir) => settings.download_dir = out_dir.unwrap_or(settings.download_dir),
+                    Err(e) => {
+                        self.modal_out_dir_error = Some(e);
+                        return Command::none();
+                    },
+                }
+
+                // An exported download list queues its unfinished downloads again
+                if is_download_list_path(&self.modal_url) {
+                    match DownloadList::load(Path::new(self.modal_url.trim())) {
+                        Ok(list) => {
+                            let entries : VecDeque<DownloadListEntry> = list.unfinished().cloned().collect();
+                            self.show_modal = false;
+                            self.modal_url = String::from("");
+                            self.modal_accept_invalid_certs = false;
+                            self.modal_start_at = String::new();
+                            self.modal_file_mode = String::new();
+                            self.modal_referer = String::new();
+                            self.modal_out_dir = String::new();
+                            self.modal_connections = None;
+                            return self.init_list_import(Box::new(ListImport { entries, settings }));
+                        },
+                        Err(e) => {
+                            println!("{}", e);
+                            return Command::none();
+                        },
+                    }
+                }
+
+                // A zsync control file updates the older version of the file in the background
+                if is_zsync_url(&self.modal_url) {
+                    let command = Command::perform(RustleGUI::delta_download(self.modal_url.clone(), self.modal_seed_path.clone(), settings, self.protocols.clone())
+                                                , Message::DeltaDownloadCallback);
+                    self.show_modal = false;
+                    self.modal_url = String::from("");
+                    self.modal_accept_invalid_certs = false;
+                    self.modal_start_at = String::new();
+                    self.modal_file_mode = String::new();
+                    self.modal_referer = String::new();
+                    self.modal_out_dir = String::new();
+                    self.modal_connections = None;
+                    self.modal_seed_path = String::new();
+                    return command;
+                }
+
+                // A pattern, a list or pasted urls add a batch of downloads, initialized one after the other so
+                // the server isn't flooded with requests
+                let mut failures = None;
+                let entries = if is_list_path(&self.modal_url) {
+                    let path = PathBuf::from(self.modal_url.trim());
+                    BatchImport::load(&path).map(|batch| {
+                        for error in batch.errors {
+                            println!("{}: {}", path.display(), error);
+                        }
+                        batch.entries.into_iter().map(|entry| BatchEntry { referer: referer.clone(), ..entry }).collect()
+                    })
+                } else if let Some(urls) = split_urls(&self.modal_url) {
+                    // The invalid urls are skipped, they're reported with the ones failing to initialize
+                    let mut invalid = Vec::new();
+                    let entries = urls.into_iter()
+                        .filter(|url| match self.protocols.check_url(url) {
+                            Ok(()) => true,
+                            Err(e) => {
+                                invalid.push((url.clone(), e));
+                                false
+                            },
+                        })
+                        .enumerate()
+                        .map(|(i, url)| BatchEntry { referer: referer.clone(), ..BatchEntry::new(i + 1, &url) })
+                        .collect();
+                    failures = Some(invalid);
+                    Ok(entries)
+                } else if !CurlCommand::is_curl(&self.modal_url) && is_url_pattern(self.modal_url.trim()) {
+                    expand_url_pattern(self.modal_url.trim()).map(|urls| {
+                        urls.into_iter().enumerate()
+                            .map(|(i, url)| BatchEntry { referer: referer.clone(), ..BatchEntry::new(i + 1, &url) })
+                            .collect()
+                    })
+                } else {
+                    Ok(Vec::new())
+                };
+                match entries {
+                    Ok(entries) if entries.is_empty() => {},
+                    Ok(entries) => {
+                        let start_at = parse_start_time(&self.modal_start_at).ok().flatten();
+                        self.show_modal = false;
+                        self.modal_url = String::from("");
+                        self.modal_accept_invalid_certs = false;
+                        self.modal_start_at = String::new();
+                        self.modal_file_mode = String::new();
+                        self.modal_referer = String::new();
+                        self.modal_out_dir = String::new();
+                        self.modal_connections = None;
+                        return self.init_batch(Box::new(DownloadBatch { entries: entries.into(), settings, start_at, failures }));
+                    },
+                    Err(e) => {
+                        println!("{}", e);
+                        return Command::none();
+                    },
+                }
+
+                self.modal_is_loading = true;
+                Command::perform (RustleGUI::init_download(self.modal_url.clone(), 
+                                                            referer,
+                                                            settings,
+                                                            self.connection_manager.clone(),
+                                                            self.protocols.clone()), 
+                                Message::DownloadInitCallback)
+            },
+            Message::DownloadInitCallback (res) => {
+                match res {
+                    Ok(pair) => {
+                        self.show_modal = false;
+                        self.modal_is_loading = false;
+                        let mut command = Command::none();
+                        if let Some(headers) = pair.0 {
+                            let id = self.add_row(modal_download_url(&self.modal_url), headers, pair.1);
+
+                            // Schedule the download, then wait for it / Update the gui progress once it starts
+                            if let Ok(Some(start_at)) = parse_start_time(&self.modal_start_at) {
+                                command = self.schedule_row(id, start_at);
+                            }
+                        }
+                        self.modal_url = String::from("");
+                        self.modal_accept_invalid_certs = false;
+                        self.modal_start_at = String::new();
+                        self.modal_file_mode = String::new();
+                        self.modal_referer = String::new();
+                        self.modal_out_dir = String::new();
+                        self.modal_connections = None;
+                        command
+                    },
+                    Err(e) => {
+                        self.modal_is_loading = false;
+                        println!("{}", e);
+                        Command::none()
+                    },
+                }
+            },
+            Message::BatchInitCallback((entry, res, mut batch)) => {
+                // The downloads of a batch are queued right away, unless a start time was given
+                let command = match res {
+                    Ok((Some(headers), engine)) => {
+                        let id = self.add_row(entry.url, headers, engine);
+                        if let Some(file_name) = entry.file_name {
+                            self.downloads.get_mut(&id).expect("the row was just added").file_name = Some(file_name);
+                        }
+                        match batch.start_at {
+                            Some(start_at) => self.schedule_row(id, start_at),
+                            None => self.start_row(id),
+                        }
+                    },
+                    Ok((None, _)) => Command::none(),
+                    Err(e) => {
+                        println!("{} : {}", entry.url, e);
+                        if let Some(failures) = batch.failures.as_mut() {
+                            failures.push((entry.url, e));
+                        }
+                        Command::none()
+                    },
+                };
+                // The pasted urls that couldn't be added are shown in the modal once the others are, ready to be submitted again
+                if batch.entries.is_empty() {
+                    if let Some(failures) = batch.failures.take().filter(|failures| !failures.is_empty()) {
+                        self.modal_url = failures.iter().map(|(url, _)| url.as_str()).collect::<Vec<&str>>().join(" ");
+                        self.modal_failures = failures.into_iter().map(|(url, e)| format!("{} : {}", url, e)).collect();
+                        self.show_modal = true;
+                    }
+                }
+                Command::batch(vec![command, self.init_batch(batch)])
+            },
+            Message::ListInitCallback((entry, res, import)) => {
+                // Downloads that were queued or running are started again, scheduled ones keep their start time
+                let command = match res {
+                    Ok((Some(headers), engine)) => {
+                        let id = self.add_row(entry.url.clone(), headers, engine);
+                        if let Err(e) = self.queue_manager.set_priority(id, entry.priority) {
+                            println!("{}", e);
+                        }
+                        match entry.start_time() {
+                            Some(start_at) => self.schedule_row(id, start_at),
+                            None if entry.should_start() => self.start_row(id),
+                            None => Command::none(),
+                        }
+                    },
+                    Ok((None, _)) => Command::none(),
+                    Err(e) => {
+                        println!("{} : {}", entry.url, e);
+                        Command::none()
+                    },
+                };
+                Command::batch(vec![command, self.init_list_import(import)])
+            },
+            Message::GrabLinksButtonPressed => {
+                self.modal_is_loading = true;
+                let mut settings = self.profiles.active().clone();
+                settings.tls.accept_invalid_certs |= self.modal_accept_invalid_certs;
+                Command::perform(RustleGUI::grab_links(self.modal_url.clone(), settings, self.protocols.clone())
+                                            , Message::GrabLinksCallback)
+            },
+            Message::GrabLinksCallback(res) => {
+                self.modal_is_loading = false;
+                match res {
+                    Ok(links) if links.is_empty() => println!("No links found in {}", self.modal_url.trim()),
+                    Ok(links) => {
+                        self.link_grabber = Some(LinkGrabber {
+                            selected: vec![false; links.len()],
+                            links,
+                            extensions: String::new(),
+                            pattern: String::new(),
+                            accept_invalid_certs: self.modal_accept_invalid_certs,
+                            out_dir: parse_out_dir(&self.modal_out_dir).ok().flatten(),
+                            connections: self.modal_connections,
+                            page_url: self.modal_url.trim().to_string(),
+                            depth: CrawlOptions::default().max_depth.to_string(),
+                            same_host: true,
+                            respect_robots: true,
+                            is_crawling: false
+                        });
+                        self.show_modal = false;
+                        self.modal_url = String::from("");
+                        self.modal_accept_invalid_certs = false;
+                        self.modal_start_at = String::new();
+                        self.modal_file_mode = String::new();
+                        self.modal_referer = String::new();
+                        self.modal_out_dir = String::new();
+                        self.modal_connections = None;
+                    },
+                    Err(e) => println!("{}", e),
+                }
+                Command::none()
+            },
+            Message::LinkExtensionsOnInput(t_str) => {
+                if let Some(grabber) = self.link_grabber.as_mut() {
+                    grabber.extensions = t_str;
+                }
+                Command::none()
+            },
+            Message::LinkPatternOnInput(t_str) => {
+                if let Some(grabber) = self.link_grabber.as_mut() {
+                    grabber.pattern = t_str;
+                }
+                Command::none()
+            },
+            Message::LinkToggled((link_i, checked)) => {
+                if let Some(selected) = self.link_grabber.as_mut().and_then(|grabber| grabber.selected.get_mut(link_i)) {
+                    *selected = checked;
+                }
+                Command::none()
+            },
+            Message::LinkSelectAllPressed => {
+                // Selects the links matching the filter, or unselects them if they all are
+                if let Some(grabber) = self.link_grabber.as_mut() {
+                    let filter = LinkFilter::new(&grabber.extensions, &grabber.pattern).unwrap_or_default();
+                    let matching : Vec<usize> = (0..grabber.links.len()).filter(|i| filter.matches(&grabber.links[*i])).collect();
+                    let select = !matching.iter().all(|i| grabber.selected[*i]);
+                    for i in matching {
+                        grabber.selected[i] = select;
+                    }
+                }
+                Command::none()
+            },
+            Message::LinkQueueButtonPressed => {
+                let Some(grabber) = self.link_grabber.take() else { return Command::none() };
+                let Ok(filter) = LinkFilter::new(&grabber.extensions, &grabber.pattern) else { return Command::none() };
+                let entries : VecDeque<BatchEntry> = grabber.links.into_iter()
+                    .zip(grabber.selected)
+                    .filter(|(link, selected)| *selected && filter.matches(link))
+                    .enumerate()
+                    // The page is the referer of its links, hosts may refuse the requests without it
+                    .map(|(i, (link, _))| BatchEntry { referer: Some(grabber.page_url.clone()), ..BatchEntry::new(i + 1, &link.url) })
+                    .collect();
+                let mut settings = self.profiles.active().clone();
+                settings.tls.accept_invalid_certs |= grabber.accept_invalid_certs;
+                settings.download_dir = grabber.out_dir.unwrap_or(settings.download_dir);
+                if let Some(connections) = grabber.connections {
+                    settings.max_connections = connections;
+                    settings.auto_connections = false;
+                }
+                self.init_batch(Box::new(DownloadBatch { entries, settings, start_at: None, failures: None }))
+            },
+            Message::LinkCancelButtonPressed => {
+                self.link_grabber = None;
+                Command::none()
+            },
+            Message::LinkDepthOnInput(t_str) => {
+                if let Some(grabber) = self.link_grabber.as_mut() {
+                    grabber.depth = t_str;
+                }
+                Command::none()
+            },
+            Message::LinkSameHostToggled(same_host) => {
+                if let Some(grabber) = self.link_grabber.as_mut() {
+                    grabber.same_host = same_host;
+                }
+                Command::none()
+            },
+            Message::LinkRespectRobotsToggled(respect_robots) => {
+                if let Some(grabber) = self.link_grabber.as_mut() {
+                    grabber.respect_robots = respect_robots;
+                }
+                Command::none()
+            },
+            Message::MirrorSiteButtonPressed => {
+                let Some(grabber) = self.link_grabber.as_mut() else { return Command::none() };
+                let (Ok(filter), Ok(max_depth)) = (LinkFilter::new(&grabber.extensions, &grabber.pattern), grabber.depth.trim().parse()) else {
+                    return Command::none()
+                };
+                grabber.is_crawling = true;
+                let options = CrawlOptions { max_depth, same_host: grabber.same_host, respect_robots: grabber.respect_robots, filter, ..Default::default() };
+                let mut settings = self.profiles.active().clone();
+                settings.tls.accept_invalid_certs |= grabber.accept_invalid_certs;
+                Command::perform(RustleGUI::mirror_site(grabber.page_url.clone(), settings, self.protocols.clone(), options)
+                                            , Message::MirrorSiteCallback)
+            },
+            Message::MirrorSiteCallback(res) => {
+                match res {
+                    Ok(result) => {
+                        let Some(grabber) = self.link_grabber.take() else { return Command::none() };
+                        for e in result.errors {
+                            println!("{}", e);
+                        }
+                        println!("Mirroring {} files of {} pages from {}, {} urls disallowed by robots.txt",
+                            result.files.len(), result.pages, grabber.page_url, result.disallowed);
+                        let mut settings = self.profiles.active().clone();
+                        settings.tls.accept_invalid_certs |= grabber.accept_invalid_certs;
+                        settings.download_dir = grabber.out_dir.unwrap_or(settings.download_dir);
+                        if let Some(connections) = grabber.connections {
+                            settings.max_connections = connections;
+                            settings.auto_connections = false;
+                        }
+                        self.init_batch(Box::new(DownloadBatch { entries: result.files.into(), settings, start_at: None, failures: None }))
+                    },
+                    Err(e) => {
+                        if let Some(grabber) = self.link_grabber.as_mut() {
+                            grabber.is_crawling = false;
+                        }
+                        println!("{}", e);
+                        Command::none()
+                    },
+                }
+            },
+            Message::BundleInitCallback(res) => {
+                match res {
+                    // Bundles are queued right away, as if added and started by the user
+                    Ok((Some(headers), engine, bundle)) => {
+                        let id = self.add_row(bundle.urls[0].clone(), headers, engine);
+                        if let Some(file_name) = bundle.file_name {
+                            self.downloads.get_mut(&id).expect("the row was just added").file_name = Some(file_name);
+                        }
+                        self.start_row(id)
+                    },
+                    Ok((None, _, _)) => Command::none(),
+                    Err(e) => {
+                        println!("{}", e);
+                        Command::none()
+                    },
+                }
+            },
+            Message::ExportBundleButtonPressed(row_i) => {
+                let row = &self.downloads[&row_i];
+                Command::perform(RustleGUI::export_bundle(row.engine.clone(), row.file_url.clone().unwrap_or_default())
+                                            , Message::ExportBundleCallback)
+            },
+            Message::ExportBundleCallback(res) => {
+                match res {
+                    Ok(path) => println!("Exported the download to {}", path.display()),
+                    Err(e) => println!("{}", e),
+                }
+                Command::none()
+            },
+            Message::MenuSelected(action) => {
+                let path = self.profiles.active().download_dir.join(DOWNLOAD_LIST_FILE_NAME);
+                match action {
+                    MenuAction::ExportList => Command::perform(RustleGUI::export_download_list(self.queue_manager.clone(), path)
+                                                , Message::ExportListCallback),
+                    MenuAction::Statistics => Command::perform(RustleGUI::load_statistics(self.history.clone(), self.connection_manager.data_usage(), self.statistics_period)
+                                                , Message::StatisticsCallback),
+                    MenuAction::ImportList => {
+                        // The list is picked in the Add dialog, filled with the default export path
+                        self.modal_url = path.display().to_string();
+                        self.show_modal = true;
+                        Command::none()
+                    },
+                    MenuAction::SwitchTheme => {
+                        // The theme is saved to the active profile of the settings file
+                        // A theme following the OS is switched to the opposite of its current color scheme
+                        let theme = match self.palette() == &DARK_PALETTE {
+                            true => ThemeChoice::Light,
+                            false => ThemeChoice::Dark,
+                        };
+                        let saved = Settings::default_path()
+                            .ok_or_else(|| String::from("Couldn't find the config directory of the settings file"))
+                            .and_then(|path| self.profiles.set(&path, "theme", Value::from(theme.as_str())));
+                        if let Err(e) = saved {
+                            println!("{}", e);
+                        }
+                        Command::none()
+                    },
+                }
+            },
+            Message::StatisticsCallback(res) => {
+                match res {
+                    Ok(lines) => self.statistics = Some(lines),
+                    Err(e) => println!("{}", e),
+                }
+                Command::none()
+            },
+            Message::StatisticsWeeklyToggled(weekly) => {
+                self.statistics_period = if weekly { StatsPeriod::Week } else { StatsPeriod::Day };
+                Command::perform(RustleGUI::load_statistics(self.history.clone(), self.connection_manager.data_usage(), self.statistics_period)
+                                            , Message::StatisticsCallback)
+            },
+            Message::StatisticsCloseButtonPressed => {
+                self.statistics = None;
+                Command::none()
+            },
+            Message::WindowResized((width, height)) => {
+                self.window_geometry.width = width;
+                self.window_geometry.height = height;
+                Command::none()
+            },
+            Message::WindowMoved(position) => {
+                self.window_geometry.position = Some(position);
+                Command::none()
+            },
+            Message::SystemThemeChanged(theme) => {
+                self.system_theme = theme;
+                Command::none()
+            },
+            Message::QueueDrainChanged(draining) => {
+                self.is_draining = draining;
+                Command::none()
+            },
+            Message::TimelineButtonPressed(row_i) => {
+                self.timeline_row = Some(row_i);
+                Command::none()
+            },
+            Message::TimelineCloseButtonPressed => {
+                self.timeline_row = None;
+                Command::none()
+            },
+            Message::SettingsButtonPressed => {
+                self.settings_form = Some(SettingsForm::new(self.profiles.active()));
+                Command::none()
+            },
+            Message::SettingsDownloadDirOnInput(t_str) => {
+                if let Some(form) = self.settings_form.as_mut() {
+                    form.download_dir = t_str;
+                }
+                Command::none()
+            },
+            Message::SettingsMaxConnectionsOnInput(t_str) => {
+                if let Some(form) = self.settings_form.as_mut() {
+                    form.max_connections = t_str;
+                }
                 Command::none()
             },
-            Message::ModalSubmitButtonPressed => {
-                self.modal_is_loading = true;
-                Command::perform (RustleGUI::init_download(self.modal_url.clone(), String::from("./")), Message::DownloadInitCallback)
+            Message::SettingsSpeedLimitOnInput(t_str) => {
+                if let Some(form) = self.settings_form.as_mut() {
+                    form.speed_limit = t_str;
+                }
+                Command::none()
             },
-            Message::DownloadInitCallback (res) => {
-                match res {
-                    Ok(pair) => {
-                        self.show_modal = false;
-                        self.modal_is_loading = false;
-                        self.modal_url = String::from("");
-                        if let Some(headers) = pair.0 {
-                            let e = pair.1;
-                            self.downloads.insert(self.downloads_counter,
-                                DownloadRowInfo { 
-                                    file_url: Some(self.modal_url.clone()), 
-                                    file_name: headers.file_name, 
-                                    file_size: Some(headers.content_length.unwrap_or(0)), 
-                                    file_type: headers.content_type, 
-                                    download_progress: Vec::new(), 
-                                    error: None,
-                                    engine: Arc::new(e),
-                                    download_status: DownloadStatus::Idle
-                                }
-                            );
-                            self.downloads_counter+=1;
+            Message::SettingsGlobalSpeedLimitOnInput(t_str) => {
+                if let Some(form) = self.settings_form.as_mut() {
+                    form.global_speed_limit = t_str;
+                }
+                Command::none()
+            },
+            Message::SettingsThemeSelected(theme) => {
+                if let Some(form) = self.settings_form.as_mut() {
+                    form.theme = theme;
+                }
+                Command::none()
+            },
+            Message::SettingsNotifyOnDoneToggled(notify) => {
+                if let Some(form) = self.settings_form.as_mut() {
+                    form.notify_on_done = notify;
+                }
+                Command::none()
+            },
+            Message::SettingsNotifyOnErrorToggled(notify) => {
+                if let Some(form) = self.settings_form.as_mut() {
+                    form.notify_on_error = notify;
+                }
+                Command::none()
+            },
+            Message::SettingsSaveButtonPressed => {
+                let Some(form) = self.settings_form.take() else { return Command::none() };
+                // The changed settings are saved to the active profile of the settings file, new downloads use them
+                let saved = form.changes(self.profiles.active())
+                    .and_then(|changes| {
+                        let path = Settings::default_path()
+                            .ok_or_else(|| String::from("Couldn't find the config directory of the settings file"))?;
+                        changes.into_iter().try_for_each(|(key, value)| self.profiles.set(&path, key, value))
+                    });
+                match saved {
+                    Ok(()) => {
+                        // The total speed limit is shared by the running downloads, it applies right away
+                        if let Err(e) = self.connection_manager.set_speed_limit(self.profiles.active().global_speed_limit) {
+                            println!("{}", e);
                         }
-                        Command::none()
                     },
                     Err(e) => {
-                        self.modal_is_loading = false;
                         println!("{}", e);
-                        Command::none()
+                        self.settings_form = Some(form);
                     },
                 }
+                Command::none()
+            },
+            Message::SettingsCancelButtonPressed => {
+                self.settings_form = None;
+                Command::none()
+            },
+            Message::ExportListCallback(res) => {
+                match res {
+                    Ok(path) => println!("Exported the download list to {}", path.display()),
+                    Err(e) => println!("{}", e),
+                }
+                Command::none()
+            },
+            Message::DeltaDownloadCallback(res) => {
+                match res {
+                    Ok(stats) => println!("Updated {} ({}), reused {} and downloaded {}", stats.path.display(), format_file_size(stats.length),
+                                                format_file_size(stats.reused), format_file_size(stats.downloaded)),
+                    Err(e) => println!("{}", e),
+                }
+                Command::none()
             },
             Message::ModalTextInputOnInput(t_str) => {
                 self.modal_url = t_str;
                 Command::none()
             },
-            Message::StartDownloadCallback(_res) => {
+            Message::ModalUrlPasted(t_str) => {
+                // The text input drops the line breaks of the pasted text, the clipboard is read again to keep its lines apart
+                let text = std::mem::replace(&mut self.modal_url, t_str.clone());
+                clipboard::read(move |pasted| Message::ModalClipboardRead((text.clone(), t_str.clone(), pasted)))
+            },
+            Message::ModalClipboardRead((before, after, pasted)) => {
+                // Skipped if the text was edited since the paste
+                if self.modal_url == after {
+                    if let Some(joined) = pasted.and_then(|pasted| join_pasted_lines(&before, &after, &pasted)) {
+                        self.modal_url = joined;
+                    }
+                }
+                Command::none()
+            },
+            Message::ModalAcceptInvalidCertsToggled(accept) => {
+                self.modal_accept_invalid_certs = accept;
+                Command::none()
+            },
+            Message::ModalStartAtOnInput(t_str) => {
+                self.modal_start_at = t_str;
+                Command::none()
+            },
+            Message::ModalFileModeOnInput(t_str) => {
+                self.modal_file_mode = t_str;
+                Command::none()
+            },
+            Message::ModalRefererOnInput(t_str) => {
+                self.modal_referer = t_str;
+                Command::none()
+            },
+            Message::ModalSeedPathOnInput(t_str) => {
+                self.modal_seed_path = t_str;
+                Command::none()
+            },
+            Message::ModalOutDirOnInput(t_str) => {
+                self.modal_out_dir_error = parse_out_dir(&t_str).err();
+                self.modal_out_dir = t_str;
+                Command::none()
+            },
+            Message::ModalConnectionsSelected(connections) => {
+                self.modal_connections = Some(connections);
+                Command::none()
+            },
+            Message::ProfileSelected(name) => {
+                match self.profiles.select(&name) {
+                    Ok(settings) => {
+                        // The manager is shared, running downloads pick up the profile's limits too
+                        if let Some(data_usage) = self.connection_manager.data_usage() {
+                            data_usage.set_quota(settings.data_quota());
+                        }
+                        if let Err(e) = self.connection_manager.set_per_host_limit(settings.per_host_connections)
+                            .and_then(|_| self.connection_manager.set_total_limit(settings.total_connections))
+                            .and_then(|_| self.connection_manager.set_speed_limit(settings.global_speed_limit))
+                            .and_then(|_| self.connection_manager.set_memory_budget(settings.max_memory)) {
+                            println!("{}", e);
+                        }
+                        // The queue is shared, its limits apply right away
+                        let _ = self.queue_manager.set_max_active(settings.max_active_downloads);
+                        self.queue_manager.set_max_retries(settings.queue_retries);
+                    },
+                    Err(e) => {
+                        println!("{}", e);
+                    },
+                }
+                Command::none()
+            },
+            Message::PrioritySelected((row_i, priority)) => {
+                // Reorders the queue, running downloads aren't affected
+                if let Err(e) = self.queue_manager.set_priority(row_i, priority) {
+                    println!("{}", e);
+                }
+                Command::none()
+            },
+            Message::StartDownloadCallback((row_id, res)) => {
                 // Download callback after it's done
+                if let (Err(e), Some(row)) = (res, self.downloads.get_mut(&row_id)) {
+                    row.error = Some(e);
+                    row.download_status = DownloadStatus::Error;
+                }
                 Command::none()
             },
             Message::StartDownloadButtonPressed(row_i) => {
-                let engine_arc = self.downloads[&row_i].engine.clone();
-
-                // Fire up two commands to start the download / Update the gui progress
-                let mut commands : Vec<Command<Message>> = Vec::new();
-                commands.push(Command::perform(RustleGUI::start_download(engine_arc.clone())
-                                                , Message::StartDownloadCallback));
-
-                commands.push(Command::perform(RustleGUI::update_download(engine_arc, row_i)
-                                                , Message::UpdateDownloadCallback));
-
-                Command::batch(commands)
+                // A scheduled download, or one waiting for a retry, is already waited for, just queue it now
+                if matches!(self.queue_manager.state(row_i), Some(QueueState::Scheduled | QueueState::DelayedRetry)) {
+                    if let Err(e) = self.queue_manager.start(row_i) {
+                        println!("{}", e);
+                    }
+                    return Command::none();
+                }
+                // Fire up two commands to queue the download / Update the gui progress
+                self.start_row(row_i)
 
             },
             Message::UpdateDownloadCallback(update_pairs) => {
@@ -326,20 +1793,46 @@ impl Application for RustleGUI {
                 let download_status = update_pairs.1;
                 let row_id = update_pairs.2;
                 let engine = update_pairs.3;
+                let eta = update_pairs.4;
+                let retry = update_pairs.5;
+                let paused_offline = update_pairs.6;
+                let http_version = update_pairs.7;
+                let restart_reason = update_pairs.8;
+                let moving = update_pairs.9;
+                let type_warning = update_pairs.10;
+                let paused_quota = update_pairs.11;
 
                 match self.downloads.get_mut(&row_id) {
                     Some(row) => {
                         // update gui progress bar
                         row.download_progress = update_progress;
+                        row.eta = eta;
+                        row.retry = retry;
+                        row.paused_offline = paused_offline;
+                        row.paused_quota = paused_quota;
+                        row.restart_reason = restart_reason;
+                        row.moving = moving;
+                        row.type_warning = type_warning;
+                        row.http_version = http_version.or(row.http_version.take());
                         // update row download status
                         row.download_status = download_status;
 
                         match download_status {
+                            // Keep polling while the download waits in the queue
+                            _ if self.is_starting(row_id, download_status) => {
+                                Command::perform(RustleGUI::update_download(engine,  row_id), Message::UpdateDownloadCallback)
+                            }
                             DownloadStatus::Done => {Command::none()},
                             DownloadStatus::Error=> {/* To Do */ Command::none()},
                             DownloadStatus::Idle => {Command::none()}
+                            // Keep polling a download paused for the network or the quota, it's resumed once it's back
+                            DownloadStatus::Paused if paused_offline || paused_quota => {
+                                Command::perform(RustleGUI::update_download(engine,  row_id), Message::UpdateDownloadCallback)
+                            }
                             DownloadStatus::Paused => {Command::none()}
-                            DownloadStatus::Downloading => {
+                            DownloadStatus::Cancelled => {Command::none()}
+                            DownloadStatus::Warning => {Command::none()}
+                            DownloadStatus::Downloading | DownloadStatus::Retrying => {
                                 Command::perform(RustleGUI::update_download(engine,  row_id), Message::UpdateDownloadCallback)
                             }
                         }
@@ -359,21 +1852,47 @@ impl Application for RustleGUI {
                 // println!("Resume download pressed");
                 let engine = self.downloads[&row_i].engine.clone();
 
-                // Fire up two commands to resume the download / Update the gui progress
-                let mut commands : Vec<Command<Message>> = Vec::new();
-
-                commands.push(Command::perform(RustleGUI::resume_download(engine.clone(), row_i), Message::ResumeDownloadCallback));
+                // A retrying download, or one paused for the network or the quota, is still polled, only a paused one needs its updates restarted
+                let row = &self.downloads[&row_i];
+                if row.download_status == DownloadStatus::Retrying || row.paused_offline || row.paused_quota {
+                    return Command::perform(RustleGUI::resume_download(engine, row_i), Message::ResumeDownloadCallback);
+                }
 
-                commands.push(Command::perform(RustleGUI::update_download(engine, row_i)
-                                                , Message::UpdateDownloadCallback));
+                // Fire up two commands to resume the download / Update the gui progress
+                let commands : Vec<Command<Message>> = vec![
+                    Command::perform(RustleGUI::resume_download(engine.clone(), row_i), Message::ResumeDownloadCallback),
+                    Command::perform(RustleGUI::update_download(engine, row_i)
+                                                , Message::UpdateDownloadCallback)
+                ];
 
                 Command::batch(commands)
             },
             Message::CancelDownloadButtonPressed(row_i) => {
-                self.downloads.remove(&row_i);
+                // Ask whether to keep the partial data only if something was downloaded
+                match self.downloads.get(&row_i).map(|row| row.download_status) {
+                    Some(DownloadStatus::Downloading) | Some(DownloadStatus::Retrying) | Some(DownloadStatus::Paused) | Some(DownloadStatus::Cancelled) => {
+                        self.cancel_confirm_row = Some(row_i);
+                    },
+                    _ => {
+                        self.downloads.remove(&row_i);
+                        self.queue_manager.remove(row_i);
+                    },
+                }
 
                 Command::none()
             },
+            Message::CancelConfirmButtonPressed(keep_partial) => {
+                match self.cancel_confirm_row.take().and_then(|row_i| self.downloads.get(&row_i).map(|row| (row_i, row.engine.clone()))) {
+                    Some((row_i, engine)) => {
+                        Command::perform(RustleGUI::cancel_download(engine, row_i, keep_partial), Message::CancelDownloadCallback)
+                    },
+                    None => {Command::none()},
+                }
+            },
+            Message::CancelDismissButtonPressed => {
+                self.cancel_confirm_row = None;
+                Command::none()
+            },
             Message::PauseDownloadCallback(row_i) => {
                 match self.downloads.get_mut(&row_i) {
                     Some(row) => {
@@ -383,6 +1902,22 @@ impl Application for RustleGUI {
                     None => {Command::none()},
                 }
             },
+            Message::ConfirmTypeButtonPressed(row_i) => {
+                let engine = self.downloads[&row_i].engine.clone();
+
+                Command::perform(RustleGUI::confirm_type(engine, row_i), Message::ConfirmTypeCallback)
+            },
+            Message::ConfirmTypeCallback(row_i) => {
+                // The type is confirmed, the download is queued again and transfers the file
+                match self.downloads.get_mut(&row_i) {
+                    Some(row) => {
+                        row.type_warning = None;
+                        row.download_status = DownloadStatus::Idle;
+                        self.start_row(row_i)
+                    },
+                    None => {Command::none()},
+                }
+            },
             Message::ResumeDownloadCallback(row_i) => {
                 match self.downloads.get_mut(&row_i) {
                     Some(row) => {
@@ -391,16 +1926,142 @@ impl Application for RustleGUI {
                     },
                     None => {Command::none()},
                 }
+            },
+            Message::HostPauseButtonPressed(row_i) => {
+                match self.queue_manager.host(row_i) {
+                    Some(host) => Command::perform(RustleGUI::toggle_host_pause(self.queue_manager.clone(), host), Message::HostPauseCallback),
+                    None => Command::none(),
+                }
+            },
+            Message::HostPauseCallback((paused, rows)) => {
+                // The paused rows stop polling on their own, the resumed ones poll again
+                let mut commands = Vec::new();
+                for row_i in rows {
+                    if let Some(row) = self.downloads.get_mut(&row_i) {
+                        if paused {
+                            row.download_status = DownloadStatus::Paused;
+                        } else {
+                            row.download_status = DownloadStatus::Downloading;
+                            commands.push(Command::perform(RustleGUI::update_download(row.engine.clone(), row_i), Message::UpdateDownloadCallback));
+                        }
+                    }
+                }
+                Command::batch(commands)
+            },
+            Message::CancelDownloadCallback((row_i, keep_partial)) => {
+                // A cancelled row keeping its partial data can be started again from it
+                if keep_partial {
+                    if let Some(row) = self.downloads.get_mut(&row_i) {
+                        row.download_status = DownloadStatus::Cancelled;
+                    }
+                } else {
+                    self.downloads.remove(&row_i);
+                    self.queue_manager.remove(row_i);
+                }
+                Command::none()
+            },
+            Message::SessionRestoreCallback(restored) => {
+                // Downloads that were running or queued start again, the paused ones wait for the user
+                let mut commands = Vec::new();
+                for (entry, result) in restored {
+                    let (headers, progress, engine) = match result {
+                        Ok((Some(headers), progress, engine)) => (headers, progress, engine),
+                        Ok((None, _, _)) => continue,
+                        Err(e) => {
+                            println!("Couldn't restore the download of {}, error : {}", entry.token.url, e);
+                            continue;
+                        },
+                    };
+                    let row_i = self.add_row(entry.token.url.clone(), headers, engine);
+                    if let Some(row) = self.downloads.get_mut(&row_i) {
+                        row.download_progress = progress;
+                    }
+                    if let Err(e) = self.queue_manager.set_priority(row_i, entry.priority) {
+                        println!("{}", e);
+                    }
+                    if let Some(start_at) = entry.start_time() {
+                        commands.push(self.schedule_row(row_i, start_at));
+                    } else if entry.resume {
+                        commands.push(self.start_row(row_i));
+                    }
+                }
+                Command::batch(commands)
+            },
+            Message::ShutdownRequested => {
+                // Closing the window or terminating the process saves the downloads first, once
+                if self.is_shutting_down {
+                    return Command::none();
+                }
+                self.is_shutting_down = true;
+                if let Err(e) = self.save_window_geometry() {
+                    println!("{}", e);
+                }
+                Command::perform(RustleGUI::save_session(self.queue_manager.clone(), self.profiles.active().part_files.clone()), |_| Message::ShutdownCallback)
+            },
+            Message::ShutdownCallback => {
+                // The downloads are saved, a `rustle queue drain --wait` waiting for them can return
+                if let Some(Err(e)) = Settings::drain_path().map(|path| release_drain(&path)) {
+                    println!("{}", e);
+                }
+                window::close()
             }
         }
     }
 
+    /// Returns the theme of the active profile, built from its palette.
+    fn theme(&self) -> Theme {
+        self.palette().theme()
+    }
+
+    /// Returns the subscriptions saving the downloads when the window is closed or the process terminated.
+    fn subscription(&self) -> Subscription<Message> {
+        let mut subscriptions = vec![
+            subscription::events_with(|event, _status| match event {
+                Event::Window(window::Event::CloseRequested) => Some(Message::ShutdownRequested),
+                Event::Window(window::Event::Resized { width, height }) => Some(Message::WindowResized((width, height))),
+                Event::Window(window::Event::Moved { x, y }) => Some(Message::WindowMoved((x, y))),
+                _ => None,
+            }),
+            subscription::unfold("termination", (), |_| async {
+                termination_signal().await;
+                (Message::ShutdownRequested, ())
+            }),
+        ];
+        // The OS doesn't tell when its color scheme changes, it's read again periodically
+        if self.profiles.active().theme == ThemeChoice::System {
+            subscriptions.push(subscription::unfold("system theme", (), |_| async {
+                tokio::time::sleep(SYSTEM_THEME_INTERVAL).await;
+                (Message::SystemThemeChanged(system_theme().await), ())
+            }));
+        }
+        // The drain requested with `rustle queue drain` is checked periodically, the queue drains while it exists
+        if let Some(path) = Settings::drain_path() {
+            let queue_manager = self.queue_manager.clone();
+            subscriptions.push(subscription::unfold("queue drain", self.is_draining, move |draining| {
+                let (path, queue_manager) = (path.clone(), queue_manager.clone());
+                async move {
+                    loop {
+                        tokio::time::sleep(DRAIN_POLL_INTERVAL).await;
+                        match sync_drain(&path, &queue_manager).await {
+                            Ok(changed) if changed != draining => return (Message::QueueDrainChanged(changed), changed),
+                            Ok(_) => {},
+                            Err(e) => println!("{}", e),
+                        }
+                    }
+                }
+            }));
+        }
+        Subscription::batch(subscriptions)
+    }
+
     /// Generates the GUI view based on the current state of `RustleGUI`.
     ///
     /// # Returns
     ///
     /// Returns an `Element` representing the GUI's user interface.
-    fn view(&self) -> Element<Message> {
+    fn view(&self) -> Element<'_, Message> {
+        let palette = self.palette();
+
         /*
             GUI Elements
          */
@@ -418,9 +2079,28 @@ impl Application for RustleGUI {
                 Column::new().push(
                     // 1st row
                     Row::new()
-                    .push(badge(row.file_name.clone().unwrap_or(String::from("Unknown")), BadgeStyles::Primary))    
-                    .push(badge(format_file_size(row.file_size.clone().unwrap_or(0)), BadgeStyles::Secondary))
-                    .push(badge(row.file_type.clone().unwrap_or(String::from("Unknown")), BadgeStyles::Info))
+                    .push(badge(row.file_name.clone().or(row.file_url.clone()).unwrap_or(String::from("Unknown")), BadgeStyles::Primary))    
+                    .push(badge(format_file_size(row.file_size.unwrap_or(0)), BadgeStyles::Secondary))
+                    .push(badge(row.file_type_label(), BadgeStyles::Info))
+                    .push(horizontal_space(Length::Fill))
+                    .push( // pauses or resumes all the downloads from the row's host
+                        match self.queue_manager.host(*key) {
+                            Some(host) if self.queue_manager.is_host_paused(&host) => {
+                                button(Text::new(format!("Resume {}", host)), Some(Message::HostPauseButtonPressed(*key)), play_submit_button_style(palette))
+                            },
+                            Some(host) => {
+                                button(Text::new(format!("Pause {}", host)), Some(Message::HostPauseButtonPressed(*key)), pause_button_style(palette))
+                            },
+                            None => {
+                                button(Text::new("Unknown host"), None, pause_button_style(palette))
+                            }
+                        }
+                    )
+                    .push( // priority selector
+                        PickList::new(&Priority::ALL[..],
+                                      self.queue_manager.priority(*key),
+                                      |priority| Message::PrioritySelected((*key, priority)))
+                    )
                     .spacing(10)
                     .padding(10)
                 ).push(
@@ -428,14 +2108,14 @@ impl Application for RustleGUI {
                     Row::new()
                     .push( // progress bar
                         match row.download_status {
-                            DownloadStatus::Paused => {
-                                progress_bar(row.get_total_download_progress(), paused_pb_style())
+                            DownloadStatus::Paused | DownloadStatus::Retrying => {
+                                progress_bar(row.get_total_download_progress(), paused_pb_style(palette))
                             },
                             DownloadStatus::Downloading => {
-                                progress_bar(row.get_total_download_progress(), downloading_pb_style())
+                                progress_bar(row.get_total_download_progress(), downloading_pb_style(palette))
                             },
                             DownloadStatus::Done => {
-                                progress_bar(row.get_total_download_progress(),done_pb_style())
+                                progress_bar(row.get_total_download_progress(),done_pb_style(palette))
                             }
                             _ => {
                                 progress_bar(row.get_total_download_progress(), theme::ProgressBar::Danger)
@@ -444,35 +2124,92 @@ impl Application for RustleGUI {
                     )
                     .push( // badge progress status
                         match row.download_status {
+                            _ if self.queue_manager.state(*key) == Some(QueueState::DelayedRetry) => {
+                                badge(format!("Failed, retrying at {} ({}/{})",
+                                    self.queue_manager.start_time(*key).map(format_start_time).unwrap_or_default(),
+                                    self.queue_manager.retries(*key).unwrap_or(0),
+                                    self.queue_manager.max_retries()
+                                ), BadgeStyles::Warning)
+                            },
+                            _ if self.queue_manager.state(*key) == Some(QueueState::Scheduled) => {
+                                badge(format!("Scheduled {}", self.queue_manager.start_time(*key).map(format_start_time).unwrap_or_default()), BadgeStyles::Dark)
+                            },
+                            _ if self.queue_manager.state(*key) == Some(QueueState::Queued) => {
+                                let host_paused = self.queue_manager.host(*key).is_some_and(|host| self.queue_manager.is_host_paused(&host));
+                                badge(format!("Queued #{}{}",
+                                    self.queue_manager.queue_position(*key).map_or(0, |position| position + 1),
+                                    if host_paused { ", host paused" } else { "" }
+                                ), BadgeStyles::Dark)
+                            },
                             DownloadStatus::Done => {
                                 badge(String::from("Done"), BadgeStyles::Success)
                             },
+                            DownloadStatus::Paused if row.paused_offline => {
+                                badge(String::from("Waiting for the network"), BadgeStyles::Warning)
+                            },
+                            DownloadStatus::Paused if row.paused_quota => {
+                                badge(String::from("Data quota reached"), BadgeStyles::Warning)
+                            },
                             DownloadStatus::Paused => {
                                 badge(String::from("Paused"), BadgeStyles::Dark)
                             },
+                            DownloadStatus::Cancelled => {
+                                badge(String::from("Cancelled"), BadgeStyles::Dark)
+                            },
                             DownloadStatus::Error => {
-                                badge(String::from("Error"), BadgeStyles::Danger)
+                                badge(row.error.clone().unwrap_or(String::from("Error")), BadgeStyles::Danger)
+                            },
+                            // Play downloads the file anyway, cancel removes it
+                            DownloadStatus::Warning => {
+                                badge(format!("{}, download it anyway?", row.type_warning.clone().unwrap_or(String::from("Unexpected file type"))), BadgeStyles::Warning)
+                            },
+                            DownloadStatus::Retrying => {
+                                badge(row.retry.as_ref().map(|retry| format!("{}, retrying in {} ({}/{})",
+                                    retry.kind,
+                                    format_duration(retry.next_attempt.saturating_duration_since(Instant::now())),
+                                    retry.retry,
+                                    retry.max_retries
+                                )).unwrap_or(String::from("Retrying")), BadgeStyles::Warning)
+                            },
+                            // The complete file is moved from the staging directory
+                            _ if row.moving.is_some() => {
+                                let (moved, total) = row.moving.unwrap_or_default();
+                                badge(format!("Moving to the download directory | {:.2} %", moved as f64 / total.max(1) as f64 * 100.0), BadgeStyles::Light)
                             },
                             // Downloading Badge 
                             _ => {
                                 badge (
-                                format!("{:.2} MB/s | {:.2} %",
+                                format!("{:.2} MB/s | {:.2} %{}{}",
                                     row.get_download_speed_mbs(),
-                                    row.get_total_download_progress()
+                                    row.get_total_download_progress(),
+                                    row.eta.map(|eta| format!(" | ETA {}", format_duration(eta))).unwrap_or_default(),
+                                    // Explains why the progress went back to zero
+                                    row.restart_reason.as_ref().map(|reason| format!(" | Started over: {}", reason)).unwrap_or_default()
                                 ), BadgeStyles::Light)
                             }
                         }
                 ) 
                     .push( // play button
                         match row.download_status {
-                            DownloadStatus::Paused => {
-                                button(play_icon(), Some(Message::ResumeDownloadButtonPressed(*key)), play_submit_button_style())
+                            _ if self.queue_manager.state(*key) == Some(QueueState::Queued) => {
+                                button(play_icon(), None, play_submit_button_style(palette))
+                            },
+                            // Retries a download waiting for its retry right away
+                            _ if self.queue_manager.state(*key) == Some(QueueState::DelayedRetry) => {
+                                button(play_icon(), Some(Message::StartDownloadButtonPressed(*key)), play_submit_button_style(palette))
                             },
-                            DownloadStatus::Idle => {
-                                button(play_icon(), Some(Message::StartDownloadButtonPressed(*key)), play_submit_button_style())
+                            // Resuming a retrying download retries it right away
+                            DownloadStatus::Paused | DownloadStatus::Retrying => {
+                                button(play_icon(), Some(Message::ResumeDownloadButtonPressed(*key)), play_submit_button_style(palette))
+                            },
+                            DownloadStatus::Idle | DownloadStatus::Cancelled => {
+                                button(play_icon(), Some(Message::StartDownloadButtonPressed(*key)), play_submit_button_style(palette))
+                            },
+                            DownloadStatus::Warning => {
+                                button(play_icon(), Some(Message::ConfirmTypeButtonPressed(*key)), play_submit_button_style(palette))
                             },
                             _ => {
-                                button(play_icon(), None, play_submit_button_style())
+                                button(play_icon(), None, play_submit_button_style(palette))
                             }
                         }
                     
@@ -480,16 +2217,30 @@ impl Application for RustleGUI {
                     .push( // pause button
                         match row.download_status {
                             DownloadStatus::Downloading => {
-                                button( pause_icon(), Some(Message::PauseDownloadButtonPressed(*key)), pause_button_style())
+                                button( pause_icon(), Some(Message::PauseDownloadButtonPressed(*key)), pause_button_style(palette))
                             },
                             _ => {
-                                button(pause_icon(), None, pause_button_style())
+                                button(pause_icon(), None, pause_button_style(palette))
                             }
                         }
                     
                     )
+                    .push( // export button
+                        match row.download_status {
+                            // Unfinished downloads are exported with their session, once they're stopped
+                            DownloadStatus::Done | DownloadStatus::Paused | DownloadStatus::Error => {
+                                button(share_icon(), Some(Message::ExportBundleButtonPressed(*key)), pause_button_style(palette))
+                            },
+                            _ => {
+                                button(share_icon(), None, pause_button_style(palette))
+                            }
+                        }
+                    )
+                    .push( // timeline button
+                        button(info_icon(), Some(Message::TimelineButtonPressed(*key)), pause_button_style(palette))
+                    )
                     .push( // cancel button
-                        button(cancel_icon(), Some(Message::CancelDownloadButtonPressed(*key)), cancel_button_style())
+                        button(cancel_icon(), Some(Message::CancelDownloadButtonPressed(*key)), cancel_button_style(palette))
                     )
                     .spacing(10)
                     .padding(10)
@@ -505,9 +2256,9 @@ impl Application for RustleGUI {
         let initial_info_container = Container::new(
             Row::new()
             .push(
-                info_icon().size(22).style(grey_color_text_style())
+                info_icon().size(22).style(grey_color_text_style(palette))
             ).push(
-                Text::new("Add downloads using the floating button").size(22).style(grey_color_text_style())
+                Text::new("Add downloads using the floating button").size(22).style(grey_color_text_style(palette))
             ).spacing(3)
         ).width(Length::Fill)
         .height(Length::Fill)
@@ -521,14 +2272,31 @@ impl Application for RustleGUI {
         let main_column = Column::new()
                             .push(
                             Row::new().push(
-                                Text::new("Downloads").size(50).style(theme::Text::Color(GREEN_COLOR_MAIN)) 
+                                Text::new("Downloads").size(50).style(accent_text_style(palette)) 
+                            ).push(
+                                file_download_icon().size(50).style(accent_text_style(palette))
+                            ).push(
+                                match self.is_draining {
+                                    true => badge(String::from("Queue draining"), BadgeStyles::Warning),
+                                    false => horizontal_space(Length::Shrink).into(),
+                                }
                             ).push(
-                                file_download_icon().size(50).style(theme::Text::Color(GREEN_COLOR_MAIN))
+                                horizontal_space(Length::Fill)
+                            ).push( // menu
+                                PickList::new(&MenuAction::ALL[..], None, Message::MenuSelected)
+                                    .placeholder("Menu")
+                            ).push( // profile switcher
+                                PickList::new(self.profiles.names(), 
+                                              Some(self.profiles.active_name().to_string()), 
+                                              Message::ProfileSelected)
+                            ).push( // settings
+                                button(gear_icon(), Some(Message::SettingsButtonPressed), pause_button_style(palette))
                             ).spacing(15)
+                            .align_items(Alignment::Center)
                             
                             )
                             .push(
-                            Text::new("----------------------------------------------------------------").style(theme::Text::Color(GREEN_COLOR_MAIN))
+                            Text::new("----------------------------------------------------------------").style(accent_text_style(palette))
                                 .width(Length::Fill)
                             )
                             .push(
@@ -551,12 +2319,12 @@ impl Application for RustleGUI {
         Container::new(main_column)
                         .width(Length::Fill)
                         .height(Length::Fill)
-                        .style(white_container_style()),
+                        .style(background_container_style(palette)),
                     || {
                         // Floating Button
                         button(plus_icon().size(45),
                             Some(Message::ActionButtonPressed),
-                                 circular_floating_button_style()
+                                 circular_floating_button_style(palette)
                             ).padding(5).into()
                     }
                 )
@@ -573,7 +2341,7 @@ impl Application for RustleGUI {
             .center_y();
         
         // Modal that is set to show dynamically
-        Modal::new (
+        let add_url_modal = Modal::new (
                     self.show_modal,
                     main_screen_container,
                     || {
@@ -597,27 +2365,136 @@ impl Application for RustleGUI {
                             .into()
                         },
                         false => {
+                            // Only the URLs of a supported protocol can be submitted
+                            let mut pattern_count = None;
+                            let mut list_warning = None;
+                            let url_error = if is_download_list_path(&self.modal_url) {
+                                // Finished downloads of the list aren't imported
+                                DownloadList::load(Path::new(self.modal_url.trim()))
+                                    .and_then(|list| match list.unfinished().count() {
+                                        0 => Err(String::from("The list doesn't have any unfinished download")),
+                                        count => {
+                                            pattern_count = Some(count);
+                                            Ok(())
+                                        },
+                                    })
+                                    .err()
+                            } else if is_list_path(&self.modal_url) {
+                                // Invalid lines are skipped when importing, the first one is shown
+                                BatchImport::load(Path::new(self.modal_url.trim()))
+                                    .and_then(|batch| {
+                                        pattern_count = Some(batch.entries.len());
+                                        list_warning = batch.errors.first().map(|error| format!("Skips {} invalid line(s), {}", batch.errors.len(), error));
+                                        match batch.entries.is_empty() {
+                                            true => Err(String::from("The list doesn't have any valid URL")),
+                                            false => Ok(()),
+                                        }
+                                    })
+                                    .err()
+                            } else if CurlCommand::is_curl(&self.modal_url) {
+                                CurlCommand::parse(&self.modal_url).and_then(|curl| self.protocols.check_url(&curl.url)).err()
+                            } else if let Some(urls) = split_urls(&self.modal_url) {
+                                // The invalid urls are skipped when adding the others, the first one is shown
+                                let invalid : Vec<String> = urls.iter().filter_map(|url| self.protocols.check_url(url).err()).collect();
+                                pattern_count = Some(urls.len() - invalid.len());
+                                list_warning = invalid.first().map(|error| format!("Skips {} invalid URL(s), {}", invalid.len(), error));
+                                match invalid.len() == urls.len() {
+                                    true => Some(String::from("None of the URLs is valid")),
+                                    false => None,
+                                }
+                            } else if is_url_pattern(self.modal_url.trim()) {
+                                expand_url_pattern(self.modal_url.trim())
+                                    .and_then(|urls| {
+                                        pattern_count = Some(urls.len());
+                                        urls.iter().try_for_each(|url| self.protocols.check_url(url))
+                                    })
+                                    .err()
+                            } else {
+                                self.protocols.check_url(self.modal_url.trim()).err()
+                            };
+                            let mut url_column = Column::new()
+                                .push(Text::new("Enter the file url to be downloaded, the path of a list of urls, or paste several urls or a curl command"))
+                                .push(TextInput::new("Url, or pattern like file_[001-120].jpg", &self.modal_url)
+                                        .on_input(Message::ModalTextInputOnInput)
+                                        .on_paste(Message::ModalUrlPasted))
+                                .spacing(10);
+                            // The pasted urls that couldn't be added, the first ones are listed
+                            if !self.modal_failures.is_empty() {
+                                url_column = url_column.push(Text::new(format!("{} URL(s) couldn't be added", self.modal_failures.len())).size(14).style(error_text_style(palette)));
+                                for failure in self.modal_failures.iter().take(MODAL_FAILURES_SHOWN) {
+                                    url_column = url_column.push(Text::new(failure.as_str()).size(14).style(error_text_style(palette)));
+                                }
+                            }
+                            if let Some(count) = pattern_count.filter(|count| *count > 0) {
+                                url_column = url_column.push(Text::new(format!("Adds {} downloads", count)).size(14));
+                            }
+                            if let Some(e) = url_error.clone().filter(|_| !self.modal_url.trim().is_empty()).or(list_warning) {
+                                url_column = url_column.push(Text::new(e).size(14).style(error_text_style(palette)));
+                            }
+                            // A zsync control file reuses the blocks of an older version of the file
+                            if is_zsync_url(&self.modal_url) {
+                                url_column = url_column
+                                    .push(Text::new("Older version of the file, only the changed blocks are downloaded"))
+                                    .push(TextInput::new("Path of the older file, empty to download it all", &self.modal_seed_path).on_input(Message::ModalSeedPathOnInput));
+                            }
+                            // The links of a single web page can be grabbed instead of downloading it
+                            let can_grab_links = url_error.is_none() && pattern_count.is_none() && !CurlCommand::is_curl(&self.modal_url) && self.modal_out_dir_error.is_none();
 
-                            Card::new(
-                                Text::new("Add Url"),
-                                Column::new()
-                                .push(Text::new("Enter the file url to be downloaded"))
-                                .push(TextInput::new("Url to be downloaded", &self.modal_url).on_input(Message::ModalTextInputOnInput))
+                            let mut form = Column::new()
+                                .push(url_column)
+                                .push(Text::new("Output directory, leave empty for the one of the settings"))
+                                .push(TextInput::new(&self.profiles.active().download_dir.display().to_string(), &self.modal_out_dir).on_input(Message::ModalOutDirOnInput));
+                            if let Some(e) = self.modal_out_dir_error.clone() {
+                                form = form.push(Text::new(e).size(14).style(error_text_style(palette)));
+                            }
+                            form = form
+                                .push(
+                                    Row::new()
+                                    .push(Text::new("Connections"))
+                                    .push(PickList::new(&CONNECTION_CHOICES[..],
+                                                        Some(self.modal_connections.unwrap_or(self.profiles.active().max_connections)),
+                                                        Message::ModalConnectionsSelected))
+                                    .spacing(10)
+                                    .align_items(Alignment::Center)
+                                )
+                                .push(Checkbox::new("Accept invalid certificates", self.modal_accept_invalid_certs, Message::ModalAcceptInvalidCertsToggled))
+                                .push(Text::new("Start at, leave empty to start it manually"))
+                                .push(TextInput::new("YYYY-MM-DD HH:MM or HH:MM", &self.modal_start_at).on_input(Message::ModalStartAtOnInput))
                                 .spacing(10)
-                                .padding(10)
-                            
-                            )
+                                .padding(10);
+                            // The mode of the files is only set on Unix
+                            if cfg!(unix) {
+                                form = form
+                                    .push(Text::new("File mode, leave empty for the default"))
+                                    .push(TextInput::new("Octal, e.g. 644 or 755", &self.modal_file_mode).on_input(Message::ModalFileModeOnInput));
+                                if let Err(e) = parse_file_mode(&self.modal_file_mode) {
+                                    form = form.push(Text::new(e).size(14).style(error_text_style(palette)));
+                                }
+                            }
+                            // Hosts refusing the requests that don't come from their pages need the page as the referer
+                            form = form
+                                .push(Text::new("Referer, leave empty to send none"))
+                                .push(TextInput::new("URL of the page linking to the file", &self.modal_referer).on_input(Message::ModalRefererOnInput));
+                            if let Err(e) = parse_referer(&self.modal_referer) {
+                                form = form.push(Text::new(e).size(14).style(error_text_style(palette)));
+                            }
+
+                            Card::new(Text::new("Add Url"), form)
                             .foot(
                                 Row::new()
                                     .spacing(10)
                                     .padding(5)
                                     .width(Length::Fill)
                                     .push(
-                                        button(Text::new("Cancel").horizontal_alignment(Horizontal::Center), Some(Message::ModalCancelButtonPressed), cancel_button_style())
+                                        button(Text::new("Cancel").horizontal_alignment(Horizontal::Center), Some(Message::ModalCancelButtonPressed), cancel_button_style(palette))
+                                        .width(Length::Fill)
+                                    )
+                                    .push(
+                                        button(Text::new("Grab links").horizontal_alignment(Horizontal::Center), can_grab_links.then_some(Message::GrabLinksButtonPressed), pause_button_style(palette))
                                         .width(Length::Fill)
                                     )
                                     .push(
-                                        button(Text::new("Submit").horizontal_alignment(Horizontal::Center), Some(Message::ModalSubmitButtonPressed), play_submit_button_style())
+                                        button(Text::new("Submit").horizontal_alignment(Horizontal::Center), (url_error.is_none() && parse_file_mode(&self.modal_file_mode).is_ok() && parse_referer(&self.modal_referer).is_ok() && self.modal_out_dir_error.is_none()).then_some(Message::ModalSubmitButtonPressed), play_submit_button_style(palette))
                                         .width(Length::Fill)
                                     ),
                             ).max_width(450.0)
@@ -625,6 +2502,235 @@ impl Application for RustleGUI {
                         }
                     }
                 }
+            );
+
+        // Modal selecting the links of a grabbed page to queue
+        let link_grabber_modal = Modal::new (
+                    self.link_grabber.is_some(),
+                    add_url_modal,
+                    || {
+                        let Some(grabber) = self.link_grabber.as_ref() else { return Column::new().into() };
+                        let filter = LinkFilter::new(&grabber.extensions, &grabber.pattern);
+                        let matching : Vec<usize> = match filter.as_ref() {
+                            Ok(filter) => (0..grabber.links.len()).filter(|i| filter.matches(&grabber.links[*i])).collect(),
+                            Err(_) => Vec::new(),
+                        };
+                        let selected_count = matching.iter().filter(|i| grabber.selected[**i]).count();
+
+                        let mut filter_column = Column::new()
+                            .push(Text::new("Filter by extensions, e.g. jpg, png"))
+                            .push(TextInput::new("Any extension", &grabber.extensions).on_input(Message::LinkExtensionsOnInput))
+                            .push(Text::new("and by regular expression"))
+                            .push(TextInput::new("Any url", &grabber.pattern).on_input(Message::LinkPatternOnInput))
+                            .spacing(10);
+                        if let Err(e) = filter.as_ref() {
+                            filter_column = filter_column.push(Text::new(e.clone()).size(14).style(error_text_style(palette)));
+                        }
+
+                        // The site can be mirrored instead, downloading the files matching the filter
+                        let depth_is_valid = grabber.depth.trim().parse::<usize>().is_ok();
+                        let mirror_row = Row::new()
+                            .push(Text::new("Mirror depth"))
+                            .push(TextInput::new("1", &grabber.depth).on_input(Message::LinkDepthOnInput).width(50))
+                            .push(Checkbox::new("Same host", grabber.same_host, Message::LinkSameHostToggled).text_size(14))
+                            .push(Checkbox::new("Robots.txt", grabber.respect_robots, Message::LinkRespectRobotsToggled).text_size(14))
+                            .spacing(10)
+                            .align_items(Alignment::Center);
+                        let can_mirror = filter.is_ok() && depth_is_valid && !grabber.is_crawling;
+
+                        let links_column = matching.iter().fold(Column::new().spacing(5).width(Length::Fill), |column, i| {
+                            let link = &grabber.links[*i];
+                            let i = *i;
+                            column.push(
+                                Checkbox::new(format!("{} ({})", link.url, link.kind.as_str()), grabber.selected[i], move |checked| Message::LinkToggled((i, checked)))
+                                .text_size(14)
+                            )
+                        });
+
+                        Card::new(
+                            Text::new("Grab Links"),
+                            Column::new()
+                            .push(filter_column)
+                            .push(Text::new(format!("{} of {} matching links selected, out of {}", selected_count, matching.len(), grabber.links.len())))
+                            .push(Scrollable::new(links_column).height(300).width(Length::Fill))
+                            .push(mirror_row)
+                            .push(Text::new(match grabber.is_crawling {
+                                true => String::from("Crawling the site .."),
+                                false => String::from("Mirroring follows the links of the page up to the depth, keeping the site's directories"),
+                            }).size(14))
+                            .spacing(10)
+                            .padding(10)
+                        )
+                        .foot(
+                            Row::new()
+                                .spacing(10)
+                                .padding(5)
+                                .width(Length::Fill)
+                                .push(
+                                    button(Text::new("Cancel").horizontal_alignment(Horizontal::Center), Some(Message::LinkCancelButtonPressed), cancel_button_style(palette))
+                                    .width(Length::Fill)
+                                )
+                                .push(
+                                    button(Text::new("Select all").horizontal_alignment(Horizontal::Center), (!matching.is_empty()).then_some(Message::LinkSelectAllPressed), pause_button_style(palette))
+                                    .width(Length::Fill)
+                                )
+                                .push(
+                                    button(Text::new("Mirror site").horizontal_alignment(Horizontal::Center), can_mirror.then_some(Message::MirrorSiteButtonPressed), pause_button_style(palette))
+                                    .width(Length::Fill)
+                                )
+                                .push(
+                                    button(Text::new(format!("Queue {}", selected_count)).horizontal_alignment(Horizontal::Center), (selected_count > 0).then_some(Message::LinkQueueButtonPressed), play_submit_button_style(palette))
+                                    .width(Length::Fill)
+                                ),
+                        ).max_width(550.0)
+                        .into()
+                    }
+            );
+
+        // Modal showing the statistics of the download history
+        let statistics_modal = Modal::new (
+                    self.statistics.is_some(),
+                    link_grabber_modal,
+                    || {
+                        let lines = self.statistics.as_deref().unwrap_or_default();
+                        Card::new(
+                            Text::new(format!("Statistics of the last {} days", STATISTICS_DAYS)),
+                            Column::new()
+                            .push(Checkbox::new("Per week", self.statistics_period == StatsPeriod::Week, Message::StatisticsWeeklyToggled))
+                            .push(Scrollable::new(
+                                lines.iter().fold(Column::new().spacing(5), |column, line| column.push(Text::new(line.as_str()).size(14)))
+                            ).height(400))
+                            .spacing(10)
+                            .padding(10)
+                        )
+                        .foot(
+                            Row::new()
+                                .spacing(10)
+                                .padding(5)
+                                .width(Length::Fill)
+                                .push(
+                                    button(Text::new("Close").horizontal_alignment(Horizontal::Center), Some(Message::StatisticsCloseButtonPressed), pause_button_style(palette))
+                                    .width(Length::Fill)
+                                ),
+                        ).max_width(550.0)
+                        .into()
+                    }
+            );
+
+        // Modal showing the timeline of a download, read again at each update while it's open
+        let timeline_modal = Modal::new (
+                    self.timeline_row.is_some_and(|row_i| self.downloads.contains_key(&row_i)),
+                    statistics_modal,
+                    || {
+                        let Some(row) = self.timeline_row.and_then(|row_i| self.downloads.get(&row_i)) else { return Column::new().into() };
+                        let lines = format_timeline(&row.engine.get_timeline());
+                        Card::new(
+                            Text::new(format!("Timeline of {}", row.file_name.clone().or(row.file_url.clone()).unwrap_or(String::from("Unknown")))),
+                            Column::new()
+                            .push(Scrollable::new(
+                                lines.into_iter().fold(Column::new().spacing(5), |column, line| column.push(Text::new(line).size(14)))
+                            ).height(400))
+                            .spacing(10)
+                            .padding(10)
+                        )
+                        .foot(
+                            Row::new()
+                                .spacing(10)
+                                .padding(5)
+                                .width(Length::Fill)
+                                .push(
+                                    button(Text::new("Close").horizontal_alignment(Horizontal::Center), Some(Message::TimelineCloseButtonPressed), pause_button_style(palette))
+                                    .width(Length::Fill)
+                                ),
+                        ).max_width(550.0)
+                        .into()
+                    }
+            );
+
+        // Modal editing the settings of the active profile
+        let settings_modal = Modal::new (
+                    self.settings_form.is_some(),
+                    timeline_modal,
+                    || {
+                        let Some(form) = self.settings_form.as_ref() else { return Column::new().into() };
+                        let changes = form.changes(self.profiles.active());
+                        let mut column = Column::new()
+                            .push(Text::new("Download directory"))
+                            .push(TextInput::new("Directory of the new downloads", &form.download_dir).on_input(Message::SettingsDownloadDirOnInput))
+                            .push(Text::new("Connections per download, from 1 to 16"))
+                            .push(TextInput::new("4", &form.max_connections).on_input(Message::SettingsMaxConnectionsOnInput))
+                            .push(Text::new("Speed limit of each download in KiB/s, 0 for no limit"))
+                            .push(TextInput::new("0", &form.speed_limit).on_input(Message::SettingsSpeedLimitOnInput))
+                            .push(Text::new("Total speed limit of the downloads in KiB/s, 0 for no limit"))
+                            .push(TextInput::new("0", &form.global_speed_limit).on_input(Message::SettingsGlobalSpeedLimitOnInput))
+                            .push(
+                                Row::new()
+                                .push(Text::new("Theme"))
+                                .push(PickList::new(&ThemeChoice::ALL[..], Some(form.theme), Message::SettingsThemeSelected))
+                                .spacing(10)
+                                .align_items(Alignment::Center)
+                            )
+                            .push(Checkbox::new("Notify when a download finishes", form.notify_on_done, Message::SettingsNotifyOnDoneToggled))
+                            .push(Checkbox::new("Notify when a download fails", form.notify_on_error, Message::SettingsNotifyOnErrorToggled))
+                            .spacing(10)
+                            .padding(10);
+                        if let Err(e) = changes.as_ref() {
+                            column = column.push(Text::new(e.clone()).size(14).style(error_text_style(palette)));
+                        }
+                        Card::new(
+                            Text::new(format!("Settings of the {} profile", self.profiles.active_name())),
+                            column
+                        )
+                        .foot(
+                            Row::new()
+                                .spacing(10)
+                                .padding(5)
+                                .width(Length::Fill)
+                                .push(
+                                    button(Text::new("Cancel").horizontal_alignment(Horizontal::Center), Some(Message::SettingsCancelButtonPressed), cancel_button_style(palette))
+                                    .width(Length::Fill)
+                                )
+                                .push(
+                                    button(Text::new("Save").horizontal_alignment(Horizontal::Center), changes.is_ok_and(|changes| !changes.is_empty()).then_some(Message::SettingsSaveButtonPressed), play_submit_button_style(palette))
+                                    .width(Length::Fill)
+                                ),
+                        ).max_width(450.0)
+                        .into()
+                    }
+            );
+
+        // Confirmation modal shown when cancelling a started download
+        Modal::new (
+                    self.cancel_confirm_row.is_some(),
+                    settings_modal,
+                    || {
+                        Card::new(
+                            Text::new("Cancel Download"),
+                            Column::new()
+                            .push(Text::new("Keep the data downloaded so far to continue later, or delete it?"))
+                            .spacing(10)
+                            .padding(10)
+                        )
+                        .foot(
+                            Row::new()
+                                .spacing(10)
+                                .padding(5)
+                                .width(Length::Fill)
+                                .push(
+                                    button(Text::new("Back").horizontal_alignment(Horizontal::Center), Some(Message::CancelDismissButtonPressed), pause_button_style(palette))
+                                    .width(Length::Fill)
+                                )
+                                .push(
+                                    button(Text::new("Keep").horizontal_alignment(Horizontal::Center), Some(Message::CancelConfirmButtonPressed(true)), play_submit_button_style(palette))
+                                    .width(Length::Fill)
+                                )
+                                .push(
+                                    button(Text::new("Delete").horizontal_alignment(Horizontal::Center), Some(Message::CancelConfirmButtonPressed(false)), cancel_button_style(palette))
+                                    .width(Length::Fill)
+                                ),
+                        ).max_width(450.0)
+                        .into()
+                    }
             ).into()
     }
 
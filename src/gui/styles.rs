@@ -1,8 +1,128 @@
+use std::process::Stdio;
+use std::time::Duration;
 use iced::{theme::{self}, Theme, widget::{button, button::Appearance, progress_bar, container}, Color};
 use iced_aw::style::colors;
+use tokio::process::Command;
+use rustle::config::settings::ThemeChoice;
 
-pub const GREEN_COLOR_MAIN : Color = Color::from_rgb(0.0, 0.749, 0.388);
-pub const BLUE_COLOR_MAIN : Color = Color::from_rgb(0.1, 0.5, 0.9);
+/// Time between two checks of the color scheme of the OS, while the theme follows it.
+pub const SYSTEM_THEME_INTERVAL : Duration = Duration::from_secs(5);
+
+/*
+    Palettes
+*/
+
+/// Colors of a theme of the GUI, the style helpers take the active one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Palette {
+    /// background of the window and the cards
+    pub background : Color,
+    /// default color of the text
+    pub text : Color,
+    /// title, icon and floating button
+    pub accent : Color,
+    /// play and submit buttons, progress of the running downloads
+    pub primary : Color,
+    /// pause and neutral buttons
+    pub secondary : Color,
+    /// cancel buttons and validation errors
+    pub danger : Color,
+    /// progress of the finished downloads
+    pub success : Color,
+    /// progress of the paused downloads
+    pub paused : Color,
+    /// hints shown in place of the content
+    pub muted : Color
+}
+
+/// Palette of the light theme.
+pub const LIGHT_PALETTE : Palette = Palette {
+    background: Color::WHITE,
+    text: Color::BLACK,
+    accent: Color::from_rgb(0.0, 0.749, 0.388),
+    primary: Color::from_rgb(0.1, 0.5, 0.9),
+    secondary: Color::from_rgb(0.5, 0.5, 0.5),
+    danger: Color::from_rgb(0.8, 0.2, 0.2),
+    success: colors::SUCCESS,
+    paused: Color::from_rgb(0.2, 0.2, 0.2),
+    muted: Color::from_rgba(0.5, 0.5, 0.5, 0.6)
+};
+
+/// Palette of the dark theme.
+pub const DARK_PALETTE : Palette = Palette {
+    background: Color::from_rgb(0.125, 0.129, 0.141),
+    text: Color::from_rgb(0.9, 0.9, 0.9),
+    accent: Color::from_rgb(0.0, 0.8, 0.42),
+    primary: Color::from_rgb(0.2, 0.55, 0.95),
+    secondary: Color::from_rgb(0.4, 0.4, 0.42),
+    danger: Color::from_rgb(0.9, 0.3, 0.3),
+    success: Color::from_rgb(0.2, 0.7, 0.33),
+    paused: Color::from_rgb(0.6, 0.6, 0.6),
+    muted: Color::from_rgba(0.7, 0.7, 0.7, 0.6)
+};
+
+impl Palette {
+    /// Returns the palette of a theme, `ThemeChoice::System` picks the one of `system`.
+    ///
+    /// # Arguments
+    ///
+    /// * `choice` - The theme of the settings.
+    /// * `system` - The color scheme of the OS, `ThemeChoice::Light` or `ThemeChoice::Dark`.
+    pub fn of(choice : ThemeChoice, system : ThemeChoice) -> &'static Palette {
+        match choice {
+            ThemeChoice::Light => &LIGHT_PALETTE,
+            ThemeChoice::Dark => &DARK_PALETTE,
+            ThemeChoice::System => Palette::of(system, ThemeChoice::Light),
+        }
+    }
+
+    /// Returns the iced theme of the palette, styling the widgets without a custom style.
+    pub fn theme(&self) -> Theme {
+        Theme::custom(theme::Palette {
+            background: self.background,
+            text: self.text,
+            primary: self.primary,
+            success: self.success,
+            danger: self.danger,
+        })
+    }
+}
+
+/// Returns `color` with its components scaled by `factor`, darker below 1 and lighter above.
+fn shade(color : Color, factor : f32) -> Color {
+    Color::from_rgba((color.r * factor).min(1.0), (color.g * factor).min(1.0), (color.b * factor).min(1.0), color.a)
+}
+
+/// Reads the color scheme of the OS, `ThemeChoice::Light` if it can't be found.
+///
+/// # Returns
+///
+/// Returns `ThemeChoice::Dark` if the OS prefers dark applications, `ThemeChoice::Light` otherwise.
+pub async fn system_theme() -> ThemeChoice {
+    // GTK_THEME overrides the theme of the desktop, e.g. `Adwaita:dark`
+    if let Ok(gtk_theme) = std::env::var("GTK_THEME") {
+        return if gtk_theme.to_lowercase().contains("dark") { ThemeChoice::Dark } else { ThemeChoice::Light };
+    }
+    let command : &[&str] = if cfg!(target_os = "macos") {
+        &["defaults", "read", "-g", "AppleInterfaceStyle"]
+    } else if cfg!(windows) {
+        &["reg", "query", r"HKCU\Software\Microsoft\Windows\CurrentVersion\Themes\Personalize", "/v", "AppsUseLightTheme"]
+    } else {
+        &["gsettings", "get", "org.gnome.desktop.interface", "color-scheme"]
+    };
+    let mut process = Command::new(command[0]);
+    process.args(&command[1..]).stdin(Stdio::null()).stderr(Stdio::null());
+    // The console of reg.exe isn't shown
+    #[cfg(windows)]
+    process.creation_flags(0x08000000);
+    let output = match process.output().await {
+        Ok(output) => String::from_utf8_lossy(&output.stdout).to_lowercase(),
+        Err(_) => return ThemeChoice::Light,
+    };
+    // `Dark` on macOS, `0x0` for AppsUseLightTheme on Windows, `'prefer-dark'` on Linux
+    let dark = if cfg!(windows) { output.contains("0x0") } else { output.contains("dark") };
+    if dark { ThemeChoice::Dark } else { ThemeChoice::Light }
+}
 
 struct ContainerStyle {
     theme: theme::Container,
@@ -108,121 +228,180 @@ impl button::StyleSheet for ButtonStyle {
 
 /// Returns a custom button style for circular floating buttons.
 ///
+/// # Arguments
+///
+/// * `palette` - The palette of the active theme.
+///
 /// # Returns
 ///
-/// Returns a button style with a circular shape, using main green color for background and gradients for hover effect.
-pub fn circular_floating_button_style() -> iced::theme::Button {
+/// Returns a button style with a circular shape, using the accent color for background and shades of it for hover effect.
+pub fn circular_floating_button_style(palette : &Palette) -> iced::theme::Button {
     theme::Button::Custom(Box::new(
         ButtonStyle::new( theme::Button::Primary, 
                     100.0,
-                    GREEN_COLOR_MAIN,
-                    Color::from_rgb(0.0, 0.85, 0.35),
-                    Color::from_rgb(0.0, 0.7, 0.35)
+                    palette.accent,
+                    shade(palette.accent, 1.15),
+                    shade(palette.accent, 0.9)
         )
     ))
 }
 
 /// Returns a custom button style for play/submit buttons.
 ///
+/// # Arguments
+///
+/// * `palette` - The palette of the active theme.
+///
 /// # Returns
 ///
-/// Returns a button style with a slightly rounded rectangle shape, using main blue color for background and gradients for hover effect.
-pub fn play_submit_button_style() -> iced::theme::Button {
+/// Returns a button style with a slightly rounded rectangle shape, using the primary color for background and shades of it for hover effect.
+pub fn play_submit_button_style(palette : &Palette) -> iced::theme::Button {
    theme::Button::Custom(Box::new(ButtonStyle::new(
     theme::Button::Primary,
     5.0,
-    BLUE_COLOR_MAIN,
-    Color::from_rgb(0.2, 0.6, 1.0),
-    Color::from_rgb(0.0, 0.3, 0.7),
+    palette.primary,
+    shade(palette.primary, 1.15),
+    shade(palette.primary, 0.75),
     )))
 } 
 
 /// Returns a custom button style for pause buttons.
 ///
+/// # Arguments
+///
+/// * `palette` - The palette of the active theme.
+///
 /// # Returns
 ///
-/// Returns a button style with a slightly rounded rectangle shape, using a gray color for background and darker shades for hover effect.
-pub fn pause_button_style() -> iced::theme::Button {
+/// Returns a button style with a slightly rounded rectangle shape, using the secondary color for background and darker shades for hover effect.
+pub fn pause_button_style(palette : &Palette) -> iced::theme::Button {
    theme::Button::Custom(Box::new(ButtonStyle::new(
     theme::Button::Secondary,
     5.0,
-    Color::from_rgb(0.5, 0.5, 0.5),   
-    Color::from_rgb(0.4, 0.4, 0.4),   
-    Color::from_rgb(0.3, 0.3, 0.3),   
+    palette.secondary,
+    shade(palette.secondary, 0.8),
+    shade(palette.secondary, 0.6),
 )))
 } 
 
 /// Returns a custom button style for cancel buttons.
 ///
+/// # Arguments
+///
+/// * `palette` - The palette of the active theme.
+///
 /// # Returns
 ///
-/// Returns a button style with a slightly rounded rectangle shape, using a red color for background and darker shades for hover effect.
-pub fn cancel_button_style() -> iced::theme::Button {
+/// Returns a button style with a slightly rounded rectangle shape, using the danger color for background and darker shades for hover effect.
+pub fn cancel_button_style(palette : &Palette) -> iced::theme::Button {
    theme::Button::Custom(Box::new(ButtonStyle::new(
     theme::Button::Destructive,
     5.0,
-    Color::from_rgb(0.8, 0.2, 0.2),
-    Color::from_rgb(0.7, 0.1, 0.1),
-    Color::from_rgb(0.6, 0.0, 0.0),
+    palette.danger,
+    shade(palette.danger, 0.875),
+    shade(palette.danger, 0.75),
     )))
 }
 
 /// Returns a custom progress bar style for downloading state.
 ///
+/// # Arguments
+///
+/// * `palette` - The palette of the active theme.
+///
 /// # Returns
 ///
-/// Returns a progress bar style with the primary color transitioning from light to dark blue.
-pub fn downloading_pb_style() -> iced::theme::ProgressBar{
+/// Returns a progress bar style with the primary color of the palette.
+pub fn downloading_pb_style(palette : &Palette) -> iced::theme::ProgressBar{
    theme::ProgressBar::Custom(Box::new(ProgressBarStyle::new(
     theme::ProgressBar::Primary,
-    Color::from_rgb(0.1, 0.5, 0.9),
+    palette.primary,
     )))
 }
 
 /// Returns a custom progress bar style for paused state.
 ///
+/// # Arguments
+///
+/// * `palette` - The palette of the active theme.
+///
 /// # Returns
 ///
-/// Returns a progress bar style with the primary color set to gray.
-pub fn paused_pb_style() -> iced::theme::ProgressBar{
+/// Returns a progress bar style with the paused color of the palette.
+pub fn paused_pb_style(palette : &Palette) -> iced::theme::ProgressBar{
    theme::ProgressBar::Custom(Box::new(ProgressBarStyle::new(
     theme::ProgressBar::Primary,
-    Color::from_rgb(0.2, 0.2, 0.2),
+    palette.paused,
     )))
 }
 
 /// Returns a custom progress bar style for done state.
 ///
+/// # Arguments
+///
+/// * `palette` - The palette of the active theme.
+///
 /// # Returns
 ///
-/// Returns a progress bar style with the primary color set to a success color.
-pub fn done_pb_style() -> iced::theme::ProgressBar{
+/// Returns a progress bar style with the success color of the palette.
+pub fn done_pb_style(palette : &Palette) -> iced::theme::ProgressBar{
    theme::ProgressBar::Custom(Box::new(ProgressBarStyle::new(
     theme::ProgressBar::Primary,
-    colors::SUCCESS
+    palette.success
     )))
 }
 
-/// Returns a custom container style with a white background.
+/// Returns a custom container style with the background of the palette.
+///
+/// # Arguments
+///
+/// * `palette` - The palette of the active theme.
 ///
 /// # Returns
 ///
-/// Returns a container style with a white background color.
-pub fn white_container_style() -> iced::theme::Container{
+/// Returns a container style with the background color of the palette.
+pub fn background_container_style(palette : &Palette) -> iced::theme::Container{
    theme::Container::Custom(Box::new(ContainerStyle::new(
     theme::Container::Box,
-    Color::WHITE
+    palette.background
     )))
 }
 
-/// Returns a custom text style with a gray color and reduced opacity.
+/// Returns a custom text style with the accent color, for the title.
+///
+/// # Arguments
+///
+/// * `palette` - The palette of the active theme.
+///
+/// # Returns
+///
+/// Returns a text style with the accent color of the palette.
+pub fn accent_text_style(palette : &Palette) -> theme::Text { 
+    theme::Text::Color(palette.accent)
+}
+
+/// Returns a custom text style with a muted color, for a subdued appearance.
+///
+/// # Arguments
+///
+/// * `palette` - The palette of the active theme.
 ///
 /// # Returns
 ///
-/// Returns a text style with a gray color and reduced opacity for a subdued appearance.
-pub fn grey_color_text_style() -> theme::Text { 
-    theme::Text::Color(
-        Color::from_rgba(0.5, 0.5, 0.5, 0.6)
-    )
+/// Returns a text style with the muted color of the palette.
+pub fn grey_color_text_style(palette : &Palette) -> theme::Text { 
+    theme::Text::Color(palette.muted)
 }
 
+/// Returns a custom text style with a red color, for validation errors.
+///
+/// # Arguments
+///
+/// * `palette` - The palette of the active theme.
+///
+/// # Returns
+///
+/// Returns a text style with the danger color of the cancel buttons.
+pub fn error_text_style(palette : &Palette) -> theme::Text { 
+    theme::Text::Color(palette.danger)
+}
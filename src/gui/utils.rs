@@ -1,27 +1,249 @@
-/// Formats a file size in bytes into a human-readable string.
+use std::fs::{self, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use chrono::{DateTime, Local, NaiveDateTime, NaiveTime, TimeZone};
+use rustle_core::bundle::BUNDLE_EXTENSION;
+use rustle_core::curl::CurlCommand;
+use rustle_core::zsync::ZSYNC_EXTENSION;
+use url::Url;
+
+// Formats accepted for a start time, in local time
+const START_TIME_FORMAT: &str = "%Y-%m-%d %H:%M";
+const START_TIME_CLOCK_FORMAT: &str = "%H:%M";
+
+/// Parses a start time typed by the user, in local time.
 ///
-/// The function takes a file size in bytes as input and returns a formatted string
-/// representing the size in the most appropriate unit (B, KB, MB, GB, TB, or PB).
+/// Accepts a full date and time (`2024-05-01 23:30`) or only a time of day (`23:30`),
+/// which refers to its next occurrence, i.e. tomorrow if it already passed today.
 ///
 /// # Arguments
 ///
-/// * `bytes` - The file size in bytes. Must be a non-negative integer.
+/// * `text` - The text to parse, an empty text means no start time.
 ///
 /// # Returns
 ///
-/// A formatted string representing the file size with the appropriate unit.
-pub fn format_file_size(bytes: u64) -> String {
-    if bytes < 1024 {
-        return format!("{} B", bytes);
-    } else if bytes < 1024_u64.pow(2) {
-        return format!("{:.2} KB", bytes as f64 / 1024.0);
-    } else if bytes < 1024_u64.pow(3) {
-        return format!("{:.2} MB", bytes as f64 / 1024.0 / 1024.0);
-    } else if bytes < 1024_u64.pow(4) {
-        return format!("{:.2} GB", bytes as f64 / 1024.0 / 1024.0 / 1024.0);
-    } else if bytes < 1024_u64.pow(5) {
-        return format!("{:.2} TB", bytes as f64 / 1024.0 / 1024.0 / 1024.0 / 1024.0);
-    } else {
-        return format!("{:.2} PB", bytes as f64 / 1024.0 / 1024.0 / 1024.0 / 1024.0 / 1024.0);
-    }
-}
\ No newline at end of file
+/// * `Result<Option<SystemTime>, String>` - The start time, or an error if the text isn't a valid time.
+pub fn parse_start_time(text: &str) -> Result<Option<SystemTime>, String> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Ok(None);
+    }
+
+    let date_time = match NaiveDateTime::parse_from_str(text, START_TIME_FORMAT) {
+        Ok(date_time) => date_time,
+        Err(_) => {
+            let time = NaiveTime::parse_from_str(text, START_TIME_CLOCK_FORMAT)
+                .map_err(|_| format!("Invalid start time `{}`, expected YYYY-MM-DD HH:MM or HH:MM", text))?;
+            let now = Local::now().naive_local();
+            let today = now.date().and_time(time);
+            if today > now { today } else { today + chrono::Duration::days(1) }
+        }
+    };
+
+    // Ambiguous times (clocks going back) take the earliest one
+    Local.from_local_datetime(&date_time).earliest()
+        .map(|date_time| Some(date_time.into()))
+        .ok_or(format!("Start time `{}` doesn't exist in the local time zone", text))
+}
+
+/// Parses a file mode typed by the user, in octal, e.g. `644`, `0755` or `0o755`.
+///
+/// # Arguments
+///
+/// * `text` - The text to parse, an empty text means the default mode.
+///
+/// # Returns
+///
+/// * `Result<Option<u32>, String>` - The mode, or an error if the text isn't a valid mode.
+pub fn parse_file_mode(text: &str) -> Result<Option<u32>, String> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Ok(None);
+    }
+    u32::from_str_radix(text.strip_prefix("0o").unwrap_or(text), 8).ok()
+        .filter(|mode| *mode <= 0o7777)
+        .map(Some)
+        .ok_or(format!("Invalid file mode `{}`, expected an octal mode like 644", text))
+}
+
+/// Parses the referer typed in the Add dialog.
+///
+/// # Arguments
+///
+/// * `text` - The text to parse, an empty text means no referer.
+///
+/// # Returns
+///
+/// * `Result<Option<String>, String>` - The referer, or an error if the text isn't an absolute URL.
+pub fn parse_referer(text: &str) -> Result<Option<String>, String> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Ok(None);
+    }
+    Url::parse(text)
+        .map(|_| Some(text.to_string()))
+        .map_err(|e| format!("Invalid referer `{}`, error : {}", text, e))
+}
+
+/// Parses the output directory typed in the Add dialog. Only its existence is checked, as it's
+/// parsed on every keystroke, see `check_writable` for the files to be written to it.
+///
+/// # Arguments
+///
+/// * `text` - The text to parse, an empty text means the download directory of the settings.
+///
+/// # Returns
+///
+/// * `Result<Option<PathBuf>, String>` - The directory, or an error if it doesn't exist.
+pub fn parse_out_dir(text: &str) -> Result<Option<PathBuf>, String> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Ok(None);
+    }
+    let dir = PathBuf::from(text);
+    if !dir.is_dir() {
+        return Err(format!("The directory `{}` doesn't exist", text));
+    }
+    Ok(Some(dir))
+}
+
+/// Checks that files can be written to a directory by creating and removing a file in it.
+///
+/// # Arguments
+///
+/// * `dir` - The directory to check.
+///
+/// Returns an error if the directory isn't writable.
+pub fn check_writable(dir: &Path) -> Result<(), String> {
+    // The permissions don't tell about read-only mounts or ACLs, a file is created to find out
+    let probe = dir.join(format!(".rustle-{}.tmp", std::process::id()));
+    OpenOptions::new().write(true).create_new(true).open(&probe)
+        .map_err(|e| format!("The directory `{}` isn't writable, error : {}", dir.display(), e))?;
+    let _ = fs::remove_file(&probe);
+    Ok(())
+}
+
+/// Formats a start time in local time, in the format accepted by `parse_start_time`.
+pub fn format_start_time(time: SystemTime) -> String {
+    DateTime::<Local>::from(time).format(START_TIME_FORMAT).to_string()
+}
+
+/// Returns the URL of the download typed in the Add dialog, the URL of a pasted curl command
+/// or the text itself.
+pub fn modal_download_url(text: &str) -> String {
+    match CurlCommand::is_curl(text) {
+        true => CurlCommand::parse(text).map(|curl| curl.url).unwrap_or_else(|_| text.to_string()),
+        false => text.to_string(),
+    }
+}
+
+/// Splits the URLs pasted or typed in the Add dialog, separated by whitespace.
+///
+/// # Arguments
+///
+/// * `text` - The text of the Add dialog, the lines of a pasted text are joined with spaces.
+///
+/// # Returns
+///
+/// * `Option<Vec<String>>` - The URLs, or `None` for a single URL or a curl command.
+pub fn split_urls(text: &str) -> Option<Vec<String>> {
+    if CurlCommand::is_curl(text) {
+        return None;
+    }
+    let urls : Vec<String> = text.split_whitespace().map(String::from).collect();
+    (urls.len() > 1).then_some(urls)
+}
+
+/// Joins the lines of a text pasted in the Add dialog with spaces, as its text input drops the line breaks.
+/// The paste is located by comparing the text before and after it, so pasting over a selection or in
+/// the middle of the text keeps the text around it.
+///
+/// # Arguments
+///
+/// * `before` - The text of the Add dialog before the paste.
+/// * `after` - The text of the Add dialog after the paste, holding the pasted text without its line breaks.
+/// * `pasted` - The content of the clipboard.
+///
+/// # Returns
+///
+/// * `Option<String>` - The text with the pasted lines in place, or `None` if a single line was pasted
+///   or `after` doesn't hold the pasted text.
+pub fn join_pasted_lines(before: &str, after: &str, pasted: &str) -> Option<String> {
+    let lines : Vec<&str> = pasted.lines().map(str::trim).filter(|line| !line.is_empty()).collect();
+    if lines.len() < 2 {
+        return None;
+    }
+    // The text input inserts the clipboard without its control characters, the line breaks included
+    let inserted : String = pasted.chars().filter(|c| !c.is_control()).collect();
+    let prefix = before.char_indices().zip(after.chars())
+        .find(|((_, old), new)| old != new)
+        .map_or(before.len().min(after.len()), |((i, _), _)| i);
+    // The paste may start inside the common prefix, when it begins like the text it replaced
+    let (start, rest) = (0..=prefix).rev()
+        .filter(|start| after.is_char_boundary(*start))
+        .find_map(|start| {
+            let rest = after[start..].strip_prefix(inserted.as_str())?;
+            (before.ends_with(rest) && before.len() - rest.len() >= start).then_some((start, rest))
+        })?;
+    let head = after[..start].trim_end();
+    let tail = rest.trim_start();
+    Some(std::iter::once(head).chain(lines).chain(std::iter::once(tail)).filter(|part| !part.is_empty()).collect::<Vec<&str>>().join(" "))
+}
+
+/// Returns whether the text typed in the Add dialog is the path of a list of URLs to import,
+/// i.e. an existing file that isn't a `.rustle` bundle or an exported download list.
+pub fn is_list_path(text: &str) -> bool {
+    let path = Path::new(text.trim());
+    !text.trim().is_empty() && path.is_file() && !is_download_list_path(text)
+        && path.extension().is_none_or(|extension| extension != BUNDLE_EXTENSION)
+}
+
+/// Returns whether the text typed in the Add dialog is the path of a download list exported as
+/// JSON, i.e. an existing `.json` file.
+pub fn is_download_list_path(text: &str) -> bool {
+    let path = Path::new(text.trim());
+    path.is_file() && path.extension().is_some_and(|extension| extension == "json")
+}
+
+/// Returns whether the text typed in the Add dialog is the URL of a zsync control file, which
+/// updates an older version of the file by only downloading the blocks that changed.
+pub fn is_zsync_url(text: &str) -> bool {
+    Url::parse(text.trim())
+        .is_ok_and(|url| Path::new(url.path()).extension().is_some_and(|extension| extension == ZSYNC_EXTENSION))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pasted_lines_replace_the_selection() {
+        let pasted = "https://example.com/a.iso\nhttps://example.com/b.iso\n";
+        // "old" was selected when the lines were pasted, the text input dropped their line break
+        let before = "https://example.com/first.iso old https://example.com/last.iso";
+        let after = "https://example.com/first.iso https://example.com/a.isohttps://example.com/b.iso https://example.com/last.iso";
+
+        let joined = join_pasted_lines(before, after, pasted).unwrap();
+        assert_eq!(joined, "https://example.com/first.iso https://example.com/a.iso https://example.com/b.iso https://example.com/last.iso");
+        assert_eq!(split_urls(&joined).unwrap(), vec![
+            "https://example.com/first.iso",
+            "https://example.com/a.iso",
+            "https://example.com/b.iso",
+            "https://example.com/last.iso",
+        ]);
+    }
+
+    #[test]
+    fn pasted_lines_keep_the_text_around_them() {
+        let pasted = "https://example.com/a.iso\r\nhttps://example.com/b.iso";
+        assert_eq!(join_pasted_lines("", "https://example.com/a.isohttps://example.com/b.iso", pasted).unwrap(),
+            "https://example.com/a.iso https://example.com/b.iso");
+        // Pasted at the start of the text, which begins like the pasted text
+        assert_eq!(join_pasted_lines("https://example.com/c.iso", "https://example.com/a.isohttps://example.com/b.isohttps://example.com/c.iso", pasted).unwrap(),
+            "https://example.com/a.iso https://example.com/b.iso https://example.com/c.iso");
+
+        assert_eq!(join_pasted_lines("", "https://example.com/a.iso", "https://example.com/a.iso\n"), None);
+        assert_eq!(join_pasted_lines("edited", "edited since", pasted), None);
+        assert_eq!(split_urls("https://example.com/a.iso"), None);
+    }
+}
@@ -0,0 +1,292 @@
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::Arc;
+use futures::future::BoxFuture;
+use tokio::process::Command;
+use tokio::task;
+use url::Url;
+use rustle_core::downloader::RustleDownloader;
+use rustle_core::refresh::UrlRefresher;
+
+/// Placeholders replaced in the hook commands, the values are quoted for the shell.
+pub const HOOK_PLACEHOLDERS: [&str; 5] = ["{path}", "{url}", "{size}", "{status}", "{error}"];
+
+/// Hooks represents the commands run after a download finishes or fails, e.g. moving the file,
+/// notifying a script or triggering a media library scan. Cancelled downloads don't run any.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Hooks {
+    pub on_done: Option<String>,    // Command run when a download finishes
+    pub on_error: Option<String>,   // Command run when a download fails, after its retries
+    pub refresh_url: Option<String>,    // Command printing a new URL when the URL of a download expires, e.g. signing it again
+}
+
+/// HookContext represents the download a hook is run for, its values replace the placeholders.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HookContext {
+    pub path: Option<PathBuf>,  // Path of the downloaded file, `{path}`
+    pub url: String,            // URL of the file, `{url}`
+    pub size: Option<u64>,      // Size of the file in bytes if known, `{size}`
+    pub error: Option<String>,  // Error of a failed download, `{error}`, its `{status}` is `failed` instead of `done`
+}
+
+impl HookContext {
+    /// Creates the context of a download from its engine and the result of `RustleDownloader::download`.
+    ///
+    /// Returns `None` if the download was cancelled.
+    pub async fn from_engine(engine: &RustleDownloader, result: &Result<bool, String>) -> Option<HookContext> {
+        let error = match result {
+            Ok(true) => None,
+            Ok(false) => return None,
+            Err(e) => Some(e.clone()),
+        };
+        Some(HookContext {
+            path: engine.get_file_path().await,
+            url: engine.get_url().await.unwrap_or_default(),
+            size: engine.get_file_info().await.and_then(|info| info.content_length),
+            error,
+        })
+    }
+
+    /// Returns `done` or `failed`, the value of `{status}`.
+    pub fn status(&self) -> &'static str {
+        if self.error.is_some() { "failed" } else { "done" }
+    }
+}
+
+impl Hooks {
+    /// Returns the command run for the download of `context`, if any.
+    pub fn command(&self, context: &HookContext) -> Option<&str> {
+        match context.error {
+            None => self.on_done.as_deref(),
+            Some(_) => self.on_error.as_deref(),
+        }
+        .filter(|command| !command.trim().is_empty())
+    }
+
+    /// Runs the hook of a download once `RustleDownloader::download` returned `result`, see `run()`.
+    /// Cancelled downloads don't run any.
+    pub async fn run_for(&self, engine: &RustleDownloader, result: &Result<bool, String>) -> Result<(), String> {
+        match HookContext::from_engine(engine, result).await {
+            Some(context) => self.run(&context),
+            None => Ok(()),
+        }
+    }
+
+    /// Runs the hook of the download in the background with the system shell, the exit status
+    /// is only reported if the command fails.
+    ///
+    /// Returns an error if the command couldn't be started.
+    pub fn run(&self, context: &HookContext) -> Result<(), String> {
+        let Some(command) = self.command(context) else { return Ok(()) };
+        let command = expand_placeholders(command, context);
+
+        let mut child = shell_command(&command)
+            .stdin(Stdio::null())
+            .spawn()
+            .map_err(|e| format!("Couldn't run the hook `{}`, error : {}", command, e))?;
+
+        task::spawn(async move {
+            match child.wait().await {
+                Ok(status) if status.success() => {},
+                Ok(status) => eprintln!("The hook `{}` failed, {}", command, status),
+                Err(e) => eprintln!("The hook `{}` failed, error : {}", command, e),
+            }
+        });
+        Ok(())
+    }
+}
+
+/// CommandRefresher refreshes expired URLs with the `refresh_url` hook: the command is run with
+/// the expired URL as `{url}`, and the first line it prints is the new URL.
+#[derive(Debug, Clone)]
+pub struct CommandRefresher {
+    command: String,    // Command printing the new URL
+}
+
+impl UrlRefresher for CommandRefresher {
+    fn refresh_url(&self, expired: &Url) -> BoxFuture<'static, Result<String, String>> {
+        let context = HookContext { url: expired.to_string(), ..Default::default() };
+        let command = expand_placeholders(&self.command, &context);
+        Box::pin(async move {
+            let output = shell_command(&command)
+                .stdin(Stdio::null())
+                .stderr(Stdio::inherit())
+                .output()
+                .await
+                .map_err(|e| format!("Couldn't run the hook `{}`, error : {}", command, e))?;
+            if !output.status.success() {
+                return Err(format!("The hook `{}` failed, {}", command, output.status));
+            }
+            String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .map(str::trim)
+                .find(|line| !line.is_empty())
+                .map(String::from)
+                .ok_or_else(|| format!("The hook `{}` didn't print a URL", command))
+        })
+    }
+}
+
+impl Hooks {
+    /// Returns the refresher running the `refresh_url` hook, if it's set.
+    pub fn url_refresher(&self) -> Option<Arc<dyn UrlRefresher>> {
+        let command = self.refresh_url.as_deref().filter(|command| !command.trim().is_empty())?;
+        Some(Arc::new(CommandRefresher { command: command.to_string() }))
+    }
+}
+
+/// Returns the system shell running `command`.
+fn shell_command(command: &str) -> Command {
+    #[cfg(windows)]
+    let mut process = Command::new("cmd");
+    #[cfg(windows)]
+    process.arg("/C");
+    #[cfg(not(windows))]
+    let mut process = Command::new("sh");
+    #[cfg(not(windows))]
+    process.arg("-c");
+    process.arg(command);
+    process
+}
+
+/// Replaces the placeholders of `command` with the values of `context`, quoted so they're
+/// passed as single arguments whatever they contain (the URL and file name come from the server).
+///
+/// # Arguments
+///
+/// * `command` - The hook command, e.g. `mv {path} ~/Videos`.
+/// * `context` - The download the hook is run for.
+pub fn expand_placeholders(command: &str, context: &HookContext) -> String {
+    let values = [
+        context.path.as_ref().map(|path| path.to_string_lossy().into_owned()).unwrap_or_default(),
+        context.url.clone(),
+        context.size.map(|size| size.to_string()).unwrap_or_default(),
+        context.status().to_string(),
+        context.error.clone().unwrap_or_default(),
+    ];
+
+    let mut expanded = String::with_capacity(command.len());
+    let mut rest = command;
+    while let Some(start) = rest.find('{') {
+        expanded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        match HOOK_PLACEHOLDERS.iter().position(|placeholder| rest.starts_with(placeholder)) {
+            Some(index) => {
+                expanded.push_str(&shell_quote(&values[index]));
+                rest = &rest[HOOK_PLACEHOLDERS[index].len()..];
+            },
+            None => {
+                expanded.push('{');
+                rest = &rest[1..];
+            },
+        }
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+/// Quotes a value as a single argument of the system shell.
+fn shell_quote(value: &str) -> String {
+    if cfg!(windows) { cmd_quote(value) } else { sh_quote(value) }
+}
+
+/// Quotes a value as a single `sh` argument.
+fn sh_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+/// Quotes a value as a single `cmd` argument.
+fn cmd_quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('"', "\"\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context() -> HookContext {
+        HookContext {
+            path: Some(PathBuf::from("/downloads/it's a file.iso")),
+            url: String::from("https://example.com/it's a file.iso?a=1&b=2"),
+            size: Some(1024),
+            error: None,
+        }
+    }
+
+    #[test]
+    fn values_are_quoted_for_sh() {
+        assert_eq!(sh_quote("file.iso"), "'file.iso'");
+        assert_eq!(sh_quote("a file.iso"), "'a file.iso'");
+        assert_eq!(sh_quote("it's"), r"'it'\''s'");
+        assert_eq!(sh_quote("$(rm -rf ~) \"x\""), "'$(rm -rf ~) \"x\"'");
+        assert_eq!(sh_quote(""), "''");
+    }
+
+    #[test]
+    fn values_are_quoted_for_cmd() {
+        assert_eq!(cmd_quote("file.iso"), "\"file.iso\"");
+        assert_eq!(cmd_quote("a file.iso"), "\"a file.iso\"");
+        assert_eq!(cmd_quote("it's"), "\"it's\"");
+        assert_eq!(cmd_quote("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(cmd_quote(""), "\"\"");
+    }
+
+    #[test]
+    fn placeholders_are_replaced_with_quoted_values() {
+        let context = context();
+        let expanded = expand_placeholders("mv {path} ~/Videos && echo {url} {size} {status}{error}", &context);
+        assert_eq!(expanded, format!(
+            "mv {} ~/Videos && echo {} {} {}{}",
+            shell_quote("/downloads/it's a file.iso"),
+            shell_quote("https://example.com/it's a file.iso?a=1&b=2"),
+            shell_quote("1024"),
+            shell_quote("done"),
+            shell_quote(""),
+        ));
+
+        let failed = HookContext { error: Some(String::from("connection reset")), size: None, ..context };
+        assert_eq!(
+            expand_placeholders("notify {status} {error} {size}", &failed),
+            format!("notify {} {} {}", shell_quote("failed"), shell_quote("connection reset"), shell_quote("")),
+        );
+    }
+
+    #[test]
+    fn unknown_placeholders_are_kept() {
+        let context = context();
+        assert_eq!(expand_placeholders("echo {name} {} { {{size}}", &context), format!("echo {{name}} {{}} {{ {{{}}}", shell_quote("1024")));
+        assert_eq!(expand_placeholders("echo done", &context), "echo done");
+        assert_eq!(expand_placeholders("awk '{print $1}' {", &context), "awk '{print $1}' {");
+    }
+
+    #[test]
+    fn hooks_are_picked_from_the_result() {
+        let hooks = Hooks { on_done: Some(String::from("echo {path}")), on_error: Some(String::from("  ")), refresh_url: None };
+        assert_eq!(hooks.command(&context()), Some("echo {path}"));
+        let failed = HookContext { error: Some(String::from("timed out")), ..context() };
+        assert_eq!(hooks.command(&failed), None);
+    }
+
+    #[tokio::test]
+    async fn context_is_created_from_the_engine() {
+        let mut engine = RustleDownloader::new(1).unwrap();
+        engine.set_url("https://example.com/file.iso").await.unwrap();
+        engine.set_out_dir("/downloads").await.unwrap();
+        engine.set_file_name("renamed.iso").await;
+
+        let done = HookContext::from_engine(&engine, &Ok(true)).await.unwrap();
+        assert_eq!(done, HookContext {
+            path: Some(PathBuf::from("/downloads/renamed.iso")),
+            url: String::from("https://example.com/file.iso"),
+            size: None,
+            error: None,
+        });
+        assert_eq!(done.status(), "done");
+
+        let failed = HookContext::from_engine(&engine, &Err(String::from("connection reset"))).await.unwrap();
+        assert_eq!(failed.error.as_deref(), Some("connection reset"));
+        assert_eq!(failed.status(), "failed");
+
+        assert_eq!(HookContext::from_engine(&engine, &Ok(false)).await, None);
+    }
+}
@@ -0,0 +1,153 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Desktop entry installed by the packages, `Exec` is rewritten to the running binary when installed by `rustle integrate`.
+const DESKTOP_ENTRY: &str = include_str!("../packaging/linux/rustle.desktop");
+
+/// File name of the desktop entry, both in the applications and the autostart directories.
+const DESKTOP_ENTRY_FILE: &str = "rustle.desktop";
+
+/// Shared MIME info definition of `.rustle` bundles, so file managers open them with Rustle.
+const MIME_PACKAGE: &str = include_str!("../packaging/linux/rustle.xml");
+
+/// File name of the MIME definition in the `mime/packages` data directory.
+const MIME_PACKAGE_FILE: &str = "rustle.xml";
+
+/// Handles the `rustle integrate <install|uninstall> [--autostart]` command.
+///
+/// # Arguments
+///
+/// * `args` - The arguments following `integrate`.
+///
+/// Returns an error message if the arguments are invalid or the integration failed.
+pub fn run(args: &[String]) -> Result<(), String> {
+    let autostart = args.iter().any(|arg| arg == "--autostart");
+    match args.first().map(String::as_str) {
+        Some("install") => {
+            for path in install(autostart)? {
+                println!("Installed {}", path.display());
+            }
+            Ok(())
+        },
+        Some("uninstall") => {
+            for path in uninstall()? {
+                println!("Removed {}", path.display());
+            }
+            Ok(())
+        },
+        _ => Err(String::from("Usage: rustle integrate <install|uninstall> [--autostart]")),
+    }
+}
+
+/// Returns the desktop entry paths, in the applications and the autostart directories,
+/// and the path of the MIME definition.
+fn entry_paths() -> Result<(PathBuf, PathBuf, PathBuf), String> {
+    let data_dir = dirs::data_dir().ok_or("Couldn't find the user data directory")?;
+    let config_dir = dirs::config_dir().ok_or("Couldn't find the user config directory")?;
+    Ok(entry_paths_in(&data_dir, &config_dir))
+}
+
+/// Returns the paths of `entry_paths()` in the given user data and config directories.
+fn entry_paths_in(data_dir: &Path, config_dir: &Path) -> (PathBuf, PathBuf, PathBuf) {
+    (
+        data_dir.join("applications").join(DESKTOP_ENTRY_FILE),
+        config_dir.join("autostart").join(DESKTOP_ENTRY_FILE),
+        data_dir.join("mime").join("packages").join(MIME_PACKAGE_FILE),
+    )
+}
+
+/// Returns the desktop entry launching `exe`, quoted so paths with spaces work.
+#[cfg(target_os = "linux")]
+fn desktop_entry(exe: &Path) -> String {
+    DESKTOP_ENTRY.replace("Exec=rustle", &format!("Exec=\"{}\"", exe.display()))
+}
+
+/// Rebuilds the user MIME database containing `mime_package`, if `update-mime-database` is available.
+/// Failures are ignored, the definition is picked up the next time the database is rebuilt.
+fn update_mime_database(mime_package: &Path) {
+    if let Some(mime_dir) = mime_package.parent().and_then(Path::parent) {
+        let _ = std::process::Command::new("update-mime-database").arg(mime_dir).status();
+    }
+}
+
+/// Installs the desktop entry of the running binary and the MIME definition of bundles,
+/// and the autostart entry if `autostart` is set.
+/// Returns the paths of the written files.
+#[cfg(target_os = "linux")]
+fn install(autostart: bool) -> Result<Vec<PathBuf>, String> {
+    let exe = std::env::current_exe().map_err(|e| format!("Couldn't find the rustle binary, error : {}", e))?;
+    let entry = desktop_entry(&exe);
+
+    let (application, autostart_entry, mime_package) = entry_paths()?;
+    let mut files = vec![(application, entry.as_str()), (mime_package.clone(), MIME_PACKAGE)];
+    if autostart {
+        files.push((autostart_entry, entry.as_str()));
+    }
+
+    for (path, content) in &files {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| format!("Couldn't create {}, error : {}", parent.display(), e))?;
+        }
+        fs::write(path, content).map_err(|e| format!("Couldn't write {}, error : {}", path.display(), e))?;
+    }
+    update_mime_database(&mime_package);
+    Ok(files.into_iter().map(|(path, _)| path).collect())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn install(_autostart: bool) -> Result<Vec<PathBuf>, String> {
+    Err(String::from("Desktop integration is only supported on Linux"))
+}
+
+/// Removes the entries written by `install()`, returns the paths of the removed files.
+fn uninstall() -> Result<Vec<PathBuf>, String> {
+    let (application, autostart, mime_package) = entry_paths()?;
+    let mut removed = Vec::new();
+    for path in [application, autostart, mime_package.clone()] {
+        if path.is_file() {
+            fs::remove_file(&path).map_err(|e| format!("Couldn't remove {}, error : {}", path.display(), e))?;
+            removed.push(path);
+        }
+    }
+    if removed.contains(&mime_package) {
+        update_mime_database(&mime_package);
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn desktop_entry_launches_the_running_binary() {
+        let entry = desktop_entry(Path::new("/opt/My Apps/rustle"));
+        let exec: Vec<&str> = entry.lines().filter(|line| line.starts_with("Exec=")).collect();
+        assert_eq!(exec, vec!["Exec=\"/opt/My Apps/rustle\" %F"]);
+        assert_eq!(entry.lines().count(), DESKTOP_ENTRY.lines().count());
+    }
+
+    #[test]
+    fn desktop_entry_opens_bundles() {
+        let mime_types = DESKTOP_ENTRY.lines().find_map(|line| line.strip_prefix("MimeType=")).unwrap();
+        assert!(mime_types.split(';').any(|mime_type| MIME_PACKAGE.contains(&format!("<mime-type type=\"{}\">", mime_type))));
+        assert!(MIME_PACKAGE.contains("<glob pattern=\"*.rustle\"/>"));
+    }
+
+    #[test]
+    fn entries_are_installed_in_the_user_directories() {
+        let (application, autostart, mime_package) = entry_paths_in(Path::new("/home/user/.local/share"), Path::new("/home/user/.config"));
+        assert_eq!(application, Path::new("/home/user/.local/share/applications/rustle.desktop"));
+        assert_eq!(autostart, Path::new("/home/user/.config/autostart/rustle.desktop"));
+        assert_eq!(mime_package, Path::new("/home/user/.local/share/mime/packages/rustle.xml"));
+    }
+
+    #[test]
+    fn unknown_commands_print_the_usage() {
+        let usage = Err(String::from("Usage: rustle integrate <install|uninstall> [--autostart]"));
+        assert_eq!(run(&[]), usage);
+        assert_eq!(run(&[String::from("--autostart")]), usage);
+        assert_eq!(run(&[String::from("remove")]), usage);
+    }
+}
@@ -0,0 +1,6 @@
+pub mod config;
+pub mod format;
+pub mod hooks;
+pub mod queue;
+#[cfg(feature = "testing-server")]
+pub mod testing_server;
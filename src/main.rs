@@ -1,21 +1,114 @@
-mod download_utils;
 mod gui;
+mod integration;
+mod crash_report;
+mod stats;
+mod credentials;
 
-use gui::rustle_gui::RustleGUI;
+use std::path::PathBuf;
+use gui::rustle_gui::{RustleGUI, GuiFlags};
 use iced::{Settings, window, Application};
+use rustle::config::profiles::Profiles;
+use rustle::config::settings::Settings as RustleSettings;
+use rustle_core::bundle::BUNDLE_EXTENSION;
+
+/// Returns the value of the `--profile <name>` (or `--profile=<name>`) command line flag, if present.
+fn profile_arg() -> Option<String> {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--profile" {
+            return args.next();
+        }
+        if let Some(name) = arg.strip_prefix("--profile=") {
+            return Some(name.to_string());
+        }
+    }
+    None
+}
+
+/// Returns the `.rustle` bundles passed on the command line, e.g. by the file manager when one is opened.
+fn bundle_args() -> Vec<PathBuf> {
+    std::env::args().skip(1)
+        .map(PathBuf::from)
+        .filter(|path| path.extension().is_some_and(|extension| extension == BUNDLE_EXTENSION))
+        .collect()
+}
 
 fn main() -> iced::Result {
 
+    crash_report::install();
+
+    // Desktop integration runs instead of the GUI
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("integrate") {
+        if let Err(e) = integration::run(&args[1..]) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    // Load the user settings, falling back to the defaults for invalid entries
+    let mut profiles = match RustleSettings::default_path() {
+        Some(path) => {
+            let (profiles, issues) = Profiles::load(&path);
+            for issue in issues {
+                eprintln!("{}: {}", path.display(), issue);
+            }
+            profiles
+        },
+        None => Profiles::default(),
+    };
+
+    if let Some(name) = profile_arg() {
+        if let Err(e) = profiles.select(&name) {
+            eprintln!("{}", e);
+        }
+    }
+
+    // The statistics of the download history are printed instead of running the GUI
+    if args.first().map(String::as_str) == Some("stats") {
+        if let Err(e) = stats::run(&args[1..], profiles.active()) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    // The queue of the running instance is drained or resumed instead of running the GUI
+    if args.first().map(String::as_str) == Some("queue") {
+        if let Err(e) = rustle::queue::run("rustle", &args[1..]) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    // The credentials of a host are managed in the OS credential store instead of running the GUI
+    if args.first().map(String::as_str) == Some("credentials") {
+        if let Err(e) = credentials::run(&args[1..]) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
     let font_bytes = include_bytes!("../assets/fonts/victor_mono/static/VictorMono-Medium.ttf");
 
+    // The window opens with the size and position it had when it was last closed
+    let geometry = profiles.active().window;
     let settings = Settings {
         window: window::Settings {
-            size: (600, 800),
-            resizable: false,
+            size: (geometry.width, geometry.height),
+            position: geometry.position.map(|(x, y)| window::Position::Specific(x, y)).unwrap_or_default(),
+            min_size: Some((450, 500)),
+            resizable: true,
             decorations: true,
             ..window::Settings::default()
         },
         default_font: Some(font_bytes),
+        flags: GuiFlags { profiles, bundles: bundle_args() },
+        // The downloads are paused and saved before the window closes, see `Message::ShutdownRequested`
+        exit_on_close_request: false,
         ..Default::default()
     };
 
@@ -0,0 +1,114 @@
+use std::path::Path;
+use std::time::{Duration, Instant};
+use crate::config::settings::Settings;
+use rustle_core::drain::{clear_drain, read_drain_state, write_drain_state, DrainState, DRAIN_POLL_INTERVAL};
+
+/// Arguments of the `queue` command, following the name of the binary.
+const USAGE: &str = "queue <drain [--wait]|resume|status>";
+
+/// Time given to the running application to pick up a drain request, several times its poll interval.
+const PICKUP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Handles the `queue` command of `rustle` and `rustle-tui`, draining the queue of the running
+/// application before maintenance: its running downloads finish, but no queued download is
+/// started until the queue is resumed. `drain --wait` returns once no download is running anymore.
+///
+/// # Arguments
+///
+/// * `program` - The name of the binary, shown in the usage.
+/// * `args` - The arguments following `queue`.
+///
+/// Returns an error message if the arguments are invalid or the drain request couldn't be written.
+pub fn run(program: &str, args: &[String]) -> Result<(), String> {
+    let usage = || format!("Usage: {} {}", program, USAGE);
+    let path = Settings::drain_path().ok_or("Couldn't find the data directory of the drain request")?;
+    let wait = args.iter().any(|arg| arg == "--wait");
+    let action = args.iter().find(|arg| !arg.starts_with('-')).map(String::as_str);
+    if args.iter().any(|arg| arg.starts_with('-') && arg != "--wait") || (wait && action != Some("drain")) {
+        return Err(usage());
+    }
+
+    match action {
+        Some("drain") => {
+            if read_drain_state(&path)?.is_none() {
+                write_drain_state(&path, DrainState::Requested)?;
+            }
+            if !wait {
+                println!("Requested a drain, running downloads finish and queued ones wait for `{} queue resume`", program);
+                return Ok(());
+            }
+            match wait_drained(&path, PICKUP_TIMEOUT, DRAIN_POLL_INTERVAL)? {
+                true => println!("The queue is drained"),
+                false => println!("Rustle isn't running, no download to wait for"),
+            }
+            Ok(())
+        },
+        Some("resume") => {
+            clear_drain(&path)?;
+            println!("Resumed the queue");
+            Ok(())
+        },
+        Some("status") => {
+            match read_drain_state(&path)? {
+                Some(state) => println!("{}", state),
+                None => println!("running"),
+            }
+            Ok(())
+        },
+        _ => Err(usage()),
+    }
+}
+
+/// Waits until the drain request at `path` is drained.
+///
+/// # Arguments
+///
+/// * `path` - The path of the drain request.
+/// * `pickup_timeout` - Time after which a request still not picked up means the application isn't running.
+/// * `poll_interval` - Time between two reads of the request.
+///
+/// # Returns
+///
+/// * `Result<bool, String>` - Whether the running application drained its queue, `false` if none
+///   picked the request up, or an error if the request was removed or couldn't be read.
+fn wait_drained(path: &Path, pickup_timeout: Duration, poll_interval: Duration) -> Result<bool, String> {
+    let started = Instant::now();
+    loop {
+        match read_drain_state(path)? {
+            Some(DrainState::Drained) => return Ok(true),
+            Some(DrainState::Requested) if started.elapsed() >= pickup_timeout => return Ok(false),
+            Some(_) => std::thread::sleep(poll_interval),
+            None => return Err(String::from("The drain was cancelled, the queue was resumed")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn waiting_follows_the_drain_request() {
+        let dir = std::env::temp_dir().join(format!("rustle-test-queue-drain-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let path = dir.join("drain");
+        let poll = Duration::from_millis(5);
+
+        write_drain_state(&path, DrainState::Requested).unwrap();
+        assert_eq!(wait_drained(&path, Duration::from_millis(20), poll), Ok(false));
+
+        // The running application drains its queue while the command waits
+        write_drain_state(&path, DrainState::Draining).unwrap();
+        let drained = path.clone();
+        let application = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            write_drain_state(&drained, DrainState::Drained).unwrap();
+        });
+        assert_eq!(wait_drained(&path, Duration::ZERO, poll), Ok(true));
+        application.join().unwrap();
+
+        clear_drain(&path).unwrap();
+        assert!(wait_drained(&path, Duration::ZERO, poll).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,79 @@
+use std::time::{Duration, SystemTime};
+use rustle::config::settings::Settings;
+use rustle::format::{format_stats, format_usage};
+use rustle_core::stats::StatsPeriod;
+
+/// Default number of days counted by `rustle stats`.
+const DEFAULT_DAYS: u64 = 30;
+
+/// Usage of the `rustle stats` command.
+const USAGE: &str = "Usage: rustle stats [--days <n>] [--weekly] [--profile <name>]";
+
+/// Handles the `rustle stats [--days <n>] [--weekly]` command, printing the statistics of the
+/// download history of the last days (`0` counts all of them), per day or per week, followed by
+/// the data usage.
+///
+/// # Arguments
+///
+/// * `args` - The arguments following `stats`.
+/// * `settings` - The settings of the active profile, locating the history.
+///
+/// Returns an error message if the arguments are invalid or the history or the data usage couldn't be read.
+pub fn run(args: &[String], settings: &Settings) -> Result<(), String> {
+    let (days, period) = parse_args(args)?;
+    let since = (days > 0).then(|| SystemTime::now() - Duration::from_secs(days * 24 * 3600));
+    match settings.open_history()? {
+        Some(history) => {
+            for line in format_stats(&history.stats(since, period)?, period) {
+                println!("{}", line);
+            }
+        },
+        None => println!("The download history is disabled in the settings"),
+    }
+    for line in format_usage(&settings.open_data_usage()?, since) {
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+/// Parses the arguments of the `stats` command.
+///
+/// # Returns
+///
+/// * `Result<(u64, StatsPeriod), String>` - The number of days counted and the length of the
+///   periods, or the usage if the arguments are invalid.
+fn parse_args(args: &[String]) -> Result<(u64, StatsPeriod), String> {
+    let mut days = DEFAULT_DAYS;
+    let mut period = StatsPeriod::Day;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--days" => days = args.next().and_then(|days| days.parse().ok()).ok_or(USAGE)?,
+            "--weekly" => period = StatsPeriod::Week,
+            // The profile was already selected
+            "--profile" => { args.next(); },
+            arg if arg.starts_with("--profile=") => {},
+            _ => return Err(String::from(USAGE)),
+        }
+    }
+    Ok((days, period))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn downloads_are_grouped_per_day_by_default() {
+        assert_eq!(parse_args(&[]), Ok((DEFAULT_DAYS, StatsPeriod::Day)));
+        assert_eq!(parse_args(&args(&["--weekly", "--days", "0"])), Ok((0, StatsPeriod::Week)));
+        assert_eq!(parse_args(&args(&["--profile", "work", "--days", "7", "--profile=home"])), Ok((7, StatsPeriod::Day)));
+        assert_eq!(parse_args(&args(&["--days"])), Err(String::from(USAGE)));
+        assert_eq!(parse_args(&args(&["--days", "week"])), Err(String::from(USAGE)));
+        assert_eq!(parse_args(&args(&["--monthly"])), Err(String::from(USAGE)));
+    }
+}
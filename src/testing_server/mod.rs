@@ -1 +1,141 @@
-pub mod server;
\ No newline at end of file
+//! Local HTTP server serving a test file, run by the `testing_server` binary and started
+//! in-process by the integration tests.
+//!
+//! Routes:
+//! - `GET|HEAD /download` serves the file, honoring ranges and sending its validators.
+//! - `GET|HEAD /throttled/{delay_ms}/download` serves it the same way, waiting before each chunk.
+//! - `GET|HEAD /no-ranges/download` sends the whole file for every request, without `Accept-Ranges`.
+//! - `GET|HEAD /status/{code}` answers with the status code only.
+
+use std::io;
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::thread::JoinHandle;
+use std::time::Duration;
+use actix_files::NamedFile;
+use actix_web::body::{BodySize, BoxBody, MessageBody, SizedStream};
+use actix_web::dev::ServerHandle;
+use actix_web::http::StatusCode;
+use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+use futures::StreamExt;
+
+/// Path of the served file, shared by the handlers.
+#[derive(Debug, Clone)]
+struct ServedFile(PathBuf);
+
+/// Registers the routes serving `file` on an actix application.
+///
+/// # Arguments
+///
+/// * `file` - The file served by the download routes.
+pub fn routes(file: PathBuf) -> impl FnOnce(&mut web::ServiceConfig) + Clone {
+    move |config| {
+        config
+            .app_data(web::Data::new(ServedFile(file)))
+            .route("/", web::get().to(|| async { HttpResponse::Ok().body("Ok!") }))
+            .route("/download", web::route().to(download))
+            .route("/throttled/{delay_ms}/download", web::route().to(throttled_download))
+            .route("/no-ranges/download", web::route().to(download_without_ranges))
+            .route("/status/{code}", web::route().to(status));
+    }
+}
+
+/// Serves the file, the ranges and validators are handled by `NamedFile`.
+async fn download(request: HttpRequest, file: web::Data<ServedFile>) -> HttpResponse {
+    match NamedFile::open_async(&file.0).await {
+        Ok(named_file) => named_file.into_response(&request),
+        Err(e) => HttpResponse::InternalServerError().body(format!("Couldn't open the file, err : {}", e)),
+    }
+}
+
+/// Serves the file like `download`, waiting `delay_ms` before each chunk of the body so the
+/// transfer can be paused while running.
+async fn throttled_download(request: HttpRequest, file: web::Data<ServedFile>, delay_ms: web::Path<u64>) -> HttpResponse {
+    let delay = Duration::from_millis(delay_ms.into_inner());
+    download(request, file).await.map_body(|_, body| {
+        let size = match body.size() {
+            BodySize::Sized(size) => size,
+            _ => 0,
+        };
+        let mut body = Box::pin(body);
+        let chunks = futures::stream::poll_fn(move |cx| body.as_mut().poll_next(cx))
+            .then(move |chunk| async move {
+                tokio::time::sleep(delay).await;
+                chunk
+            });
+        BoxBody::new(SizedStream::new(size, chunks))
+    })
+}
+
+/// Sends the whole file whatever the requested range, like servers that don't support ranges.
+async fn download_without_ranges(file: web::Data<ServedFile>) -> HttpResponse {
+    match tokio::fs::read(&file.0).await {
+        Ok(content) => HttpResponse::Ok().body(content),
+        Err(e) => HttpResponse::InternalServerError().body(format!("Couldn't read the file, err : {}", e)),
+    }
+}
+
+/// Answers with the requested status code, to exercise the error paths of the clients.
+async fn status(code: web::Path<u16>) -> HttpResponse {
+    match StatusCode::from_u16(code.into_inner()) {
+        Ok(code) => HttpResponse::build(code).finish(),
+        Err(_) => HttpResponse::BadRequest().body("Invalid status code"),
+    }
+}
+
+/// TestingServer runs the routes on a loopback port, in a thread of its own so it can be
+/// started from any test. The server is stopped once it's dropped.
+#[derive(Debug)]
+pub struct TestingServer {
+    base_url: String,               // URL of the server, e.g. `http://127.0.0.1:41235`
+    handle: ServerHandle,           // Handle stopping the server
+    thread: Option<JoinHandle<()>>, // Thread running the server
+}
+
+impl TestingServer {
+    /// Starts a server serving `file` on a free loopback port.
+    ///
+    /// Returns an error if no port could be bound.
+    pub fn start(file: impl Into<PathBuf>) -> io::Result<TestingServer> {
+        let file = file.into();
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let base_url = format!("http://{}", listener.local_addr()?);
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let thread = std::thread::spawn(move || {
+            actix_web::rt::System::new().block_on(async move {
+                let server = HttpServer::new(move || App::new().configure(routes(file.clone())))
+                    .workers(2)
+                    .listen(listener);
+                match server {
+                    Ok(server) => {
+                        let server = server.run();
+                        let _ = sender.send(Ok(server.handle()));
+                        let _ = server.await;
+                    },
+                    Err(e) => {
+                        let _ = sender.send(Err(e));
+                    },
+                }
+            })
+        });
+
+        let handle = receiver.recv().map_err(|_| io::Error::other("The testing server stopped while starting"))??;
+        Ok(TestingServer { base_url, handle, thread: Some(thread) })
+    }
+
+    /// Returns the URL of a route of the server, e.g. `url("/download")`.
+    pub fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+}
+
+impl Drop for TestingServer {
+    fn drop(&mut self) {
+        // The stop command is sent right away, the thread ends with the server
+        drop(self.handle.stop(false));
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
@@ -1,52 +1,30 @@
 use std::path::Path;
 use std::process::Command;
-use actix_web::{HttpRequest, 
-                HttpResponse, 
-                Responder, 
-                HttpServer,
-                App,
-                get,
-                route
-            };
-use actix_files::NamedFile;
+use actix_web::{App, HttpServer};
+use rustle::testing_server::routes;
 
+/// File served by the `/download` routes, 64 MiB of random bytes created on first start.
+const FILE_PATH: &str = "file.temp.bin";
 
-#[route("/download", method="GET", method="HEAD")]
-async fn download(request : HttpRequest) -> impl Responder {
-    let file_path = "file.temp.bin";
-
+#[actix_web::main]
+async fn main() -> std::io::Result<()> {
     // If file wasn't created i.e first time
-    if !Path::new(file_path).is_file(){
+    if !Path::new(FILE_PATH).is_file() {
         let status = Command::new("dd")
-                                .args(["if=/dev/urandom", &format!("of={}", file_path), "bs=64M", "count=1"])
-                                .status();        
-        if status.is_err(){
-            return HttpResponse::InternalServerError().body(format!("Couldn't create the temp file, err : {}", status.err().unwrap().to_string()))
+                                .args(["if=/dev/urandom", &format!("of={}", FILE_PATH), "bs=64M", "count=1"])
+                                .status()?;
+        if !status.success() {
+            return Err(std::io::Error::other(format!("Couldn't create the temp file, dd exited with {}", status)));
         }
     }
 
-    let response = NamedFile::open(file_path).unwrap().into_response(&request);
-
-    return response;
-}
-
-
-#[get("/")]
-async fn root() -> HttpResponse {
-    HttpResponse::Ok().body("Ok!")
-}
-
-#[actix_web::main]
-async fn main() -> std::io::Result<()> {
     println!("-> Server starting ..");
 
     HttpServer::new(|| {
         App::new()
-            .service(root)
-            .service(download)
+            .configure(routes(FILE_PATH.into()))
     })
     .bind(("127.0.0.1", 5555))?
     .run()
     .await
-
-}
\ No newline at end of file
+}
@@ -0,0 +1,604 @@
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ratatui::widgets::TableState;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::task;
+use rustle::config::settings::Settings;
+use rustle_core::batch::BatchImport;
+use rustle_core::bundle::{DownloadBundle, BUNDLE_EXTENSION};
+use rustle_core::connection_manager::ConnectionManager;
+use rustle_core::curl::CurlCommand;
+use rustle_core::error::ErrorKind;
+use rustle_core::url_pattern::{expand_url_pattern, is_url_pattern};
+use rustle_core::downloader::{DownloadStatus, RetryInfo, RustleDownloader};
+use rustle_core::drain;
+use rustle_core::history::DownloadHistory;
+use rustle_core::journal::{remove_orphan_part_files, SessionJournal};
+use rustle_core::queue_manager::{DownloadId, Priority, QueueManager, QueueState};
+
+/// AppEvent represents an input of the application: a key press or the result of a background task.
+#[derive(Debug)]
+pub enum AppEvent {
+    Key(KeyEvent),
+    Initialized(Result<(String, RustleDownloader), String>),   // URL and engine of an added download
+    Finished(DownloadId, Result<bool, String>),                 // Result of a started download
+}
+
+/// InputMode represents what the key presses are used for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InputMode {
+    Normal,             // Keybindings act on the selected download
+    AddUrl(String),     // Typing the URL (pattern, curl command, `.rustle` bundle or list path) of a new download
+}
+
+/// DownloadRow represents a download in the list, along with its last refreshed progress.
+#[derive(Debug)]
+pub struct DownloadRow {
+    pub id: DownloadId,                 // Identifier of the download in the queue manager
+    pub file_name: String,              // Name of the downloaded file
+    pub file_size: Option<u64>,         // Size of the file, if known
+    pub engine: Arc<RustleDownloader>,  // Engine of the download
+    pub status: DownloadStatus,         // Status of the engine
+    pub queue_state: QueueState,        // State of the download in the queue
+    pub queue_position: Option<usize>,  // Position in the queue while queued
+    pub start_at: Option<SystemTime>,   // Time the download is queued at while scheduled or waiting for a retry
+    pub priority: Priority,             // Priority in the queue
+    pub downloaded: u64,                // Number of bytes received
+    pub speed: f64,                     // Smoothed speed, in bytes per second
+    pub eta: Option<Duration>,          // Estimated remaining time
+    pub error: Option<String>,          // Error of the last run, if it failed
+    pub retry: Option<RetryInfo>,       // Upcoming retry while the download is retrying
+    pub paused_offline: bool,           // Whether the download is paused until the network is back
+    pub paused_quota: bool,             // Whether the download is paused until the data quota is available again
+    pub restart_reason: Option<String>, // Why the received data was discarded when the download was resumed, if the file changed
+    pub moving: Option<(u64, u64)>,     // Bytes moved from the staging directory and size of the file, while it's moved
+    pub type_warning: Option<String>,   // Why the type of the file doesn't match its extension, while the download waits for a confirmation
+    pub host_paused: bool,              // Whether the downloads from the download's host are paused
+}
+
+impl DownloadRow {
+    /// Returns the progress of the download, between `0.0` and `1.0`.
+    pub fn ratio(&self) -> f64 {
+        match self.file_size {
+            Some(size) if size > 0 => (self.downloaded as f64 / size as f64).min(1.0),
+            _ if self.status == DownloadStatus::Done => 1.0,
+            _ => 0.0,
+        }
+    }
+
+    /// Returns whether the download holds or waits for a slot, or has data saved to the journal when quitting.
+    fn is_running(&self) -> bool {
+        matches!(self.queue_state, QueueState::Scheduled | QueueState::Queued | QueueState::DelayedRetry)
+            || matches!(self.status, DownloadStatus::Downloading | DownloadStatus::Retrying | DownloadStatus::Paused)
+    }
+}
+
+/// App represents the state of the terminal interface.
+#[derive(Debug)]
+pub struct App {
+    pub settings: Settings,                         // Settings of the active profile, applied to new downloads
+    pub rows: Vec<DownloadRow>,                     // Downloads, in the order they were added
+    pub table_state: TableState,                    // Selected row of the downloads table
+    pub input_mode: InputMode,                      // What the key presses are used for
+    pub message: Option<String>,                    // Last notice or error shown in the footer
+    pub pending: usize,                             // Number of downloads being initialized
+    pub show_timeline: bool,                        // Whether the timeline of the selected download is shown under the table
+    pub queue_manager: Arc<QueueManager>,           // Queue manager owning the downloads
+    connection_manager: Arc<ConnectionManager>,     // Connection manager shared by all downloads
+    history: Option<DownloadHistory>,               // History the finished and failed downloads are recorded in, if enabled
+    sender: UnboundedSender<AppEvent>,              // Sends the results of background tasks to the event loop
+    draining: bool,                                 // Whether the queue drains, requested with `rustle-tui queue drain`
+    quit_requested: bool,                           // Quit was pressed once while downloads were running
+    quit: bool,                                     // The event loop should stop
+}
+
+impl App {
+    /// Creates a new App.
+    ///
+    /// # Arguments
+    ///
+    /// * `settings` - The settings of the active profile.
+    /// * `sender` - The sender of the event loop's channel, background tasks report through it.
+    pub fn new(settings: Settings, sender: UnboundedSender<AppEvent>) -> App {
+        let connection_manager = settings.new_connection_manager();
+        let queue_manager = QueueManager::new(settings.max_active_downloads)
+            .expect("max_active_downloads is validated to be non-zero");
+        queue_manager.set_max_retries(settings.queue_retries);
+        let (history, mut message) = match settings.open_history() {
+            Ok(history) => (history, None),
+            Err(e) => (None, Some(e)),
+        };
+        match settings.open_data_usage() {
+            Ok(data_usage) => connection_manager.set_data_usage(Some(Arc::new(data_usage))),
+            Err(e) => { message.get_or_insert(e); },
+        }
+        App {
+            settings,
+            rows: Vec::new(),
+            table_state: TableState::default(),
+            input_mode: InputMode::Normal,
+            message,
+            pending: 0,
+            show_timeline: false,
+            queue_manager: Arc::new(queue_manager),
+            connection_manager: Arc::new(connection_manager),
+            history,
+            sender,
+            draining: false,
+            quit_requested: false,
+            quit: false,
+        }
+    }
+
+    /// Returns the time between two refreshes of the progress.
+    pub fn refresh_interval(&self) -> Duration {
+        self.settings.progress_interval
+    }
+
+    /// Returns whether the event loop should stop.
+    pub fn should_quit(&self) -> bool {
+        self.quit
+    }
+
+    /// Restores the downloads saved to the session journal when the application last exited, then
+    /// deletes the journal. Downloads that were running or queued start again. The part files of the
+    /// previous sessions that aren't restored are deleted.
+    pub async fn restore_session(&mut self) {
+        let journal = Settings::journal_path().filter(|path| path.exists()).map(|path| {
+            let journal = SessionJournal::load(&path);
+            if let Err(e) = std::fs::remove_file(&path) {
+                self.message = Some(format!("Couldn't delete the session journal {}, error : {}", path.display(), e));
+            }
+            journal
+        });
+        for dir in self.settings.part_file_dirs() {
+            remove_orphan_part_files(&dir, journal.as_ref().and_then(|journal| journal.as_ref().ok()));
+        }
+        let mut journal = match journal {
+            Some(Ok(journal)) => journal,
+            Some(Err(e)) => {
+                self.message = Some(e);
+                return;
+            },
+            None => return,
+        };
+        if let Some(e) = journal.read_part_files().pop() {
+            self.message = Some(e);
+        }
+
+        for entry in journal.downloads {
+            let mut engine = match self.settings.restore_engine(&entry, self.connection_manager.clone()).await {
+                Ok(engine) => engine,
+                Err(e) => {
+                    self.message = Some(format!("Couldn't restore the download of {}, error : {}", entry.token.url, e));
+                    continue;
+                },
+            };
+            if let Err(e) = self.settings.enable_debug_logs(&mut engine).await {
+                self.message = Some(e);
+            }
+            let id = self.add_row(entry.token.url.clone(), engine).await;
+            let _ = self.queue_manager.set_priority(id, entry.priority);
+            if let Some(start_at) = entry.start_time() {
+                self.schedule(id, start_at);
+            } else if entry.resume {
+                self.start(id);
+            }
+        }
+        self.refresh().await;
+    }
+
+    /// Removes the drain request left from before the application started, e.g. before a reboot.
+    pub fn clear_drain(&mut self) {
+        if let Some(Err(e)) = Settings::drain_path().map(|path| drain::clear_drain(&path)) {
+            self.message = Some(e);
+        }
+    }
+
+    /// Drains the queue while `rustle-tui queue drain` requests it, and resumes it once the request is removed.
+    pub async fn sync_drain(&mut self) {
+        let Some(path) = Settings::drain_path() else { return };
+        match drain::sync_drain(&path, &self.queue_manager).await {
+            Ok(draining) if draining != self.draining => {
+                self.draining = draining;
+                self.message = Some(String::from(match draining {
+                    true => "Draining the queue, the running downloads finish and the queued ones wait",
+                    false => "Resumed the queue",
+                }));
+            },
+            Ok(_) => {},
+            Err(e) => self.message = Some(e),
+        }
+    }
+
+    /// Marks the drain request as drained once the downloads are saved, the application is exiting.
+    pub fn release_drain(&self) {
+        if let Some(Err(e)) = Settings::drain_path().map(|path| drain::release_drain(&path)) {
+            eprintln!("{}", e);
+        }
+    }
+
+    /// Pauses the downloads and saves them to the session journal, so they're restored at the next launch.
+    ///
+    /// Returns the number of saved downloads, or the errors of the downloads that couldn't be saved.
+    pub async fn save_session(&self) -> Result<usize, String> {
+        let (mut journal, errors) = SessionJournal::shutdown(&self.queue_manager).await;
+        // The bytes of the downloads whose part file couldn't be written are saved in the journal
+        let mut part_file_errors = Vec::new();
+        if !journal.downloads.is_empty() {
+            let path = Settings::journal_path().ok_or("Couldn't find the data directory of the session journal")?;
+            part_file_errors = journal.write_part_files(&self.settings.part_files);
+            journal.save(&path)?;
+        }
+        if !part_file_errors.is_empty() {
+            return Err(part_file_errors.join(", "));
+        }
+        if !errors.is_empty() {
+            return Err(format!("Couldn't save the download {}", errors.join(", ")));
+        }
+        Ok(journal.downloads.len())
+    }
+
+    /// Initializes a download in the background, it's queued once initialized. The downloads of
+    /// a URL pattern or a list are initialized one after the other.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - The URL of the file, a URL pattern, a curl command, the path of a `.rustle` bundle
+    ///   or of a list of URLs (see `BatchImport`).
+    pub fn add(&mut self, source: String) {
+        let sources = if is_bundle_path(&source) || CurlCommand::is_curl(&source) {
+            vec![(source, None)]
+        } else if Path::new(&source).is_file() {
+            match BatchImport::load(Path::new(&source)) {
+                Ok(batch) => {
+                    if let Some(error) = batch.errors.first() {
+                        self.message = Some(format!("Skipped {} invalid line(s) of {}, {}", batch.errors.len(), source, error));
+                    }
+                    batch.entries.into_iter().map(|entry| (entry.url, entry.file_name)).collect()
+                },
+                Err(e) => {
+                    self.message = Some(e);
+                    return;
+                },
+            }
+        } else if is_url_pattern(&source) {
+            match expand_url_pattern(&source) {
+                Ok(urls) => urls.into_iter().map(|url| (url, None)).collect(),
+                Err(e) => {
+                    self.message = Some(e);
+                    return;
+                },
+            }
+        } else {
+            vec![(source, None)]
+        };
+
+        let settings = self.settings.clone();
+        let connection_manager = self.connection_manager.clone();
+        let sender = self.sender.clone();
+        self.pending += sources.len();
+
+        task::spawn(async move {
+            for (source, file_name) in sources {
+                let result = App::init_source(&settings, connection_manager.clone(), source, file_name).await;
+                if sender.send(AppEvent::Initialized(result)).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    /// Creates and initializes the engine of a download, returning its URL and engine.
+    /// `file_name` overrides the detected name of a URL's file.
+    async fn init_source(settings: &Settings, connection_manager: Arc<ConnectionManager>, source: String, file_name: Option<String>) -> Result<(String, RustleDownloader), String> {
+        let mut engine = settings.new_engine(connection_manager).await?;
+        if let Some(file_name) = file_name.as_ref() {
+            engine.set_file_name(file_name).await;
+        }
+        let url = if is_bundle_path(&source) {
+            let bundle = DownloadBundle::load(Path::new(&source))?;
+            bundle.apply(&mut engine).await?;
+            bundle.urls[0].clone()
+        } else if CurlCommand::is_curl(&source) {
+            let curl = CurlCommand::parse(&source)?;
+            if curl.insecure {
+                let mut tls = settings.tls.clone();
+                tls.accept_invalid_certs = true;
+                engine.set_tls_options(tls).await;
+            }
+            curl.apply(&mut engine).await?;
+            curl.url
+        } else {
+            engine.set_url(&source).await?;
+            engine.init().await?;
+            source
+        };
+        settings.enable_debug_logs(&mut engine).await?;
+        Ok((url, engine))
+    }
+
+    /// Handles a key press or the result of a background task.
+    pub async fn handle_event(&mut self, event: AppEvent) {
+        match event {
+            AppEvent::Key(key) => self.handle_key(key).await,
+            AppEvent::Initialized(result) => {
+                self.pending -= 1;
+                match result {
+                    Ok((url, engine)) => {
+                        let id = self.add_row(url, engine).await;
+                        self.start(id);
+                    },
+                    Err(e) => self.message = Some(e),
+                }
+            },
+            AppEvent::Finished(id, result) => {
+                if let Some(row) = self.rows.iter_mut().find(|row| row.id == id) {
+                    // Run the hook of the finished or failed download, and record it in the history
+                    let hook = self.settings.hooks.run_for(&row.engine, &result).await;
+                    let recorded = match self.history.as_ref() {
+                        Some(history) => history.record_for(&row.engine, &result).await,
+                        None => Ok(()),
+                    };
+                    match result {
+                        Ok(true) => self.message = Some(format!("Downloaded {}", row.file_name)),
+                        Ok(false) => {},
+                        // The kind of a classified failure comes first, e.g. "Host not found"
+                        Err(e) => row.error = Some(match row.engine.get_error_kind().await.filter(|kind| *kind != ErrorKind::Other) {
+                            Some(kind) => format!("{}: {}", kind, e),
+                            None => e,
+                        }),
+                    }
+                    if let Err(e) = hook.and(recorded) {
+                        self.message = Some(e);
+                    }
+                }
+                self.refresh().await;
+            },
+        }
+    }
+
+    /// Reads the progress of every download from its engine and the queue manager.
+    pub async fn refresh(&mut self) {
+        for row in self.rows.iter_mut() {
+            row.status = row.engine.get_status().await;
+            row.downloaded = row.engine.get_progress_vec().await.iter().map(|part| part.downloaded_bytes as u64).sum();
+            row.speed = row.engine.get_download_speed().await.smoothed;
+            row.eta = row.engine.get_eta().await;
+            row.retry = row.engine.get_retry_info().await;
+            row.paused_offline = row.engine.is_paused_offline().await;
+            row.paused_quota = row.engine.is_paused_quota().await;
+            row.restart_reason = row.engine.get_resume_mismatch().await.map(|mismatch| mismatch.to_string());
+            row.moving = row.engine.get_move_progress().await;
+            row.type_warning = row.engine.get_type_mismatch().await.filter(|_| row.status == DownloadStatus::Warning).map(|mismatch| mismatch.to_string());
+            row.host_paused = self.queue_manager.host(row.id).is_some_and(|host| self.queue_manager.is_host_paused(&host));
+            row.queue_state = self.queue_manager.state(row.id).unwrap_or_default();
+            row.queue_position = self.queue_manager.queue_position(row.id);
+            row.start_at = self.queue_manager.start_time(row.id);
+            row.priority = self.queue_manager.priority(row.id).unwrap_or_default();
+        }
+    }
+
+    /// Hands an initialized download to the queue manager and adds its row, selecting it.
+    async fn add_row(&mut self, url: String, engine: RustleDownloader) -> DownloadId {
+        let file_size = engine.get_file_info().await.and_then(|info| info.content_length);
+        let file_name = engine.get_file_path().await
+            .and_then(|path| path.file_name().map(|name| name.to_string_lossy().into_owned()))
+            .unwrap_or(url);
+
+        let id = self.queue_manager.add(engine);
+        let engine = self.queue_manager.engine(id).expect("the download was just added");
+        self.rows.push(DownloadRow {
+            id,
+            file_name,
+            file_size,
+            engine,
+            status: DownloadStatus::Idle,
+            queue_state: QueueState::Idle,
+            queue_position: None,
+            start_at: None,
+            priority: Priority::default(),
+            downloaded: 0,
+            speed: 0.0,
+            eta: None,
+            error: None,
+            retry: None,
+            paused_offline: false,
+            paused_quota: false,
+            restart_reason: None,
+            moving: None,
+            type_warning: None,
+            host_paused: false,
+        });
+        self.table_state.select(Some(self.rows.len() - 1));
+        id
+    }
+
+    /// Queues a download in the background, its result is reported as `AppEvent::Finished`.
+    fn start(&mut self, id: DownloadId) {
+        if let Some(row) = self.rows.iter_mut().find(|row| row.id == id) {
+            row.error = None;
+        }
+        let queue_manager = self.queue_manager.clone();
+        let sender = self.sender.clone();
+        task::spawn(async move {
+            let result = queue_manager.run(id).await;
+            let _ = sender.send(AppEvent::Finished(id, result));
+        });
+    }
+
+    /// Queues a download at `start_at` in the background, its result is reported as `AppEvent::Finished`.
+    fn schedule(&mut self, id: DownloadId, start_at: SystemTime) {
+        if let Err(e) = self.queue_manager.start_at(id, start_at) {
+            self.message = Some(e);
+            return;
+        }
+        let queue_manager = self.queue_manager.clone();
+        let sender = self.sender.clone();
+        task::spawn(async move {
+            let result = queue_manager.wait(id).await;
+            let _ = sender.send(AppEvent::Finished(id, result));
+        });
+    }
+
+    /// Removes a download from the queue manager and the list, keeping a row selected.
+    fn remove(&mut self, id: DownloadId) {
+        self.queue_manager.remove(id);
+        self.rows.retain(|row| row.id != id);
+        let selected = self.table_state.selected().map(|index| index.min(self.rows.len().saturating_sub(1)));
+        self.table_state.select(selected.filter(|_| !self.rows.is_empty()));
+    }
+
+    /// Returns the selected row.
+    pub fn selected(&self) -> Option<&DownloadRow> {
+        self.table_state.selected().and_then(|index| self.rows.get(index))
+    }
+
+    /// Moves the selection by `offset` rows, staying within the list.
+    fn select_by(&mut self, offset: isize) {
+        if self.rows.is_empty() {
+            return;
+        }
+        let index = self.table_state.selected().unwrap_or(0) as isize + offset;
+        self.table_state.select(Some(index.clamp(0, self.rows.len() as isize - 1) as usize));
+    }
+
+    /// Handles a key press according to the input mode.
+    async fn handle_key(&mut self, key: KeyEvent) {
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            self.quit = true;
+            return;
+        }
+
+        if let InputMode::AddUrl(input) = &mut self.input_mode {
+            match key.code {
+                KeyCode::Enter => {
+                    let source = input.trim().to_string();
+                    self.input_mode = InputMode::Normal;
+                    if !source.is_empty() {
+                        self.add(source);
+                    }
+                },
+                KeyCode::Esc => self.input_mode = InputMode::Normal,
+                KeyCode::Backspace => { input.pop(); },
+                KeyCode::Char(c) => input.push(c),
+                _ => {},
+            }
+            return;
+        }
+
+        // Any other key cancels a pending quit confirmation
+        let quit_requested = std::mem::take(&mut self.quit_requested);
+        self.message = None;
+
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => {
+                if quit_requested || !self.rows.iter().any(DownloadRow::is_running) {
+                    self.quit = true;
+                } else {
+                    self.quit_requested = true;
+                    self.message = Some(String::from("Downloads are running, press q again to pause them until the next launch"));
+                }
+            },
+            KeyCode::Char('a') => self.input_mode = InputMode::AddUrl(String::new()),
+            KeyCode::Up | KeyCode::Char('k') => self.select_by(-1),
+            KeyCode::Down | KeyCode::Char('j') => self.select_by(1),
+            KeyCode::Enter | KeyCode::Char('s') => {
+                let Some(row) = self.selected() else { return };
+                match (row.status, row.queue_state) {
+                    // Resuming a retrying download retries it right away
+                    (DownloadStatus::Paused | DownloadStatus::Retrying, _) => row.engine.resume().await,
+                    (_, QueueState::Scheduled | QueueState::DelayedRetry) => { let _ = self.queue_manager.start(row.id); },
+                    (DownloadStatus::Idle | DownloadStatus::Cancelled | DownloadStatus::Error, QueueState::Idle | QueueState::Finished) => {
+                        let id = row.id;
+                        self.start(id);
+                    },
+                    // Downloads a file whose type doesn't match its extension anyway
+                    (DownloadStatus::Warning, _) => {
+                        let id = row.id;
+                        row.engine.confirm_type().await;
+                        self.start(id);
+                    },
+                    _ => {},
+                }
+            },
+            KeyCode::Char('p') | KeyCode::Char(' ') => {
+                let Some(row) = self.selected() else { return };
+                match row.status {
+                    DownloadStatus::Downloading => row.engine.pause().await,
+                    DownloadStatus::Paused => row.engine.resume().await,
+                    _ => {},
+                }
+            },
+            KeyCode::Char('c') | KeyCode::Char('d') => {
+                let Some(row) = self.selected() else { return };
+                let keep_partial = key.code == KeyCode::Char('c');
+                match (row.status, row.queue_state) {
+                    (DownloadStatus::Downloading | DownloadStatus::Retrying | DownloadStatus::Paused, _) => row.engine.cancel(keep_partial).await,
+                    (DownloadStatus::Cancelled, _) if !keep_partial => row.engine.cancel(false).await,
+                    (DownloadStatus::Warning, _) => row.engine.cancel(false).await,
+                    // Not running, waiting in the queue, for its start time or a retry, like the GUI it's removed
+                    (_, QueueState::Scheduled | QueueState::Queued | QueueState::DelayedRetry) => {
+                        let id = row.id;
+                        self.remove(id);
+                    },
+                    _ => {},
+                }
+            },
+            KeyCode::Char('x') | KeyCode::Delete => {
+                let Some(row) = self.selected() else { return };
+                if row.is_running() {
+                    self.message = Some(String::from("Cancel the download before removing it"));
+                    return;
+                }
+                let id = row.id;
+                self.remove(id);
+            },
+            KeyCode::Char('+') | KeyCode::Char('-') => {
+                let Some(row) = self.selected() else { return };
+                let priority = match (key.code, row.priority) {
+                    (KeyCode::Char('+'), Priority::Low) => Priority::Normal,
+                    (KeyCode::Char('+'), _) => Priority::High,
+                    (_, Priority::High) => Priority::Normal,
+                    (_, _) => Priority::Low,
+                };
+                let _ = self.queue_manager.set_priority(row.id, priority);
+            },
+            KeyCode::Char('i') => self.show_timeline = !self.show_timeline,
+            // Hands the download off, e.g. to the GUI, as a session bundle next to its file
+            KeyCode::Char('e') => {
+                let Some(row) = self.selected() else { return };
+                let exported = match row.engine.get_file_path().await {
+                    Some(file_path) if row.status != DownloadStatus::Done => {
+                        let bundle_path = DownloadBundle::path_for(&file_path);
+                        DownloadBundle::from_download(&row.engine).await
+                            .and_then(|bundle| bundle.save(&bundle_path))
+                            .map(|_| format!("Exported the session to {}", bundle_path.display()))
+                    },
+                    Some(_) => Err(String::from("The download is finished, there's no session to export")),
+                    None => Err(String::from("The download isn't initialized")),
+                };
+                self.message = Some(exported.unwrap_or_else(|e| e));
+            },
+            KeyCode::Char('h') => {
+                let Some(host) = self.selected().and_then(|row| self.queue_manager.host(row.id)) else { return };
+                if self.queue_manager.is_host_paused(&host) {
+                    let resumed = self.queue_manager.resume_host(&host).await;
+                    self.message = Some(format!("Resumed {} ({} downloads)", host, resumed.len()));
+                } else {
+                    let paused = self.queue_manager.pause_host(&host).await;
+                    self.message = Some(format!("Paused {} ({} downloads), press h again to resume", host, paused.len()));
+                }
+            },
+            _ => {},
+        }
+        self.refresh().await;
+    }
+}
+
+/// Returns whether `source` is the path of a `.rustle` bundle.
+fn is_bundle_path(source: &str) -> bool {
+    Path::new(source).extension().is_some_and(|extension| extension == BUNDLE_EXTENSION)
+}
@@ -0,0 +1,131 @@
+mod app;
+mod ui;
+
+use std::time::Duration;
+use ratatui::crossterm::event::{self, Event, KeyEventKind};
+use tokio::sync::mpsc;
+use tokio::task;
+use rustle::config::profiles::Profiles;
+use rustle::config::settings::Settings;
+use rustle_core::drain::DRAIN_POLL_INTERVAL;
+use rustle_core::journal::termination_signal;
+use app::{App, AppEvent};
+
+/// Usage of the `rustle-tui` binary.
+const USAGE: &str = "Usage: rustle-tui [--profile <name>] [<url> | <file.rustle>]...\n       rustle-tui queue <drain [--wait]|resume|status>";
+
+/// Parses the command line: the `--profile <name>` (or `--profile=<name>`) flag and the
+/// URLs or `.rustle` bundles to queue at startup.
+fn parse_args() -> Result<(Option<String>, Vec<String>), String> {
+    let mut profile = None;
+    let mut downloads = Vec::new();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--profile" {
+            profile = Some(args.next().ok_or(USAGE)?);
+        } else if let Some(name) = arg.strip_prefix("--profile=") {
+            profile = Some(name.to_string());
+        } else if arg == "-h" || arg == "--help" || arg.starts_with('-') {
+            return Err(String::from(USAGE));
+        } else {
+            downloads.push(arg);
+        }
+    }
+    Ok((profile, downloads))
+}
+
+#[tokio::main]
+async fn main() {
+    // The queue of the running instance is drained or resumed instead of running the interface
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("queue") {
+        // Waiting for the drain blocks, the runtime's threads aren't needed
+        if let Err(e) = task::block_in_place(|| rustle::queue::run("rustle-tui", &args[1..])) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let (profile, downloads) = match parse_args() {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        },
+    };
+
+    // Load the user settings, falling back to the defaults for invalid entries
+    let mut profiles = match Settings::default_path() {
+        Some(path) => {
+            let (profiles, issues) = Profiles::load(&path);
+            for issue in issues {
+                eprintln!("{}: {}", path.display(), issue);
+            }
+            profiles
+        },
+        None => Profiles::default(),
+    };
+    if let Some(name) = profile {
+        if let Err(e) = profiles.select(&name) {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    }
+    let settings = profiles.active().clone();
+
+    // Terminal events are read on their own thread, `event::read()` blocks until the next one
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    let key_sender = sender.clone();
+    std::thread::spawn(move || {
+        while let Ok(event) = event::read() {
+            if let Event::Key(key) = event {
+                if key.kind == KeyEventKind::Press && key_sender.send(AppEvent::Key(key)).is_err() {
+                    break;
+                }
+            }
+        }
+    });
+
+    let mut app = App::new(settings, sender);
+    app.clear_drain();
+    app.restore_session().await;
+    for download in downloads {
+        app.add(download);
+    }
+
+    let mut terminal = ratatui::init();
+    let mut refresh = tokio::time::interval(app.refresh_interval().max(Duration::from_millis(50)));
+    let mut drain = tokio::time::interval(DRAIN_POLL_INTERVAL);
+    let termination = termination_signal();
+    tokio::pin!(termination);
+    let result = loop {
+        if let Err(e) = terminal.draw(|frame| ui::draw(frame, &mut app)) {
+            break Err(e);
+        }
+        if app.should_quit() {
+            break Ok(());
+        }
+
+        tokio::select! {
+            _ = refresh.tick() => app.refresh().await,
+            _ = drain.tick() => app.sync_drain().await,
+            Some(event) = receiver.recv() => app.handle_event(event).await,
+            _ = &mut termination => break Ok(()),
+        }
+    };
+    ratatui::restore();
+
+    // The running downloads are paused and saved, the next launch restores them
+    match app.save_session().await {
+        Ok(0) => {},
+        Ok(saved) => println!("Saved {} download(s), they're restored at the next launch", saved),
+        Err(e) => eprintln!("{}", e),
+    }
+    app.release_drain();
+
+    if let Err(e) = result {
+        eprintln!("Couldn't draw the terminal interface, error : {}", e);
+        std::process::exit(1);
+    }
+}
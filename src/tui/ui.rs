@@ -0,0 +1,146 @@
+use std::time::{Instant, SystemTime};
+use ratatui::layout::{Constraint, Layout, Position};
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Cell, Paragraph, Row, Table};
+use ratatui::Frame;
+use rustle_core::downloader::DownloadStatus;
+use rustle_core::queue_manager::QueueState;
+use rustle::format::{format_duration, format_file_size, format_timeline};
+use super::app::{App, DownloadRow, InputMode};
+
+/// Width of the progress bar drawn in the progress column, in cells.
+const PROGRESS_BAR_WIDTH: usize = 12;
+
+/// Keybindings shown in the footer.
+const HELP: &str = "a add  s start  p pause  h pause host  c cancel  d cancel & delete  x remove  +/- priority  i timeline  e export session  q quit";
+
+/// Height of the timeline panel, borders included.
+const TIMELINE_HEIGHT: u16 = 10;
+
+/// Draws the whole interface: the downloads table, the timeline of the selected download if shown, and the footer.
+pub fn draw(frame: &mut Frame, app: &mut App) {
+    let timeline_height = if app.show_timeline { TIMELINE_HEIGHT } else { 0 };
+    let [table_area, timeline_area, footer_area] = Layout::vertical([
+            Constraint::Min(3),
+            Constraint::Length(timeline_height),
+            Constraint::Length(3),
+        ])
+        .areas(frame.area());
+
+    let active = app.rows.iter().filter(|row| row.queue_state == QueueState::Active).count();
+    let mut title = format!(" Rustle | {} downloads | {}/{} active ", app.rows.len(), active, app.queue_manager.max_active());
+    if app.pending > 0 {
+        title.push_str(&format!("| {} loading ", app.pending));
+    }
+
+    let header = Row::new(["Name", "Size", "Progress", "Speed", "ETA", "Priority", "State"])
+        .style(Style::new().add_modifier(Modifier::BOLD));
+    let rows = app.rows.iter().map(table_row);
+    let table = Table::new(rows, [
+            Constraint::Fill(1),
+            Constraint::Length(10),
+            Constraint::Length(PROGRESS_BAR_WIDTH as u16 + 6),
+            Constraint::Length(11),
+            Constraint::Length(11),
+            Constraint::Length(8),
+            Constraint::Length(19),
+        ])
+        .header(header)
+        .block(Block::bordered().title(title).fg(Color::Green))
+        .row_highlight_style(Style::new().add_modifier(Modifier::REVERSED))
+        .highlight_symbol("> ");
+    frame.render_stateful_widget(table, table_area, &mut app.table_state);
+
+    // Timeline of the selected download, its latest events fill the panel
+    if let Some(row) = app.selected().filter(|_| app.show_timeline) {
+        let lines = format_timeline(&row.engine.get_timeline());
+        let visible = lines.len().saturating_sub(TIMELINE_HEIGHT as usize - 2);
+        let timeline = Paragraph::new(lines[visible..].iter().map(|line| Line::from(line.as_str())).collect::<Vec<_>>())
+            .block(Block::bordered().title(format!(" Timeline of {} ", row.file_name)));
+        frame.render_widget(timeline, timeline_area);
+    }
+
+    // Footer: the URL being typed, or the last message and the keybindings
+    let footer = match &app.input_mode {
+        InputMode::AddUrl(input) => {
+            let prefix = "URL or .rustle file: ";
+            frame.set_cursor_position(Position::new(
+                footer_area.x + 1 + (prefix.len() + input.chars().count()) as u16,
+                footer_area.y + 1,
+            ));
+            Paragraph::new(Line::from(vec![Span::raw(prefix).bold(), Span::raw(input.as_str())]))
+                .block(Block::bordered().title(" Add download (Enter to add, Esc to cancel) "))
+        },
+        InputMode::Normal => {
+            let line = match &app.message {
+                Some(message) => Line::from(message.as_str()).fg(Color::Yellow),
+                None => Line::from(HELP).fg(Color::DarkGray),
+            };
+            Paragraph::new(line).block(Block::bordered())
+        },
+    };
+    frame.render_widget(footer, footer_area);
+}
+
+/// Returns the table row of a download.
+fn table_row(row: &DownloadRow) -> Row<'static> {
+    let filled = (row.ratio() * PROGRESS_BAR_WIDTH as f64).round() as usize;
+    let progress = format!("{}{} {:>3.0}%",
+        "█".repeat(filled),
+        "░".repeat(PROGRESS_BAR_WIDTH - filled),
+        row.ratio() * 100.0
+    );
+
+    let downloading = row.status == DownloadStatus::Downloading;
+    let speed = if downloading { format!("{:.2} MB/s", row.speed / 1_000_000.0) } else { String::new() };
+    let eta = row.eta.filter(|_| downloading).map(format_duration).unwrap_or_default();
+
+    let (state, color) = match (row.queue_state, row.status) {
+        (QueueState::Scheduled, _) => (String::from("Scheduled"), Color::Blue),
+        (QueueState::DelayedRetry, _) => match row.start_at.and_then(|start_at| start_at.duration_since(SystemTime::now()).ok()) {
+            Some(remaining) => (format!("Retry in {}", format_duration(remaining)), Color::Yellow),
+            None => (String::from("Retrying"), Color::Yellow),
+        },
+        (QueueState::Queued, _) if row.host_paused => (String::from("Host paused"), Color::Yellow),
+        (QueueState::Queued, _) => (format!("Queued #{}", row.queue_position.map_or(0, |position| position + 1)), Color::Blue),
+        (_, DownloadStatus::Downloading) => match row.moving {
+            Some((moved, total)) => (format!("Moving {}%", moved * 100 / total.max(1)), Color::Green),
+            None => (String::from("Downloading"), Color::Green),
+        },
+        (_, DownloadStatus::Paused) if row.paused_offline => (String::from("Offline"), Color::Yellow),
+        (_, DownloadStatus::Paused) if row.paused_quota => (String::from("Quota"), Color::Yellow),
+        (_, DownloadStatus::Paused) if row.host_paused => (String::from("Host paused"), Color::Yellow),
+        (_, DownloadStatus::Paused) => (String::from("Paused"), Color::Yellow),
+        (_, DownloadStatus::Retrying) => match row.retry.as_ref() {
+            Some(retry) => (format!("Retry in {}", format_duration(retry.next_attempt.saturating_duration_since(Instant::now()))), Color::Yellow),
+            None => (String::from("Retrying"), Color::Yellow),
+        },
+        (_, DownloadStatus::Cancelled) => (String::from("Cancelled"), Color::DarkGray),
+        (_, DownloadStatus::Done) => (String::from("Done"), Color::Cyan),
+        (_, DownloadStatus::Error) => (String::from("Error"), Color::Red),
+        (_, DownloadStatus::Warning) => (String::from("Check type"), Color::Yellow),
+        (QueueState::Active, DownloadStatus::Idle) => (String::from("Starting"), Color::Green),
+        (_, DownloadStatus::Idle) => (String::from("Idle"), Color::DarkGray),
+    };
+
+    // Errors, type warnings and the reason a download started over replace the name, there's no other room for them
+    let name = match (row.error.as_ref(), row.restart_reason.as_ref()) {
+        _ if row.type_warning.is_some() => {
+            Cell::from(format!("{} ({}, s to download anyway)", row.file_name, row.type_warning.as_deref().unwrap_or_default())).fg(Color::Yellow)
+        },
+        (Some(error), _) => Cell::from(format!("{} ({})", row.file_name, error)).fg(Color::Red),
+        (None, Some(reason)) => Cell::from(format!("{} (started over: {})", row.file_name, reason)).fg(Color::Yellow),
+        (None, None) => Cell::from(row.file_name.clone()),
+    };
+
+    Row::new([
+        name,
+        Cell::from(row.file_size.map(format_file_size).unwrap_or_else(|| String::from("?"))),
+        Cell::from(progress).fg(color),
+        Cell::from(speed),
+        Cell::from(eta),
+        Cell::from(row.priority.to_string()),
+        Cell::from(state).fg(color),
+    ])
+}
@@ -0,0 +1,120 @@
+//! End-to-end downloads against the bundled testing server, started in-process.
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use rustle::testing_server::TestingServer;
+use rustle_core::downloader::SupportPartialRequest;
+use rustle_core::{DownloadStatus, RetryPolicy, RustleDownloader};
+
+/// Size of the served file.
+const FILE_SIZE: usize = 8 * 1024 * 1024;
+
+/// Creates a new temporary directory named after the test.
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rustle-it-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Writes the served file into `dir` and starts a server serving it.
+fn start_server(dir: &Path) -> (TestingServer, Vec<u8>) {
+    let content: Vec<u8> = (0..FILE_SIZE).map(|i| (i * 7 % 251) as u8).collect();
+    let file = dir.join("served.bin");
+    std::fs::write(&file, &content).unwrap();
+    (TestingServer::start(file).unwrap(), content)
+}
+
+/// Builds an initialized download of `url` into `out_dir`, without retries.
+async fn engine(url: &str, out_dir: &Path, connections: u8) -> Result<RustleDownloader, String> {
+    RustleDownloader::builder()
+        .url(url)
+        .out_dir(out_dir)
+        .connections(connections)
+        .retry_policy(RetryPolicy { max_retries: 0, ..RetryPolicy::default() })
+        .build()
+        .await
+}
+
+#[tokio::test]
+async fn parts_are_merged_into_the_file() {
+    let dir = temp_dir("parts");
+    let (server, content) = start_server(&dir);
+
+    for connections in [1, 3, 8] {
+        let out_dir = dir.join(format!("out-{}", connections));
+        let engine = engine(&server.url("/download"), &out_dir, connections).await.unwrap();
+        assert_eq!(engine.get_file_info().await.unwrap().support_partial, SupportPartialRequest::Yes);
+        assert_eq!(engine.download(false).await, Ok(true));
+        // A part left behind by the others has its remaining bytes split into more parts
+        assert!(engine.get_progress_vec().await.len() >= connections as usize);
+        assert_eq!(std::fs::read(out_dir.join("served.bin")).unwrap(), content);
+    }
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn paused_download_resumes_where_it_stopped() {
+    let dir = temp_dir("pause");
+    let (server, content) = start_server(&dir);
+    let out_dir = dir.join("out");
+    let engine = engine(&server.url("/throttled/20/download"), &out_dir, 4).await.unwrap();
+
+    let running = {
+        let engine = engine.clone();
+        tokio::spawn(async move { engine.download(false).await })
+    };
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    engine.pause().await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(engine.get_status().await, DownloadStatus::Paused);
+
+    let received: usize = engine.get_progress_vec().await.iter().map(|part| part.downloaded_bytes).sum();
+    assert!(received > 0 && received < FILE_SIZE, "{} bytes received before the pause", received);
+    engine.resume().await;
+
+    assert_eq!(running.await.unwrap(), Ok(true));
+    assert_eq!(engine.get_status().await, DownloadStatus::Done);
+    assert_eq!(std::fs::read(out_dir.join("served.bin")).unwrap(), content);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn server_without_ranges_is_downloaded_over_one_connection() {
+    let dir = temp_dir("no-ranges");
+    let (server, content) = start_server(&dir);
+    let out_dir = dir.join("out");
+    let engine = engine(&server.url("/no-ranges/download"), &out_dir, 4).await.unwrap();
+
+    assert_ne!(engine.get_file_info().await.unwrap().support_partial, SupportPartialRequest::Yes);
+    assert_eq!(engine.download(false).await, Ok(true));
+    assert_eq!(engine.get_progress_vec().await.len(), 1);
+    assert_eq!(std::fs::read(out_dir.join("download")).unwrap(), content);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn failures_are_reported() {
+    let dir = temp_dir("errors");
+    let (server, _) = start_server(&dir);
+
+    // Missing files fail the initialization
+    for code in [404, 500] {
+        let error = engine(&server.url(&format!("/status/{}", code)), &dir, 4).await.unwrap_err();
+        assert!(error.contains(&code.to_string()), "{}", error);
+    }
+
+    // A corrupted download never lands in the output directory
+    let out_dir = dir.join("out");
+    let mut engine = engine(&server.url("/download"), &out_dir, 4).await.unwrap();
+    engine.set_expected_sha256(&"0".repeat(64)).await.unwrap();
+    assert!(engine.download(false).await.unwrap_err().contains("Checksum mismatch"));
+    assert_eq!(engine.get_status().await, DownloadStatus::Error);
+    assert!(!out_dir.join("served.bin").exists());
+
+    // The server going away fails the download
+    let engine = self::engine(&server.url("/download"), &out_dir, 4).await.unwrap();
+    drop(server);
+    assert!(engine.download(false).await.is_err());
+    std::fs::remove_dir_all(dir).unwrap();
+}